@@ -0,0 +1,252 @@
+//! Snapshots of the ABI that bindings use for an interface.
+//!
+//! A `wit-abi.lock` file records, for every interface in a workspace, the
+//! flattened core wasm signature of each function in both directions and
+//! the size and alignment of each named type. The bindgen macros and the CLI
+//! look for one in the crate directory and its ancestors and refuse to
+//! generate bindings for an interface whose ABI no longer matches its
+//! snapshot. Unlike `wit.lock`, which catches interfaces being edited, this
+//! catches the generators themselves changing how an unchanged interface is
+//! lowered, which would break communication with bytecode built against the
+//! old layout.
+//!
+//! Each interface gets a section listing its functions and types:
+//!
+//! ```text
+//! [contract]
+//! func execute: import (i32, i32, i32) -> (); export (i32, i32) -> (i32)
+//! type operation: size 12, align 4
+//! ```
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Path, PathBuf};
+use wit_parser::abi::{AbiVariant, WasmSignature, WasmType};
+use wit_parser::{Interface, SizeAlign, Type};
+
+/// The name of ABI lockfiles.
+pub const ABI_LOCKFILE: &str = "wit-abi.lock";
+
+const HEADER: &str = "\
+# This file is generated by `wit-bindgen abi-lock` and records the ABI of every
+# interface used in this workspace. It is not meant to be edited by hand.
+";
+
+/// Returns the lines describing the ABI of `iface`.
+pub fn snapshot(iface: &Interface) -> Vec<String> {
+    let mut lines = Vec::new();
+    for func in iface.functions.iter() {
+        lines.push(format!(
+            "func {}: import {}; export {}",
+            func.name,
+            signature(&iface.wasm_signature(AbiVariant::GuestImport, func)),
+            signature(&iface.wasm_signature(AbiVariant::GuestExport, func)),
+        ));
+    }
+
+    let mut sizes = SizeAlign::default();
+    sizes.fill(iface);
+    for (id, ty) in iface.types.iter() {
+        if let Some(name) = &ty.name {
+            let ty = Type::Id(id);
+            lines.push(format!(
+                "type {}: size {}, align {}",
+                name,
+                sizes.size(&ty),
+                sizes.align(&ty)
+            ));
+        }
+    }
+    lines
+}
+
+fn signature(sig: &WasmSignature) -> String {
+    let list = |types: &[WasmType]| {
+        let types = types
+            .iter()
+            .map(|t| match t {
+                WasmType::I32 => "i32",
+                WasmType::I64 => "i64",
+                WasmType::F32 => "f32",
+                WasmType::F64 => "f64",
+            })
+            .collect::<Vec<_>>();
+        format!("({})", types.join(", "))
+    };
+    let mut s = format!("{} -> {}", list(&sig.params), list(&sig.results));
+    if sig.indirect_params {
+        s.push_str(" indirect-params");
+    }
+    if sig.retptr {
+        s.push_str(" retptr");
+    }
+    s
+}
+
+/// The ABI snapshots recorded in a `wit-abi.lock` file.
+#[derive(Debug, Clone)]
+pub struct AbiLock {
+    path: PathBuf,
+    interfaces: BTreeMap<String, Vec<String>>,
+}
+
+impl AbiLock {
+    /// Creates an empty ABI lockfile which will be saved to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> AbiLock {
+        AbiLock {
+            path: path.into(),
+            interfaces: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the ABI lockfile at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<AbiLock> {
+        let mut lock = AbiLock::new(path);
+        let contents = fs::read_to_string(&lock.path)
+            .with_context(|| format!("failed to read {:?}", lock.path))?;
+        let mut current = None;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                lock.interfaces.insert(name.to_string(), Vec::new());
+                current = Some(name.to_string());
+                continue;
+            }
+            match &current {
+                Some(name) => lock
+                    .interfaces
+                    .get_mut(name)
+                    .unwrap()
+                    .push(line.to_string()),
+                None => bail!("{:?}:{}: entry outside of an interface", lock.path, i + 1),
+            }
+        }
+        Ok(lock)
+    }
+
+    /// Finds the ABI lockfile governing `dir`, looking in `dir` and then in
+    /// each of its ancestors.
+    pub fn find(dir: &Path) -> Result<Option<AbiLock>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(ABI_LOCKFILE);
+            if path.is_file() {
+                return AbiLock::load(path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns where this lockfile is saved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks that the ABI of `iface` matches its snapshot.
+    pub fn verify(&self, iface: &Interface) -> Result<()> {
+        let locked = match self.interfaces.get(&iface.name) {
+            Some(locked) => locked,
+            None => bail!(
+                "the ABI of `{}` is not recorded in {:?}; run `wit-bindgen abi-lock` to add it",
+                iface.name,
+                self.path
+            ),
+        };
+        let current = snapshot(iface);
+        if *locked == current {
+            return Ok(());
+        }
+        let mut diff = String::new();
+        for line in locked.iter().filter(|l| !current.contains(l)) {
+            writeln!(diff, "  - {}", line).unwrap();
+        }
+        for line in current.iter().filter(|l| !locked.contains(l)) {
+            writeln!(diff, "  + {}", line).unwrap();
+        }
+        bail!(
+            "the ABI of `{}` no longer matches its snapshot in {:?}:\n{}\
+             run `wit-bindgen abi-lock` if the change is intended",
+            iface.name,
+            self.path,
+            diff
+        )
+    }
+
+    /// Records the current ABI of `iface`.
+    pub fn insert(&mut self, iface: &Interface) {
+        self.interfaces.insert(iface.name.clone(), snapshot(iface));
+    }
+
+    /// Writes this lockfile back to disk.
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, self.to_string())
+            .with_context(|| format!("failed to write {:?}", self.path))
+    }
+}
+
+impl fmt::Display for AbiLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(HEADER)?;
+        for (name, lines) in self.interfaces.iter() {
+            writeln!(f, "\n[{}]", name)?;
+            for line in lines {
+                writeln!(f, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_verify() {
+        let dir = std::env::temp_dir().join(format!("wit-abi-lock-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let iface = Interface::parse(
+            "contract",
+            "
+                record op {
+                    kind: u8,
+                    amount: u64,
+                }
+                execute: func(o: op, data: list<u8>) -> string
+            ",
+        )
+        .unwrap();
+
+        let mut lock = AbiLock::new(dir.join(ABI_LOCKFILE));
+        assert!(lock.verify(&iface).is_err());
+        lock.insert(&iface);
+        lock.save().unwrap();
+
+        let lock = AbiLock::find(&dir).unwrap().unwrap();
+        let contents = lock.to_string();
+        assert!(contents.contains("\n[contract]\n"));
+        assert!(contents.contains("type op: size 16, align 8\n"));
+        lock.verify(&iface).unwrap();
+
+        let changed = Interface::parse(
+            "contract",
+            "
+                record op {
+                    kind: u8,
+                    amount: u32,
+                }
+                execute: func(o: op, data: list<u8>) -> string
+            ",
+        )
+        .unwrap();
+        let err = lock.verify(&changed).unwrap_err().to_string();
+        assert!(err.contains("- type op: size 16, align 8"));
+        assert!(err.contains("+ type op: size 8, align 4"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
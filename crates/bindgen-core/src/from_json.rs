@@ -0,0 +1,490 @@
+//! Drafting WIT types from sample JSON documents, to ease moving an
+//! existing JSON API over to an interface.
+//!
+//! The samples are merged into a single shape: objects become records whose
+//! fields missing from some samples are optional, `null`s make options, and
+//! values of different kinds at the same place become variants. Choices the
+//! samples can't settle, such as the width of integers or whether strings
+//! are really an enum, are put to a [`Refine`], which may ask the user.
+
+use anyhow::{bail, Result};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+/// Strings taking at most this many distinct values are offered as enums.
+const MAX_ENUM_CASES: usize = 8;
+
+/// Settles the choices the samples leave open.
+pub trait Refine {
+    /// Picks one of `choices` as the answer to `question`, returning its
+    /// index. The first choice is the one inferred from the samples.
+    fn choose(&mut self, question: &str, choices: &[String]) -> usize;
+}
+
+/// Takes the inferred choice every time.
+pub struct Defaults;
+
+impl Refine for Defaults {
+    fn choose(&mut self, _question: &str, _choices: &[String]) -> usize {
+        0
+    }
+}
+
+/// The shape of the values seen at some place in the samples.
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    /// No value, as for the elements of empty arrays.
+    Unknown,
+    Null,
+    Bool,
+    Int {
+        min: i128,
+        max: i128,
+    },
+    Float,
+    /// Strings, along with their distinct values while there are few.
+    String(Option<BTreeSet<String>>),
+    List(Box<Shape>),
+    Record(Vec<Field>),
+    Option(Box<Shape>),
+    /// Values of several kinds, each of a different one.
+    Variant(Vec<Shape>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Field {
+    /// The key of the field in the samples.
+    key: String,
+    shape: Shape,
+    /// Whether some samples lack the field.
+    optional: bool,
+}
+
+impl Shape {
+    fn of(value: &Value) -> Shape {
+        match value {
+            Value::Null => Shape::Null,
+            Value::Bool(_) => Shape::Bool,
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => Shape::Int {
+                    min: i.into(),
+                    max: i.into(),
+                },
+                (None, Some(u)) => Shape::Int {
+                    min: u.into(),
+                    max: u.into(),
+                },
+                (None, None) => Shape::Float,
+            },
+            Value::String(s) => Shape::String(Some([s.clone()].into_iter().collect())),
+            Value::Array(values) => {
+                let elements = values.iter().map(Shape::of);
+                Shape::List(Box::new(elements.fold(Shape::Unknown, Shape::merge)))
+            }
+            Value::Object(fields) => Shape::Record(
+                fields
+                    .iter()
+                    .map(|(key, value)| Field {
+                        key: key.clone(),
+                        shape: Shape::of(value),
+                        optional: false,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Returns the shape of the values of both `self` and `other`.
+    fn merge(self, other: Shape) -> Shape {
+        match (self, other) {
+            (Shape::Unknown, s) | (s, Shape::Unknown) => s,
+            (Shape::Null, Shape::Null) => Shape::Null,
+            (Shape::Null, Shape::Option(s)) | (Shape::Option(s), Shape::Null) => Shape::Option(s),
+            (Shape::Null, s) | (s, Shape::Null) => Shape::Option(Box::new(s)),
+            (Shape::Option(a), Shape::Option(b)) => Shape::Option(Box::new(a.merge(*b))),
+            (Shape::Option(a), b) | (b, Shape::Option(a)) => Shape::Option(Box::new(a.merge(b))),
+            (Shape::Bool, Shape::Bool) => Shape::Bool,
+            (Shape::Int { min, max }, Shape::Int { min: m, max: n }) => Shape::Int {
+                min: min.min(m),
+                max: max.max(n),
+            },
+            (Shape::Int { .. } | Shape::Float, Shape::Int { .. } | Shape::Float) => Shape::Float,
+            (Shape::String(a), Shape::String(b)) => Shape::String(match (a, b) {
+                (Some(mut a), Some(b)) => {
+                    a.extend(b);
+                    Some(a).filter(|values| values.len() <= MAX_ENUM_CASES)
+                }
+                _ => None,
+            }),
+            (Shape::List(a), Shape::List(b)) => Shape::List(Box::new(a.merge(*b))),
+            (Shape::Record(a), Shape::Record(b)) => Shape::Record(merge_fields(a, b)),
+            (Shape::Variant(mut cases), s) | (s, Shape::Variant(mut cases)) => {
+                add_case(&mut cases, s);
+                Shape::Variant(cases)
+            }
+            (a, b) => {
+                let mut cases = vec![a];
+                add_case(&mut cases, b);
+                Shape::Variant(cases)
+            }
+        }
+    }
+
+    /// Names the kind of the shape, for the cases of variants.
+    fn kind(&self) -> &'static str {
+        match self {
+            Shape::Unknown | Shape::Null => "none",
+            Shape::Bool => "boolean",
+            Shape::Int { .. } | Shape::Float => "number",
+            Shape::String(_) => "text",
+            Shape::List(_) => "items",
+            Shape::Record(_) => "object",
+            Shape::Option(s) => s.kind(),
+            Shape::Variant(_) => "any",
+        }
+    }
+}
+
+fn merge_fields(a: Vec<Field>, mut b: Vec<Field>) -> Vec<Field> {
+    let mut fields = Vec::new();
+    for mut field in a {
+        match b.iter().position(|f| f.key == field.key) {
+            Some(i) => {
+                let other = b.remove(i);
+                field.shape = field.shape.merge(other.shape);
+                field.optional |= other.optional;
+            }
+            None => field.optional = true,
+        }
+        fields.push(field);
+    }
+    fields.extend(b.into_iter().map(|f| Field {
+        optional: true,
+        ..f
+    }));
+    fields
+}
+
+/// Adds `shape` to the `cases` of a variant, merging it into the case of the
+/// same kind if there is one.
+fn add_case(cases: &mut Vec<Shape>, shape: Shape) {
+    match shape {
+        Shape::Variant(shapes) => {
+            for shape in shapes {
+                add_case(cases, shape);
+            }
+        }
+        shape => match cases.iter().position(|c| c.kind() == shape.kind()) {
+            Some(i) => {
+                let case = std::mem::replace(&mut cases[i], Shape::Unknown);
+                cases[i] = case.merge(shape);
+            }
+            None => cases.push(shape),
+        },
+    }
+}
+
+/// Drafts WIT types for the `samples`, returning the source of an
+/// interface defining them, where the type of the samples is called `name`.
+pub fn infer(name: &str, samples: &[Value], refine: &mut dyn Refine) -> Result<String> {
+    if samples.is_empty() {
+        bail!("no samples to infer types from");
+    }
+    let shape = samples
+        .iter()
+        .map(Shape::of)
+        .fold(Shape::Unknown, Shape::merge);
+    let mut writer = Writer {
+        refine,
+        defined: Vec::new(),
+        src: String::new(),
+    };
+    let name = to_id(name);
+    match &shape {
+        Shape::Record(_) | Shape::Variant(_) => {
+            writer.define(&name, &shape, &name);
+        }
+        _ => {
+            let ty = writer.ty(&shape, &name, &name);
+            // Strings may have been made an enum called `name` already.
+            if ty != name {
+                writeln!(writer.src, "type {} = {}", name, ty).unwrap();
+            }
+        }
+    }
+    Ok(writer.src)
+}
+
+struct Writer<'a> {
+    refine: &'a mut dyn Refine,
+    /// The types defined so far, to name new ones uniquely.
+    defined: Vec<(String, Shape)>,
+    src: String,
+}
+
+impl Writer<'_> {
+    /// Returns the WIT type for `shape`, found at `path` in the samples,
+    /// defining a type called after `name` first if it needs one.
+    fn ty(&mut self, shape: &Shape, name: &str, path: &str) -> String {
+        match shape {
+            Shape::Unknown | Shape::Null => {
+                let question = format!("no value of `{}` was seen: which type is it?", path);
+                let choices = ["string", "u64", "float64", "bool"].map(String::from);
+                let choice = self.refine.choose(&question, &choices);
+                let ty = choices[choice].clone();
+                if *shape == Shape::Null {
+                    format!("option<{}>", ty)
+                } else {
+                    ty
+                }
+            }
+            Shape::Bool => "bool".to_string(),
+            Shape::Float => "float64".to_string(),
+            Shape::Int { min, max } => {
+                let choices = int_types(*min, *max);
+                let question = format!(
+                    "`{}` ranges over {}..={} in the samples: which integer type is it?",
+                    path, min, max
+                );
+                let choice = self.refine.choose(&question, &choices);
+                choices[choice].clone()
+            }
+            Shape::String(Some(values)) if values.len() > 1 && values.iter().all(|v| is_id(v)) => {
+                let cases = values
+                    .iter()
+                    .map(|v| format!("`{}`", v))
+                    .collect::<Vec<_>>();
+                let question = format!(
+                    "`{}` only takes the values {} in the samples: is it an enum?",
+                    path,
+                    cases.join(", ")
+                );
+                let choices = ["string", "enum"].map(String::from);
+                match self.refine.choose(&question, &choices) {
+                    0 => "string".to_string(),
+                    _ => self.define(name, shape, path),
+                }
+            }
+            Shape::String(_) => "string".to_string(),
+            Shape::List(element) => {
+                let element = self.ty(element, &format!("{}-item", name), &format!("{}[]", path));
+                format!("list<{}>", element)
+            }
+            Shape::Option(inner) => format!("option<{}>", self.ty(inner, name, path)),
+            Shape::Record(_) | Shape::Variant(_) => self.define(name, shape, path),
+        }
+    }
+
+    /// Defines a named type for `shape`, unless one was already defined
+    /// alike, returning its name.
+    fn define(&mut self, name: &str, shape: &Shape, path: &str) -> String {
+        if let Some((name, _)) = self.defined.iter().find(|(_, s)| s == shape) {
+            return name.clone();
+        }
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while self.defined.iter().any(|(defined, _)| *defined == unique) {
+            n += 1;
+            unique = format!("{}{}", name, n);
+        }
+        self.defined.push((unique.clone(), shape.clone()));
+
+        // The types of the fields and cases are defined before this one.
+        let mut body = String::new();
+        let keyword = match shape {
+            Shape::Record(fields) => {
+                for field in fields {
+                    let id = to_id(&field.key);
+                    let path = format!("{}.{}", path, field.key);
+                    let mut ty = self.ty(&field.shape, &id, &path);
+                    if field.optional && !matches!(field.shape, Shape::Option(_) | Shape::Null) {
+                        ty = format!("option<{}>", ty);
+                    }
+                    writeln!(body, "  {}: {},", id, ty).unwrap();
+                }
+                "record"
+            }
+            Shape::Variant(cases) => {
+                for case in cases {
+                    let id = format!("{}-{}", unique, case.kind());
+                    let ty = self.ty(case, &id, path);
+                    writeln!(body, "  {}({}),", case.kind(), ty).unwrap();
+                }
+                "variant"
+            }
+            Shape::String(Some(values)) => {
+                for value in values {
+                    writeln!(body, "  {},", value).unwrap();
+                }
+                "enum"
+            }
+            _ => unreachable!(),
+        };
+        if !self.src.is_empty() {
+            self.src.push('\n');
+        }
+        writeln!(self.src, "{} {} {{\n{}}}", keyword, unique, body).unwrap();
+        unique
+    }
+}
+
+/// Lists the integer types holding `min..=max`, the widest first, as JSON
+/// APIs rarely bound their numbers.
+fn int_types(min: i128, max: i128) -> Vec<String> {
+    let types: [(&str, i128, i128); 8] = [
+        ("u64", 0, u64::MAX.into()),
+        ("s64", i64::MIN.into(), i64::MAX.into()),
+        ("u32", 0, u32::MAX.into()),
+        ("s32", i32::MIN.into(), i32::MAX.into()),
+        ("u16", 0, u16::MAX.into()),
+        ("s16", i16::MIN.into(), i16::MAX.into()),
+        ("u8", 0, u8::MAX.into()),
+        ("s8", i8::MIN.into(), i8::MAX.into()),
+    ];
+    let fitting = types.iter().filter(|(_, lo, hi)| *lo <= min && max <= *hi);
+    fitting.map(|(name, _, _)| name.to_string()).collect()
+}
+
+fn is_id(s: &str) -> bool {
+    wit_parser::validate_id(s).is_ok() && !is_keyword(s)
+}
+
+/// Whether `id` is lexed as something other than an identifier.
+fn is_keyword(id: &str) -> bool {
+    match wit_parser::tokens(id) {
+        Ok(tokens) => tokens.len() != 1 || tokens[0].kind != wit_parser::TokenKind::Id,
+        Err(_) => true,
+    }
+}
+
+/// Turns a JSON key such as `createdAt` or `created_at` into a WIT
+/// identifier, `created-at`.
+fn to_id(key: &str) -> String {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut prev = None::<char>;
+    for c in key.chars() {
+        if !c.is_ascii_alphanumeric() {
+            parts.push(std::mem::take(&mut part));
+            prev = None;
+            continue;
+        }
+        if c.is_ascii_uppercase()
+            && prev.is_some_and(|p| p.is_ascii_lowercase() || p.is_ascii_digit())
+        {
+            parts.push(std::mem::take(&mut part));
+        }
+        part.push(c.to_ascii_lowercase());
+        prev = Some(c);
+    }
+    parts.push(part);
+    parts.retain(|part| !part.is_empty());
+    if parts.is_empty() {
+        parts.push("field".to_string());
+    }
+    // Parts of identifiers must start with a letter.
+    for part in parts.iter_mut() {
+        if part.starts_with(|c: char| c.is_ascii_digit()) {
+            part.insert(0, 'n');
+        }
+    }
+    let id = parts.join("-");
+    if is_keyword(&id) {
+        format!("%{}", id)
+    } else {
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(samples: &[&str]) -> String {
+        let samples = samples
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect::<Vec<_>>();
+        super::infer("payment", &samples, &mut Defaults).unwrap()
+    }
+
+    #[test]
+    fn merges_objects_into_records() {
+        let src = infer(&[
+            r#"{"amount": 10, "payee": {"name": "a"}, "memo": null}"#,
+            r#"{"amount": 20, "payee": {"name": "b"}, "memo": "rent", "tags": []}"#,
+        ]);
+        assert_eq!(
+            src,
+            "\
+record payee {
+  name: string,
+}
+
+record payment {
+  amount: u64,
+  memo: option<string>,
+  payee: payee,
+  tags: option<list<string>>,
+}
+"
+        );
+        wit_parser::Interface::parse("payments", &src).unwrap();
+    }
+
+    #[test]
+    fn makes_variants_of_mixed_values() {
+        let src = infer(&["1", "\"one\""]);
+        assert_eq!(
+            src,
+            "\
+variant payment {
+  number(u64),
+  text(string),
+}
+"
+        );
+    }
+
+    #[test]
+    fn names_ids_after_keys() {
+        assert_eq!(to_id("createdAt"), "created-at");
+        assert_eq!(to_id("created_at"), "created-at");
+        assert_eq!(to_id("type"), "%type");
+        assert_eq!(to_id("2fa"), "n2fa");
+        assert_eq!(to_id("$ref"), "ref");
+    }
+
+    struct Answers(Vec<usize>);
+
+    impl Refine for Answers {
+        fn choose(&mut self, _question: &str, choices: &[String]) -> usize {
+            self.0.remove(0).min(choices.len() - 1)
+        }
+    }
+
+    #[test]
+    fn asks_to_refine_choices() {
+        let samples = [
+            serde_json::json!({"status": "paid", "count": 3}),
+            serde_json::json!({"status": "pending", "count": -1}),
+        ];
+        // `count` is asked about first, as fields are sorted by key.
+        let src = super::infer("payment", &samples, &mut Answers(vec![2, 1])).unwrap();
+        assert_eq!(
+            src,
+            "\
+enum status {
+  paid,
+  pending,
+}
+
+record payment {
+  count: s16,
+  status: status,
+}
+"
+        );
+    }
+}
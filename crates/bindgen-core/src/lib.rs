@@ -6,7 +6,21 @@ use std::path::Path;
 use wit_parser::*;
 
 pub use wit_parser;
+pub mod abi_lock;
+pub mod from_json;
+pub mod lint;
+pub mod lock;
+#[cfg(feature = "macros")]
+pub mod macro_options;
 mod ns;
+pub mod options;
+pub mod profile;
+pub mod registry;
+pub mod size_report;
+pub mod stats;
+pub mod suggest;
+pub mod vectors;
+pub mod workspace;
 
 pub use ns::Ns;
 
@@ -322,7 +336,9 @@ impl Types {
         }
     }
 
-    fn set_param_result_ty(&mut self, iface: &Interface, ty: &Type, param: bool, result: bool) {
+    /// Marks `ty`, and everything it refers to, as used in parameter and/or
+    /// result position even if no function mentions it.
+    pub fn set_param_result_ty(&mut self, iface: &Interface, ty: &Type, param: bool, result: bool) {
         match ty {
             Type::Id(id) => {
                 self.type_id_info(iface, *id);
@@ -0,0 +1,148 @@
+//! Checks for interface shapes which are slow to pass across the boundary.
+//!
+//! The canonical ABI passes a function's parameters as core wasm values only
+//! while they flatten to at most 16 of them, and otherwise quietly stores them
+//! in linear memory and passes a pointer instead. Large records likewise cost
+//! a copy through memory every time they cross. [`Thresholds`] flags both at
+//! generation time so that hot interfaces keep to their budget; violations are
+//! reported as warnings unless [`Thresholds::deny`] turns them into errors.
+
+use anyhow::{bail, Result};
+use std::fmt;
+use wit_parser::abi::AbiVariant;
+use wit_parser::{Interface, SizeAlign, Type, TypeDefKind};
+
+/// Limits on the shapes of an interface's functions and types.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Thresholds {
+    /// The most core wasm parameters a function's parameters may flatten to.
+    pub max_flat_params: Option<usize>,
+    /// The largest size, in bytes, of a record in linear memory.
+    pub max_record_size: Option<usize>,
+    /// Whether exceeding a threshold is an error rather than a warning.
+    pub deny: bool,
+}
+
+/// A function or type exceeding one of the [`Thresholds`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    pub interface: String,
+    /// The name of the offending function or type.
+    pub item: String,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}: {}", self.interface, self.item, self.message)
+    }
+}
+
+impl Thresholds {
+    /// Returns every function and record of `iface` exceeding a threshold.
+    pub fn check(&self, iface: &Interface) -> Vec<Lint> {
+        let mut lints = Vec::new();
+        let lint = |item: &str, message: String| Lint {
+            interface: iface.name.clone(),
+            item: item.to_string(),
+            message,
+        };
+
+        if let Some(max) = self.max_flat_params {
+            for func in iface.functions.iter() {
+                let count = iface.flat_param_count(AbiVariant::GuestImport, func);
+                if count > max {
+                    lints.push(lint(
+                        &func.name,
+                        format!(
+                            "parameters flatten to {} core wasm values, more than the limit of {}",
+                            count, max
+                        ),
+                    ));
+                }
+            }
+        }
+
+        if let Some(max) = self.max_record_size {
+            let mut sizes = SizeAlign::default();
+            sizes.fill(iface);
+            for (id, ty) in iface.types.iter() {
+                let name = match (&ty.name, &ty.kind) {
+                    (Some(name), TypeDefKind::Record(_)) => name,
+                    _ => continue,
+                };
+                let size = sizes.size(&Type::Id(id));
+                if size > max {
+                    lints.push(lint(
+                        name,
+                        format!(
+                            "record takes {} bytes, more than the limit of {}",
+                            size, max
+                        ),
+                    ));
+                }
+            }
+        }
+
+        lints
+    }
+
+    /// Checks `interfaces`, printing violations as warnings to standard
+    /// error, or failing on them if [`Thresholds::deny`] is set.
+    pub fn enforce<'a>(&self, interfaces: impl IntoIterator<Item = &'a Interface>) -> Result<()> {
+        let lints = interfaces
+            .into_iter()
+            .flat_map(|iface| self.check(iface))
+            .collect::<Vec<_>>();
+        if self.deny && !lints.is_empty() {
+            let lints = lints.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+            bail!("interface exceeds its thresholds:\n{}", lints.join("\n"));
+        }
+        for lint in lints.iter() {
+            eprintln!("warning: {}", lint);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_wide_functions_and_large_records() {
+        let iface = Interface::parse(
+            "hot",
+            "
+                record small {
+                    a: u32,
+                }
+                record large {
+                    a: u64,
+                    b: u64,
+                    c: tuple<u64, u64>,
+                }
+                narrow: func(a: u32, b: small)
+                wide: func(a: large, b: large)
+            ",
+        )
+        .unwrap();
+        let thresholds = Thresholds {
+            max_flat_params: Some(4),
+            max_record_size: Some(16),
+            deny: false,
+        };
+        let lints = thresholds.check(&iface);
+        let items = lints.iter().map(|l| l.item.as_str()).collect::<Vec<_>>();
+        assert_eq!(items, ["wide", "large"]);
+        assert!(lints[0].to_string().starts_with("hot::wide: "));
+
+        assert!(thresholds.enforce([&iface]).is_ok());
+        let deny = Thresholds {
+            deny: true,
+            ..thresholds
+        };
+        assert!(deny.enforce([&iface]).is_err());
+        assert!(Thresholds::default().check(&iface).is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! Lockfiles pinning the interfaces used by a workspace.
+//!
+//! A `wit.lock` file records the hash (see [`interface_hash`]) of every
+//! interface file and registry reference that bindings are generated from.
+//! The bindgen macros and the CLI look for a lockfile in the crate directory
+//! and its ancestors, and refuse to generate bindings for an interface which
+//! is missing from it or no longer matches its recorded hash. This catches a
+//! shared interface being edited, or republished under the same version,
+//! without its consumers noticing.
+//!
+//! Each line of the lockfile holds a hash, the kind of entry and its name:
+//!
+//! ```text
+//! sha256:3a4f... file wit/contract.wit
+//! sha256:91bc... registry linera:contract@2
+//! ```
+//!
+//! File names are relative to the directory containing the lockfile.
+
+use crate::registry::{interface_hash, Registry, RegistryRef};
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wit_parser::Interface;
+
+/// The name of lockfiles.
+pub const LOCKFILE: &str = "wit.lock";
+
+const HEADER: &str = "\
+# This file is generated by `wit-bindgen lock` and records the hash of every
+# interface used in this workspace. It is not meant to be edited by hand.
+";
+
+/// Where an interface is loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterfaceSource {
+    /// A `*.wit` file.
+    File(PathBuf),
+    /// A published interface, fetched from the registry named by
+    /// [`REGISTRY_ENV`](crate::registry::REGISTRY_ENV).
+    Registry(RegistryRef),
+}
+
+impl InterfaceSource {
+    /// Loads and parses the interface.
+    ///
+    /// Registry interfaces are checked against the hash pinned for them in
+    /// `lockfile`, and can't be loaded without one.
+    pub fn load(&self, lockfile: Option<&Lockfile>) -> Result<Interface> {
+        match self {
+            InterfaceSource::File(path) => Interface::parse_file(path),
+            InterfaceSource::Registry(reference) => {
+                let pinned = match lockfile.and_then(|lockfile| lockfile.pinned(reference)) {
+                    Some(pinned) => pinned,
+                    None => bail!(
+                        "`{}` isn't pinned in a lockfile; record its hash with \
+                         `wit-bindgen lock --registry-ref {}`",
+                        reference,
+                        reference
+                    ),
+                };
+                Registry::from_env()?.fetch(reference, pinned)
+            }
+        }
+    }
+}
+
+/// The hashes recorded in a `wit.lock` file.
+#[derive(Debug, Clone)]
+pub struct Lockfile {
+    path: PathBuf,
+    dir: PathBuf,
+    entries: BTreeMap<String, String>,
+}
+
+impl Lockfile {
+    /// Creates an empty lockfile which will be saved to `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Result<Lockfile> {
+        let path = path.into();
+        let dir = match path.parent() {
+            Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("failed to resolve {:?}", dir))?;
+        Ok(Lockfile {
+            path,
+            dir,
+            entries: BTreeMap::new(),
+        })
+    }
+
+    /// Reads the lockfile at `path`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Lockfile> {
+        let mut lockfile = Lockfile::new(path)?;
+        let contents = fs::read_to_string(&lockfile.path)
+            .with_context(|| format!("failed to read {:?}", lockfile.path))?;
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once(' ') {
+                Some((hash, key)) if hash.starts_with("sha256:") => {
+                    lockfile.entries.insert(key.to_string(), hash.to_string());
+                }
+                _ => bail!("{:?}:{}: malformed lockfile entry", lockfile.path, i + 1),
+            }
+        }
+        Ok(lockfile)
+    }
+
+    /// Finds the lockfile governing `dir`, looking in `dir` and then in each
+    /// of its ancestors.
+    pub fn find(dir: &Path) -> Result<Option<Lockfile>> {
+        for dir in dir.ancestors() {
+            let path = dir.join(LOCKFILE);
+            if path.is_file() {
+                return Lockfile::load(path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns where this lockfile is saved.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks that `iface`, loaded from `source`, is recorded with its
+    /// current hash.
+    pub fn verify(&self, source: &InterfaceSource, iface: &Interface) -> Result<()> {
+        let key = self.key(source)?;
+        let hash = interface_hash(iface);
+        match self.entries.get(&key) {
+            None => bail!(
+                "`{}` is not recorded in {:?}; run `wit-bindgen lock` to add it",
+                key,
+                self.path
+            ),
+            Some(locked) if *locked != hash => bail!(
+                "`{}` has changed since it was recorded in {:?} (locked {}, found {}); \
+                 publish the change under a new version, or run `wit-bindgen lock` if \
+                 it is intended",
+                key,
+                self.path,
+                locked,
+                hash
+            ),
+            Some(_) => Ok(()),
+        }
+    }
+
+    /// Returns the hash recorded for the registry interface `reference`.
+    pub fn pinned(&self, reference: &RegistryRef) -> Option<&str> {
+        self.entries
+            .get(&format!("registry {}", reference))
+            .map(|hash| hash.as_str())
+    }
+
+    /// Records the current hash of `iface`, loaded from `source`.
+    pub fn insert(&mut self, source: &InterfaceSource, iface: &Interface) -> Result<()> {
+        let key = self.key(source)?;
+        self.entries.insert(key, interface_hash(iface));
+        Ok(())
+    }
+
+    /// Returns the sources of every recorded interface.
+    pub fn sources(&self) -> Result<Vec<InterfaceSource>> {
+        self.entries
+            .keys()
+            .map(|key| match key.split_once(' ') {
+                Some(("file", name)) => Ok(InterfaceSource::File(self.dir.join(name))),
+                Some(("registry", reference)) => Ok(InterfaceSource::Registry(reference.parse()?)),
+                _ => bail!("unknown lockfile entry `{}` in {:?}", key, self.path),
+            })
+            .collect()
+    }
+
+    /// Writes this lockfile back to disk.
+    pub fn save(&self) -> Result<()> {
+        fs::write(&self.path, self.to_string())
+            .with_context(|| format!("failed to write {:?}", self.path))
+    }
+
+    fn key(&self, source: &InterfaceSource) -> Result<String> {
+        match source {
+            InterfaceSource::File(path) => {
+                let path = path
+                    .canonicalize()
+                    .with_context(|| format!("failed to resolve {:?}", path))?;
+                let name = match path.strip_prefix(&self.dir) {
+                    Ok(relative) => relative
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join("/"),
+                    Err(_) => path.display().to_string(),
+                };
+                Ok(format!("file {}", name))
+            }
+            InterfaceSource::Registry(reference) => Ok(format!("registry {}", reference)),
+        }
+    }
+}
+
+impl std::fmt::Display for Lockfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(HEADER)?;
+        for (key, hash) in self.entries.iter() {
+            writeln!(f, "{} {}", hash, key)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_verify() {
+        let dir = std::env::temp_dir().join(format!("wit-lock-{}", std::process::id()));
+        fs::create_dir_all(dir.join("wit")).unwrap();
+        let wit = dir.join("wit/contract.wit");
+        fs::write(&wit, "run: func(x: u32)").unwrap();
+        let source = InterfaceSource::File(wit.clone());
+
+        let mut lockfile = Lockfile::new(dir.join(LOCKFILE)).unwrap();
+        let iface = source.load(None).unwrap();
+        assert!(lockfile.verify(&source, &iface).is_err());
+        lockfile.insert(&source, &iface).unwrap();
+        lockfile.save().unwrap();
+
+        let lockfile = Lockfile::find(&dir.join("wit")).unwrap().unwrap();
+        assert!(lockfile.to_string().contains(" file wit/contract.wit\n"));
+        assert_eq!(lockfile.sources().unwrap().len(), 1);
+        lockfile.verify(&source, &iface).unwrap();
+
+        fs::write(&wit, "run: func(x: u64)").unwrap();
+        let changed = source.load(Some(&lockfile)).unwrap();
+        let err = lockfile.verify(&source, &changed).unwrap_err();
+        assert!(err.to_string().contains("has changed"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn registry_interfaces_must_be_pinned() {
+        let source = InterfaceSource::Registry("linera:contract@2".parse().unwrap());
+        let err = source.load(None).unwrap_err();
+        assert!(err.to_string().contains("isn't pinned"));
+    }
+}
@@ -0,0 +1,92 @@
+//! Parsing of the braced configuration of the macros, such as
+//! `import!({ src["x"]: "...", unchecked })`.
+
+use crate::suggest;
+use std::collections::BTreeSet;
+use syn::ext::IdentExt;
+use syn::parse::{Error, Parse, ParseStream, Result};
+use syn::Token;
+
+/// The keys accepted in the braced configuration of a macro.
+pub struct Keys {
+    /// Every key, to suggest in place of unknown ones.
+    pub known: &'static [&'static str],
+    /// Keys which may be given several times, each adding to the others.
+    pub repeatable: &'static [&'static str],
+}
+
+impl Keys {
+    /// Parses the comma-separated fields of a braced configuration, rejecting
+    /// a key given twice at its second occurrence unless it is repeatable.
+    pub fn parse_fields<T: Parse>(&self, input: ParseStream<'_>) -> Result<Vec<T>> {
+        let mut keys = BTreeSet::new();
+        let mut fields = Vec::new();
+        while !input.is_empty() {
+            if let Ok(key) = input.fork().call(syn::Ident::parse_any) {
+                let name = key.to_string();
+                if !self.repeatable.contains(&name.as_str()) && !keys.insert(name) {
+                    return Err(Error::new(key.span(), format!("duplicate `{}`", key)));
+                }
+            }
+            fields.push(input.parse()?);
+            if input.is_empty() {
+                break;
+            }
+            input.parse::<Token![,]>()?;
+        }
+        Ok(fields)
+    }
+
+    /// Reports the unknown option which `input` starts with, suggesting the
+    /// closest known one.
+    pub fn unknown(&self, input: ParseStream<'_>) -> Error {
+        let key = match input.call(syn::Ident::parse_any) {
+            Ok(key) => key,
+            Err(e) => return e,
+        };
+        let name = key.to_string();
+        let msg = match suggest::closest(&name, self.known) {
+            Some(option) => format!("unknown option `{}`, did you mean `{}`?", name, option),
+            None => format!("unknown option `{}`", name),
+        };
+        Error::new(key.span(), msg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse::Parser;
+
+    const KEYS: Keys = Keys {
+        known: &["src", "paths"],
+        repeatable: &["src"],
+    };
+
+    #[test]
+    fn rejects_keys_given_twice_unless_repeatable() {
+        let parse = |input| {
+            (|input: ParseStream<'_>| KEYS.parse_fields::<syn::Ident>(input)).parse_str(input)
+        };
+        assert_eq!(parse("src, src, paths").unwrap().len(), 3);
+        assert_eq!(
+            parse("paths, src, paths").unwrap_err().to_string(),
+            "duplicate `paths`"
+        );
+    }
+
+    #[test]
+    fn suggests_known_keys() {
+        let unknown = |input| {
+            (|input: ParseStream<'_>| Err::<(), _>(KEYS.unknown(input)))
+                .parse_str(input)
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            unknown("path"),
+            "unknown option `path`, did you mean `paths`?"
+        );
+        assert_eq!(unknown("foo"), "unknown option `foo`");
+    }
+}
@@ -0,0 +1,185 @@
+//! Descriptions of the options of generators, so that tools can list them:
+//! the CLI for its help, the cargo subcommand to check metadata tables
+//! against, and editors to complete the keys of macro invocations.
+
+/// An option of a generator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionSchema {
+    /// The name of the option as the macros spell it, such as `types_path`.
+    pub name: &'static str,
+    /// The Rust type of the option, such as `Option<String>`.
+    pub ty: &'static str,
+    /// The documentation of the option, with its lines unindented.
+    pub docs: String,
+    /// The value the option defaults to, in `Debug` syntax.
+    pub default: String,
+    /// Whether the option can also be passed on the command line.
+    pub cli: bool,
+}
+
+impl OptionSchema {
+    /// Returns the flag setting the option on the command line, such as
+    /// `--types-path`.
+    pub fn flag(&self) -> String {
+        format!("--{}", self.name.replace('_', "-"))
+    }
+}
+
+/// The options of a generator, declared with [`options!`](crate::options).
+pub trait Options {
+    /// Describes every option, in the order they are declared in.
+    fn schema() -> Vec<OptionSchema>;
+}
+
+/// Renders `schema` as a JSON array with an object per option, whose keys
+/// are `name`, `type`, `docs`, `default` and `cli`.
+pub fn to_json(schema: &[OptionSchema]) -> String {
+    let options = schema
+        .iter()
+        .map(|option| {
+            serde_json::json!({
+                "name": option.name,
+                "type": option.ty,
+                "docs": option.docs,
+                "default": option.default,
+                "cli": option.cli,
+            })
+        })
+        .collect::<Vec<_>>();
+    serde_json::Value::Array(options).to_string()
+}
+
+/// Joins the lines of a doc comment, removing the space after each `///`.
+#[doc(hidden)]
+pub fn join_docs(lines: &[&str]) -> String {
+    let lines = lines
+        .iter()
+        .map(|line| line.strip_prefix(' ').unwrap_or(line));
+    lines.collect::<Vec<_>>().join("\n")
+}
+
+/// Declares the options struct of a generator, implementing [`Options`]
+/// for it from the names, types and doc comments of its fields, along with
+/// their values in its `Default` implementation.
+///
+/// Fields skipped by `structopt` are described as not available on the
+/// command line.
+///
+/// [`Options`]: crate::options::Options
+#[macro_export]
+macro_rules! options {
+    (
+        $(#[$($attr:tt)*])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$($field_attr:tt)*])*
+                $field_vis:vis $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$($attr)*])*
+        $vis struct $name {
+            $(
+                $(#[$($field_attr)*])*
+                $field_vis $field: $ty,
+            )*
+        }
+
+        impl $crate::options::Options for $name {
+            fn schema() -> Vec<$crate::options::OptionSchema> {
+                #[allow(unused_variables)]
+                let defaults = <$name as Default>::default();
+                vec![$({
+                    let (docs, cli) = $crate::__option_attrs!([] true $([$($field_attr)*])*);
+                    $crate::options::OptionSchema {
+                        name: stringify!($field),
+                        ty: stringify!($ty),
+                        docs: $crate::options::join_docs(docs),
+                        default: format!("{:?}", defaults.$field),
+                        cli,
+                    }
+                }),*]
+            }
+        }
+    };
+}
+
+/// Collects the doc comments among the attributes of a field of
+/// [`options!`], and whether `structopt` skips it.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __option_attrs {
+    ([$($docs:literal,)*] $cli:tt) => {
+        (&[$($docs),*] as &[&str], $cli)
+    };
+    ([$($docs:literal,)*] $cli:tt [doc = $doc:literal] $($rest:tt)*) => {
+        $crate::__option_attrs!([$($docs,)* $doc,] $cli $($rest)*)
+    };
+    (
+        [$($docs:literal,)*] $cli:tt
+        [cfg_attr(feature = "structopt", structopt(skip))] $($rest:tt)*
+    ) => {
+        $crate::__option_attrs!([$($docs,)*] false $($rest)*)
+    };
+    ([$($docs:literal,)*] $cli:tt [$($attr:tt)*] $($rest:tt)*) => {
+        $crate::__option_attrs!([$($docs,)*] $cli $($rest)*)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::options! {
+        #[derive(Default, Debug)]
+        struct Opts {
+            /// Whether checks are skipped.
+            ///
+            /// Only for trusted hosts.
+            pub unchecked: bool,
+
+            /// A prefix for exported symbols.
+            #[allow(dead_code)]
+            pub symbol_namespace: String,
+
+            pub max_list_len: Option<usize>,
+        }
+    }
+
+    #[test]
+    fn describes_fields() {
+        let schema = Opts::schema();
+        let names = schema.iter().map(|o| o.name).collect::<Vec<_>>();
+        assert_eq!(names, ["unchecked", "symbol_namespace", "max_list_len"]);
+
+        assert_eq!(schema[0].ty, "bool");
+        assert_eq!(
+            schema[0].docs,
+            "Whether checks are skipped.\n\nOnly for trusted hosts."
+        );
+        assert_eq!(schema[0].default, "false");
+        assert!(schema[0].cli);
+        assert_eq!(schema[0].flag(), "--unchecked");
+
+        assert_eq!(schema[1].default, "\"\"");
+
+        assert_eq!(schema[2].ty, "Option<usize>");
+        assert_eq!(schema[2].docs, "");
+        assert_eq!(schema[2].flag(), "--max-list-len");
+    }
+
+    #[test]
+    fn renders_json() {
+        let json = to_json(&Opts::schema()[1..2]);
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+            serde_json::json!([{
+                "name": "symbol_namespace",
+                "type": "String",
+                "docs": "A prefix for exported symbols.",
+                "default": "\"\"",
+                "cli": true,
+            }])
+        );
+    }
+}
@@ -0,0 +1,117 @@
+//! Call counts recorded in production, used to target generated optimizations.
+//!
+//! A profile is a text file with one `interface.function count` entry per
+//! line, where blank lines and lines starting with `#` are ignored:
+//!
+//! ```text
+//! # calls per function over one day
+//! storage.read 120412
+//! storage.hash 118003
+//! storage.compact 0
+//! ```
+//!
+//! Generators use it to decide which functions are hot enough to get inlined
+//! shims, or to keep batching and caching for, and which are never called.
+
+use anyhow::{bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// How many calls make a function hot, unless configured otherwise.
+pub const DEFAULT_HOT_CALLS: u64 = 1000;
+
+/// The number of calls of every function in a profile, by interface.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    calls: BTreeMap<String, BTreeMap<String, u64>>,
+}
+
+impl Profile {
+    /// Reads the profile at `path`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Profile> {
+        let path = path.as_ref();
+        let contents =
+            fs::read_to_string(path).with_context(|| format!("failed to read {:?}", path))?;
+        Profile::parse(&contents).with_context(|| format!("invalid profile {:?}", path))
+    }
+
+    /// Parses the contents of a profile.
+    pub fn parse(contents: &str) -> Result<Profile> {
+        let mut profile = Profile::default();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, count) = match line.split_once(char::is_whitespace) {
+                Some((name, count)) => (name, count.trim()),
+                None => bail!("line {}: expected `interface.function count`", i + 1),
+            };
+            let (iface, func) = match name.split_once('.') {
+                Some(names) => names,
+                None => bail!("line {}: `{}` is not `interface.function`", i + 1, name),
+            };
+            let count = count
+                .parse::<u64>()
+                .with_context(|| format!("line {}: invalid call count `{}`", i + 1, count))?;
+            *profile
+                .calls
+                .entry(iface.to_string())
+                .or_default()
+                .entry(func.to_string())
+                .or_default() += count;
+        }
+        Ok(profile)
+    }
+
+    /// Returns how many times `func` of `iface` was called, which is zero if
+    /// the profile doesn't mention it.
+    pub fn calls(&self, iface: &str, func: &str) -> u64 {
+        self.calls
+            .get(iface)
+            .and_then(|funcs| funcs.get(func))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns whether `func` of `iface` was called at least `hot_calls`
+    /// times.
+    pub fn is_hot(&self, iface: &str, func: &str, hot_calls: u64) -> bool {
+        self.calls(iface, func) >= hot_calls.max(1)
+    }
+
+    /// Returns whether `func` of `iface` was never called.
+    pub fn is_cold(&self, iface: &str, func: &str) -> bool {
+        self.calls(iface, func) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_call_counts() {
+        let profile = Profile::parse(
+            "
+                # calls per function
+                storage.read 1500
+                storage.hash 20
+                storage.read 500
+                storage.compact 0
+            ",
+        )
+        .unwrap();
+        assert_eq!(profile.calls("storage", "read"), 2000);
+        assert!(profile.is_hot("storage", "read", DEFAULT_HOT_CALLS));
+        assert!(!profile.is_hot("storage", "hash", DEFAULT_HOT_CALLS));
+        assert!(profile.is_cold("storage", "compact"));
+        assert!(profile.is_cold("storage", "missing"));
+        assert!(!profile.is_hot("storage", "compact", 0));
+
+        assert!(Profile::parse("storage.read").is_err());
+        assert!(Profile::parse("read 10").is_err());
+        assert!(Profile::parse("storage.read many").is_err());
+    }
+}
@@ -0,0 +1,289 @@
+//! Publishing and fetching interfaces through a schema registry.
+//!
+//! A registry stores every published version of an interface as three files
+//! next to each other: the WIT source, the interface's canonical JSON (see
+//! [`Interface::canonical_json`]) and the SHA-256 hash of that JSON. Fetching
+//! an interface parses the source again and checks that it hashes to the
+//! value pinned by its consumer, usually in their lockfile (see
+//! [`crate::lock`]), so a registry entry which was edited after the fact is
+//! rejected instead of silently changing the generated bindings. The hash
+//! published next to it is only trusted when a consumer first records it.
+//!
+//! The registry root is either a local directory, for example a checkout of a
+//! shared repository, or an `http://` or `https://` URL. Remote registries are
+//! accessed with `curl`, using `GET` to fetch and `PUT` to publish.
+
+use anyhow::{anyhow, bail, Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::Mutex;
+use wit_parser::Interface;
+
+/// The environment variable holding the registry root used by the bindgen
+/// macros.
+pub const REGISTRY_ENV: &str = "WIT_BINDGEN_REGISTRY";
+
+/// A published version of an interface, written `namespace:name@version`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RegistryRef {
+    pub namespace: String,
+    pub name: String,
+    pub version: String,
+}
+
+impl FromStr for RegistryRef {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<RegistryRef> {
+        let (namespace, rest) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("registry reference `{}` is missing a namespace", s))?;
+        let (name, version) = rest
+            .rsplit_once('@')
+            .ok_or_else(|| anyhow!("registry reference `{}` is missing a version", s))?;
+        for part in [namespace, name, version] {
+            let valid = !part.is_empty()
+                && !part.starts_with('.')
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+            if !valid {
+                bail!("invalid component `{}` in registry reference `{}`", part, s);
+            }
+        }
+        Ok(RegistryRef {
+            namespace: namespace.to_string(),
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for RegistryRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}@{}", self.namespace, self.name, self.version)
+    }
+}
+
+/// Returns the hash identifying `iface`, in the form `sha256:<hex>`.
+pub fn interface_hash(iface: &Interface) -> String {
+    let digest = Sha256::digest(iface.canonical_json().as_bytes());
+    let mut hash = String::from("sha256:");
+    for byte in digest.iter() {
+        hash.push_str(&format!("{:02x}", byte));
+    }
+    hash
+}
+
+/// A registry of published interfaces.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    root: String,
+}
+
+impl Registry {
+    /// Creates a registry rooted at the directory or URL `root`.
+    pub fn new(root: impl Into<String>) -> Registry {
+        Registry { root: root.into() }
+    }
+
+    /// Creates a registry rooted at the value of [`REGISTRY_ENV`].
+    pub fn from_env() -> Result<Registry> {
+        match std::env::var(REGISTRY_ENV) {
+            Ok(root) if !root.is_empty() => Ok(Registry::new(root)),
+            _ => bail!(
+                "`{}` must be set to a directory or URL to use registry interfaces",
+                REGISTRY_ENV
+            ),
+        }
+    }
+
+    /// Publishes the WIT `source` as `reference`, returning its hash.
+    pub fn publish(&self, reference: &RegistryRef, source: &str) -> Result<String> {
+        let iface = Interface::parse(&reference.name, source)
+            .with_context(|| format!("failed to parse `{}`", reference))?;
+        let hash = interface_hash(&iface);
+        self.write(reference, "wit", source)?;
+        self.write(reference, "json", &iface.canonical_json())?;
+        self.write(reference, "sha256", &hash)?;
+        Ok(hash)
+    }
+
+    /// Returns the hash `reference` was published with, to be recorded by a
+    /// consumer which trusts the registry as it is now.
+    pub fn published_hash(&self, reference: &RegistryRef) -> Result<String> {
+        Ok(self.read(reference, "sha256")?.trim().to_string())
+    }
+
+    /// Fetches `reference`, checking that it hashes to `pinned`.
+    ///
+    /// Interfaces are only fetched once per process for each pinned hash, as
+    /// the macros of a workspace often bind the same ones.
+    pub fn fetch(&self, reference: &RegistryRef, pinned: &str) -> Result<Interface> {
+        type Fetched = BTreeMap<(String, String, String), Interface>;
+        static FETCHED: Mutex<Fetched> = Mutex::new(BTreeMap::new());
+
+        let key = (self.root.clone(), reference.to_string(), pinned.to_string());
+        if let Some(iface) = FETCHED.lock().unwrap().get(&key) {
+            return Ok(iface.clone());
+        }
+        let (iface, _) = self.fetch_with_source(reference, pinned)?;
+        FETCHED.lock().unwrap().insert(key, iface.clone());
+        Ok(iface)
+    }
+
+    /// Like [`Registry::fetch`], but always fetches, and also returns the WIT
+    /// source.
+    pub fn fetch_with_source(
+        &self,
+        reference: &RegistryRef,
+        pinned: &str,
+    ) -> Result<(Interface, String)> {
+        let source = self.read(reference, "wit")?;
+        let iface = Interface::parse(&reference.name, &source)
+            .with_context(|| format!("failed to parse `{}` from the registry", reference))?;
+        let actual = interface_hash(&iface);
+        if actual != pinned {
+            bail!(
+                "`{}` is pinned to hash {} but the registry's copy hashes to {}",
+                reference,
+                pinned,
+                actual
+            );
+        }
+        Ok((iface, source))
+    }
+
+    fn is_remote(&self) -> bool {
+        self.root.starts_with("http://") || self.root.starts_with("https://")
+    }
+
+    fn location(&self, reference: &RegistryRef, ext: &str) -> String {
+        format!(
+            "{}/{}/{}/{}.{}",
+            self.root.trim_end_matches('/'),
+            reference.namespace,
+            reference.name,
+            reference.version,
+            ext
+        )
+    }
+
+    fn read(&self, reference: &RegistryRef, ext: &str) -> Result<String> {
+        let location = self.location(reference, ext);
+        if !self.is_remote() {
+            return fs::read_to_string(&location)
+                .with_context(|| format!("failed to read `{}` for `{}`", location, reference));
+        }
+        let output = Command::new("curl")
+            .arg("-fsSL")
+            .arg(&location)
+            .output()
+            .context("failed to run `curl`")?;
+        if !output.status.success() {
+            bail!(
+                "failed to fetch `{}` for `{}`: {}",
+                location,
+                reference,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout)
+            .with_context(|| format!("`{}` is not valid UTF-8", location))
+    }
+
+    fn write(&self, reference: &RegistryRef, ext: &str, contents: &str) -> Result<()> {
+        let location = self.location(reference, ext);
+        if !self.is_remote() {
+            if let Some(parent) = Path::new(&location).parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("failed to create `{}`", parent.display()))?;
+            }
+            return fs::write(&location, contents)
+                .with_context(|| format!("failed to write `{}`", location));
+        }
+        let mut child = Command::new("curl")
+            .arg("-fsS")
+            .arg("-X")
+            .arg("PUT")
+            .arg("--data-binary")
+            .arg("@-")
+            .arg(&location)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("failed to run `curl`")?;
+        child.stdin.take().unwrap().write_all(contents.as_bytes())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(
+                "failed to publish `{}`: {}",
+                location,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reference() {
+        let r = "linera:contract@2".parse::<RegistryRef>().unwrap();
+        assert_eq!(r.namespace, "linera");
+        assert_eq!(r.name, "contract");
+        assert_eq!(r.version, "2");
+        assert_eq!(r.to_string(), "linera:contract@2");
+
+        assert!("contract@2".parse::<RegistryRef>().is_err());
+        assert!("linera:contract".parse::<RegistryRef>().is_err());
+        assert!("linera:../contract@2".parse::<RegistryRef>().is_err());
+        assert!("linera:contract@".parse::<RegistryRef>().is_err());
+    }
+
+    #[test]
+    fn publish_and_fetch() {
+        let root = std::env::temp_dir().join(format!("wit-registry-{}", std::process::id()));
+        let registry = Registry::new(root.to_str().unwrap());
+        let reference = "linera:contract@2".parse::<RegistryRef>().unwrap();
+
+        let hash = registry
+            .publish(&reference, "/// Runs.\nrun: func(x: u32) -> string")
+            .unwrap();
+        assert_eq!(registry.published_hash(&reference).unwrap(), hash);
+        let (iface, _) = registry.fetch_with_source(&reference, &hash).unwrap();
+        assert_eq!(interface_hash(&iface), hash);
+        assert_eq!(iface.functions[0].name, "run");
+
+        // Doc comments don't change the hash, but signatures do.
+        fs::write(
+            root.join("linera/contract/2.wit"),
+            "run: func(x: u32) -> string",
+        )
+        .unwrap();
+        registry.fetch_with_source(&reference, &hash).unwrap();
+        fs::write(
+            root.join("linera/contract/2.wit"),
+            "run: func(x: u64) -> string",
+        )
+        .unwrap();
+        assert!(registry.fetch_with_source(&reference, &hash).is_err());
+        // Republishing doesn't help, as the hash is pinned by the consumer.
+        registry
+            .publish(&reference, "run: func(x: u64) -> string")
+            .unwrap();
+        assert!(registry.fetch_with_source(&reference, &hash).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,285 @@
+//! Attributes the code of a compiled guest to the functions of its interfaces.
+//!
+//! Generated bindings name their code after the interface functions they bind:
+//!
+//! * export shims are exported under the canonical ABI name of the function,
+//!   optionally prefixed with a symbol namespace or a `module#`, and are
+//!   defined as `__wit_bindgen_{interface}_{function}`;
+//! * import shims are Rust functions at `{interface}::{function}`, or
+//!   `{interface}::{Resource}::{function}` for resource methods, whose legacy
+//!   mangled symbols keep those path segments.
+//!
+//! [`SizeReport::analyze`] matches the function names of a module's name
+//! section, along with its exports, against these schemes and sums up the
+//! size of the matching code bodies. It only sees what survived the linker
+//! and optimizers, so code inlined into a shim counts towards it and shims
+//! inlined into their callers are missing from the report altogether.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use wasmparser::{ExternalKind, Name, NameSectionReader, Parser, Payload, TypeRef};
+use wit_parser::{Function, Interface};
+
+/// The code generated for one interface function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FunctionSize {
+    pub interface: String,
+    pub function: String,
+    /// Bytes of code of the shims exporting the function.
+    pub export_bytes: u64,
+    /// Bytes of code of the shims calling the imported function.
+    pub import_bytes: u64,
+}
+
+impl FunctionSize {
+    pub fn total(&self) -> u64 {
+        self.export_bytes + self.import_bytes
+    }
+}
+
+/// The code size of a module, broken down by interface function.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeReport {
+    /// Functions with code attributed to them, largest first.
+    pub functions: Vec<FunctionSize>,
+    /// Bytes of code not attributed to any interface function.
+    pub other_bytes: u64,
+    /// Bytes of code in the whole module.
+    pub total_bytes: u64,
+}
+
+impl SizeReport {
+    /// Reads the compiled module `wasm` and attributes its code to the
+    /// functions of `interfaces`.
+    pub fn analyze(wasm: &[u8], interfaces: &[Interface]) -> Result<SizeReport> {
+        let mut imported_funcs = 0;
+        let mut bodies = Vec::new();
+        let mut names = HashMap::new();
+        let mut exports = HashMap::<u32, Vec<String>>::new();
+        for payload in Parser::new(0).parse_all(wasm) {
+            match payload.context("failed to parse module")? {
+                Payload::ImportSection(s) => {
+                    for import in s {
+                        if let TypeRef::Func(_) = import?.ty {
+                            imported_funcs += 1;
+                        }
+                    }
+                }
+                Payload::ExportSection(s) => {
+                    for export in s {
+                        let export = export?;
+                        if let ExternalKind::Func = export.kind {
+                            exports
+                                .entry(export.index)
+                                .or_default()
+                                .push(export.name.to_string());
+                        }
+                    }
+                }
+                Payload::CodeSectionEntry(body) => {
+                    let range = body.range();
+                    bodies.push((range.end - range.start) as u64);
+                }
+                Payload::CustomSection(s) if s.name() == "name" => {
+                    let mut reader = NameSectionReader::new(s.data(), s.data_offset())?;
+                    while !reader.eof() {
+                        if let Name::Function(map) = reader.read()? {
+                            let mut map = map.get_map()?;
+                            for _ in 0..map.get_count() {
+                                let naming = map.read()?;
+                                names.insert(naming.index, naming.name.to_string());
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut functions = BTreeMap::new();
+        let mut report = SizeReport::default();
+        for (i, size) in bodies.into_iter().enumerate() {
+            let index = imported_funcs + i as u32;
+            report.total_bytes += size;
+            let name = names.get(&index).map(|s| s.as_str());
+            let exports = exports.get(&index).map(|e| &e[..]).unwrap_or(&[]);
+            let (iface, func, is_export) = match attribute(interfaces, name, exports) {
+                Some(found) => found,
+                None => {
+                    report.other_bytes += size;
+                    continue;
+                }
+            };
+            let entry = functions
+                .entry((iface.name.clone(), func.name.clone()))
+                .or_insert_with(|| FunctionSize {
+                    interface: iface.name.clone(),
+                    function: func.name.clone(),
+                    ..FunctionSize::default()
+                });
+            if is_export {
+                entry.export_bytes += size;
+            } else {
+                entry.import_bytes += size;
+            }
+        }
+
+        report.functions = functions.into_values().collect();
+        report
+            .functions
+            .sort_by(|a, b| b.total().cmp(&a.total()).then(a.function.cmp(&b.function)));
+        Ok(report)
+    }
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10}  function",
+            "total", "export", "import"
+        )?;
+        for func in self.functions.iter() {
+            writeln!(
+                f,
+                "{:>10} {:>10} {:>10}  {}.{}",
+                func.total(),
+                func.export_bytes,
+                func.import_bytes,
+                func.interface,
+                func.function
+            )?;
+        }
+        let bindings = self.total_bytes - self.other_bytes;
+        writeln!(f, "{:>10} {:>10} {:>10}  (bindings)", bindings, "", "")?;
+        writeln!(f, "{:>10} {:>10} {:>10}  (other)", self.other_bytes, "", "")?;
+        write!(f, "{:>10} {:>10} {:>10}  (total)", self.total_bytes, "", "")
+    }
+}
+
+/// Finds the interface function a code body was generated for, and whether
+/// it exports rather than imports it.
+fn attribute<'a>(
+    interfaces: &'a [Interface],
+    name: Option<&str>,
+    exports: &[String],
+) -> Option<(&'a Interface, &'a Function, bool)> {
+    let path = name.and_then(demangle_path);
+    for iface in interfaces {
+        let iface_name = snake(&iface.name);
+        for func in iface.functions.iter() {
+            let mangled = iface.mangle_funcname(func);
+            let shim = format!("__wit_bindgen_{}_{}", iface_name, snake(&func.name));
+            let is_export =
+                exports.iter().any(|e| e.ends_with(&mangled)) || name.is_some_and(|n| n == shim);
+            if is_export {
+                return Some((iface, func, true));
+            }
+
+            // The path of an import shim ends in the function's name, below
+            // the interface's module and possibly a resource.
+            if let Some(path) = &path {
+                let item = snake(func.item_name());
+                if let Some((last, parents)) = path.split_last() {
+                    let in_iface = parents.iter().rev().take(2).any(|p| *p == iface_name);
+                    if *last == item && in_iface {
+                        return Some((iface, func, false));
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Splits a legacy mangled Rust symbol into the segments of its path,
+/// without the trailing hash.
+fn demangle_path(symbol: &str) -> Option<Vec<&str>> {
+    let mut rest = symbol
+        .strip_prefix("_ZN")
+        .or_else(|| symbol.strip_prefix("__ZN"))?;
+    let mut path = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.find(|c: char| !c.is_ascii_digit())?;
+        let len = rest[..digits].parse::<usize>().ok()?;
+        let segment = rest.get(digits..digits + len)?;
+        path.push(segment);
+        rest = &rest[digits + len..];
+    }
+    if let Some(last) = path.last() {
+        if last.len() == 17 && last.starts_with('h') {
+            path.pop();
+        }
+    }
+    Some(path)
+}
+
+/// The snake-case Rust name generators give to a WIT identifier, such as
+/// `counter_bump` for `counter::bump`.
+fn snake(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attributes_shims_to_functions() {
+        let iface = Interface::parse(
+            "host",
+            "
+                resource counter {
+                    bump: func()
+                }
+                ping: func(x: u32) -> u32
+                read-all: func() -> string
+            ",
+        )
+        .unwrap();
+        let read_all = iface.mangle_funcname(&iface.functions[2]);
+        let wat = format!(
+            r#"
+                (module
+                    (func $_ZN5guest4host4ping17h0123456789abcdefE (result i32)
+                        i32.const 1
+                        i32.const 2
+                        i32.add)
+                    (func $_ZN5guest4host7Counter4bump17h0123456789abcdefE)
+                    (func $__wit_bindgen_host_read_all (export "{}"))
+                    (func $_ZN5guest4main17h0123456789abcdefE
+                        nop)
+                )
+            "#,
+            read_all
+        );
+        let wasm = wat::parse_str(&wat).unwrap();
+        let report = SizeReport::analyze(&wasm, &[iface]).unwrap();
+
+        let sizes = report
+            .functions
+            .iter()
+            .map(|f| (f.function.as_str(), f.export_bytes, f.import_bytes))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            [("ping", 0, 7), ("counter::bump", 0, 2), ("read-all", 2, 0)]
+        );
+        assert_eq!(report.other_bytes, 3);
+        assert_eq!(report.total_bytes, 14);
+    }
+
+    #[test]
+    fn demangles_legacy_symbols() {
+        assert_eq!(
+            demangle_path("_ZN5guest4host4ping17h0123456789abcdefE"),
+            Some(vec!["guest", "host", "ping"])
+        );
+        assert_eq!(demangle_path("__wit_bindgen_host_ping"), None);
+        assert_eq!(demangle_path("_ZN5gue"), None);
+    }
+}
@@ -0,0 +1,166 @@
+//! Summarizes the size and shape of a set of interfaces.
+//!
+//! [`Stats::collect`] counts the functions and types of interfaces, how
+//! deeply their types nest and how many core wasm values their signatures
+//! flatten to, which are what make bindings slow to compile and to call.
+//! [`Stats::measure`] adds the size of the code a generator emits for them,
+//! so that changes to an interface can be reviewed for what they cost each
+//! language binding it.
+
+use crate::Files;
+use std::fmt;
+use wit_parser::abi::AbiVariant;
+use wit_parser::{Interface, Type};
+
+/// How many signatures [`Stats`] prints, widest first.
+const SHOWN_SIGNATURES: usize = 10;
+
+/// The flattened signature of an interface function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    pub interface: String,
+    pub function: String,
+    /// The number of core wasm values the parameters flatten to.
+    pub params: usize,
+    /// The number of core wasm values the result flattens to.
+    pub results: usize,
+}
+
+impl Signature {
+    pub fn width(&self) -> usize {
+        self.params + self.results
+    }
+}
+
+/// The statistics of a set of interfaces.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of interfaces, nested ones included.
+    pub interfaces: usize,
+    pub functions: usize,
+    /// The number of named types.
+    pub types: usize,
+    /// How deeply the most nested type nests, as in
+    /// [`Interface::nesting_depth`].
+    pub type_depth: usize,
+    /// The signatures of all functions, widest first.
+    pub signatures: Vec<Signature>,
+    /// The bytes of code generated for the interfaces, by language.
+    pub code_sizes: Vec<(String, usize)>,
+}
+
+impl Stats {
+    /// Collects the statistics of `interfaces` and the interfaces nested in
+    /// them.
+    pub fn collect<'a>(interfaces: impl IntoIterator<Item = &'a Interface>) -> Stats {
+        let mut stats = Stats::default();
+        for iface in interfaces {
+            stats.add(iface);
+        }
+        stats
+            .signatures
+            .sort_by(|a, b| b.width().cmp(&a.width()).then(b.params.cmp(&a.params)));
+        stats
+    }
+
+    fn add(&mut self, iface: &Interface) {
+        self.interfaces += 1;
+        self.functions += iface.functions.len();
+        for (id, ty) in iface.types.iter() {
+            if ty.name.is_some() {
+                self.types += 1;
+            }
+            self.type_depth = self.type_depth.max(iface.nesting_depth(&Type::Id(id)));
+        }
+        for func in iface.functions.iter() {
+            self.signatures.push(Signature {
+                interface: iface.name.clone(),
+                function: func.name.clone(),
+                params: iface.flat_param_count(AbiVariant::GuestImport, func),
+                results: iface.flat_result_count(AbiVariant::GuestImport, func),
+            });
+        }
+        for (_, nested) in iface.interfaces.iter() {
+            self.add(nested);
+        }
+    }
+
+    /// Records the size of the `files` generated for `language`.
+    pub fn measure(&mut self, language: &str, files: &Files) {
+        let bytes = files.iter().map(|(_, contents)| contents.len()).sum();
+        self.code_sizes.push((language.to_string(), bytes));
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:>10}  interfaces", self.interfaces)?;
+        writeln!(f, "{:>10}  functions", self.functions)?;
+        writeln!(f, "{:>10}  types", self.types)?;
+        writeln!(f, "{:>10}  type depth", self.type_depth)?;
+        if !self.signatures.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:>10} {:>10}  widest signatures", "params", "results")?;
+            for sig in self.signatures.iter().take(SHOWN_SIGNATURES) {
+                writeln!(
+                    f,
+                    "{:>10} {:>10}  {}.{}",
+                    sig.params, sig.results, sig.interface, sig.function
+                )?;
+            }
+        }
+        if !self.code_sizes.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "{:>10}  generated code", "bytes")?;
+            for (language, bytes) in self.code_sizes.iter() {
+                writeln!(f, "{:>10}  {}", bytes, language)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_functions_types_and_flat_values() {
+        let iface = Interface::parse(
+            "shop",
+            "
+                record item {
+                    name: string,
+                    tags: list<list<string>>,
+                }
+                enum color { red, green }
+                add: func(item: item, count: u32) -> u64
+                clear: func()
+                paint: func(c: color) -> option<item>
+            ",
+        )
+        .unwrap();
+        let mut stats = Stats::collect([&iface]);
+        assert_eq!(stats.interfaces, 1);
+        assert_eq!(stats.functions, 3);
+        assert_eq!(stats.types, 2);
+        assert_eq!(stats.type_depth, 4);
+
+        let widths = stats
+            .signatures
+            .iter()
+            .map(|s| (s.function.as_str(), s.params, s.results))
+            .collect::<Vec<_>>();
+        assert_eq!(widths, [("add", 5, 1), ("paint", 1, 5), ("clear", 0, 0)]);
+
+        let mut files = Files::default();
+        files.push("shop.rs", b"fn add() {}");
+        files.push("shop.h", b"void add();");
+        stats.measure("rust", &files);
+        assert_eq!(stats.code_sizes, [("rust".to_string(), 22)]);
+        let report = stats.to_string();
+        assert!(report.contains("         3  functions\n"));
+        assert!(report.contains("         1          5  shop.paint\n"));
+        assert!(report.contains("        22  rust\n"));
+    }
+}
@@ -0,0 +1,45 @@
+//! Suggestions for misspelled names, such as the options of the macros.
+
+/// Returns the candidate closest to `name`, if any is close enough to be
+/// what was meant: within a third of the length of `name` in edits.
+pub fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max = name.chars().count().div_ceil(3);
+    candidates
+        .iter()
+        .map(|candidate| (distance(name, candidate), *candidate))
+        .filter(|(d, _)| *d <= max)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev + usize::from(ca != *cb);
+            prev = row[j + 1];
+            row[j + 1] = substitute.min(prev + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_close_names_only() {
+        let options = ["paths", "unchecked", "types_path", "types"];
+        assert_eq!(closest("path", &options), Some("paths"));
+        assert_eq!(closest("uncheked", &options), Some("unchecked"));
+        assert_eq!(closest("type_path", &options), Some("types_path"));
+        assert_eq!(closest("types", &options), Some("types"));
+        assert_eq!(closest("foo", &options), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
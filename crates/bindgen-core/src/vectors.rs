@@ -0,0 +1,627 @@
+//! Canonical test vectors for the types of an interface.
+//!
+//! A test vector pairs a value, written as a WIT value literal, with the bytes
+//! the canonical ABI stores for it in linear memory. Implementations in other
+//! languages can check their marshaling against a corpus of them without
+//! running any of our code.
+//!
+//! The bytes are a memory image starting at address 0: the value itself comes
+//! first, laid out as [`SizeAlign`] describes it, and the contents of its
+//! strings and lists follow in the order they are reached, each placed at the
+//! next address aligned for its elements. Pointers in the image are addresses
+//! within it, empty lists point where their contents would have gone, and
+//! padding is zeroed.
+//!
+//! Literals follow the WebAssembly value syntax: `{a: 1, b: "x"}` for
+//! records, `(1, 2)` for tuples, `[1, 2]` for lists, `{read, write}` for
+//! flags, `case(payload)` or just `case` for variants and enums, `some(x)`,
+//! `none`, `ok(x)` and `err(x)`. Floats are written `nan`, `inf` and `-inf`
+//! when not finite, the unit value is `()`, and the case of a union, which has
+//! no name, is written by its index as `%1(payload)`.
+
+use anyhow::{Context, Result};
+use std::fmt::{self, Write as _};
+use std::path::Path;
+use wit_parser::{FlagsRepr, Int, Interface, SizeAlign, Type, TypeDefKind};
+
+/// A value of a WIT type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    S8(i8),
+    S16(i16),
+    S32(i32),
+    S64(i64),
+    Float32(f32),
+    Float64(f64),
+    Char(char),
+    String(String),
+    List(Vec<Value>),
+    Record(Vec<(String, Value)>),
+    Tuple(Vec<Value>),
+    /// The names of the flags which are set.
+    Flags(Vec<String>),
+    Variant(String, Box<Value>),
+    Enum(String),
+    Union(usize, Box<Value>),
+    Option(Option<Box<Value>>),
+    Result(Result<Box<Value>, Box<Value>>),
+}
+
+/// A value paired with the bytes it is stored as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    pub value: Value,
+    pub bytes: Vec<u8>,
+}
+
+/// Returns test vectors for `ty`, covering the edge cases of each primitive
+/// in it and every case of each variant, or `None` if it contains resources,
+/// futures or streams, which have no value outside of an instance.
+pub fn vectors(iface: &Interface, sizes: &SizeAlign, ty: &Type) -> Option<Vec<TestVector>> {
+    let values = samples(iface, ty)?;
+    Some(
+        values
+            .into_iter()
+            .map(|value| TestVector {
+                bytes: store(iface, sizes, ty, &value),
+                value,
+            })
+            .collect(),
+    )
+}
+
+/// Writes the test vectors of every named type of `iface` to `dir`, one
+/// `{type}.txt` file per type, returning how many vectors were written.
+///
+/// Each line of a file holds a literal and the hex-encoded bytes of a vector,
+/// separated by a tab, after comment lines starting with `#`.
+pub fn write_corpus(iface: &Interface, dir: &Path) -> Result<usize> {
+    let mut sizes = SizeAlign::default();
+    sizes.fill(iface);
+    std::fs::create_dir_all(dir).with_context(|| format!("failed to create {:?}", dir))?;
+
+    let mut count = 0;
+    for (id, def) in iface.types.iter() {
+        let name = match &def.name {
+            Some(name) => name,
+            None => continue,
+        };
+        let ty = Type::Id(id);
+        let vectors = match vectors(iface, &sizes, &ty) {
+            Some(vectors) => vectors,
+            None => continue,
+        };
+        let mut contents = String::new();
+        writeln!(contents, "# {}.{}", iface.name, name).unwrap();
+        writeln!(
+            contents,
+            "# size {}, align {}",
+            sizes.size(&ty),
+            sizes.align(&ty)
+        )
+        .unwrap();
+        for vector in vectors.iter() {
+            write!(contents, "{}\t", vector.value).unwrap();
+            for byte in vector.bytes.iter() {
+                write!(contents, "{:02x}", byte).unwrap();
+            }
+            contents.push('\n');
+        }
+        let path = dir.join(format!("{}.txt", name));
+        std::fs::write(&path, contents).with_context(|| format!("failed to write {:?}", path))?;
+        count += vectors.len();
+    }
+    Ok(count)
+}
+
+/// Returns values of `ty`, varying one part of a compound value at a time
+/// so that the number of samples grows with the size of the type rather than
+/// combinatorially.
+fn samples(iface: &Interface, ty: &Type) -> Option<Vec<Value>> {
+    let values = match ty {
+        Type::Unit => vec![Value::Unit],
+        Type::Bool => vec![Value::Bool(false), Value::Bool(true)],
+        Type::U8 => vec![Value::U8(0), Value::U8(1), Value::U8(u8::MAX)],
+        Type::U16 => vec![Value::U16(0), Value::U16(1), Value::U16(u16::MAX)],
+        Type::U32 => vec![Value::U32(0), Value::U32(1), Value::U32(u32::MAX)],
+        Type::U64 => vec![Value::U64(0), Value::U64(1), Value::U64(u64::MAX)],
+        Type::S8 => vec![
+            Value::S8(0),
+            Value::S8(-1),
+            Value::S8(i8::MIN),
+            Value::S8(i8::MAX),
+        ],
+        Type::S16 => vec![
+            Value::S16(0),
+            Value::S16(-1),
+            Value::S16(i16::MIN),
+            Value::S16(i16::MAX),
+        ],
+        Type::S32 => vec![
+            Value::S32(0),
+            Value::S32(-1),
+            Value::S32(i32::MIN),
+            Value::S32(i32::MAX),
+        ],
+        Type::S64 => vec![
+            Value::S64(0),
+            Value::S64(-1),
+            Value::S64(i64::MIN),
+            Value::S64(i64::MAX),
+        ],
+        Type::Float32 => vec![
+            Value::Float32(0.0),
+            Value::Float32(-1.5),
+            Value::Float32(f32::INFINITY),
+            Value::Float32(f32::NEG_INFINITY),
+            Value::Float32(f32::NAN),
+        ],
+        Type::Float64 => vec![
+            Value::Float64(0.0),
+            Value::Float64(-1.5),
+            Value::Float64(f64::INFINITY),
+            Value::Float64(f64::NEG_INFINITY),
+            Value::Float64(f64::NAN),
+        ],
+        Type::Char => vec![
+            Value::Char('a'),
+            Value::Char('\0'),
+            Value::Char('ß'),
+            Value::Char('\u{1f600}'),
+        ],
+        Type::String => vec![
+            Value::String(String::new()),
+            Value::String("hello".to_string()),
+            Value::String("\"quoted\"\n\u{1f600}".to_string()),
+        ],
+        Type::Handle(_) => return None,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(t) => samples(iface, t)?,
+            TypeDefKind::Record(r) => {
+                let fields = r
+                    .fields
+                    .iter()
+                    .map(|f| Some((f.name.clone(), samples(iface, &f.ty)?)))
+                    .collect::<Option<Vec<_>>>()?;
+                vary(&fields.iter().map(|(_, s)| &s[..]).collect::<Vec<_>>())
+                    .into_iter()
+                    .map(|values| {
+                        let names = fields.iter().map(|(name, _)| name.clone());
+                        Value::Record(names.zip(values).collect())
+                    })
+                    .collect()
+            }
+            TypeDefKind::Tuple(t) => {
+                let types = t
+                    .types
+                    .iter()
+                    .map(|ty| samples(iface, ty))
+                    .collect::<Option<Vec<_>>>()?;
+                vary(&types.iter().map(|s| &s[..]).collect::<Vec<_>>())
+                    .into_iter()
+                    .map(Value::Tuple)
+                    .collect()
+            }
+            TypeDefKind::List(t) => {
+                let elements = samples(iface, t)?;
+                vec![
+                    Value::List(Vec::new()),
+                    Value::List(vec![elements[0].clone()]),
+                    Value::List(elements),
+                ]
+            }
+            TypeDefKind::Flags(f) => {
+                let names = f.flags.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+                let mut values = vec![Value::Flags(Vec::new())];
+                values.extend(names.iter().map(|name| Value::Flags(vec![name.clone()])));
+                if names.len() > 1 {
+                    values.push(Value::Flags(names));
+                }
+                values
+            }
+            TypeDefKind::Variant(v) => {
+                let mut values = Vec::new();
+                for case in v.cases.iter() {
+                    for payload in samples(iface, &case.ty)? {
+                        values.push(Value::Variant(case.name.clone(), Box::new(payload)));
+                    }
+                }
+                values
+            }
+            TypeDefKind::Enum(e) => e
+                .cases
+                .iter()
+                .map(|case| Value::Enum(case.name.clone()))
+                .collect(),
+            TypeDefKind::Union(u) => {
+                let mut values = Vec::new();
+                for (i, case) in u.cases.iter().enumerate() {
+                    for payload in samples(iface, &case.ty)? {
+                        values.push(Value::Union(i, Box::new(payload)));
+                    }
+                }
+                values
+            }
+            TypeDefKind::Option(t) => {
+                let mut values = vec![Value::Option(None)];
+                for payload in samples(iface, t)? {
+                    values.push(Value::Option(Some(Box::new(payload))));
+                }
+                values
+            }
+            TypeDefKind::Result(r) => {
+                let mut values = Vec::new();
+                for payload in samples(iface, &r.ok)? {
+                    values.push(Value::Result(Ok(Box::new(payload))));
+                }
+                for payload in samples(iface, &r.err)? {
+                    values.push(Value::Result(Err(Box::new(payload))));
+                }
+                values
+            }
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => return None,
+        },
+    };
+    Some(values)
+}
+
+/// Returns the combination of the first sample of every part, followed by
+/// one combination per other sample of each part.
+fn vary(parts: &[&[Value]]) -> Vec<Vec<Value>> {
+    let first = parts.iter().map(|s| s[0].clone()).collect::<Vec<_>>();
+    let mut combinations = vec![first.clone()];
+    for (i, samples) in parts.iter().enumerate() {
+        for sample in samples.iter().skip(1) {
+            let mut combination = first.clone();
+            combination[i] = sample.clone();
+            combinations.push(combination);
+        }
+    }
+    combinations
+}
+
+/// Returns the memory image of `value`, which must be of type `ty`.
+fn store(iface: &Interface, sizes: &SizeAlign, ty: &Type, value: &Value) -> Vec<u8> {
+    let mut memory = Memory {
+        iface,
+        sizes,
+        bytes: Vec::new(),
+    };
+    let ptr = memory.alloc(sizes.size(ty), sizes.align(ty));
+    memory.store(ty, value, ptr);
+    memory.bytes
+}
+
+struct Memory<'a> {
+    iface: &'a Interface,
+    sizes: &'a SizeAlign,
+    bytes: Vec<u8>,
+}
+
+impl Memory<'_> {
+    fn alloc(&mut self, size: usize, align: usize) -> usize {
+        let ptr = align_to(self.bytes.len(), align);
+        self.bytes.resize(ptr + size, 0);
+        ptr
+    }
+
+    fn write(&mut self, offset: usize, bytes: &[u8]) {
+        self.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn write_int(&mut self, offset: usize, int: Int, value: u64) {
+        let bytes = value.to_le_bytes();
+        let size = match int {
+            Int::U8 => 1,
+            Int::U16 => 2,
+            Int::U32 => 4,
+            Int::U64 => 8,
+        };
+        self.write(offset, &bytes[..size]);
+    }
+
+    fn store(&mut self, ty: &Type, value: &Value, offset: usize) {
+        match (ty, value) {
+            (Type::Unit, Value::Unit) => {}
+            (Type::Bool, Value::Bool(b)) => self.write(offset, &[*b as u8]),
+            (Type::U8, Value::U8(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::U16, Value::U16(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::U32, Value::U32(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::U64, Value::U64(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::S8, Value::S8(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::S16, Value::S16(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::S32, Value::S32(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::S64, Value::S64(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::Float32, Value::Float32(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::Float64, Value::Float64(v)) => self.write(offset, &v.to_le_bytes()),
+            (Type::Char, Value::Char(c)) => self.write(offset, &(*c as u32).to_le_bytes()),
+            (Type::String, Value::String(s)) => {
+                let ptr = self.alloc(s.len(), 1);
+                self.write(ptr, s.as_bytes());
+                self.write_list(offset, ptr, s.len());
+            }
+            (Type::Id(id), _) => self.store_def(*id, value, offset),
+            _ => panic!("value {} is not of type {:?}", value, ty),
+        }
+    }
+
+    fn store_def(&mut self, id: wit_parser::TypeId, value: &Value, offset: usize) {
+        let iface = self.iface;
+        let sizes = self.sizes;
+        match (&iface.types[id].kind, value) {
+            (TypeDefKind::Type(t), _) => self.store(t, value, offset),
+            (TypeDefKind::Record(r), Value::Record(fields)) => {
+                let offsets = sizes.field_offsets(r.fields.iter().map(|f| &f.ty));
+                for ((field, (_, value)), field_offset) in r.fields.iter().zip(fields).zip(offsets)
+                {
+                    self.store(&field.ty, value, offset + field_offset);
+                }
+            }
+            (TypeDefKind::Tuple(t), Value::Tuple(values)) => {
+                let offsets = sizes.field_offsets(t.types.iter());
+                for ((ty, value), field_offset) in t.types.iter().zip(values).zip(offsets) {
+                    self.store(ty, value, offset + field_offset);
+                }
+            }
+            (TypeDefKind::List(t), Value::List(values)) => {
+                let size = sizes.size(t);
+                let ptr = self.alloc(size * values.len(), sizes.align(t));
+                for (i, value) in values.iter().enumerate() {
+                    self.store(t, value, ptr + i * size);
+                }
+                self.write_list(offset, ptr, values.len());
+            }
+            (TypeDefKind::Flags(f), Value::Flags(names)) => {
+                let mut bits = vec![0u32; f.flags.len().max(1) / 32 + 1];
+                for name in names {
+                    let i = f.flags.iter().position(|f| f.name == *name).unwrap();
+                    bits[i / 32] |= 1 << (i % 32);
+                }
+                match f.repr() {
+                    FlagsRepr::U8 => self.write(offset, &[bits[0] as u8]),
+                    FlagsRepr::U16 => self.write(offset, &(bits[0] as u16).to_le_bytes()),
+                    FlagsRepr::U32(n) => {
+                        for (i, word) in bits.iter().take(n).enumerate() {
+                            self.write(offset + i * 4, &word.to_le_bytes());
+                        }
+                    }
+                }
+            }
+            (TypeDefKind::Variant(v), Value::Variant(name, payload)) => {
+                let index = v.cases.iter().position(|c| c.name == *name).unwrap();
+                let payload_offset = sizes.payload_offset(v.tag(), v.cases.iter().map(|c| &c.ty));
+                self.write_int(offset, v.tag(), index as u64);
+                self.store(&v.cases[index].ty, payload, offset + payload_offset);
+            }
+            (TypeDefKind::Enum(e), Value::Enum(name)) => {
+                let index = e.cases.iter().position(|c| c.name == *name).unwrap();
+                self.write_int(offset, e.tag(), index as u64);
+            }
+            (TypeDefKind::Union(u), Value::Union(index, payload)) => {
+                let payload_offset = sizes.payload_offset(u.tag(), u.cases.iter().map(|c| &c.ty));
+                self.write_int(offset, u.tag(), *index as u64);
+                self.store(&u.cases[*index].ty, payload, offset + payload_offset);
+            }
+            (TypeDefKind::Option(t), Value::Option(payload)) => {
+                let payload_offset = sizes.payload_offset(Int::U8, [&Type::Unit, t]);
+                match payload {
+                    None => self.write_int(offset, Int::U8, 0),
+                    Some(payload) => {
+                        self.write_int(offset, Int::U8, 1);
+                        self.store(t, payload, offset + payload_offset);
+                    }
+                }
+            }
+            (TypeDefKind::Result(r), Value::Result(result)) => {
+                let payload_offset = sizes.payload_offset(Int::U8, [&r.ok, &r.err]);
+                let (tag, ty, payload) = match result {
+                    Ok(payload) => (0, &r.ok, payload),
+                    Err(payload) => (1, &r.err, payload),
+                };
+                self.write_int(offset, Int::U8, tag);
+                self.store(ty, payload, offset + payload_offset);
+            }
+            (kind, _) => panic!("value {} is not of type {:?}", value, kind),
+        }
+    }
+
+    fn write_list(&mut self, offset: usize, ptr: usize, len: usize) {
+        self.write(offset, &(ptr as u32).to_le_bytes());
+        self.write(offset + 4, &(len as u32).to_le_bytes());
+    }
+}
+
+fn align_to(val: usize, align: usize) -> usize {
+    (val + align - 1) & !(align - 1)
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Unit => f.write_str("()"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::U8(v) => write!(f, "{}", v),
+            Value::U16(v) => write!(f, "{}", v),
+            Value::U32(v) => write!(f, "{}", v),
+            Value::U64(v) => write!(f, "{}", v),
+            Value::S8(v) => write!(f, "{}", v),
+            Value::S16(v) => write!(f, "{}", v),
+            Value::S32(v) => write!(f, "{}", v),
+            Value::S64(v) => write!(f, "{}", v),
+            Value::Float32(v) if v.is_finite() => write!(f, "{:?}", v),
+            Value::Float64(v) if v.is_finite() => write!(f, "{:?}", v),
+            Value::Float32(v) => write_non_finite(f, *v as f64),
+            Value::Float64(v) => write_non_finite(f, *v),
+            Value::Char(c) => {
+                f.write_char('\'')?;
+                write_escaped(f, *c, '\'')?;
+                f.write_char('\'')
+            }
+            Value::String(s) => {
+                f.write_char('"')?;
+                for c in s.chars() {
+                    write_escaped(f, c, '"')?;
+                }
+                f.write_char('"')
+            }
+            Value::List(values) => {
+                f.write_char('[')?;
+                write_list(f, values.iter())?;
+                f.write_char(']')
+            }
+            Value::Record(fields) => {
+                f.write_char('{')?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                f.write_char('}')
+            }
+            Value::Tuple(values) => {
+                f.write_char('(')?;
+                write_list(f, values.iter())?;
+                f.write_char(')')
+            }
+            Value::Flags(names) => write!(f, "{{{}}}", names.join(", ")),
+            Value::Variant(name, payload) => match **payload {
+                Value::Unit => f.write_str(name),
+                _ => write!(f, "{}({})", name, payload),
+            },
+            Value::Enum(name) => f.write_str(name),
+            Value::Union(index, payload) => write!(f, "%{}({})", index, payload),
+            Value::Option(None) => f.write_str("none"),
+            Value::Option(Some(payload)) => write!(f, "some({})", payload),
+            Value::Result(Ok(payload)) => write!(f, "ok({})", payload),
+            Value::Result(Err(payload)) => write!(f, "err({})", payload),
+        }
+    }
+}
+
+fn write_non_finite(f: &mut fmt::Formatter<'_>, v: f64) -> fmt::Result {
+    if v.is_nan() {
+        f.write_str("nan")
+    } else if v > 0.0 {
+        f.write_str("inf")
+    } else {
+        f.write_str("-inf")
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, c: char, quote: char) -> fmt::Result {
+    match c {
+        '\\' => f.write_str("\\\\"),
+        '\n' => f.write_str("\\n"),
+        '\r' => f.write_str("\\r"),
+        '\t' => f.write_str("\\t"),
+        c if c == quote => write!(f, "\\{}", c),
+        c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32),
+        c => f.write_char(c),
+    }
+}
+
+fn write_list<'a>(
+    f: &mut fmt::Formatter<'_>,
+    values: impl Iterator<Item = &'a Value>,
+) -> fmt::Result {
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{}", value)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vectors_of(src: &str, name: &str) -> Vec<(String, Vec<u8>)> {
+        let iface = Interface::parse("test", src).unwrap();
+        let mut sizes = SizeAlign::default();
+        sizes.fill(&iface);
+        let id = iface
+            .types
+            .iter()
+            .find(|(_, def)| def.name.as_deref() == Some(name))
+            .unwrap()
+            .0;
+        vectors(&iface, &sizes, &Type::Id(id))
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.value.to_string(), v.bytes))
+            .collect()
+    }
+
+    #[test]
+    fn stores_records_with_padding() {
+        let vectors = vectors_of("record point { tag: u8, x: u32 }", "point");
+        assert_eq!(vectors.len(), 5);
+        assert_eq!(vectors[0], ("{tag: 0, x: 0}".to_string(), vec![0; 8]));
+        assert_eq!(
+            vectors[2],
+            (
+                "{tag: 255, x: 0}".to_string(),
+                vec![255, 0, 0, 0, 0, 0, 0, 0]
+            )
+        );
+        assert_eq!(vectors[4].1, [0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff].to_vec());
+    }
+
+    #[test]
+    fn stores_lists_after_the_value() {
+        let vectors = vectors_of("type bytes = list<u16>", "bytes");
+        assert_eq!(vectors[0], ("[]".to_string(), vec![8, 0, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(
+            vectors[2],
+            (
+                "[0, 1, 65535]".to_string(),
+                vec![8, 0, 0, 0, 3, 0, 0, 0, 0, 0, 1, 0, 0xff, 0xff]
+            )
+        );
+
+        let vectors = vectors_of("type names = list<string>", "names");
+        assert_eq!(vectors[1].0, "[\"\"]");
+        assert_eq!(
+            vectors[2].0,
+            "[\"\", \"hello\", \"\\\"quoted\\\"\\n\u{1f600}\"]"
+        );
+    }
+
+    #[test]
+    fn stores_variant_payloads_aligned() {
+        let vectors = vectors_of(
+            "variant shape { empty, round(u64), tagged(option<bool>) }",
+            "shape",
+        );
+        let literals = vectors.iter().map(|(l, _)| l.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            literals,
+            [
+                "empty",
+                "round(0)",
+                "round(1)",
+                "round(18446744073709551615)",
+                "tagged(none)",
+                "tagged(some(false))",
+                "tagged(some(true))",
+            ]
+        );
+        assert_eq!(
+            vectors[2].1,
+            [1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(
+            vectors[6].1,
+            [2, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0]
+        );
+    }
+}
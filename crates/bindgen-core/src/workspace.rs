@@ -0,0 +1,548 @@
+//! Checking that the crates of a workspace agree on their interfaces.
+//!
+//! [`scan`] finds every `import!` and `export!` invocation in the Rust
+//! sources below a directory and records which interfaces and options each
+//! one uses. [`check`] then looks for drift between them: copies of an
+//! interface file which no longer match each other, and the same interface
+//! being bound by the same macro with different options in different places.
+//!
+//! Invocations are found textually rather than by expanding macros, so
+//! bindings hidden behind other macros aren't seen.
+
+use crate::registry::interface_hash;
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wit_parser::Interface;
+
+/// A single invocation of a bindgen macro.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    /// The source file containing the invocation.
+    pub file: PathBuf,
+    /// The line the invocation starts on.
+    pub line: usize,
+    /// The macro as written, e.g. `wit_bindgen_guest_rust::import`.
+    pub macro_name: String,
+    pub interfaces: Vec<BoundInterface>,
+    /// Every option other than the interfaces, whitespace-normalized and
+    /// sorted.
+    pub options: Vec<String>,
+}
+
+/// An interface used by a [`Binding`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BoundInterface {
+    /// A `*.wit` file, resolved against the manifest directory of the crate
+    /// containing the invocation.
+    File(PathBuf),
+    /// A `registry("...")` reference.
+    Registry(String),
+    /// An interface given inline with `src["name"]: "..."`.
+    Inline(String),
+}
+
+impl BoundInterface {
+    /// Returns the name the interface is bound under.
+    pub fn name(&self) -> String {
+        match self {
+            BoundInterface::File(path) => path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            BoundInterface::Registry(reference) => reference.clone(),
+            BoundInterface::Inline(name) => name.clone(),
+        }
+    }
+}
+
+/// A problem found by [`check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// Several files define an interface with the same name but different
+    /// contents.
+    DivergentCopies {
+        name: String,
+        /// Each copy with its hash.
+        copies: Vec<(PathBuf, String)>,
+    },
+    /// The same macro binds an interface with different options.
+    InconsistentOptions {
+        macro_name: String,
+        interface: String,
+        /// The location and options of each binding.
+        bindings: Vec<(PathBuf, usize, Vec<String>)>,
+    },
+    /// An interface file couldn't be loaded.
+    Unreadable { path: PathBuf, error: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Issue::DivergentCopies { name, copies } => {
+                write!(f, "interface `{}` has divergent copies:", name)?;
+                for (path, hash) in copies {
+                    write!(f, "\n    {} ({})", path.display(), hash)?;
+                }
+                Ok(())
+            }
+            Issue::InconsistentOptions {
+                macro_name,
+                interface,
+                bindings,
+            } => {
+                write!(
+                    f,
+                    "`{}` is bound by `{}!` with different options:",
+                    interface, macro_name
+                )?;
+                for (path, line, options) in bindings {
+                    let options = if options.is_empty() {
+                        "(none)".to_string()
+                    } else {
+                        options.join(", ")
+                    };
+                    write!(f, "\n    {}:{}: {}", path.display(), line, options)?;
+                }
+                Ok(())
+            }
+            Issue::Unreadable { path, error } => {
+                write!(f, "failed to load {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+/// Finds every bindgen macro invocation in the Rust files below `root`,
+/// skipping `target` directories and hidden directories.
+pub fn scan(root: &Path) -> Result<Vec<Binding>> {
+    let mut bindings = Vec::new();
+    scan_dir(root, &mut bindings)?;
+    Ok(bindings)
+}
+
+fn scan_dir(dir: &Path, bindings: &mut Vec<Binding>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {:?}", dir))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            if name != "target" && !name.starts_with('.') {
+                scan_dir(&path, bindings)?;
+            }
+        } else if name.ends_with(".rs") {
+            let src =
+                fs::read_to_string(&path).with_context(|| format!("failed to read {:?}", path))?;
+            let manifest_dir = path
+                .ancestors()
+                .skip(1)
+                .find(|dir| dir.join("Cargo.toml").is_file())
+                .unwrap_or(dir);
+            for (line, macro_name, body) in invocations(&src) {
+                let (interfaces, options) = parse_body(&body, manifest_dir);
+                bindings.push(Binding {
+                    file: path.clone(),
+                    line,
+                    macro_name,
+                    interfaces,
+                    options,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reports drift between `bindings`.
+pub fn check(bindings: &[Binding]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    let mut files = BTreeMap::<String, BTreeMap<PathBuf, String>>::new();
+    for binding in bindings {
+        for interface in binding.interfaces.iter() {
+            if let BoundInterface::File(path) = interface {
+                let path = path.canonicalize().unwrap_or_else(|_| path.clone());
+                files
+                    .entry(interface.name())
+                    .or_default()
+                    .insert(path, String::new());
+            }
+        }
+    }
+    for (name, copies) in files.iter_mut() {
+        for (path, hash) in copies.iter_mut() {
+            match Interface::parse_file(path) {
+                Ok(iface) => *hash = interface_hash(&iface),
+                Err(e) => issues.push(Issue::Unreadable {
+                    path: path.clone(),
+                    error: format!("{:#}", e),
+                }),
+            }
+        }
+        let mut hashes = copies
+            .values()
+            .filter(|h| !h.is_empty())
+            .collect::<Vec<_>>();
+        hashes.sort();
+        hashes.dedup();
+        if hashes.len() > 1 {
+            issues.push(Issue::DivergentCopies {
+                name: name.clone(),
+                copies: copies
+                    .iter()
+                    .filter(|(_, h)| !h.is_empty())
+                    .map(|(p, h)| (p.clone(), h.clone()))
+                    .collect(),
+            });
+        }
+    }
+
+    let mut uses = BTreeMap::<(String, String), Vec<&Binding>>::new();
+    for binding in bindings {
+        let macro_name = binding.macro_name.rsplit("::").take(2).collect::<Vec<_>>();
+        let macro_name = macro_name.into_iter().rev().collect::<Vec<_>>().join("::");
+        for interface in binding.interfaces.iter() {
+            uses.entry((macro_name.clone(), interface.name()))
+                .or_default()
+                .push(binding);
+        }
+    }
+    for ((macro_name, interface), uses) in uses {
+        if uses.iter().all(|b| b.options == uses[0].options) {
+            continue;
+        }
+        issues.push(Issue::InconsistentOptions {
+            macro_name,
+            interface,
+            bindings: uses
+                .iter()
+                .map(|b| (b.file.clone(), b.line, b.options.clone()))
+                .collect(),
+        });
+    }
+
+    issues
+}
+
+/// Finds invocations of macros named `import` or `export` in `src`,
+/// returning the line, macro path and the text between the delimiters of
+/// each one.
+fn invocations(src: &str) -> Vec<(usize, String, String)> {
+    let bytes = src.as_bytes();
+    let mut ret = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(end) = skip_comment_or_string(bytes, i) {
+            i = end;
+            continue;
+        }
+        let c = bytes[i];
+        if !(c.is_ascii_alphabetic() || c == b'_') {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || b"_:".contains(&bytes[i])) {
+            i += 1;
+        }
+        let path = &src[start..i];
+        let name = path.rsplit("::").next().unwrap();
+        if (name != "import" && name != "export") || bytes.get(i) != Some(&b'!') {
+            continue;
+        }
+        let mut open = i + 1;
+        while open < bytes.len() && bytes[open].is_ascii_whitespace() {
+            open += 1;
+        }
+        if let Some(close) = matching_delimiter(bytes, open) {
+            let line = src[..start].matches('\n').count() + 1;
+            ret.push((line, path.to_string(), src[open + 1..close].to_string()));
+            i = close + 1;
+        }
+    }
+    ret
+}
+
+/// If a comment or string literal starts at `i`, returns where it ends.
+fn skip_comment_or_string(bytes: &[u8], i: usize) -> Option<usize> {
+    let rest = &bytes[i..];
+    if rest.starts_with(b"//") {
+        let len = rest.iter().position(|b| *b == b'\n').unwrap_or(rest.len());
+        return Some(i + len);
+    }
+    if rest.starts_with(b"/*") {
+        let len = rest
+            .windows(2)
+            .position(|w| w == b"*/")
+            .map_or(rest.len(), |p| p + 2);
+        return Some(i + len);
+    }
+    if rest.starts_with(b"r\"") || rest.starts_with(b"r#") {
+        let hashes = rest[1..].iter().take_while(|b| **b == b'#').count();
+        if rest.get(1 + hashes) == Some(&b'"') {
+            let mut end = i + 2 + hashes;
+            while end < bytes.len() {
+                if bytes[end] == b'"' && bytes[end + 1..].iter().take(hashes).all(|b| *b == b'#') {
+                    return Some((end + 1 + hashes).min(bytes.len()));
+                }
+                end += 1;
+            }
+            return Some(bytes.len());
+        }
+    }
+    // Character literals, so that `'"'` doesn't start a string. Lifetimes
+    // have no closing quote and are left alone.
+    if rest.starts_with(b"'\\") {
+        let len = rest[2..]
+            .iter()
+            .position(|b| *b == b'\'')
+            .map_or(1, |p| p + 3);
+        return Some(i + len);
+    }
+    if rest.starts_with(b"'") && rest.get(2) == Some(&b'\'') {
+        return Some(i + 3);
+    }
+    if rest.starts_with(b"\"") {
+        let mut end = i + 1;
+        while end < bytes.len() && bytes[end] != b'"' {
+            end += if bytes[end] == b'\\' { 2 } else { 1 };
+        }
+        return Some((end + 1).min(bytes.len()));
+    }
+    None
+}
+
+/// Returns the index of the delimiter closing the one at `open`.
+fn matching_delimiter(bytes: &[u8], open: usize) -> Option<usize> {
+    if !matches!(bytes.get(open), Some(b'(' | b'[' | b'{')) {
+        return None;
+    }
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        if let Some(end) = skip_comment_or_string(bytes, i) {
+            i = end;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Splits `s` at the commas which aren't nested in delimiters or strings.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let bytes = s.as_bytes();
+    let mut ret = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(end) = skip_comment_or_string(bytes, i) {
+            i = end;
+            continue;
+        }
+        match bytes[i] {
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b',' if depth == 0 => {
+                ret.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    ret.push(&s[start..]);
+    ret.into_iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+/// Returns the contents of the string literal `s`, if it is one.
+fn string_literal(s: &str) -> Option<String> {
+    let s = s.trim();
+    let hashes = s
+        .strip_prefix('r')
+        .map(|r| r.len() - r.trim_start_matches('#').len());
+    let inner = match hashes {
+        Some(n) => s.get(1 + n..s.len() - n)?,
+        None => s,
+    };
+    let inner = inner.strip_prefix('"')?.strip_suffix('"')?;
+    if hashes.is_some() {
+        return Some(inner.to_string());
+    }
+    Some(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+fn normalize(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Splits the body of a macro invocation into the interfaces it binds and
+/// the rest of its options.
+fn parse_body(body: &str, manifest_dir: &Path) -> (Vec<BoundInterface>, Vec<String>) {
+    let mut interfaces = Vec::new();
+    let mut options = Vec::new();
+    let body = body.trim();
+
+    match body.strip_prefix('{').and_then(|b| b.strip_suffix('}')) {
+        Some(fields) => {
+            for field in split_top_level(fields) {
+                if let Some(paths) = field.strip_prefix("paths") {
+                    let paths = paths.trim_start().trim_start_matches(':').trim();
+                    let paths = paths.trim_start_matches('[').trim_end_matches(']');
+                    for path in split_top_level(paths) {
+                        if let Some(file) = string_literal(path) {
+                            interfaces.push(BoundInterface::File(manifest_dir.join(file)));
+                        } else if let Some(reference) = path.strip_prefix("registry") {
+                            let reference = reference.trim().trim_start_matches('(');
+                            if let Some(reference) = string_literal(reference.trim_end_matches(')'))
+                            {
+                                interfaces.push(BoundInterface::Registry(reference));
+                            }
+                        }
+                    }
+                } else if let Some(src) = field.strip_prefix("src") {
+                    let name = src.trim_start().trim_start_matches('[');
+                    if let Some(end) = name.find(']') {
+                        if let Some(name) = string_literal(&name[..end]) {
+                            interfaces.push(BoundInterface::Inline(name));
+                        }
+                    }
+                } else {
+                    options.push(normalize(field));
+                }
+            }
+        }
+        // The older form is a sequence of files and `key = "value"` pairs.
+        None => {
+            let mut rest = body;
+            while !rest.is_empty() {
+                let end = match skip_comment_or_string(rest.as_bytes(), 0) {
+                    Some(end) => end,
+                    None => rest.find('"').unwrap_or(rest.len()),
+                };
+                let (item, tail) = rest.split_at(end);
+                let item = item.trim();
+                if let Some(key) = item.strip_suffix('=') {
+                    let value = match skip_comment_or_string(tail.as_bytes(), 0) {
+                        Some(end) => &tail[..end],
+                        None => "",
+                    };
+                    options.push(format!("{} = {}", key.trim(), value));
+                    rest = tail[value.len()..].trim_start();
+                    continue;
+                }
+                if let Some(file) = string_literal(item) {
+                    interfaces.push(BoundInterface::File(manifest_dir.join(file)));
+                }
+                rest = tail.trim_start();
+            }
+        }
+    }
+
+    options.sort();
+    (interfaces, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_invocations() {
+        let src = r#"
+            // wit_bindgen_guest_rust::import!("commented.wit");
+            wit_bindgen_guest_rust::import!({
+                paths: ["wit/a.wit", registry("linera:b@2")],
+                derive_ord,
+                batch = ["f", "g"],
+            });
+            const S: &str = "export!(\"in-string.wit\")";
+            wit_bindgen_host_wasmtime_rust::export!("wit/c.wit" export_macro = "x");
+            wit_bindgen_guest_rust::export!({ src["d"]: "f: func()" });
+        "#;
+        let found = invocations(src);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].0, 3);
+        assert_eq!(found[0].1, "wit_bindgen_guest_rust::import");
+
+        let dir = Path::new("crate");
+        let (interfaces, options) = parse_body(&found[0].2, dir);
+        assert_eq!(
+            interfaces,
+            [
+                BoundInterface::File(dir.join("wit/a.wit")),
+                BoundInterface::Registry("linera:b@2".to_string()),
+            ]
+        );
+        assert_eq!(options, ["batch = [\"f\", \"g\"]", "derive_ord"]);
+
+        let (interfaces, options) = parse_body(&found[1].2, dir);
+        assert_eq!(interfaces, [BoundInterface::File(dir.join("wit/c.wit"))]);
+        assert_eq!(options, ["export_macro = \"x\""]);
+
+        let (interfaces, options) = parse_body(&found[2].2, dir);
+        assert_eq!(interfaces, [BoundInterface::Inline("d".to_string())]);
+        assert!(options.is_empty());
+    }
+
+    #[test]
+    fn reports_drift() {
+        let dir = std::env::temp_dir().join(format!("wit-workspace-{}", std::process::id()));
+        for (krate, options, wit) in [
+            ("a", "derive_ord,", "f: func(x: u32)"),
+            ("b", "", "f: func(x: u32)"),
+            ("c", "derive_ord,", "f: func(x: u64)"),
+        ] {
+            fs::create_dir_all(dir.join(krate).join("src")).unwrap();
+            fs::write(dir.join(krate).join("Cargo.toml"), "").unwrap();
+            fs::write(dir.join(krate).join("api.wit"), wit).unwrap();
+            fs::write(
+                dir.join(krate).join("src/lib.rs"),
+                format!(
+                    "wit_bindgen_guest_rust::import!({{ paths: [\"api.wit\"], {} }});",
+                    options
+                ),
+            )
+            .unwrap();
+        }
+
+        let bindings = scan(&dir).unwrap();
+        assert_eq!(bindings.len(), 3);
+        let issues = check(&bindings);
+        assert_eq!(issues.len(), 2);
+        match &issues[0] {
+            Issue::DivergentCopies { name, copies } => {
+                assert_eq!(name, "api");
+                assert_eq!(copies.len(), 3);
+            }
+            issue => panic!("unexpected issue: {}", issue),
+        }
+        match &issues[1] {
+            Issue::InconsistentOptions { bindings, .. } => assert_eq!(bindings.len(), 3),
+            issue => panic!("unexpected issue: {}", issue),
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
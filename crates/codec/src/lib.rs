@@ -0,0 +1,385 @@
+//! A small, non-self-describing byte encoding for interface values.
+//!
+//! This is used by generated bindings to serialize values that leave the
+//! current module as opaque bytes, for example cross-chain messages. The
+//! format is deliberately simple: integers and floats are fixed-width little
+//! endian, lengths are `u32`s, and variant-like types are prefixed with their
+//! case index using the same width as their canonical ABI discriminant.
+//!
+//! Integer record fields may opt into [`Varint`] instead, trading a little
+//! work for smaller payloads when most values are small.
+//!
+//! The guest and host runtimes both re-export this crate as their `codec`
+//! module, so that either side decodes what the other encodes.
+
+use std::fmt;
+
+/// A value which can be encoded into bytes.
+pub trait Encode {
+    /// Appends the encoding of `self` to `buf`.
+    fn encode_to(&self, buf: &mut Vec<u8>);
+
+    /// Returns the encoding of `self` as a fresh buffer.
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        self.encode_to(&mut buf);
+        buf
+    }
+}
+
+/// A value which can be decoded from bytes produced by [`Encode`].
+pub trait Decode: Sized {
+    /// Decodes a value from the front of `input`, advancing it past the bytes
+    /// that were consumed.
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    /// Decodes a value from `bytes`, requiring that all of the input is used.
+    fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let ret = Self::decode_from(&mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(bytes.len()));
+        }
+        Ok(ret)
+    }
+}
+
+/// Errors which can happen when decoding a value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before the value was complete.
+    UnexpectedEnd,
+    /// The input had this many bytes left over after decoding.
+    TrailingBytes(usize),
+    /// A discriminant was out of range for the named type.
+    InvalidTag { ty: &'static str, tag: u32 },
+    /// A `char` was not a valid unicode scalar value.
+    InvalidChar(u32),
+    /// A string was not valid UTF-8.
+    InvalidUtf8,
+    /// A set of flags contained unknown bits.
+    InvalidFlags(&'static str),
+    /// A variable-length integer didn't fit in the named type.
+    InvalidVarint(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => f.write_str("unexpected end of input"),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes after value", n),
+            DecodeError::InvalidTag { ty, tag } => write!(f, "invalid tag {} for `{}`", tag, ty),
+            DecodeError::InvalidChar(c) => write!(f, "invalid char {:#x}", c),
+            DecodeError::InvalidUtf8 => f.write_str("invalid utf-8 in string"),
+            DecodeError::InvalidFlags(ty) => write!(f, "invalid flags for `{}`", ty),
+            DecodeError::InvalidVarint(ty) => write!(f, "invalid varint for `{}`", ty),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Splits `n` bytes off the front of `input`.
+pub fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (head, rest) = input.split_at(n);
+    *input = rest;
+    Ok(head)
+}
+
+/// Encodes a collection length.
+pub fn encode_len(len: usize, buf: &mut Vec<u8>) {
+    (len as u32).encode_to(buf);
+}
+
+/// Decodes a collection length.
+pub fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    Ok(u32::decode_from(input)? as usize)
+}
+
+macro_rules! numbers {
+    ($($ty:ident)*) => ($(
+        impl Encode for $ty {
+            fn encode_to(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                let bytes = take(input, std::mem::size_of::<$ty>())?;
+                Ok($ty::from_le_bytes(bytes.try_into().unwrap()))
+            }
+        }
+    )*)
+}
+
+numbers!(u8 u16 u32 u64 u128 i8 i16 i32 i64 f32 f64);
+
+/// An integer which can also be encoded with a variable length.
+///
+/// Unsigned integers use LEB128, seven bits per byte with the high bit set on
+/// all but the last byte. Signed integers are zigzag-mapped first so that
+/// small negative numbers stay short too.
+pub trait Varint: Sized {
+    /// Appends the variable-length encoding of `self` to `buf`.
+    fn encode_varint_to(&self, buf: &mut Vec<u8>);
+
+    /// Decodes a variable-length integer from the front of `input`.
+    fn decode_varint_from(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/// Appends the LEB128 encoding of `value` to `buf`.
+pub fn encode_uleb128(mut value: u64, buf: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+/// Decodes a LEB128 integer from the front of `input`, failing with
+/// [`DecodeError::InvalidVarint`] naming `ty` if it doesn't fit in 64 bits.
+pub fn decode_uleb128(input: &mut &[u8], ty: &'static str) -> Result<u64, DecodeError> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = u8::decode_from(input)?;
+        if shift == 63 && byte > 1 {
+            return Err(DecodeError::InvalidVarint(ty));
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+macro_rules! unsigned_varints {
+    ($($ty:ident)*) => ($(
+        impl Varint for $ty {
+            fn encode_varint_to(&self, buf: &mut Vec<u8>) {
+                encode_uleb128(u64::from(*self), buf);
+            }
+
+            fn decode_varint_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                let value = decode_uleb128(input, stringify!($ty))?;
+                $ty::try_from(value).map_err(|_| DecodeError::InvalidVarint(stringify!($ty)))
+            }
+        }
+    )*)
+}
+
+unsigned_varints!(u8 u16 u32 u64);
+
+macro_rules! signed_varints {
+    ($($ty:ident)*) => ($(
+        impl Varint for $ty {
+            fn encode_varint_to(&self, buf: &mut Vec<u8>) {
+                let value = i64::from(*self);
+                encode_uleb128(((value << 1) ^ (value >> 63)) as u64, buf);
+            }
+
+            fn decode_varint_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                let value = decode_uleb128(input, stringify!($ty))?;
+                let value = (value >> 1) as i64 ^ -((value & 1) as i64);
+                $ty::try_from(value).map_err(|_| DecodeError::InvalidVarint(stringify!($ty)))
+            }
+        }
+    )*)
+}
+
+signed_varints!(i8 i16 i32 i64);
+
+impl Encode for () {
+    fn encode_to(&self, _buf: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode_from(_input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(())
+    }
+}
+
+impl Encode for bool {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+}
+
+impl Decode for bool {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode_from(input)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DecodeError::InvalidTag {
+                ty: "bool",
+                tag: tag.into(),
+            }),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode_to(buf);
+    }
+}
+
+impl Decode for char {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let c = u32::decode_from(input)?;
+        char::from_u32(c).ok_or(DecodeError::InvalidChar(c))
+    }
+}
+
+impl Encode for str {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        encode_len(self.len(), buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Encode for String {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.as_str().encode_to(buf);
+    }
+}
+
+impl Decode for String {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        encode_len(self.len(), buf);
+        for item in self {
+            item.encode_to(buf);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode_to(buf);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        // Don't trust `len`: reject it if there's less than a byte of input
+        // left per element, so that elements encoded as no bytes at all can't
+        // make this loop up to `u32::MAX` times.
+        if len > input.len() {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let mut ret = Vec::with_capacity(len);
+        for _ in 0..len {
+            ret.push(T::decode_from(input)?);
+        }
+        Ok(ret)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        (**self).encode_to(buf);
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(e) => {
+                buf.push(1);
+                e.encode_to(buf);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode_from(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode_from(input)?)),
+            tag => Err(DecodeError::InvalidTag {
+                ty: "option",
+                tag: tag.into(),
+            }),
+        }
+    }
+}
+
+impl<T: Encode, E: Encode> Encode for Result<T, E> {
+    fn encode_to(&self, buf: &mut Vec<u8>) {
+        match self {
+            Ok(e) => {
+                buf.push(0);
+                e.encode_to(buf);
+            }
+            Err(e) => {
+                buf.push(1);
+                e.encode_to(buf);
+            }
+        }
+    }
+}
+
+impl<T: Decode, E: Decode> Decode for Result<T, E> {
+    fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode_from(input)? {
+            0 => Ok(Ok(T::decode_from(input)?)),
+            1 => Ok(Err(E::decode_from(input)?)),
+            tag => Err(DecodeError::InvalidTag {
+                ty: "result",
+                tag: tag.into(),
+            }),
+        }
+    }
+}
+
+macro_rules! tuples {
+    ($(($($t:ident)*))*) => ($(
+        #[allow(non_snake_case)]
+        impl<$($t: Encode,)*> Encode for ($($t,)*) {
+            fn encode_to(&self, buf: &mut Vec<u8>) {
+                let ($($t,)*) = self;
+                $($t.encode_to(buf);)*
+            }
+        }
+
+        impl<$($t: Decode,)*> Decode for ($($t,)*) {
+            fn decode_from(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                Ok(($($t::decode_from(input)?,)*))
+            }
+        }
+    )*)
+}
+
+tuples! {
+    (A)
+    (A B)
+    (A B C)
+    (A B C D)
+    (A B C D E)
+    (A B C D E F)
+    (A B C D E F G)
+    (A B C D E F G H)
+    (A B C D E F G H I)
+    (A B C D E F G H I J)
+    (A B C D E F G H I J K)
+    (A B C D E F G H I J K L)
+}
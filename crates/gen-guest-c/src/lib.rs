@@ -40,10 +40,12 @@ struct Func {
     src: Source,
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    // ...
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        // ...
+    }
 }
 
 impl Opts {
@@ -1,14 +1,27 @@
 use heck::*;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::{Read, Write};
 use std::mem;
+use std::path::PathBuf;
 use std::process::{Command, Stdio};
+use wit_bindgen_core::profile::{Profile, DEFAULT_HOT_CALLS};
+use wit_bindgen_core::registry::interface_hash;
 use wit_bindgen_core::wit_parser::abi::{AbiVariant, Bindgen, Instruction, LiftLower, WasmType};
 use wit_bindgen_core::{wit_parser::*, Direction, Files, Generator, Source, TypeInfo, Types};
 use wit_bindgen_gen_rust_lib::{
-    int_repr, wasm_type, FnSig, RustFlagsRepr, RustFunctionGenerator, RustGenerator, TypeMode,
+    batched_functions, chunked_functions, encoded_param_by_ref, has_error_location,
+    idempotent_functions, int_repr, interned_functions, is_error_context, memoized_functions,
+    named_types_within, protocol_transitions, same_type, spliced_functions, to_rust_ident,
+    wasm_type, FnSig, RustFlagsRepr, RustFunctionGenerator, RustGenerator, TypeInfoExt, TypeMode,
 };
 
+pub mod scaffold;
+pub mod shim;
+
+/// The types generated so far, by name, along with the index of the
+/// interface defining each and the names and modes it's printed with.
+type SharedTypes = HashMap<String, Vec<(usize, TypeId, Vec<(String, TypeMode)>)>>;
+
 #[derive(Default)]
 pub struct RustWasm {
     src: Source,
@@ -23,103 +36,2882 @@ pub struct RustWasm {
     return_pointer_area_size: usize,
     return_pointer_area_align: usize,
     sizes: SizeAlign,
+
+    // The `batch` and `memoize` options for the current interface, keeping
+    // only the functions the profile, if any, shows to be hot.
+    batch: Vec<String>,
+    memoize: Vec<String>,
+    // Imports the profile shows to be hot or never called.
+    hot: HashSet<String>,
+    cold: HashSet<String>,
+    // The items re-exported by the prelude, by the path they are found at
+    // from within it.
+    prelude: Vec<(String, Vec<String>)>,
+    // The interfaces generated so far and, by name, the types they define
+    // along with the names and modes they're printed with, to be shared.
+    shared_ifaces: Vec<Interface>,
+    shared_types: SharedTypes,
+    // The types of the current interface re-exported from an earlier one.
+    reused_types: HashSet<TypeId>,
+    // The indices in `shared_ifaces` of the interfaces generated without a
+    // wrapping module, next to their export macro.
+    unwrapped_ifaces: HashSet<usize>,
+    // The options set for every interface while the current one overrides
+    // them.
+    global_opts: Option<Opts>,
+}
+
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        /// Whether or not `rustfmt` is executed to format generated code.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub rustfmt: bool,
+
+        /// Adds the wit module name into import binding names when enabled.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub multi_module: bool,
+
+        /// Whether or not the bindings assume interface values are always
+        /// well-formed or whether checks are performed.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub unchecked: bool,
+
+        /// A prefix to prepend to all exported symbols. Note that this is only
+        /// intended for testing because it breaks the general form of the ABI.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub symbol_namespace: String,
+
+        /// The visibility of the module generated for each interface, such as
+        /// `pub`. Modules are private by default.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub module_visibility: String,
+
+        /// Paths of the modules to generate interfaces in instead of modules
+        /// named after them, keyed by the interface name, such as
+        /// `system::query` for `queryable-system`.
+        ///
+        /// Interfaces placed in nested modules are generated in a module of
+        /// their own, which the innermost one re-exports.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub module_names: Vec<(String, String)>,
+
+        /// If set, the code generation is intended for standalone crates.
+        ///
+        /// Standalone mode generates bindings without a wrapping module.
+        ///
+        /// For exported interfaces, an export macro is also generated
+        /// that can be used to export an implementation from a different
+        /// crate.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub export_macro: Option<String>,
+
+        /// Path inside the standalone crate where the generated types can be found.
+        ///
+        /// Used inside the export macro and is prefixed with `$crate::`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub types_path: Option<String>,
+
+        /// Path to access this crate from inside the generated macro if it has been re-exported.
+        ///
+        /// This is only relevant if the [`export_macro`] is set, and is prefixed with `$crate::`. If
+        /// it is not set, the generated code will use the crate assuming it has been declared as a
+        /// dependency by the crate that uses the macro.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub reexported_crate_path: Option<String>,
+
+        /// Leaves `#[macro_export]` off the [`export_macro`], so that it isn't
+        /// part of the public API of the crate, and can only be invoked after
+        /// its definition or through [`export_macro_vis`].
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub no_macro_export: bool,
+
+        /// Visibility of a `use` of the [`export_macro`] following its
+        /// definition, such as `pub(crate)`, so that it can be invoked by path
+        /// like other items. Requires [`no_macro_export`].
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub export_macro_vis: Option<String>,
+
+        /// Name of a module the [`export_macro`] is defined in, rather than in
+        /// the bindings themselves.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub export_macro_module: Option<String>,
+
+        /// Export macros to generate instead of [`export_macro`], keyed by the
+        /// name of the exported interface each is for, so that one set of
+        /// bindings declares several, such as those of a contract and of its
+        /// service.
+        ///
+        /// Like with [`export_macro`], the interfaces are generated without a
+        /// wrapping module, where the named types they declare identically are
+        /// defined once.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub export_macros: Vec<(String, String)>,
+
+        /// Path of the type implementing the exported interface, relative to the
+        /// generated module.
+        ///
+        /// Defaults to `super::{Interface}`, a type named after the interface in
+        /// the module invoking the bindgen macro. Not used with
+        /// [`export_macro`], where the type is the argument of the macro.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub export_type: Option<String>,
+
+        /// Name of a variant type used as the envelope for cross-chain messages.
+        ///
+        /// Encoding and decoding helpers are generated for the variant and every
+        /// type it refers to, along with a handler trait that has one method per
+        /// case and a dispatcher that matches exhaustively on incoming messages.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub message: Option<String>,
+
+        /// Record fields, written as `record.field`, whose integers are encoded
+        /// as LEB128 (zigzag for signed integers) in messages and other encoded
+        /// values rather than at their fixed width.
+        ///
+        /// This must match the list given to whatever decodes the values.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub varint: Vec<String>,
+
+        /// Name of a variant type whose cases are the queries a service accepts.
+        ///
+        /// Together with [`Opts::query_response`] this generates a typed client
+        /// with one method per query case, each returning the payload of the
+        /// response case with the same name.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub query: Option<String>,
+
+        /// Name of a variant type whose cases are the responses to [`Opts::query`].
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub query_response: Option<String>,
+
+        /// Names of imported functions whose calls can be queued into a batch.
+        ///
+        /// A batch type is generated with one method per function that encodes
+        /// the call into a buffer instead of performing it, and a `flush` method
+        /// which hands every queued call to the host in a single crossing.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub batch: Vec<String>,
+
+        /// Names of imported functions whose results are memoized.
+        ///
+        /// Each result is cached under the encoding of the arguments it was
+        /// computed from until `clear_memoized` is called, so repeated calls with
+        /// the same arguments only cross into the host once.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub memoize: Vec<String>,
+
+        /// The size in bytes above which list results of imports are left on
+        /// the host and read in chunks.
+        ///
+        /// When set, every import returning a list whose parameters are
+        /// scalars, strings, enums, flags or byte lists gets a `*_chunked`
+        /// counterpart returning an iterator. Results of at most this size are
+        /// transferred along with the call, while larger ones stay behind a
+        /// continuation handle and cross only as fast as they are consumed.
+        ///
+        /// Only import results are chunked: lists passed to imports already
+        /// live in wasm's memory, and lists passed to or returned from exports
+        /// still cross in one piece. The host bindings must be generated with
+        /// `chunked`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub chunk_threshold: Option<usize>,
+
+        /// The number of bytes transferred per chunk by chunked imports once
+        /// their result exceeds `chunk_threshold`.
+        ///
+        /// Defaults to 64 KiB.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub chunk_size: Option<usize>,
+
+        /// Names of imported functions whose string parameters are interned.
+        ///
+        /// Each distinct string is copied to the host only once, after which
+        /// calls pass the id the host registered it under. This suits strings
+        /// such as application ids and keys which are passed over and over, and
+        /// must match the list given to the host bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub interned: Vec<String>,
+
+        /// Whether imported interfaces get `bulk_writer` and `bulk_reader`
+        /// functions to exchange large blobs through buffers kept on the host,
+        /// passing only their handles to imports.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub bulk: bool,
+
+        /// Whether exported interfaces also export a dispatch table mapping each
+        /// operation id, the index of a function in the interface, to its export
+        /// in the indirect function table.
+        ///
+        /// This lets hosts call exports by id without looking them up by name.
+        /// The module must be linked with `--export-table` for hosts to reach the
+        /// functions.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub dispatch_table: bool,
+
+        /// A profile of call counts per function, as written by
+        /// `wit_bindgen_core::profile`, to target optimizations at.
+        ///
+        /// Hot imports get inlined shims and never-called ones are marked cold,
+        /// while `batch` and `memoize` only keep the functions which are hot.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub profile: Option<PathBuf>,
+
+        /// How many calls in the profile make a function hot, 1000 by default.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub hot_calls: Option<u64>,
+
+        /// Derives `PartialEq`, `Eq`, `PartialOrd` and `Ord` on generated types
+        /// whose contents all support them, for example to use them as `BTreeMap`
+        /// keys. Types containing floats or handles are left as they are.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub derive_ord: bool,
+
+        /// Derives `zeroize::Zeroize` on types containing anything marked
+        /// `@sensitive`, plus `zeroize::ZeroizeOnDrop` on those that bindings
+        /// only ever receive, and zeroes buffers used while lowering or lifting
+        /// lists before freeing them.
+        ///
+        /// The crate using the bindings must depend on `zeroize` with its
+        /// `derive` feature enabled.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub zeroize: bool,
+
+        /// Implements `subtle::ConstantTimeEq` for records containing anything
+        /// marked `@sensitive`, such as signatures and MACs, comparing all of
+        /// their fields in constant time.
+        ///
+        /// The crate using the bindings must depend on `subtle`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub ct_eq: bool,
+
+        /// Exports a `proptest-echo-{name}` function for each plain data type
+        /// of exported interfaces, which lifts its argument and lowers it back.
+        ///
+        /// Host bindings generated with `proptest` pass arbitrary values
+        /// through these functions, checking that they come back unchanged
+        /// from the glue on either side. Imported interfaces are unaffected.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub proptest: bool,
+
+        /// Aborts when exported functions are reentered through calls to the
+        /// host more than this many levels deep, instead of running until the
+        /// wasm stack is exhausted.
+        ///
+        /// Hosts should size their wasm stack for the same depth, which the
+        /// wasmtime host generator does with its own `call_depth` option.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub call_depth_limit: Option<u32>,
+
+        /// Pins the generated code to an older version of the ABI, so that it
+        /// keeps working with hosts built against it. See
+        /// `wit_parser::abi::ABI_VERSION` for the differences between versions.
+        ///
+        /// Version 1 requires the `abi-v1` feature of the runtime crate, which
+        /// exports the allocator under its old name.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub abi_version: Option<u32>,
+
+        /// Requires the bindings to never allocate, which holds for interfaces
+        /// whose functions only pass scalars and other fixed-size types, and
+        /// without any of the options which encode values. Generation fails for
+        /// any other interface.
+        ///
+        /// The runtime crate can then be used without its default `realloc`
+        /// feature, which exports the allocator hosts use to pass lists and
+        /// strings, so that modules such as pure math libraries can be built
+        /// without any allocator at all.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub no_alloc: bool,
+
+        /// Whether the bindings are for `#![no_std]` crates, taking `String`,
+        /// `Vec` and the allocator from `alloc`, which such crates declare with
+        /// `extern crate alloc;`, and everything else from `core`.
+        ///
+        /// Options whose code needs `std`, such as `memoize` and its
+        /// thread-local caches, can't be used along with it.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub no_std: bool,
+
+        /// Names of pure imported functions which also get a `const fn`
+        /// counterpart, `{name}_const`, usable in const contexts.
+        ///
+        /// Each takes at most one parameter, a `bool` or an enum, returns a
+        /// scalar or an enum, and lists its results in a `@table` annotation, one
+        /// for each value of the parameter in order, such as `@table("1", "10")`
+        /// for a `bool`. The host must return the same results.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub const_fns: Vec<String>,
+
+        /// Also writes `stubs/{interface}.rs` for each exported interface: an
+        /// implementation of its export trait with every function left as
+        /// `todo!()`, to be filled in next to the bindings.
+        ///
+        /// The macros expand the stubs in place instead, so that a new crate
+        /// builds before any export is implemented.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub stubs: bool,
+
+        /// Generates a `prelude` module re-exporting the traits, types and
+        /// resources of every interface, along with the export macro, so that
+        /// they can all be brought into scope with `use prelude::*`.
+        ///
+        /// Items with the same name in several interfaces are left out, as are
+        /// those listed in [`Opts::prelude_exclude`].
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub prelude: bool,
+
+        /// Names of items the prelude doesn't re-export, for example because
+        /// they clash with names used by the crate.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub prelude_exclude: Vec<String>,
+
+        /// Name of a record with `message: string` and `context: list<string>`
+        /// fields, through which errors keep the contexts they were given on
+        /// either side of the boundary, innermost first.
+        ///
+        /// The record gets a `context` method adding a context to it, and results
+        /// failing with it get the same through an `{Record}Context` trait, much
+        /// like `anyhow::Context`. Hosts rebuild chained errors from it.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub error_context: Option<String>,
+
+        /// Name of a `string` field which error records fill with the source
+        /// location their constructor was called from, so that host logs show
+        /// where an error originated.
+        ///
+        /// Records returned by exports which have the field get a
+        /// `#[track_caller]` `new` constructor taking their other fields, and
+        /// variant cases carrying such a record get one named after the case.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub error_location: Option<String>,
+
+        /// Makes the generated glue legible in backtraces and debuggers.
+        ///
+        /// Import wrappers are `#[track_caller]`, so that panics while lifting
+        /// their results point at the call site, and export shims are never
+        /// inlined, keeping a frame of their own named after the interface and
+        /// function. Each starts with an `// iface.function (generated)` marker.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub debug_frames: bool,
+
+        /// Defines the named types which several interfaces declare identically
+        /// only in the first of them, which the others re-export, so that their
+        /// values can be passed from one interface to another.
+        ///
+        /// Types `use`d from another WIT file are shared even without this
+        /// option. Types with handles are never shared, nor are the types of
+        /// interfaces encoding values. Sharing is off with `export_macro` and
+        /// `zeroize`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub share_types: bool,
+
+        /// Error through which exports report failed checks, written `type` for
+        /// an error record or `type.case` for a case of an error variant.
+        ///
+        /// Exporting interfaces declaring it get `ensure!` and `bail!` macros,
+        /// like anyhow's, returning it with the formatted message. The case must
+        /// carry a `string` or a record built from one, as must the record, which
+        /// is either the `error_context` or has only the `error_location` field
+        /// besides the message.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub ensure: Option<String>,
+
+        /// Functions to leave out of the bindings, named `func` for those of
+        /// every interface or `iface.func` for those of one, such as functions
+        /// implemented by hand or unsupported on the target.
+        ///
+        /// Skipped exports get no trait method or export shim, and skipped
+        /// imports no function calling them.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub skip: Vec<String>,
+
+        /// Rust names to use instead of those derived from WIT names, keyed by
+        /// the WIT name of a function, record, variant, enum, flags or type
+        /// alias, or by `type.case` for a case of a variant or enum.
+        ///
+        /// Only the Rust side is renamed: the names in the canonical ABI are
+        /// still the WIT ones, so renamed bindings interoperate with others.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub rename: Vec<(String, String)>,
+
+        /// Whether records, variants, unions, enums and flags get a
+        /// `lowered_size` method returning how many bytes a value takes up in
+        /// linear memory once lowered, strings and lists included, so that
+        /// payloads can be checked against limits before they are passed.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub lowered_size: bool,
+
+        /// Whether each interface's module gets a `SCHEMA` static describing its
+        /// functions and named types, which the module can inspect at runtime.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub schema: bool,
+
+        /// Existing Rust types to use instead of generating the named types
+        /// they are keyed by, such as `linera_base::CryptoHash` for a
+        /// `crypto-hash` record.
+        ///
+        /// The bindings lift and lower values of a mapped type through its public
+        /// fields or cases, which must be named like those of the WIT type, and
+        /// the type must implement any codec traits the bindings need.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub with: Vec<(String, String)>,
+
+        /// Paths of derive macros, such as `serde::Serialize`, applied to every
+        /// generated record, variant, union, enum and flags type.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub additional_derives: Vec<String>,
+
+        /// The most elements a list, or bytes a string, lifted from the host may
+        /// have. Longer ones panic before anything is allocated for them.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub max_list_len: Option<usize>,
+
+        /// Whether `unchecked` bindings still check that the lists the host
+        /// passes in are aligned for their elements, which is cheap, while
+        /// trusting their contents. Checked bindings always do.
+        ///
+        /// Reading through a misaligned pointer is undefined behavior.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub check_alignment: bool,
+
+        /// Only generates the canonical ABI plumbing of imports for wasm32, so
+        /// that crates using the bindings also build natively, e.g. for unit
+        /// tests. There imports panic when called instead.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub native_fallback: bool,
+
+        /// Features whose items, annotated with `@feature("name")`, are
+        /// generated, where the others are omitted.
+        ///
+        /// Applied by the macros as they load the interfaces. The CLI takes
+        /// `--feature` for every generator instead.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub features: Vec<String>,
+
+        /// Path of a module declared with `wit_bindgen_guest_rust::intrinsics!`,
+        /// whose return area the bindings use instead of declaring one per
+        /// interface, so that the bindings of several invocations share it.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub intrinsics_path: Option<String>,
+
+        /// How the named types of imported interfaces hold strings, lists and
+        /// handles: `owning`, `borrowing` or `borrowing-duplicate-if-necessary`,
+        /// the default.
+        ///
+        /// Owning types are passed to imports by value, which saves cloning
+        /// values built for them from owned data. Borrowing ones are built from
+        /// references instead, which saves copying data kept around anyway.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub ownership: Option<Ownership>,
+
+        /// Options replacing these ones for the interfaces they're named after,
+        /// such as `unchecked` bindings for a trusted interface only.
+        ///
+        /// Options that apply to all the bindings at once, like `prelude`, are
+        /// only read from the others.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub overrides: Vec<(String, Opts)>,
+    }
+}
+
+/// How the named types of imported interfaces hold strings, lists and
+/// handles, which the `ownership` option picks.
+///
+/// Functions take strings and lists which aren't inside named types as `&str`
+/// and `&[T]` regardless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ownership {
+    /// Types own their contents, so imports take them by value.
+    Owning,
+    /// Types borrow their contents where they're only used as parameters,
+    /// and own them where they're also used as results.
+    Borrowing,
+    /// Types borrow their contents where they're used as parameters, with a
+    /// `*Param` variant of their own if they're also used as results, for a
+    /// `*Result` variant owning them. This is the default.
+    BorrowingDuplicateIfNecessary,
+}
+
+impl std::str::FromStr for Ownership {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Ownership, String> {
+        match s {
+            "owning" => Ok(Ownership::Owning),
+            "borrowing" => Ok(Ownership::Borrowing),
+            "borrowing-duplicate-if-necessary" => Ok(Ownership::BorrowingDuplicateIfNecessary),
+            _ => Err(format!(
+                "unknown ownership `{}`, expected `owning`, `borrowing` or \
+                 `borrowing-duplicate-if-necessary`",
+                s
+            )),
+        }
+    }
+}
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Default)]
+struct Trait {
+    methods: Vec<String>,
+    resource_methods: BTreeMap<ResourceId, Vec<String>>,
+}
+
+/// How an import annotated with `@paginated(cursor, items, next)` threads
+/// its cursor from one page to the next.
+struct Pagination<'a> {
+    func: &'a Function,
+    /// The `option` parameter holding the cursor of the page to fetch, which
+    /// is `none` for the first page.
+    cursor: &'a str,
+    /// Whether the cursor is owned by the page but borrowed by the import.
+    cursor_by_ref: bool,
+    /// The list field of the page holding its items.
+    items: String,
+    item: Type,
+    /// The `option` field of the page holding the cursor of the next page.
+    next: String,
+    /// The error type if pages are returned as `result<page, error>`.
+    error: Option<Type>,
+}
+
+impl Opts {
+    pub fn build(self) -> RustWasm {
+        let mut r = RustWasm::new();
+        r.opts = self;
+        r
+    }
+
+    /// Returns whether the function `func` of the interface `iface` is named
+    /// by [`Opts::skip`].
+    pub fn skips(&self, iface: &str, func: &str) -> bool {
+        self.skip.iter().any(|name| match name.split_once('.') {
+            Some((iface_name, func_name)) => iface_name == iface && func_name == func,
+            None => name == func,
+        })
+    }
+
+    /// Like [`Opts::build`], after checking that the options are consistent
+    /// with [`Opts::validate`].
+    pub fn try_build(self) -> anyhow::Result<RustWasm> {
+        self.validate()?;
+        Ok(self.build())
+    }
+
+    /// Checks that the options make sense together, such as `types_path`
+    /// only being set along with `export_macro`, rather than leaving
+    /// generation to fail or emit code which doesn't compile.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.export_macro.is_some() {
+            if self.export_type.is_some() {
+                anyhow::bail!("`export_type` can't be used with `export_macro`");
+            }
+            // The macro expands in crates which don't declare the module.
+            if self.intrinsics_path.is_some() {
+                anyhow::bail!("`intrinsics_path` can't be used with `export_macro`");
+            }
+            // Macros exported by a macro expansion can't be re-exported by path.
+            if self.prelude {
+                anyhow::bail!("`prelude` can't be used with `export_macro`");
+            }
+        } else if self.export_macros.is_empty() {
+            if self.types_path.is_some() {
+                anyhow::bail!("`types_path` requires `export_macro`");
+            }
+            if self.reexported_crate_path.is_some() {
+                anyhow::bail!("`reexported_crate_path` requires `export_macro`");
+            }
+            if self.no_macro_export {
+                anyhow::bail!("`no_macro_export` requires `export_macro`");
+            }
+            if self.export_macro_vis.is_some() {
+                anyhow::bail!("`export_macro_vis` requires `export_macro`");
+            }
+            if self.export_macro_module.is_some() {
+                anyhow::bail!("`export_macro_module` requires `export_macro`");
+            }
+        }
+        if !self.export_macros.is_empty() {
+            if self.export_macro.is_some() {
+                anyhow::bail!("`export_macros` can't be used with `export_macro`");
+            }
+            // Each macro would open a module of the same name.
+            if self.export_macro_module.is_some() {
+                anyhow::bail!("`export_macro_module` can't be used with `export_macros`");
+            }
+            let mut names = HashSet::new();
+            for (iface, export_macro) in &self.export_macros {
+                if !names.insert(export_macro) {
+                    anyhow::bail!("export macro `{}` is given more than once", export_macro);
+                }
+                let opts = Opts {
+                    export_macro: Some(export_macro.clone()),
+                    export_macros: Vec::new(),
+                    ..self.clone()
+                };
+                opts.validate()
+                    .map_err(|e| e.context(format!("invalid options for `{}`", iface)))?;
+            }
+        }
+        // A `use` of an exported macro would clash with it at the crate root.
+        if self.export_macro_vis.is_some() && !self.no_macro_export {
+            anyhow::bail!("`export_macro_vis` requires `no_macro_export`");
+        }
+        // Otherwise nothing could name the macro outside of its module.
+        if self.export_macro_module.is_some()
+            && self.no_macro_export
+            && self.export_macro_vis.is_none()
+        {
+            anyhow::bail!(
+                "`export_macro_module` with `no_macro_export` requires `export_macro_vis`"
+            );
+        }
+        match (&self.query, &self.query_response) {
+            (Some(_), None) => anyhow::bail!("`query` requires `query_response`"),
+            (None, Some(_)) => anyhow::bail!("`query_response` requires `query`"),
+            _ => {}
+        }
+        if self.hot_calls.is_some() && self.profile.is_none() {
+            anyhow::bail!("`hot_calls` requires `profile`");
+        }
+        if self.chunk_size == Some(0) {
+            anyhow::bail!("`chunk_size` must be positive");
+        }
+        if !self.prelude_exclude.is_empty() && !self.prelude {
+            anyhow::bail!("`prelude_exclude` requires `prelude`");
+        }
+        if self.no_alloc && self.abi_version == Some(1) {
+            anyhow::bail!("`no_alloc` can't be used with version 1 of the ABI");
+        }
+        if self.no_std {
+            let needs_std = |opts: &Opts| {
+                if !opts.memoize.is_empty() {
+                    Some("memoize")
+                } else if !opts.interned.is_empty() {
+                    Some("interned")
+                } else {
+                    None
+                }
+            };
+            let overrides = self.overrides.iter().map(|(_, opts)| opts);
+            if let Some(option) = std::iter::once(self).chain(overrides).find_map(needs_std) {
+                anyhow::bail!("`no_std` can't be used with `{}`", option);
+            }
+        }
+        if !self.module_names.is_empty() && self.export_macro.is_some() {
+            anyhow::bail!("`module_names` can't be used with `export_macro`");
+        }
+        let mut paths = HashSet::new();
+        for (iface, path) in &self.module_names {
+            let is_ident = |s: &str| {
+                s.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                    && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+            };
+            if !path.split("::").all(is_ident) {
+                anyhow::bail!(
+                    "`{}` is mapped to `{}`, which isn't a module path",
+                    iface,
+                    path
+                );
+            }
+            if !paths.insert(path) {
+                anyhow::bail!("module `{}` is given more than once", path);
+            }
+        }
+        for (_, path) in &self.module_names {
+            let prefix = format!("{}::", path);
+            let nested = self
+                .module_names
+                .iter()
+                .find(|(_, p)| p.starts_with(&prefix));
+            if let Some((_, other)) = nested {
+                anyhow::bail!("module `{}` can't also contain module `{}`", path, other);
+            }
+        }
+        for (name, opts) in &self.overrides {
+            opts.validate()
+                .map_err(|e| e.context(format!("invalid options for `{}`", name)))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the name of the function or trait method generated for the
+    /// WIT function `name`.
+    pub fn function_ident(&self, name: &str) -> String {
+        match self.renamed(name) {
+            Some(renamed) => renamed.to_string(),
+            None => to_rust_ident(name),
+        }
+    }
+
+    fn renamed(&self, wit_name: &str) -> Option<&str> {
+        self.rename
+            .iter()
+            .find(|(from, _)| from == wit_name)
+            .map(|(_, to)| to.as_str())
+    }
+
+    fn module_path(&self, iface: &str) -> Option<&str> {
+        self.module_names
+            .iter()
+            .find(|(name, _)| name == iface)
+            .map(|(_, path)| path.as_str())
+    }
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    /// Whether or not `rustfmt` is executed to format generated code.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub rustfmt: bool,
+impl RustWasm {
+    pub fn new() -> RustWasm {
+        RustWasm::default()
+    }
+
+    /// Picks the functions of `iface` which get batching, memoization and
+    /// inlined shims according to the profile, if there is one.
+    fn apply_profile(&mut self, iface: &Interface) {
+        self.batch = self.opts.batch.clone();
+        self.memoize = self.opts.memoize.clone();
+        self.hot.clear();
+        self.cold.clear();
+        let path = match &self.opts.profile {
+            Some(path) => path,
+            None => return,
+        };
+        let profile = match Profile::load(path) {
+            Ok(profile) => profile,
+            Err(e) => panic!("{:?}", e),
+        };
+        let hot_calls = self.opts.hot_calls.unwrap_or(DEFAULT_HOT_CALLS);
+        for func in iface.functions.iter() {
+            if profile.is_hot(&iface.name, &func.name, hot_calls) {
+                self.hot.insert(func.name.clone());
+            } else if profile.is_cold(&iface.name, &func.name) {
+                self.cold.insert(func.name.clone());
+            }
+        }
+        let hot = &self.hot;
+        self.batch.retain(|name| hot.contains(name));
+        self.memoize.retain(|name| hot.contains(name));
+    }
+
+    fn abi_variant(dir: Direction) -> AbiVariant {
+        // This generator uses the obvious direction to ABI variant mapping.
+        match dir {
+            Direction::Export => AbiVariant::GuestExport,
+            Direction::Import => AbiVariant::GuestImport,
+        }
+    }
+
+    fn ret_area_type_name(iface: &Interface) -> String {
+        format!("__{}RetArea", iface.name.to_camel_case())
+    }
+
+    fn ret_area_name(iface: &Interface) -> String {
+        format!("__{}_RET_AREA", iface.name.to_shouty_snake_case())
+    }
+
+    fn crate_path(&self) -> String {
+        if self.in_macro {
+            self.opts
+                .reexported_crate_path
+                .as_ref()
+                .map(|path| format!("$crate::{path}"))
+                .unwrap_or_else(|| "wit_bindgen_guest_rust".to_owned())
+        } else {
+            "wit_bindgen_guest_rust".to_owned()
+        }
+    }
+
+    /// Returns the path of `item`, such as `vec::Vec`, from `alloc`, which is
+    /// re-exported by `std` and partly by its prelude unless the bindings are
+    /// `no_std`.
+    fn alloc_item(&self, item: &str) -> String {
+        const PRELUDE: &[&str] = &["boxed::Box", "format", "string::String", "vec::Vec"];
+        if self.no_std() {
+            format!("alloc::{item}")
+        } else if PRELUDE.contains(&item) {
+            item.rsplit("::").next().unwrap().to_string()
+        } else {
+            format!("std::{item}")
+        }
+    }
+
+    /// Returns the expression converting `value` to a `String` through its
+    /// `Display` implementation, which `no_std` bindings can't call as a
+    /// method as `ToString` isn't in the `core` prelude.
+    fn to_string_expr(&self, value: &str) -> String {
+        if self.no_std() {
+            format!("alloc::string::ToString::to_string(&{value})")
+        } else {
+            format!("{value}.to_string()")
+        }
+    }
+
+    /// Returns the type implementing the exported interface `name`, as seen
+    /// from the generated module.
+    fn export_type(&self, name: &str) -> String {
+        match &self.opts.export_type {
+            Some(path) => path.clone(),
+            None => format!("super::{}", name.to_camel_case()),
+        }
+    }
+
+    fn message_type(&self, iface: &Interface) -> Option<TypeId> {
+        let name = self.opts.message.as_ref()?;
+        Some(iface.type_lookup[name])
+    }
+
+    /// Returns the query and response variants of the service described by
+    /// `iface`, if both are configured.
+    fn query_types(&self, iface: &Interface) -> Option<(TypeId, TypeId)> {
+        let query = iface.type_lookup[self.opts.query.as_ref()?];
+        let response = iface.type_lookup[self.opts.query_response.as_ref()?];
+        Some((query, response))
+    }
+
+    /// Checks that the `message`, `query` and `query_response` options name
+    /// variants of `iface`.
+    fn check_encoded_types(&self, iface: &Interface) -> Result<(), String> {
+        let options = [
+            ("message", &self.opts.message),
+            ("query", &self.opts.query),
+            ("query_response", &self.opts.query_response),
+        ];
+        for (option, name) in options {
+            let name = match name {
+                Some(name) => name,
+                None => continue,
+            };
+            let id = match iface.type_lookup.get(name) {
+                Some(id) => *id,
+                None => {
+                    return Err(format!(
+                        "`{}` names `{}`, which isn't a type of `{}`",
+                        option, name, iface.name
+                    ))
+                }
+            };
+            if !matches!(iface.types[id].kind, TypeDefKind::Variant(_)) {
+                return Err(format!(
+                    "`{}` names `{}`, which isn't a variant",
+                    option, name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the types which are encoded into bytes rather than passed
+    /// through the canonical ABI.
+    fn encoded_types(&self, iface: &Interface) -> Vec<Type> {
+        let queries = self
+            .query_types(iface)
+            .map(|(query, response)| [Type::Id(query), Type::Id(response)]);
+        self.message_type(iface)
+            .map(Type::Id)
+            .into_iter()
+            .chain(queries.into_iter().flatten())
+            .chain(iface.events.iter().map(|e| e.payload))
+            .chain(self.encoded_param_types(iface))
+            .collect()
+    }
+
+    /// Returns the parameter types of imports whose arguments are encoded,
+    /// either to queue them in a batch, to key a memoized result or to start a
+    /// chunked transfer, along with the element types of chunked results.
+    fn encoded_param_types(&self, iface: &Interface) -> Vec<Type> {
+        if !self.in_import {
+            return Vec::new();
+        }
+        let chunked = match self.opts.chunk_threshold {
+            Some(_) => chunked_functions(iface),
+            None => Vec::new(),
+        };
+        batched_functions(iface, &self.batch)
+            .into_iter()
+            .map(|(_, func)| func)
+            .chain(memoized_functions(iface, &self.memoize))
+            .chain(chunked.iter().map(|(_, func, _)| *func))
+            .flat_map(|func| func.params.iter().map(|(_, ty)| *ty))
+            .chain(chunked.iter().map(|(_, _, element)| *element))
+            .collect()
+    }
+
+    /// Generates a `*_chunked` variant of each chunked import, which returns
+    /// an iterator over the result, leaving it on the host if it exceeds the
+    /// threshold and reading it piece by piece.
+    fn print_chunked_imports(&mut self, iface: &Interface) {
+        let threshold = match self.opts.chunk_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        let codec = format!("{}::codec", self.crate_path());
+        let chunked = format!("{}::chunked", self.crate_path());
+        let module = iface.module.as_deref().unwrap_or(&iface.name);
+        let chunk_size = self.opts.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE);
+        for (index, func, element) in chunked_functions(iface) {
+            let name = self.func_ident(&func.name);
+            self.src.push_str(&format!(
+                "/// Like [`{name}`], but returns an iterator over the result.\n\
+                 ///\n\
+                 /// Results of more than {threshold} bytes are left with the host\n\
+                 /// and read {chunk_size} bytes at a time as the iterator advances.\n"
+            ));
+            self.src.push_str(&format!("pub fn {name}_chunked("));
+            for (param, ty) in func.params.iter() {
+                self.src.push_str(&format!("{}: ", to_rust_ident(param)));
+                self.print_ty(iface, ty, TypeMode::AllBorrowed("'_"));
+                self.src.push_str(", ");
+            }
+            self.src.push_str(&format!(") -> {chunked}::Chunked<"));
+            self.print_ty(iface, &element, TypeMode::Owned);
+            self.src.push_str("> {\n");
+            let vec = self.alloc_item("vec::Vec");
+            self.src
+                .push_str(&format!("let mut args = {vec}::new();\n"));
+            for (param, _) in func.params.iter() {
+                self.src.push_str(&format!(
+                    "{codec}::Encode::encode_to(&{}, &mut args);\n",
+                    to_rust_ident(param),
+                ));
+            }
+            self.src.push_str(&format!(
+                "
+                    #[link(wasm_import_module = \"{module}\")]
+                    extern \"C\" {{
+                        #[cfg_attr(target_arch = \"wasm32\", link_name = \"chunked_call\")]
+                        #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_chunked_call\")]
+                        fn call(_: i32, _: i32, _: i32, _: i32, _: i32) -> i32;
+                        #[cfg_attr(target_arch = \"wasm32\", link_name = \"chunked_read\")]
+                        #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_chunked_read\")]
+                        fn read(_: i32, _: i32, _: i32) -> i32;
+                        #[cfg_attr(target_arch = \"wasm32\", link_name = \"chunked_release\")]
+                        #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_chunked_release\")]
+                        fn release(_: i32);
+                    }}
+                    unsafe {{
+                        {chunked}::Chunked::call(call, {index}, &args, read, release, {threshold}, {chunk_size})
+                    }}
+                }}
+                "
+            ));
+        }
+    }
+
+    /// Returns the imports annotated with `@paginated`, checking that each
+    /// has a cursor parameter and returns pages with matching fields.
+    fn paginated_functions(iface: &Interface) -> Vec<Pagination<'_>> {
+        fn kind<'a>(iface: &'a Interface, ty: &Type) -> Option<&'a TypeDefKind> {
+            match ty {
+                Type::Id(id) => match &iface.types[*id].kind {
+                    TypeDefKind::Type(t) => kind(iface, t),
+                    kind => Some(kind),
+                },
+                _ => None,
+            }
+        }
+
+        let mut ret = Vec::new();
+        for func in iface.functions.iter() {
+            let annotation = match func.docs.annotation("paginated") {
+                Some(annotation) => annotation,
+                None => continue,
+            };
+            let name = &func.name;
+            if annotation.args.len() > 3 {
+                panic!(
+                    "`@paginated` on `{}` takes at most a cursor parameter, \
+                     an items field and a next cursor field",
+                    name
+                );
+            }
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "paginated function `{}` must not be a resource function",
+                    name
+                );
+            }
+            let arg = |i: usize, default: &'static str| {
+                annotation
+                    .args
+                    .get(i)
+                    .map(|s| s.as_str())
+                    .unwrap_or(default)
+            };
+
+            let cursor = arg(0, "cursor");
+            let cursor_ty = match func.params.iter().find(|(param, _)| param == cursor) {
+                Some((_, ty)) => match kind(iface, ty) {
+                    Some(TypeDefKind::Option(ty)) => *ty,
+                    _ => panic!(
+                        "cursor parameter `{}` of paginated function `{}` must be an option",
+                        cursor, name
+                    ),
+                },
+                None => panic!(
+                    "paginated function `{}` has no cursor parameter `{}`",
+                    name, cursor
+                ),
+            };
+            let cursor_by_ref = encoded_param_by_ref(iface, &cursor_ty).unwrap_or_else(|| {
+                panic!(
+                    "cursor of paginated function `{}` must be a scalar, string, \
+                     enum, flags or byte list",
+                    name
+                )
+            });
+            for (param, ty) in func.params.iter() {
+                if param != cursor && encoded_param_by_ref(iface, ty).is_none() {
+                    panic!(
+                        "parameter `{}` of paginated function `{}` must be a scalar, \
+                         string, enum, flags or byte list",
+                        param, name
+                    );
+                }
+            }
+
+            let (page, error) = match kind(iface, &func.result) {
+                Some(TypeDefKind::Result(r)) => (r.ok, Some(r.err)),
+                _ => (func.result, None),
+            };
+            let fields = match kind(iface, &page) {
+                Some(TypeDefKind::Record(r)) => &r.fields,
+                _ => panic!(
+                    "paginated function `{}` must return a record or a result of one",
+                    name
+                ),
+            };
+            let field = |field: &str| {
+                fields.iter().find(|f| f.name == field).unwrap_or_else(|| {
+                    panic!("page returned by `{}` has no field `{}`", name, field)
+                })
+            };
+
+            let items = arg(1, "items");
+            let item = match kind(iface, &field(items).ty) {
+                Some(TypeDefKind::List(ty)) => *ty,
+                _ => panic!(
+                    "field `{}` of the page returned by `{}` must be a list",
+                    items, name
+                ),
+            };
+            let next = arg(2, "next-cursor");
+            match kind(iface, &field(next).ty) {
+                Some(TypeDefKind::Option(ty)) if *ty == cursor_ty => {}
+                _ => panic!(
+                    "field `{}` of the page returned by `{}` must have the same \
+                     type as its `{}` parameter",
+                    next, name, cursor
+                ),
+            }
+
+            ret.push(Pagination {
+                func,
+                cursor,
+                cursor_by_ref,
+                items: to_rust_ident(items),
+                item,
+                next: to_rust_ident(next),
+                error,
+            });
+        }
+        ret
+    }
+
+    /// Generates a `const fn` for each of the `const_fns` imports, returning
+    /// the results listed by its `@table` annotation.
+    fn print_const_fns(&mut self, iface: &Interface) {
+        let names = self.opts.const_fns.clone();
+        for name in names.iter() {
+            let func = iface
+                .functions
+                .iter()
+                .find(|f| f.name == *name)
+                .unwrap_or_else(|| panic!("const function `{}` does not exist", name));
+            if func.kind != FunctionKind::Freestanding {
+                panic!("const function `{}` must not be a resource function", name);
+            }
+            let table = match func.docs.annotation("table") {
+                Some(annotation) => &annotation.args,
+                None => panic!("const function `{}` must have a `@table` annotation", name),
+            };
+            let result = |value: &String| {
+                const_literal(self, iface, &func.result, value).unwrap_or_else(|| {
+                    panic!(
+                        "`{}` is not a valid result for const function `{}`",
+                        value, name
+                    )
+                })
+            };
+            let body = match &func.params[..] {
+                [] if table.len() == 1 => result(&table[0]),
+                [] => panic!("`@table` of const function `{}` must list one result", name),
+                [(param, ty)] => {
+                    let values = const_domain(self, iface, ty).unwrap_or_else(|| {
+                        panic!(
+                            "parameter `{}` of const function `{}` must be a bool or an enum",
+                            param, name
+                        )
+                    });
+                    if values.len() != table.len() {
+                        panic!(
+                            "`@table` of const function `{}` must list {} results, \
+                             one for each value of `{}`",
+                            name,
+                            values.len(),
+                            param
+                        );
+                    }
+                    let mut body = format!("match {} {{\n", to_rust_ident(param));
+                    for (value, result) in values.iter().zip(table.iter().map(result)) {
+                        body.push_str(&format!("{} => {},\n", value, result));
+                    }
+                    body.push('}');
+                    body
+                }
+                _ => panic!("const function `{}` must take at most one parameter", name),
+            };
+            self.rustdoc(&func.docs);
+            self.src.push_str(&format!(
+                "pub const fn {}_const(",
+                self.func_ident(&func.name)
+            ));
+            if let Some((param, ty)) = func.params.first() {
+                self.src.push_str(&format!("{}: ", to_rust_ident(param)));
+                self.print_ty(iface, ty, TypeMode::Owned);
+            }
+            self.src.push_str(") -> ");
+            self.print_ty(iface, &func.result, TypeMode::Owned);
+            self.src.push_str(&format!(" {{\n{}\n}}\n", body));
+        }
+    }
+
+    /// Generates an iterator for each paginated import which yields the
+    /// items of every page in turn, requesting the next page with the cursor
+    /// returned by the previous one until the host stops returning cursors.
+    fn print_paginated_imports(&mut self, iface: &Interface) {
+        for p in Self::paginated_functions(iface) {
+            let name = self.func_ident(&p.func.name);
+            let iter = format!("{}Iter", name.to_camel_case());
+            let params = p
+                .func
+                .params
+                .iter()
+                .filter(|(param, _)| param != p.cursor)
+                .collect::<Vec<_>>();
+            let (_, cursor_ty) = p
+                .func
+                .params
+                .iter()
+                .find(|(param, _)| param == p.cursor)
+                .unwrap();
+
+            self.src.push_str(&format!(
+                "/// Iterates over the items of every page returned by [`{name}`],\n\
+                 /// fetching the next page whenever the previous one runs out.\n"
+            ));
+            self.src.push_str(&format!("pub fn {name}_iter<'a>("));
+            for (param, ty) in params.iter() {
+                self.src.push_str(&format!("{}: ", to_rust_ident(param)));
+                self.print_ty(iface, ty, TypeMode::AllBorrowed("'a"));
+                self.src.push_str(", ");
+            }
+            self.src
+                .push_str(&format!(") -> {iter}<'a> {{\n{iter} {{\nargs: ("));
+            for (param, _) in params.iter() {
+                self.src.push_str(&format!("{}, ", to_rust_ident(param)));
+            }
+            self.src.push_str(&format!(
+                "),
+                    cursor: Some(None),
+                    items: {}::new().into_iter(),
+                    _marker: core::marker::PhantomData,
+                }}
+                }}
+                ",
+                self.alloc_item("vec::Vec"),
+            ));
+
+            self.src
+                .push_str(&format!("/// Iterator returned by [`{name}_iter`].\n"));
+            self.src
+                .push_str(&format!("pub struct {iter}<'a> {{\nargs: ("));
+            for (_, ty) in params.iter() {
+                self.print_ty(iface, ty, TypeMode::AllBorrowed("'a"));
+                self.src.push_str(", ");
+            }
+            self.src.push_str("),\ncursor: Option<");
+            self.print_ty(iface, cursor_ty, TypeMode::Owned);
+            self.src
+                .push_str(&format!(">,\nitems: {}<", self.alloc_item("vec::IntoIter")));
+            self.print_ty(iface, &p.item, TypeMode::Owned);
+            self.src
+                .push_str(">,\n_marker: core::marker::PhantomData<&'a ()>,\n}\n");
+
+            self.src.push_str(&format!(
+                "impl<'a> Iterator for {iter}<'a> {{\ntype Item = "
+            ));
+            match &p.error {
+                Some(error) => {
+                    self.src.push_str("Result<");
+                    self.print_ty(iface, &p.item, TypeMode::Owned);
+                    self.src.push_str(", ");
+                    self.print_ty(iface, error, TypeMode::Owned);
+                    self.src.push_str(">");
+                }
+                None => self.print_ty(iface, &p.item, TypeMode::Owned),
+            }
+            let item = if p.error.is_some() {
+                "Ok(item)"
+            } else {
+                "item"
+            };
+            self.src.push_str(&format!(
+                ";
+                    fn next(&mut self) -> Option<Self::Item> {{
+                        loop {{
+                            if let Some(item) = self.items.next() {{
+                                return Some({item});
+                            }}
+                            // The cursor is only gone once the last page has
+                            // been fetched.
+                            let cursor = self.cursor.take()?;
+                            let page = {name}(
+                "
+            ));
+            let mut arg = 0;
+            for (param, _) in p.func.params.iter() {
+                if param == p.cursor {
+                    if p.cursor_by_ref {
+                        self.src.push_str("cursor.as_deref(), ");
+                    } else {
+                        self.src.push_str("cursor, ");
+                    }
+                } else {
+                    self.src.push_str(&format!("self.args.{arg}, "));
+                    arg += 1;
+                }
+            }
+            self.src.push_str(");\n");
+            if p.error.is_some() {
+                self.src.push_str(
+                    "let page = match page {
+                        Ok(page) => page,
+                        Err(e) => return Some(Err(e)),
+                    };
+                    ",
+                );
+            }
+            self.src.push_str(&format!(
+                "
+                            self.cursor = page.{next}.map(Some);
+                            self.items = page.{items}.into_iter();
+                        }}
+                    }}
+                }}
+                ",
+                next = p.next,
+                items = p.items,
+            ));
+        }
+    }
 
-    /// Adds the wit module name into import binding names when enabled.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub multi_module: bool,
+    /// Generates the export returning a pointer to the indices, in the
+    /// indirect function table, of every exported function by operation id.
+    fn print_dispatch_table(&mut self, iface: &Interface) {
+        let iface_name = iface.name.to_snake_case();
+        let entries = iface
+            .functions
+            .iter()
+            .map(|func| {
+                format!(
+                    "__wit_bindgen_{}_{} as usize as u32,\n",
+                    iface_name,
+                    func.name.to_snake_case()
+                )
+            })
+            .collect::<String>();
+        self.src.push_str(&format!(
+            "
+                #[export_name = \"{ns}canonical_abi_dispatch_table\"]
+                unsafe extern \"C\" fn __wit_bindgen_{iface_name}_dispatch_table() -> i32 {{
+                    static mut DISPATCH_TABLE: [u32; {len}] = [0; {len}];
+                    DISPATCH_TABLE = [{entries}];
+                    DISPATCH_TABLE.as_ptr() as i32
+                }}
+            ",
+            ns = self.opts.symbol_namespace,
+            len = iface.functions.len(),
+        ));
+    }
+
+    /// Generates the functions creating writers and readers of the host's
+    /// bulk buffers.
+    fn print_bulk(&mut self, iface: &Interface) {
+        if !self.opts.bulk {
+            return;
+        }
+        let bulk = format!("{}::bulk", self.crate_path());
+        let module = iface.module.as_deref().unwrap_or(&iface.name);
+        let mut externs = String::new();
+        for (name, sig) in [
+            ("create", "(_: i32) -> i32"),
+            ("len", "(_: i32) -> i32"),
+            ("write", "(_: i32, _: i32, _: i32, _: i32)"),
+            ("read", "(_: i32, _: i32, _: i32, _: i32) -> i32"),
+            ("release", "(_: i32)"),
+        ] {
+            externs.push_str(&format!(
+                "
+                    #[cfg_attr(target_arch = \"wasm32\", link_name = \"bulk_{name}\")]
+                    #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_bulk_{name}\")]
+                    fn bulk_{name}{sig};
+                "
+            ));
+        }
+        self.src.push_str(&format!(
+            "
+                #[link(wasm_import_module = \"{module}\")]
+                extern \"C\" {{
+                    {externs}
+                }}
+
+                static BULK_IMPORTS: {bulk}::BulkImports = unsafe {{
+                    {bulk}::BulkImports::new(bulk_create, bulk_len, bulk_write, bulk_read, bulk_release)
+                }};
+
+                /// Starts filling a new buffer on the host, whose handle can
+                /// then be passed to imports in place of the data.
+                pub fn bulk_writer() -> {bulk}::BulkWriter {{
+                    {bulk}::BulkWriter::new(&BULK_IMPORTS)
+                }}
+
+                /// Reads the buffer behind a handle returned by an import,
+                /// releasing it once the reader is dropped.
+                ///
+                /// # Safety
+                ///
+                /// `handle` must not be read or released anywhere else.
+                pub unsafe fn bulk_reader(handle: u32) -> {bulk}::BulkReader {{
+                    {bulk}::BulkReader::new(&BULK_IMPORTS, handle)
+                }}
+            "
+        ));
+    }
+
+    /// Checks that the bindings of `iface` won't allocate: none of its
+    /// functions pass lists, strings or handles, and no option generates code
+    /// which encodes values.
+    fn check_no_alloc(&mut self, iface: &Interface) -> Result<(), String> {
+        if let Some((_, resource)) = iface.resources.iter().next() {
+            return Err(format!(
+                "`no_alloc` bindings can't have resources such as `{}`",
+                resource.name
+            ));
+        }
+        for func in iface.functions.iter() {
+            let tys = func.params.iter().map(|(_, ty)| ty).chain([&func.result]);
+            for ty in tys {
+                let info = self.types.type_info(iface, ty);
+                if info.has_list || info.has_handle {
+                    return Err(format!(
+                        "`no_alloc` bindings can't pass lists, strings or handles, \
+                         as `{}` does",
+                        func.name
+                    ));
+                }
+            }
+        }
+        let encodes = !self.encoded_types(iface).is_empty()
+            || (self.in_import && (!self.opts.interned.is_empty() || self.opts.bulk));
+        if encodes {
+            return Err(
+                "`no_alloc` bindings can't have messages, queries, events, or use \
+                 `batch`, `memoize`, `chunk_threshold`, `interned` or `bulk`"
+                    .to_string(),
+            );
+        }
+        if self.opts.abi_version == Some(1) {
+            return Err("`no_alloc` bindings can't use version 1 of the ABI".to_string());
+        }
+        Ok(())
+    }
+
+    /// Writes an implementation of the export trait of `iface`, and of those
+    /// of its resources, with `todo!()` for every function without a default.
+    ///
+    /// The stub goes in the module invoking the bindings, where the export
+    /// types are expected by default.
+    fn print_stub(&self, iface: &Interface) -> Option<Source> {
+        let module = self.module_name(&iface.name);
+        let name = iface.name.to_camel_case();
+        let trait_ = self.traits.get(&name)?;
+        if self.opts.export_macro.is_some() {
+            panic!("`stubs` can't be used with `export_macro`");
+        }
+
+        // Handles are written `super::{Resource}` from within the bindings.
+        let stub_methods = |methods: &[String]| {
+            let mut src = String::new();
+            for method in methods {
+                if let Some(sig) = method.trim_end().strip_suffix(';') {
+                    src.push_str(&sig.replace("super::", ""));
+                    src.push_str(" {\ntodo!()\n}\n");
+                }
+            }
+            src
+        };
+        let mut src = Source::default();
+        src.push_str(&format!(
+            "// An implementation of the `{}` interface, to be filled in.\n\n\
+             #[allow(unused_imports)]\n\
+             use {}::*;\n\n\
+             pub struct {};\n\n\
+             #[allow(unused_variables)]\n\
+             impl {}::{} for {} {{\n",
+            iface.name, module, name, module, name, name,
+        ));
+        for id in trait_.resource_methods.keys() {
+            let resource = iface.resources[*id].name.to_camel_case();
+            src.push_str(&format!("type {0} = {0};\n", resource));
+        }
+        src.push_str(&stub_methods(&trait_.methods));
+        src.push_str("}\n");
+        for (id, methods) in trait_.resource_methods.iter() {
+            let resource = iface.resources[*id].name.to_camel_case();
+            src.push_str(&format!(
+                "\npub struct {0};\n\n\
+                 #[allow(unused_variables)]\n\
+                 impl {1}::{0} for {0} {{\n",
+                resource, module,
+            ));
+            src.push_str(&stub_methods(methods));
+            src.push_str("}\n");
+        }
+        Some(src)
+    }
+
+    /// Generates the methods adding contexts to the error context record
+    /// `id`, and to results failing with it.
+    fn print_error_context(&mut self, iface: &Interface, id: TypeId, record: &Record) {
+        // Only owned errors can be given more context.
+        if !self.info(id).result {
+            return;
+        }
+        let name = self.result_name(iface, id);
+        self.src.push_str(&format!("impl {name} {{\n"));
+        let located = has_error_location(iface, id, self.opts.error_location.as_deref());
+        if record.fields.len() == 2 + usize::from(located) {
+            let location = match &self.opts.error_location {
+                Some(field) if located => format!(
+                    "{}: {},",
+                    to_rust_ident(field),
+                    self.to_string_expr("core::panic::Location::caller()"),
+                ),
+                _ => String::new(),
+            };
+            let message = self.to_string_expr("message");
+            let vec = self.alloc_item("vec::Vec");
+            self.src.push_str(&format!(
+                "
+                    /// Creates an error without any context yet.
+                    #[track_caller]
+                    pub fn new(message: impl core::fmt::Display) -> Self {{
+                        Self {{
+                            message: {message},
+                            context: {vec}::new(),
+                            {location}
+                        }}
+                    }}
+                ",
+            ));
+        }
+        let context = self.to_string_expr("context");
+        self.src.push_str(&format!(
+            "
+                    /// Adds `context` to the error, around the contexts it already has.
+                    pub fn context(mut self, context: impl core::fmt::Display) -> Self {{
+                        self.context.push({context});
+                        self
+                    }}
+                }}
+
+                /// Adds context to the `{name}` of a failed result, like `anyhow::Context`.
+                pub trait {name}Context<T> {{
+                    fn context(self, context: impl core::fmt::Display) -> Result<T, {name}>;
+
+                    fn with_context<C: core::fmt::Display>(
+                        self,
+                        f: impl FnOnce() -> C,
+                    ) -> Result<T, {name}>;
+                }}
+
+                impl<T> {name}Context<T> for Result<T, {name}> {{
+                    fn context(self, context: impl core::fmt::Display) -> Result<T, {name}> {{
+                        self.map_err(|err| err.context(context))
+                    }}
+
+                    fn with_context<C: core::fmt::Display>(
+                        self,
+                        f: impl FnOnce() -> C,
+                    ) -> Result<T, {name}> {{
+                        self.map_err(|err| err.context(f()))
+                    }}
+                }}
+            "
+        ));
+    }
+
+    /// Returns the expression building the error `ty` out of a `message:
+    /// String`, if it can be built out of just a message.
+    fn message_error(&mut self, iface: &Interface, ty: &Type) -> Option<String> {
+        let id = match ty {
+            Type::String => return Some("message".to_string()),
+            Type::Id(id) => *id,
+            _ => return None,
+        };
+        let record = match &iface.types[id].kind {
+            TypeDefKind::Record(record) => record,
+            _ => return None,
+        };
+        let error_location = self.opts.error_location.as_deref();
+        let located = has_error_location(iface, id, error_location);
+        let name = self.result_name(iface, id);
+        if is_error_context(iface, id, self.opts.error_context.as_deref()) {
+            if record.fields.len() == 2 + usize::from(located) {
+                return Some(format!("{name}::new(message)"));
+            }
+            return None;
+        }
+        match record.fields.as_slice() {
+            [field] if field.ty == Type::String => Some(format!(
+                "{name} {{ {}: message }}",
+                to_rust_ident(&field.name)
+            )),
+            [_, _] if located => {
+                let message = record
+                    .fields
+                    .iter()
+                    .find(|f| Some(f.name.as_str()) != error_location)
+                    .unwrap();
+                if message.ty == Type::String {
+                    Some(format!("{name}::new(message)"))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Generates the `SCHEMA` static describing the functions and named
+    /// types of the interface with the types of the `schema` runtime module.
+    fn print_schema(&mut self, iface: &Interface) {
+        let schema = format!("{}::schema", self.crate_path());
+        let mut functions = String::new();
+        for func in iface.functions.iter() {
+            let params = func
+                .params
+                .iter()
+                .map(|(name, ty)| format!("({:?}, {}),", name, schema_type(iface, ty, &schema)))
+                .collect::<String>();
+            functions.push_str(&format!(
+                "{schema}::Function {{ name: {:?}, params: &[{params}], result: {} }},\n",
+                func.name,
+                schema_type(iface, &func.result, &schema),
+            ));
+        }
+        let mut types = String::new();
+        for (id, ty) in iface.types.iter() {
+            let name = match &ty.name {
+                Some(name) => name,
+                None => continue,
+            };
+            let kind = match &ty.kind {
+                TypeDefKind::Record(r) => {
+                    let fields = r.fields.iter().map(|f| (&f.name, &f.ty));
+                    format!("Record(&[{}])", schema_cases(iface, fields, &schema))
+                }
+                TypeDefKind::Variant(v) => {
+                    let cases = v.cases.iter().map(|c| (&c.name, &c.ty));
+                    format!("Variant(&[{}])", schema_cases(iface, cases, &schema))
+                }
+                TypeDefKind::Union(u) => {
+                    let cases = u
+                        .cases
+                        .iter()
+                        .map(|c| format!("{},", schema_type(iface, &c.ty, &schema)))
+                        .collect::<String>();
+                    format!("Union(&[{cases}])")
+                }
+                TypeDefKind::Enum(e) => {
+                    let cases = e.cases.iter().map(|c| format!("{:?},", c.name));
+                    format!("Enum(&[{}])", cases.collect::<String>())
+                }
+                TypeDefKind::Flags(f) => {
+                    let flags = f.flags.iter().map(|f| format!("{:?},", f.name));
+                    format!("Flags(&[{}])", flags.collect::<String>())
+                }
+                TypeDefKind::Type(ty) => format!("Alias({})", schema_type(iface, ty, &schema)),
+                _ => format!("Alias({})", schema_shape(iface, id, &schema)),
+            };
+            types.push_str(&format!(
+                "{schema}::TypeDef {{ name: {name:?}, kind: {schema}::TypeKind::{kind} }},\n"
+            ));
+        }
+        self.src.push_str(&format!(
+            "
+                /// The functions and named types of this interface.
+                pub static SCHEMA: {schema}::Interface = {schema}::Interface {{
+                    name: {:?},
+                    exported: {},
+                    functions: &[{functions}],
+                    types: &[{types}],
+                }};
+            ",
+            iface.name, !self.in_import,
+        ));
+    }
+
+    /// Generates the `ensure!` and `bail!` macros returning the error named
+    /// by [`Opts::ensure`] from exports, when this interface declares it.
+    fn print_ensure(&mut self, iface: &Interface) {
+        let ensure = match &self.opts.ensure {
+            Some(ensure) => ensure.clone(),
+            None => return,
+        };
+        let (ty, case) = match ensure.split_once('.') {
+            Some((ty, case)) => (ty, Some(case)),
+            None => (ensure.as_str(), None),
+        };
+        let id = match iface.type_lookup.get(ty) {
+            Some(id) => *id,
+            None => return,
+        };
+        if self.opts.export_macro.is_some() {
+            panic!("`ensure` can't be used with `export_macro`");
+        }
+        if !self.info(id).result {
+            panic!("`{}` is never returned, so can't be used by `ensure!`", ty);
+        }
+        let build = match (&iface.types[id].kind, case) {
+            (TypeDefKind::Variant(variant), Some(case)) => {
+                let case = variant
+                    .cases
+                    .iter()
+                    .find(|c| c.name == case)
+                    .unwrap_or_else(|| panic!("`{}` has no case `{}`", ty, case));
+                self.message_error(iface, &case.ty)
+                    .map(|payload| format!("Self::{}({payload})", self.case_ident(ty, &case.name)))
+            }
+            (TypeDefKind::Record(_), None) => self.message_error(iface, &Type::Id(id)),
+            _ => panic!(
+                "`ensure` must name an error record or a case of an error variant, not `{}`",
+                ensure
+            ),
+        };
+        let build =
+            build.unwrap_or_else(|| panic!("`{}` can't be built out of just a message", ensure));
+        let name = self.result_name(iface, id);
+        let string = self.alloc_item("string::String");
+        let format = self.alloc_item("format");
+        self.src.push_str(&format!(
+            "
+                impl From<{string}> for {name} {{
+                    #[track_caller]
+                    fn from(message: {string}) -> Self {{
+                        {build}
+                    }}
+                }}
+
+                /// Returns early with a `{name}` carrying the formatted message,
+                /// like `anyhow::bail!`.
+                #[allow(unused_macros)]
+                macro_rules! bail {{
+                    ($($arg:tt)+) => {{
+                        return Err(core::convert::From::from({format}!($($arg)+)))
+                    }};
+                }}
+
+                /// Returns early with a `{name}` carrying the formatted message
+                /// unless the condition holds, like `anyhow::ensure!`.
+                #[allow(unused_macros)]
+                macro_rules! ensure {{
+                    ($cond:expr, $($arg:tt)+) => {{
+                        if !($cond) {{
+                            return Err(core::convert::From::from({format}!($($arg)+)));
+                        }}
+                    }};
+                }}
+
+                #[allow(unused_imports)]
+                pub(crate) use {{bail, ensure}};
+            "
+        ));
+    }
+
+    /// Generates a constructor for the error record `id` which fills its
+    /// error location field with the location of its caller.
+    fn print_located_new(&mut self, iface: &Interface, id: TypeId, record: &Record) {
+        if !self.info(id).result {
+            return;
+        }
+        let location = self.opts.error_location.clone().unwrap();
+        let name = self.result_name(iface, id);
+        self.src.push_str(&format!(
+            "impl {name} {{\n\
+             /// Creates the error, recording where it is created.\n\
+             #[track_caller]\n\
+             pub fn new(",
+        ));
+        for field in record.fields.iter().filter(|f| f.name != location) {
+            self.src
+                .push_str(&format!("{}: ", to_rust_ident(&field.name)));
+            self.print_ty(iface, &field.ty, TypeMode::Owned);
+            self.src.push_str(", ");
+        }
+        self.src.push_str(") -> Self {\nSelf {\n");
+        for field in record.fields.iter() {
+            let field = to_rust_ident(&field.name);
+            if field == to_rust_ident(&location) {
+                self.src.push_str(&format!(
+                    "{field}: {},\n",
+                    self.to_string_expr("core::panic::Location::caller()"),
+                ));
+            } else {
+                self.src.push_str(&format!("{field},\n"));
+            }
+        }
+        self.src.push_str("}\n}\n}\n");
+    }
+
+    /// Generates constructors for the cases of variant `id` which carry an
+    /// error record with an error location field, recording where they are
+    /// called from.
+    fn print_located_cases(&mut self, iface: &Interface, id: TypeId, variant: &Variant) {
+        if !self.info(id).result {
+            return;
+        }
+        let location = self.opts.error_location.clone().unwrap();
+        let error_context = self.opts.error_context.as_deref();
+        let mut cases = Vec::new();
+        for case in variant.cases.iter() {
+            match case.ty {
+                Type::Id(payload)
+                    if has_error_location(iface, payload, Some(&location))
+                        && !is_error_context(iface, payload, error_context) =>
+                {
+                    cases.push((case, payload));
+                }
+                _ => {}
+            }
+        }
+        if cases.is_empty() {
+            return;
+        }
+        let name = self.result_name(iface, id);
+        let wit_name = iface.types[id].name.as_deref().unwrap();
+        self.src.push_str(&format!("impl {name} {{\n"));
+        for (case, payload) in cases {
+            let case_name = self.case_ident(wit_name, &case.name);
+            let record = match &iface.types[payload].kind {
+                TypeDefKind::Record(record) => record,
+                _ => unreachable!(),
+            };
+            self.src.push_str(&format!(
+                "/// Creates the `{}` error, recording where it is created.\n\
+                 #[track_caller]\n\
+                 pub fn {}(",
+                case.name,
+                to_rust_ident(&case_name.to_snake_case()),
+            ));
+            let mut args = Vec::new();
+            for field in record.fields.iter().filter(|f| f.name != location) {
+                let arg = to_rust_ident(&field.name);
+                self.src.push_str(&format!("{arg}: "));
+                self.print_ty(iface, &field.ty, TypeMode::Owned);
+                self.src.push_str(", ");
+                args.push(arg);
+            }
+            self.src.push_str(&format!(
+                ") -> Self {{\nSelf::{}({}::new({}))\n}}\n",
+                case_name,
+                self.result_name(iface, payload),
+                args.join(", "),
+            ));
+        }
+        self.src.push_str("}\n");
+    }
+
+    /// Generates the import of `func`, lowering its arguments for and lifting
+    /// its results from the call to `lowered`, if given, rather than `func`
+    /// itself or its `-interned` counterpart.
+    fn print_import(&mut self, iface: &Interface, func: &Function, lowered: Option<&Function>) {
+        let mut sig = FnSig::default();
+        let param_mode = TypeMode::AllBorrowed("'_");
+        match &func.kind {
+            FunctionKind::Freestanding => {}
+            FunctionKind::Static { resource, .. } | FunctionKind::Method { resource, .. } => {
+                sig.use_item_name = true;
+                self.src.push_str(&format!(
+                    "impl {} {{\n",
+                    iface.resources[*resource].name.to_camel_case()
+                ));
+            }
+        }
+        if let FunctionKind::Method { .. } = func.kind {
+            sig.self_arg = Some("&self".to_string());
+            sig.self_is_first_param = true;
+        }
+        if self.opts.native_fallback {
+            self.print_native_import(iface, func, param_mode, &sig);
+            self.src.push_str("#[cfg(target_arch = \"wasm32\")]\n");
+        }
+        if self.hot.contains(&func.name) {
+            self.src.push_str("#[inline]\n");
+        } else if self.cold.contains(&func.name) {
+            self.src.push_str("#[cold]\n");
+        }
+        if self.opts.debug_frames {
+            self.print_frame_marker(iface, func);
+            self.src.push_str("#[track_caller]\n");
+        }
+        let params = self.print_signature(iface, func, param_mode, &sig);
+        self.src.push_str("{\n");
+        let interned = interned_functions(iface, &self.opts.interned)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name)
+            .map(|(_, interned)| interned);
+        if interned.is_some() {
+            for ((_, ty), param) in func.params.iter().zip(params.iter()) {
+                if *ty == Type::String {
+                    self.src
+                        .push_str(&format!("let {param} = intern_string_id({param});\n"));
+                }
+            }
+        }
+        let memoized = self.memoize.contains(&func.name);
+        if memoized {
+            let codec = format!("{}::codec", self.crate_path());
+            let memo = Self::memo_name(func);
+            // Named so as not to shadow a parameter called `key`.
+            self.src.push_str("let mut __memo_key = Vec::new();\n");
+            for param in params.iter() {
+                self.src.push_str(&format!(
+                    "{codec}::Encode::encode_to(&{param}, &mut __memo_key);\n"
+                ));
+            }
+            self.src.push_str(&format!(
+                "
+                    if let Some(result) = {memo}.with(|memo| memo.borrow().get(&__memo_key).cloned()) {{
+                        return result;
+                    }}
+                "
+            ));
+            self.src.push_str("let result = ");
+        }
+        self.src.push_str("unsafe {\n");
+
+        let mut f = FunctionBindgen::new(self, params);
+        iface.call(
+            AbiVariant::GuestImport,
+            LiftLower::LowerArgsLiftResults,
+            lowered.or(interned.as_ref()).unwrap_or(func),
+            &mut f,
+        );
+        let FunctionBindgen {
+            needs_cleanup_list,
+            src,
+            ..
+        } = f;
+
+        if needs_cleanup_list {
+            let vec = self.alloc_item("vec::Vec");
+            self.src
+                .push_str(&format!("let mut cleanup_list = {vec}::new();\n"));
+        }
+        self.src.push_str(&String::from(src));
+
+        if memoized {
+            self.src.push_str("};\n");
+            self.src.push_str(&format!(
+                "{}.with(|memo| memo.borrow_mut().insert(__memo_key, result.clone()));\n",
+                Self::memo_name(func),
+            ));
+            self.src.push_str("result\n");
+        } else {
+            self.src.push_str("}\n");
+        }
+        self.src.push_str("}\n");
+
+        match &func.kind {
+            FunctionKind::Freestanding => {}
+            FunctionKind::Static { .. } | FunctionKind::Method { .. } => {
+                self.src.push_str("}\n");
+            }
+        }
+    }
+
+    /// Generates the import of the idempotent `func`, calling its
+    /// `{name}_with_key` counterpart with a new idempotency key.
+    fn print_idempotent_import(&mut self, iface: &Interface, func: &Function) {
+        let params =
+            self.print_signature(iface, func, TypeMode::AllBorrowed("'_"), &FnSig::default());
+        self.src.push_str(&format!(
+            "{{\n{}({}next_idempotency_key())\n}}\n",
+            self.func_ident(&format!("{}-with-key", func.name)),
+            params.iter().map(|p| format!("{p}, ")).collect::<String>(),
+        ));
+    }
+
+    /// Generates the function drawing the idempotency keys of calls to
+    /// idempotent imports.
+    fn print_idempotency_keys(&mut self, iface: &Interface) {
+        if idempotent_functions(iface).is_empty() {
+            return;
+        }
+        self.src.push_str(
+            "
+                /// Returns a new idempotency key, to call an idempotent import
+                /// through its `*_with_key` function with a key which retries of
+                /// the call can pass again.
+                ///
+                /// Keys are drawn from a counter, so they differ between the calls
+                /// to the imports of this interface.
+                pub fn next_idempotency_key() -> u64 {
+                    static NEXT: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+                    NEXT.fetch_add(1, core::sync::atomic::Ordering::Relaxed)
+                }
+            ",
+        );
+    }
+
+    /// Marks the start of the glue of `func`, for readers of the expanded
+    /// bindings and of the source lines debug info points them at.
+    /// Prints the import `func` for targets other than wasm32, which panics.
+    fn print_native_import(
+        &mut self,
+        iface: &Interface,
+        func: &Function,
+        param_mode: TypeMode,
+        sig: &FnSig,
+    ) {
+        self.src.push_str("#[cfg(not(target_arch = \"wasm32\"))]\n");
+        let params = self.print_signature(iface, func, param_mode, sig);
+        self.src.push_str("{\n");
+        let params = params.iter().filter(|p| *p != "self");
+        self.src.push_str(&format!(
+            "let _ = ({});\n",
+            params.map(|p| format!("{p}, ")).collect::<String>()
+        ));
+        self.src.push_str(&format!(
+            "{}::rt::native_import(\"{}\", \"{}\")\n",
+            self.crate_path(),
+            iface.name,
+            func.name,
+        ));
+        self.src.push_str("}\n");
+    }
+
+    fn print_frame_marker(&mut self, iface: &Interface, func: &Function) {
+        self.src
+            .push_str(&format!("// {}.{} (generated)\n", iface.name, func.name));
+    }
+
+    /// Prints the wasm export of `func`, which lifts its arguments, calls
+    /// the trait, or just returns its argument if `echo` is set, and lowers
+    /// the result.
+    fn print_export_glue(&mut self, iface: &Interface, func: &Function, echo: bool) {
+        let iface_name = iface.name.to_snake_case();
+
+        if self.opts.debug_frames {
+            self.print_frame_marker(iface, func);
+            self.src.push_str("#[inline(never)]\n");
+        }
+        self.src.push_str("#[export_name = \"");
+        match &iface.module {
+            Some(module) => {
+                self.src.push_str(module);
+                self.src.push_str("#");
+                self.src.push_str(&iface.mangle_funcname(func));
+            }
+            None => {
+                self.src.push_str(&self.opts.symbol_namespace);
+                self.src.push_str(&iface.mangle_funcname(func));
+            }
+        }
+        self.src.push_str("\"]\n");
+        self.src.push_str("unsafe extern \"C\" fn __wit_bindgen_");
+        self.src.push_str(&iface_name);
+        self.src.push_str("_");
+        self.src.push_str(&func.name.to_snake_case());
+        self.src.push_str("(");
+        let sig = iface.wasm_signature(AbiVariant::GuestExport, func);
+        let mut params = Vec::new();
+        for (i, param) in sig.params.iter().enumerate() {
+            let name = format!("arg{}", i);
+            self.src.push_str(&name);
+            self.src.push_str(": ");
+            self.wasm_type(*param);
+            self.src.push_str(", ");
+            params.push(name);
+        }
+        self.src.push_str(")");
+
+        match sig.results.len() {
+            0 => {}
+            1 => {
+                self.src.push_str(" -> ");
+                self.wasm_type(sig.results[0]);
+            }
+            _ => unimplemented!(),
+        }
+
+        self.push_str("{\n");
+
+        if self.opts.export_macro.is_some() {
+            // Force the macro code to reference wit_bindgen_guest_rust for standalone crates.
+            // Also ensure any referenced types are also used from the external crate.
+            self.src.push_str("#[allow(unused_imports)]\nuse ");
+            self.src.push_str(&self.crate_path());
+            self.src.push_str(";\nuse $crate");
+            if let Some(types_path) = &self.opts.types_path {
+                self.src.push_str("::");
+                self.src.push_str(types_path);
+            }
+            self.src.push_str("::*;\n");
+        }
+
+        if let Some(limit) = self.opts.call_depth_limit {
+            self.src.push_str(&format!(
+                "let _depth = {}::rt::CallDepthGuard::enter({});\n",
+                self.crate_path(),
+                limit,
+            ));
+        }
+
+        let mut f = FunctionBindgen::new(self, params);
+        f.echo = echo;
+        iface.call(
+            AbiVariant::GuestExport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+        );
+        let FunctionBindgen {
+            needs_cleanup_list,
+            src,
+            ..
+        } = f;
+        assert!(!needs_cleanup_list);
+        self.src.push_str(&String::from(src));
+        self.src.push_str("}\n");
+    }
+
+    /// Returns whether the named type `id` is defined elsewhere, either by
+    /// the user through [`Opts::with`] or as a shared type.
+    fn skips_definition(&mut self, iface: &Interface, id: TypeId) -> bool {
+        let name = iface.types[id].name.as_deref().unwrap();
+        self.mapped(name).is_some() || self.reuse_shared_type(iface, id)
+    }
+
+    /// With `share_types`, or for a type `use`d from another file,
+    /// re-exports the type `id` from an earlier interface declaring it
+    /// identically instead of defining it again, returning whether it did.
+    ///
+    /// Interfaces generated next to their export macro share a scope, in
+    /// which only the first of them defines a type they all declare.
+    fn reuse_shared_type(&mut self, iface: &Interface, id: TypeId) -> bool {
+        if self.opts.zeroize {
+            return false;
+        }
+        let unwrapped = self.opts.export_macro.is_some();
+        let info = self.info(id);
+        if info.has_handle {
+            return false;
+        }
+        let name = iface.types[id].name.clone().unwrap();
+        let names = match &iface.types[id].kind {
+            TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => {
+                vec![(self.type_ident(&name), TypeMode::Owned)]
+            }
+            // Types without data are printed the same in every mode.
+            _ => self
+                .modes_of(iface, id)
+                .into_iter()
+                .map(|(name, mode)| match info.owns_data() {
+                    true => (name, mode),
+                    false => (name, TypeMode::Owned),
+                })
+                .collect::<Vec<_>>(),
+        };
+        if names.is_empty() {
+            return false;
+        }
+
+        // The codec impls of encoded types can only be given once, and the
+        // types within a shared type must be the ones it was declared with.
+        let encodes = !self.encoded_types(iface).is_empty();
+        let within = named_types_within(iface, &Type::Id(id));
+        // A type used from another file is the same wherever it's used.
+        let shares = unwrapped || self.opts.share_types || iface.types[id].foreign_module.is_some();
+        if shares && !encodes && within.iter().all(|ty| self.reused_types.contains(ty)) {
+            let candidates = self.shared_types.get(&name).into_iter().flatten();
+            for (i, other, other_names) in candidates {
+                let other_iface = &self.shared_ifaces[*i];
+                if *other_names == names
+                    && same_type(other_iface, &Type::Id(*other), iface, &Type::Id(id))
+                {
+                    let other_unwrapped = self.unwrapped_ifaces.contains(i);
+                    let module = match (unwrapped, other_unwrapped) {
+                        (true, true) => None,
+                        (true, false) => Some(self.module_name(&other_iface.name)),
+                        (false, true) => Some("super".to_string()),
+                        (false, false) => {
+                            Some(format!("super::{}", self.module_name(&other_iface.name)))
+                        }
+                    };
+                    if let Some(module) = module {
+                        let items = names.iter().map(|(name, _)| name.as_str());
+                        self.src.push_str(&format!(
+                            "pub use {}::{{{}}};\n",
+                            module,
+                            items.collect::<Vec<_>>().join(", "),
+                        ));
+                    }
+                    self.reused_types.insert(id);
+                    return true;
+                }
+            }
+        }
+        if unwrapped {
+            let mut candidates = self.shared_types.get(&name).into_iter().flatten();
+            if let Some((i, ..)) = candidates.find(|(i, ..)| self.unwrapped_ifaces.contains(i)) {
+                panic!(
+                    "`{}` can't be shared by `{}` and `{}`, which are generated in the same module",
+                    name, self.shared_ifaces[*i].name, iface.name,
+                );
+            }
+        }
+        let current = self.shared_ifaces.len() - 1;
+        self.shared_types
+            .entry(name)
+            .or_default()
+            .push((current, id, names));
+        false
+    }
+
+    /// Asserts, within the export macro, that the trait it implements was
+    /// generated from the same version of `iface` as the macro, so that
+    /// mixing up versions fails to compile rather than producing a module
+    /// exporting the wrong functions.
+    fn print_hash_assertion(&mut self, iface: &Interface) {
+        if !self.traits.contains_key(&iface.name.to_camel_case()) {
+            return;
+        }
+        let crate_path = self.crate_path();
+        let types_path = match &self.opts.types_path {
+            Some(path) => format!("{path}::"),
+            None => String::new(),
+        };
+        self.src.push_str(&format!(
+            "const _: () = assert!(\n\
+                 {crate_path}::rt::str_eq($crate::{types_path}{}, \"{}\"),\n\
+                 \"the export macro of `{}` was generated from a different version of the interface than its trait\",\n\
+             );\n",
+            hash_const(iface),
+            interface_hash(iface),
+            iface.name,
+        ));
+    }
+
+    /// Lists the items of `iface` for the prelude to re-export: the trait
+    /// of an exported interface and those of its resources, the resources of
+    /// an imported one, and named types.
+    fn prelude_names(&self, iface: &Interface) -> Vec<String> {
+        let mut names = Vec::new();
+        let trait_name = iface.name.to_camel_case();
+        if let Some(trait_) = self.traits.get(&trait_name) {
+            names.push(trait_name);
+            names.extend(
+                trait_
+                    .resource_methods
+                    .keys()
+                    .map(|id| iface.resources[*id].name.to_camel_case()),
+            );
+        }
+        if self.in_import {
+            names.extend(iface.resources.iter().map(|(_, r)| r.name.to_camel_case()));
+        }
+        for (id, ty) in iface.types.iter() {
+            let name = match &ty.name {
+                Some(name) => name,
+                None => continue,
+            };
+            match &ty.kind {
+                TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => names.push(self.type_ident(name)),
+                _ => names.extend(self.modes_of(iface, id).into_iter().map(|(name, _)| name)),
+            }
+        }
+        names
+    }
+
+    /// Returns the name of the module the interface `iface` is generated in:
+    /// the one `module_names` maps it to, one re-exported at that path if it
+    /// is nested, or else one named after it.
+    fn module_name(&self, iface: &str) -> String {
+        match self.opts.module_path(iface) {
+            Some(path) if path.contains("::") => format!("__{}", path.replace("::", "_")),
+            Some(path) => path.to_string(),
+            None => iface.to_snake_case(),
+        }
+    }
+
+    /// Generates the modules at the nested paths `module_names` maps
+    /// interfaces to, each re-exporting the bindings of its interface, if
+    /// there are any.
+    fn print_module_paths(&self) -> Option<String> {
+        let wrapped = self
+            .shared_ifaces
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| !self.unwrapped_ifaces.contains(i))
+            .map(|(_, iface)| iface.name.as_str())
+            .collect::<Vec<_>>();
+        let mut paths = wrapped
+            .iter()
+            .filter_map(|iface| {
+                let path = self.opts.module_path(iface)?;
+                let segments = path.split("::").collect::<Vec<_>>();
+                (segments.len() > 1).then(|| (segments, self.module_name(iface)))
+            })
+            .collect::<Vec<_>>();
+        if paths.is_empty() {
+            return None;
+        }
+        paths.sort();
+        for iface in wrapped.iter() {
+            let module = self.module_name(iface);
+            if paths.iter().any(|(segments, _)| segments[0] == module) {
+                panic!(
+                    "the module of `{}` is also the parent of nested modules",
+                    iface
+                );
+            }
+        }
+
+        fn print(src: &mut String, paths: &[(Vec<&str>, String)], depth: usize, vis: &str) {
+            let mut rest = paths;
+            while let Some(((segments, _), _)) = rest.split_first() {
+                let segment = segments[depth];
+                let len = rest.iter().take_while(|(s, _)| s[depth] == segment).count();
+                let (children, next) = rest.split_at(len);
+                src.push_str(&format!("{} mod {} {{\n", vis, segment));
+                match children {
+                    [(segments, module)] if segments.len() == depth + 1 => {
+                        src.push_str(&format!(
+                            "#[allow(unused_imports)]\npub use {}{}::*;\n",
+                            "super::".repeat(depth + 1),
+                            module,
+                        ));
+                    }
+                    _ => print(src, children, depth + 1, "pub"),
+                }
+                src.push_str("}\n");
+                rest = next;
+            }
+        }
+        let mut src = String::new();
+        print(&mut src, &paths, 0, &self.opts.module_visibility);
+        Some(src)
+    }
+
+    fn print_prelude(&self) -> String {
+        let mut uses = HashMap::<&str, Vec<&str>>::new();
+        for (path, names) in self.prelude.iter() {
+            for name in names {
+                uses.entry(name).or_default().push(path);
+            }
+        }
+        let mut src = String::from(
+            "/// The items of the bindings, to be imported with `use prelude::*`.\n\
+             pub mod prelude {\n",
+        );
+        for (path, names) in self.prelude.iter() {
+            let names = names
+                .iter()
+                .filter(|name| uses[name.as_str()].len() == 1)
+                .filter(|name| !self.opts.prelude_exclude.contains(name))
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>();
+            if !names.is_empty() {
+                src.push_str(&format!("pub use {}::{{{}}};\n", path, names.join(", ")));
+            }
+        }
+        src.push_str("}\n");
+        src
+    }
+
+    /// Generates the function returning the id of a string passed to an
+    /// interned import, registering it with the host on first use.
+    fn print_interner(&mut self, iface: &Interface) {
+        if interned_functions(iface, &self.opts.interned).is_empty() {
+            return;
+        }
+        let intern = format!("{}::intern", self.crate_path());
+        let module = iface.module.as_deref().unwrap_or(&iface.name);
+        self.src.push_str(&format!(
+            "
+                fn intern_string_id(string: &str) -> u32 {{
+                    #[link(wasm_import_module = \"{module}\")]
+                    extern \"C\" {{
+                        #[cfg_attr(target_arch = \"wasm32\", link_name = \"intern_string\")]
+                        #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_intern_string\")]
+                        fn register(_: i32, _: i32) -> i32;
+                    }}
+                    thread_local! {{
+                        static INTERNED: {intern}::Interner = unsafe {{ {intern}::Interner::new(register) }};
+                    }}
+                    INTERNED.with(|interner| interner.intern(string))
+                }}
+            "
+        ));
+    }
+
+    fn memo_name(func: &Function) -> String {
+        format!("{}_MEMO", func.name.to_shouty_snake_case())
+    }
+
+    /// Generates the per-thread caches backing memoized imports along with a
+    /// function to clear them.
+    fn print_memo_caches(&mut self, iface: &Interface) {
+        let memoized = memoized_functions(iface, &self.memoize);
+        if memoized.is_empty() {
+            return;
+        }
+        self.src.push_str("thread_local! {\n");
+        for func in memoized.iter() {
+            self.src.push_str(&format!(
+                "static {}: core::cell::RefCell<std::collections::HashMap<Vec<u8>, ",
+                Self::memo_name(func),
+            ));
+            self.print_ty(iface, &func.result, TypeMode::Owned);
+            self.src
+                .push_str(">> = core::cell::RefCell::new(std::collections::HashMap::new());\n");
+        }
+        self.src.push_str("}\n");
+        self.src.push_str(
+            "
+                /// Forgets every memoized import result, so that the next call
+                /// to each import reaches the host again.
+                ///
+                /// This should be called whenever the host's answers may have
+                /// changed, typically at the end of each call into this module.
+                pub fn clear_memoized() {
+            ",
+        );
+        for func in memoized.iter() {
+            self.src.push_str(&format!(
+                "{}.with(|memo| memo.borrow_mut().clear());\n",
+                Self::memo_name(func),
+            ));
+        }
+        self.src.push_str("}\n");
+    }
 
-    /// Whether or not the bindings assume interface values are always
-    /// well-formed or whether checks are performed.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub unchecked: bool,
+    /// Generates a type which queues calls to the batched imports and sends
+    /// them to the host all at once.
+    fn print_batch(&mut self, iface: &Interface) {
+        let batched = batched_functions(iface, &self.batch);
+        if batched.is_empty() {
+            return;
+        }
+        let codec = format!("{}::codec", self.crate_path());
+        let module = iface.module.as_deref().unwrap_or(&iface.name);
+        let name = format!("{}Batch", iface.name.to_camel_case());
+        let vec = self.alloc_item("vec::Vec");
+        self.src.push_str(&format!(
+            "
+                /// Queues calls to imported functions so that they reach the
+                /// host together when [`{name}::flush`] is called.
+                ///
+                /// Calls are performed in the order they were queued. Any calls
+                /// still queued when the batch is dropped are flushed then.
+                #[derive(Default)]
+                pub struct {name} {{
+                    calls: {vec}<u8>,
+                }}
 
-    /// A prefix to prepend to all exported symbols. Note that this is only
-    /// intended for testing because it breaks the general form of the ABI.
-    #[cfg_attr(feature = "structopt", structopt(skip))]
-    pub symbol_namespace: String,
+                impl {name} {{
+                    pub fn new() -> Self {{
+                        Self::default()
+                    }}
 
-    /// If set, the code generation is intended for standalone crates.
-    ///
-    /// Standalone mode generates bindings without a wrapping module.
-    ///
-    /// For exported interfaces, an export macro is also generated
-    /// that can be used to export an implementation from a different
-    /// crate.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub export_macro: Option<String>,
+                    /// Returns whether no calls are queued.
+                    pub fn is_empty(&self) -> bool {{
+                        self.calls.is_empty()
+                    }}
 
-    /// Path inside the standalone crate where the generated types can be found.
-    ///
-    /// Used inside the export macro and is prefixed with `$crate::`.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub types_path: Option<String>,
+                    /// Performs every queued call with a single call into the
+                    /// host.
+                    pub fn flush(&mut self) {{
+                        if self.calls.is_empty() {{
+                            return;
+                        }}
+                        #[link(wasm_import_module = \"{module}\")]
+                        extern \"C\" {{
+                            #[cfg_attr(target_arch = \"wasm32\", link_name = \"flush_batch\")]
+                            #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_flush_batch\")]
+                            fn wit_import(_: i32, _: i32);
+                        }}
+                        unsafe {{
+                            wit_import(self.calls.as_ptr() as i32, self.calls.len() as i32);
+                        }}
+                        self.calls.clear();
+                    }}
+            ",
+        ));
+        for (index, func) in batched {
+            self.src.push_str(&format!(
+                "/// Queues a call to [`{}`].\n",
+                self.func_ident(&func.name),
+            ));
+            self.src
+                .push_str(&format!("pub fn {}(&mut self", self.func_ident(&func.name),));
+            for (param, ty) in func.params.iter() {
+                self.src.push_str(&format!(", {}: ", to_rust_ident(param)));
+                self.print_ty(iface, ty, TypeMode::AllBorrowed("'_"));
+            }
+            self.src.push_str(") {\n");
+            self.src.push_str(&format!(
+                "{codec}::Encode::encode_to(&{index}u32, &mut self.calls);\n"
+            ));
+            for (param, _) in func.params.iter() {
+                self.src.push_str(&format!(
+                    "{codec}::Encode::encode_to(&{}, &mut self.calls);\n",
+                    to_rust_ident(param),
+                ));
+            }
+            self.src.push_str("}\n");
+        }
+        self.src.push_str("}\n");
+        self.src.push_str(&format!(
+            "
+                impl Drop for {name} {{
+                    fn drop(&mut self) {{
+                        self.flush();
+                    }}
+                }}
+            "
+        ));
+    }
 
-    /// Path to access this crate from inside the generated macro if it has been re-exported.
-    ///
-    /// This is only relevant if the [`export_macro`] is set, and is prefixed with `$crate::`. If
-    /// it is not set, the generated code will use the crate assuming it has been declared as a
-    /// dependency by the crate that uses the macro.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub reexported_crate_path: Option<String>,
-}
+    /// Generates an `emit_*` function for each event, which encodes the
+    /// payload and hands it to the host along with the event's topic.
+    fn print_event_emitters(&mut self, iface: &Interface) {
+        let codec = format!("{}::codec", self.crate_path());
+        let module = iface.module.as_deref().unwrap_or(&iface.name);
+        for event in iface.events.iter() {
+            let name = event.name.to_snake_case();
+            let topic = format!("{}_TOPIC", event.name.to_shouty_snake_case());
+            self.src.push_str(&format!(
+                "/// The topic under which `{}` events are emitted.\n",
+                event.name,
+            ));
+            self.src.push_str(&format!(
+                "pub const {topic}: u64 = {:#x};\n",
+                iface.event_topic(event),
+            ));
+            self.rustdoc(&event.docs);
+            self.src.push_str(&format!("pub fn emit_{name}("));
+            if event.payload == Type::Unit {
+                self.src.push_str(") {\n");
+                self.src.push_str("let payload = &();\n");
+            } else {
+                self.src.push_str("payload: &");
+                self.print_ty(iface, &event.payload, TypeMode::Owned);
+                self.src.push_str(") {\n");
+            }
+            self.src.push_str(&format!(
+                "
+                    let payload = {codec}::Encode::encode(payload);
+                    #[link(wasm_import_module = \"{module}\")]
+                    extern \"C\" {{
+                        #[cfg_attr(target_arch = \"wasm32\", link_name = \"emit_event\")]
+                        #[cfg_attr(not(target_arch = \"wasm32\"), link_name = \"{module}_emit_event\")]
+                        fn wit_import(_: i64, _: i32, _: i32);
+                    }}
+                    unsafe {{
+                        wit_import({topic} as i64, payload.as_ptr() as i32, payload.len() as i32);
+                    }}
+                }}
+                "
+            ));
+        }
+    }
 
-#[derive(Default)]
-struct Trait {
-    methods: Vec<String>,
-    resource_methods: BTreeMap<ResourceId, Vec<String>>,
-}
+    /// Generates the type-state `protocol::Session` through which the imports
+    /// annotated with `@transition` can only be called in the order their
+    /// states allow, as a compile-time check.
+    fn print_protocol(&mut self, iface: &Interface) {
+        let transitions = protocol_transitions(iface);
+        let initial = match transitions.first() {
+            Some(transition) => transition.from.to_camel_case(),
+            None => return,
+        };
+        let mut states = Vec::new();
+        for transition in transitions.iter() {
+            for state in [&transition.from, &transition.to] {
+                let state = state.to_camel_case();
+                if !states.contains(&state) {
+                    states.push(state);
+                }
+            }
+        }
+        // The session's methods name the interface's types through a glob
+        // import, which the session and its states would shadow.
+        for (_, ty) in iface.types.iter() {
+            if let Some(name) = &ty.name {
+                let name = name.to_camel_case();
+                if name == "Session" || states.contains(&name) {
+                    panic!("type `{}` is shadowed by the protocol's session", name);
+                }
+            }
+        }
 
-impl Opts {
-    pub fn build(self) -> RustWasm {
-        let mut r = RustWasm::new();
-        r.opts = self;
-        r
+        self.src.push_str(&format!(
+            "
+                /// Wrappers of the imports annotated with `@transition`, which
+                /// can only be called in the state of the protocol they go
+                /// from.
+                pub mod protocol {{
+                    #[allow(unused_imports)]
+                    use super::*;
+
+                    /// A session of the protocol, in state `S`.
+                    ///
+                    /// The protocol starts in state [`{initial}`], through
+                    /// [`Session::new`].
+                    pub struct Session<S>(core::marker::PhantomData<S>);
+
+                    impl Session<{initial}> {{
+                        pub fn new() -> Self {{
+                            Session(core::marker::PhantomData)
+                        }}
+                    }}
+            "
+        ));
+        for state in states.iter() {
+            self.src.push_str(&format!(
+                "/// A state of the protocol.
+pub enum {state} {{}}
+"
+            ));
+        }
+        for state in states.iter() {
+            let from = transitions
+                .iter()
+                .filter(|t| t.from.to_camel_case() == *state)
+                .collect::<Vec<_>>();
+            if from.is_empty() {
+                continue;
+            }
+            self.src.push_str(&format!(
+                "impl Session<{state}> {{
+"
+            ));
+            for transition in from {
+                let func = transition.func;
+                let to = transition.to.to_camel_case();
+                let stays = to == *state;
+                let sig = FnSig {
+                    self_arg: Some(if stays { "&mut self" } else { "self" }.to_string()),
+                    ..FnSig::default()
+                };
+                let params =
+                    self.print_docs_and_params(iface, func, TypeMode::AllBorrowed("'_"), &sig);
+                let call = format!(
+                    "super::{}({})",
+                    self.opts.function_ident(&func.name),
+                    params.join(", "),
+                );
+                match (stays, func.result == Type::Unit) {
+                    (true, _) => {
+                        self.src.push_str(" -> ");
+                        self.print_ty(iface, &func.result, TypeMode::Owned);
+                        self.src.push_str(&format!(
+                            " {{
+{call}
+}}
+"
+                        ));
+                    }
+                    (false, true) => {
+                        self.src.push_str(&format!(
+                            " -> Session<{to}> {{
+{call};
+Session(core::marker::PhantomData)
+}}
+"
+                        ));
+                    }
+                    (false, false) => {
+                        self.src.push_str(&format!(" -> (Session<{to}>, "));
+                        self.print_ty(iface, &func.result, TypeMode::Owned);
+                        self.src.push_str(&format!(
+                            ") {{
+let result = {call};
+(Session(core::marker::PhantomData), result)
+}}
+"
+                        ));
+                    }
+                }
+            }
+            self.src.push_str(
+                "}
+",
+            );
+        }
+        self.src.push_str(
+            "}
+",
+        );
     }
-}
 
-impl RustWasm {
-    pub fn new() -> RustWasm {
-        RustWasm::default()
-    }
+    /// Generates the handler trait and dispatcher for the message envelope
+    /// `id`.
+    fn print_message_envelope(&mut self, iface: &Interface, id: TypeId) {
+        let codec = format!("{}::codec", self.crate_path());
+        let name = self.result_name(iface, id);
+        let wit_name = iface.types[id].name.as_deref().unwrap();
+        let handler = format!("{}Handler", name);
+        let variant = match &iface.types[id].kind {
+            TypeDefKind::Variant(v) => v,
+            _ => unreachable!(),
+        };
 
-    fn abi_variant(dir: Direction) -> AbiVariant {
-        // This generator uses the obvious direction to ABI variant mapping.
-        match dir {
-            Direction::Export => AbiVariant::GuestExport,
-            Direction::Import => AbiVariant::GuestImport,
+        self.src.push_str(&format!(
+            "/// Receives each kind of [`{name}`] after it has been decoded.\n"
+        ));
+        self.src.push_str(&format!("pub trait {handler} {{\n"));
+        for case in variant.cases.iter() {
+            self.rustdoc(&case.docs);
+            self.src.push_str(&format!(
+                "fn {}(&mut self",
+                to_rust_ident(&format!("handle_{}", case.name.to_snake_case())),
+            ));
+            if case.ty != Type::Unit {
+                self.src.push_str(", message: ");
+                self.print_ty(iface, &case.ty, TypeMode::Owned);
+            }
+            self.src.push_str(");\n");
         }
-    }
+        self.src.push_str("}\n");
 
-    fn ret_area_type_name(iface: &Interface) -> String {
-        format!("__{}RetArea", iface.name.to_camel_case())
-    }
+        self.src.push_str(&format!("impl {name} {{\n"));
+        let vec = self.alloc_item("vec::Vec");
+        self.src.push_str(&format!(
+            "
+                /// Encodes this message into bytes suitable for sending to
+                /// another chain.
+                pub fn encode(&self) -> {vec}<u8> {{
+                    {codec}::Encode::encode(self)
+                }}
 
-    fn ret_area_name(iface: &Interface) -> String {
-        format!("__{}_RET_AREA", iface.name.to_shouty_snake_case())
+                /// Decodes a message previously produced by [`{name}::encode`].
+                pub fn decode(bytes: &[u8]) -> Result<Self, {codec}::DecodeError> {{
+                    {codec}::Decode::decode(bytes)
+                }}
+
+                /// Decodes `bytes` and passes the message to `handler`.
+                pub fn dispatch_bytes(
+                    bytes: &[u8],
+                    handler: &mut impl {handler},
+                ) -> Result<(), {codec}::DecodeError> {{
+                    Self::decode(bytes)?.dispatch(handler);
+                    Ok(())
+                }}
+
+                /// Passes this message to the `handler` method for its case.
+                pub fn dispatch(self, handler: &mut impl {handler}) {{
+                    match self {{
+            ",
+        ));
+        for case in variant.cases.iter() {
+            let method = to_rust_ident(&format!("handle_{}", case.name.to_snake_case()));
+            let case_name = self.case_ident(wit_name, &case.name);
+            if case.ty == Type::Unit {
+                self.src
+                    .push_str(&format!("{name}::{case_name} => handler.{method}(),\n"));
+            } else {
+                self.src
+                    .push_str(&format!("{name}::{case_name}(e) => handler.{method}(e),\n"));
+            }
+        }
+        self.src.push_str("}\n");
+        self.src.push_str("}\n");
+        self.src.push_str("}\n");
     }
 
-    fn crate_path(&self) -> String {
-        if self.in_macro {
-            self.opts
-                .reexported_crate_path
-                .as_ref()
-                .map(|path| format!("$crate::{path}"))
-                .unwrap_or_else(|| "wit_bindgen_guest_rust".to_owned())
+    /// Generates a client for the service whose queries are the cases of
+    /// `query`, pairing each case with the case of `response` that has the
+    /// same name.
+    fn print_query_client(&mut self, iface: &Interface, query: TypeId, response: TypeId) {
+        let codec = format!("{}::codec", self.crate_path());
+        let query_name = self.result_name(iface, query);
+        let response_name = self.result_name(iface, response);
+        let query_wit = iface.types[query].name.as_deref().unwrap();
+        let response_wit = iface.types[response].name.as_deref().unwrap();
+        let client = format!("{}Client", query_name);
+        let transport = format!("{}Transport", query_name);
+        let error = format!("{}ClientError", query_name);
+        let (queries, responses) = match (&iface.types[query].kind, &iface.types[response].kind) {
+            (TypeDefKind::Variant(q), TypeDefKind::Variant(r)) => (q, r),
+            _ => unreachable!(),
+        };
+
+        let vec = self.alloc_item("vec::Vec");
+        // `no_std` has no `Error` trait to implement.
+        let error_impl = if self.no_std() {
+            String::new()
         } else {
-            "wit_bindgen_guest_rust".to_owned()
+            format!(
+                "
+                    impl<E: std::error::Error + 'static> std::error::Error for {error}<E> {{
+                        fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {{
+                            match self {{
+                                {error}::Transport(e) => Some(e),
+                                {error}::Decode(e) => Some(e),
+                                {error}::Mismatch {{ .. }} => None,
+                            }}
+                        }}
+                    }}
+                "
+            )
+        };
+        self.src.push_str(&format!(
+            "
+                /// Sends encoded [`{query_name}`]s to a service and returns its
+                /// encoded [`{response_name}`]s.
+                pub trait {transport} {{
+                    type Error;
+
+                    fn query(&mut self, query: &[u8]) -> Result<{vec}<u8>, Self::Error>;
+                }}
+
+                /// Errors returned by [`{client}`].
+                #[derive(Debug)]
+                pub enum {error}<E> {{
+                    /// The transport failed to deliver the query.
+                    Transport(E),
+                    /// The response could not be decoded.
+                    Decode({codec}::DecodeError),
+                    /// The service answered with a response for a different
+                    /// query.
+                    Mismatch {{
+                        expected: &'static str,
+                        found: {response_name},
+                    }},
+                }}
+
+                impl<E: core::fmt::Display> core::fmt::Display for {error}<E> {{
+                    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {{
+                        match self {{
+                            {error}::Transport(e) => write!(f, \"transport error: {{}}\", e),
+                            {error}::Decode(e) => write!(f, \"invalid response: {{}}\", e),
+                            {error}::Mismatch {{ expected, found }} => {{
+                                write!(f, \"expected response to `{{}}`, found {{:?}}\", expected, found)
+                            }}
+                        }}
+                    }}
+                }}
+
+                {error_impl}
+
+                /// A typed client with one method per [`{query_name}`] case.
+                pub struct {client}<T> {{
+                    pub transport: T,
+                }}
+
+                impl<T: {transport}> {client}<T> {{
+                    pub fn new(transport: T) -> Self {{
+                        {client} {{ transport }}
+                    }}
+
+                    fn send(&mut self, query: &{query_name}) -> Result<{response_name}, {error}<T::Error>> {{
+                        let bytes = {codec}::Encode::encode(query);
+                        let bytes = self.transport.query(&bytes).map_err({error}::Transport)?;
+                        {codec}::Decode::decode(&bytes).map_err({error}::Decode)
+                    }}
+            ",
+        ));
+
+        for case in queries.cases.iter() {
+            let matching = responses
+                .cases
+                .iter()
+                .find(|r| r.name == case.name)
+                .unwrap_or_else(|| {
+                    panic!(
+                        "query `{}` has no response case with the same name",
+                        case.name
+                    )
+                });
+            let method = to_rust_ident(&case.name.to_snake_case());
+            let case_name = self.case_ident(query_wit, &case.name);
+            let response_case = self.case_ident(response_wit, &matching.name);
+            self.rustdoc(&case.docs);
+            self.src.push_str(&format!("pub fn {method}(&mut self"));
+            if case.ty != Type::Unit {
+                self.src.push_str(", query: ");
+                self.print_ty(iface, &case.ty, TypeMode::Owned);
+            }
+            self.src.push_str(") -> Result<");
+            self.print_ty(iface, &matching.ty, TypeMode::Owned);
+            self.src.push_str(&format!(", {error}<T::Error>> {{\n"));
+            if case.ty == Type::Unit {
+                self.src.push_str(&format!(
+                    "match self.send(&{query_name}::{case_name})? {{\n"
+                ));
+            } else {
+                self.src.push_str(&format!(
+                    "match self.send(&{query_name}::{case_name}(query))? {{\n"
+                ));
+            }
+            if matching.ty == Type::Unit {
+                self.src
+                    .push_str(&format!("{response_name}::{response_case} => Ok(()),\n"));
+            } else {
+                self.src
+                    .push_str(&format!("{response_name}::{response_case}(e) => Ok(e),\n"));
+            }
+            if responses.cases.len() > 1 {
+                self.src.push_str(&format!(
+                    "found => Err({error}::Mismatch {{ expected: \"{}\", found }}),\n",
+                    case.name,
+                ));
+            }
+            self.src.push_str("}\n");
+            self.src.push_str("}\n");
         }
+        self.src.push_str("}\n");
     }
 }
 
 impl RustGenerator for RustWasm {
+    fn renamed(&self, wit_name: &str) -> Option<&str> {
+        self.opts.renamed(wit_name)
+    }
+
+    fn mapped(&self, wit_name: &str) -> Option<&str> {
+        self.opts
+            .with
+            .iter()
+            .find(|(from, _)| from == wit_name)
+            .map(|(_, to)| to.as_str())
+    }
+
     fn default_param_mode(&self) -> TypeMode {
         if self.in_import {
             // We default to borrowing as much as possible to maximize the ability
@@ -145,6 +2937,49 @@ impl RustGenerator for RustWasm {
         Some(*resource) == self.in_resource
     }
 
+    fn derive_ord(&self) -> bool {
+        self.opts.derive_ord
+    }
+
+    fn zeroize(&self) -> bool {
+        self.opts.zeroize
+    }
+
+    fn ct_eq(&self) -> bool {
+        self.opts.ct_eq
+    }
+
+    fn additional_derives(&self) -> &[String] {
+        &self.opts.additional_derives
+    }
+
+    fn no_std(&self) -> bool {
+        // Every interface's bindings end up in the same crate.
+        self.global_opts.as_ref().unwrap_or(&self.opts).no_std
+    }
+
+    fn lowered_sizes(&self) -> Option<&SizeAlign> {
+        self.opts.lowered_size.then_some(&self.sizes)
+    }
+
+    fn lowers_owned(&self, info: &TypeInfo) -> bool {
+        // Exports return owned values, which are taken apart to be lowered,
+        // as are the owned arguments of imports.
+        if self.in_import {
+            info.param && !self.borrows_named_type(info)
+        } else {
+            info.result
+        }
+    }
+
+    fn borrows_named_type(&self, info: &TypeInfo) -> bool {
+        match self.opts.ownership {
+            Some(Ownership::Owning) => false,
+            Some(Ownership::Borrowing) => !info.result,
+            Some(Ownership::BorrowingDuplicateIfNecessary) | None => true,
+        }
+    }
+
     fn handle_wrapper(&self) -> Option<String> {
         if self.in_import {
             None
@@ -186,19 +3021,91 @@ impl RustGenerator for RustWasm {
 }
 
 impl Generator for RustWasm {
+    fn generate_all(&mut self, imports: &[Interface], exports: &[Interface], files: &mut Files) {
+        // Skipped functions are removed from the interfaces up front, so
+        // that nothing generated for an interface refers to them.
+        let without_skipped = |iface: &Interface| {
+            let mut iface = iface.clone();
+            let name = iface.name.clone();
+            iface.functions.retain(|f| !self.opts.skips(&name, &f.name));
+            iface
+        };
+        let imports = imports.iter().map(without_skipped).collect::<Vec<_>>();
+        let exports = exports.iter().map(without_skipped).collect::<Vec<_>>();
+
+        self.preprocess_all(&imports, &exports);
+        for imp in &imports {
+            self.generate_one(imp, Direction::Import, files);
+        }
+        for exp in &exports {
+            self.generate_one(exp, Direction::Export, files);
+        }
+        self.finish_all(files);
+    }
+
     fn preprocess_one(&mut self, iface: &Interface, dir: Direction) {
+        let overridden = self.opts.overrides.iter().find(|(n, _)| *n == iface.name);
+        let export_macro = match dir {
+            Direction::Export => self
+                .opts
+                .export_macros
+                .iter()
+                .find(|(n, _)| *n == iface.name),
+            Direction::Import => None,
+        };
+        if overridden.is_some() || export_macro.is_some() {
+            let mut opts = overridden.map_or_else(|| self.opts.clone(), |(_, o)| o.clone());
+            if let Some((_, name)) = export_macro {
+                opts.export_macro = Some(name.clone());
+            }
+            self.global_opts = Some(mem::replace(&mut self.opts, opts));
+        }
         let variant = Self::abi_variant(dir);
         self.in_import = variant == AbiVariant::GuestImport;
+        self.apply_profile(iface);
         self.types.analyze(iface);
+        if let Err(e) = self.check_encoded_types(iface) {
+            panic!("{}", e);
+        }
+        // Messages, queries and events are always encoded from and decoded into owned
+        // values, even if their types are never mentioned by a function.
+        for ty in self.encoded_types(iface) {
+            self.types.set_param_result_ty(iface, &ty, false, true);
+        }
         self.trait_name = iface.name.to_camel_case();
+        self.shared_ifaces.push(iface.clone());
+        if self.opts.export_macro.is_some() {
+            self.unwrapped_ifaces.insert(self.shared_ifaces.len() - 1);
+        }
+        self.reused_types.clear();
+        if self.opts.no_alloc {
+            if let Err(e) = self.check_no_alloc(iface) {
+                panic!("{}", e);
+            }
+        }
 
         if self.opts.export_macro.is_none() {
             self.src.push_str(&format!(
-                "#[allow(clippy::all)]\nmod {} {{\n",
-                iface.name.to_snake_case(),
+                "#[allow(clippy::all)]\n{} mod {} {{\n",
+                self.opts.module_visibility,
+                self.module_name(&iface.name),
             ));
         }
 
+        if let Some(version) = self.opts.abi_version {
+            if let Err(e) = abi::check_abi_version(version) {
+                panic!("{}", e);
+            }
+            if version == 1 {
+                // Fail to compile unless the runtime exports the allocator
+                // under the name hosts built against this ABI look for.
+                self.src.push_str(&format!(
+                    "#[allow(unused_imports)]\nuse {}::rt::canonical_abi_realloc as _;\n",
+                    self.crate_path(),
+                ));
+            }
+        }
+
         self.sizes.fill(iface);
     }
 
@@ -210,7 +3117,15 @@ impl Generator for RustWasm {
         record: &Record,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_record(iface, id, record, docs);
+        if is_error_context(iface, id, self.opts.error_context.as_deref()) {
+            self.print_error_context(iface, id, record);
+        } else if has_error_location(iface, id, self.opts.error_location.as_deref()) {
+            self.print_located_new(iface, id, record);
+        }
     }
 
     fn type_tuple(
@@ -221,23 +3136,32 @@ impl Generator for RustWasm {
         tuple: &Tuple,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_tuple(iface, id, tuple, docs);
     }
 
     fn type_flags(
         &mut self,
-        _iface: &Interface,
-        _id: TypeId,
+        iface: &Interface,
+        id: TypeId,
         name: &str,
         flags: &Flags,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.src.push_str(&self.crate_path());
         self.src.push_str("::bitflags::bitflags! {\n");
         self.rustdoc(docs);
+        self.print_additional_derives();
         let repr = RustFlagsRepr::new(flags);
-        self.src
-            .push_str(&format!("pub struct {}: {repr} {{\n", name.to_camel_case(),));
+        self.src.push_str(&format!(
+            "pub struct {}: {repr} {{\n",
+            self.type_ident(name)
+        ));
         for (i, flag) in flags.flags.iter().enumerate() {
             self.rustdoc(&flag.docs);
             self.src.push_str(&format!(
@@ -251,7 +3175,7 @@ impl Generator for RustWasm {
 
         // Add a `from_bits_preserve` method.
         self.src
-            .push_str(&format!("impl {} {{\n", name.to_camel_case()));
+            .push_str(&format!("impl {} {{\n", self.type_ident(name)));
         self.src.push_str(&format!(
             "    /// Convert from a raw integer, preserving any unknown bits. See\n"
         ));
@@ -264,6 +3188,9 @@ impl Generator for RustWasm {
         self.src.push_str(&format!("        Self {{ bits }}\n"));
         self.src.push_str(&format!("    }}\n"));
         self.src.push_str(&format!("}}\n"));
+
+        let name = self.type_ident(name);
+        self.print_lowered_size(id, &name, None, None);
     }
 
     fn type_variant(
@@ -274,7 +3201,13 @@ impl Generator for RustWasm {
         variant: &Variant,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_variant(iface, id, variant, docs);
+        if self.opts.error_location.is_some() {
+            self.print_located_cases(iface, id, variant);
+        }
     }
 
     fn type_union(
@@ -285,6 +3218,9 @@ impl Generator for RustWasm {
         union: &Union,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_union(iface, id, union, docs);
     }
 
@@ -296,6 +3232,9 @@ impl Generator for RustWasm {
         payload: &Type,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_option(iface, id, payload, docs);
     }
 
@@ -307,21 +3246,32 @@ impl Generator for RustWasm {
         result: &Result_,
         docs: &Docs,
     ) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_result(iface, id, result, docs);
     }
 
-    fn type_enum(&mut self, _iface: &Interface, id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+    fn type_enum(&mut self, iface: &Interface, id: TypeId, name: &str, enum_: &Enum, docs: &Docs) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_enum(id, name, enum_, docs);
     }
 
     fn preprocess_resources(&mut self, _: &Interface, dir: Direction) {
         if dir == Direction::Export {
             if let Some(export_macro) = &self.opts.export_macro {
-                self.src.push_str(&format!(
-                    "/// Declares the export of the interface for the given type.\n\
-                     #[macro_export]\n\
-                     macro_rules! {export_macro}(($t:ty) => {{\n",
-                ));
+                if let Some(module) = &self.opts.export_macro_module {
+                    self.src.push_str(&format!("pub mod {module} {{\n"));
+                }
+                self.src
+                    .push_str("/// Declares the export of the interface for the given type.\n");
+                if !self.opts.no_macro_export {
+                    self.src.push_str("#[macro_export]\n");
+                }
+                self.src
+                    .push_str(&format!("macro_rules! {export_macro}(($t:ty) => {{\n"));
                 self.in_macro = true;
             }
         }
@@ -357,7 +3307,7 @@ impl Generator for RustWasm {
             } else {
                 (
                     format!("super::{}", resource_trait),
-                    format!("<super::{iface_name} as {iface_name}>"),
+                    format!("<{} as {iface_name}>", self.export_type(&iface.name)),
                 )
             };
             let crate_path = self.crate_path();
@@ -421,7 +3371,7 @@ impl Generator for RustWasm {
 
                     const _: () = {{
                         #[export_name = \"{ns}canonical_abi_drop_{name}\"]
-                        extern \"C\" fn drop(ty: Box<{resource_impl}>) {{
+                        extern \"C\" fn drop(ty: {boxed}<{resource_impl}>) {{
                             {iface_impl}::drop_{name_snake}(*ty)
                         }}
                     }};
@@ -430,6 +3380,7 @@ impl Generator for RustWasm {
                 name_snake = iface.resources[ty].name.to_snake_case(),
                 ns = self.opts.symbol_namespace,
                 panic_not_wasm = panic,
+                boxed = self.alloc_item("boxed::Box"),
             ));
             let trait_ = self.traits.entry(iface_name).or_insert(Trait::default());
             trait_.methods.push(format!(
@@ -512,144 +3463,64 @@ impl Generator for RustWasm {
     }
 
     fn type_alias(&mut self, iface: &Interface, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_typedef_alias(iface, id, ty, docs);
     }
 
     fn type_list(&mut self, iface: &Interface, id: TypeId, _name: &str, ty: &Type, docs: &Docs) {
+        if self.skips_definition(iface, id) {
+            return;
+        }
         self.print_type_list(iface, id, ty, docs);
     }
 
     fn type_builtin(&mut self, iface: &Interface, _id: TypeId, name: &str, ty: &Type, docs: &Docs) {
         self.rustdoc(docs);
         self.src
-            .push_str(&format!("pub type {}", name.to_camel_case()));
+            .push_str(&format!("pub type {}", self.type_ident(name)));
         self.src.push_str(" = ");
         self.print_ty(iface, ty, TypeMode::Owned);
         self.src.push_str(";\n");
     }
 
     fn import(&mut self, iface: &Interface, func: &Function) {
-        let mut sig = FnSig::default();
-        let param_mode = TypeMode::AllBorrowed("'_");
-        match &func.kind {
-            FunctionKind::Freestanding => {}
-            FunctionKind::Static { resource, .. } | FunctionKind::Method { resource, .. } => {
-                sig.use_item_name = true;
-                self.src.push_str(&format!(
-                    "impl {} {{\n",
-                    iface.resources[*resource].name.to_camel_case()
-                ));
+        let keyed = idempotent_functions(iface)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name)
+            .map(|(_, keyed)| keyed);
+        if let Some(keyed) = keyed {
+            if self.opts.interned.contains(&func.name) {
+                panic!("idempotent function `{}` can't also be interned", func.name);
             }
+            // Calls made through `{name}_with_key` pass the key they're
+            // given, so that they can be retried, and the others a new one.
+            self.print_idempotent_import(iface, func);
+            let mut with_key = keyed.clone();
+            with_key.name = format!("{}-with-key", func.name);
+            with_key.docs.contents = Some(format!(
+                "Like [`{}`], but making the call with `idempotency_key`, such as one \
+                 from [`next_idempotency_key`], which a retry of the call passes again.",
+                self.func_ident(&func.name),
+            ));
+            self.print_import(iface, &with_key, Some(&keyed));
+        } else {
+            self.print_import(iface, func, None);
         }
-        if let FunctionKind::Method { .. } = func.kind {
-            sig.self_arg = Some("&self".to_string());
-            sig.self_is_first_param = true;
-        }
-        let params = self.print_signature(iface, func, param_mode, &sig);
-        self.src.push_str("{\n");
-        self.src.push_str("unsafe {\n");
-
-        let mut f = FunctionBindgen::new(self, params);
-        iface.call(
-            AbiVariant::GuestImport,
-            LiftLower::LowerArgsLiftResults,
-            func,
-            &mut f,
-        );
-        let FunctionBindgen {
-            needs_cleanup_list,
-            src,
-            ..
-        } = f;
-
-        if needs_cleanup_list {
-            self.src.push_str("let mut cleanup_list = Vec::new();\n");
-        }
-        self.src.push_str(&String::from(src));
-
-        self.src.push_str("}\n");
-        self.src.push_str("}\n");
 
-        match &func.kind {
-            FunctionKind::Freestanding => {}
-            FunctionKind::Static { .. } | FunctionKind::Method { .. } => {
-                self.src.push_str("}\n");
-            }
+        // Spliced functions are also imported under a name of their own,
+        // exchanging byte lists as tokens.
+        let spliced = spliced_functions(iface)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name);
+        if let Some((_, spliced)) = spliced {
+            self.import(iface, &spliced);
         }
     }
 
     fn export(&mut self, iface: &Interface, func: &Function) {
-        let iface_name = iface.name.to_snake_case();
-
-        self.src.push_str("#[export_name = \"");
-        match &iface.module {
-            Some(module) => {
-                self.src.push_str(module);
-                self.src.push_str("#");
-                self.src.push_str(&iface.mangle_funcname(func));
-            }
-            None => {
-                self.src.push_str(&self.opts.symbol_namespace);
-                self.src.push_str(&iface.mangle_funcname(func));
-            }
-        }
-        self.src.push_str("\"]\n");
-        self.src.push_str("unsafe extern \"C\" fn __wit_bindgen_");
-        self.src.push_str(&iface_name);
-        self.src.push_str("_");
-        self.src.push_str(&func.name.to_snake_case());
-        self.src.push_str("(");
-        let sig = iface.wasm_signature(AbiVariant::GuestExport, func);
-        let mut params = Vec::new();
-        for (i, param) in sig.params.iter().enumerate() {
-            let name = format!("arg{}", i);
-            self.src.push_str(&name);
-            self.src.push_str(": ");
-            self.wasm_type(*param);
-            self.src.push_str(", ");
-            params.push(name);
-        }
-        self.src.push_str(")");
-
-        match sig.results.len() {
-            0 => {}
-            1 => {
-                self.src.push_str(" -> ");
-                self.wasm_type(sig.results[0]);
-            }
-            _ => unimplemented!(),
-        }
-
-        self.push_str("{\n");
-
-        if self.opts.export_macro.is_some() {
-            // Force the macro code to reference wit_bindgen_guest_rust for standalone crates.
-            // Also ensure any referenced types are also used from the external crate.
-            self.src.push_str("#[allow(unused_imports)]\nuse ");
-            self.src.push_str(&self.crate_path());
-            self.src.push_str(";\nuse $crate");
-            if let Some(types_path) = &self.opts.types_path {
-                self.src.push_str("::");
-                self.src.push_str(types_path);
-            }
-            self.src.push_str("::*;\n");
-        }
-
-        let mut f = FunctionBindgen::new(self, params);
-        iface.call(
-            AbiVariant::GuestExport,
-            LiftLower::LiftArgsLowerResults,
-            func,
-            &mut f,
-        );
-        let FunctionBindgen {
-            needs_cleanup_list,
-            src,
-            ..
-        } = f;
-        assert!(!needs_cleanup_list);
-        self.src.push_str(&String::from(src));
-        self.src.push_str("}\n");
+        self.print_export_glue(iface, func, false);
 
         let prev = mem::take(&mut self.src);
         self.in_trait = true;
@@ -693,7 +3564,25 @@ impl Generator for RustWasm {
     }
 
     fn finish_functions(&mut self, iface: &Interface, dir: Direction) {
-        if self.return_pointer_area_align > 0 {
+        if self.opts.proptest && dir == Direction::Export {
+            for (_, echo) in self.proptest_echoes(iface) {
+                self.print_export_glue(iface, &echo, true);
+            }
+        }
+
+        if let (Some(path), true) = (
+            &self.opts.intrinsics_path,
+            self.return_pointer_area_align > 0,
+        ) {
+            self.src.push_str(&format!(
+                "const _: () = assert!(
+                    {size} <= {path}::RET_AREA_SIZE,
+                    \"the bindings of `{iface}` return results of up to {size} bytes, more than the shared return area takes\",
+                );\n",
+                size = self.return_pointer_area_size,
+                iface = iface.name,
+            ));
+        } else if self.return_pointer_area_align > 0 {
             self.src.push_str(&format!(
                 "
                     #[repr(align({align}))]
@@ -707,14 +3596,56 @@ impl Generator for RustWasm {
             ));
         }
 
+        if self.opts.dispatch_table && dir == Direction::Export {
+            self.print_dispatch_table(iface);
+        }
+
         // For standalone generation, close the export! macro
         if self.opts.export_macro.is_some() && dir == Direction::Export {
+            self.print_hash_assertion(iface);
             self.src.push_str("});\n");
             self.in_macro = false;
+            if let Some(vis) = &self.opts.export_macro_vis {
+                let export_macro = self.opts.export_macro.as_ref().unwrap();
+                self.src.push_str(&format!("{vis} use {export_macro};\n"));
+            }
+            if self.opts.export_macro_module.is_some() {
+                self.src.push_str("}\n");
+            }
         }
     }
 
     fn finish_one(&mut self, iface: &Interface, files: &mut Files) {
+        let encoded = self.encoded_types(iface);
+        if !encoded.is_empty() {
+            let codec = format!("{}::codec", self.crate_path());
+            let varint = self.opts.varint.clone();
+            self.print_codec_impls(iface, &encoded, &codec, &varint);
+        }
+        if let Some(id) = self.message_type(iface) {
+            self.print_message_envelope(iface, id);
+        }
+        if let Some((query, response)) = self.query_types(iface) {
+            self.print_query_client(iface, query, response);
+        }
+        self.print_event_emitters(iface);
+        if self.in_import {
+            self.print_batch(iface);
+            self.print_memo_caches(iface);
+            self.print_chunked_imports(iface);
+            self.print_interner(iface);
+            self.print_idempotency_keys(iface);
+            self.print_bulk(iface);
+            self.print_paginated_imports(iface);
+            self.print_const_fns(iface);
+            self.print_protocol(iface);
+        } else {
+            self.print_ensure(iface);
+        }
+        if self.opts.schema {
+            self.print_schema(iface);
+        }
+
         let mut src = mem::take(&mut self.src);
 
         for (name, trait_) in self.traits.iter() {
@@ -739,6 +3670,16 @@ impl Generator for RustWasm {
 
             src.push_str("}\n");
 
+            if self.opts.export_macro.is_some() {
+                src.push_str(&format!(
+                    "/// The hash of the interface `{name}` was generated from, which\n\
+                     /// the export macro checks its own against.\n\
+                     pub const {}: &str = \"{}\";\n",
+                    hash_const(iface),
+                    interface_hash(iface),
+                ));
+            }
+
             for (name, (_, methods)) in resource_names.iter().zip(&trait_.resource_methods) {
                 src.push_str(&format!("pub trait {name}"));
                 src.push_str(": ");
@@ -752,35 +3693,56 @@ impl Generator for RustWasm {
             }
         }
 
+        if self.opts.prelude {
+            let names = self.prelude_names(iface);
+            match &self.opts.export_macro {
+                Some(export_macro) => {
+                    self.prelude.push(("super".to_string(), names));
+                    if !self.in_import {
+                        self.prelude
+                            .push(("crate".to_string(), vec![export_macro.clone()]));
+                    }
+                }
+                None => {
+                    let path = format!("super::{}", self.module_name(&iface.name));
+                    self.prelude.push((path, names));
+                }
+            }
+        }
+
         // Close the opening `mod`.
         if self.opts.export_macro.is_none() {
             src.push_str("}\n");
         }
 
         if self.opts.rustfmt {
-            let mut child = Command::new("rustfmt")
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()
-                .expect("failed to spawn `rustfmt`");
-            child
-                .stdin
-                .take()
-                .unwrap()
-                .write_all(src.as_bytes())
-                .unwrap();
-            src.as_mut_string().truncate(0);
-            child
-                .stdout
-                .take()
-                .unwrap()
-                .read_to_string(src.as_mut_string())
-                .unwrap();
-            let status = child.wait().unwrap();
-            assert!(status.success());
+            rustfmt(&mut src);
         }
 
         files.push("bindings.rs", src.as_bytes());
+
+        if self.opts.stubs && !self.in_import {
+            if let Some(mut stub) = self.print_stub(iface) {
+                if self.opts.rustfmt {
+                    rustfmt(&mut stub);
+                }
+                let name = format!("stubs/{}.rs", iface.name.to_snake_case());
+                files.push(&name, stub.as_bytes());
+            }
+        }
+
+        if let Some(opts) = self.global_opts.take() {
+            self.opts = opts;
+        }
+    }
+
+    fn finish_all(&mut self, files: &mut Files) {
+        if let Some(modules) = self.print_module_paths() {
+            files.push("bindings.rs", modules.as_bytes());
+        }
+        if self.opts.prelude {
+            files.push("bindings.rs", self.print_prelude().as_bytes());
+        }
     }
 }
 
@@ -793,6 +3755,9 @@ struct FunctionBindgen<'a> {
     tmp: usize,
     needs_cleanup_list: bool,
     cleanup: Vec<(String, String)>,
+    /// Whether the function returns its argument rather than calling the
+    /// trait, as `proptest-echo-{name}` exports do.
+    echo: bool,
 }
 
 impl FunctionBindgen<'_> {
@@ -806,24 +3771,62 @@ impl FunctionBindgen<'_> {
             tmp: 0,
             needs_cleanup_list: false,
             cleanup: Vec::new(),
+            echo: false,
         }
     }
 
     fn emit_cleanup(&mut self) {
+        let zeroize = self.gen.opts.zeroize;
         for (ptr, layout) in mem::take(&mut self.cleanup) {
+            self.push_str(&format!("if {layout}.size() != 0 {{\n"));
+            if zeroize {
+                self.push_str(&format!(
+                    "core::ptr::write_bytes({ptr}, 0, {layout}.size());\n"
+                ));
+            }
+            let dealloc = self.gen.alloc_item("alloc::dealloc");
+            self.push_str(&format!("{dealloc}({ptr}, {layout});\n}}\n"));
+        }
+        if self.needs_cleanup_list {
+            self.push_str("for (ptr, layout) in cleanup_list {\nif layout.size() != 0 {\n");
+            if zeroize {
+                self.push_str("core::ptr::write_bytes(ptr, 0, layout.size());\n");
+            }
+            let dealloc = self.gen.alloc_item("alloc::dealloc");
+            self.push_str(&format!("{dealloc}(ptr, layout);\n}}\n}}\n"));
+        }
+    }
+
+    /// Panics if the lifted length `len` is over `max_list_len`, if set.
+    fn check_len(&mut self, len: &str) {
+        if let Some(max) = self.gen.opts.max_list_len {
+            let crate_path = self.gen.crate_path();
             self.push_str(&format!(
-                "if {layout}.size() != 0 {{\nstd::alloc::dealloc({ptr}, {layout});\n}}\n"
+                "if {len} > {max} {{\n{crate_path}::rt::list_too_long({len}, {max});\n}}\n"
             ));
         }
-        if self.needs_cleanup_list {
-            self.push_str(
-                "for (ptr, layout) in cleanup_list {\n
-                    if layout.size() != 0 {\n
-                        std::alloc::dealloc(ptr, layout);\n
-                    }\n
-                }\n",
-            );
+    }
+
+    /// Panics if `ptr`, received from the host, isn't aligned to `align`,
+    /// unless the bindings are `unchecked` without `check_alignment`.
+    fn check_alignment(&mut self, ptr: &str, align: usize) {
+        if align == 1 || (self.gen.opts.unchecked && !self.gen.opts.check_alignment) {
+            return;
+        }
+        let crate_path = self.gen.crate_path();
+        self.push_str(&format!(
+            "{crate_path}::rt::check_alignment({ptr}, {align});\n"
+        ));
+    }
+
+    /// Returns the address `offset` bytes past `base`, which panics rather
+    /// than wrapping around if it runs past the end of the address space.
+    fn address(&self, base: &str, offset: i32) -> String {
+        if offset == 0 {
+            return format!("({})", base);
         }
+        let crate_path = self.gen.crate_path();
+        format!("{crate_path}::rt::address({base}, {offset})")
     }
 
     fn declare_import(
@@ -933,10 +3936,13 @@ impl Bindgen for FunctionBindgen<'_> {
         self.gen.return_pointer_area_align = self.gen.return_pointer_area_align.max(align);
         let tmp = self.tmp();
 
+        let area = match &self.gen.opts.intrinsics_path {
+            Some(path) => format!("{path}::RET_AREA"),
+            None => RustWasm::ret_area_name(iface),
+        };
         self.push_str(&format!(
             "let ptr{} = {}.0.as_mut_ptr() as i32;\n",
-            tmp,
-            RustWasm::ret_area_name(iface),
+            tmp, area
         ));
         format!("ptr{}", tmp)
     }
@@ -945,6 +3951,10 @@ impl Bindgen for FunctionBindgen<'_> {
         &self.gen.sizes
     }
 
+    fn abi_version(&self) -> u32 {
+        self.gen.opts.abi_version.unwrap_or(abi::ABI_VERSION)
+    }
+
     fn is_list_canonical(&self, iface: &Interface, ty: &Type) -> bool {
         iface.all_bits_valid(ty)
     }
@@ -1053,7 +4063,7 @@ impl Bindgen for FunctionBindgen<'_> {
                         "match {} {{
                             0 => false,
                             1 => true,
-                            _ => panic!(\"invalid bool discriminant\"),
+                            _ => {crate_path}::rt::invalid_discriminant(\"bool\"),
                         }}",
                         operands[0],
                     ));
@@ -1089,7 +4099,7 @@ impl Bindgen for FunctionBindgen<'_> {
             }
             Instruction::FlagsLift { name, flags, .. } => {
                 let repr = RustFlagsRepr::new(flags);
-                let name = name.to_camel_case();
+                let name = self.gen.type_ident(name);
                 let mut result = format!("{}::empty()", name);
                 for (i, op) in operands.iter().enumerate() {
                     result.push_str(&format!(
@@ -1120,9 +4130,9 @@ impl Bindgen for FunctionBindgen<'_> {
 
             Instruction::VariantLower {
                 variant,
+                name: wit_name,
                 results: result_types,
                 ty,
-                ..
             } => {
                 let blocks = self
                     .blocks
@@ -1133,7 +4143,7 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str(&format!("match {op0} {{\n"));
                 let name = self.typename_lower(iface, *ty);
                 for (case, block) in variant.cases.iter().zip(blocks) {
-                    let case_name = case.name.to_camel_case();
+                    let case_name = self.gen.case_ident(wit_name, &case.name);
                     self.push_str(&format!("{name}::{case_name}"));
                     if case.ty == Type::Unit {
                         self.push_str(&format!(" => {{\nlet e = ();\n{block}\n}}\n"));
@@ -1151,7 +4161,7 @@ impl Bindgen for FunctionBindgen<'_> {
             {
                 self.blocks.drain(self.blocks.len() - variant.cases.len()..);
                 let mut result = format!("core::mem::transmute::<_, ");
-                result.push_str(&name.to_camel_case());
+                result.push_str(&self.gen.type_ident(name));
                 result.push_str(">(");
                 result.push_str(&operands[0]);
                 result.push_str(" as ");
@@ -1160,7 +4170,11 @@ impl Bindgen for FunctionBindgen<'_> {
                 results.push(result);
             }
 
-            Instruction::VariantLift { variant, ty, .. } => {
+            Instruction::VariantLift {
+                variant,
+                name: wit_name,
+                ty,
+            } => {
                 let blocks = self
                     .blocks
                     .drain(self.blocks.len() - variant.cases.len()..)
@@ -1179,11 +4193,13 @@ impl Bindgen for FunctionBindgen<'_> {
                     } else {
                         String::new()
                     };
-                    let case = case.name.to_camel_case();
+                    let case = self.gen.case_ident(wit_name, &case.name);
                     result.push_str(&format!("{pat} => {name}::{case}{block},\n"));
                 }
                 if !unchecked {
-                    result.push_str("_ => panic!(\"invalid enum discriminant\"),\n");
+                    result.push_str(&format!(
+                        "_ => {crate_path}::rt::invalid_discriminant(\"enum\"),\n"
+                    ));
                 }
                 result.push_str("}");
                 results.push(result);
@@ -1237,7 +4253,9 @@ impl Bindgen for FunctionBindgen<'_> {
                     result.push_str(&format!("{pat} => {name}::{case_name}({block}),\n"));
                 }
                 if !unchecked {
-                    result.push_str("_ => panic!(\"invalid union discriminant\"),\n");
+                    result.push_str(&format!(
+                        "_ => {crate_path}::rt::invalid_discriminant(\"union\"),\n"
+                    ));
                 }
                 result.push_str("}");
                 results.push(result);
@@ -1265,9 +4283,9 @@ impl Bindgen for FunctionBindgen<'_> {
                 assert_eq!(none, "()");
                 let operand = &operands[0];
                 let invalid = if unchecked {
-                    "std::hint::unreachable_unchecked()"
+                    "core::hint::unreachable_unchecked()".to_string()
                 } else {
-                    "panic!(\"invalid enum discriminant\")"
+                    format!("{crate_path}::rt::invalid_discriminant(\"enum\")")
                 };
                 results.push(format!(
                     "match {operand} {{
@@ -1299,9 +4317,9 @@ impl Bindgen for FunctionBindgen<'_> {
                 let ok = self.blocks.pop().unwrap();
                 let operand = &operands[0];
                 let invalid = if unchecked {
-                    "std::hint::unreachable_unchecked()"
+                    "core::hint::unreachable_unchecked()".to_string()
                 } else {
-                    "panic!(\"invalid enum discriminant\")"
+                    format!("{crate_path}::rt::invalid_discriminant(\"enum\")")
                 };
                 results.push(format!(
                     "match {operand} {{
@@ -1314,10 +4332,10 @@ impl Bindgen for FunctionBindgen<'_> {
 
             Instruction::EnumLower { enum_, name, .. } => {
                 let mut result = format!("match {} {{\n", operands[0]);
-                let name = name.to_camel_case();
+                let ty = self.gen.type_ident(name);
                 for (i, case) in enum_.cases.iter().enumerate() {
-                    let case = case.name.to_camel_case();
-                    result.push_str(&format!("{name}::{case} => {i},\n"));
+                    let case = self.gen.case_ident(name, &case.name);
+                    result.push_str(&format!("{ty}::{case} => {i},\n"));
                 }
                 result.push_str("}");
                 results.push(result);
@@ -1327,7 +4345,7 @@ impl Bindgen for FunctionBindgen<'_> {
             // defined the type so we can transmute directly into it.
             Instruction::EnumLift { enum_, name, .. } if unchecked => {
                 let mut result = format!("core::mem::transmute::<_, ");
-                result.push_str(&name.to_camel_case());
+                result.push_str(&self.gen.type_ident(name));
                 result.push_str(">(");
                 result.push_str(&operands[0]);
                 result.push_str(" as ");
@@ -1340,12 +4358,14 @@ impl Bindgen for FunctionBindgen<'_> {
                 let mut result = format!("match ");
                 result.push_str(&operands[0]);
                 result.push_str(" {\n");
-                let name = name.to_camel_case();
+                let ty = self.gen.type_ident(name);
                 for (i, case) in enum_.cases.iter().enumerate() {
-                    let case = case.name.to_camel_case();
-                    result.push_str(&format!("{i} => {name}::{case},\n"));
+                    let case = self.gen.case_ident(name, &case.name);
+                    result.push_str(&format!("{i} => {ty}::{case},\n"));
                 }
-                result.push_str("_ => panic!(\"invalid enum discriminant\"),\n");
+                result.push_str(&format!(
+                    "_ => {crate_path}::rt::invalid_discriminant(\"enum\"),\n"
+                ));
                 result.push_str("}");
                 results.push(result);
             }
@@ -1370,16 +4390,20 @@ impl Bindgen for FunctionBindgen<'_> {
                 results.push(len);
             }
 
-            Instruction::ListCanonLift { free, .. } => {
+            Instruction::ListCanonLift { element, free, .. } => {
                 // This only happens when we're receiving a list from the
                 // outside world, so `free` should always be `Some`.
                 assert!(free.is_some());
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 self.push_str(&format!("let {} = {} as usize;\n", len, operands[1]));
+                self.check_len(&len);
+                self.check_alignment(&operands[0], self.gen.sizes.align(element));
                 let result = format!(
-                    "Vec::from_raw_parts({} as *mut _, {1}, {1})",
-                    operands[0], len
+                    "{}::from_raw_parts({} as *mut _, {2}, {2})",
+                    self.gen.alloc_item("vec::Vec"),
+                    operands[0],
+                    len
                 );
                 results.push(result);
             }
@@ -1411,14 +4435,18 @@ impl Bindgen for FunctionBindgen<'_> {
                 let tmp = self.tmp();
                 let len = format!("len{}", tmp);
                 self.push_str(&format!("let {} = {} as usize;\n", len, operands[1]));
+                self.check_len(&len);
                 let result = format!(
-                    "Vec::from_raw_parts({} as *mut _, {1}, {1})",
-                    operands[0], len
+                    "{}::from_raw_parts({} as *mut _, {2}, {2})",
+                    self.gen.alloc_item("vec::Vec"),
+                    operands[0],
+                    len
                 );
+                let string = self.gen.alloc_item("string::String");
                 if unchecked {
-                    results.push(format!("String::from_utf8_unchecked({})", result));
+                    results.push(format!("{string}::from_utf8_unchecked({result})"));
                 } else {
-                    results.push(format!("String::from_utf8({}).unwrap()", result));
+                    results.push(format!("{string}::from_utf8({result}).unwrap()"));
                 }
             }
 
@@ -1436,19 +4464,21 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str(&format!("let {len} = {vec}.len() as i32;\n"));
                 let size = self.gen.sizes.size(element);
                 let align = self.gen.sizes.align(element);
+                let crate_path = self.gen.crate_path();
                 self.push_str(&format!(
-                    "let {layout} = core::alloc::Layout::from_size_align_unchecked({vec}.len() * {size}, {align});\n",
+                    "let {layout} = {crate_path}::rt::list_layout({vec}.len(), {size}, {align});\n",
                 ));
+                let alloc = self.gen.alloc_item("alloc");
                 self.push_str(&format!(
-                    "let {result} = if {layout}.size() != 0\n{{\nlet ptr = std::alloc::alloc({layout});\n",
+                    "let {result} = if {layout}.size() != 0\n{{\nlet ptr = {alloc}::alloc({layout});\n",
                 ));
                 self.push_str(&format!(
-                    "if ptr.is_null()\n{{\nstd::alloc::handle_alloc_error({layout});\n}}\nptr\n}}",
+                    "if ptr.is_null()\n{{\n{alloc}::handle_alloc_error({layout});\n}}\nptr\n}}",
                 ));
-                self.push_str(&format!("else {{\nstd::ptr::null_mut()\n}};\n",));
+                self.push_str("else {\ncore::ptr::null_mut()\n};\n");
                 self.push_str(&format!("for (i, e) in {vec}.into_iter().enumerate() {{\n",));
                 self.push_str(&format!(
-                    "let base = {result} as i32 + (i as i32) * {size};\n",
+                    "let base = {crate_path}::rt::element({result} as i32, i, {size});\n",
                 ));
                 self.push_str(&body);
                 self.push_str("}\n");
@@ -1482,26 +4512,37 @@ impl Bindgen for FunctionBindgen<'_> {
                     "let {len} = {operand1};\n",
                     operand1 = operands[1]
                 ));
+                self.check_len(&format!("{len} as usize"));
+                self.check_alignment(&base, align);
+                let crate_path = self.gen.crate_path();
+                let layout = format!("layout{tmp}");
+                self.push_str(&format!(
+                    "let {layout} = {crate_path}::rt::lifted_list_layout({base}, {len}, {size}, {align});\n",
+                ));
                 self.push_str(&format!(
-                    "let mut {result} = Vec::with_capacity({len} as usize);\n",
+                    "let mut {result} = {}::with_capacity({len} as usize);\n",
+                    self.gen.alloc_item("vec::Vec"),
                 ));
 
-                self.push_str("for i in 0..");
-                self.push_str(&len);
-                self.push_str(" {\n");
-                self.push_str("let base = ");
-                self.push_str(&base);
-                self.push_str(" + i *");
-                self.push_str(&size.to_string());
-                self.push_str(";\n");
+                self.push_str(&format!("for i in 0..{len} {{\n"));
+                self.push_str(&format!(
+                    "let base = {crate_path}::rt::element({base}, i as u32 as usize, {size});\n",
+                ));
                 self.push_str(&result);
                 self.push_str(".push(");
                 self.push_str(&body);
                 self.push_str(");\n");
                 self.push_str("}\n");
                 results.push(result);
+                self.push_str(&format!("if {len} != 0 {{\n"));
+                if self.gen.opts.zeroize {
+                    self.push_str(&format!(
+                        "core::ptr::write_bytes({base} as *mut u8, 0, {layout}.size());\n"
+                    ));
+                }
                 self.push_str(&format!(
-                    "if {len} != 0 {{\nstd::alloc::dealloc({base} as *mut _, std::alloc::Layout::from_size_align_unchecked(({len} as usize) * {size}, {align}));\n}}\n",
+                    "{}::dealloc({base} as *mut _, {layout});\n}}\n",
+                    self.gen.alloc_item("alloc"),
                 ));
             }
 
@@ -1528,6 +4569,11 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str(");\n");
             }
 
+            Instruction::CallInterface { .. } if self.echo => {
+                self.push_str(&format!("let result = {};\n", operands[0]));
+                results.push("result".to_string());
+            }
+
             Instruction::CallInterface { module, func } => {
                 let types_path = if let Some(path) = &self.gen.opts.types_path {
                     format!("{path}::")
@@ -1542,13 +4588,14 @@ impl Bindgen for FunctionBindgen<'_> {
                             // For standalone mode, use the macro identifier
                             self.push_str(&format!(
                                 "<$t as $crate::{types_path}{t}>::{}",
-                                func.name.to_snake_case(),
+                                self.gen.func_ident(&func.name),
                                 t = module.to_camel_case(),
                             ));
                         } else {
+                            let export_type = self.gen.export_type(module);
                             self.push_str(&format!(
-                                "<super::{m} as {m}>::{}",
-                                func.name.to_snake_case(),
+                                "<{export_type} as {m}>::{}",
+                                self.gen.func_ident(&func.name),
                                 m = module.to_camel_case()
                             ));
                         }
@@ -1590,76 +4637,66 @@ impl Bindgen for FunctionBindgen<'_> {
             }
 
             Instruction::I32Load { offset } => {
-                results.push(format!("*(({} + {}) as *const i32)", operands[0], offset));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("*({} as *const i32)", address));
             }
             Instruction::I32Load8U { offset } => {
-                results.push(format!(
-                    "i32::from(*(({} + {}) as *const u8))",
-                    operands[0], offset
-                ));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("i32::from(*({} as *const u8))", address));
             }
             Instruction::I32Load8S { offset } => {
-                results.push(format!(
-                    "i32::from(*(({} + {}) as *const i8))",
-                    operands[0], offset
-                ));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("i32::from(*({} as *const i8))", address));
             }
             Instruction::I32Load16U { offset } => {
-                results.push(format!(
-                    "i32::from(*(({} + {}) as *const u16))",
-                    operands[0], offset
-                ));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("i32::from(*({} as *const u16))", address));
             }
             Instruction::I32Load16S { offset } => {
-                results.push(format!(
-                    "i32::from(*(({} + {}) as *const i16))",
-                    operands[0], offset
-                ));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("i32::from(*({} as *const i16))", address));
             }
             Instruction::I64Load { offset } => {
-                results.push(format!("*(({} + {}) as *const i64)", operands[0], offset));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("*({} as *const i64)", address));
             }
             Instruction::F32Load { offset } => {
-                results.push(format!("*(({} + {}) as *const f32)", operands[0], offset));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("*({} as *const f32)", address));
             }
             Instruction::F64Load { offset } => {
-                results.push(format!("*(({} + {}) as *const f64)", operands[0], offset));
+                let address = self.address(&operands[0], *offset);
+                results.push(format!("*({} as *const f64)", address));
             }
             Instruction::I32Store { offset } => {
-                self.push_str(&format!(
-                    "*(({} + {}) as *mut i32) = {};\n",
-                    operands[1], offset, operands[0]
-                ));
+                let address = self.address(&operands[1], *offset);
+                self.push_str(&format!("*({} as *mut i32) = {};\n", address, operands[0]));
             }
             Instruction::I32Store8 { offset } => {
+                let address = self.address(&operands[1], *offset);
                 self.push_str(&format!(
-                    "*(({} + {}) as *mut u8) = ({}) as u8;\n",
-                    operands[1], offset, operands[0]
+                    "*({} as *mut u8) = ({}) as u8;\n",
+                    address, operands[0]
                 ));
             }
             Instruction::I32Store16 { offset } => {
+                let address = self.address(&operands[1], *offset);
                 self.push_str(&format!(
-                    "*(({} + {}) as *mut u16) = ({}) as u16;\n",
-                    operands[1], offset, operands[0]
+                    "*({} as *mut u16) = ({}) as u16;\n",
+                    address, operands[0]
                 ));
             }
             Instruction::I64Store { offset } => {
-                self.push_str(&format!(
-                    "*(({} + {}) as *mut i64) = {};\n",
-                    operands[1], offset, operands[0]
-                ));
+                let address = self.address(&operands[1], *offset);
+                self.push_str(&format!("*({} as *mut i64) = {};\n", address, operands[0]));
             }
             Instruction::F32Store { offset } => {
-                self.push_str(&format!(
-                    "*(({} + {}) as *mut f32) = {};\n",
-                    operands[1], offset, operands[0]
-                ));
+                let address = self.address(&operands[1], *offset);
+                self.push_str(&format!("*({} as *mut f32) = {};\n", address, operands[0]));
             }
             Instruction::F64Store { offset } => {
-                self.push_str(&format!(
-                    "*(({} + {}) as *mut f64) = {};\n",
-                    operands[1], offset, operands[0]
-                ));
+                let address = self.address(&operands[1], *offset);
+                self.push_str(&format!("*({} as *mut f64) = {};\n", address, operands[0]));
             }
 
             Instruction::Malloc { .. } => unimplemented!(),
@@ -1676,3 +4713,169 @@ impl Bindgen for FunctionBindgen<'_> {
         }
     }
 }
+
+/// Formats `src` in place with `rustfmt`.
+fn rustfmt(src: &mut Source) {
+    let mut child = Command::new("rustfmt")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to spawn `rustfmt`");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(src.as_bytes())
+        .unwrap();
+    src.as_mut_string().truncate(0);
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(src.as_mut_string())
+        .unwrap();
+    let status = child.wait().unwrap();
+    assert!(status.success());
+}
+
+/// Returns the `schema::Type` describing `ty`, where `schema` is the path of
+/// the `schema` runtime module.
+fn schema_type(iface: &Interface, ty: &Type, schema: &str) -> String {
+    let name = match ty {
+        Type::Unit => "Unit",
+        Type::Bool => "Bool",
+        Type::U8 => "U8",
+        Type::U16 => "U16",
+        Type::U32 => "U32",
+        Type::U64 => "U64",
+        Type::S8 => "S8",
+        Type::S16 => "S16",
+        Type::S32 => "S32",
+        Type::S64 => "S64",
+        Type::Float32 => "Float32",
+        Type::Float64 => "Float64",
+        Type::Char => "Char",
+        Type::String => "String",
+        Type::Handle(id) => {
+            return format!("{schema}::Type::Handle({:?})", iface.resources[*id].name);
+        }
+        Type::Id(id) => {
+            return match &iface.types[*id].name {
+                Some(name) => format!("{schema}::Type::Named({:?})", name),
+                None => schema_shape(iface, *id, schema),
+            };
+        }
+    };
+    format!("{schema}::Type::{name}")
+}
+
+/// Returns the `schema::Type` describing the shape of the type `id`
+/// regardless of its name.
+fn schema_shape(iface: &Interface, id: TypeId, schema: &str) -> String {
+    let ty = |ty: &Type| schema_type(iface, ty, schema);
+    match &iface.types[id].kind {
+        TypeDefKind::Type(t) => ty(t),
+        TypeDefKind::List(t) => format!("{schema}::Type::List(&{})", ty(t)),
+        TypeDefKind::Option(t) => format!("{schema}::Type::Option(&{})", ty(t)),
+        TypeDefKind::Result(r) => {
+            format!("{schema}::Type::Result(&{}, &{})", ty(&r.ok), ty(&r.err))
+        }
+        TypeDefKind::Tuple(t) => {
+            let types = t.types.iter().map(|t| format!("{},", ty(t)));
+            format!("{schema}::Type::Tuple(&[{}])", types.collect::<String>())
+        }
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => unimplemented!(),
+        TypeDefKind::Record(_)
+        | TypeDefKind::Variant(_)
+        | TypeDefKind::Union(_)
+        | TypeDefKind::Enum(_)
+        | TypeDefKind::Flags(_) => unreachable!("anonymous named type"),
+    }
+}
+
+/// Returns the `(name, schema::Type)` pairs of the fields or cases `cases`.
+fn schema_cases<'a>(
+    iface: &Interface,
+    cases: impl Iterator<Item = (&'a String, &'a Type)>,
+    schema: &str,
+) -> String {
+    cases
+        .map(|(name, ty)| format!("({:?}, {}),", name, schema_type(iface, ty, schema)))
+        .collect()
+}
+
+/// Returns the Rust literal for `value`, written in a `@table` annotation, as
+/// a value of `ty`, if it is one.
+fn const_literal(gen: &RustWasm, iface: &Interface, ty: &Type, value: &str) -> Option<String> {
+    let int = |min: i128, max: i128| {
+        let int = value.parse::<i128>().ok()?;
+        (min..=max).contains(&int).then(|| int.to_string())
+    };
+    match ty {
+        Type::Bool => ["false", "true"]
+            .contains(&value)
+            .then(|| value.to_string()),
+        Type::U8 => int(0, u8::MAX.into()),
+        Type::U16 => int(0, u16::MAX.into()),
+        Type::U32 => int(0, u32::MAX.into()),
+        Type::U64 => int(0, u64::MAX.into()),
+        Type::S8 => int(i8::MIN.into(), i8::MAX.into()),
+        Type::S16 => int(i16::MIN.into(), i16::MAX.into()),
+        Type::S32 => int(i32::MIN.into(), i32::MAX.into()),
+        Type::S64 => int(i64::MIN.into(), i64::MAX.into()),
+        Type::Float32 | Type::Float64 => {
+            let float = value.parse::<f64>().ok()?;
+            float.is_finite().then(|| format!("{:?}", float))
+        }
+        Type::Char => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(format!("{:?}", c)),
+                _ => None,
+            }
+        }
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(ty) => const_literal(gen, iface, ty, value),
+            TypeDefKind::Enum(e) => {
+                let index = e
+                    .cases
+                    .iter()
+                    .position(|c| c.name.to_camel_case() == value.to_camel_case())?;
+                const_domain(gen, iface, ty)?.into_iter().nth(index)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns the Rust patterns for every value of `ty`, in order, if it is a
+/// `bool` or an enum.
+fn const_domain(gen: &RustWasm, iface: &Interface, ty: &Type) -> Option<Vec<String>> {
+    let id = match ty {
+        Type::Bool => return Some(vec!["false".to_string(), "true".to_string()]),
+        Type::Id(id) => *id,
+        _ => return None,
+    };
+    let ty = &iface.types[id];
+    match &ty.kind {
+        TypeDefKind::Type(ty) => const_domain(gen, iface, ty),
+        TypeDefKind::Enum(e) => {
+            let wit_name = ty.name.as_ref()?;
+            let name = gen.type_ident(wit_name);
+            let cases = e.cases.iter();
+            Some(
+                cases
+                    .map(|c| format!("{}::{}", name, gen.case_ident(wit_name, &c.name)))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Returns the name of the constant holding the hash of `iface` next to its
+/// export trait.
+fn hash_const(iface: &Interface) -> String {
+    format!("{}_INTERFACE_HASH", iface.name.to_shouty_snake_case())
+}
@@ -0,0 +1,140 @@
+//! Example guest crates to start new applications from.
+//!
+//! [`generate`] lays out a crate which binds the given interfaces with the
+//! `import!` and `export!` macros, implements every export with the stubs of
+//! [`Opts::stubs`](crate::Opts::stubs), and checks in a test that each export
+//! is implemented. Its Cargo configuration adds a `build-wasm` alias building
+//! the module for `wasm32-unknown-unknown`, while `cargo test` runs natively.
+
+use crate::Opts;
+use anyhow::{bail, Result};
+use heck::*;
+use std::path::Path;
+use wit_bindgen_core::{wit_parser::Interface, Files, Generator};
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+pub struct ScaffoldOpts {
+    /// The name of the crate. Defaults to the name of the first exported
+    /// interface.
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    pub name: Option<String>,
+
+    /// The path of a checkout of the runtime crate to depend on, instead of
+    /// the released version matching this generator.
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    pub runtime_path: Option<String>,
+}
+
+/// An interface of the crate, along with the file name and the WIT source it
+/// is copied from.
+pub struct Wit<'a> {
+    pub file_name: &'a str,
+    pub source: &'a str,
+}
+
+/// Generates the files of an example crate importing `imports` and
+/// exporting `exports`, with paths relative to the crate's root.
+pub fn generate(opts: &ScaffoldOpts, imports: &[Wit], exports: &[Wit]) -> Result<Files> {
+    let parse = |wit: &Wit| {
+        let name = Path::new(wit.file_name)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or(wit.file_name);
+        Interface::parse(name, wit.source)
+    };
+    let import_ifaces = imports.iter().map(parse).collect::<Result<Vec<_>>>()?;
+    let export_ifaces = exports.iter().map(parse).collect::<Result<Vec<_>>>()?;
+    let name = match (&opts.name, export_ifaces.first()) {
+        (Some(name), _) => name.clone(),
+        (None, Some(iface)) => iface.name.to_kebab_case(),
+        (None, None) => bail!("the crate needs a name when it exports no interface"),
+    };
+
+    let mut files = Files::default();
+    let runtime = match &opts.runtime_path {
+        Some(path) => format!("path = {:?}", path),
+        None => format!("version = \"{}\"", env!("CARGO_PKG_VERSION")),
+    };
+    files.push(
+        "Cargo.toml",
+        format!(
+            "[package]\n\
+             name = \"{name}\"\n\
+             version = \"0.1.0\"\n\
+             edition = \"2021\"\n\
+             publish = false\n\
+             \n\
+             [lib]\n\
+             crate-type = [\"cdylib\", \"rlib\"]\n\
+             \n\
+             [dependencies]\n\
+             wit-bindgen-guest-rust = {{ {runtime}, package = \"linera-wit-bindgen-guest-rust\" }}\n",
+        )
+        .as_bytes(),
+    );
+    files.push(
+        ".cargo/config.toml",
+        b"# `cargo build-wasm` builds the module, found under\n\
+          # `target/wasm32-unknown-unknown/release`. Tests run natively.\n\
+          [alias]\n\
+          build-wasm = \"build --release --target wasm32-unknown-unknown\"\n",
+    );
+    for wit in imports.iter().chain(exports) {
+        files.push(&format!("wit/{}", wit.file_name), wit.source.as_bytes());
+    }
+
+    let mut lib = String::new();
+    for wit in imports {
+        lib.push_str(&format!(
+            "wit_bindgen_guest_rust::import!(\"wit/{}\");\n",
+            wit.file_name
+        ));
+    }
+    for wit in exports {
+        lib.push_str(&format!(
+            "wit_bindgen_guest_rust::export!(\"wit/{}\");\n",
+            wit.file_name
+        ));
+    }
+
+    // The stubs come from the same generator as the bindings, so that their
+    // signatures match the export traits. The imports go through it as well,
+    // so that malformed ones fail here rather than once the crate builds.
+    let mut stubs = Files::default();
+    let mut gen = Opts {
+        stubs: true,
+        ..Opts::default()
+    }
+    .build();
+    gen.generate_all(&import_ifaces, &export_ifaces, &mut stubs);
+    let mut implemented = Vec::new();
+    for iface in export_ifaces.iter() {
+        let module = iface.name.to_snake_case();
+        let stub = stubs
+            .iter()
+            .find(|(name, _)| *name == format!("stubs/{}.rs", module));
+        if let Some((_, stub)) = stub {
+            lib.push('\n');
+            lib.push_str(&String::from_utf8_lossy(stub));
+            implemented.push((module, iface.name.to_camel_case()));
+        }
+    }
+
+    lib.push_str(
+        "\n#[cfg(test)]\n\
+         mod tests {\n\
+         /// Checks that the bindings build and that every export is implemented.\n\
+         #[test]\n\
+         fn implements_exports() {\n",
+    );
+    for (module, name) in implemented.iter() {
+        lib.push_str(&format!(
+            "fn {module}<T: super::{module}::{name}>() {{}}\n\
+             {module}::<super::{name}>();\n",
+        ));
+    }
+    lib.push_str("}\n}\n");
+    files.push("src/lib.rs", lib.as_bytes());
+    Ok(files)
+}
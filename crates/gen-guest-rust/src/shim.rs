@@ -0,0 +1,623 @@
+//! Compatibility shims between two versions of an exported interface.
+//!
+//! Given the old and new versions of an interface, [`generate`] writes a
+//! module which implements the old version's export trait on top of an
+//! implementation of the new one, and the new trait on top of the old one if
+//! every function of the new version can be expressed that way. A module can
+//! then implement only the new version while still exporting the old one to
+//! callers which haven't upgraded yet.
+//!
+//! Types, functions, fields, cases, flags and parameters are matched between
+//! the two versions by name, unless [`ShimOpts::renames`] says otherwise.
+//! Record fields and parameters which only exist in the target version are
+//! filled in from [`ShimOpts::defaults`]. Conversions which would lose
+//! information, like a case the target version doesn't have, are refused.
+
+use anyhow::{anyhow, bail, Result};
+use heck::*;
+use std::collections::{BTreeMap, HashMap};
+use wit_bindgen_core::{wit_parser::*, Source};
+use wit_bindgen_gen_rust_lib::to_rust_ident;
+
+#[derive(Default, Debug, Clone)]
+#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+pub struct ShimOpts {
+    /// Renames an item of the old interface, written `old=new`.
+    ///
+    /// Fields, cases, flags and parameters are written `parent.member=new`,
+    /// using the old name of the parent.
+    #[cfg_attr(
+        feature = "structopt",
+        structopt(long = "rename", parse(try_from_str = parse_pair))
+    )]
+    pub renames: Vec<(String, String)>,
+
+    /// A Rust expression for a record field or parameter which only exists in
+    /// one of the versions, written `parent.member=expr` using the names of
+    /// the version which has it.
+    #[cfg_attr(
+        feature = "structopt",
+        structopt(long = "default", parse(try_from_str = parse_pair))
+    )]
+    pub defaults: Vec<(String, String)>,
+
+    /// The path to the bindings of the old version, `super::<name>` by
+    /// default.
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    pub old_module: Option<String>,
+
+    /// The path to the bindings of the new version, `super::<name>` by
+    /// default.
+    #[cfg_attr(feature = "structopt", structopt(long))]
+    pub new_module: Option<String>,
+}
+
+#[cfg(feature = "structopt")]
+fn parse_pair(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) => Ok((key.trim().to_string(), value.trim().to_string())),
+        None => Err(format!("expected `key=value`, found `{}`", s)),
+    }
+}
+
+/// Generates a module adapting between the exports of `old` and `new`.
+pub fn generate(old: &Interface, new: &Interface, opts: &ShimOpts) -> Result<String> {
+    let old_module = opts
+        .old_module
+        .clone()
+        .unwrap_or_else(|| format!("super::{}", old.name.to_snake_case()));
+    let new_module = opts
+        .new_module
+        .clone()
+        .unwrap_or_else(|| format!("super::{}", new.name.to_snake_case()));
+    if old_module == new_module {
+        bail!(
+            "both versions are bound at `{}`; set the module paths",
+            old_module
+        );
+    }
+
+    // Members are renamed relative to the old name of their parent, so the
+    // reverse mapping has to be keyed by the new name of the parent.
+    let renames = opts.renames.iter().cloned().collect::<HashMap<_, _>>();
+    let mut reverse = HashMap::new();
+    for (from, to) in opts.renames.iter() {
+        let (key, name) = match from.split_once('.') {
+            Some((parent, member)) => {
+                let parent = renames.get(parent).map_or(parent, |s| s.as_str());
+                (format!("{}.{}", parent, to), member)
+            }
+            None => (to.clone(), from.as_str()),
+        };
+        reverse.insert(key, name.to_string());
+    }
+
+    let mut shim = Shim {
+        old,
+        new,
+        old_module,
+        new_module,
+        renames,
+        reverse,
+        defaults: opts.defaults.iter().cloned().collect(),
+        conversions: HashMap::new(),
+        functions: BTreeMap::new(),
+        tmp: 0,
+    };
+
+    let old_impl = shim.trait_impl(Dir::Downgrade)?;
+    let new_impl = shim.trait_impl(Dir::Upgrade);
+
+    let mut src = Source::default();
+    src.push_str(&format!(
+        "use {} as old;\nuse {} as new;\n\n",
+        shim.old_module, shim.new_module
+    ));
+    for body in shim.functions.values() {
+        src.push_str(body);
+    }
+
+    let old_trait = old.name.to_camel_case();
+    let new_trait = new.name.to_camel_case();
+    src.push_str(&format!(
+        "
+            /// Exports the old version of the interface, forwarding every call
+            /// to `T`'s implementation of the new one.
+            pub struct Old<T>(core::marker::PhantomData<T>);
+
+            #[allow(unused_variables)]
+            impl<T: new::{new_trait}> old::{old_trait} for Old<T> {{
+        "
+    ));
+    src.push_str(&old_impl);
+    src.push_str("}\n");
+    match new_impl {
+        Ok(new_impl) => {
+            src.push_str(&format!(
+                "
+                    /// Exports the new version of the interface, forwarding every
+                    /// call to `T`'s implementation of the old one.
+                    pub struct New<T>(core::marker::PhantomData<T>);
+
+                    #[allow(unused_variables)]
+                    impl<T: old::{old_trait}> new::{new_trait} for New<T> {{
+                "
+            ));
+            src.push_str(&new_impl);
+            src.push_str("}\n");
+        }
+        Err(e) => src.push_str(&format!(
+            "\n// The new version can't be implemented in terms of the old one: {}\n",
+            e
+        )),
+    }
+    Ok(src.into())
+}
+
+/// The direction of a conversion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+enum Dir {
+    /// From the old version to the new one.
+    Upgrade,
+    /// From the new version to the old one.
+    Downgrade,
+}
+
+struct Shim<'a> {
+    old: &'a Interface,
+    new: &'a Interface,
+    old_module: String,
+    new_module: String,
+    renames: HashMap<String, String>,
+    reverse: HashMap<String, String>,
+    defaults: HashMap<String, String>,
+    /// The conversion function for each named type, or why there can't be
+    /// one.
+    conversions: HashMap<(Dir, TypeId), Result<String, String>>,
+    /// The source of every conversion function, by name.
+    functions: BTreeMap<(Dir, String), String>,
+    tmp: usize,
+}
+
+impl<'a> Shim<'a> {
+    /// Returns the interfaces converted from and to in `dir`.
+    fn ifaces(&self, dir: Dir) -> (&'a Interface, &'a Interface) {
+        match dir {
+            Dir::Upgrade => (self.old, self.new),
+            Dir::Downgrade => (self.new, self.old),
+        }
+    }
+
+    fn modules(&self, dir: Dir) -> (&'static str, &'static str) {
+        match dir {
+            Dir::Upgrade => ("old", "new"),
+            Dir::Downgrade => ("new", "old"),
+        }
+    }
+
+    /// Returns the name in the target version of the item named `name` in the
+    /// source version.
+    fn item(&self, dir: Dir, name: &str) -> String {
+        let map = match dir {
+            Dir::Upgrade => &self.renames,
+            Dir::Downgrade => &self.reverse,
+        };
+        map.get(name).cloned().unwrap_or_else(|| name.to_string())
+    }
+
+    /// Like `item`, but for a member of the item named `parent`.
+    fn member(&self, dir: Dir, parent: &str, name: &str) -> String {
+        let map = match dir {
+            Dir::Upgrade => &self.renames,
+            Dir::Downgrade => &self.reverse,
+        };
+        map.get(&format!("{}.{}", parent, name))
+            .cloned()
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    fn default(&self, parent: &str, name: &str) -> Result<String> {
+        match self.defaults.get(&format!("{}.{}", parent, name)) {
+            Some(expr) => Ok(expr.clone()),
+            None => bail!(
+                "`{}.{}` has no counterpart in the other version and no default",
+                parent,
+                name
+            ),
+        }
+    }
+
+    fn tmp(&mut self) -> String {
+        self.tmp += 1;
+        format!("v{}", self.tmp)
+    }
+
+    /// Generates the body of an implementation of the target version's trait
+    /// in terms of the source version's.
+    fn trait_impl(&mut self, dir: Dir) -> Result<String> {
+        let (from, to) = self.ifaces(dir);
+        let mut src = String::new();
+        for func in to.functions.iter() {
+            if func.kind != FunctionKind::Freestanding {
+                bail!("resource function `{}` can't be shimmed", func.name);
+            }
+            let source = from
+                .functions
+                .iter()
+                .find(|f| self.item(dir, &f.name) == func.name)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "function `{}` has no counterpart in the other version",
+                        func.name
+                    )
+                })?;
+            if source.kind != FunctionKind::Freestanding {
+                bail!("resource function `{}` can't be shimmed", source.name);
+            }
+
+            // The trait being implemented receives the target's parameters,
+            // so they are converted back to the source's.
+            let back = match dir {
+                Dir::Upgrade => Dir::Downgrade,
+                Dir::Downgrade => Dir::Upgrade,
+            };
+            let (_, to_module) = self.modules(dir);
+            let mut params = Vec::new();
+            for (name, ty) in func.params.iter() {
+                params.push(format!(
+                    "{}: {}",
+                    to_rust_ident(name),
+                    self.rust_ty(to, to_module, ty)?
+                ));
+            }
+            let mut args = Vec::new();
+            for (name, ty) in source.params.iter() {
+                let target = self.member(dir, &source.name, name);
+                let arg = match func.params.iter().find(|(n, _)| *n == target) {
+                    Some((_, param_ty)) => {
+                        self.convert(back, param_ty, ty, to_rust_ident(&target))?
+                    }
+                    None => self.default(&source.name, name)?,
+                };
+                args.push(arg);
+            }
+            let call = format!("T::{}({})", to_rust_ident(&source.name), args.join(", "));
+            let result = self.convert(dir, &source.result, &func.result, call)?;
+            let ret = match func.result {
+                Type::Unit => String::new(),
+                ty => format!(" -> {}", self.rust_ty(to, to_module, &ty)?),
+            };
+            src.push_str(&format!(
+                "fn {}({}){} {{\n{}\n}}\n",
+                to_rust_ident(&func.name),
+                params.join(", "),
+                ret,
+                result
+            ));
+        }
+        Ok(src)
+    }
+
+    /// Returns an expression converting `expr` of type `from_ty` in the
+    /// source version of `dir` to `to_ty` in the target version.
+    fn convert(&mut self, dir: Dir, from_ty: &Type, to_ty: &Type, expr: String) -> Result<String> {
+        let (from, to) = self.ifaces(dir);
+        let from_ty = unalias(from, from_ty);
+        let to_ty = unalias(to, to_ty);
+        let (from_id, to_id) = match (from_ty, to_ty) {
+            (Type::Id(a), Type::Id(b)) => (a, b),
+            (Type::Handle(_), _) | (_, Type::Handle(_)) => bail!("handles can't be shimmed"),
+            (a, b) if a == b => return Ok(expr),
+            (a, b) => bail!(
+                "`{}` can't be converted to `{}`",
+                type_name(from, &a),
+                type_name(to, &b)
+            ),
+        };
+        let (a, b) = (&from.types[from_id], &to.types[to_id]);
+        let v = self.tmp();
+        let ret = match (&a.kind, &b.kind) {
+            (TypeDefKind::Option(a), TypeDefKind::Option(b)) => {
+                let inner = self.convert(dir, a, b, v.clone())?;
+                if inner == v {
+                    expr
+                } else {
+                    format!("{}.map(|{}| {})", expr, v, inner)
+                }
+            }
+            (TypeDefKind::List(a), TypeDefKind::List(b)) => {
+                let inner = self.convert(dir, a, b, v.clone())?;
+                if inner == v {
+                    expr
+                } else {
+                    format!(
+                        "{}.into_iter().map(|{}| {}).collect::<Vec<_>>()",
+                        expr, v, inner
+                    )
+                }
+            }
+            (TypeDefKind::Result(a), TypeDefKind::Result(b)) => {
+                let ok = self.convert(dir, &a.ok, &b.ok, v.clone())?;
+                let err = self.convert(dir, &a.err, &b.err, v.clone())?;
+                let mut ret = expr;
+                if ok != v {
+                    ret = format!("{}.map(|{}| {})", ret, v, ok);
+                }
+                if err != v {
+                    ret = format!("{}.map_err(|{}| {})", ret, v, err);
+                }
+                ret
+            }
+            (TypeDefKind::Tuple(a), TypeDefKind::Tuple(b)) if a.types.len() == b.types.len() => {
+                let names = (0..a.types.len())
+                    .map(|i| format!("{}_{}", v, i))
+                    .collect::<Vec<_>>();
+                let mut fields = Vec::new();
+                for ((a, b), name) in a.types.iter().zip(&b.types).zip(&names) {
+                    fields.push(self.convert(dir, a, b, name.clone())?);
+                }
+                if fields == names {
+                    expr
+                } else {
+                    format!(
+                        "{{ let ({},) = {}; ({},) }}",
+                        names.join(", "),
+                        expr,
+                        fields.join(", ")
+                    )
+                }
+            }
+            (
+                TypeDefKind::Record(_)
+                | TypeDefKind::Flags(_)
+                | TypeDefKind::Enum(_)
+                | TypeDefKind::Variant(_),
+                _,
+            ) if a.name.is_some() => {
+                let name = a.name.as_deref().unwrap();
+                if b.name.as_deref() != Some(self.item(dir, name).as_str()) {
+                    bail!(
+                        "`{}` has no counterpart `{}` in the other version",
+                        name,
+                        self.item(dir, name)
+                    );
+                }
+                let func = self
+                    .conversion(dir, from_id, to_id)
+                    .map_err(|e| anyhow!(e))?;
+                format!("{}({})", func, expr)
+            }
+            _ => bail!(
+                "`{}` can't be converted to `{}`",
+                type_name(from, &from_ty),
+                type_name(to, &to_ty)
+            ),
+        };
+        Ok(ret)
+    }
+
+    /// Returns the name of the function converting the named type `from_id`
+    /// to `to_id`, generating it if necessary.
+    fn conversion(&mut self, dir: Dir, from_id: TypeId, to_id: TypeId) -> Result<String, String> {
+        if let Some(ret) = self.conversions.get(&(dir, from_id)) {
+            return ret.clone();
+        }
+        let (from, to) = self.ifaces(dir);
+        let (from_module, to_module) = self.modules(dir);
+        let (a, b) = (&from.types[from_id], &to.types[to_id]);
+        let from_name = a.name.as_deref().unwrap();
+        let to_name = b.name.as_deref().unwrap();
+        let func = format!(
+            "{}_{}",
+            match dir {
+                Dir::Upgrade => "upgrade",
+                Dir::Downgrade => "downgrade",
+            },
+            from_name.to_snake_case()
+        );
+        // Record the name first so that the body can refer to it.
+        self.conversions.insert((dir, from_id), Ok(func.clone()));
+
+        let from_ty = format!("{}::{}", from_module, from_name.to_camel_case());
+        let to_ty = format!("{}::{}", to_module, to_name.to_camel_case());
+        let body = self
+            .conversion_body(dir, a, b, &from_ty, &to_ty)
+            .map_err(|e| e.to_string());
+        let ret = body.map(|body| {
+            self.functions.insert(
+                (dir, func.clone()),
+                format!(
+                    "pub fn {}(v: {}) -> {} {{\n{}\n}}\n\n",
+                    func, from_ty, to_ty, body
+                ),
+            );
+            func
+        });
+        self.conversions.insert((dir, from_id), ret.clone());
+        ret
+    }
+
+    fn conversion_body(
+        &mut self,
+        dir: Dir,
+        a: &TypeDef,
+        b: &TypeDef,
+        from_ty: &str,
+        to_ty: &str,
+    ) -> Result<String> {
+        let from_name = a.name.as_deref().unwrap();
+        let to_name = b.name.as_deref().unwrap();
+        let ret = match (&a.kind, &b.kind) {
+            (TypeDefKind::Record(ra), TypeDefKind::Record(rb)) => {
+                let mut fields = Vec::new();
+                for field in rb.fields.iter() {
+                    let source = ra
+                        .fields
+                        .iter()
+                        .find(|f| self.member(dir, from_name, &f.name) == field.name);
+                    let value = match source {
+                        Some(source) => self.convert(
+                            dir,
+                            &source.ty,
+                            &field.ty,
+                            format!("v.{}", to_rust_ident(&source.name)),
+                        )?,
+                        None => self.default(to_name, &field.name)?,
+                    };
+                    fields.push(format!("{}: {}", to_rust_ident(&field.name), value));
+                }
+                format!("{} {{ {} }}", to_ty, fields.join(", "))
+            }
+            (TypeDefKind::Flags(fa), TypeDefKind::Flags(fb)) => {
+                let mut ret = format!("let mut ret = {}::empty();\n", to_ty);
+                for flag in fa.flags.iter() {
+                    let target = self.member(dir, from_name, &flag.name);
+                    if !fb.flags.iter().any(|f| f.name == target) {
+                        bail!("flag `{}.{}` has no counterpart", from_name, flag.name);
+                    }
+                    ret.push_str(&format!(
+                        "if v.contains({}::{}) {{ ret |= {}::{}; }}\n",
+                        from_ty,
+                        flag.name.to_shouty_snake_case(),
+                        to_ty,
+                        target.to_shouty_snake_case()
+                    ));
+                }
+                ret.push_str("ret");
+                ret
+            }
+            (TypeDefKind::Enum(ea), TypeDefKind::Enum(eb)) => {
+                let mut arms = Vec::new();
+                for case in ea.cases.iter() {
+                    let target = self.member(dir, from_name, &case.name);
+                    if !eb.cases.iter().any(|c| c.name == target) {
+                        bail!("case `{}.{}` has no counterpart", from_name, case.name);
+                    }
+                    arms.push(format!(
+                        "{}::{} => {}::{},\n",
+                        from_ty,
+                        case.name.to_camel_case(),
+                        to_ty,
+                        target.to_camel_case()
+                    ));
+                }
+                format!("match v {{\n{}}}", arms.concat())
+            }
+            (TypeDefKind::Variant(va), TypeDefKind::Variant(vb)) => {
+                let mut arms = Vec::new();
+                for case in va.cases.iter() {
+                    let target = self.member(dir, from_name, &case.name);
+                    let other = vb.cases.iter().find(|c| c.name == target).ok_or_else(|| {
+                        anyhow!("case `{}.{}` has no counterpart", from_name, case.name)
+                    })?;
+                    let pattern = format!("{}::{}", from_ty, case.name.to_camel_case());
+                    let result = format!("{}::{}", to_ty, target.to_camel_case());
+                    arms.push(match (case.ty, other.ty) {
+                        (Type::Unit, Type::Unit) => format!("{} => {},\n", pattern, result),
+                        (Type::Unit, _) | (_, Type::Unit) => bail!(
+                            "the payload of case `{}.{}` changed to or from `unit`",
+                            from_name,
+                            case.name
+                        ),
+                        (a, b) => {
+                            let value = self.convert(dir, &a, &b, "e".to_string())?;
+                            format!("{}(e) => {}({}),\n", pattern, result, value)
+                        }
+                    });
+                }
+                format!("match v {{\n{}}}", arms.concat())
+            }
+            _ => bail!(
+                "`{}` and `{}` are different kinds of type",
+                from_name,
+                to_name
+            ),
+        };
+        Ok(ret)
+    }
+
+    /// Returns the Rust type the guest bindings use for `ty` in exports.
+    fn rust_ty(&self, iface: &Interface, module: &str, ty: &Type) -> Result<String> {
+        Ok(match ty {
+            Type::Unit => "()".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::U8 => "u8".to_string(),
+            Type::U16 => "u16".to_string(),
+            Type::U32 => "u32".to_string(),
+            Type::U64 => "u64".to_string(),
+            Type::S8 => "i8".to_string(),
+            Type::S16 => "i16".to_string(),
+            Type::S32 => "i32".to_string(),
+            Type::S64 => "i64".to_string(),
+            Type::Float32 => "f32".to_string(),
+            Type::Float64 => "f64".to_string(),
+            Type::Char => "char".to_string(),
+            Type::String => "String".to_string(),
+            Type::Handle(_) => bail!("handles can't be shimmed"),
+            Type::Id(id) => {
+                let ty = &iface.types[*id];
+                if let Some(name) = &ty.name {
+                    return Ok(format!("{}::{}", module, name.to_camel_case()));
+                }
+                match &ty.kind {
+                    TypeDefKind::Option(t) => {
+                        format!("Option<{}>", self.rust_ty(iface, module, t)?)
+                    }
+                    TypeDefKind::List(t) => format!("Vec<{}>", self.rust_ty(iface, module, t)?),
+                    TypeDefKind::Result(r) => format!(
+                        "Result<{}, {}>",
+                        self.rust_ty(iface, module, &r.ok)?,
+                        self.rust_ty(iface, module, &r.err)?
+                    ),
+                    TypeDefKind::Tuple(t) => {
+                        let types = t
+                            .types
+                            .iter()
+                            .map(|ty| self.rust_ty(iface, module, ty))
+                            .collect::<Result<Vec<_>>>()?;
+                        match types.len() {
+                            1 => format!("({},)", types[0]),
+                            _ => format!("({})", types.join(", ")),
+                        }
+                    }
+                    TypeDefKind::Type(t) => self.rust_ty(iface, module, t)?,
+                    _ => bail!("`{}` can't be shimmed", type_name(iface, &Type::Id(*id))),
+                }
+            }
+        })
+    }
+}
+
+/// Looks through type aliases, which are structurally the type they name.
+fn unalias(iface: &Interface, ty: &Type) -> Type {
+    match ty {
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(t) => unalias(iface, t),
+            _ => *ty,
+        },
+        _ => *ty,
+    }
+}
+
+/// Returns a name for `ty` to use in error messages.
+fn type_name(iface: &Interface, ty: &Type) -> String {
+    match ty {
+        Type::Id(id) => {
+            let ty = &iface.types[*id];
+            match &ty.name {
+                Some(name) => name.clone(),
+                None => match &ty.kind {
+                    TypeDefKind::Option(_) => "option".to_string(),
+                    TypeDefKind::List(_) => "list".to_string(),
+                    TypeDefKind::Result(_) => "result".to_string(),
+                    TypeDefKind::Tuple(_) => "tuple".to_string(),
+                    TypeDefKind::Future(_) => "future".to_string(),
+                    TypeDefKind::Stream(_) => "stream".to_string(),
+                    _ => "type".to_string(),
+                },
+            }
+        }
+        Type::Handle(id) => iface.resources[*id].name.clone(),
+        other => format!("{:?}", other).to_lowercase(),
+    }
+}
@@ -0,0 +1,4 @@
+# Calls per function recorded by a test deployment.
+ledger.balance 52000
+ledger.owner 40
+ledger.history 0
@@ -1,5 +1,8 @@
 #![allow(dead_code, type_alias_bounds)]
 
+// Bindings generated with `no_std` take `String` and `Vec` from `alloc`.
+extern crate alloc;
+
 #[test]
 fn ok() {}
 
@@ -30,3 +33,1680 @@ mod exports {
         "!host.wit"
     );
 }
+
+mod messages {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record transfer {
+                owner: string,
+                amount: u64,
+            }
+            flags permissions {
+                read,
+                write,
+            }
+            variant message {
+                credit(transfer),
+                grant(tuple<string, permissions>),
+                batch(list<option<transfer>>),
+                ping,
+            }
+        ",
+        message = "message",
+    });
+
+    use x::{Message, MessageHandler, Permissions, Transfer};
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl MessageHandler for Recorder {
+        fn handle_credit(&mut self, message: Transfer) {
+            self.0
+                .push(format!("credit {} {}", message.owner, message.amount));
+        }
+        fn handle_grant(&mut self, (owner, perms): (String, Permissions)) {
+            self.0.push(format!("grant {} {}", owner, perms.bits()));
+        }
+        fn handle_batch(&mut self, message: Vec<Option<Transfer>>) {
+            self.0.push(format!("batch {}", message.len()));
+        }
+        fn handle_ping(&mut self) {
+            self.0.push("ping".to_string());
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let messages = [
+            Message::Credit(Transfer {
+                owner: "alice".to_string(),
+                amount: 10,
+            }),
+            Message::Grant(("bob".to_string(), Permissions::READ | Permissions::WRITE)),
+            Message::Batch(vec![
+                None,
+                Some(Transfer {
+                    owner: "carol".to_string(),
+                    amount: 1,
+                }),
+            ]),
+            Message::Ping,
+        ];
+        let mut recorder = Recorder::default();
+        for message in messages.iter() {
+            Message::dispatch_bytes(&message.encode(), &mut recorder).unwrap();
+        }
+        assert_eq!(
+            recorder.0,
+            ["credit alice 10", "grant bob 3", "batch 2", "ping"]
+        );
+
+        assert!(Message::decode(&[4]).is_err());
+        assert!(Message::decode(&[3, 0]).is_err());
+        assert!(Message::decode(&[0]).is_err());
+    }
+}
+
+mod batching {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            enum mode {
+                overwrite,
+                append,
+            }
+            set: func(key: string, value: list<u8>, mode: mode)
+            remove: func(key: string)
+            get: func(key: string) -> option<list<u8>>
+        ",
+        batch = ["set", "remove"],
+    });
+}
+
+mod memoization {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            enum scope {
+                chain,
+                application,
+            }
+            read: func(key: string, scope: scope) -> option<list<u8>>
+            config: func() -> string
+        ",
+        memoize = ["read", "config"],
+    });
+}
+
+mod chunking {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record entry {
+                key: string,
+                value: list<u8>,
+            }
+            scan: func(prefix: string) -> list<entry>
+            log: func(since: u64) -> list<string>
+            // Takes a record, so it can't be called in chunks.
+            put: func(entries: list<entry>) -> list<u32>
+        ",
+        chunk_threshold = 1024,
+        chunk_size = 4096,
+    });
+
+    use wit_bindgen_guest_rust::chunked::Chunked;
+
+    fn scan(prefix: &str) -> Chunked<x::EntryResult> {
+        x::scan_chunked(prefix)
+    }
+
+    fn log(since: u64) -> Chunked<String> {
+        x::log_chunked(since)
+    }
+}
+
+mod pagination {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record account {
+                owner: string,
+                balance: u64,
+            }
+            record accounts {
+                items: list<account>,
+                next-cursor: option<string>,
+            }
+            record blocks {
+                heights: list<u64>,
+                after: option<u64>,
+            }
+            enum error {
+                unavailable,
+            }
+
+            @paginated
+            list-accounts: func(owner: string, cursor: option<string>) -> accounts
+            /// Lists blocks after `start`.
+            @paginated(start, heights, after)
+            list-blocks: func(chain: list<u8>, start: option<u64>) -> result<blocks, error>
+        ",
+    });
+
+    fn accounts(owner: &str) -> impl Iterator<Item = x::Account> + '_ {
+        x::list_accounts_iter(owner)
+    }
+
+    fn heights(chain: &[u8]) -> Result<Vec<u64>, x::Error> {
+        x::list_blocks_iter(chain).collect()
+    }
+}
+
+mod ordering {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record chain-id {
+                owner: string,
+                index: u32,
+            }
+            enum kind {
+                user,
+                system,
+            }
+            variant account {
+                chain(chain-id),
+                named(tuple<kind, string>),
+            }
+            record price {
+                amount: float64,
+            }
+            accounts: func() -> list<account>
+            price: func() -> price
+        ",
+        derive_ord,
+    });
+
+    use std::collections::BTreeMap;
+    use x::{Account, ChainId, Kind};
+
+    #[test]
+    fn usable_as_keys() {
+        let chain = |index| {
+            Account::Chain(ChainId {
+                owner: "alice".to_string(),
+                index,
+            })
+        };
+        let mut balances = BTreeMap::new();
+        balances.insert(chain(2), 20);
+        balances.insert(Account::Named((Kind::System, "fees".to_string())), 5);
+        balances.insert(chain(1), 10);
+        balances.insert(Account::Named((Kind::User, "bob".to_string())), 1);
+        assert_eq!(
+            balances.values().copied().collect::<Vec<_>>(),
+            [10, 20, 1, 5]
+        );
+        assert!(Kind::User < Kind::System);
+    }
+}
+
+mod sensitive {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record key-pair {
+                public: list<u8>,
+                @sensitive
+                secret: list<u8>,
+            }
+            variant credential {
+                @sensitive
+                password(string),
+                token(string),
+            }
+            generate: func() -> key-pair
+            login: func() -> credential
+        ",
+    });
+
+    use x::{Credential, KeyPair};
+
+    #[test]
+    fn debug_redacts() {
+        let pair = KeyPair {
+            public: vec![1, 2],
+            secret: vec![3, 4],
+        };
+        assert_eq!(
+            format!("{:?}", pair),
+            "KeyPair { public: [1, 2], secret: <redacted> }"
+        );
+        assert_eq!(
+            format!("{:?}", Credential::Password("hunter2".to_string())),
+            "Credential::Password(<redacted>)"
+        );
+        assert_eq!(
+            format!("{:?}", Credential::Token("abc".to_string())),
+            "Credential::Token(\"abc\")"
+        );
+    }
+}
+
+mod zeroizing {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record key-pair {
+                public: list<u8>,
+                @sensitive
+                secret: list<u8>,
+            }
+            @sensitive
+            record session {
+                keys: key-pair,
+                nonce: u64,
+                peers: list<string>,
+            }
+            generate: func() -> key-pair
+            resume: func(id: u32) -> session
+        ",
+        zeroize,
+    });
+
+    use x::{KeyPair, Session};
+    use zeroize::{Zeroize, ZeroizeOnDrop};
+
+    fn assert_zeroize_on_drop<T: ZeroizeOnDrop>() {}
+
+    #[test]
+    fn zeroizes_sensitive_fields() {
+        assert_zeroize_on_drop::<Session>();
+
+        let mut pair = KeyPair {
+            public: vec![1],
+            secret: vec![2],
+        };
+        pair.zeroize();
+        assert_eq!(pair.public, [1]);
+        assert!(pair.secret.is_empty());
+
+        let mut session = Session {
+            keys: KeyPair {
+                public: vec![1],
+                secret: vec![2],
+            },
+            nonce: 3,
+            peers: vec!["alice".to_string()],
+        };
+        session.zeroize();
+        assert_eq!(session.keys.public, [1]);
+        assert!(session.keys.secret.is_empty());
+        assert_eq!(session.nonce, 0);
+        assert!(session.peers.is_empty());
+    }
+}
+
+mod constant_time {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            @sensitive
+            record signature {
+                scheme: u8,
+                bytes: list<u8>,
+            }
+            record signed {
+                payload: string,
+                @sensitive
+                signatures: list<signature>,
+                valid: bool,
+            }
+            sign: func() -> signed
+        ",
+        ct_eq,
+    });
+
+    use subtle::ConstantTimeEq;
+    use x::{Signature, Signed};
+
+    #[test]
+    fn compares_in_constant_time() {
+        let signature = |bytes: &[u8]| Signature {
+            scheme: 1,
+            bytes: bytes.to_vec(),
+        };
+        let signed = |bytes: &[u8]| Signed {
+            payload: "hello".to_string(),
+            signatures: vec![signature(bytes)],
+            valid: true,
+        };
+        assert!(bool::from(signature(&[1, 2]).ct_eq(&signature(&[1, 2]))));
+        assert!(!bool::from(signature(&[1, 2]).ct_eq(&signature(&[1, 3]))));
+        assert!(bool::from(signed(&[4]).ct_eq(&signed(&[4]))));
+        assert!(!bool::from(signed(&[4]).ct_eq(&signed(&[5]))));
+    }
+}
+
+mod queries {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            variant query {
+                balance(string),
+                owners,
+            }
+            variant response {
+                balance(u64),
+                owners(list<string>),
+            }
+        ",
+        query = "query",
+        query_response = "response",
+    });
+
+    use wit_bindgen_guest_rust::codec::{Decode, Encode};
+    use x::{Query, QueryClient, QueryClientError, QueryTransport, Response};
+
+    /// Answers every query with a fixed response.
+    struct Fixed(Response);
+
+    impl QueryTransport for Fixed {
+        type Error = ();
+
+        fn query(&mut self, query: &[u8]) -> Result<Vec<u8>, ()> {
+            Query::decode(query).unwrap();
+            Ok(self.0.encode())
+        }
+    }
+
+    #[test]
+    fn typed_client() {
+        let mut client = QueryClient::new(Fixed(Response::Balance(7)));
+        assert_eq!(client.balance("alice".to_string()).unwrap(), 7);
+        match client.owners() {
+            Err(QueryClientError::Mismatch { expected, .. }) => assert_eq!(expected, "owners"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let owners = vec!["alice".to_string()];
+        let mut client = QueryClient::new(Fixed(Response::Owners(owners.clone())));
+        assert_eq!(client.owners().unwrap(), owners);
+    }
+}
+
+mod guest_attribute {
+    struct Greeter;
+
+    #[wit_bindgen_guest_rust::guest(export = "tests/guest-attribute.wit")]
+    impl GuestAttribute for Greeter {
+        fn greet(g: guest_attribute::Greeting) -> Vec<String> {
+            (0..g.times).map(|_| format!("hello {}", g.name)).collect()
+        }
+    }
+
+    struct Counter;
+
+    #[wit_bindgen_guest_rust::guest(
+        src["counter"]: "
+            record step {
+                by: u32,
+            }
+            increment-by: func(s: step) -> u64
+        ",
+        derive_ord,
+    )]
+    impl Counter for Counter {
+        fn increment_by(s: counter::Step) -> u64 {
+            u64::from(s.by)
+        }
+    }
+
+    struct MyService;
+
+    #[wit_bindgen_guest_rust::guest("tests/service.wit")]
+    impl MyService {
+        fn serve(service::Request { path, body }: service::Request) -> Vec<u8> {
+            Self::prefixed(&path, body)
+        }
+
+        fn status() -> u32 {
+            200
+        }
+
+        fn prefixed(path: &str, body: Vec<u8>) -> Vec<u8> {
+            path.bytes().chain(body).collect()
+        }
+    }
+
+    struct Echo;
+
+    #[wit_bindgen_guest_rust::attr::export("tests/echo.wit")]
+    impl Echo {
+        fn echo(body: Vec<u8>) -> Vec<u8> {
+            body
+        }
+    }
+}
+
+mod call_depth {
+    wit_bindgen_guest_rust::export!({
+        src["depth"]: "
+            record tree {
+                children: list<option<u32>>,
+            }
+            visit: func(t: tree) -> u32
+        ",
+        call_depth_limit = 2,
+    });
+
+    use wit_bindgen_guest_rust::rt::CallDepthGuard;
+
+    struct Depth;
+
+    impl depth::Depth for Depth {
+        fn visit(t: depth::Tree) -> u32 {
+            t.children.len() as u32
+        }
+    }
+
+    #[test]
+    fn guard_limits_nesting() {
+        let outer = CallDepthGuard::try_enter(2).unwrap();
+        let inner = CallDepthGuard::try_enter(2).unwrap();
+        assert_eq!(CallDepthGuard::depth(), 2);
+        assert!(CallDepthGuard::try_enter(2).is_none());
+
+        drop(inner);
+        assert!(CallDepthGuard::try_enter(2).is_some());
+        drop(outer);
+        assert_eq!(CallDepthGuard::depth(), 0);
+    }
+}
+
+mod lint_thresholds {
+    wit_bindgen_guest_rust::import!({
+        src["hot"]: "
+            record sample {
+                at: u64,
+                value: float32,
+            }
+            record-sample: func(s: sample, tag: u32)
+        ",
+        max_flat_params = 4,
+        max_record_size = 16,
+        deny_lints,
+    });
+}
+
+mod round_trips {
+    wit_bindgen_guest_rust::export!({
+        src["store"]: "
+            flags permissions { read, write, admin }
+            enum level { low, high }
+            record entry {
+                key: string,
+                value: option<list<u8>>,
+                level: level,
+                permissions: permissions,
+                position: tuple<float32, s64>,
+            }
+            variant change {
+                insert(entry),
+                remove(string),
+                clear,
+            }
+            union id { u64, string }
+            apply: func(changes: list<change>, ids: list<id>) -> result<tuple<list<change>, list<id>>, string>
+            entries: func() -> list<entry>
+        ",
+        proptest,
+    });
+
+    struct Store;
+
+    impl store::Store for Store {
+        fn apply(
+            changes: Vec<store::Change>,
+            ids: Vec<store::Id>,
+        ) -> Result<(Vec<store::Change>, Vec<store::Id>), String> {
+            Ok((changes, ids))
+        }
+
+        fn entries() -> Vec<store::Entry> {
+            Vec::new()
+        }
+    }
+}
+
+mod interning {
+    wit_bindgen_guest_rust::import!({
+        src["metrics"]: "
+            record-value: func(app: string, key: string, value: u64)
+        ",
+        interned = ["record-value"],
+    });
+}
+
+mod varint {
+    wit_bindgen_guest_rust::import!({
+        src["x"]: "
+            record sample {
+                at: u64,
+                delta: s32,
+                value: u32,
+            }
+            variant message {
+                observed(sample),
+            }
+        ",
+        message = "message",
+        varint = ["sample.at", "sample.delta"],
+    });
+
+    use x::{Message, Sample};
+
+    #[test]
+    fn compact_fields() {
+        let sample = Sample {
+            at: 300,
+            delta: -1,
+            value: 7,
+        };
+        let bytes = Message::Observed(sample).encode();
+        assert_eq!(bytes, [0, 0xac, 0x02, 0x01, 7, 0, 0, 0]);
+        match Message::decode(&bytes).unwrap() {
+            Message::Observed(decoded) => {
+                assert_eq!((decoded.at, decoded.delta, decoded.value), (300, -1, 7))
+            }
+        }
+
+        // The last byte of `at` claims a continuation which never comes.
+        assert!(Message::decode(&[0, 0xac]).is_err());
+    }
+}
+
+mod bulk {
+    wit_bindgen_guest_rust::import!({
+        src["blobs"]: "
+            store: func(blob: u32) -> u32
+            load: func(key: u32) -> u32
+        ",
+        bulk,
+    });
+
+    #[allow(dead_code)]
+    fn round_trip(data: &[u8]) -> Vec<u8> {
+        let mut writer = blobs::bulk_writer();
+        writer.write(data);
+        let key = blobs::store(writer.finish());
+        unsafe { blobs::bulk_reader(blobs::load(key)) }.into_vec()
+    }
+}
+
+mod splicing {
+    wit_bindgen_guest_rust::import!({
+        src["storage"]: "
+            @splice
+            read: func(key: string) -> list<u8>
+            @splice
+            hash: func(data: list<u8>) -> u64
+        ",
+    });
+
+    /// Hashes a stored value without copying it into this module.
+    fn hash_stored(key: &str) -> u64 {
+        storage::hash_spliced(storage::read_spliced(key))
+    }
+}
+
+mod dispatch_table {
+    wit_bindgen_guest_rust::export!({
+        src["ops"]: "
+            add: func(a: u64, b: u64) -> u64
+            name: func() -> string
+        ",
+        dispatch_table,
+    });
+
+    struct Ops;
+
+    impl ops::Ops for Ops {
+        fn add(a: u64, b: u64) -> u64 {
+            a + b
+        }
+
+        fn name() -> String {
+            "ops".to_string()
+        }
+    }
+}
+
+mod profile {
+    wit_bindgen_guest_rust::import!({
+        src["ledger"]: "
+            balance: func(account: string) -> u64
+            owner: func(account: string) -> string
+            history: func(account: string) -> list<u64>
+        ",
+        memoize = ["balance", "owner"],
+        profile = "tests/calls.profile",
+        hot_calls = 10000,
+    });
+
+    /// Only `balance` is hot enough to keep its memoization.
+    #[allow(dead_code)]
+    fn refresh() {
+        ledger::clear_memoized();
+    }
+}
+
+mod named_modules {
+    wit_bindgen_guest_rust::import!({
+        src["kv"]: "get: func(key: string) -> option<string>",
+        module = "first",
+    });
+
+    wit_bindgen_guest_rust::import!({
+        src["kv"]: "get: func(key: string) -> option<string>",
+        module = "second",
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["greeter"]: "greet: func(name: string) -> string",
+        module = "exported",
+    });
+
+    struct Greeter;
+
+    impl exported::greeter::Greeter for Greeter {
+        fn greet(name: String) -> String {
+            format!("hello, {}", name)
+        }
+    }
+
+    #[allow(dead_code)]
+    fn both() -> (Option<String>, Option<String>) {
+        (first::kv::get("a"), second::kv::get("a"))
+    }
+}
+
+mod skip {
+    mod imports {
+        wit_bindgen_guest_rust::import!({
+            src["clock"]: "
+                now: func() -> u64
+                sleep: func(nanos: u64)
+            ",
+            skip = ["sleep"],
+        });
+
+        /// Stands in for the skipped `sleep`.
+        pub fn sleep(_nanos: u64) {}
+
+        #[allow(dead_code)]
+        fn nap() {
+            let start = clock::now();
+            sleep(1_000);
+            let _ = clock::now() - start;
+        }
+    }
+
+    mod exports {
+        wit_bindgen_guest_rust::export!({
+            src["ops"]: "
+                multiply: func(a: u64, b: u64) -> u64
+                debug-dump: func() -> string
+            ",
+            skip = ["ops.debug-dump"],
+        });
+
+        struct Ops;
+
+        impl ops::Ops for Ops {
+            fn multiply(a: u64, b: u64) -> u64 {
+                a * b
+            }
+        }
+    }
+}
+
+mod module_names {
+    wit_bindgen_guest_rust::import!({
+        src["queryable-system"]: "chain-id: func() -> u64",
+        src["storage-system"]: "read: func(key: string) -> option<list<u8>>",
+        src["log"]: "write: func(line: string)",
+        module_names = {
+            "queryable-system": "system::query",
+            "storage-system": "system::storage",
+            "log": "logging",
+        },
+    });
+
+    #[allow(dead_code)]
+    fn log_chain_id() -> Option<Vec<u8>> {
+        logging::write(&system::query::chain_id().to_string());
+        system::storage::read("key")
+    }
+}
+
+mod protocol {
+    wit_bindgen_guest_rust::import!({
+        src["kv"]: r#"
+            @transition("idle", "batch")
+            begin-batch: func()
+            @transition("batch", "batch")
+            write: func(key: string, value: list<u8>) -> bool
+            @transition("batch", "idle")
+            commit: func() -> u32
+            read: func(key: string) -> option<list<u8>>
+        "#,
+    });
+
+    use kv::protocol::{Idle, Session};
+
+    #[allow(dead_code)]
+    fn write_both(session: Session<Idle>) -> (Session<Idle>, u32) {
+        let mut batch = session.begin_batch();
+        batch.write("a", &[1]);
+        batch.write("b", &[2]);
+        let (idle, count) = batch.commit();
+        assert!(kv::read("a").is_some());
+        (idle, count)
+    }
+}
+
+mod idempotent {
+    wit_bindgen_guest_rust::import!({
+        src["payments"]: "
+            @idempotent
+            transfer: func(to: string, amount: u64) -> result<u64, string>
+            balance: func() -> u64
+        ",
+    });
+
+    /// Retries a transfer once with the key of its first attempt.
+    #[allow(dead_code)]
+    fn transfer_with_retry(to: &str, amount: u64) -> Result<u64, String> {
+        let key = payments::next_idempotency_key();
+        payments::transfer_with_key(to, amount, key)
+            .or_else(|_| payments::transfer_with_key(to, amount, key))
+    }
+
+    #[allow(dead_code)]
+    fn transfer_all(to: &str) -> Result<u64, String> {
+        payments::transfer(to, payments::balance())
+    }
+}
+
+mod ownership {
+    mod owning {
+        wit_bindgen_guest_rust::import!({
+            src["store"]: "
+                record entry { key: string, value: list<u8> }
+                get: func(key: string) -> option<entry>
+                put: func(entry: entry)
+                put-all: func(entries: list<entry>)
+            ",
+            ownership = "owning",
+        });
+
+        #[allow(dead_code)]
+        fn copy(key: &str) {
+            if let Some(entry) = store::get(key) {
+                store::put_all(core::slice::from_ref(&entry));
+                store::put(entry);
+            }
+        }
+    }
+
+    mod borrowing {
+        wit_bindgen_guest_rust::import!({
+            src["store"]: "
+                record entry { key: string, value: list<u8> }
+                record key-range { start: string, end: string }
+                scan: func(range: key-range) -> list<entry>
+                put: func(entry: entry)
+            ",
+            ownership = "borrowing",
+        });
+
+        #[allow(dead_code)]
+        fn touch(start: &str, end: &str) {
+            for entry in store::scan(store::KeyRange { start, end }) {
+                store::put(entry);
+            }
+        }
+    }
+
+    mod borrowing_duplicate_if_necessary {
+        wit_bindgen_guest_rust::import!({
+            src["store"]: "
+                record entry { key: string, value: list<u8> }
+                get: func(key: string) -> option<entry>
+                put: func(entry: entry)
+            ",
+            ownership = "borrowing-duplicate-if-necessary",
+        });
+
+        #[allow(dead_code)]
+        fn copy(key: &str) {
+            if let Some(entry) = store::get(key) {
+                store::put(store::EntryParam {
+                    key: &entry.key,
+                    value: &entry.value,
+                });
+            }
+        }
+    }
+}
+
+mod prelude {
+    wit_bindgen_guest_rust::import!({
+        src["store"]: "
+            record entry { key: string, value: list<u8> }
+            enum mode { read, write }
+            resource blob {
+                size: func() -> u64
+            }
+            open: func(mode: mode) -> blob
+            entries: func() -> list<entry>
+        ",
+        module = "bindings",
+        prelude,
+        prelude_exclude = ["Mode"],
+    });
+
+    use bindings::prelude::*;
+
+    /// `Mode` is excluded, so it stays behind the interface's module.
+    #[allow(dead_code)]
+    fn sizes() -> Vec<u64> {
+        let entries: Vec<Entry> = bindings::store::entries();
+        let blob: Blob = bindings::store::open(bindings::store::Mode::Read);
+        vec![entries.len() as u64, blob.size()]
+    }
+}
+
+mod no_alloc {
+    wit_bindgen_guest_rust::export!({
+        src["curve"]: "
+            record point { x: u64, y: u64 }
+            flags checks { on-curve, in-subgroup }
+            add: func(a: point, b: point) -> option<point>
+            check: func(p: point) -> checks
+            scale: func(p: point, k: u32) -> result<point, u8>
+        ",
+        no_alloc,
+    });
+
+    struct Curve;
+
+    impl curve::Curve for Curve {
+        fn add(a: curve::Point, b: curve::Point) -> Option<curve::Point> {
+            Some(curve::Point {
+                x: a.x.checked_add(b.x)?,
+                y: a.y.checked_add(b.y)?,
+            })
+        }
+
+        fn check(_: curve::Point) -> curve::Checks {
+            curve::Checks::ON_CURVE
+        }
+
+        fn scale(p: curve::Point, k: u32) -> Result<curve::Point, u8> {
+            match (p.x.checked_mul(k.into()), p.y.checked_mul(k.into())) {
+                (Some(x), Some(y)) => Ok(curve::Point { x, y }),
+                _ => Err(0),
+            }
+        }
+    }
+}
+
+mod no_std {
+    wit_bindgen_guest_rust::import!({
+        src["ledger"]: "
+            record entry { owner: string, amounts: list<u64> }
+            enum ledger-error { missing, frozen }
+            entries: func(owner: string) -> result<list<entry>, ledger-error>
+        ",
+        no_std,
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["indexer"]: "
+            index: func(names: list<string>) -> list<u32>
+        ",
+        no_std,
+    });
+
+    #[allow(dead_code)]
+    fn owners() -> alloc::vec::Vec<alloc::string::String> {
+        match ledger::entries("alice") {
+            Ok(entries) => entries.into_iter().map(|entry| entry.owner).collect(),
+            Err(_) => alloc::vec::Vec::new(),
+        }
+    }
+
+    struct Indexer;
+
+    impl indexer::Indexer for Indexer {
+        fn index(names: alloc::vec::Vec<alloc::string::String>) -> alloc::vec::Vec<u32> {
+            names.iter().map(|name| name.len() as u32).collect()
+        }
+    }
+}
+
+mod const_fns {
+    wit_bindgen_guest_rust::import!({
+        src["fees"]: "
+            enum tier { free, standard, premium }
+
+            /// The fee multiplier of each tier.
+            @table(\"0\", \"10\", \"25\")
+            multiplier: func(t: tier) -> u32
+
+            @table(\"8\")
+            decimals: func() -> u8
+
+            @table(\"1.5\", \"-0.25\")
+            adjustment: func(discounted: bool) -> float64
+
+            @table(\"standard\", \"free\")
+            fallback: func(strict: bool) -> tier
+        ",
+        const_fns = ["multiplier", "decimals", "adjustment", "fallback"],
+    });
+
+    const PREMIUM: u32 = fees::multiplier_const(fees::Tier::Premium);
+    const SCALE: u64 = 10u64.pow(fees::decimals_const() as u32);
+    const DISCOUNT: f64 = fees::adjustment_const(true);
+    const FALLBACK: fees::Tier = fees::fallback_const(false);
+
+    #[allow(dead_code)]
+    fn premium_fee(amount: u64) -> u64 {
+        let _ = (DISCOUNT, FALLBACK);
+        amount * u64::from(PREMIUM) / SCALE
+    }
+}
+
+mod error_context {
+    wit_bindgen_guest_rust::export!({
+        src["ledger"]: "
+            record error { message: string, context: list<string> }
+            balance: func(owner: string) -> result<u64, error>
+        ",
+        error_context = "error",
+    });
+
+    use ledger::{Error, ErrorContext};
+
+    struct Ledger;
+
+    fn lookup(owner: &str) -> Result<u64, Error> {
+        if owner.is_empty() {
+            return Err(Error::new("no such account"));
+        }
+        Ok(10)
+    }
+
+    impl ledger::Ledger for Ledger {
+        fn balance(owner: String) -> Result<u64, Error> {
+            lookup(&owner).with_context(|| format!("reading the balance of {owner:?}"))
+        }
+    }
+}
+
+mod error_location {
+    wit_bindgen_guest_rust::export!({
+        src["vault"]: "
+            record error { message: string, context: list<string>, location: string }
+            record denied { owner: string, location: string }
+            variant withdraw-error { denied(denied), failed(error) }
+            withdraw: func(owner: string, amount: u64) -> result<u64, withdraw-error>
+            seal: func() -> result<unit, error>
+        ",
+        error_context = "error",
+        error_location = "location",
+    });
+
+    use vault::{Error, WithdrawError};
+
+    struct Vault;
+
+    impl vault::Vault for Vault {
+        fn withdraw(owner: String, amount: u64) -> Result<u64, WithdrawError> {
+            if owner.is_empty() {
+                return Err(WithdrawError::denied(owner));
+            }
+            if amount == 0 {
+                return Err(WithdrawError::Failed(Error::new("nothing to withdraw")));
+            }
+            Ok(amount)
+        }
+
+        fn seal() -> Result<(), Error> {
+            let err = Error::new("already sealed");
+            assert!(err.location.contains("codegen.rs"));
+            Err(err)
+        }
+    }
+}
+
+mod ensure {
+    wit_bindgen_guest_rust::export!({
+        src["bank"]: "
+            record error { message: string, context: list<string> }
+            variant transfer-error { invalid(string), failed(error) }
+            transfer: func(sender: string, to: string, amount: u64) -> result<u64, transfer-error>
+        ",
+        error_context = "error",
+        ensure = "transfer-error.invalid",
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["teller"]: "
+            record error { message: string, context: list<string>, location: string }
+            deposit: func(amount: u64) -> result<u64, error>
+        ",
+        error_context = "error",
+        error_location = "location",
+        ensure = "error",
+    });
+
+    use bank::TransferError;
+
+    struct Bank;
+
+    impl bank::Bank for Bank {
+        fn transfer(sender: String, to: String, amount: u64) -> Result<u64, TransferError> {
+            bank::ensure!(sender != to, "can't transfer from {sender:?} to itself");
+            if amount == 0 {
+                bank::bail!("nothing to transfer");
+            }
+            Ok(amount)
+        }
+    }
+
+    struct Teller;
+
+    impl teller::Teller for Teller {
+        fn deposit(amount: u64) -> Result<u64, teller::Error> {
+            teller::ensure!(amount <= 1_000, "{amount} is over the limit");
+            Ok(amount)
+        }
+    }
+}
+
+mod rename {
+    wit_bindgen_guest_rust::import!({
+        src["clock"]: "
+            enum zone { utc, local }
+            record ts-pair { start: u64, end: u64 }
+            get-ts: func(z: zone) -> u64
+            get-span: func() -> ts-pair
+        ",
+        rename = {
+            "get-ts": "timestamp",
+            "ts-pair": "Span",
+            "zone.utc": "Universal",
+        },
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["color"]: "
+            variant shade { light(u8), dark(u8) }
+            enum channel { red, green, blue }
+            pick: func(c: channel) -> shade
+        ",
+        rename = {
+            "pick": "choose",
+            "shade": "Tone",
+            "channel.red": "Crimson",
+            "shade.dark": "Deep",
+        },
+    });
+
+    pub fn elapsed() -> u64 {
+        let clock::Span { start, end } = clock::get_span();
+        clock::timestamp(clock::Zone::Universal) - start.min(end)
+    }
+
+    struct Color;
+
+    impl color::Color for Color {
+        fn choose(c: color::Channel) -> color::Tone {
+            match c {
+                color::Channel::Crimson => color::Tone::Deep(255),
+                color::Channel::Green | color::Channel::Blue => color::Tone::Light(0),
+            }
+        }
+    }
+}
+
+mod lowered_size {
+    wit_bindgen_guest_rust::export!({
+        src["journal"]: "
+            record entry { key: string, tags: list<string>, value: option<list<u8>> }
+            variant change { put(entry), delete(string), clear }
+            enum kind { small, large }
+            flags access { read, write }
+            apply: func(c: change, k: kind, a: access) -> u32
+        ",
+        lowered_size,
+    });
+
+    use journal::{Access, Change, Entry, Kind};
+
+    struct Journal;
+
+    impl journal::Journal for Journal {
+        fn apply(c: Change, k: Kind, a: Access) -> u32 {
+            (c.lowered_size() + k.lowered_size() + a.lowered_size()) as u32
+        }
+    }
+
+    #[test]
+    fn counts_owned_strings_and_lists() {
+        let entry = Entry {
+            key: "ab".to_string(),
+            tags: vec!["x".to_string(), "yz".to_string()],
+            value: Some(vec![1, 2, 3]),
+        };
+        // 28 bytes for the record, 2 for the key, 2 * 8 + 3 for the tags and 3
+        // for the value.
+        assert_eq!(entry.lowered_size(), 52);
+        assert_eq!(Change::Put(entry).lowered_size(), 56);
+        assert_eq!(Change::Delete("abc".to_string()).lowered_size(), 35);
+        assert_eq!(Change::Clear.lowered_size(), 32);
+        assert_eq!(Kind::Large.lowered_size(), 1);
+        assert_eq!(Access::READ.lowered_size(), 1);
+    }
+}
+
+mod schema {
+    wit_bindgen_guest_rust::import!({
+        src["registry"]: "
+            record app { id: u64, tags: list<string> }
+            lookup: func(id: u64) -> option<app>
+        ",
+        schema,
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["router"]: "
+            enum method { get, post }
+            route: func(m: method, path: string) -> result<list<u8>, string>
+        ",
+        schema,
+    });
+
+    use wit_bindgen_guest_rust::schema::{Function, Type, TypeDef, TypeKind};
+
+    struct Router;
+
+    impl router::Router for Router {
+        fn route(_m: router::Method, path: String) -> Result<Vec<u8>, String> {
+            // Dispatches by name through the schema of the import.
+            match registry::SCHEMA.function(&path) {
+                Some(f) => Ok(f.name.as_bytes().to_vec()),
+                None => Err(path),
+            }
+        }
+    }
+
+    #[test]
+    fn describes_functions_and_types() {
+        assert_eq!(registry::SCHEMA.name, "registry");
+        assert!(!registry::SCHEMA.exported);
+        assert_eq!(
+            registry::SCHEMA.function("lookup"),
+            Some(&Function {
+                name: "lookup",
+                params: &[("id", Type::U64)],
+                result: Type::Option(&Type::Named("app")),
+            })
+        );
+        assert_eq!(
+            registry::SCHEMA.type_def("app"),
+            Some(&TypeDef {
+                name: "app",
+                kind: TypeKind::Record(&[("id", Type::U64), ("tags", Type::List(&Type::String))]),
+            })
+        );
+        assert!(router::SCHEMA.exported);
+        assert_eq!(
+            router::SCHEMA.function("route").unwrap().result,
+            Type::Result(&Type::List(&Type::U8), &Type::String)
+        );
+        assert_eq!(
+            router::SCHEMA.type_def("method").unwrap().kind,
+            TypeKind::Enum(&["get", "post"])
+        );
+    }
+}
+
+mod with_types {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct CryptoHash {
+        pub part1: u64,
+        pub part2: u64,
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    pub enum Status {
+        Active,
+        Closed,
+    }
+
+    wit_bindgen_guest_rust::import!({
+        src["chain"]: "
+            record crypto-hash { part1: u64, part2: u64 }
+            enum status { active, closed }
+            record block { hash: crypto-hash, height: u64 }
+            latest: func() -> block
+            status-of: func(hash: crypto-hash) -> status
+        ",
+        with = {
+            "crypto-hash": "super::CryptoHash",
+            "status": "super::Status",
+        },
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["ledger"]: "
+            record crypto-hash { part1: u64, part2: u64 }
+            owner: func(hash: crypto-hash) -> crypto-hash
+        ",
+        with = { "crypto-hash": "super::CryptoHash" },
+    });
+
+    pub fn latest_status() -> (CryptoHash, Status) {
+        let chain::Block { hash, height: _ } = chain::latest();
+        (hash, chain::status_of(hash))
+    }
+
+    struct Ledger;
+
+    impl ledger::Ledger for Ledger {
+        fn owner(hash: CryptoHash) -> CryptoHash {
+            hash
+        }
+    }
+}
+
+mod additional_derives {
+    wit_bindgen_guest_rust::export!({
+        src["catalog"]: "
+            record item { name: string, price: u64 }
+            variant entry { listed(item), hidden }
+            enum tier { basic, premium }
+            add: func(e: entry, t: tier) -> u64
+        ",
+        additional_derives = ["core::hash::Hash"],
+    });
+
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    struct Catalog;
+
+    impl catalog::Catalog for Catalog {
+        fn add(e: catalog::Entry, t: catalog::Tier) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            (e, t).hash(&mut hasher);
+            hasher.finish()
+        }
+    }
+}
+
+mod max_list_len {
+    wit_bindgen_guest_rust::export!({
+        src["store"]: "
+            record blob { name: string, chunks: list<list<u8>> }
+            put: func(b: blob, tags: list<string>) -> u32
+        ",
+        max_list_len = 4096,
+    });
+
+    struct Store;
+
+    impl store::Store for Store {
+        fn put(b: store::Blob, tags: Vec<String>) -> u32 {
+            (b.name.len() + b.chunks.len() + tags.len()) as u32
+        }
+    }
+}
+
+mod check_alignment {
+    wit_bindgen_guest_rust::export!({
+        src["samples"]: "
+            record sample { at: u64, value: float64 }
+            record window { samples: list<sample>, weights: list<float32> }
+            mean: func(w: window) -> float64
+        ",
+        unchecked,
+        check_alignment,
+    });
+
+    struct Samples;
+
+    impl samples::Samples for Samples {
+        fn mean(w: samples::Window) -> f64 {
+            let total: f64 = w.samples.iter().map(|s| s.value).sum();
+            total / w.samples.len().max(1) as f64 + w.weights.len() as f64
+        }
+    }
+}
+
+mod stubs {
+    wit_bindgen_guest_rust::export!({
+        src["ledger"]: "
+            resource account {
+                static open: func(owner: string) -> account
+                balance: func() -> u64
+            }
+            transfer: func(source: account, to: string, amount: u64) -> result<unit, string>
+        ",
+        stubs,
+    });
+
+    // The stubs define the export types.
+    #[allow(dead_code)]
+    fn exports() -> (Ledger, Account) {
+        (Ledger, Account)
+    }
+}
+
+mod debug_frames {
+    wit_bindgen_guest_rust::import!({
+        src["clock"]: "
+            enum zone { utc, local }
+            now: func(z: zone) -> u64
+        ",
+        debug_frames,
+    });
+
+    wit_bindgen_guest_rust::export!({
+        src["timer"]: "
+            elapsed: func(since: u64) -> u64
+        ",
+        debug_frames,
+    });
+
+    struct Timer;
+
+    impl timer::Timer for Timer {
+        fn elapsed(since: u64) -> u64 {
+            clock::now(clock::Zone::Utc).saturating_sub(since)
+        }
+    }
+}
+
+mod generate {
+    wit_bindgen_guest_rust::generate!({
+        import: ["tests/generate-store.wit"],
+        export: ["tests/generate-app.wit"],
+        derive_ord,
+    });
+
+    struct GenerateApp;
+
+    impl generate_app::GenerateApp for GenerateApp {
+        // Entries read from the store are returned as they are, both
+        // interfaces sharing the same `Entry`.
+        fn largest(keys: Vec<String>) -> Option<generate_app::Entry> {
+            keys.iter()
+                .filter_map(|key| generate_store::get(key))
+                .max_by_key(|entry| entry.value)
+        }
+    }
+}
+
+mod world {
+    wit_bindgen_guest_rust::generate!({
+        world = "counter",
+        package = "tests/world-package",
+    });
+
+    struct Counter;
+
+    impl counter::Counter for Counter {
+        fn increment(key: String) -> u64 {
+            let value = storage::load(&key).unwrap_or(0) + 1;
+            storage::save(&key, value);
+            value
+        }
+    }
+}
+
+mod glob_paths {
+    wit_bindgen_guest_rust::import!({
+        paths: ["tests/world-package/k*.wit", "tests/**/generate-store.wit"],
+    });
+
+    #[allow(dead_code)]
+    fn copy(key: &str) {
+        if let Some(entry) = generate_store::get(key) {
+            kv::save(key, entry.value);
+        }
+    }
+}
+
+mod directory_paths {
+    wit_bindgen_guest_rust::import!({
+        paths: ["tests/deps/"],
+    });
+
+    #[allow(dead_code)]
+    fn balance(account: &str) -> u64 {
+        ledger::balance(account).cents
+    }
+
+    #[allow(unused_imports)]
+    use audit::flag;
+
+    mod recursive {
+        // `tests/deps/deps` holds the files `use`d, which aren't bound.
+        wit_bindgen_guest_rust::import!({
+            paths: [{ path: "tests/deps", recursive }],
+        });
+
+        #[allow(dead_code)]
+        fn balance(account: &str) -> u64 {
+            ledger::balance(account).cents
+        }
+
+        #[allow(unused_imports)]
+        use audit::flag;
+    }
+}
+
+mod deps {
+    wit_bindgen_guest_rust::export!({
+        paths: ["tests/deps/ledger.wit", "tests/deps/audit.wit"],
+    });
+
+    struct Ledger;
+
+    impl ledger::Ledger for Ledger {
+        fn balance(account: String) -> ledger::Amount {
+            ledger::Amount {
+                currency: account,
+                cents: 0,
+            }
+        }
+    }
+
+    struct Audit;
+
+    impl audit::Audit for Audit {
+        fn flag(account: String, spent: audit::Amount) -> bool {
+            spent.cents > 0 && spent.currency != account
+        }
+    }
+
+    // Both interfaces use the same `amount`, which is only generated once.
+    #[allow(dead_code)]
+    fn audit(account: String) -> bool {
+        let spent = <Ledger as ledger::Ledger>::balance(account.clone());
+        <Audit as audit::Audit>::flag(account, spent)
+    }
+}
+
+mod native_fallback {
+    wit_bindgen_guest_rust::import!({
+        src["clock"]: "
+            resource timer {
+                static start: func(label: string) -> timer
+                elapsed: func() -> u64
+            }
+            now: func() -> u64
+            sleep: func(ms: u64, reason: option<string>)
+        ",
+        native_fallback,
+    });
+
+    #[test]
+    #[cfg(not(target_arch = "wasm32"))]
+    #[should_panic(expected = "`clock.now` can only be called on wasm32")]
+    fn panics_natively() {
+        clock::now();
+    }
+}
+
+mod env_src {
+    // Set in the `[env]` of `.cargo/config.toml`.
+    wit_bindgen_guest_rust::import!({
+        env_src = "WIT_BINDGEN_TEST_REGISTRY",
+    });
+
+    #[allow(dead_code)]
+    fn is_registered(key: &str) -> bool {
+        wit_bindgen_test_registry::lookup(key).is_some()
+    }
+}
+
+mod shared_intrinsics {
+    mod intrinsics {
+        wit_bindgen_guest_rust::intrinsics!();
+    }
+
+    // Both return strings through the return area of `intrinsics`.
+    wit_bindgen_guest_rust::import!({
+        src["names"]: "name: func(id: u32) -> string",
+        intrinsics_path = "crate::shared_intrinsics::intrinsics",
+    });
+    wit_bindgen_guest_rust::import!({
+        src["titles"]: "title: func(id: u32) -> option<string>",
+        intrinsics_path = "crate::shared_intrinsics::intrinsics",
+    });
+
+    #[allow(dead_code)]
+    fn heading(id: u32) -> String {
+        titles::title(id).unwrap_or_else(|| names::name(id))
+    }
+}
+
+mod features {
+    mod enabled {
+        wit_bindgen_guest_rust::import!({
+            src["payments"]: "
+                pay: func(amount: u64)
+
+                @feature(\"refunds\")
+                record refund { amount: u64 }
+
+                @feature(\"refunds\")
+                last-refund: func() -> refund
+            ",
+            features = ["refunds"],
+        });
+
+        #[allow(dead_code)]
+        fn refund_again() {
+            payments::pay(payments::last_refund().amount);
+        }
+    }
+
+    mod unbraced {
+        wit_bindgen_guest_rust::import!("tests/payments.wit" features = ["refunds"]);
+
+        #[allow(dead_code)]
+        fn refund_again() {
+            payments::pay(payments::last_refund().amount);
+        }
+    }
+
+    mod disabled {
+        // Neither `refund` nor `last-refund` are generated.
+        wit_bindgen_guest_rust::import!({
+            src["payments"]: "
+                pay: func(amount: u64)
+
+                @feature(\"refunds\")
+                record refund { amount: u64 }
+
+                @feature(\"refunds\")
+                last-refund: func() -> refund
+            ",
+        });
+
+        #[allow(dead_code)]
+        fn pay() {
+            payments::pay(1);
+        }
+    }
+}
+
+mod overrides {
+    wit_bindgen_guest_rust::import!({
+        paths: [
+            { path: "tests/generate-store.wit", unchecked, max_list_len = 64 },
+            "tests/world-package/kv.wit",
+        ],
+        max_list_len = 1024,
+    });
+
+    #[allow(dead_code)]
+    fn copy(key: &str) {
+        if let Some(entry) = generate_store::get(key) {
+            kv::save(key, entry.value);
+        }
+    }
+}
+
+mod source_relative_paths {
+    // Found next to this file, rather than at the root of the crate.
+    wit_bindgen_guest_rust::import!({
+        paths: ["generate-store.wit"],
+    });
+
+    #[allow(dead_code)]
+    fn exists(key: &str) -> bool {
+        generate_store::get(key).is_some()
+    }
+}
+
+mod inline_and_file_interfaces {
+    wit_bindgen_guest_rust::import!({
+        src["clock"]: "now: func() -> u64",
+        src["log"]: "write: func(line: string)",
+        paths: ["generate-store.wit"],
+    });
+
+    #[allow(dead_code)]
+    fn log_lookup(key: &str) -> bool {
+        log::write(&format!("{} at {}", key, clock::now()));
+        generate_store::get(key).is_some()
+    }
+}
+
+mod interpolated_paths {
+    wit_bindgen_guest_rust::import!({
+        paths: [
+            "${WORKSPACE_ROOT}/crates/gen-guest-rust/tests/generate-store.wit",
+            "${CARGO_MANIFEST_DIR}/tests/world-package/kv.wit",
+        ],
+    });
+
+    #[allow(dead_code)]
+    fn copy(key: &str) {
+        if let Some(entry) = generate_store::get(key) {
+            kv::save(key, entry.value);
+        }
+    }
+}
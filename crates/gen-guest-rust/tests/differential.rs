@@ -0,0 +1,208 @@
+//! Compares the guest bindings of this fork with those of upstream wit-bindgen.
+//!
+//! Both generators are run on the interfaces of `tests/codegen` which only use
+//! the dialect they share, importing and exporting each of them, and the core
+//! wasm functions the bindings import and export are compared: their names,
+//! and the types of their parameters and results. A difference means one side
+//! flattens some function differently, or names it differently, so the two
+//! can't call each other. Agreement doesn't cover how values stored in linear
+//! memory are laid out, nor whether values get through them unchanged, which
+//! the `differential` test of `crates/gen-host-wasmtime-rust` checks by
+//! round-tripping values through guests built with either version.
+//!
+//! Differences we know about are listed, with the reason for them, in
+//! `tests/differential/divergences.txt`; any other difference fails the test,
+//! as does a listed one which went away.
+//!
+//! Upstream is run through its command line tool, built from the checkout the
+//! `WIT_BINDGEN_UPSTREAM` environment variable points to. The test is ignored
+//! by default, as it needs that checkout; run it with
+//! `cargo test --test differential -- --ignored`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wit_bindgen_core::wit_parser::Interface;
+use wit_bindgen_core::{Files, Generator};
+
+/// Interfaces of `tests/codegen` using syntax only this fork supports.
+const FORK_ONLY: &[&str] = &["events.wit"];
+
+/// A core wasm function of the bindings, keyed by direction and name, with
+/// its signature as written in the generated code.
+type Surface = BTreeMap<(&'static str, String), String>;
+
+#[test]
+#[ignore = "needs an upstream wit-bindgen checkout in `WIT_BINDGEN_UPSTREAM`"]
+fn matches_upstream() {
+    let upstream = PathBuf::from(std::env::var_os("WIT_BINDGEN_UPSTREAM").expect(
+        "point the `WIT_BINDGEN_UPSTREAM` environment variable to a checkout of upstream wit-bindgen",
+    ));
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let known = known_divergences(&root.join("tests/differential/divergences.txt"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("differential");
+    let upstream = build_upstream(&upstream, &out_dir);
+
+    let mut found = BTreeSet::new();
+    let mut wits = std::fs::read_dir(root.join("tests/codegen"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some("wit"))
+        .collect::<Vec<_>>();
+    wits.sort();
+    for wit in wits {
+        let file = wit.file_name().unwrap().to_str().unwrap().to_string();
+        if FORK_ONLY.contains(&file.as_str()) {
+            continue;
+        }
+        for direction in ["import", "export"] {
+            let ours = surface(&ours(&wit, direction));
+            let theirs = surface(&upstream_bindings(&upstream, &wit, direction, &out_dir));
+            let keys = ours.keys().chain(theirs.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let (ours, theirs) = (ours.get(key), theirs.get(key));
+                if ours != theirs {
+                    let (kind, name) = key;
+                    let entry = format!("{} {} {}", file, kind, name);
+                    if !known.contains_key(&entry) {
+                        eprintln!("{}: ours {:?}, upstream {:?}", entry, ours, theirs);
+                    }
+                    found.insert(entry);
+                }
+            }
+        }
+    }
+
+    let unexpected = found
+        .iter()
+        .filter(|e| !known.contains_key(*e))
+        .collect::<Vec<_>>();
+    let resolved = known
+        .keys()
+        .filter(|e| !found.contains(*e))
+        .collect::<Vec<_>>();
+    assert!(
+        unexpected.is_empty() && resolved.is_empty(),
+        "unlisted divergences: {:?}\nlisted divergences which are gone: {:?}",
+        unexpected,
+        resolved
+    );
+}
+
+/// Reads the `{file} {import|export} {name}: {reason}` lines of the list of
+/// known divergences.
+fn known_divergences(path: &Path) -> BTreeMap<String, String> {
+    let contents = std::fs::read_to_string(path).unwrap();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.rsplit_once(": ").expect("missing reason"))
+        .filter(|(entry, _)| matches!(entry.split(' ').nth(1), Some("import" | "export")))
+        .map(|(entry, reason)| (entry.to_string(), reason.to_string()))
+        .collect()
+}
+
+/// Builds the command line tool of the upstream checkout, and returns its
+/// path.
+fn build_upstream(checkout: &Path, out_dir: &Path) -> PathBuf {
+    let target_dir = out_dir.join("upstream");
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("--release")
+        .arg("--manifest-path")
+        .arg(checkout.join("Cargo.toml"))
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .status()
+        .expect("failed to run cargo");
+    assert!(
+        status.success(),
+        "failed to build upstream in {:?}",
+        checkout
+    );
+    target_dir.join("release/wit-bindgen")
+}
+
+fn ours(wit: &Path, direction: &str) -> String {
+    let iface = Interface::parse_file(wit).unwrap();
+    let (imports, exports) = match direction {
+        "import" => (vec![iface], vec![]),
+        _ => (vec![], vec![iface]),
+    };
+    let mut files = Files::default();
+    wit_bindgen_gen_guest_rust::Opts::default()
+        .build()
+        .generate_all(&imports, &exports, &mut files);
+    files
+        .iter()
+        .map(|(_, contents)| String::from_utf8_lossy(contents).into_owned())
+        .collect()
+}
+
+fn upstream_bindings(upstream: &Path, wit: &Path, direction: &str, out_dir: &Path) -> String {
+    let out_dir = out_dir.join(wit.file_stem().unwrap()).join(direction);
+    let _ = std::fs::remove_dir_all(&out_dir);
+    let output = Command::new(upstream)
+        .args(["guest", "rust"])
+        .arg(format!("--{}", direction))
+        .arg(wit)
+        .arg("--out-dir")
+        .arg(&out_dir)
+        .output()
+        .expect("failed to run upstream wit-bindgen");
+    assert!(
+        output.status.success(),
+        "upstream failed on {:?}: {}",
+        wit,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let mut src = String::new();
+    for entry in std::fs::read_dir(&out_dir).unwrap() {
+        src.push_str(&std::fs::read_to_string(entry.unwrap().path()).unwrap());
+    }
+    src
+}
+
+/// Extracts the core wasm functions imported and exported by generated guest
+/// bindings, which both generators still declare the same way.
+fn surface(src: &str) -> Surface {
+    let src = src.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut surface = Surface::new();
+
+    let mut rest = &src[..];
+    while let Some(i) = rest.find("wasm_import_module = \"") {
+        rest = &rest[i + "wasm_import_module = \"".len()..];
+        let module = &rest[..rest.find('"').unwrap()];
+        let name = between(rest, "target_arch = \"wasm32\", link_name = \"", "\"");
+        let sig = between(rest, "fn wit_import", ";");
+        surface.insert(("import", format!("{}.{}", module, name)), signature(sig));
+    }
+
+    let mut rest = &src[..];
+    while let Some(i) = rest.find("#[export_name = \"") {
+        rest = &rest[i + "#[export_name = \"".len()..];
+        let name = &rest[..rest.find('"').unwrap()];
+        let header = between(rest, "extern \"C\" fn ", "{");
+        let sig = &header[header.find('(').unwrap()..];
+        surface.insert(("export", name.to_string()), signature(sig));
+    }
+    surface
+}
+
+fn between<'a>(s: &'a str, start: &str, end: &str) -> &'a str {
+    let s = &s[s.find(start).unwrap() + start.len()..];
+    &s[..s.find(end).unwrap()]
+}
+
+/// Normalizes `(a: i32, b: i64, ) -> i32` to `(i32, i64) -> i32`.
+fn signature(sig: &str) -> String {
+    let close = sig.find(')').unwrap();
+    let params = sig[1..close]
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| p.rsplit(':').next().unwrap().trim())
+        .collect::<Vec<_>>();
+    let results = sig[close + 1..].trim().trim_start_matches("->").trim();
+    format!("({}) -> {}", params.join(", "), results)
+}
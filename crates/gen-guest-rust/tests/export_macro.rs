@@ -0,0 +1,90 @@
+use wit_bindgen_core::wit_parser::Interface;
+use wit_bindgen_core::{Files, Generator};
+use wit_bindgen_gen_guest_rust::Opts;
+
+fn standalone(src: &str) -> String {
+    let mut opts = Opts::default();
+    opts.export_macro = Some("export_counter".to_string());
+    generate(opts, src)
+}
+
+fn generate(opts: Opts, src: &str) -> String {
+    let iface = Interface::parse("counter", src).unwrap();
+    let mut files = Files::default();
+    opts.try_build()
+        .unwrap()
+        .generate_all(&[], &[iface], &mut files);
+    let (_, contents) = files.iter().next().unwrap();
+    String::from_utf8_lossy(contents).into_owned()
+}
+
+fn hash(src: &str) -> String {
+    let iface = Interface::parse("counter", src).unwrap();
+    wit_bindgen_core::registry::interface_hash(&iface)
+}
+
+#[test]
+fn asserts_the_interface_hash() {
+    let v1 = "increment: func(by: u32) -> u64";
+    let bindings = standalone(v1);
+    let hash = hash(v1);
+    assert!(bindings.contains(&format!(
+        "pub const COUNTER_INTERFACE_HASH: &str = \"{hash}\";"
+    )));
+    assert!(bindings.contains(&format!("$crate::COUNTER_INTERFACE_HASH, \"{hash}\")")));
+
+    // Another version of the interface is asserted against another hash.
+    let v2 = "increment: func(by: u64) -> u64";
+    assert_ne!(hash, self::hash(v2));
+    assert!(!standalone(v2).contains(&hash));
+}
+
+#[test]
+fn exports_the_macro_by_default() {
+    let bindings = standalone("increment: func(by: u32) -> u64");
+    assert!(bindings.contains("#[macro_export]\nmacro_rules! export_counter("));
+    assert!(!bindings.contains("use export_counter;"));
+}
+
+#[test]
+fn scopes_the_macro_to_a_module() {
+    let mut opts = Opts::default();
+    opts.export_macro = Some("export_counter".to_string());
+    opts.no_macro_export = true;
+    opts.export_macro_vis = Some("pub(crate)".to_string());
+    opts.export_macro_module = Some("exports".to_string());
+    let bindings = generate(opts, "increment: func(by: u32) -> u64");
+    assert!(!bindings.contains("#[macro_export]"));
+    let module = &bindings[bindings.find("pub mod exports {").unwrap()..];
+    let definition = module.find("macro_rules! export_counter(").unwrap();
+    let reexport = module.find("pub(crate) use export_counter;\n}").unwrap();
+    assert!(definition < reexport);
+}
+
+#[test]
+fn declares_a_macro_per_interface() {
+    let account = "record account { owner: u64, balance: u64 }\n";
+    let contract = Interface::parse(
+        "contract",
+        &format!("{account}execute: func(a: account) -> u64"),
+    )
+    .unwrap();
+    let service =
+        Interface::parse("service", &format!("{account}query: func() -> account")).unwrap();
+    let mut opts = Opts::default();
+    opts.export_macros = vec![
+        ("contract".to_string(), "export_contract".to_string()),
+        ("service".to_string(), "export_service".to_string()),
+    ];
+    let mut files = Files::default();
+    opts.try_build()
+        .unwrap()
+        .generate_all(&[], &[contract, service], &mut files);
+    let (_, contents) = files.iter().next().unwrap();
+    let bindings = String::from_utf8_lossy(contents);
+    assert!(bindings.contains("macro_rules! export_contract("));
+    assert!(bindings.contains("macro_rules! export_service("));
+    assert!(!bindings.contains("mod contract"));
+    assert_eq!(bindings.matches("pub struct Account ").count(), 1);
+    assert!(!bindings.contains("pub use"));
+}
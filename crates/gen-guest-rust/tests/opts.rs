@@ -0,0 +1,182 @@
+use wit_bindgen_gen_guest_rust::Opts;
+
+fn error(opts: &Opts) -> String {
+    format!("{:#}", opts.validate().unwrap_err())
+}
+
+#[test]
+fn accepts_consistent_options() {
+    let mut opts = Opts::default();
+    assert!(opts.validate().is_ok());
+    opts.export_macro = Some("export_counter".to_string());
+    opts.types_path = Some("bindings::".to_string());
+    opts.reexported_crate_path = Some("wit_bindgen".to_string());
+    assert!(opts.try_build().is_ok());
+}
+
+#[test]
+fn rejects_inconsistent_options() {
+    let mut opts = Opts::default();
+    opts.types_path = Some("bindings::".to_string());
+    assert_eq!(error(&opts), "`types_path` requires `export_macro`");
+
+    let mut opts = Opts::default();
+    opts.export_macro = Some("export_counter".to_string());
+    opts.prelude = true;
+    assert_eq!(error(&opts), "`prelude` can't be used with `export_macro`");
+
+    let mut opts = Opts::default();
+    opts.export_macro_vis = Some("pub(crate)".to_string());
+    assert_eq!(error(&opts), "`export_macro_vis` requires `export_macro`");
+    opts.export_macro = Some("export_counter".to_string());
+    assert_eq!(
+        error(&opts),
+        "`export_macro_vis` requires `no_macro_export`"
+    );
+    opts.no_macro_export = true;
+    assert!(opts.validate().is_ok());
+    opts.export_macro_vis = None;
+    opts.export_macro_module = Some("exports".to_string());
+    assert_eq!(
+        error(&opts),
+        "`export_macro_module` with `no_macro_export` requires `export_macro_vis`"
+    );
+
+    let mut opts = Opts::default();
+    opts.export_macros = vec![
+        ("contract".to_string(), "export_app".to_string()),
+        ("service".to_string(), "export_app".to_string()),
+    ];
+    assert_eq!(
+        error(&opts),
+        "export macro `export_app` is given more than once"
+    );
+    opts.export_macros.pop();
+    assert!(opts.validate().is_ok());
+    opts.export_type = Some("Contract".to_string());
+    assert_eq!(
+        error(&opts),
+        "invalid options for `contract`: `export_type` can't be used with `export_macro`"
+    );
+    opts.export_type = None;
+    opts.export_macro = Some("export_counter".to_string());
+    assert_eq!(
+        error(&opts),
+        "`export_macros` can't be used with `export_macro`"
+    );
+
+    let mut opts = Opts::default();
+    opts.intrinsics_path = Some("crate::intrinsics".to_string());
+    assert!(opts.validate().is_ok());
+    opts.export_macro = Some("export_counter".to_string());
+    assert_eq!(
+        error(&opts),
+        "`intrinsics_path` can't be used with `export_macro`"
+    );
+
+    let mut opts = Opts::default();
+    opts.module_names = vec![
+        ("queryable-system".to_string(), "system::query".to_string()),
+        ("storage-system".to_string(), "system::storage".to_string()),
+    ];
+    assert!(opts.validate().is_ok());
+    opts.module_names
+        .push(("system".to_string(), "system".to_string()));
+    assert_eq!(
+        error(&opts),
+        "module `system` can't also contain module `system::query`"
+    );
+    opts.module_names.pop();
+    opts.module_names
+        .push(("log".to_string(), "system::storage".to_string()));
+    assert_eq!(
+        error(&opts),
+        "module `system::storage` is given more than once"
+    );
+    opts.module_names.pop();
+    opts.module_names
+        .push(("log".to_string(), "system::1log".to_string()));
+    assert_eq!(
+        error(&opts),
+        "`log` is mapped to `system::1log`, which isn't a module path"
+    );
+
+    let mut opts = Opts::default();
+    opts.no_std = true;
+    assert!(opts.validate().is_ok());
+    opts.memoize = vec!["balance".to_string()];
+    assert_eq!(error(&opts), "`no_std` can't be used with `memoize`");
+    let mut interned = Opts::default();
+    interned.interned = vec!["read-key".to_string()];
+    opts.memoize.clear();
+    opts.overrides.push(("storage".to_string(), interned));
+    assert_eq!(error(&opts), "`no_std` can't be used with `interned`");
+
+    let mut opts = Opts::default();
+    opts.query = Some("query".to_string());
+    assert_eq!(error(&opts), "`query` requires `query_response`");
+    assert!(opts.try_build().is_err());
+}
+
+#[test]
+fn names_the_interface_of_invalid_overrides() {
+    let mut invalid = Opts::default();
+    invalid.hot_calls = Some(10);
+    let mut opts = Opts::default();
+    opts.overrides.push(("counter".to_string(), invalid));
+    assert_eq!(
+        error(&opts),
+        "invalid options for `counter`: `hot_calls` requires `profile`"
+    );
+}
+
+#[test]
+fn describes_options() {
+    use wit_bindgen_core::options::Options;
+
+    let schema = Opts::schema();
+    let option = |name: &str| schema.iter().find(|o| o.name == name).unwrap();
+    assert_eq!(schema[0].name, "rustfmt");
+
+    let types_path = option("types_path");
+    assert_eq!(types_path.ty, "Option<String>");
+    assert_eq!(types_path.default, "None");
+    assert!(types_path
+        .docs
+        .starts_with("Path inside the standalone crate"));
+    assert!(types_path.cli);
+
+    assert!(!option("symbol_namespace").cli);
+    assert!(!option("overrides").cli);
+}
+
+fn generate(opts: Opts) {
+    use wit_bindgen_core::wit_parser::Interface;
+    use wit_bindgen_core::{Files, Generator};
+
+    let src = "
+        record transfer { owner: string, amount: u64 }
+        variant query { balance(string) }
+        variant response { balance(u64) }
+    ";
+    let iface = Interface::parse("bank", src).unwrap();
+    opts.build()
+        .generate_all(&[iface], &[], &mut Files::default());
+}
+
+#[test]
+#[should_panic(expected = "`message` names `mesage`, which isn't a type of `bank`")]
+fn rejects_unknown_message_types() {
+    let mut opts = Opts::default();
+    opts.message = Some("mesage".to_string());
+    generate(opts);
+}
+
+#[test]
+#[should_panic(expected = "`query_response` names `transfer`, which isn't a variant")]
+fn rejects_queries_of_records() {
+    let mut opts = Opts::default();
+    opts.query = Some("query".to_string());
+    opts.query_response = Some("transfer".to_string());
+    generate(opts);
+}
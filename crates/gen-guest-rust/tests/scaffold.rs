@@ -0,0 +1,48 @@
+use wit_bindgen_gen_guest_rust::scaffold::{generate, ScaffoldOpts, Wit};
+
+fn file(files: &wit_bindgen_core::Files, name: &str) -> String {
+    let (_, contents) = files.iter().find(|(n, _)| *n == name).unwrap();
+    String::from_utf8_lossy(contents).into_owned()
+}
+
+#[test]
+fn scaffolds_crate() {
+    let imports = [Wit {
+        file_name: "system.wit",
+        source: "now: func() -> u64",
+    }];
+    let exports = [Wit {
+        file_name: "token-contract.wit",
+        source: "transfer: func(to: string, amount: u64) -> result<unit, string>",
+    }];
+    let files = generate(&ScaffoldOpts::default(), &imports, &exports).unwrap();
+
+    let manifest = file(&files, "Cargo.toml");
+    assert!(manifest.contains("name = \"token-contract\""));
+    assert!(manifest.contains("crate-type = [\"cdylib\", \"rlib\"]"));
+    assert!(file(&files, ".cargo/config.toml").contains("wasm32-unknown-unknown"));
+    assert_eq!(file(&files, "wit/system.wit"), imports[0].source);
+
+    let lib = file(&files, "src/lib.rs");
+    assert!(lib.contains("wit_bindgen_guest_rust::import!(\"wit/system.wit\");"));
+    assert!(lib.contains("wit_bindgen_guest_rust::export!(\"wit/token-contract.wit\");"));
+    assert!(lib.contains("impl token_contract::TokenContract for TokenContract {"));
+    assert!(lib.contains("todo!()"));
+    assert!(lib.contains("token_contract::<super::TokenContract>();"));
+}
+
+#[test]
+fn needs_a_name_without_exports() {
+    let imports = [Wit {
+        file_name: "system.wit",
+        source: "now: func() -> u64",
+    }];
+    assert!(generate(&ScaffoldOpts::default(), &imports, &[]).is_err());
+
+    let opts = ScaffoldOpts {
+        name: Some("client".to_string()),
+        ..ScaffoldOpts::default()
+    };
+    let files = generate(&opts, &imports, &[]).unwrap();
+    assert!(file(&files, "Cargo.toml").contains("name = \"client\""));
+}
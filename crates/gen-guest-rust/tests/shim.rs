@@ -0,0 +1,105 @@
+use wit_bindgen_core::wit_parser::Interface;
+use wit_bindgen_gen_guest_rust::shim::{generate, ShimOpts};
+
+fn opts(renames: &[(&str, &str)], defaults: &[(&str, &str)]) -> ShimOpts {
+    let pairs = |pairs: &[(&str, &str)]| {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    };
+    ShimOpts {
+        renames: pairs(renames),
+        defaults: pairs(defaults),
+        old_module: Some("super::v1".to_string()),
+        new_module: Some("super::v2".to_string()),
+    }
+}
+
+#[test]
+fn renamed_and_defaulted() {
+    let old = Interface::parse(
+        "contract",
+        "
+            record point { x: u32, y: u32 }
+            enum color { red, green }
+            get: func(p: point, c: color) -> option<point>
+        ",
+    )
+    .unwrap();
+    let new = Interface::parse(
+        "contract",
+        "
+            record point { x: u32, y: u32, z: u32 }
+            enum colour { red, green, blue }
+            fetch: func(p: point, c: colour, scale: u32) -> option<point>
+        ",
+    )
+    .unwrap();
+    let shim = generate(
+        &old,
+        &new,
+        &opts(
+            &[("color", "colour"), ("get", "fetch")],
+            &[("point.z", "0"), ("fetch.scale", "1")],
+        ),
+    )
+    .unwrap();
+
+    assert!(shim.contains("pub fn upgrade_point(v: old::Point) -> new::Point"));
+    assert!(shim.contains("new::Point { x: v.x, y: v.y, z: 0 }"));
+    assert!(shim.contains("pub fn downgrade_point(v: new::Point) -> old::Point"));
+    assert!(shim.contains("old::Color::Green => new::Colour::Green,"));
+    assert!(shim.contains("impl<T: new::Contract> old::Contract for Old<T>"));
+    assert!(shim.contains("fn get(p: old::Point, c: old::Color) -> Option<old::Point>"));
+    assert!(shim.contains("T::fetch(upgrade_point(p), upgrade_color(c), 1)"));
+
+    // `blue` can't be represented in the old version.
+    assert!(!shim.contains("for New<T>"));
+    assert!(shim.contains("case `colour.blue` has no counterpart"));
+}
+
+#[test]
+fn both_directions() {
+    let old = Interface::parse(
+        "contract",
+        "
+            flags perms { read, write }
+            variant change { created(string), deleted }
+            apply: func(e: change, p: perms) -> result<list<tuple<u32, change>>, string>
+        ",
+    )
+    .unwrap();
+    let new = Interface::parse(
+        "contract",
+        "
+            flags perms { read, write, admin }
+            variant change { created(string), removed }
+            apply: func(e: change, p: perms) -> result<list<tuple<u32, change>>, string>
+        ",
+    )
+    .unwrap();
+
+    // The old trait can still be implemented, but dropping `admin` from the
+    // parameters of the new one would lose information.
+    let shim = generate(&old, &new, &opts(&[("change.deleted", "removed")], &[])).unwrap();
+    assert!(shim.contains("for Old<T>"));
+    assert!(!shim.contains("for New<T>"));
+    assert!(shim.contains("flag `perms.admin` has no counterpart"));
+
+    let new = Interface::parse(
+        "contract",
+        "
+            flags perms { read, write }
+            variant change { created(string), removed }
+            apply: func(e: change, p: perms) -> result<list<tuple<u32, change>>, string>
+        ",
+    )
+    .unwrap();
+    let shim = generate(&old, &new, &opts(&[("change.deleted", "removed")], &[])).unwrap();
+    assert!(shim.contains("old::Change::Deleted => new::Change::Removed,"));
+    assert!(shim.contains("new::Change::Removed => old::Change::Deleted,"));
+    assert!(shim.contains("if v.contains(old::Perms::WRITE) { ret |= new::Perms::WRITE; }"));
+    assert!(shim.contains("impl<T: new::Contract> old::Contract for Old<T>"));
+    assert!(shim.contains("impl<T: old::Contract> new::Contract for New<T>"));
+}
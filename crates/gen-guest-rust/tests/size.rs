@@ -0,0 +1,72 @@
+//! Keeps the size of reference guests in check once optimized by `wasm-opt`.
+//!
+//! Each guest of `test-rust-wasm` listed below is built in release mode and
+//! run through `wasm-opt -Oz`, and the result must fit in its budget. A change
+//! to the generated bindings that makes shims harder to inline or pulls more
+//! code into them shows up here. Budgets may be lowered whenever the output
+//! shrinks, and raised only for a change that is worth the extra bytes.
+//!
+//! The test is skipped when `wasm-opt` isn't installed.
+
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// The reference guests and the most bytes each may take after `wasm-opt`.
+const BUDGETS: &[(&str, u64)] = &[("smoke", 24_000), ("flavorful", 96_000)];
+
+#[test]
+fn wasm_opt_size() {
+    if !has_wasm_opt() {
+        eprintln!("skipping: `wasm-opt` was not found");
+        return;
+    }
+
+    let target_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("size");
+    let mut cmd = Command::new(env!("CARGO"));
+    cmd.arg("build")
+        .arg("--release")
+        .arg("--target=wasm32-wasi")
+        .current_dir(Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-rust-wasm"))
+        .env("CARGO_TARGET_DIR", &target_dir)
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .env_remove("RUSTFLAGS");
+    for (name, _) in BUDGETS {
+        cmd.arg("--bin").arg(name);
+    }
+    let status = cmd.status().unwrap();
+    assert!(status.success());
+
+    let mut over = Vec::new();
+    for (name, budget) in BUDGETS {
+        let wasm = target_dir
+            .join("wasm32-wasi/release")
+            .join(name)
+            .with_extension("wasm");
+        let optimized = wasm.with_extension("opt.wasm");
+        let status = Command::new("wasm-opt")
+            .arg("-Oz")
+            .arg("--strip-debug")
+            .arg(&wasm)
+            .arg("-o")
+            .arg(&optimized)
+            .status()
+            .unwrap();
+        assert!(status.success(), "wasm-opt failed on {:?}", wasm);
+
+        let size = optimized.metadata().unwrap().len();
+        println!("{}: {} bytes (budget {})", name, size, budget);
+        if size > *budget {
+            over.push(format!("{} is {} bytes, over {}", name, size, budget));
+        }
+    }
+    assert!(over.is_empty(), "guests over budget: {}", over.join(", "));
+}
+
+fn has_wasm_opt() -> bool {
+    match Command::new("wasm-opt").arg("--version").output() {
+        Ok(output) => output.status.success(),
+        Err(e) if e.kind() == ErrorKind::NotFound => false,
+        Err(e) => panic!("failed to spawn wasm-opt: {}", e),
+    }
+}
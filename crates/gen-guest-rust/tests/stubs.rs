@@ -0,0 +1,46 @@
+use wit_bindgen_core::wit_parser::Interface;
+use wit_bindgen_core::{Files, Generator};
+
+fn stub(src: &str) -> Option<String> {
+    let iface = Interface::parse("wallet", src).unwrap();
+    let mut files = Files::default();
+    let mut opts = wit_bindgen_gen_guest_rust::Opts::default();
+    opts.stubs = true;
+    opts.build().generate_all(&[], &[iface], &mut files);
+    let stub = files
+        .iter()
+        .find(|(name, _)| *name == "stubs/wallet.rs")
+        .map(|(_, contents)| String::from_utf8_lossy(contents).into_owned());
+    stub
+}
+
+#[test]
+fn stubs_every_function() {
+    let stub = stub(
+        "
+            record transfer { to: string, amount: u64 }
+            resource account {
+                static open: func(owner: string) -> account
+                balance: func() -> u64
+            }
+            transfer: func(source: account, t: transfer) -> result<unit, string>
+            total: func() -> u64
+        ",
+    )
+    .unwrap();
+
+    assert!(stub.contains("use wallet::*;"));
+    assert!(stub.contains("pub struct Wallet;"));
+    assert!(stub.contains("impl wallet::Wallet for Wallet {"));
+    assert!(stub.contains("type Account = Account;"));
+    assert!(stub.contains("pub struct Account;"));
+    assert!(stub.contains("impl wallet::Account for Account {"));
+    // Handles refer to the resource at the invocation site.
+    assert!(!stub.contains("super::"));
+    assert_eq!(stub.matches("todo!()").count(), 4);
+}
+
+#[test]
+fn skips_interfaces_without_functions() {
+    assert_eq!(stub("record unused { x: u32 }"), None);
+}
@@ -36,11 +36,13 @@ struct Exports {
     resource_funcs: BTreeMap<ResourceId, Vec<Source>>,
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    #[cfg_attr(feature = "structopt", structopt(long = "no-typescript"))]
-    pub no_typescript: bool,
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        #[cfg_attr(feature = "structopt", structopt(long = "no-typescript"))]
+        pub no_typescript: bool,
+    }
 }
 
 impl Opts {
@@ -54,21 +54,35 @@ struct Exports {
     funcs: Vec<String>,
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    /// Whether or not `rustfmt` is executed to format generated code.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub rustfmt: bool,
-
-    /// Whether or not to emit `tracing` macro calls on function entry/exit.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub tracing: bool,
-
-    /// A flag to indicate that all trait methods in imports should return a
-    /// custom trait-defined error. Applicable for import bindings.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub custom_error: bool,
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        /// Whether or not `rustfmt` is executed to format generated code.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub rustfmt: bool,
+
+        /// Whether or not to emit `tracing` macro calls on function entry/exit.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub tracing: bool,
+
+        /// A flag to indicate that all trait methods in imports should return a
+        /// custom trait-defined error. Applicable for import bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub custom_error: bool,
+
+        /// Derives `PartialEq`, `Eq`, `PartialOrd` and `Ord` on generated types
+        /// whose contents all support them. Types containing floats or handles
+        /// are left as they are.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub derive_ord: bool,
+
+        /// Pins the generated code to an older version of the ABI, so that it
+        /// keeps working with wasm modules built against it. See
+        /// `wit_parser::abi::ABI_VERSION` for the differences between versions.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub abi_version: Option<u32>,
+    }
 }
 
 impl Opts {
@@ -201,6 +215,10 @@ impl RustGenerator for Wasmer {
         None
     }
 
+    fn derive_ord(&self) -> bool {
+        self.opts.derive_ord
+    }
+
     fn push_str(&mut self, s: &str) {
         self.src.push_str(s);
     }
@@ -263,6 +281,11 @@ impl Generator for Wasmer {
         let variant = Self::abi_variant(dir);
         self.types.analyze(iface);
         self.in_import = variant == AbiVariant::GuestImport;
+        if let Some(version) = self.opts.abi_version {
+            if let Err(e) = abi::check_abi_version(version) {
+                panic!("{}", e);
+            }
+        }
         self.trait_name = iface.name.to_camel_case();
         self.src.push_str(&format!(
             "#[allow(clippy::all)]\npub mod {} {{\n",
@@ -1419,6 +1442,10 @@ impl Bindgen for FunctionBindgen<'_> {
         &self.gen.sizes
     }
 
+    fn abi_version(&self) -> u32 {
+        self.gen.opts.abi_version.unwrap_or(abi::ABI_VERSION)
+    }
+
     fn push_block(&mut self) {
         let prev = mem::take(&mut self.src);
         self.block_storage.push(prev);
@@ -60,11 +60,13 @@ struct Export {
     base_name: String,
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    #[cfg_attr(feature = "structopt", structopt(long = "no-typescript"))]
-    pub no_typescript: bool,
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        #[cfg_attr(feature = "structopt", structopt(long = "no-typescript"))]
+        pub no_typescript: bool,
+    }
 }
 
 impl Opts {
@@ -6,7 +6,10 @@ use std::process::{Command, Stdio};
 use wit_bindgen_core::wit_parser::abi::{AbiVariant, Bindgen, Instruction, LiftLower, WasmType};
 use wit_bindgen_core::{wit_parser::*, Direction, Files, Generator, Source, TypeInfo, Types};
 use wit_bindgen_gen_rust_lib::{
-    to_rust_ident, wasm_type, FnSig, RustFlagsRepr, RustFunctionGenerator, RustGenerator, TypeMode,
+    batched_functions, chunked_functions, encoded_param_by_ref, error_context_ok,
+    has_error_location, idempotent_functions, interned_functions, is_byte_list, is_error_context,
+    protocol_transitions, spliced_functions, to_rust_ident, wasm_type, FnSig, RustFlagsRepr,
+    RustFunctionGenerator, RustGenerator, Transition, TypeMode,
 };
 
 #[derive(Default)]
@@ -21,6 +24,9 @@ pub struct Wasmtime {
     needs_raw_mem: bool,
     needs_bad_int: bool,
     needs_copy_slice: bool,
+    needs_chunked_results: bool,
+    needs_interned_strings: bool,
+    needs_data_tokens: bool,
     needs_buffer_glue: bool,
     needs_le: bool,
     needs_custom_error_to_trap: bool,
@@ -43,102 +49,1740 @@ enum NeededFunction {
 
 struct Import {
     name: String,
-    trait_signature: String,
+    /// The method of the host trait this import calls, or `None` if the import
+    /// is handled entirely by generated code.
+    trait_signature: Option<String>,
+    /// The call of the trait method without its receiver, as `name(args)`,
+    /// through which the adapters of grouped traits forward it.
+    trait_call: String,
     closure: String,
+    /// The `#[cfg]` attribute gating the import, if any.
+    cfg: String,
+    /// The trait of its `@group("name")`, if any, declaring the trait method
+    /// instead of the host trait itself.
+    group: Option<String>,
 }
 
 #[derive(Default)]
 struct Exports {
     fields: BTreeMap<String, (String, String)>,
     funcs: Vec<String>,
+    /// Methods of the `spawn_blocking` wrapper.
+    async_funcs: Vec<String>,
+    /// Cases of the request and response enums of the `coalesce`d exports.
+    requests: Vec<String>,
+    responses: Vec<String>,
 }
 
-#[derive(Default, Debug, Clone)]
-#[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
-pub struct Opts {
-    /// Whether or not `rustfmt` is executed to format generated code.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub rustfmt: bool,
-
-    /// Whether or not to emit `tracing` macro calls on function entry/exit.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub tracing: bool,
-
-    /// A flag to indicate that all trait methods in imports should return a
-    /// custom trait-defined error. Applicable for import bindings.
-    #[cfg_attr(feature = "structopt", structopt(long))]
-    pub custom_error: bool,
+wit_bindgen_core::options! {
+    #[derive(Default, Debug, Clone)]
+    #[cfg_attr(feature = "structopt", derive(structopt::StructOpt))]
+    pub struct Opts {
+        /// Whether or not `rustfmt` is executed to format generated code.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub rustfmt: bool,
+
+        /// Whether or not to emit `tracing` macro calls on function entry/exit.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub tracing: bool,
+
+        /// A flag to indicate that all trait methods in imports should return a
+        /// custom trait-defined error. Applicable for import bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub custom_error: bool,
+
+        /// Names of functions whose calls wasm can queue into a batch.
+        ///
+        /// The batch is flushed through a single import which decodes each call
+        /// and passes it on to the host trait. This must match the list given to
+        /// the guest bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub batch: Vec<String>,
+
+        /// Whether wasm can read the list results of imports in chunks, as
+        /// guests generated with `chunk_threshold` do.
+        ///
+        /// This covers every import returning a list whose parameters are
+        /// scalars, strings, enums, flags or byte lists. The result is encoded
+        /// and, unless it fits into the buffer wasm passes along with the call,
+        /// kept on the host until wasm has read all of it through a
+        /// continuation handle.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub chunked: bool,
+
+        /// Names of imported functions whose string arguments wasm may pass as
+        /// ids of strings it registered once through `intern_string`.
+        ///
+        /// This must match the list given to the guest bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub interned: Vec<String>,
+
+        /// Record fields, written as `record.field`, whose integers are encoded
+        /// as LEB128 rather than at their fixed width.
+        ///
+        /// This must match the list given to the guest bindings.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub varint: Vec<String>,
+
+        /// Whether wasm is given imports to exchange bulk data through buffers
+        /// kept on the host, which the host reaches through a `bulk_buffers`
+        /// trait method.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub bulk: bool,
+
+        /// Whether exports are also looked up through the dispatch table wasm
+        /// exports, so they can be called by operation id without naming them.
+        ///
+        /// The guest bindings must be generated with `dispatch_table` too, and
+        /// the module must export its `__indirect_function_table`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub dispatch_table: bool,
+
+        /// Whether generated code asks a `MemoryBudget` before allocating guest
+        /// memory to lower lists and strings into, trapping if it refuses.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub memory_budget: bool,
+
+        /// Derives `PartialEq`, `Eq`, `PartialOrd` and `Ord` on generated types
+        /// whose contents all support them. Types containing floats or handles
+        /// are left as they are.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub derive_ord: bool,
+
+        /// Whether the struct wrapping the exports of each interface gets a
+        /// `check_round_trips` method, which passes arbitrary values of every
+        /// plain data type through the `proptest-echo-{name}` functions of
+        /// guests generated with `proptest`, and checks that they come back
+        /// unchanged.
+        ///
+        /// Exported functions then take their arguments by value, and the
+        /// types they pass derive `PartialEq`. The crate using the bindings
+        /// must depend on `proptest`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub proptest: bool,
+
+        /// Whether the generated host traits must be usable as trait objects.
+        ///
+        /// The traits drop their `Sized` supertrait, and `add_to_linker` and the
+        /// handle tables accept unsized implementations, so the host state can
+        /// hand out a `&mut dyn Trait` chosen at runtime, such as a plugin.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub object_safe: bool,
+
+        /// The thread-safety bounds (`send-sync`, `send` or `local`) placed on
+        /// host traits, their handle types and the state stored by generated
+        /// types.
+        ///
+        /// When unset, host traits are left unbounded while stored state must be
+        /// `Send + Sync`. Closures registered with a `wasmtime::Linker` always
+        /// need both, since wasmtime requires it.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub thread_bounds: Option<ThreadBounds>,
+
+        /// Whether host traits must expose a `TaskScope` owned by the instance,
+        /// through which host functions spawn background work that is cancelled
+        /// when the instance is dropped. Requires the `async` feature of the
+        /// runtime crate.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub task_scope: bool,
+
+        /// Whether host traits follow the conventions of wasmtime's
+        /// component-model `bindgen!` macro.
+        ///
+        /// The trait of each interface is named `Host`, its methods take owned
+        /// arguments and return an `anyhow::Result` whose errors trap, and
+        /// `add_to_linker` keeps its usual signature. Implementations can then be
+        /// moved over to the component model without changes. This can't be
+        /// combined with resources, `custom_error`, `batch` or `chunked`.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub component_compat: bool,
+
+        /// Whether to generate an `{Interface}Async` wrapper around wasm exports
+        /// and their store, whose `async` methods make each call on tokio's
+        /// blocking thread pool. Requires the `tokio` feature of the runtime
+        /// crate.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub spawn_blocking: bool,
+
+        /// Names of exports whose calls through the `spawn_blocking` wrapper
+        /// are coalesced: a call made while an identical one is in flight
+        /// shares its result instead of entering wasm again.
+        ///
+        /// The host decides which calls may be merged through a
+        /// `CoalescePolicy` over the generated `{Interface}Request` enum. The
+        /// parameters of these functions must be hashable, so they can't hold
+        /// floats, records, variants or handles.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub coalesce: Vec<String>,
+
+        /// The depth to which guests may reenter their exports through calls to
+        /// the host, as enforced by the guest generator's `call_depth_limit`.
+        ///
+        /// When set, each interface gets a `configure_stack` function setting
+        /// wasmtime's maximum wasm stack to what that many nested calls need,
+        /// given how deeply the interface's values nest.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub call_depth: Option<u32>,
+
+        /// Pins the generated code to an older version of the ABI, so that it
+        /// keeps working with wasm modules built against it. See
+        /// `wit_parser::abi::ABI_VERSION` for the differences between versions.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub abi_version: Option<u32>,
+
+        /// Name of a record with `message: string` and `context: list<string>`
+        /// fields, through which errors keep the contexts they were given on
+        /// either side of the boundary, innermost first.
+        ///
+        /// The record converts to a chained `anyhow::Error` and can be built back
+        /// from one, and wasm exports returning it as the error of a `result`
+        /// also get a `{name}_chained` method folding traps and errors into one
+        /// `anyhow::Error`. This should match the guest bindings' own option.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub error_context: Option<String>,
+
+        /// Name of a `string` field in which the guest records the source
+        /// location its errors were created at. The chained `anyhow::Error` of
+        /// the error context record then mentions it after the message. This
+        /// should match the guest bindings' own option.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub error_location: Option<String>,
+
+        /// Whether to describe the glue guest bindings generate for each
+        /// function in a `SYMBOLS` constant per interface, with which
+        /// `wit_bindgen_host_wasmtime_rust::symbolicate` rewrites the frames of
+        /// that glue in backtraces into readable descriptions.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub symbols: bool,
+
+        /// Whether host functions pass their results through the
+        /// `wit_bindgen_host_wasmtime_rust::mutate::Mutator` returned by a
+        /// `mutator` trait method before wasm gets them, so that guest test
+        /// suites can be run against hosts corrupting one of them.
+        ///
+        /// Results containing handles are left as they are.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub mutants: bool,
+
+        /// Whether host function calls go through the
+        /// `wit_bindgen_host_wasmtime_rust::fault::FaultInjector` returned by a
+        /// `fault_injector` trait method, which may delay them, fail them with a
+        /// trap, or truncate the lists and strings of their results.
+        ///
+        /// Results containing handles are never truncated.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub faults: bool,
+
+        /// Whether host function calls, and the bytes of the lists and strings
+        /// they transfer, are charged to the `wit_bindgen_host_wasmtime_rust::Quotas`
+        /// returned by a `quotas` trait method, trapping once a function goes
+        /// over its quota.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub quotas: bool,
+
+        /// Whether records, variants, unions, enums and flags get a
+        /// `lowered_size` method returning how many bytes a value takes up in
+        /// the guest's memory once lowered, strings and lists included, so that
+        /// arguments can be checked against limits before calling an export.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub lowered_size: bool,
+
+        /// Whether host function calls are entered, with summaries of their
+        /// arguments and results, into the
+        /// `wit_bindgen_host_wasmtime_rust::call_log::CallLog` returned by a
+        /// `call_log` trait method, which keeps the last ones to show what led to
+        /// a trap.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub call_log: bool,
+
+        /// The most elements a list, or bytes a string, lifted from wasm may
+        /// have. Longer ones trap before anything is copied out of or borrowed
+        /// from the guest's memory.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub max_list_len: Option<u32>,
+
+        /// Budgets of bytes, keyed by interface name, which a single call to or
+        /// from the interface may lift out of guest memory. Lists and strings
+        /// count their size in guest memory, with each element counting at least
+        /// one byte, and a call going over its budget traps with a
+        /// `wit_bindgen_host_wasmtime_rust::LiftBudgetExceeded` before copying
+        /// anything more out.
+        #[cfg_attr(feature = "structopt", structopt(skip))]
+        pub lift_budget: Vec<(String, u64)>,
+
+        /// Gates the imports annotated with `@feature("name")` behind the
+        /// cargo feature of the same name, so that hosts can compile groups
+        /// of them, such as debugging calls, out of some builds.
+        ///
+        /// Both the trait method of an import and its registration in
+        /// `add_to_linker` are gated, so implementations gate theirs alike.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub cfg_features: bool,
+
+        /// Whether the docs of host trait methods show examples of the
+        /// arguments they are called with and the values they return, built
+        /// like any other values of their Rust types.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub doc_examples: bool,
+
+        /// Whether calls to imports annotated with `@transition("from", "to")`
+        /// are checked against the state of the
+        /// `wit_bindgen_host_wasmtime_rust::protocol::Protocol` returned by a
+        /// `protocol` trait method, trapping on those out of order.
+        #[cfg_attr(feature = "structopt", structopt(long))]
+        pub protocol: bool,
+    }
 }
 
-impl Opts {
-    pub fn build(self) -> Wasmtime {
-        let mut r = Wasmtime::new();
-        r.opts = self;
-        r
+impl Opts {
+    pub fn build(self) -> Wasmtime {
+        let mut r = Wasmtime::new();
+        r.opts = self;
+        r
+    }
+}
+
+/// Which auto traits generated code requires of host state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadBounds {
+    /// `Send + Sync`, for state shared between threads.
+    SendSync,
+    /// `Send`, for state moved between threads but never shared.
+    Send,
+    /// No bounds, for single-threaded embedders.
+    Local,
+}
+
+impl ThreadBounds {
+    /// Returns the bounds to append to a list of trait bounds.
+    fn bounds(self) -> &'static str {
+        match self {
+            ThreadBounds::SendSync => " + Send + Sync",
+            ThreadBounds::Send => " + Send",
+            ThreadBounds::Local => "",
+        }
+    }
+}
+
+impl std::str::FromStr for ThreadBounds {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ThreadBounds, String> {
+        match s {
+            "send-sync" => Ok(ThreadBounds::SendSync),
+            "send" => Ok(ThreadBounds::Send),
+            "local" => Ok(ThreadBounds::Local),
+            _ => Err(format!(
+                "unknown thread bounds `{}`, expected `send-sync`, `send` or `local`",
+                s
+            )),
+        }
+    }
+}
+
+enum FunctionRet {
+    /// The function return is normal and needs to extra handling.
+    Normal,
+    /// The function return was wrapped in a `Result` in Rust. The `Ok` variant
+    /// is the actual value that will be lowered, and the `Err`, if present,
+    /// means that a trap has occurred.
+    CustomToTrap,
+    /// The function returns a `Result` in both wasm and in Rust, but the
+    /// Rust error type is a custom error and must be converted to `err`. The
+    /// `ok` variant payload is provided here too.
+    CustomToError { ok: Type, err: String },
+    /// The function returns an `anyhow::Result` wrapping the value to lower,
+    /// as in wasmtime's component bindings. Errors are turned into traps.
+    Anyhow,
+}
+
+impl Wasmtime {
+    pub fn new() -> Wasmtime {
+        Wasmtime::default()
+    }
+
+    fn abi_variant(dir: Direction) -> AbiVariant {
+        // This generator uses a reversed mapping! In the Wasmtime host-side
+        // bindings, we don't use any extra adapter layer between guest wasm
+        // modules and the host. When the guest imports functions using the
+        // `GuestImport` ABI, the host directly implements the `GuestImport`
+        // ABI, even though the host is *exporting* functions. Similarly, when
+        // the guest exports functions using the `GuestExport` ABI, the host
+        // directly imports them with the `GuestExport` ABI, even though the
+        // host is *importing* functions.
+        match dir {
+            Direction::Import => AbiVariant::GuestExport,
+            Direction::Export => AbiVariant::GuestImport,
+        }
+    }
+
+    fn print_intrinsics(&mut self) {
+        if self.needs_raw_mem {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::RawMem;\n");
+        }
+        if self.needs_char_from_i32 {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::char_from_i32;\n");
+        }
+        if self.needs_invalid_variant {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::invalid_variant;\n");
+        }
+        if self.needs_bad_int {
+            self.push_str("use core::convert::TryFrom;\n");
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::bad_int;\n");
+        }
+        if self.needs_validate_flags {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::validate_flags;\n");
+        }
+        if self.needs_le {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::Le;\n");
+        }
+        if self.needs_copy_slice {
+            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::copy_slice;\n");
+        }
+    }
+
+    /// Generates the topic constants and an enum of all events in `iface`
+    /// which can be decoded from a topic and payload.
+    ///
+    /// When wasm imports this interface, the `emit_event` intrinsic that its
+    /// `emit_*` helpers call is also added to the host trait.
+    fn print_events(&mut self, iface: &Interface) {
+        let codec = "wit_bindgen_host_wasmtime_rust::codec";
+        let name = format!("{}Event", iface.name.to_camel_case());
+        let mut cases = Vec::new();
+        for event in iface.events.iter() {
+            let topic = format!("{}_TOPIC", event.name.to_shouty_snake_case());
+            self.push_str(&format!(
+                "/// The topic under which `{}` events are emitted.\n",
+                event.name,
+            ));
+            self.push_str(&format!(
+                "pub const {topic}: u64 = {:#x};\n",
+                iface.event_topic(event),
+            ));
+            cases.push((event.name.to_camel_case(), topic, event));
+        }
+
+        self.push_str(&format!(
+            "/// An event emitted by wasm through the `{}` interface.\n",
+            iface.name,
+        ));
+        self.push_str("#[derive(Clone, Debug)]\n");
+        self.push_str(&format!("pub enum {name} {{\n"));
+        for (case, _, event) in cases.iter() {
+            self.rustdoc(&event.docs);
+            self.push_str(case);
+            if event.payload != Type::Unit {
+                self.push_str("(");
+                self.print_ty(iface, &event.payload, TypeMode::Owned);
+                self.push_str(")");
+            }
+            self.push_str(",\n");
+        }
+        self.push_str("}\n");
+
+        self.push_str(&format!("impl {name} {{\n"));
+        self.push_str("/// Returns the topic this event is emitted under.\n");
+        self.push_str("pub fn topic(&self) -> u64 {\n");
+        self.push_str("match self {\n");
+        for (case, topic, event) in cases.iter() {
+            if event.payload == Type::Unit {
+                self.push_str(&format!("{name}::{case} => {topic},\n"));
+            } else {
+                self.push_str(&format!("{name}::{case}(_) => {topic},\n"));
+            }
+        }
+        self.push_str("}\n");
+        self.push_str("}\n");
+        self.push_str(&format!(
+            "
+                /// Decodes an event from the topic it was emitted under and its
+                /// encoded payload.
+                ///
+                /// Returns `Ok(None)` if `topic` doesn't belong to any event of
+                /// this interface.
+                pub fn decode(
+                    topic: u64,
+                    payload: &[u8],
+                ) -> Result<Option<Self>, {codec}::DecodeError> {{
+                    Ok(Some(match topic {{
+            "
+        ));
+        for (case, topic, event) in cases.iter() {
+            if event.payload == Type::Unit {
+                self.push_str(&format!(
+                    "{topic} => {{
+                        <() as {codec}::Decode>::decode(payload)?;
+                        {name}::{case}
+                    }}\n"
+                ));
+            } else {
+                self.push_str(&format!(
+                    "{topic} => {name}::{case}({codec}::Decode::decode(payload)?),\n"
+                ));
+            }
+        }
+        self.push_str("_ => return Ok(None),\n");
+        self.push_str("}))\n");
+        self.push_str("}\n");
+        self.push_str("}\n");
+
+        if !self.in_import {
+            return;
+        }
+        let mut closure = format!(
+            "move |mut caller: wasmtime::Caller<'_, T>, topic: i64, ptr: i32, len: i32| {{
+                let memory = &get_memory(&mut caller, \"memory\")?;
+                let payload = copy_slice::<u8>(&mut caller, memory, ptr, len, 1)?;
+                let event = {name}::decode(topic as u64, &payload)
+                    .map_err(|e| wasmtime::Trap::new(format!(\"invalid event payload: {{}}\", e)))?
+                    .ok_or_else(|| wasmtime::Trap::new(format!(\"unknown event topic {{:#x}}\", topic)))?;
+                let host = get(caller.data_mut());
+            "
+        );
+        if !self.all_needed_handles.is_empty() {
+            closure.push_str("let (host, _tables) = host;\n");
+        }
+        closure.push_str("host.emit_event(event);\nOk(())\n}");
+        self.needs_get_memory = true;
+        self.needs_copy_slice = true;
+        self.guest_imports
+            .entry(iface.name.to_string())
+            .or_default()
+            .push(Import {
+                name: "emit_event".to_string(),
+                trait_signature: Some(format!(
+                    "/// Called whenever wasm emits one of this interface's events.\n\
+                     fn emit_event(&mut self, event: {name})"
+                )),
+                trait_call: "emit_event(event)".to_string(),
+                closure,
+                cfg: String::new(),
+                group: None,
+            });
+    }
+
+    /// Returns the types which are decoded from bytes rather than lifted
+    /// through the canonical ABI.
+    fn encoded_types(&self, iface: &Interface) -> Vec<Type> {
+        let (batched, chunked) = if self.in_import {
+            (
+                batched_functions(iface, &self.opts.batch),
+                if self.opts.chunked {
+                    chunked_functions(iface)
+                } else {
+                    Vec::new()
+                },
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        iface
+            .events
+            .iter()
+            .map(|e| e.payload)
+            .chain(
+                batched
+                    .iter()
+                    .map(|(_, func)| *func)
+                    .chain(chunked.iter().map(|(_, func, _)| *func))
+                    .flat_map(|func| func.params.iter().map(|(_, ty)| *ty)),
+            )
+            .chain(chunked.iter().map(|(_, _, element)| *element))
+            .collect()
+    }
+
+    /// Generates code decoding the parameters of `func` from `input`, and
+    /// returns it along with an expression calling the host trait with them.
+    ///
+    /// The expression evaluates to the function's result, returning a trap
+    /// if the host reports a custom error.
+    fn decoded_call(&mut self, iface: &Interface, func: &Function) -> (String, String) {
+        let codec = "wit_bindgen_host_wasmtime_rust::codec";
+        let mut decls = String::new();
+        let mut args = Vec::new();
+        for (i, (_, ty)) in func.params.iter().enumerate() {
+            let prev = mem::take(&mut self.src);
+            self.print_ty(iface, ty, TypeMode::Owned);
+            let ty_name = String::from(mem::replace(&mut self.src, prev));
+            decls.push_str(&format!(
+                "let param{i}: {ty_name} = {codec}::Decode::decode_from(&mut input).map_err(invalid)?;\n"
+            ));
+            if encoded_param_by_ref(iface, ty) == Some(true) {
+                args.push(format!("&param{i}"));
+            } else {
+                args.push(format!("param{i}"));
+            }
+        }
+        let call = format!("host.{}({})", func.name.to_snake_case(), args.join(", "));
+        let call = match self.classify_fn_ret(iface, func) {
+            FunctionRet::Normal => call,
+            _ => format!(
+                "match {call} {{
+                    Ok(val) => val,
+                    Err(e) => return Err(host.error_to_trap(e)),
+                }}"
+            ),
+        };
+        (decls, call)
+    }
+
+    /// Adds the `flush_batch` import, which decodes the calls that wasm
+    /// queued into a batch and performs each of them on the host trait.
+    fn print_batch(&mut self, iface: &Interface) {
+        let codec = "wit_bindgen_host_wasmtime_rust::codec";
+        let mut closure = String::from(
+            "move |mut caller: wasmtime::Caller<'_, T>, ptr: i32, len: i32| {
+                let memory = &get_memory(&mut caller, \"memory\")?;
+                let calls = copy_slice::<u8>(&mut caller, memory, ptr, len, 1)?;
+                let host = get(caller.data_mut());
+            ",
+        );
+        if !self.all_needed_handles.is_empty() {
+            closure.push_str("let (host, _tables) = host;\n");
+        }
+        closure.push_str(&format!(
+            "
+                let invalid = |e: {codec}::DecodeError| {{
+                    wasmtime::Trap::new(format!(\"invalid batched call: {{}}\", e))
+                }};
+                let mut input = &calls[..];
+                while !input.is_empty() {{
+                    match <u32 as {codec}::Decode>::decode_from(&mut input).map_err(invalid)? {{
+            "
+        ));
+        for (index, func) in batched_functions(iface, &self.opts.batch) {
+            let (decls, call) = self.decoded_call(iface, func);
+            closure.push_str(&format!("{index} => {{\n{decls}{call};\n}}\n"));
+        }
+        closure.push_str(
+            "index => {
+                    return Err(wasmtime::Trap::new(format!(
+                        \"function {} cannot be called in a batch\",
+                        index
+                    )))
+                }
+            }
+            }
+            Ok(())
+            }",
+        );
+        self.needs_get_memory = true;
+        self.needs_copy_slice = true;
+        self.guest_imports
+            .entry(iface.name.to_string())
+            .or_default()
+            .push(Import {
+                name: "flush_batch".to_string(),
+                trait_signature: None,
+                trait_call: String::new(),
+                closure,
+                cfg: String::new(),
+                group: None,
+            });
+    }
+
+    /// Adds the imports through which wasm starts chunked calls and reads
+    /// their results, which are kept in a table shared by the imports until
+    /// they have been read in full or released.
+    fn print_chunked(&mut self, iface: &Interface) {
+        let codec = "wit_bindgen_host_wasmtime_rust::codec";
+        let mut call = String::from(
+            "{
+                let chunked_results = chunked_results.clone();
+                move |mut caller: wasmtime::Caller<'_, T>, index: i32, ptr: i32, len: i32, buf: i32, buf_len: i32| {
+                    let memory = &get_memory(&mut caller, \"memory\")?;
+                    let args = copy_slice::<u8>(&mut caller, memory, ptr, len, 1)?;
+                    let host = get(caller.data_mut());
+            ",
+        );
+        if !self.all_needed_handles.is_empty() {
+            call.push_str("let (host, _tables) = host;\n");
+        }
+        call.push_str(&format!(
+            "
+                let invalid = |e: {codec}::DecodeError| {{
+                    wasmtime::Trap::new(format!(\"invalid chunked call: {{}}\", e))
+                }};
+                let mut input = &args[..];
+                let mut bytes = Vec::new();
+                match index {{
+            "
+        ));
+        for (index, func, _) in chunked_functions(iface) {
+            let (decls, result) = self.decoded_call(iface, func);
+            call.push_str(&format!(
+                "{index} => {{
+                    {decls}
+                    for element in {result}.iter() {{
+                        {codec}::Encode::encode_to(element, &mut bytes);
+                    }}
+                }}\n"
+            ));
+        }
+        call.push_str(&format!(
+            "
+                        index => {{
+                            return Err(wasmtime::Trap::new(format!(
+                                \"function {{}} cannot be called in chunks\",
+                                index
+                            )))
+                        }}
+                    }}
+                    if !input.is_empty() {{
+                        return Err(invalid({codec}::DecodeError::TrailingBytes(input.len())));
+                    }}
+                    if bytes.len() <= buf_len as u32 as usize {{
+                        memory
+                            .write(&mut caller, buf as u32 as usize, &bytes)
+                            .map_err(|_| wasmtime::Trap::new(\"out of bounds write\"))?;
+                        return Ok(-1 - bytes.len() as i32);
+                    }}
+                    Ok(chunked_results.lock().unwrap().insert(bytes) as i32)
+                }}
+            }}"
+        ));
+        let read = String::from(
+            "{
+                let chunked_results = chunked_results.clone();
+                move |mut caller: wasmtime::Caller<'_, T>, handle: i32, ptr: i32, len: i32| {
+                    let memory = &get_memory(&mut caller, \"memory\")?;
+                    let chunk = chunked_results
+                        .lock()
+                        .unwrap()
+                        .read(handle as u32, len as u32 as usize)?;
+                    memory
+                        .write(&mut caller, ptr as u32 as usize, &chunk)
+                        .map_err(|_| wasmtime::Trap::new(\"out of bounds write\"))?;
+                    Ok(chunk.len() as i32)
+                }
+            }",
+        );
+        let release = String::from(
+            "{
+                let chunked_results = chunked_results.clone();
+                move |_caller: wasmtime::Caller<'_, T>, handle: i32| {
+                    chunked_results.lock().unwrap().release(handle as u32)
+                }
+            }",
+        );
+        self.needs_get_memory = true;
+        self.needs_copy_slice = true;
+        self.needs_chunked_results = true;
+        let imports = self
+            .guest_imports
+            .entry(iface.name.to_string())
+            .or_default();
+        for (name, closure) in [
+            ("chunked_call", call),
+            ("chunked_read", read),
+            ("chunked_release", release),
+        ] {
+            imports.push(Import {
+                name: name.to_string(),
+                trait_signature: None,
+                trait_call: String::new(),
+                closure,
+                cfg: String::new(),
+                group: None,
+            });
+        }
+    }
+
+    /// Adds the import through which wasm registers a string once, getting
+    /// back the id it passes to interned functions in its place.
+    fn print_interned(&mut self, iface: &Interface) {
+        let closure = String::from(
+            "{
+                let interned_strings = interned_strings.clone();
+                move |mut caller: wasmtime::Caller<'_, T>, ptr: i32, len: i32| {
+                    let memory = &get_memory(&mut caller, \"memory\")?;
+                    let bytes = copy_slice::<u8>(&mut caller, memory, ptr, len, 1)?;
+                    let string = String::from_utf8(bytes)
+                        .map_err(|_| wasmtime::Trap::new(\"invalid utf-8 in interned string\"))?;
+                    Ok(interned_strings.lock().unwrap().insert(string)? as i32)
+                }
+            }",
+        );
+        self.needs_get_memory = true;
+        self.needs_copy_slice = true;
+        self.needs_interned_strings = true;
+        self.guest_imports
+            .entry(iface.name.to_string())
+            .or_default()
+            .push(Import {
+                name: "intern_string".to_string(),
+                trait_signature: None,
+                trait_call: String::new(),
+                closure,
+                cfg: String::new(),
+                group: None,
+            });
+    }
+
+    /// Adds the imports through which wasm creates, fills, reads and releases
+    /// the bulk buffers returned by the host's `bulk_buffers`.
+    fn print_bulk(&mut self, iface: &Interface) {
+        let host = if !self.all_needed_handles.is_empty() {
+            "get(data).0"
+        } else {
+            "get(data)"
+        };
+        let with_memory = |params: &str, body: &str| {
+            format!(
+                "move |mut caller: wasmtime::Caller<'_, T>, {params}| {{
+                    let memory = &get_memory(&mut caller, \"memory\")?;
+                    let (mem, data) = memory.data_and_store_mut(&mut caller);
+                    {host}.bulk_buffers().{body}
+                }}"
+            )
+        };
+        let imports = [
+            (
+                "bulk_create",
+                format!(
+                    "move |mut caller: wasmtime::Caller<'_, T>, len: i32| {{
+                        let data = caller.data_mut();
+                        Ok({host}.bulk_buffers().insert(vec![0; len as u32 as usize]) as i32)
+                    }}"
+                ),
+            ),
+            (
+                "bulk_len",
+                format!(
+                    "move |mut caller: wasmtime::Caller<'_, T>, handle: i32| {{
+                        let data = caller.data_mut();
+                        Ok({host}.bulk_buffers().get(handle as u32)?.len() as i32)
+                    }}"
+                ),
+            ),
+            (
+                "bulk_write",
+                with_memory(
+                    "handle: i32, offset: i32, ptr: i32, len: i32",
+                    "write_from(mem, handle as u32, offset as u32, ptr as u32, len as u32)",
+                ),
+            ),
+            (
+                "bulk_read",
+                with_memory(
+                    "handle: i32, offset: i32, ptr: i32, len: i32",
+                    "read_into(mem, handle as u32, offset as u32, ptr as u32, len as u32)
+                        .map(|n| n as i32)",
+                ),
+            ),
+            (
+                "bulk_release",
+                format!(
+                    "move |mut caller: wasmtime::Caller<'_, T>, handle: i32| {{
+                        let data = caller.data_mut();
+                        {host}.bulk_buffers().remove(handle as u32).map(drop)
+                    }}"
+                ),
+            ),
+        ];
+        self.needs_get_memory = true;
+        let entry = self
+            .guest_imports
+            .entry(iface.name.to_string())
+            .or_default();
+        for (name, closure) in imports {
+            entry.push(Import {
+                name: name.to_string(),
+                trait_signature: None,
+                trait_call: String::new(),
+                closure,
+                cfg: String::new(),
+                group: None,
+            });
+        }
+    }
+
+    /// Returns the `#[cfg]` attribute gating the import of `func` behind the
+    /// features it's annotated with, with `cfg_features`.
+    fn cfg_attr(&self, func: &Function) -> String {
+        if !self.opts.cfg_features {
+            return String::new();
+        }
+        let features = func
+            .docs
+            .features()
+            .map(|f| format!("feature = \"{}\"", f))
+            .collect::<Vec<_>>();
+        match features.len() {
+            0 => String::new(),
+            1 => format!("#[cfg({})]\n", features[0]),
+            _ => format!("#[cfg(all({}))]\n", features.join(", ")),
+        }
+    }
+
+    /// Generates the closure passed to a `Linker` for `func`, which lifts its
+    /// arguments, calls the host trait and lowers the result.
+    ///
+    /// With `synthetic`, `func` is the `-interned` or `-spliced` counterpart
+    /// of a function, whose arguments are looked up on the host before
+    /// calling it.
+    fn linker_closure(
+        &mut self,
+        iface: &Interface,
+        func: &Function,
+        synthetic: Option<SyntheticCall>,
+    ) -> String {
+        let prev = mem::take(&mut self.src);
+
+        // Generate the closure that's passed to a `Linker`, the final piece of
+        // codegen here.
+        let sig = iface.wasm_signature(AbiVariant::GuestImport, func);
+        let params = (0..sig.params.len())
+            .map(|i| format!("arg{}", i))
+            .collect::<Vec<_>>();
+        let name = synthetic.as_ref().map_or(&func.name, |s| &s.name);
+        let quota = self.opts.quotas.then(|| format!("{}.{}", iface.name, name));
+        let lift_budget = self.lift_budget(iface, name);
+        let mut f = FunctionBindgen::new(self, params);
+        f.quota = quota;
+        f.lift_budget = lift_budget;
+        f.synthetic = synthetic;
+        iface.call(
+            AbiVariant::GuestImport,
+            LiftLower::LiftArgsLowerResults,
+            func,
+            &mut f,
+        );
+        let FunctionBindgen {
+            src,
+            cleanup,
+            needs_borrow_checker,
+            needs_memory,
+            needs_buffer_transaction,
+            needs_functions,
+            closures,
+            lift_budget,
+            charges_lift,
+            ..
+        } = f;
+        assert!(cleanup.is_none());
+        assert!(!needs_buffer_transaction);
+
+        // Generate the closure that's passed to a `Linker`, the final piece of
+        // codegen here.
+        self.src
+            .push_str("move |mut caller: wasmtime::Caller<'_, T>");
+        for (i, param) in sig.params.iter().enumerate() {
+            let arg = format!("arg{}", i);
+            self.src.push_str(",");
+            self.src.push_str(&arg);
+            self.src.push_str(":");
+            self.wasm_type(*param);
+        }
+        self.src.push_str("| {\n");
+
+        if self.opts.tracing {
+            self.src.push_str(&format!(
+                "
+                    let span = wit_bindgen_host_wasmtime_rust::tracing::span!(
+                        wit_bindgen_host_wasmtime_rust::tracing::Level::TRACE,
+                        \"wit-bindgen abi\",
+                        module = \"{}\",
+                        function = \"{}\",
+                    );
+                    let _enter = span.enter();
+                ",
+                iface.name, func.name,
+            ));
+        }
+        self.src.push_str(&closures);
+
+        for (name, func) in needs_functions {
+            self.src.push_str(&format!(
+                "
+                    let func = get_func(&mut caller, \"{name}\")?;
+                    let func_{name} = func.typed::<{cvt}, _>(&caller)?;
+                ",
+                name = name,
+                cvt = func.cvt(),
+            ));
+            self.needs_get_func = true;
+        }
+
+        if needs_memory || needs_borrow_checker {
+            self.src
+                .push_str("let memory = &get_memory(&mut caller, \"memory\")?;\n");
+            self.needs_get_memory = true;
+        }
+
+        if needs_borrow_checker {
+            self.src.push_str(
+                "let (mem, data) = memory.data_and_store_mut(&mut caller);
+                let mut _bc = wit_bindgen_host_wasmtime_rust::BorrowChecker::new(mem);
+                let host = get(data);\n",
+            );
+        } else {
+            self.src.push_str("let host = get(caller.data_mut());\n");
+        }
+
+        if !self.all_needed_handles.is_empty() {
+            self.src.push_str("let (host, _tables) = host;\n");
+        }
+
+        if charges_lift {
+            self.print_lift_budget(lift_budget.unwrap());
+        }
+        self.src.push_str(&String::from(src));
+
+        self.src.push_str("}");
+        mem::replace(&mut self.src, prev).into()
+    }
+
+    /// Returns the `interface.function` name of calls to `name` and the bytes
+    /// they may lift, if `iface` has a lift budget.
+    fn lift_budget(&self, iface: &Interface, name: &str) -> Option<(String, u64)> {
+        self.opts
+            .lift_budget
+            .iter()
+            .find(|(i, _)| *i == iface.name)
+            .map(|(_, limit)| (format!("{}.{}", iface.name, name), *limit))
+    }
+
+    /// Starts the lift budget of a call, which `charge_lift` charges.
+    fn print_lift_budget(&mut self, (function, limit): (String, u64)) {
+        self.src.push_str(&format!(
+            "let mut lift_budget = wit_bindgen_host_wasmtime_rust::LiftBudget::new(\"{function}\", {limit});\n"
+        ));
+    }
+
+    /// Generates the lookup of exports by operation id, which is the index of
+    /// the function in the interface.
+    fn print_dispatch(&mut self, iface: &Interface) {
+        let operations = iface
+            .functions
+            .iter()
+            .map(|func| format!("\"{}\",", func.name))
+            .collect::<String>();
+        self.push_str(&format!(
+            "
+                /// Names of the operations in the dispatch table, indexed by
+                /// operation id.
+                pub const OPERATIONS: &'static [&'static str] = &[{operations}];
+
+                /// Returns the export for the operation `op` without looking
+                /// it up by name.
+                pub fn dispatch(&self, op: u32) -> Option<&wasmtime::Func> {{
+                    self.dispatch_table.get(op as usize)
+                }}
+
+                /// Calls the export for the operation `op` with the core wasm
+                /// `params`, writing its core wasm results to `results`.
+                pub fn invoke(
+                    &self,
+                    mut store: impl wasmtime::AsContextMut<Data = T>,
+                    op: u32,
+                    params: &[wasmtime::Val],
+                    results: &mut [wasmtime::Val],
+                ) -> anyhow::Result<()> {{
+                    let func = self
+                        .dispatch(op)
+                        .ok_or_else(|| anyhow::anyhow!(\"unknown operation {{}}\", op))?;
+                    func.call(&mut store, params, results)
+                }}
+            "
+        ));
+    }
+
+    fn print_async_exports(&mut self, name: &str, exports: &Exports) {
+        if self.opts.thread_bounds == Some(ThreadBounds::Local) {
+            panic!("`spawn_blocking` requires exports which can be sent to another thread");
+        }
+        let coalesce = !exports.requests.is_empty();
+        if coalesce {
+            self.push_str(&format!(
+                "
+                    /// A call made through [`{name}Async`] which may share the
+                    /// result of an identical call already in flight, as
+                    /// decided by the [`CoalescePolicy`](wit_bindgen_host_wasmtime_rust::coalesce::CoalescePolicy)
+                    /// given to [`{name}Async::with_coalesce_policy`].
+                    #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+                    pub enum {name}Request {{
+                        {requests}
+                    }}
+
+                    #[derive(Clone)]
+                    enum {name}Response {{
+                        {responses}
+                    }}
+
+                    type {name}Coalescer = wit_bindgen_host_wasmtime_rust::coalesce::Coalescer<
+                        {name}Request,
+                        Result<{name}Response, wasmtime::Trap>,
+                        Box<dyn Fn(&{name}Request) -> bool + Send + Sync>,
+                    >;
+                ",
+                requests = exports.requests.concat(),
+                responses = exports.responses.concat(),
+            ));
+        }
+        let (field, clone, new, unwrap) = if coalesce {
+            (
+                format!("coalescer: std::sync::Arc<{name}Coalescer>,"),
+                "coalescer: self.coalescer.clone(),",
+                "Self::with_coalesce_policy(exports, store, wit_bindgen_host_wasmtime_rust::coalesce::AlwaysCoalesce)",
+                "let Self { inner, coalescer } = self;",
+            )
+        } else {
+            (
+                String::new(),
+                "",
+                "Self { inner: std::sync::Arc::new(std::sync::Mutex::new((exports, store))) }",
+                "let Self { inner } = self;",
+            )
+        };
+        self.push_str(&format!(
+            "
+                /// The wasm exports together with their store, calling into
+                /// wasm on tokio's blocking thread pool so that async hosts
+                /// don't stall their executor while wasm runs.
+                ///
+                /// Calls are made one at a time. Functions taking borrowed
+                /// records or variants have no method of their own and are
+                /// called through [`{name}Async::call`].
+                pub struct {name}Async<T> {{
+                    inner: std::sync::Arc<std::sync::Mutex<({name}<T>, wasmtime::Store<T>)>>,
+                    {field}
+                }}
+
+                impl<T> Clone for {name}Async<T> {{
+                    fn clone(&self) -> Self {{
+                        Self {{
+                            inner: self.inner.clone(),
+                            {clone}
+                        }}
+                    }}
+                }}
+
+                impl<T: Send + 'static> {name}Async<T> {{
+                    /// Wraps `exports`, which were instantiated in `store`.
+                    pub fn new(exports: {name}<T>, store: wasmtime::Store<T>) -> Self {{
+                        {new}
+                    }}
+
+                    /// Returns the exports and their store, or `self` back if
+                    /// it has been cloned or a call is still running.
+                    pub fn into_inner(self) -> Result<({name}<T>, wasmtime::Store<T>), Self> {{
+                        {unwrap}
+                        match std::sync::Arc::try_unwrap(inner) {{
+                            Ok(inner) => Ok(inner.into_inner().unwrap_or_else(|e| e.into_inner())),
+                            Err(inner) => Err(Self {{ inner, {reclaim} }}),
+                        }}
+                    }}
+
+                    /// Runs `f` with the exports and their store on the
+                    /// blocking thread pool.
+                    pub async fn call<R, F>(&self, f: F) -> Result<R, wasmtime::Trap>
+                    where
+                        F: FnOnce(&{name}<T>, &mut wasmtime::Store<T>) -> Result<R, wasmtime::Trap>
+                            + Send
+                            + 'static,
+                        R: Send + 'static,
+                    {{
+                        let inner = self.inner.clone();
+                        wit_bindgen_host_wasmtime_rust::tokio::task::spawn_blocking(move || {{
+                            let mut inner = inner.lock().unwrap_or_else(|e| e.into_inner());
+                            let (exports, store) = &mut *inner;
+                            f(exports, store)
+                        }})
+                        .await
+                        .map_err(|e| wasmtime::Trap::new(format!(\"wasm call failed: {{}}\", e)))?
+                    }}
+            ",
+            name = name,
+            reclaim = if coalesce { "coalescer" } else { "" },
+        ));
+        if coalesce {
+            self.push_str(&format!(
+                "
+                    /// Wraps `exports`, which were instantiated in `store`,
+                    /// only letting calls share the result of an identical
+                    /// call in flight when `policy` allows it.
+                    pub fn with_coalesce_policy(
+                        exports: {name}<T>,
+                        store: wasmtime::Store<T>,
+                        policy: impl wit_bindgen_host_wasmtime_rust::coalesce::CoalescePolicy<{name}Request>
+                            + Send
+                            + Sync
+                            + 'static,
+                    ) -> Self {{
+                        let policy = move |request: &{name}Request| policy.coalesce(request);
+                        Self {{
+                            inner: std::sync::Arc::new(std::sync::Mutex::new((exports, store))),
+                            coalescer: std::sync::Arc::new({name}Coalescer::with_policy(Box::new(policy))),
+                        }}
+                    }}
+                "
+            ));
+        }
+        for func in exports.async_funcs.iter() {
+            self.push_str(func);
+        }
+        self.push_str("}\n");
+    }
+
+    /// Generates the method of the `spawn_blocking` wrapper calling `func`.
+    ///
+    /// The wrapper's methods take owned arguments so they can be moved to the
+    /// blocking thread. Functions whose arguments can't simply be borrowed
+    /// from their owned form get no method, and are called through `call`.
+    fn async_export(&mut self, iface: &Interface, func: &Function) -> Option<String> {
+        let mut args = Vec::new();
+        for (name, ty) in func.params.iter() {
+            let by_ref = self.borrowed_from_owned(iface, ty)?;
+            let name = to_rust_ident(name);
+            args.push(if by_ref { format!("&{}", name) } else { name });
+        }
+
+        let prev = mem::take(&mut self.src);
+        let sig = FnSig {
+            async_: true,
+            self_arg: Some("&self".to_string()),
+            ..Default::default()
+        };
+        self.print_docs_and_params(iface, func, TypeMode::Owned, &sig);
+        self.push_str("-> Result<");
+        self.print_ty(iface, &func.result, TypeMode::Owned);
+        self.push_str(", wasmtime::Trap> {\n");
+        let call = format!(
+            "self.call(move |exports, store| exports.{}(store, {})).await",
+            to_rust_ident(&func.name),
+            args.join(", "),
+        );
+        if self.opts.coalesce.contains(&func.name) {
+            self.coalesced_call(iface, func, &call);
+        } else {
+            self.push_str(&call);
+            self.push_str("\n");
+        }
+        self.push_str("}\n");
+        Some(mem::replace(&mut self.src, prev).into())
+    }
+
+    /// Generates the body of the `spawn_blocking` wrapper's method calling
+    /// `func` through `call`, sharing the result of an identical call in
+    /// flight, and records the cases of the request and response enums.
+    fn coalesced_call(&mut self, iface: &Interface, func: &Function, call: &str) {
+        let case = func.name.to_camel_case();
+        let mut fields = Vec::new();
+        let mut clones = Vec::new();
+        for (name, ty) in func.params.iter() {
+            if !hashable(iface, ty) {
+                panic!(
+                    "`coalesce` needs the parameters of `{}` to be hashable, but `{}` isn't",
+                    func.name, name
+                );
+            }
+            let name = to_rust_ident(name);
+            let prev = mem::take(&mut self.src);
+            self.print_ty(iface, ty, TypeMode::Owned);
+            let ty = String::from(mem::replace(&mut self.src, prev));
+            fields.push(format!("{}: {}", name, ty));
+            clones.push(format!("{0}: {0}.clone()", name));
+        }
+        let prev = mem::take(&mut self.src);
+        self.print_ty(iface, &func.result, TypeMode::Owned);
+        let result = String::from(mem::replace(&mut self.src, prev));
+
+        let name = iface.name.to_camel_case();
+        self.push_str(&format!(
+            "
+                let request = {name}Request::{case} {{ {clones} }};
+                let response = self
+                    .coalescer
+                    .run(request, || async move {{ {call}.map({name}Response::{case}) }})
+                    .await?;
+                match response {{
+                    {name}Response::{case}(result) => Ok(result),
+                    #[allow(unreachable_patterns)]
+                    _ => unreachable!(),
+                }}
+            ",
+            clones = clones.join(", "),
+        ));
+        let exports = self.guest_exports.get_mut(&iface.name).unwrap();
+        exports
+            .requests
+            .push(format!("{} {{ {} }},\n", case, fields.join(", ")));
+        exports.responses.push(format!("{}({}),\n", case, result));
+    }
+
+    /// Returns how a parameter of type `ty` is passed to a wasm export given
+    /// its owned form: `Some(true)` if it's borrowed, `Some(false)` if it's
+    /// passed as is, and `None` if its borrowed form is a different type.
+    fn borrowed_from_owned(&self, iface: &Interface, ty: &Type) -> Option<bool> {
+        match ty {
+            Type::String => Some(true),
+            Type::Handle(_) => None,
+            Type::Id(id) => match &iface.types[*id].kind {
+                TypeDefKind::Type(t) => self.borrowed_from_owned(iface, t),
+                TypeDefKind::List(element) if iface.all_bits_valid(element) => Some(true),
+                _ => {
+                    let info = self.info(*id);
+                    if info.has_list || info.has_handle {
+                        None
+                    } else {
+                        Some(false)
+                    }
+                }
+            },
+            _ => Some(false),
+        }
+    }
+
+    /// Classifies the return value of a function to see if it needs handling
+    /// with respect to the `custom_error` configuration option.
+    /// Converts the error context record `id` to and from chained
+    /// `anyhow::Error`s.
+    fn print_error_context(&mut self, iface: &Interface, id: TypeId) {
+        let info = self.info(id);
+        if !info.result {
+            return;
+        }
+        let name = self.result_name(iface, id);
+        let others = match &iface.types[id].kind {
+            TypeDefKind::Record(r) => r.fields.len() > 2,
+            _ => unreachable!(),
+        };
+        let message = match &self.opts.error_location {
+            Some(field) if has_error_location(iface, id, Some(field)) => {
+                format!(
+                    "format!(\"{{}} (at {{}})\", err.message, err.{})",
+                    to_rust_ident(field)
+                )
+            }
+            _ => "err.message".to_string(),
+        };
+        self.src.push_str(&format!(
+            "
+                impl From<{name}> for anyhow::Error {{
+                    fn from(err: {name}) -> anyhow::Error {{
+                        wit_bindgen_host_wasmtime_rust::context::chain({message}, err.context)
+                    }}
+                }}
+            "
+        ));
+        if !others {
+            self.src.push_str(&format!(
+                "
+                    impl From<&anyhow::Error> for {name} {{
+                        fn from(err: &anyhow::Error) -> {name} {{
+                            let (message, context) =
+                                wit_bindgen_host_wasmtime_rust::context::unchain(err);
+                            {name} {{ message, context }}
+                        }}
+                    }}
+                "
+            ));
+        }
     }
-}
 
-enum FunctionRet {
-    /// The function return is normal and needs to extra handling.
-    Normal,
-    /// The function return was wrapped in a `Result` in Rust. The `Ok` variant
-    /// is the actual value that will be lowered, and the `Err`, if present,
-    /// means that a trap has occurred.
-    CustomToTrap,
-    /// The function returns a `Result` in both wasm and in Rust, but the
-    /// Rust error type is a custom error and must be converted to `err`. The
-    /// `ok` variant payload is provided here too.
-    CustomToError { ok: Type, err: String },
-}
+    /// Generates the `Mutate` impls of the named types host functions can
+    /// return, through which their results are corrupted.
+    fn print_mutate_impls(&mut self, iface: &Interface) {
+        for (id, ty) in iface.types.iter() {
+            let info = self.info(id);
+            if ty.name.is_none() || !info.result || info.has_handle {
+                continue;
+            }
+            let name = self.result_name(iface, id);
+            let mut body = String::new();
+            match &ty.kind {
+                TypeDefKind::Record(record) => {
+                    for field in record.fields.iter() {
+                        body.push_str(&format!(
+                            "self.{}.mutate(mutator);\n",
+                            to_rust_ident(&field.name)
+                        ));
+                    }
+                }
+                TypeDefKind::Flags(flags) => {
+                    for flag in flags.flags.iter() {
+                        body.push_str(&format!(
+                            "if mutator.site(Mutation::FlipBool) {{\n\
+                                 self.toggle(Self::{});\n\
+                             }}\n",
+                            flag.name.to_shouty_snake_case()
+                        ));
+                    }
+                }
+                TypeDefKind::Enum(enum_) => {
+                    let cases = enum_
+                        .cases
+                        .iter()
+                        .map(|c| (c.name.to_camel_case(), &Type::Unit))
+                        .collect::<Vec<_>>();
+                    body.push_str(&self.permuted_cases(&name, &cases));
+                }
+                TypeDefKind::Variant(variant) => {
+                    let cases = variant
+                        .cases
+                        .iter()
+                        .map(|c| (c.name.to_camel_case(), &c.ty))
+                        .collect::<Vec<_>>();
+                    body.push_str(&self.permuted_cases(&name, &cases));
+                }
+                TypeDefKind::Union(union) => {
+                    let names = self.union_case_names(iface, union);
+                    let cases = names
+                        .into_iter()
+                        .zip(union.cases.iter().map(|c| &c.ty))
+                        .collect::<Vec<_>>();
+                    body.push_str(&self.permuted_cases(&name, &cases));
+                }
+                _ => continue,
+            }
+            self.src.push_str(&format!(
+                "
+                    impl wit_bindgen_host_wasmtime_rust::mutate::Mutate for {name} {{
+                        #[allow(unused_imports, unused_variables)]
+                        fn mutate(&mut self, mutator: &wit_bindgen_host_wasmtime_rust::mutate::Mutator) {{
+                            use wit_bindgen_host_wasmtime_rust::mutate::{{Mutate, Mutation}};
+                            {body}
+                        }}
+                    }}
+                "
+            ));
+        }
+    }
 
-impl Wasmtime {
-    pub fn new() -> Wasmtime {
-        Wasmtime::default()
+    /// Returns the `match` by which a value of the enum `name` with `cases`
+    /// may be swapped for the next case with the same payload, and otherwise
+    /// has its payload mutated.
+    fn permuted_cases(&self, name: &str, cases: &[(String, &Type)]) -> String {
+        let mut arms = String::new();
+        for (i, (case, ty)) in cases.iter().enumerate() {
+            let next = cases[i + 1..]
+                .iter()
+                .chain(&cases[..i])
+                .find(|(_, other)| other == ty);
+            let (pattern, payload) = match ty {
+                Type::Unit => (format!("{name}::{case}"), String::new()),
+                _ => (format!("{name}::{case}(e)"), "(e.clone())".to_string()),
+            };
+            if let Some((next, _)) = next {
+                arms.push_str(&format!(
+                    "{pattern} if mutator.site(Mutation::PermuteTag) => \
+                     *self = {name}::{next}{payload},\n"
+                ));
+            }
+            match ty {
+                Type::Unit => arms.push_str(&format!("{pattern} => {{}}\n")),
+                _ => arms.push_str(&format!("{pattern} => e.mutate(mutator),\n")),
+            }
+        }
+        format!("match self {{\n{arms}}}\n")
     }
 
-    fn abi_variant(dir: Direction) -> AbiVariant {
-        // This generator uses a reversed mapping! In the Wasmtime host-side
-        // bindings, we don't use any extra adapter layer between guest wasm
-        // modules and the host. When the guest imports functions using the
-        // `GuestImport` ABI, the host directly implements the `GuestImport`
-        // ABI, even though the host is *exporting* functions. Similarly, when
-        // the guest exports functions using the `GuestExport` ABI, the host
-        // directly imports them with the `GuestExport` ABI, even though the
-        // host is *importing* functions.
-        match dir {
-            Direction::Import => AbiVariant::GuestExport,
-            Direction::Export => AbiVariant::GuestImport,
+    /// Generates the `SYMBOLS` constant describing the glue of the functions
+    /// of `iface` in guest bindings.
+    fn print_symbols(&mut self, iface: &Interface) {
+        let module = iface.name.to_snake_case();
+        let mut functions = String::new();
+        for func in iface.functions.iter() {
+            // Guests call imports through a function of the interface's
+            // module, and enter exports through a shim named after both.
+            let symbol = if self.in_import {
+                let name = to_rust_ident(&func.item_name().to_snake_case());
+                match &func.kind {
+                    FunctionKind::Freestanding => format!("{module}::{name}"),
+                    FunctionKind::Static { resource, .. }
+                    | FunctionKind::Method { resource, .. } => format!(
+                        "{module}::{}::{name}",
+                        iface.resources[*resource].name.to_camel_case()
+                    ),
+                }
+            } else {
+                format!("__wit_bindgen_{module}_{}", func.name.to_snake_case())
+            };
+            functions.push_str(&format!("({:?}, {:?}),\n", func.name, symbol));
         }
+        let direction = if self.in_import { "Import" } else { "Export" };
+        self.src.push_str(&format!(
+            "
+                /// The glue which guest bindings generate for the functions of
+                /// this interface, to describe its frames in backtraces.
+                pub const SYMBOLS: wit_bindgen_host_wasmtime_rust::symbolicate::Symbols =
+                    wit_bindgen_host_wasmtime_rust::symbolicate::Symbols {{
+                        interface: {:?},
+                        direction: wit_bindgen_host_wasmtime_rust::symbolicate::Direction::{direction},
+                        functions: &[{functions}],
+                    }};
+            ",
+            iface.name,
+        ));
     }
 
-    fn print_intrinsics(&mut self) {
-        if self.needs_raw_mem {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::RawMem;\n");
+    /// Returns how the methods calling wasm exports take their arguments.
+    fn export_param_mode(&self) -> TypeMode {
+        if self.opts.proptest {
+            TypeMode::Owned
+        } else {
+            TypeMode::AllBorrowed("'_")
         }
-        if self.needs_char_from_i32 {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::char_from_i32;\n");
+    }
+
+    /// Generates the methods calling the `proptest-echo-{name}` exports of
+    /// `iface`, strategies for the values of their types, and the
+    /// `check_round_trips` method passing those values through them.
+    fn print_proptests(&mut self, iface: &Interface) {
+        let echoes = self.proptest_echoes(iface);
+        if echoes.is_empty() {
+            return;
         }
-        if self.needs_invalid_variant {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::invalid_variant;\n");
+        let mut strategies = String::new();
+        let mut checks = String::new();
+        for (id, echo) in echoes.iter() {
+            self.import(iface, echo);
+            let wit_name = iface.types[*id].name.as_ref().unwrap();
+            let snake = wit_name.to_snake_case();
+            let name = self.proptest_type_name(iface, *id);
+            let strategy = self.proptest_def_strategy(iface, *id);
+            strategies.push_str(&format!(
+                "
+                    pub(super) fn arb_{snake}() -> impl Strategy<Value = {name}> {{
+                        {strategy}
+                    }}
+                "
+            ));
+            checks.push_str(&format!(
+                "
+                    runner
+                        .run(&proptests::arb_{snake}(), |value| {{
+                            let echoed = self
+                                .{echo}(&mut *caller.borrow_mut(), value.clone())
+                                .map_err(|trap| {{
+                                    proptest::test_runner::TestCaseError::fail(trap.to_string())
+                                }})?;
+                            proptest::prop_assert_eq!(echoed, value);
+                            Ok(())
+                        }})
+                        .map_err(|e| anyhow::anyhow!(\"`{wit_name}` doesn't round-trip: {{}}\", e))?;
+                ",
+                echo = to_rust_ident(&echo.name),
+            ));
         }
-        if self.needs_bad_int {
-            self.push_str("use core::convert::TryFrom;\n");
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::bad_int;\n");
+        self.src.push_str(&format!(
+            "
+                mod proptests {{
+                    use super::*;
+                    use proptest::prelude::*;
+
+                    {strategies}
+                }}
+            "
+        ));
+        let check = format!(
+            "
+                /// Checks that arbitrary values of each plain data type of the
+                /// interface come back unchanged from the `proptest-echo-*`
+                /// exports of guests generated with `proptest`, which lift them
+                /// and lower them again.
+                pub fn check_round_trips(
+                    &self,
+                    mut caller: impl wasmtime::AsContextMut<Data = T>,
+                ) -> anyhow::Result<()> {{
+                    let caller = std::cell::RefCell::new(caller.as_context_mut());
+                    let mut runner = proptest::test_runner::TestRunner::new(
+                        proptest::test_runner::Config {{
+                            failure_persistence: None,
+                            ..Default::default()
+                        }},
+                    );
+                    {checks}
+                    Ok(())
+                }}
+            "
+        );
+        self.guest_exports
+            .get_mut(&iface.name)
+            .unwrap()
+            .funcs
+            .push(check);
+    }
+
+    fn proptest_type_name(&self, iface: &Interface, id: TypeId) -> String {
+        match &iface.types[id].kind {
+            TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => {
+                self.type_ident(iface.types[id].name.as_ref().unwrap())
+            }
+            _ => self.result_name(iface, id),
         }
-        if self.needs_validate_flags {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::validate_flags;\n");
+    }
+
+    /// Returns an expression of a strategy generating values of `ty`, which
+    /// refers to the `arb_*` function of named types.
+    fn proptest_strategy(&self, iface: &Interface, ty: &Type) -> String {
+        match ty {
+            Type::Unit => "Just(())".to_string(),
+            Type::Bool => "any::<bool>()".to_string(),
+            Type::U8 => "any::<u8>()".to_string(),
+            Type::U16 => "any::<u16>()".to_string(),
+            Type::U32 => "any::<u32>()".to_string(),
+            Type::U64 => "any::<u64>()".to_string(),
+            Type::S8 => "any::<i8>()".to_string(),
+            Type::S16 => "any::<i16>()".to_string(),
+            Type::S32 => "any::<i32>()".to_string(),
+            Type::S64 => "any::<i64>()".to_string(),
+            // Without NaNs, which don't equal themselves.
+            Type::Float32 => "any::<f32>()".to_string(),
+            Type::Float64 => "any::<f64>()".to_string(),
+            Type::Char => "any::<char>()".to_string(),
+            Type::String => "any::<String>()".to_string(),
+            Type::Handle(_) => unreachable!(),
+            Type::Id(id) => match &iface.types[*id].name {
+                Some(name) => format!("arb_{}()", name.to_snake_case()),
+                None => self.proptest_def_strategy(iface, *id),
+            },
         }
-        if self.needs_le {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::Le;\n");
+    }
+
+    fn proptest_def_strategy(&self, iface: &Interface, id: TypeId) -> String {
+        let one_of = |arms: Vec<String>| format!("prop_oneof![{}]", arms.join(", "));
+        match &iface.types[id].kind {
+            TypeDefKind::Type(t) => self.proptest_strategy(iface, t),
+            TypeDefKind::List(t) => format!(
+                "proptest::collection::vec({}, 0..8)",
+                self.proptest_strategy(iface, t)
+            ),
+            TypeDefKind::Option(t) => {
+                format!("proptest::option::of({})", self.proptest_strategy(iface, t))
+            }
+            TypeDefKind::Result(r) => one_of(vec![
+                format!("{}.prop_map(Ok)", self.proptest_strategy(iface, &r.ok)),
+                format!("{}.prop_map(Err)", self.proptest_strategy(iface, &r.err)),
+            ]),
+            TypeDefKind::Tuple(t) => {
+                let (strategy, pattern) = self.proptest_nested(iface, t.types.iter());
+                let values = (0..t.types.len())
+                    .map(|i| format!("v{i},"))
+                    .collect::<String>();
+                format!("{strategy}.prop_map(|{pattern}| ({values}))")
+            }
+            TypeDefKind::Record(r) => {
+                let name = self.proptest_type_name(iface, id);
+                let (strategy, pattern) =
+                    self.proptest_nested(iface, r.fields.iter().map(|f| &f.ty));
+                let fields = r
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(i, f)| format!("{}: v{i}, ", to_rust_ident(&f.name)))
+                    .collect::<String>();
+                format!("{strategy}.prop_map(|{pattern}| {name} {{ {fields} }})")
+            }
+            TypeDefKind::Flags(f) => format!(
+                "any::<{}>().prop_map({}::from_bits_truncate)",
+                RustFlagsRepr::new(f),
+                self.proptest_type_name(iface, id),
+            ),
+            TypeDefKind::Variant(v) => {
+                let name = self.proptest_type_name(iface, id);
+                let wit_name = iface.types[id].name.as_deref().unwrap();
+                let cases = v
+                    .cases
+                    .iter()
+                    .map(|c| (self.case_ident(wit_name, &c.name), &c.ty));
+                one_of(self.proptest_cases(iface, &name, cases))
+            }
+            TypeDefKind::Union(u) => {
+                let name = self.proptest_type_name(iface, id);
+                let names = self.union_case_names(iface, u);
+                let cases = names.into_iter().zip(u.cases.iter().map(|c| &c.ty));
+                one_of(self.proptest_cases(iface, &name, cases))
+            }
+            TypeDefKind::Enum(e) => {
+                let name = self.proptest_type_name(iface, id);
+                let wit_name = iface.types[id].name.as_deref().unwrap();
+                let cases = e
+                    .cases
+                    .iter()
+                    .map(|c| (self.case_ident(wit_name, &c.name), &Type::Unit));
+                one_of(self.proptest_cases(iface, &name, cases))
+            }
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => unreachable!(),
         }
-        if self.needs_copy_slice {
-            self.push_str("use wit_bindgen_host_wasmtime_rust::rt::copy_slice;\n");
+    }
+
+    fn proptest_cases<'a>(
+        &self,
+        iface: &Interface,
+        name: &str,
+        cases: impl Iterator<Item = (String, &'a Type)>,
+    ) -> Vec<String> {
+        cases
+            .map(|(case, ty)| match ty {
+                Type::Unit => format!("proptest::strategy::LazyJust::new(|| {name}::{case})"),
+                ty => format!(
+                    "{}.prop_map({name}::{case})",
+                    self.proptest_strategy(iface, ty)
+                ),
+            })
+            .collect()
+    }
+
+    /// Returns a strategy generating the values of `tys` as nested pairs,
+    /// which works for any number of them, along with a pattern binding them
+    /// to `v0`, `v1` and so on.
+    fn proptest_nested<'a>(
+        &self,
+        iface: &Interface,
+        tys: impl DoubleEndedIterator<Item = &'a Type> + ExactSizeIterator,
+    ) -> (String, String) {
+        let mut strategy = "Just(())".to_string();
+        let mut pattern = "()".to_string();
+        for (i, ty) in tys.enumerate().rev() {
+            strategy = format!("({}, {strategy})", self.proptest_strategy(iface, ty));
+            pattern = format!("(v{i}, {pattern})");
         }
+        (strategy, pattern)
+    }
+
+    /// Generates a method calling the wasm export `func`, whose `result`
+    /// fails with the error context record, which merges its traps and
+    /// errors into a chained `anyhow::Error`.
+    fn print_chained_export(&mut self, iface: &Interface, func: &Function, ok: &Type) -> String {
+        let prev = mem::take(&mut self.src);
+        let name = to_rust_ident(&func.name);
+        self.src.push_str(&format!(
+            "/// Calls [`Self::{name}`], merging the trap or error it may end in into\n\
+             /// one error which keeps the contexts the guest added.\n\
+             pub fn {name}_chained(&self, caller: impl wasmtime::AsContextMut<Data = T>, ",
+        ));
+        let mut params = Vec::new();
+        for (param, ty) in func.params.iter() {
+            let param = to_rust_ident(param);
+            self.src.push_str(&format!("{}: ", param));
+            self.print_ty(iface, ty, self.export_param_mode());
+            self.src.push_str(", ");
+            params.push(param);
+        }
+        self.src.push_str(") -> anyhow::Result<");
+        self.print_ty(iface, ok, TypeMode::Owned);
+        self.src.push_str(&format!(
+            "> {{
+                wit_bindgen_host_wasmtime_rust::context::flatten(
+                    \"{iface}.{func}\",
+                    self.{name}(caller, {params}),
+                )
+            }}
+            ",
+            iface = iface.name,
+            func = func.name,
+            params = params.join(", "),
+        ));
+        mem::replace(&mut self.src, prev).into()
     }
 
-    /// Classifies the return value of a function to see if it needs handling
-    /// with respect to the `custom_error` configuration option.
     fn classify_fn_ret(&mut self, iface: &Interface, f: &Function) -> FunctionRet {
+        if self.opts.component_compat {
+            return FunctionRet::Anyhow;
+        }
         if !self.opts.custom_error {
             return FunctionRet::Normal;
         }
@@ -164,10 +1808,18 @@ impl Wasmtime {
 
 impl RustGenerator for Wasmtime {
     fn default_param_mode(&self) -> TypeMode {
-        if self.in_import {
+        if self.in_import && self.opts.component_compat {
+            // Component bindings hand owned values to the host, so arguments
+            // are copied out of wasm memory as they are lifted.
+            TypeMode::Owned
+        } else if self.in_import {
             // The default here is that only leaf values can be borrowed because
             // otherwise lists and such need to be copied into our own memory.
             TypeMode::LeafBorrowed("'a")
+        } else if self.opts.proptest {
+            // Round trips generate owned values, which are then passed to
+            // wasm as they are.
+            TypeMode::Owned
         } else {
             // When we're calling wasm exports, however, there's no need to take
             // any ownership of anything from the host so everything is borrowed
@@ -178,10 +1830,15 @@ impl RustGenerator for Wasmtime {
 
     fn handle_projection(&self) -> Option<(&'static str, String)> {
         if self.in_import {
+            let bound = if self.opts.object_safe {
+                format!("{} + ?Sized", self.trait_name)
+            } else {
+                self.trait_name.clone()
+            };
             if self.in_trait {
-                Some(("Self", self.trait_name.clone()))
+                Some(("Self", bound))
             } else {
-                Some(("T", self.trait_name.clone()))
+                Some(("T", bound))
             }
         } else {
             None
@@ -192,6 +1849,18 @@ impl RustGenerator for Wasmtime {
         None
     }
 
+    fn derive_ord(&self) -> bool {
+        self.opts.derive_ord
+    }
+
+    fn derive_partial_eq(&self) -> bool {
+        self.opts.proptest
+    }
+
+    fn lowered_sizes(&self) -> Option<&SizeAlign> {
+        self.opts.lowered_size.then_some(&self.sizes)
+    }
+
     fn push_str(&mut self, s: &str) {
         self.src.push_str(s);
     }
@@ -254,7 +1923,42 @@ impl Generator for Wasmtime {
         let variant = Self::abi_variant(dir);
         self.types.analyze(iface);
         self.in_import = variant == AbiVariant::GuestImport;
-        self.trait_name = iface.name.to_camel_case();
+        if let Some(version) = self.opts.abi_version {
+            if let Err(e) = abi::check_abi_version(version) {
+                panic!("{}", e);
+            }
+        }
+        if self.in_import && self.opts.component_compat {
+            if iface.resources.len() > 0 {
+                panic!("`component_compat` does not support resources");
+            }
+            if self.opts.custom_error || !self.opts.batch.is_empty() || self.opts.chunked {
+                panic!("`component_compat` cannot be combined with `custom_error`, `batch` or `chunked`");
+            }
+        }
+        if !self.in_import && !self.opts.coalesce.is_empty() {
+            if !self.opts.spawn_blocking {
+                panic!("`coalesce` requires `spawn_blocking`");
+            }
+            for name in self.opts.coalesce.iter() {
+                if !iface.functions.iter().any(|f| f.name == *name) {
+                    panic!(
+                        "`coalesce` names `{}`, which isn't a function of `{}`",
+                        name, iface.name
+                    );
+                }
+            }
+        }
+        // Event payloads and batched calls are always decoded into owned
+        // values.
+        for ty in self.encoded_types(iface) {
+            self.types.set_param_result_ty(iface, &ty, false, true);
+        }
+        self.trait_name = if self.opts.component_compat {
+            "Host".to_string()
+        } else {
+            iface.name.to_camel_case()
+        };
         self.src.push_str(&format!(
             "#[allow(clippy::all)]\npub mod {} {{\n",
             iface.name.to_snake_case(),
@@ -274,6 +1978,9 @@ impl Generator for Wasmtime {
         docs: &Docs,
     ) {
         self.print_typedef_record(iface, id, record, docs);
+        if is_error_context(iface, id, self.opts.error_context.as_deref()) {
+            self.print_error_context(iface, id);
+        }
 
         // If this record might be used as a slice type in various places then
         // we synthesize an `Endian` implementation for it so `&[Le<ThisType>]`
@@ -334,7 +2041,7 @@ impl Generator for Wasmtime {
     fn type_flags(
         &mut self,
         _iface: &Interface,
-        _id: TypeId,
+        id: TypeId,
         name: &str,
         flags: &Flags,
         docs: &Docs,
@@ -374,6 +2081,8 @@ impl Generator for Wasmtime {
 
         self.src.push_str("}\n");
         self.src.push_str("}\n\n");
+
+        self.print_lowered_size(id, &name.to_camel_case(), None, None);
     }
 
     fn type_variant(
@@ -468,34 +2177,18 @@ impl Generator for Wasmtime {
     // so a user "export" uses the "guest import" ABI variant on the inside of
     // this `Generator` implementation.
     fn export(&mut self, iface: &Interface, func: &Function) {
+        // Idempotent functions are imported taking the key of each call too,
+        // which the host trait is given to deduplicate retries.
+        let keyed = idempotent_functions(iface)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name)
+            .map(|(_, keyed)| keyed);
+        if keyed.is_some() && self.opts.interned.contains(&func.name) {
+            panic!("idempotent function `{}` can't also be interned", func.name);
+        }
+        let func = keyed.as_ref().unwrap_or(func);
         let prev = mem::take(&mut self.src);
 
-        // Generate the closure that's passed to a `Linker`, the final piece of
-        // codegen here.
-        let sig = iface.wasm_signature(AbiVariant::GuestImport, func);
-        let params = (0..sig.params.len())
-            .map(|i| format!("arg{}", i))
-            .collect::<Vec<_>>();
-        let mut f = FunctionBindgen::new(self, params);
-        iface.call(
-            AbiVariant::GuestImport,
-            LiftLower::LiftArgsLowerResults,
-            func,
-            &mut f,
-        );
-        let FunctionBindgen {
-            src,
-            cleanup,
-            needs_borrow_checker,
-            needs_memory,
-            needs_buffer_transaction,
-            needs_functions,
-            closures,
-            ..
-        } = f;
-        assert!(cleanup.is_none());
-        assert!(!needs_buffer_transaction);
-
         // Generate the signature this function will have in the final trait
         let self_arg = "&mut self".to_string();
         self.in_trait = true;
@@ -503,7 +2196,24 @@ impl Generator for Wasmtime {
         let mut fnsig = FnSig::default();
         fnsig.private = true;
         fnsig.self_arg = Some(self_arg);
-        self.print_docs_and_params(iface, func, TypeMode::LeafBorrowed("'_"), &fnsig);
+        let param_mode = if self.opts.component_compat {
+            TypeMode::Owned
+        } else {
+            TypeMode::LeafBorrowed("'_")
+        };
+        let params = if self.opts.doc_examples {
+            let borrowed = param_mode != TypeMode::Owned;
+            let examples = self.example_docs(iface, func, borrowed);
+            let mut func = func.clone();
+            func.docs.contents = Some(match func.docs.contents {
+                Some(docs) => format!("{}\n\n{}", docs.trim(), examples),
+                None => examples,
+            });
+            self.print_docs_and_params(iface, &func, param_mode, &fnsig)
+        } else {
+            self.print_docs_and_params(iface, func, param_mode, &fnsig)
+        };
+        let trait_call = format!("{}({})", to_rust_ident(&func.name), params.join(", "));
         // The Rust return type may differ from the wasm return type based on
         // the `custom_error` configuration of this code generator.
         match self.classify_fn_ret(iface, func) {
@@ -515,101 +2225,130 @@ impl Generator for Wasmtime {
                 self.push_str(" -> Result<");
                 self.print_ty(iface, &func.result, TypeMode::Owned);
                 self.push_str(", Self::Error>");
-            }
-            FunctionRet::CustomToError { ok, .. } => {
-                self.push_str(" -> Result<");
-                self.print_ty(iface, &ok, TypeMode::Owned);
-                self.push_str(", Self::Error>");
-            }
-        }
-        self.in_trait = false;
-        let trait_signature = mem::take(&mut self.src).into();
-
-        // Generate the closure that's passed to a `Linker`, the final piece of
-        // codegen here.
-        self.src
-            .push_str("move |mut caller: wasmtime::Caller<'_, T>");
-        for (i, param) in sig.params.iter().enumerate() {
-            let arg = format!("arg{}", i);
-            self.src.push_str(",");
-            self.src.push_str(&arg);
-            self.src.push_str(":");
-            self.wasm_type(*param);
-        }
-        self.src.push_str("| {\n");
-
-        if self.opts.tracing {
-            self.src.push_str(&format!(
-                "
-                    let span = wit_bindgen_host_wasmtime_rust::tracing::span!(
-                        wit_bindgen_host_wasmtime_rust::tracing::Level::TRACE,
-                        \"wit-bindgen abi\",
-                        module = \"{}\",
-                        function = \"{}\",
-                    );
-                    let _enter = span.enter();
-                ",
-                iface.name, func.name,
-            ));
-        }
-        self.src.push_str(&closures);
-
-        for (name, func) in needs_functions {
-            self.src.push_str(&format!(
-                "
-                    let func = get_func(&mut caller, \"{name}\")?;
-                    let func_{name} = func.typed::<{cvt}, _>(&caller)?;
-                ",
-                name = name,
-                cvt = func.cvt(),
-            ));
-            self.needs_get_func = true;
+            }
+            FunctionRet::CustomToError { ok, .. } => {
+                self.push_str(" -> Result<");
+                self.print_ty(iface, &ok, TypeMode::Owned);
+                self.push_str(", Self::Error>");
+            }
+            FunctionRet::Anyhow => {
+                self.push_str(" -> anyhow::Result<");
+                self.print_ty(iface, &func.result, TypeMode::Owned);
+                self.push_str(">");
+            }
         }
+        self.in_trait = false;
+        let cfg = self.cfg_attr(func);
+        let group = trait_group(func);
+        let trait_signature: String = mem::take(&mut self.src).into();
+        let trait_signature = format!("{cfg}{trait_signature}");
 
-        if needs_memory || needs_borrow_checker {
-            self.src
-                .push_str("let memory = &get_memory(&mut caller, \"memory\")?;\n");
-            self.needs_get_memory = true;
-        }
+        self.src = prev;
+        let closure = self.linker_closure(iface, func, None);
 
-        if needs_borrow_checker {
-            self.src.push_str(
-                "let (mem, data) = memory.data_and_store_mut(&mut caller);
-                let mut _bc = wit_bindgen_host_wasmtime_rust::BorrowChecker::new(mem);
-                let host = get(data);\n",
-            );
-        } else {
-            self.src.push_str("let host = get(caller.data_mut());\n");
+        let imports = self
+            .guest_imports
+            .entry(iface.name.to_string())
+            .or_default();
+        imports.push(Import {
+            name: iface.mangle_funcname(func),
+            closure,
+            trait_signature: Some(trait_signature),
+            trait_call,
+            cfg: cfg.clone(),
+            group,
+        });
+
+        // Interned functions are also imported under a name of their own,
+        // taking ids of strings registered through `intern_string`.
+        let interned = interned_functions(iface, &self.opts.interned)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name);
+        if let Some((_, interned)) = interned {
+            let params = func
+                .params
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, ty))| *ty == Type::String)
+                .map(|(i, _)| i)
+                .collect();
+            let call = SyntheticCall {
+                name: func.name.clone(),
+                interned: params,
+                spliced: Vec::new(),
+                spliced_result: false,
+            };
+            let closure = self.linker_closure(iface, &interned, Some(call));
+            self.needs_interned_strings = true;
+            self.guest_imports
+                .get_mut(&iface.name)
+                .unwrap()
+                .push(Import {
+                    name: iface.mangle_funcname(&interned),
+                    closure: format!(
+                        "{{
+                            let interned_strings = interned_strings.clone();
+                            {closure}
+                        }}"
+                    ),
+                    trait_signature: None,
+                    trait_call: String::new(),
+                    cfg: cfg.clone(),
+                    group: None,
+                });
         }
 
-        if self.all_needed_handles.len() > 0 {
-            self.src.push_str("let (host, _tables) = host;\n");
+        // Spliced functions are imported under a name of their own too,
+        // taking and returning tokens for byte lists kept on the host.
+        let spliced = spliced_functions(iface)
+            .into_iter()
+            .find(|(f, _)| f.name == func.name);
+        if let Some((_, spliced)) = spliced {
+            let params = func
+                .params
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, ty))| is_byte_list(iface, ty))
+                .map(|(i, _)| i)
+                .collect();
+            let call = SyntheticCall {
+                name: func.name.clone(),
+                interned: Vec::new(),
+                spliced: params,
+                spliced_result: is_byte_list(iface, &func.result),
+            };
+            let closure = self.linker_closure(iface, &spliced, Some(call));
+            self.needs_data_tokens = true;
+            self.guest_imports
+                .get_mut(&iface.name)
+                .unwrap()
+                .push(Import {
+                    name: iface.mangle_funcname(&spliced),
+                    closure: format!(
+                        "{{
+                            let data_tokens = data_tokens.clone();
+                            {closure}
+                        }}"
+                    ),
+                    trait_signature: None,
+                    trait_call: String::new(),
+                    cfg: cfg.clone(),
+                    group: None,
+                });
         }
-
-        self.src.push_str(&String::from(src));
-
-        self.src.push_str("}");
-        let closure = mem::replace(&mut self.src, prev).into();
-
-        self.guest_imports
-            .entry(iface.name.to_string())
-            .or_insert(Vec::new())
-            .push(Import {
-                name: iface.mangle_funcname(func),
-                closure,
-                trait_signature,
-            });
     }
 
     // As with `abi_variant` above, we're generating host-side bindings here
     // so a user "import" uses the "export" ABI variant on the inside of
     // this `Generator` implementation.
     fn import(&mut self, iface: &Interface, func: &Function) {
+        let chained = error_context_ok(iface, func, self.opts.error_context.as_deref())
+            .map(|ok| self.print_chained_export(iface, func, &ok));
         let prev = mem::take(&mut self.src);
 
         let mut sig = FnSig::default();
         sig.self_arg = Some("&self, mut caller: impl wasmtime::AsContextMut<Data = T>".to_string());
-        self.print_docs_and_params(iface, func, TypeMode::AllBorrowed("'_"), &sig);
+        self.print_docs_and_params(iface, func, self.export_param_mode(), &sig);
         self.push_str("-> Result<");
         self.print_ty(iface, &func.result, TypeMode::Owned);
         self.push_str(", wasmtime::Trap> {\n");
@@ -619,7 +2358,9 @@ impl Generator for Wasmtime {
             .iter()
             .map(|(name, _)| to_rust_ident(name).to_string())
             .collect();
+        let lift_budget = self.lift_budget(iface, &func.name);
         let mut f = FunctionBindgen::new(self, params);
+        f.lift_budget = lift_budget;
         iface.call(
             AbiVariant::GuestExport,
             LiftLower::LowerArgsLiftResults,
@@ -633,9 +2374,16 @@ impl Generator for Wasmtime {
             needs_buffer_transaction,
             closures,
             needs_functions,
+            lift_budget,
+            charges_lift,
             ..
         } = f;
 
+        // Set up before `exports` borrows `self` below.
+        if charges_lift {
+            self.print_lift_budget(lift_budget.unwrap());
+        }
+
         let exports = self
             .guest_exports
             .entry(iface.name.to_string())
@@ -681,6 +2429,7 @@ impl Generator for Wasmtime {
         self.src.push_str("}\n");
         let func_body = mem::replace(&mut self.src, prev);
         exports.funcs.push(func_body.into());
+        exports.funcs.extend(chained);
 
         // Create the code snippet which will define the type of this field in
         // the struct that we're exporting and additionally extracts the
@@ -708,20 +2457,169 @@ impl Generator for Wasmtime {
                 ),
             ),
         );
+
+        if self.opts.dispatch_table {
+            exports.fields.insert(
+                "dispatch_table".to_string(),
+                (
+                    "Vec<wasmtime::Func>".to_string(),
+                    format!(
+                        "{{
+                            let (ptr,) = instance
+                                .get_typed_func::<(), (i32,), _>(
+                                    &mut store,
+                                    \"canonical_abi_dispatch_table\",
+                                )?
+                                .call(&mut store, ())?;
+                            let memory = instance
+                                .get_memory(&mut store, \"memory\")
+                                .ok_or_else(|| anyhow::anyhow!(\"`memory` export not a memory\"))?;
+                            let table = instance
+                                .get_table(&mut store, \"__indirect_function_table\")
+                                .ok_or_else(|| {{
+                                    anyhow::anyhow!(\"`__indirect_function_table` export not a table\")
+                                }})?;
+                            let mut funcs = Vec::with_capacity({len});
+                            for op in 0..{len} {{
+                                let mut index = [0; 4];
+                                memory.read(&store, ptr as u32 as usize + op * 4, &mut index)?;
+                                match table.get(&mut store, u32::from_le_bytes(index)) {{
+                                    Some(wasmtime::Val::FuncRef(Some(func))) => funcs.push(func),
+                                    _ => anyhow::bail!(\"invalid dispatch table entry for operation {{}}\", op),
+                                }}
+                            }}
+                            funcs
+                        }}",
+                        len = iface.functions.len(),
+                    ),
+                ),
+            );
+        }
+
+        if self.opts.spawn_blocking {
+            if let Some(func) = self.async_export(iface, func) {
+                self.guest_exports
+                    .get_mut(&iface.name)
+                    .unwrap()
+                    .async_funcs
+                    .push(func);
+            } else if self.opts.coalesce.contains(&func.name) {
+                panic!(
+                    "`coalesce` names `{}`, which takes arguments the `spawn_blocking` wrapper can't own",
+                    func.name
+                );
+            }
+        }
     }
 
     fn finish_one(&mut self, iface: &Interface, files: &mut Files) {
+        if !self.in_import && self.opts.proptest {
+            self.print_proptests(iface);
+        }
+        let encoded = self.encoded_types(iface);
+        if !encoded.is_empty() {
+            let varint = self.opts.varint.clone();
+            self.print_codec_impls(
+                iface,
+                &encoded,
+                "wit_bindgen_host_wasmtime_rust::codec",
+                &varint,
+            );
+        }
+        if !iface.events.is_empty() {
+            self.print_events(iface);
+        }
+        if self.in_import && !self.opts.batch.is_empty() {
+            self.print_batch(iface);
+        }
+        if self.in_import && self.opts.chunked {
+            self.print_chunked(iface);
+        }
+        if self.in_import && !self.opts.interned.is_empty() {
+            self.print_interned(iface);
+        }
+        if self.in_import && self.opts.bulk {
+            self.print_bulk(iface);
+        }
+        if self.opts.symbols {
+            self.print_symbols(iface);
+        }
+        if self.in_import && (self.opts.mutants || self.opts.faults) {
+            self.print_mutate_impls(iface);
+        }
+
+        // Object-safe traits may be implemented by unsized types, which the
+        // generated code then only ever handles behind a reference.
+        let unsized_bound = if self.opts.object_safe {
+            " + ?Sized"
+        } else {
+            ""
+        };
+
+        let thread_bounds = self.opts.thread_bounds.map_or("", ThreadBounds::bounds);
+
         for (module, funcs) in sorted_iter(&self.guest_imports) {
             let module_camel = module.to_camel_case();
+            let supertraits = if self.opts.object_safe {
+                ""
+            } else {
+                " + Sized"
+            };
+            let supertraits = format!("{}{}", supertraits, thread_bounds);
+
+            // Grouped methods are declared by traits of their own, which the
+            // host trait combines.
+            let mut groups = BTreeMap::new();
+            for f in funcs {
+                if let (Some(group), Some(signature)) = (&f.group, &f.trait_signature) {
+                    groups
+                        .entry(group.to_camel_case())
+                        .or_insert_with(Vec::new)
+                        .push((signature.as_str(), f.trait_call.as_str()));
+                }
+            }
+            if !groups.is_empty() && (self.opts.custom_error || !self.all_needed_handles.is_empty())
+            {
+                panic!("`@group` can't be used with resources or `custom_error`");
+            }
+            for (group, signatures) in groups.iter() {
+                self.src.push_str(&format!(
+                    "/// The methods of the `{}` group of [`{}`].\npub trait {}",
+                    group, self.trait_name, group
+                ));
+                if let Some(supertraits) = supertraits.strip_prefix(" + ") {
+                    self.src.push_str(": ");
+                    self.src.push_str(supertraits);
+                    self.src.push_str(" ");
+                }
+                self.src.push_str("{\n");
+                for (signature, _) in signatures {
+                    self.src.push_str(signature);
+                    self.src.push_str(";\n\n");
+                }
+                self.src.push_str("}\n");
+            }
+            let group_bounds = groups.keys().map(|g| format!(" + {}", g));
+            let host_supertraits = format!("{}{}", group_bounds.collect::<String>(), supertraits);
+
             self.src.push_str("pub trait ");
-            self.src.push_str(&module_camel);
-            self.src.push_str(": Sized ");
+            self.src.push_str(&self.trait_name);
+            if let Some(supertraits) = host_supertraits.strip_prefix(" + ") {
+                self.src.push_str(": ");
+                self.src.push_str(supertraits);
+                self.src.push_str(" ");
+            }
             self.src.push_str("{\n");
+            let items_start = self.src.len();
+            // The signatures and calls of the methods of the host trait which
+            // the adapters of grouped traits forward.
+            let mut forwarded = Vec::new();
             if self.all_needed_handles.len() > 0 {
                 for handle in self.all_needed_handles.iter() {
                     self.src.push_str("type ");
                     self.src.push_str(&handle.to_camel_case());
                     self.src.push_str(": std::fmt::Debug");
+                    self.src.push_str(thread_bounds);
                     self.src.push_str(";\n");
                 }
             }
@@ -740,10 +2638,136 @@ impl Generator for Wasmtime {
                     ));
                 }
             }
-            for f in funcs {
-                self.src.push_str(&f.trait_signature);
-                self.src.push_str(";\n\n");
+            if self.opts.memory_budget {
+                self.src.push_str(
+                    "
+                        /// Returns the budget for the guest memory allocated to
+                        /// return lists and strings to wasm, if any.
+                        fn memory_budget(
+                            &mut self,
+                        ) -> Option<&mut dyn wit_bindgen_host_wasmtime_rust::MemoryBudget> {
+                            None
+                        }
+                    ",
+                );
+                forwarded.push((
+                    "fn memory_budget(&mut self) -> Option<&mut dyn wit_bindgen_host_wasmtime_rust::MemoryBudget>",
+                    "memory_budget()",
+                ));
+            }
+            if self.in_import && self.opts.bulk {
+                self.src.push_str(
+                    "
+                        /// Returns the buffers wasm exchanges bulk data
+                        /// through.
+                        fn bulk_buffers(
+                            &mut self,
+                        ) -> &mut wit_bindgen_host_wasmtime_rust::bulk::BulkBuffers;
+                    ",
+                );
+                forwarded.push((
+                    "fn bulk_buffers(&mut self) -> &mut wit_bindgen_host_wasmtime_rust::bulk::BulkBuffers",
+                    "bulk_buffers()",
+                ));
+            }
+            if self.opts.mutants {
+                self.src.push_str(
+                    "
+                        /// Returns the mutator through which results are
+                        /// passed before wasm gets them.
+                        fn mutator(&self) -> &wit_bindgen_host_wasmtime_rust::mutate::Mutator;
+                    ",
+                );
+                forwarded.push((
+                    "fn mutator(&self) -> &wit_bindgen_host_wasmtime_rust::mutate::Mutator",
+                    "mutator()",
+                ));
+            }
+            if self.opts.quotas {
+                self.src.push_str(
+                    "
+                        /// Returns the quotas host function calls are charged
+                        /// to.
+                        fn quotas(&mut self) -> &mut wit_bindgen_host_wasmtime_rust::Quotas;
+                    ",
+                );
+                forwarded.push((
+                    "fn quotas(&mut self) -> &mut wit_bindgen_host_wasmtime_rust::Quotas",
+                    "quotas()",
+                ));
+            }
+            if self.opts.faults {
+                self.src.push_str(
+                    "
+                        /// Returns the injector of the faults host function
+                        /// calls run into.
+                        fn fault_injector(&self) -> &wit_bindgen_host_wasmtime_rust::fault::FaultInjector;
+                    ",
+                );
+                forwarded.push((
+                    "fn fault_injector(&self) -> &wit_bindgen_host_wasmtime_rust::fault::FaultInjector",
+                    "fault_injector()",
+                ));
+            }
+            if self.opts.call_log {
+                self.src.push_str(
+                    "
+                        /// Returns the log host function calls are entered
+                        /// into.
+                        fn call_log(&self) -> &wit_bindgen_host_wasmtime_rust::call_log::CallLog;
+                    ",
+                );
+                forwarded.push((
+                    "fn call_log(&self) -> &wit_bindgen_host_wasmtime_rust::call_log::CallLog",
+                    "call_log()",
+                ));
+            }
+            if self.opts.protocol {
+                self.src.push_str(
+                    "
+                        /// Returns the state of the protocol calls to
+                        /// host functions follow.
+                        fn protocol(&mut self) -> &mut wit_bindgen_host_wasmtime_rust::protocol::Protocol;
+                    ",
+                );
+                forwarded.push((
+                    "fn protocol(&mut self) -> &mut wit_bindgen_host_wasmtime_rust::protocol::Protocol",
+                    "protocol()",
+                ));
+            }
+            if self.opts.task_scope {
+                self.src.push_str(
+                    "
+                        /// Returns the scope owning the background tasks of
+                        /// this instance, which should be dropped along with
+                        /// it.
+                        fn task_scope(&self) -> &wit_bindgen_host_wasmtime_rust::tasks::TaskScope;
+
+                        /// Runs `task` in the background until it completes or
+                        /// the instance's task scope is dropped.
+                        fn spawn(
+                            &self,
+                            task: impl std::future::Future<Output = ()> + Send + 'static,
+                        ) where
+                            Self: Sized,
+                        {
+                            self.task_scope().spawn(task);
+                        }
+                    ",
+                );
+                forwarded.push((
+                    "fn task_scope(&self) -> &wit_bindgen_host_wasmtime_rust::tasks::TaskScope",
+                    "task_scope()",
+                ));
+            }
+            for f in funcs.iter().filter(|f| f.group.is_none()) {
+                if let Some(trait_signature) = &f.trait_signature {
+                    self.src.push_str(trait_signature);
+                    self.src.push_str(";\n\n");
+                    forwarded.push((trait_signature.as_str(), f.trait_call.as_str()));
+                }
             }
+            let items = self.src[items_start..].to_string();
             for handle in self.all_needed_handles.iter() {
                 self.src.push_str(&format!(
                     "fn drop_{}(&mut self, state: Self::{}) {{
@@ -760,6 +2784,7 @@ impl Generator for Wasmtime {
                 self.src.push_str(&module_camel);
                 self.src.push_str("Tables<T: ");
                 self.src.push_str(&module_camel);
+                self.src.push_str(unsized_bound);
                 self.src.push_str("> {\n");
                 for handle in self.all_needed_handles.iter() {
                     self.src.push_str("pub(crate) ");
@@ -772,6 +2797,7 @@ impl Generator for Wasmtime {
                 self.src.push_str("}\n");
                 self.src.push_str("impl<T: ");
                 self.src.push_str(&module_camel);
+                self.src.push_str(unsized_bound);
                 self.src.push_str("> Default for ");
                 self.src.push_str(&module_camel);
                 self.src.push_str("Tables<T> {\n");
@@ -782,6 +2808,17 @@ impl Generator for Wasmtime {
                 }
                 self.src.push_str("}}}");
             }
+
+            if !groups.is_empty() {
+                self.src.push_str(&group_adapters(
+                    &self.trait_name,
+                    &groups,
+                    &items,
+                    &forwarded,
+                    &supertraits,
+                    unsized_bound,
+                ));
+            }
         }
 
         for (module, funcs) in mem::take(&mut self.guest_imports) {
@@ -795,7 +2832,8 @@ impl Generator for Wasmtime {
             }
             self.push_str("+ Send + Sync + Copy + 'static) -> anyhow::Result<()> \n");
             self.push_str("where U: ");
-            self.push_str(&module_camel);
+            self.push_str(&self.trait_name.clone());
+            self.push_str(unsized_bound);
             self.push_str("\n{\n");
             if self.needs_get_memory {
                 self.push_str("use wit_bindgen_host_wasmtime_rust::rt::get_memory;\n");
@@ -803,11 +2841,32 @@ impl Generator for Wasmtime {
             if self.needs_get_func {
                 self.push_str("use wit_bindgen_host_wasmtime_rust::rt::get_func;\n");
             }
+            if self.needs_chunked_results {
+                self.push_str(
+                    "let chunked_results = std::sync::Arc::new(std::sync::Mutex::new(
+                        wit_bindgen_host_wasmtime_rust::rt::ChunkedResults::default(),
+                    ));\n",
+                );
+            }
+            if self.needs_interned_strings {
+                self.push_str(
+                    "let interned_strings = std::sync::Arc::new(std::sync::Mutex::new(
+                        wit_bindgen_host_wasmtime_rust::rt::InternedStrings::default(),
+                    ));\n",
+                );
+            }
+            if self.needs_data_tokens {
+                self.push_str(
+                    "let data_tokens = std::sync::Arc::new(std::sync::Mutex::new(
+                        wit_bindgen_host_wasmtime_rust::rt::DataTokens::default(),
+                    ));\n",
+                );
+            }
             for f in funcs {
                 let method = String::from("func_wrap");
                 self.push_str(&format!(
-                    "linker.{}(\"{}\", \"{}\", {})?;\n",
-                    method, module, f.name, f.closure,
+                    "{}linker.{}(\"{}\", \"{}\", {})?;\n",
+                    f.cfg, method, module, f.name, f.closure,
                 ));
             }
             for handle in self.all_needed_handles.iter() {
@@ -863,14 +2922,40 @@ impl Generator for Wasmtime {
                     r.index()
                 ));
             }
+            let state_bounds = self
+                .opts
+                .thread_bounds
+                .map_or(" + Send + Sync", ThreadBounds::bounds);
+            if self.opts.memory_budget {
+                self.push_str(&format!(
+                    "memory_budget: Option<Box<dyn wit_bindgen_host_wasmtime_rust::MemoryBudget{}>>,\n",
+                    state_bounds,
+                ));
+            }
             self.push_str("}\n");
+            if self.opts.memory_budget {
+                self.push_str(&format!(
+                    "
+                        impl {name}Data {{
+                            /// Limits the guest memory allocated to pass lists
+                            /// and strings to wasm.
+                            pub fn set_memory_budget(
+                                &mut self,
+                                budget: impl wit_bindgen_host_wasmtime_rust::MemoryBudget{state_bounds} + 'static,
+                            ) {{
+                                self.memory_budget = Some(Box::new(budget));
+                            }}
+                        }}
+                    "
+                ));
+            }
 
             self.push_str("pub struct ");
             self.push_str(&name);
             self.push_str("<T> {\n");
             self.push_str(&format!(
-                "get_state: Box<dyn Fn(&mut T) -> &mut {}Data + Send + Sync>,\n",
-                name
+                "get_state: Box<dyn Fn(&mut T) -> &mut {}Data{}>,\n",
+                name, state_bounds,
             ));
             for (name, (ty, _)) in exports.fields.iter() {
                 self.push_str(name);
@@ -1004,10 +3089,10 @@ impl Generator for Wasmtime {
                     pub fn new(
                         mut store: impl wasmtime::AsContextMut<Data = T>,
                         instance: &wasmtime::Instance,
-                        get_state: impl Fn(&mut T) -> &mut {}Data + Send + Sync + Copy + 'static,
+                        get_state: impl Fn(&mut T) -> &mut {}Data{} + Copy + 'static,
                     ) -> anyhow::Result<Self> {{
                 ",
-                name,
+                name, state_bounds,
             ));
             self.push_str("let mut store = store.as_context_mut();\n");
             assert!(!self.needs_get_func);
@@ -1045,6 +3130,9 @@ impl Generator for Wasmtime {
             for func in exports.funcs.iter() {
                 self.push_str(func);
             }
+            if self.opts.dispatch_table {
+                self.print_dispatch(iface);
+            }
 
             for r in self.exported_resources.iter() {
                 let (call, wait) = ("call", "");
@@ -1081,6 +3169,34 @@ impl Generator for Wasmtime {
             }
 
             self.push_str("}\n");
+
+            if self.opts.spawn_blocking {
+                self.print_async_exports(&name, exports);
+            }
+        }
+        if let Some(depth) = self.opts.call_depth {
+            self.push_str(&format!(
+                "
+                    /// The deepest nesting of the values passed across this
+                    /// interface.
+                    pub const MAX_TYPE_NESTING: usize = {nesting};
+
+                    /// The depth to which guests reenter their exports.
+                    pub const MAX_CALL_DEPTH: usize = {depth};
+
+                    /// Sets the maximum wasm stack of `config` to what
+                    /// [`MAX_CALL_DEPTH`] nested calls with values up to
+                    /// [`MAX_TYPE_NESTING`] levels deep need.
+                    pub fn configure_stack(config: &mut wasmtime::Config) -> &mut wasmtime::Config {{
+                        config.max_wasm_stack(wit_bindgen_host_wasmtime_rust::rt::max_wasm_stack(
+                            MAX_CALL_DEPTH,
+                            MAX_TYPE_NESTING,
+                        ))
+                    }}
+                ",
+                nesting = iface.max_nesting_depth(),
+                depth = depth,
+            ));
         }
         self.print_intrinsics();
 
@@ -1116,6 +3232,19 @@ impl Generator for Wasmtime {
     }
 }
 
+/// How the `-interned` or `-spliced` counterpart of a function calls the
+/// host.
+struct SyntheticCall {
+    /// Name of the original function, which the host implements.
+    name: String,
+    /// Indices of the parameters passed as ids of interned strings.
+    interned: Vec<usize>,
+    /// Indices of the byte list parameters passed as data tokens.
+    spliced: Vec<usize>,
+    /// Whether the byte list result is returned as a data token.
+    spliced_result: bool,
+}
+
 struct FunctionBindgen<'a> {
     gen: &'a mut Wasmtime,
 
@@ -1152,6 +3281,20 @@ struct FunctionBindgen<'a> {
     needs_borrow_checker: bool,
     needs_memory: bool,
     needs_functions: HashMap<String, NeededFunction>,
+
+    // Set when generating the `-interned` or `-spliced` counterpart of a
+    // function.
+    synthetic: Option<SyntheticCall>,
+
+    // The `interface.function` name of the host function whose calls are
+    // charged to quotas, if they are.
+    quota: Option<String>,
+
+    // The `interface.function` name of the call and the bytes it may lift,
+    // if its interface has a lift budget, and whether anything was charged
+    // to it.
+    lift_budget: Option<(String, u64)>,
+    charges_lift: bool,
 }
 
 impl FunctionBindgen<'_> {
@@ -1170,6 +3313,10 @@ impl FunctionBindgen<'_> {
             needs_borrow_checker: false,
             needs_memory: false,
             needs_functions: HashMap::new(),
+            synthetic: None,
+            quota: None,
+            lift_budget: None,
+            charges_lift: false,
             params,
         }
     }
@@ -1205,6 +3352,66 @@ impl FunctionBindgen<'_> {
         }
     }
 
+    /// Reserves `bytes` of guest memory from the memory budget, if enabled,
+    /// ahead of allocating them.
+    fn reserve_memory(&mut self, bytes: String) {
+        if !self.gen.opts.memory_budget {
+            return;
+        }
+        let budget = if !self.gen.in_import {
+            "(self.get_state)(caller.as_context_mut().data_mut()).memory_budget.as_deref_mut()"
+        } else if !self.gen.all_needed_handles.is_empty() {
+            "get(caller.data_mut()).0.memory_budget()"
+        } else {
+            "get(caller.data_mut()).memory_budget()"
+        };
+        self.push_str(&format!(
+            "wit_bindgen_host_wasmtime_rust::rt::reserve_memory({budget}, {bytes})?;\n"
+        ));
+    }
+
+    /// Charges `bytes` transferred by the host function to its quota, if
+    /// enabled.
+    fn charge_bytes(&mut self, bytes: String) {
+        let function = match &self.quota {
+            Some(function) => function.clone(),
+            None => return,
+        };
+        // After the call, `host` no longer borrows the caller, which
+        // lowering needs.
+        let quotas = if !self.after_call {
+            "host.quotas()"
+        } else if !self.gen.all_needed_handles.is_empty() {
+            "get(caller.data_mut()).0.quotas()"
+        } else {
+            "get(caller.data_mut()).quotas()"
+        };
+        self.push_str(&format!(
+            "wit_bindgen_host_wasmtime_rust::rt::charge_bytes({quotas}, \"{function}\", {bytes})?;\n"
+        ));
+    }
+
+    /// Charges `len` lifted elements of `size` bytes to the lift budget of
+    /// the call, if it has one.
+    fn charge_lift(&mut self, len: &str, size: usize) {
+        if self.lift_budget.is_none() {
+            return;
+        }
+        self.charges_lift = true;
+        self.push_str(&format!(
+            "wit_bindgen_host_wasmtime_rust::rt::charge_lift(&mut lift_budget, {len}, {size})?;\n"
+        ));
+    }
+
+    /// Traps if the lifted length `len` is over `max_list_len`, if set.
+    fn check_len(&mut self, len: &str) {
+        if let Some(max) = self.gen.opts.max_list_len {
+            self.push_str(&format!(
+                "wit_bindgen_host_wasmtime_rust::rt::check_len({len}, {max})?;\n"
+            ));
+        }
+    }
+
     fn call_intrinsic(&mut self, name: &str, args: String) {
         let (method, suffix) = ("call", "");
         self.push_str(&format!(
@@ -1219,7 +3426,7 @@ impl FunctionBindgen<'_> {
         self.gen.needs_raw_mem = true;
         let tmp = self.tmp();
         self.push_str(&format!(
-            "let load{} = {}.load::<{}>({} + {})?;\n",
+            "let load{} = {}.load::<{}>(wit_bindgen_host_wasmtime_rust::rt::address({}, {})?)?;\n",
             tmp, mem, ty, operands[0], offset
         ));
         format!("load{}", tmp)
@@ -1229,7 +3436,7 @@ impl FunctionBindgen<'_> {
         let mem = self.memory_src();
         self.gen.needs_raw_mem = true;
         self.push_str(&format!(
-            "{}.store({} + {}, wit_bindgen_host_wasmtime_rust::rt::{}({}){})?;\n",
+            "{}.store(wit_bindgen_host_wasmtime_rust::rt::address({}, {})?, wit_bindgen_host_wasmtime_rust::rt::{}({}){})?;\n",
             mem, operands[1], offset, method, operands[0], extra
         ));
     }
@@ -1266,6 +3473,10 @@ impl Bindgen for FunctionBindgen<'_> {
         &self.gen.sizes
     }
 
+    fn abi_version(&self) -> u32 {
+        self.gen.opts.abi_version.unwrap_or(abi::ABI_VERSION)
+    }
+
     fn push_block(&mut self) {
         let prev = mem::take(&mut self.src);
         self.block_storage.push(prev);
@@ -1678,11 +3889,16 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str(&format!("let {} = {};\n", val, operands[0]));
 
                 // ... and then realloc space for the result in the guest module
+                self.reserve_memory(format!("({}.len() as u64) * {}", val, size));
+                self.charge_bytes(format!("({}.len() as u64) * {}", val, size));
                 let ptr = format!("ptr{}", tmp);
                 self.push_str(&format!("let {} = ", ptr));
                 self.call_intrinsic(
                     realloc,
-                    format!("(0, 0, {}, ({}.len() as i32) * {})", align, val, size),
+                    format!(
+                        "(0, 0, {}, wit_bindgen_host_wasmtime_rust::rt::list_size({}.len(), {})?)",
+                        align, val, size
+                    ),
                 );
 
                 // ... and then copy over the result.
@@ -1705,6 +3921,8 @@ impl Bindgen for FunctionBindgen<'_> {
                     let tmp = self.tmp();
                     self.push_str(&format!("let ptr{} = {};\n", tmp, operands[0]));
                     self.push_str(&format!("let len{} = {};\n", tmp, operands[1]));
+                    self.check_len(&format!("len{}", tmp));
+                    self.charge_lift(&format!("len{}", tmp), self.sizes().size(element));
                     self.push_str(&format!(
                         "
                             let data{tmp} = copy_slice(
@@ -1729,8 +3947,19 @@ impl Bindgen for FunctionBindgen<'_> {
                     let tmp = self.tmp();
                     self.push_str(&format!("let ptr{} = {};\n", tmp, operands[0]));
                     self.push_str(&format!("let len{} = {};\n", tmp, operands[1]));
+                    self.check_len(&format!("len{}", tmp));
+                    self.charge_lift(&format!("len{}", tmp), self.sizes().size(element));
+                    let el_size = self.sizes().size(element);
+                    self.charge_bytes(format!("(len{} as u32 as u64) * {}", tmp, el_size));
                     let slice = format!("_bc.slice(ptr{0}, len{0})?", tmp);
-                    results.push(slice);
+                    if self.gen.opts.component_compat {
+                        results.push(format!(
+                            "wit_bindgen_host_wasmtime_rust::rt::copy_le({})",
+                            slice
+                        ));
+                    } else {
+                        results.push(slice);
+                    }
                 }
             },
 
@@ -1746,6 +3975,8 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str(&format!("let {} = {};\n", val, operands[0]));
 
                 // ... and then realloc space for the result in the guest module
+                self.reserve_memory(format!("{}.len() as u64", val));
+                self.charge_bytes(format!("{}.len() as u64", val));
                 let ptr = format!("ptr{}", tmp);
                 self.push_str(&format!("let {} = ", ptr));
                 self.call_intrinsic(realloc, format!("(0, 0, 1, {}.len() as i32)", val));
@@ -1771,6 +4002,8 @@ impl Bindgen for FunctionBindgen<'_> {
                     let tmp = self.tmp();
                     self.push_str(&format!("let ptr{} = {};\n", tmp, operands[0]));
                     self.push_str(&format!("let len{} = {};\n", tmp, operands[1]));
+                    self.check_len(&format!("len{}", tmp));
+                    self.charge_lift(&format!("len{}", tmp), 1);
                     self.push_str(&format!(
                         "
                             let data{tmp} = copy_slice(
@@ -1798,7 +4031,13 @@ impl Bindgen for FunctionBindgen<'_> {
                     let tmp = self.tmp();
                     self.push_str(&format!("let ptr{} = {};\n", tmp, operands[0]));
                     self.push_str(&format!("let len{} = {};\n", tmp, operands[1]));
-                    let slice = format!("_bc.slice_str(ptr{0}, len{0})?", tmp);
+                    self.check_len(&format!("len{}", tmp));
+                    self.charge_lift(&format!("len{}", tmp), 1);
+                    self.charge_bytes(format!("len{} as u32 as u64", tmp));
+                    let mut slice = format!("_bc.slice_str(ptr{0}, len{0})?", tmp);
+                    if self.gen.opts.component_compat {
+                        slice.push_str(".to_string()");
+                    }
                     results.push(slice);
                 }
             },
@@ -1819,10 +4058,17 @@ impl Bindgen for FunctionBindgen<'_> {
                 // reference it multiple times.
                 self.push_str(&format!("let {} = {};\n", vec, operands[0]));
                 self.push_str(&format!("let {} = {}.len() as i32;\n", len, vec));
+                let bytes = format!("bytes{}", tmp);
+                self.push_str(&format!(
+                    "let {} = wit_bindgen_host_wasmtime_rust::rt::list_size({}.len(), {})?;\n",
+                    bytes, vec, size
+                ));
 
                 // ... then realloc space for the result in the guest module
+                self.reserve_memory(format!("{} as u32 as u64", bytes));
+                self.charge_bytes(format!("{} as u32 as u64", bytes));
                 self.push_str(&format!("let {} = ", result));
-                self.call_intrinsic(realloc, format!("(0, 0, {}, {} * {})", align, len, size));
+                self.call_intrinsic(realloc, format!("(0, 0, {}, {})", align, bytes));
 
                 // ... then consume the vector and use the block to lower the
                 // result.
@@ -1830,7 +4076,10 @@ impl Bindgen for FunctionBindgen<'_> {
                     "for (i, e) in {}.into_iter().enumerate() {{\n",
                     vec
                 ));
-                self.push_str(&format!("let base = {} + (i as i32) * {};\n", result, size));
+                self.push_str(&format!(
+                    "let base = wit_bindgen_host_wasmtime_rust::rt::element({}, i, {})?;\n",
+                    result, size
+                ));
                 self.push_str(&body);
                 self.push_str("}");
 
@@ -1845,8 +4094,16 @@ impl Bindgen for FunctionBindgen<'_> {
                 let align = self.gen.sizes.align(element);
                 let len = format!("len{}", tmp);
                 self.push_str(&format!("let {} = {};\n", len, operands[1]));
+                self.check_len(&len);
+                self.charge_lift(&len, size);
                 let base = format!("base{}", tmp);
                 self.push_str(&format!("let {} = {};\n", base, operands[0]));
+                let bytes = format!("bytes{}", tmp);
+                self.push_str(&format!(
+                    "let {} = wit_bindgen_host_wasmtime_rust::rt::list_bounds({}, {}, {})?;\n",
+                    bytes, base, len, size
+                ));
+                self.charge_bytes(format!("{} as u32 as u64", bytes));
                 let result = format!("result{}", tmp);
                 self.push_str(&format!(
                     "let mut {} = Vec::with_capacity({} as usize);\n",
@@ -1856,11 +4113,10 @@ impl Bindgen for FunctionBindgen<'_> {
                 self.push_str("for i in 0..");
                 self.push_str(&len);
                 self.push_str(" {\n");
-                self.push_str("let base = ");
-                self.push_str(&base);
-                self.push_str(" + i *");
-                self.push_str(&size.to_string());
-                self.push_str(";\n");
+                self.push_str(&format!(
+                    "let base = wit_bindgen_host_wasmtime_rust::rt::element({}, i as u32 as usize, {})?;\n",
+                    base, size
+                ));
                 self.push_str(&result);
                 self.push_str(".push(");
                 self.push_str(&body);
@@ -1869,7 +4125,7 @@ impl Bindgen for FunctionBindgen<'_> {
                 results.push(result);
 
                 if let Some(free) = free {
-                    self.call_intrinsic(free, format!("({}, {} * {}, {})", base, len, size, align));
+                    self.call_intrinsic(free, format!("({}, {}, {})", base, bytes, align));
                     self.needs_functions
                         .insert(free.to_string(), NeededFunction::Free);
                 }
@@ -1930,13 +4186,105 @@ impl Bindgen for FunctionBindgen<'_> {
                     self.push_str(");\n");
                 }
 
-                let mut call = format!("host.{}(", func.name.to_snake_case());
+                // Ids of interned strings and tokens of spliced data are
+                // resolved before calling the original function with the
+                // values themselves.
+                let synthetic = self.synthetic.take();
+                let mut call = match &synthetic {
+                    Some(synthetic) => format!("host.{}(", synthetic.name.to_snake_case()),
+                    None => format!("host.{}(", func.name.to_snake_case()),
+                };
+                let compat = self.gen.opts.component_compat;
                 for i in 0..operands.len() {
-                    call.push_str(&format!("param{}, ", i));
+                    let synthetic = match &synthetic {
+                        Some(synthetic) => synthetic,
+                        None => {
+                            call.push_str(&format!("param{}, ", i));
+                            continue;
+                        }
+                    };
+                    if synthetic.interned.contains(&i) {
+                        self.push_str(&format!(
+                            "let param{i}_interned = interned_strings.lock().unwrap().get(param{i})?;\n",
+                        ));
+                        if compat {
+                            call.push_str(&format!("param{}_interned.to_string(), ", i));
+                        } else {
+                            call.push_str(&format!("&*param{}_interned, ", i));
+                        }
+                    } else if synthetic.spliced.contains(&i) {
+                        self.push_str(&format!(
+                            "let param{i}_spliced = data_tokens.lock().unwrap().take(param{i})?;\n",
+                        ));
+                        if compat {
+                            call.push_str(&format!("param{}_spliced, ", i));
+                        } else {
+                            call.push_str(&format!("&param{}_spliced, ", i));
+                        }
+                    } else {
+                        call.push_str(&format!("param{}, ", i));
+                    }
                 }
                 call.push_str(")");
 
-                self.push_str("let result = ");
+                let name = match &synthetic {
+                    Some(synthetic) => format!("{}.{}", iface.name, synthetic.name),
+                    None => format!("{}.{}", iface.name, func.name),
+                };
+                // Calls are logged first, so that those stopped by a quota or
+                // a fault show up as never returning.
+                if self.gen.opts.call_log {
+                    let args = (0..operands.len())
+                        .map(|i| format!("&param{i}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    self.push_str(&format!(
+                        "let crossing = host.call_log().enter(\"{name}\", &[{args}]);\n"
+                    ));
+                }
+                if self.quota.is_some() {
+                    self.push_str(&format!(
+                        "wit_bindgen_host_wasmtime_rust::rt::charge_call(host.quotas(), \"{name}\")?;\n"
+                    ));
+                }
+                if self.gen.opts.protocol {
+                    let wit_name = synthetic.as_ref().map_or(&func.name, |s| &s.name);
+                    let transitions = protocol_transitions(iface);
+                    let transition = transitions.iter().find(|t| t.func.name == *wit_name);
+                    if let Some(Transition { from, to, .. }) = transition {
+                        let initial = &transitions[0].from;
+                        self.push_str(&format!(
+                            "wit_bindgen_host_wasmtime_rust::rt::transition(
+                                host.protocol(),
+                                \"{name}\",
+                                \"{initial}\",
+                                \"{from}\",
+                                \"{to}\",
+                            )?;\n"
+                        ));
+                    }
+                }
+                if self.gen.opts.faults {
+                    self.push_str(&format!(
+                        "host.fault_injector().before_call(\"{name}\")?;\n"
+                    ));
+                }
+
+                // Results are mutated as they come out of the host, before
+                // spliced data is exchanged for its token.
+                let has_handle = match &func.result {
+                    Type::Handle(_) => true,
+                    Type::Id(id) => self.gen.info(*id).has_handle,
+                    _ => false,
+                };
+                let mutated = (self.gen.opts.mutants || self.gen.opts.faults)
+                    && func.result != Type::Unit
+                    && !has_handle;
+                if mutated {
+                    self.push_str("let mut result = ");
+                } else {
+                    self.push_str("let result = ");
+                }
                 results.push("result".to_string());
                 match self.gen.classify_fn_ret(iface, func) {
                     FunctionRet::Normal => self.push_str(&call),
@@ -1964,8 +4312,40 @@ impl Bindgen for FunctionBindgen<'_> {
                         ));
                         self.push_str("}");
                     }
+                    FunctionRet::Anyhow => {
+                        self.push_str("match ");
+                        self.push_str(&call);
+                        self.push_str("{\n");
+                        self.push_str("Ok(val) => val,\n");
+                        self.push_str(
+                            "Err(e) => return Err(wasmtime::Trap::from(
+                                Box::<dyn std::error::Error + Send + Sync>::from(e),
+                            )),\n",
+                        );
+                        self.push_str("}");
+                    }
                 }
                 self.push_str(";\n");
+                if mutated && self.gen.opts.mutants {
+                    self.push_str(&format!(
+                        "wit_bindgen_host_wasmtime_rust::mutate::Mutator::mutate_result(
+                            host.mutator(),
+                            \"{name}\",
+                            &mut result,
+                        );\n",
+                    ));
+                }
+                if mutated && self.gen.opts.faults {
+                    self.push_str(&format!(
+                        "host.fault_injector().after_call(\"{name}\", &mut result);\n"
+                    ));
+                }
+                if self.gen.opts.call_log {
+                    self.push_str("host.call_log().exit(crossing, &result);\n");
+                }
+                if synthetic.is_some_and(|synthetic| synthetic.spliced_result) {
+                    self.push_str("let result = data_tokens.lock().unwrap().insert(result);\n");
+                }
                 self.after_call = true;
                 match &func.result {
                     Type::Unit => {}
@@ -2064,8 +4444,120 @@ impl NeededFunction {
     }
 }
 
+/// Returns whether values of type `ty` are passed to exports as Rust types
+/// implementing `Hash` and `Eq`, which generated types don't.
+fn hashable(iface: &Interface, ty: &Type) -> bool {
+    match ty {
+        Type::Float32 | Type::Float64 | Type::Handle(_) => false,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(t) | TypeDefKind::Option(t) | TypeDefKind::List(t) => {
+                hashable(iface, t)
+            }
+            TypeDefKind::Tuple(t) => t.types.iter().all(|t| hashable(iface, t)),
+            TypeDefKind::Result(r) => hashable(iface, &r.ok) && hashable(iface, &r.err),
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
 fn sorted_iter<K: Ord, V>(map: &HashMap<K, V>) -> impl Iterator<Item = (&K, &V)> {
     let mut list = map.into_iter().collect::<Vec<_>>();
     list.sort_by_key(|p| p.0);
     list.into_iter()
 }
+
+/// Returns the group `func` is annotated with as `@group("name")`, whose
+/// trait declares the method implementing it.
+fn trait_group(func: &Function) -> Option<String> {
+    let annotation = func.docs.annotation("group")?;
+    match annotation.args.as_slice() {
+        [group] => Some(group.clone()),
+        _ => panic!("`@group` on `{}` takes the name of a group", func.name),
+    }
+}
+
+/// Returns the adapters between the host trait `name`, whose grouped methods
+/// are declared by the traits of `groups`, and a monolithic trait declaring
+/// all of them, so that hosts may implement either.
+///
+/// `items` are the other items of the host trait, of which the methods in
+/// `forwarded` must be forwarded by implementations.
+fn group_adapters(
+    name: &str,
+    groups: &BTreeMap<String, Vec<(&str, &str)>>,
+    items: &str,
+    forwarded: &[(&str, &str)],
+    supertraits: &str,
+    unsized_bound: &str,
+) -> String {
+    let monolithic = format!("{}Monolithic", name);
+    let supertraits = match supertraits.strip_prefix(" + ") {
+        Some(supertraits) => format!(": {}", supertraits),
+        None => String::new(),
+    };
+    let unsized_param = if unsized_bound.is_empty() {
+        ""
+    } else {
+        ": ?Sized"
+    };
+    let grouped = || groups.values().flatten();
+
+    let mut src = format!(
+        "
+            /// The methods of [`{name}`] and of all its groups in a single
+            /// trait.
+            ///
+            /// Its implementations implement [`{name}`] and its groups too,
+            /// while [`{name}Groups`] implements it for an implementation of
+            /// the groups.
+            pub trait {monolithic}{supertraits} {{
+                {items}
+        "
+    );
+    for (signature, _) in grouped() {
+        src.push_str(signature);
+        src.push_str(";\n\n");
+    }
+    src.push_str("}\n");
+
+    let forward = |src: &mut String, (signature, call): &(&str, &str)| {
+        src.push_str(&format!(
+            "{} {{ {}::{} }}\n",
+            signature,
+            monolithic,
+            call.replacen('(', "(self, ", 1),
+        ));
+    };
+    for (group, methods) in groups {
+        src.push_str(&format!(
+            "impl<T: {monolithic}{unsized_bound}> {group} for T {{\n"
+        ));
+        for method in methods {
+            forward(&mut src, method);
+        }
+        src.push_str("}\n");
+    }
+    src.push_str(&format!(
+        "impl<T: {monolithic}{unsized_bound}> {name} for T {{\n"
+    ));
+    for method in forwarded {
+        forward(&mut src, method);
+    }
+    src.push_str("}\n");
+
+    src.push_str(&format!(
+        "
+            /// Implements [`{monolithic}`] for the implementation of [`{name}`]
+            /// and its groups it wraps.
+            pub struct {name}Groups<T{unsized_param}>(pub T);
+
+            impl<T: {name}{unsized_bound}> {monolithic} for {name}Groups<T> {{
+        "
+    ));
+    for (signature, call) in grouped().chain(forwarded) {
+        src.push_str(&format!("{} {{ self.0.{} }}\n", signature, call));
+    }
+    src.push_str("}\n");
+    src
+}
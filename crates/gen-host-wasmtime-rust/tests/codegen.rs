@@ -52,3 +52,1098 @@ mod custom_errors {
         custom_error: true,
     });
 }
+
+mod batching {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["x"]: "
+            enum mode {
+                overwrite,
+                append,
+            }
+            set: func(key: string, value: list<u8>, mode: mode)
+            remove: func(key: string)
+            get: func(key: string) -> option<list<u8>>
+        ",
+        batch: ["set", "remove"],
+    });
+}
+
+mod chunking {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["x"]: "
+            record entry {
+                key: string,
+                value: list<u8>,
+            }
+            scan: func(prefix: string) -> list<entry>
+            log: func(since: u64) -> list<string>
+            put: func(entries: list<entry>) -> list<u32>
+        ",
+        chunked: true,
+    });
+}
+
+mod memory_budget {
+    mod exports {
+        wit_bindgen_host_wasmtime_rust::export!({
+            src["x"]: "
+                record page {
+                    title: string,
+                    tags: list<string>,
+                    body: list<u8>,
+                }
+                fetch: func(id: u32) -> page
+                store: func(page: page)
+            ",
+            memory_budget: true,
+        });
+    }
+
+    mod imports {
+        wit_bindgen_host_wasmtime_rust::import!({
+            src["x"]: "
+                record page {
+                    title: string,
+                    tags: list<string>,
+                    body: list<u8>,
+                }
+                fetch: func(id: u32) -> page
+                store: func(page: page)
+            ",
+            memory_budget: true,
+        });
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        fn data_is_send_sync() {
+            assert_send_sync::<x::XData>();
+        }
+    }
+
+    mod local {
+        use std::cell::Cell;
+        use std::rc::Rc;
+        use wit_bindgen_host_wasmtime_rust::{BudgetExceeded, MemoryBudget};
+
+        wit_bindgen_host_wasmtime_rust::import!({
+            src["x"]: "
+                store: func(body: list<u8>)
+            ",
+            memory_budget: true,
+            thread_bounds: "local",
+        });
+
+        /// A budget shared with the embedder, which isn't `Send`.
+        struct Shared(Rc<Cell<u64>>);
+
+        impl MemoryBudget for Shared {
+            fn reserve(&mut self, bytes: u64) -> Result<(), BudgetExceeded> {
+                let remaining = self.0.get();
+                match remaining.checked_sub(bytes) {
+                    Some(left) => {
+                        self.0.set(left);
+                        Ok(())
+                    }
+                    None => Err(BudgetExceeded {
+                        requested: bytes,
+                        remaining,
+                    }),
+                }
+            }
+        }
+
+        fn share_budget(data: &mut x::XData, budget: Rc<Cell<u64>>) {
+            data.set_memory_budget(Shared(budget));
+        }
+    }
+}
+
+mod ordering {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["x"]: "
+            record chain-id {
+                owner: string,
+                index: u32,
+            }
+            variant key {
+                chain(chain-id),
+                numeric(u64),
+            }
+            lookup: func(owner: string) -> option<key>
+        ",
+        derive_ord: true,
+    });
+
+    fn assert_ord<T: Ord>() {}
+
+    #[test]
+    fn derives_ord() {
+        assert_ord::<x::ChainId>();
+        assert_ord::<x::Key>();
+    }
+}
+
+mod round_trips {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["store"]: "
+            flags permissions { read, write, admin }
+            enum level { low, high }
+            record entry {
+                key: string,
+                value: option<list<u8>>,
+                level: level,
+                permissions: permissions,
+                position: tuple<float32, s64>,
+            }
+            variant change {
+                insert(entry),
+                remove(string),
+                clear,
+            }
+            union id { u64, string }
+            apply: func(changes: list<change>, ids: list<id>) -> result<tuple<list<change>, list<id>>, string>
+        ",
+        proptest: true,
+    });
+
+    fn assert_partial_eq<T: PartialEq + Clone>() {}
+
+    #[test]
+    fn derives_partial_eq() {
+        assert_partial_eq::<store::Entry>();
+        assert_partial_eq::<store::Change>();
+        assert_partial_eq::<store::Id>();
+    }
+}
+
+mod object_safety {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["x"]: "
+            resource session {
+                read: func() -> list<u8>
+            }
+            open: func(name: string) -> result<session, u32>
+            close: func(s: session)
+        ",
+        custom_error: true,
+        object_safe: true,
+    });
+
+    type Plugin = dyn x::X<Session = String, Error = anyhow::Error> + Send;
+
+    struct State {
+        plugin: Box<Plugin>,
+        tables: x::XTables<Plugin>,
+    }
+
+    fn add_plugins(linker: &mut wasmtime::Linker<State>) -> anyhow::Result<()> {
+        x::add_to_linker(linker, |s: &mut State| (&mut *s.plugin, &mut s.tables))
+    }
+}
+
+mod thread_bounds {
+    mod local {
+        wit_bindgen_host_wasmtime_rust::export!({
+            src["x"]: "
+                resource cursor
+                advance: func(c: cursor) -> u32
+            ",
+            thread_bounds: "local",
+        });
+
+        struct Host;
+
+        impl x::X for Host {
+            type Cursor = std::rc::Rc<std::cell::Cell<u32>>;
+
+            fn advance(&mut self, c: &Self::Cursor) -> u32 {
+                c.set(c.get() + 1);
+                c.get()
+            }
+        }
+    }
+
+    mod shared {
+        wit_bindgen_host_wasmtime_rust::export!({
+            src["x"]: "
+                resource cursor
+                advance: func(c: cursor) -> u32
+            ",
+            thread_bounds: "send-sync",
+        });
+
+        fn assert_send_sync<T: Send + Sync>() {}
+
+        fn hosts_are_send_sync<T: x::X>() {
+            assert_send_sync::<T>();
+            assert_send_sync::<T::Cursor>();
+        }
+    }
+}
+
+mod task_scope {
+    use wit_bindgen_host_wasmtime_rust::tasks::TaskScope;
+
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["x"]: "
+            prefetch: func(key: string)
+        ",
+        task_scope: true,
+    });
+
+    struct Host {
+        tasks: TaskScope,
+    }
+
+    impl x::X for Host {
+        fn task_scope(&self) -> &TaskScope {
+            &self.tasks
+        }
+
+        fn prefetch(&mut self, key: &str) {
+            let key = key.to_string();
+            self.spawn(async move { drop(key) });
+        }
+    }
+}
+
+mod component_compat {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["logger"]: "
+            record entry {
+                level: u8,
+                message: string,
+                tags: list<string>,
+            }
+            log: func(e: entry, data: list<u32>) -> result<u64, string>
+            name: func() -> string
+        ",
+        component_compat: true,
+    });
+
+    #[derive(Default)]
+    struct Logger {
+        entries: Vec<(logger::Entry, Vec<u32>)>,
+    }
+
+    impl logger::Host for Logger {
+        fn log(&mut self, e: logger::Entry, data: Vec<u32>) -> anyhow::Result<Result<u64, String>> {
+            if e.message.is_empty() {
+                anyhow::bail!("empty log message");
+            }
+            self.entries.push((e, data));
+            Ok(Ok(self.entries.len() as u64))
+        }
+
+        fn name(&mut self) -> anyhow::Result<String> {
+            Ok("logger".to_string())
+        }
+    }
+
+    fn add_to_linker(linker: &mut wasmtime::Linker<Logger>) -> anyhow::Result<()> {
+        logger::add_to_linker(linker, |logger| logger)
+    }
+}
+
+mod spawn_blocking {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["worker"]: "
+            record point {
+                x: s32,
+                y: s32,
+            }
+            record job {
+                name: string,
+            }
+            distance: func(a: point, b: point) -> u64
+            checksum: func(name: string, data: list<u8>) -> u32
+            submit: func(j: job) -> job
+        ",
+        spawn_blocking: true,
+    });
+
+    async fn run(worker: worker::WorkerAsync<()>) -> anyhow::Result<()> {
+        let origin = worker::Point { x: 0, y: 0 };
+        worker
+            .distance(origin, worker::Point { x: 3, y: 4 })
+            .await?;
+        worker.checksum("data".to_string(), vec![1, 2, 3]).await?;
+
+        // Functions taking borrowed records go through `call`.
+        let job = worker
+            .call(|exports, store| exports.submit(store, worker::JobParam { name: "job" }))
+            .await?;
+        assert_eq!(job.name, "job");
+        Ok(())
+    }
+}
+
+mod coalesce {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["storage"]: "
+            read: func(key: string) -> option<list<u8>>
+            write: func(key: string, value: list<u8>)
+        ",
+        spawn_blocking: true,
+        coalesce: ["read"],
+    });
+
+    use storage::{Storage, StorageAsync, StorageRequest};
+
+    fn assert_key<K: Clone + Eq + std::hash::Hash + Send + Sync>() {}
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[test]
+    fn requests_are_keys() {
+        assert_key::<StorageRequest>();
+        let read = StorageRequest::Read {
+            key: "a".to_string(),
+        };
+        assert_eq!(read.clone(), read);
+    }
+
+    fn open(exports: Storage<()>, store: wasmtime::Store<()>) -> StorageAsync<()> {
+        // Only reads of settings, which don't change, are merged.
+        let policy = |request: &StorageRequest| match request {
+            StorageRequest::Read { key } => key.starts_with("settings/"),
+        };
+        StorageAsync::with_coalesce_policy(exports, store, policy)
+    }
+
+    async fn run(storage: StorageAsync<()>) -> anyhow::Result<()> {
+        let read = storage.read("settings/name".to_string());
+        assert_send(&read);
+        read.await?;
+        storage.write("data".to_string(), vec![1, 2, 3]).await?;
+        Ok(())
+    }
+}
+
+mod call_depth {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["depth"]: "
+            record tree {
+                children: list<option<u32>>,
+            }
+            visit: func(t: tree) -> u32
+        ",
+        call_depth: 8,
+    });
+
+    #[test]
+    fn stack_follows_nesting() {
+        assert_eq!(depth::MAX_TYPE_NESTING, 3);
+        assert_eq!(depth::MAX_CALL_DEPTH, 8);
+
+        let mut config = wasmtime::Config::new();
+        depth::configure_stack(&mut config);
+        wasmtime::Engine::new(&config).unwrap();
+    }
+}
+
+mod abi_v1 {
+    // Lowering the string argument calls into the guest's allocator, which
+    // is looked up as `canonical_abi_realloc`.
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["legacy"]: "
+            greet: func(name: string) -> string
+        ",
+        abi_version: 1,
+    });
+}
+
+mod interning {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["metrics"]: "
+            record-value: func(app: string, key: string, value: u64)
+        ",
+        interned: ["record-value"],
+    });
+}
+
+mod bulk {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["blobs"]: "
+            store: func(blob: u32) -> u32
+            load: func(key: u32) -> u32
+        ",
+        bulk: true,
+    });
+
+    use wit_bindgen_host_wasmtime_rust::bulk::BulkBuffers;
+
+    /// Keeps stored blobs by key, handing wasm a fresh buffer on each load.
+    #[derive(Default)]
+    struct Blobs {
+        buffers: BulkBuffers,
+        stored: Vec<Vec<u8>>,
+    }
+
+    impl blobs::Blobs for Blobs {
+        fn bulk_buffers(&mut self) -> &mut BulkBuffers {
+            &mut self.buffers
+        }
+
+        fn store(&mut self, blob: u32) -> u32 {
+            let bytes = self.buffers.remove(blob).unwrap();
+            self.stored.push(bytes);
+            self.stored.len() as u32 - 1
+        }
+
+        fn load(&mut self, key: u32) -> u32 {
+            let bytes = self.stored[key as usize].clone();
+            self.buffers.insert(bytes)
+        }
+    }
+}
+
+mod splicing {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["storage"]: "
+            @splice
+            read: func(key: string) -> list<u8>
+            @splice
+            hash: func(data: list<u8>) -> u64
+        ",
+    });
+
+    struct Storage(std::collections::HashMap<String, Vec<u8>>);
+
+    impl storage::Storage for Storage {
+        fn read(&mut self, key: &str) -> Vec<u8> {
+            self.0.get(key).cloned().unwrap_or_default()
+        }
+
+        fn hash(&mut self, data: &[u8]) -> u64 {
+            data.iter().map(|b| u64::from(*b)).sum()
+        }
+    }
+}
+
+mod dispatch_table {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["ops"]: "
+            add: func(a: u64, b: u64) -> u64
+            name: func() -> string
+        ",
+        dispatch_table: true,
+    });
+
+    #[allow(dead_code)]
+    fn add_by_id(ops: &ops::Ops<()>, store: &mut wasmtime::Store<()>) -> anyhow::Result<u64> {
+        let op = ops::Ops::<()>::OPERATIONS
+            .iter()
+            .position(|name| *name == "add")
+            .unwrap() as u32;
+        let mut results = [wasmtime::Val::I64(0)];
+        ops.invoke(
+            store,
+            op,
+            &[wasmtime::Val::I64(1), wasmtime::Val::I64(2)],
+            &mut results,
+        )?;
+        Ok(results[0].unwrap_i64() as u64)
+    }
+}
+
+mod symbols {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["kv"]: "
+            get: func(key: string) -> option<u64>
+        ",
+        symbols: true,
+    });
+
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["contract"]: "
+            execute: func(operation: list<u8>) -> list<u8>
+        ",
+        symbols: true,
+    });
+
+    use wit_bindgen_host_wasmtime_rust::symbolicate::describe_frame;
+
+    #[test]
+    fn describes_glue_frames() {
+        let interfaces = [kv::SYMBOLS, contract::SYMBOLS];
+        assert_eq!(
+            describe_frame(&interfaces, Some("app::kv::get"), 0),
+            "calling import kv.get"
+        );
+        assert_eq!(
+            describe_frame(
+                &interfaces,
+                Some("app::contract::__wit_bindgen_contract_execute"),
+                1
+            ),
+            "in export contract.execute"
+        );
+    }
+}
+
+mod mutants {
+    use wit_bindgen_host_wasmtime_rust::mutate::{Mutation, Mutator};
+
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["inventory"]: "
+            flags access { read, write }
+            enum level { low, high }
+            variant stock { available(u32), reserved(u32), missing }
+            record item { name: string, access: access, level: level, stock: stock }
+            get: func(name: string) -> option<item>
+            entries: func() -> list<tuple<string, bool>>
+        ",
+        mutants: true,
+    });
+
+    use inventory::{Access, Item, Level, Stock};
+
+    struct Inventory {
+        mutator: Mutator,
+    }
+
+    impl inventory::Inventory for Inventory {
+        fn mutator(&self) -> &Mutator {
+            &self.mutator
+        }
+
+        fn get(&mut self, name: &str) -> Option<Item> {
+            Some(Item {
+                name: name.to_string(),
+                access: Access::READ,
+                level: Level::Low,
+                stock: Stock::Available(3),
+            })
+        }
+
+        fn entries(&mut self) -> Vec<(String, bool)> {
+            vec![("apples".to_string(), true)]
+        }
+    }
+
+    #[test]
+    fn permutes_cases_with_the_same_payload() {
+        let item = || Item {
+            name: "pear".to_string(),
+            access: Access::READ,
+            level: Level::Low,
+            stock: Stock::Available(3),
+        };
+        // The name, both flags, the level and the stock come one after the
+        // other.
+        let mutator = Mutator::at(4);
+        let mut stocked = item();
+        mutator.mutate_result("inventory.get", &mut stocked);
+        assert!(matches!(stocked.stock, Stock::Reserved(3)));
+        assert_eq!(mutator.applied().unwrap().mutation, Mutation::PermuteTag);
+
+        let mutator = Mutator::at(1);
+        let mut flipped = item();
+        mutator.mutate_result("inventory.get", &mut flipped);
+        assert_eq!(flipped.access, Access::empty());
+    }
+}
+
+mod faults {
+    use std::time::Duration;
+    use wit_bindgen_host_wasmtime_rust::fault::FaultInjector;
+
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["feed"]: "
+            record post { title: string, tags: list<string> }
+            latest: func(count: u32) -> list<post>
+            publish: func(post: post)
+        ",
+        faults: true,
+    });
+
+    struct Feed {
+        faults: FaultInjector,
+    }
+
+    impl feed::Feed for Feed {
+        fn fault_injector(&self) -> &FaultInjector {
+            &self.faults
+        }
+
+        fn latest(&mut self, count: u32) -> Vec<feed::PostResult> {
+            (0..count)
+                .map(|i| feed::PostResult {
+                    title: format!("post {i}"),
+                    tags: Vec::new(),
+                })
+                .collect()
+        }
+
+        fn publish(&mut self, post: feed::PostParam<'_>) {
+            drop(post);
+        }
+    }
+
+    #[allow(dead_code)]
+    fn flaky() -> Feed {
+        Feed {
+            faults: FaultInjector::new(42)
+                .errors(0.01)
+                .latency(0.1, Duration::from_millis(5))
+                .truncation(0.05),
+        }
+    }
+}
+
+mod quotas {
+    use wit_bindgen_host_wasmtime_rust::{Quota, Quotas};
+
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["storage"]: "
+            read: func(key: string) -> list<u8>
+            write: func(key: string, value: list<u8>)
+            keys: func() -> list<string>
+        ",
+        quotas: true,
+    });
+
+    struct Storage {
+        quotas: Quotas,
+    }
+
+    impl storage::Storage for Storage {
+        fn quotas(&mut self) -> &mut Quotas {
+            &mut self.quotas
+        }
+
+        fn read(&mut self, key: &str) -> Vec<u8> {
+            key.as_bytes().to_vec()
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) {
+            drop((key, value));
+        }
+
+        fn keys(&mut self) -> Vec<String> {
+            Vec::new()
+        }
+    }
+
+    #[allow(dead_code)]
+    fn limited() -> Storage {
+        Storage {
+            quotas: Quotas::new()
+                .with_default(Quota {
+                    calls: Some(100),
+                    bytes: Some(64 * 1024),
+                })
+                .with_limit(
+                    "storage.write",
+                    Quota {
+                        calls: Some(10),
+                        bytes: Some(4 * 1024),
+                    },
+                ),
+        }
+    }
+}
+
+mod lowered_size {
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["journal"]: "
+            record entry { key: string, tags: list<string> }
+            variant change { put(entry), clear }
+            apply: func(c: change) -> u32
+        ",
+        lowered_size: true,
+    });
+
+    use journal::{Change, Entry};
+
+    #[test]
+    fn counts_borrowed_strings_and_lists() {
+        let entry = Entry {
+            key: "ab",
+            tags: &["x", "yz"],
+        };
+        // 16 bytes for the record, 2 for the key and 2 * 8 + 3 for the tags.
+        assert_eq!(entry.lowered_size(), 37);
+        assert_eq!(Change::Put(entry).lowered_size(), 41);
+        assert_eq!(Change::Clear.lowered_size(), 20);
+    }
+}
+
+mod call_log {
+    use wit_bindgen_host_wasmtime_rust::call_log::CallLog;
+
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["kv"]: "
+            get: func(key: string) -> option<list<u8>>
+            set: func(key: string, value: list<u8>)
+        ",
+        call_log: true,
+    });
+
+    struct Kv {
+        log: CallLog,
+    }
+
+    impl kv::Kv for Kv {
+        fn call_log(&self) -> &CallLog {
+            &self.log
+        }
+
+        fn get(&mut self, key: &str) -> Option<Vec<u8>> {
+            Some(key.as_bytes().to_vec())
+        }
+
+        fn set(&mut self, key: &str, value: &[u8]) {
+            drop((key, value));
+        }
+    }
+
+    #[allow(dead_code)]
+    fn logged() -> Kv {
+        Kv {
+            log: CallLog::new(16).summary_len(32),
+        }
+    }
+}
+
+mod max_list_len {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["store"]: "
+            record blob { name: string, chunks: list<list<u8>> }
+            put: func(b: blob, tags: list<string>) -> u32
+        ",
+        max_list_len: 4096,
+    });
+
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["fetch"]: "
+            fetch: func(key: string) -> list<string>
+        ",
+        max_list_len: 4096,
+    });
+}
+
+mod lift_budget {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["store"]: "
+            put: func(key: string, values: list<list<u8>>)
+        ",
+        lift_budget: { "store": 65536 },
+    });
+
+    wit_bindgen_host_wasmtime_rust::import!({
+        src["fetch"]: "
+            record page { items: list<string>, next: option<string> }
+            fetch: func(key: string) -> page
+        ",
+        lift_budget: { "fetch": 65536 },
+    });
+}
+
+mod cfg_features {
+    // `structopt` is a feature of this crate, off in its tests, so the
+    // gated import is compiled out and left unimplemented.
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["syscalls"]: r#"
+            now: func() -> u64
+            @feature("structopt")
+            dump-state: func() -> string
+        "#,
+        cfg_features: true,
+    });
+
+    struct Host;
+
+    impl syscalls::Syscalls for Host {
+        fn now(&mut self) -> u64 {
+            0
+        }
+    }
+
+    fn add_syscalls(linker: &mut wasmtime::Linker<Host>) -> anyhow::Result<()> {
+        syscalls::add_to_linker(linker, |host| host)
+    }
+}
+
+mod trait_groups {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["syscalls"]: r#"
+            @group("storage")
+            read: func(key: string) -> option<list<u8>>
+            @group("storage")
+            write: func(key: string, value: list<u8>)
+            @group("crypto")
+            sha256: func(data: list<u8>) -> list<u8>
+            chain-id: func() -> u64
+        "#,
+    });
+
+    use syscalls::{Crypto, Storage, Syscalls};
+
+    #[derive(Default)]
+    struct Host {
+        entries: std::collections::HashMap<String, Vec<u8>>,
+    }
+
+    impl Storage for Host {
+        fn read(&mut self, key: &str) -> Option<Vec<u8>> {
+            self.entries.get(key).cloned()
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) {
+            self.entries.insert(key.to_string(), value.to_vec());
+        }
+    }
+
+    impl Crypto for Host {
+        fn sha256(&mut self, _data: &[u8]) -> Vec<u8> {
+            vec![0; 32]
+        }
+    }
+
+    impl Syscalls for Host {
+        fn chain_id(&mut self) -> u64 {
+            0
+        }
+    }
+
+    // Each group can be used, and mocked, on its own.
+    fn copy(storage: &mut impl Storage, from: &str, to: &str) {
+        if let Some(value) = storage.read(from) {
+            storage.write(to, &value);
+        }
+    }
+
+    fn add_syscalls(linker: &mut wasmtime::Linker<Host>) -> anyhow::Result<()> {
+        syscalls::add_to_linker(linker, |host| host)
+    }
+
+    #[test]
+    fn combines_the_groups() {
+        let mut host = Host::default();
+        host.write("a", b"1");
+        copy(&mut host, "a", "b");
+        assert_eq!(host.read("b").as_deref(), Some(&b"1"[..]));
+        assert_eq!(host.chain_id(), 0);
+    }
+}
+
+mod trait_group_adapters {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["syscalls"]: r#"
+            @group("storage")
+            read: func(key: string) -> option<list<u8>>
+            @group("storage")
+            write: func(key: string, value: list<u8>)
+            chain-id: func() -> u64
+        "#,
+    });
+
+    use syscalls::{Storage, Syscalls, SyscallsGroups, SyscallsMonolithic};
+
+    // A host written before the methods were grouped.
+    #[derive(Default)]
+    struct Monolithic {
+        entries: std::collections::HashMap<String, Vec<u8>>,
+    }
+
+    impl SyscallsMonolithic for Monolithic {
+        fn read(&mut self, key: &str) -> Option<Vec<u8>> {
+            self.entries.get(key).cloned()
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) {
+            self.entries.insert(key.to_string(), value.to_vec());
+        }
+
+        fn chain_id(&mut self) -> u64 {
+            1
+        }
+    }
+
+    #[derive(Default)]
+    struct Split(Monolithic);
+
+    impl Storage for Split {
+        fn read(&mut self, key: &str) -> Option<Vec<u8>> {
+            SyscallsMonolithic::read(&mut self.0, key)
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) {
+            SyscallsMonolithic::write(&mut self.0, key, value)
+        }
+    }
+
+    impl Syscalls for Split {
+        fn chain_id(&mut self) -> u64 {
+            2
+        }
+    }
+
+    fn copy(storage: &mut impl Storage, from: &str, to: &str) {
+        if let Some(value) = storage.read(from) {
+            storage.write(to, &value);
+        }
+    }
+
+    fn chain_id(host: &mut impl SyscallsMonolithic) -> u64 {
+        SyscallsMonolithic::chain_id(host)
+    }
+
+    fn add_monolithic(linker: &mut wasmtime::Linker<Monolithic>) -> anyhow::Result<()> {
+        syscalls::add_to_linker(linker, |host| host)
+    }
+
+    #[test]
+    fn monolithic_hosts_implement_the_groups() {
+        let mut host = Monolithic::default();
+        host.entries.insert("a".to_string(), b"1".to_vec());
+        copy(&mut host, "a", "b");
+        assert_eq!(host.entries["b"], b"1");
+        assert_eq!(Syscalls::chain_id(&mut host), 1);
+    }
+
+    #[test]
+    fn grouped_hosts_implement_the_monolithic_trait() {
+        let mut host = SyscallsGroups(Split::default());
+        SyscallsMonolithic::write(&mut host, "a", b"1");
+        assert_eq!(Storage::read(&mut host.0, "a").as_deref(), Some(&b"1"[..]));
+        assert_eq!(chain_id(&mut host), 2);
+    }
+}
+
+mod doc_examples {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["ledger"]: r#"
+            record transfer { owner: string, amount: u64 }
+            enum kind { credit, debit }
+            flags rights { read, write }
+
+            /// Moves tokens between accounts.
+            apply: func(transfer: transfer, kind: kind, memo: option<list<u8>>) -> result<u64, string>
+            rights-of: func(owner: string) -> rights
+            reset: func()
+        "#,
+        doc_examples: true,
+    });
+
+    #[derive(Default)]
+    struct Ledger {
+        balance: u64,
+    }
+
+    impl ledger::Ledger for Ledger {
+        fn apply(
+            &mut self,
+            transfer: ledger::Transfer<'_>,
+            kind: ledger::Kind,
+            _memo: Option<&[u8]>,
+        ) -> Result<u64, String> {
+            match kind {
+                ledger::Kind::Credit => self.balance += transfer.amount,
+                ledger::Kind::Debit => self.balance -= transfer.amount,
+            }
+            Ok(self.balance)
+        }
+
+        fn rights_of(&mut self, _owner: &str) -> ledger::Rights {
+            ledger::Rights::READ
+        }
+
+        fn reset(&mut self) {
+            self.balance = 0;
+        }
+    }
+
+    fn add_ledger(linker: &mut wasmtime::Linker<Ledger>) -> anyhow::Result<()> {
+        ledger::add_to_linker(linker, |host| host)
+    }
+}
+
+mod protocol {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["kv"]: r#"
+            @transition("idle", "batch")
+            begin-batch: func()
+            @transition("batch", "batch")
+            write: func(key: string, value: list<u8>)
+            @transition("batch", "idle")
+            commit: func() -> u32
+        "#,
+        protocol: true,
+    });
+
+    use wit_bindgen_host_wasmtime_rust::protocol::Protocol;
+
+    #[derive(Default)]
+    struct Kv {
+        protocol: Protocol,
+        pending: Vec<(String, Vec<u8>)>,
+    }
+
+    impl kv::Kv for Kv {
+        fn protocol(&mut self) -> &mut Protocol {
+            &mut self.protocol
+        }
+
+        fn begin_batch(&mut self) {
+            self.pending.clear();
+        }
+
+        fn write(&mut self, key: &str, value: &[u8]) {
+            self.pending.push((key.to_string(), value.to_vec()));
+        }
+
+        fn commit(&mut self) -> u32 {
+            self.pending.drain(..).count() as u32
+        }
+    }
+
+    fn add_kv(linker: &mut wasmtime::Linker<Kv>) -> anyhow::Result<()> {
+        kv::add_to_linker(linker, |host| host)
+    }
+}
+
+mod idempotent {
+    wit_bindgen_host_wasmtime_rust::export!({
+        src["payments"]: "
+            @idempotent
+            transfer: func(to: string, amount: u64) -> result<u64, string>
+            balance: func() -> u64
+        ",
+    });
+
+    use wit_bindgen_host_wasmtime_rust::idempotency::Deduplicator;
+
+    #[derive(Default)]
+    struct Payments {
+        balance: u64,
+        transfers: Deduplicator<u64>,
+    }
+
+    impl payments::Payments for Payments {
+        fn transfer(
+            &mut self,
+            _to: &str,
+            amount: u64,
+            idempotency_key: u64,
+        ) -> Result<u64, String> {
+            let balance = &mut self.balance;
+            self.transfers.try_call(idempotency_key, || {
+                *balance = balance
+                    .checked_sub(amount)
+                    .ok_or_else(|| "insufficient funds".to_string())?;
+                Ok(*balance)
+            })
+        }
+
+        fn balance(&mut self) -> u64 {
+            self.balance
+        }
+    }
+
+    fn add_payments(linker: &mut wasmtime::Linker<Payments>) -> anyhow::Result<()> {
+        payments::add_to_linker(linker, |host| host)
+    }
+}
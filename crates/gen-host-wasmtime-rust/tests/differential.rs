@@ -0,0 +1,271 @@
+//! Round-trips values through guests built with the bindings of this fork and
+//! with those of upstream wit-bindgen.
+//!
+//! `tests/differential/echo.rs` implements the functions of
+//! `tests/differential/echo.wit`, which return their argument, and is built
+//! for `wasm32-unknown-unknown` once against each version of the guest
+//! runtime. The host bindings of this fork then pass the same values to both
+//! modules, and each function's outcome, whether the value came back
+//! unchanged, changed, or trapped, is compared. Values must round-trip through
+//! the fork's own guest; a function which behaves differently with upstream's
+//! is a divergence.
+//!
+//! Divergences we know about are listed as `echo.wit round-trip {function}`,
+//! with the reason for them, in `tests/differential/divergences.txt`, along
+//! with those found by the `differential` test of `crates/gen-guest-rust`. Any
+//! other divergence fails the test, as does a listed one which went away. A
+//! guest which can't be instantiated is reported as the `instantiate`
+//! function.
+//!
+//! Upstream is found through the `WIT_BINDGEN_UPSTREAM` environment variable,
+//! which points to a checkout of it. The test is ignored by default, as it
+//! needs that checkout and builds two wasm modules; run it with
+//! `cargo test --test differential -- --ignored`.
+
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Debug, Display};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use wasmtime::{Engine, Linker, Module, Store};
+
+wit_bindgen_host_wasmtime_rust::import!("../../tests/differential/echo.wit");
+
+use echo::*;
+
+/// The outcome of each function, by name.
+type Outcomes = BTreeMap<&'static str, String>;
+
+const ROUND_TRIPS: &str = "round-trips";
+
+#[test]
+#[ignore = "needs an upstream wit-bindgen checkout in `WIT_BINDGEN_UPSTREAM`"]
+fn round_trips_like_upstream() {
+    let upstream = PathBuf::from(std::env::var_os("WIT_BINDGEN_UPSTREAM").expect(
+        "point the `WIT_BINDGEN_UPSTREAM` environment variable to a checkout of upstream wit-bindgen",
+    ));
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("../..");
+    let known = known_divergences(&root.join("tests/differential/divergences.txt"));
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("differential");
+
+    let ours = build_guest(
+        &root,
+        &out_dir.join("ours"),
+        &format!(
+            "{{ path = {:?}, package = \"linera-wit-bindgen-guest-rust\" }}",
+            root.join("crates/guest-rust")
+        ),
+    );
+    let theirs = build_guest(
+        &root,
+        &out_dir.join("upstream"),
+        &format!("{{ path = {:?} }}", upstream.join("crates/guest-rust")),
+    );
+    let ours = outcomes(&ours).expect("failed to instantiate the guest built with this fork");
+    for (name, outcome) in ours.iter() {
+        assert_eq!(outcome, ROUND_TRIPS, "`{}` doesn't round-trip", name);
+    }
+    let theirs = outcomes(&theirs).unwrap_or_else(|e| {
+        let mut outcomes = Outcomes::new();
+        outcomes.insert("instantiate", e.to_string());
+        outcomes
+    });
+
+    let mut found = BTreeSet::new();
+    let names = match theirs.contains_key("instantiate") {
+        true => theirs.keys().collect::<BTreeSet<_>>(),
+        false => ours.keys().chain(theirs.keys()).collect(),
+    };
+    for name in names {
+        let (ours, theirs) = (ours.get(name), theirs.get(name));
+        if ours != theirs {
+            let entry = format!("echo.wit round-trip {}", name);
+            if !known.contains_key(&entry) {
+                eprintln!("{}: ours {:?}, upstream {:?}", entry, ours, theirs);
+            }
+            found.insert(entry);
+        }
+    }
+
+    let unexpected = found
+        .iter()
+        .filter(|e| !known.contains_key(*e))
+        .collect::<Vec<_>>();
+    let resolved = known
+        .keys()
+        .filter(|e| !found.contains(*e))
+        .collect::<Vec<_>>();
+    assert!(
+        unexpected.is_empty() && resolved.is_empty(),
+        "unlisted divergences: {:?}\nlisted divergences which are gone: {:?}",
+        unexpected,
+        resolved
+    );
+}
+
+/// Reads the `echo.wit round-trip {function}: {reason}` lines of the list of
+/// known divergences.
+fn known_divergences(path: &Path) -> BTreeMap<String, String> {
+    let contents = std::fs::read_to_string(path).unwrap();
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(|l| l.rsplit_once(": ").expect("missing reason"))
+        .filter(|(entry, _)| entry.split(' ').nth(1) == Some("round-trip"))
+        .map(|(entry, reason)| (entry.to_string(), reason.to_string()))
+        .collect()
+}
+
+/// Builds `tests/differential/echo.rs` in `dir`, against the guest runtime
+/// given as a Cargo dependency, and returns the path of the module.
+fn build_guest(root: &Path, dir: &Path, runtime: &str) -> PathBuf {
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::copy(
+        root.join("tests/differential/echo.wit"),
+        dir.join("echo.wit"),
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("Cargo.toml"),
+        format!(
+            "[package]\n\
+             name = \"echo\"\n\
+             version = \"0.0.0\"\n\
+             edition = \"2021\"\n\
+             publish = false\n\
+             \n\
+             [lib]\n\
+             crate-type = [\"cdylib\"]\n\
+             path = {:?}\n\
+             \n\
+             [dependencies]\n\
+             wit-bindgen-guest-rust = {}\n\
+             \n\
+             [workspace]\n",
+            root.join("tests/differential/echo.rs"),
+            runtime,
+        ),
+    )
+    .unwrap();
+    let status = Command::new("cargo")
+        .arg("build")
+        .arg("--release")
+        .arg("--target=wasm32-unknown-unknown")
+        .current_dir(dir)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .env_remove("CARGO_ENCODED_RUSTFLAGS")
+        .status()
+        .unwrap();
+    assert!(status.success(), "failed to build the guest in {:?}", dir);
+    dir.join("target/wasm32-unknown-unknown/release/echo.wasm")
+}
+
+/// Calls every function of the guest in `wasm`, with the same values whichever
+/// bindings it was built with.
+fn outcomes(wasm: &Path) -> Result<Outcomes> {
+    let engine = Engine::default();
+    let module = Module::from_file(&engine, wasm)?;
+    let mut linker = Linker::new(&engine);
+    let mut store = Store::new(&engine, EchoData::default());
+    let (echo, _instance) = Echo::instantiate(&mut store, &module, &mut linker, |cx| cx)?;
+
+    let mut outcomes = Outcomes::new();
+    let mut check = |name, outcome| {
+        outcomes.insert(name, outcome);
+    };
+    check(
+        "echo-u8",
+        outcome(echo.echo_u8(&mut store, u8::MAX), u8::MAX),
+    );
+    check(
+        "echo-s64",
+        outcome(echo.echo_s64(&mut store, i64::MIN), i64::MIN),
+    );
+    check(
+        "echo-float32",
+        outcome(echo.echo_float32(&mut store, -1.5), -1.5),
+    );
+    check(
+        "echo-float64",
+        outcome(echo.echo_float64(&mut store, f64::MAX), f64::MAX),
+    );
+    check("echo-char", outcome(echo.echo_char(&mut store, '⚑'), '⚑'));
+    check(
+        "echo-string",
+        outcome(
+            echo.echo_string(&mut store, "hello ⚑ world"),
+            "hello ⚑ world".to_string(),
+        ),
+    );
+    check(
+        "echo-bytes",
+        outcome(echo.echo_bytes(&mut store, &[0, 1, 255]), vec![0, 1, 255]),
+    );
+    check(
+        "echo-strings",
+        outcome(
+            echo.echo_strings(&mut store, &["", "a", "bc"]),
+            vec!["".to_string(), "a".to_string(), "bc".to_string()],
+        ),
+    );
+    let point = Point { x: -1, y: i32::MAX };
+    check(
+        "echo-point",
+        outcome(echo.echo_point(&mut store, point), point),
+    );
+    let permissions = Permissions::READ | Permissions::ADMIN;
+    check(
+        "echo-permissions",
+        outcome(echo.echo_permissions(&mut store, permissions), permissions),
+    );
+    check(
+        "echo-level",
+        outcome(echo.echo_level(&mut store, Level::High), Level::High),
+    );
+    let shapes = [Shape::Circle(0.5), Shape::Rectangle(point), Shape::Empty];
+    check(
+        "echo-shapes",
+        outcome(echo.echo_shapes(&mut store, &shapes), shapes.to_vec()),
+    );
+    check(
+        "echo-number",
+        outcome(
+            echo.echo_number(&mut store, Number::F64(2.5)),
+            Number::F64(2.5),
+        ),
+    );
+    check(
+        "echo-option",
+        outcome(
+            echo.echo_option(&mut store, Some("x")),
+            Some("x".to_string()),
+        ),
+    );
+    check(
+        "echo-result",
+        outcome(
+            echo.echo_result(&mut store, Err("no")),
+            Err("no".to_string()),
+        ),
+    );
+    check(
+        "echo-tuple",
+        outcome(
+            echo.echo_tuple(&mut store, (7, "seven", 7.0)),
+            (7, "seven".to_string(), 7.0),
+        ),
+    );
+    Ok(outcomes)
+}
+
+/// Describes what became of `expected` on its way through the guest. Values
+/// are compared through their `Debug` representation, as the host bindings
+/// don't derive `PartialEq`.
+fn outcome<T: Debug>(result: Result<T, impl Display>, expected: T) -> String {
+    match result {
+        Ok(value) if format!("{:?}", value) == format!("{:?}", expected) => ROUND_TRIPS.to_string(),
+        Ok(value) => format!("came back as {:?}", value),
+        Err(e) => format!("trapped: {}", e),
+    }
+}
@@ -1,5 +1,5 @@
 use heck::*;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Write};
 use std::iter::zip;
 use wit_bindgen_core::wit_parser::abi::{Bitcast, LiftLower, WasmType};
@@ -36,6 +36,169 @@ pub trait RustGenerator {
         false
     }
 
+    /// Whether records, variants, unions and enums should derive the
+    /// comparison traits up to `Ord` wherever all of their contents can.
+    fn derive_ord(&self) -> bool {
+        false
+    }
+
+    /// Whether records, variants and unions without handles should derive
+    /// `PartialEq` where they don't derive `Ord` and its supertraits already.
+    fn derive_partial_eq(&self) -> bool {
+        false
+    }
+
+    /// Paths of the derive macros to apply to every generated record,
+    /// variant, union, enum and flags type besides the built-in ones.
+    fn additional_derives(&self) -> &[String] {
+        &[]
+    }
+
+    /// Whether the generated code is for `#![no_std]` crates, which take
+    /// `String` and `Vec` from `alloc` and have no `std::error::Error`.
+    fn no_std(&self) -> bool {
+        false
+    }
+
+    /// Whether the named type `info` describes borrows its contents where it's
+    /// used as a parameter, which then takes a variant of its own if it's
+    /// also used as a result. Otherwise it's owned wherever it's used.
+    fn borrows_named_type(&self, _info: &TypeInfo) -> bool {
+        true
+    }
+
+    /// Emits the derives of [`RustGenerator::additional_derives`], if any.
+    fn print_additional_derives(&mut self) {
+        let derives = self.additional_derives();
+        if derives.is_empty() {
+            return;
+        }
+        let derives = format!("#[derive({})]\n", derives.join(", "));
+        self.push_str(&derives);
+    }
+
+    /// Whether sensitive types should derive `zeroize::Zeroize`, and
+    /// `zeroize::ZeroizeOnDrop` where the bindings allow it.
+    fn zeroize(&self) -> bool {
+        false
+    }
+
+    /// Whether the bindings move owned values of a type apart while lowering
+    /// them, which rules out giving the type a `Drop` impl.
+    fn lowers_owned(&self, _info: &TypeInfo) -> bool {
+        true
+    }
+
+    /// The Rust name the user gave to the function or type `wit_name`, or to
+    /// a case written `type.case`, if any.
+    fn renamed(&self, _wit_name: &str) -> Option<&str> {
+        None
+    }
+
+    /// The path of the existing Rust type the user mapped the named type
+    /// `wit_name` onto, if any, which is used instead of defining one.
+    fn mapped(&self, _wit_name: &str) -> Option<&str> {
+        None
+    }
+
+    /// Returns the Rust name of the function `name`.
+    fn func_ident(&self, name: &str) -> String {
+        match self.renamed(name) {
+            Some(renamed) => renamed.to_string(),
+            None => to_rust_ident(name),
+        }
+    }
+
+    /// Returns the Rust name of the named type `name`, before any `Param` or
+    /// `Result` suffix.
+    fn type_ident(&self, name: &str) -> String {
+        if let Some(path) = self.mapped(name) {
+            return path.to_string();
+        }
+        match self.renamed(name) {
+            Some(renamed) => renamed.to_string(),
+            None => name.to_camel_case(),
+        }
+    }
+
+    /// Returns the Rust name of `case` of the enum or variant named `ty`.
+    fn case_ident(&self, ty: &str, case: &str) -> String {
+        match self.renamed(&format!("{}.{}", ty, case)) {
+            Some(renamed) => renamed.to_string(),
+            None => case.to_camel_case(),
+        }
+    }
+
+    /// Emits the `zeroize` derives for the named type `id` when printed with
+    /// lifetime `lt`, returning whether its fields need `zeroize` attributes.
+    fn print_zeroize_derives(&mut self, iface: &Interface, id: TypeId, lt: Option<&str>) -> bool {
+        let info = self.info(id);
+        if !self.zeroize() || lt.is_some() || info.has_handle || !is_sensitive_type(iface, id) {
+            return false;
+        }
+        self.push_str("#[derive(zeroize::Zeroize");
+        if info.owns_data() && !self.lowers_owned(&info) {
+            self.push_str(", zeroize::ZeroizeOnDrop");
+        }
+        self.push_str(")]\n");
+        true
+    }
+
+    /// Whether sensitive records should implement `subtle::ConstantTimeEq`.
+    fn ct_eq(&self) -> bool {
+        false
+    }
+
+    /// The sizes of the types of the interface, if named types should get a
+    /// `lowered_size` method.
+    fn lowered_sizes(&self) -> Option<&SizeAlign> {
+        None
+    }
+
+    /// Emits the `lowered_size` method of the named type `id`, printed as
+    /// `name`, which adds `heap`, the size of the strings and lists the value
+    /// owns, to the size of the type.
+    fn print_lowered_size(
+        &mut self,
+        id: TypeId,
+        name: &str,
+        lt: Option<&'static str>,
+        heap: Option<String>,
+    ) {
+        let size = match self.lowered_sizes() {
+            Some(sizes) => sizes.size(&Type::Id(id)),
+            None => return,
+        };
+        let info = self.info(id);
+        self.push_str("impl");
+        self.print_generics(&info, lt, true);
+        self.push_str(" ");
+        self.push_str(name);
+        self.print_generics(&info, lt, false);
+        self.push_str(" {\n");
+        self.push_str(
+            "/// Returns the number of bytes the value takes up in linear memory \
+             once lowered,\n/// including the strings and lists it owns.\n",
+        );
+        self.push_str("pub fn lowered_size(&self) -> usize {\n");
+        match heap {
+            Some(heap) => self.push_str(&format!("{size} + {heap}\n")),
+            None => self.push_str(&format!("{size}\n")),
+        }
+        self.push_str("}\n");
+        self.push_str("}\n");
+    }
+
+    /// Emits the comparison derives for the named type `id` if they were
+    /// requested and every type it contains supports them.
+    fn print_ord_derives(&mut self, iface: &Interface, id: TypeId) {
+        if self.derive_ord() && supports_ord(iface, &Type::Id(id)) {
+            self.push_str("#[derive(PartialEq, Eq, PartialOrd, Ord)]\n");
+        } else if self.derive_partial_eq() && is_plain(iface, &Type::Id(id)) {
+            self.push_str("#[derive(PartialEq)]\n");
+        }
+    }
+
     fn rustdoc(&mut self, docs: &Docs) {
         let docs = match &docs.contents {
             Some(docs) => docs,
@@ -84,6 +247,123 @@ pub trait RustGenerator {
         // }
     }
 
+    /// Returns the Markdown of an example of the arguments `func` is called
+    /// with, and of the values it returns, in the shapes of their Rust types.
+    ///
+    /// Strings and lists among the arguments are borrowed if `borrowed` is
+    /// set, as when parameters are printed leaf-borrowed.
+    fn example_docs(&self, iface: &Interface, func: &Function, borrowed: bool) -> String {
+        let mut docs = String::from("# Examples\n");
+        if !func.params.is_empty() {
+            docs.push_str("\nCalled with arguments such as:\n\n```ignore\n");
+            for (name, ty) in func.params.iter() {
+                docs.push_str(&format!(
+                    "let {} = {};\n",
+                    to_rust_ident(name),
+                    self.example_value(iface, ty, borrowed),
+                ));
+            }
+            docs.push_str("```\n");
+        }
+        if func.result != Type::Unit {
+            docs.push_str(&format!(
+                "\nReturning values such as:\n\n```ignore\n{}\n```\n",
+                self.example_value(iface, &func.result, false),
+            ));
+        }
+        docs
+    }
+
+    /// Returns an expression building a typical value of `ty`, borrowing its
+    /// strings and lists if `borrowed` is set.
+    fn example_value(&self, iface: &Interface, ty: &Type, borrowed: bool) -> String {
+        let id = match ty {
+            Type::Unit => return "()".to_string(),
+            Type::Bool => return "true".to_string(),
+            Type::U8 | Type::U16 | Type::U32 | Type::U64 => return "42".to_string(),
+            Type::S8 | Type::S16 | Type::S32 | Type::S64 => return "-42".to_string(),
+            Type::Float32 | Type::Float64 => return "1.5".to_string(),
+            Type::Char => return "'a'".to_string(),
+            Type::String if borrowed => return "\"text\"".to_string(),
+            Type::String => return "\"text\".to_string()".to_string(),
+            Type::Handle(r) => return iface.resources[*r].name.to_snake_case(),
+            Type::Id(id) => *id,
+        };
+        let name = || {
+            if borrowed {
+                self.param_name(iface, id)
+            } else {
+                self.result_name(iface, id)
+            }
+        };
+        let wit_name = || iface.types[id].name.as_deref().unwrap_or_default();
+        match &iface.types[id].kind {
+            TypeDefKind::Type(ty) => self.example_value(iface, ty, borrowed),
+            TypeDefKind::Record(record) => {
+                let fields = record.fields.iter().map(|field| {
+                    format!(
+                        "{}: {}",
+                        to_rust_ident(&field.name),
+                        self.example_value(iface, &field.ty, borrowed)
+                    )
+                });
+                format!("{} {{ {} }}", name(), fields.collect::<Vec<_>>().join(", "))
+            }
+            TypeDefKind::Tuple(tuple) => {
+                let values = tuple
+                    .types
+                    .iter()
+                    .map(|ty| format!("{}, ", self.example_value(iface, ty, borrowed)));
+                format!("({})", values.collect::<String>().trim_end())
+            }
+            TypeDefKind::Flags(flags) => match flags.flags.first() {
+                Some(flag) => format!("{}::{}", name(), flag.name.to_shouty_snake_case()),
+                None => format!("{}::empty()", name()),
+            },
+            TypeDefKind::Variant(variant) => {
+                let case = &variant.cases[0];
+                let case_name = self.case_ident(wit_name(), &case.name);
+                match case.ty {
+                    Type::Unit => format!("{}::{}", name(), case_name),
+                    ty => format!(
+                        "{}::{}({})",
+                        name(),
+                        case_name,
+                        self.example_value(iface, &ty, borrowed)
+                    ),
+                }
+            }
+            TypeDefKind::Enum(enum_) => {
+                format!(
+                    "{}::{}",
+                    name(),
+                    self.case_ident(wit_name(), &enum_.cases[0].name)
+                )
+            }
+            TypeDefKind::Union(union) => format!(
+                "{}::{}({})",
+                name(),
+                self.union_case_names(iface, union)[0],
+                self.example_value(iface, &union.cases[0].ty, borrowed)
+            ),
+            TypeDefKind::Option(ty) => {
+                format!("Some({})", self.example_value(iface, ty, borrowed))
+            }
+            TypeDefKind::Result(result) => {
+                format!("Ok({})", self.example_value(iface, &result.ok, borrowed))
+            }
+            TypeDefKind::List(ty) if borrowed => {
+                format!("&[{}]", self.example_value(iface, ty, borrowed))
+            }
+            TypeDefKind::List(ty) => {
+                format!("vec![{}]", self.example_value(iface, ty, borrowed))
+            }
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+                "compile_error!(\"futures and streams are unsupported\")".to_string()
+            }
+        }
+    }
+
     fn print_signature(
         &mut self,
         iface: &Interface,
@@ -119,12 +399,12 @@ pub trait RustGenerator {
             self.push_str("async ");
         }
         self.push_str("fn ");
-        let func_name = if sig.use_item_name {
-            func.item_name()
-        } else {
-            &func.name
+        let func_name = match self.renamed(&func.name) {
+            Some(renamed) => renamed.to_string(),
+            None if sig.use_item_name => to_rust_ident(func.item_name()),
+            None => to_rust_ident(&func.name),
         };
-        self.push_str(&to_rust_ident(&func_name));
+        self.push_str(&func_name);
         if let Some(generics) = &sig.generics {
             self.push_str(generics);
         }
@@ -207,7 +487,13 @@ pub trait RustGenerator {
                 TypeMode::AllBorrowed(lt) | TypeMode::LeafBorrowed(lt) => {
                     self.print_borrowed_str(lt)
                 }
-                TypeMode::Owned | TypeMode::HandlesBorrowed(_) => self.push_str("String"),
+                TypeMode::Owned | TypeMode::HandlesBorrowed(_) => {
+                    if self.no_std() {
+                        self.push_str("alloc::string::String")
+                    } else {
+                        self.push_str("String")
+                    }
+                }
             },
         }
     }
@@ -216,7 +502,17 @@ pub trait RustGenerator {
         let info = self.info(id);
         let lt = self.lifetime_for(&info, mode);
         let ty = &iface.types[id];
-        if ty.name.is_some() {
+        if let Some(wit_name) = &ty.name {
+            if let Some(path) = self.mapped(wit_name) {
+                let path = path.to_string();
+                self.push_str(&path);
+                return;
+            }
+            let lt = if self.borrows_named_type(&info) {
+                lt
+            } else {
+                None
+            };
             let name = if lt.is_some() {
                 self.param_name(iface, id)
             } else {
@@ -314,6 +610,11 @@ pub trait RustGenerator {
     }
 
     fn print_list(&mut self, iface: &Interface, ty: &Type, mode: TypeMode) {
+        let vec = if self.no_std() {
+            "alloc::vec::Vec<"
+        } else {
+            "Vec<"
+        };
         match mode {
             TypeMode::AllBorrowed(lt) => {
                 self.print_borrowed_slice(iface, false, ty, lt);
@@ -322,13 +623,13 @@ pub trait RustGenerator {
                 if iface.all_bits_valid(ty) {
                     self.print_borrowed_slice(iface, false, ty, lt);
                 } else {
-                    self.push_str("Vec<");
+                    self.push_str(vec);
                     self.print_ty(iface, ty, mode);
                     self.push_str(">");
                 }
             }
             TypeMode::HandlesBorrowed(_) | TypeMode::Owned => {
-                self.push_str("Vec<");
+                self.push_str(vec);
                 self.print_ty(iface, ty, mode);
                 self.push_str(">");
             }
@@ -391,7 +692,12 @@ pub trait RustGenerator {
         let info = self.info(ty);
         let mut result = Vec::new();
         if info.param {
-            result.push((self.param_name(iface, ty), self.default_param_mode()));
+            let mode = if self.borrows_named_type(&info) {
+                self.default_param_mode()
+            } else {
+                TypeMode::Owned
+            };
+            result.push((self.param_name(iface, ty), mode));
         }
         if info.result && (!info.param || self.uses_two_names(&info)) {
             result.push((self.result_name(iface, ty), TypeMode::Owned));
@@ -399,6 +705,42 @@ pub trait RustGenerator {
         return result;
     }
 
+    /// Returns the named types of `iface` which its functions use and which
+    /// hold plain data, each along with the function guests export for it
+    /// when generated with `proptest`: `proptest-echo-{name}`, which returns
+    /// the value it's given once it has lifted it and lowered it again.
+    ///
+    /// Hosts pass arbitrary values through these functions to check that
+    /// both sides of the canonical ABI agree on them.
+    fn proptest_echoes(&self, iface: &Interface) -> Vec<(TypeId, Function)> {
+        iface
+            .types
+            .iter()
+            .filter(|(id, def)| {
+                let info = self.info(*id);
+                def.name.is_some() && (info.param || info.result) && is_plain(iface, &Type::Id(*id))
+            })
+            .map(|(id, def)| {
+                let name = def.name.as_ref().unwrap();
+                let echo = Function {
+                    docs: Docs {
+                        contents: Some(format!(
+                            "Returns `value` as wasm got it, after lifting it as a `{}` \
+                             and lowering it again.",
+                            name
+                        )),
+                        annotations: Vec::new(),
+                    },
+                    name: format!("proptest-echo-{}", name),
+                    kind: FunctionKind::Freestanding,
+                    params: vec![("value".to_string(), Type::Id(id))],
+                    result: Type::Id(id),
+                };
+                (id, echo)
+            })
+            .collect()
+    }
+
     /// Writes the camel-cased 'name' of the passed type to `out`, as used to name union variants.
     fn write_name(&self, iface: &Interface, ty: &Type, out: &mut String) {
         match ty {
@@ -420,7 +762,7 @@ pub trait RustGenerator {
             Type::Id(id) => {
                 let ty = &iface.types[*id];
                 match &ty.name {
-                    Some(name) => out.push_str(&name.to_camel_case()),
+                    Some(name) => out.push_str(&self.type_ident(name)),
                     None => match &ty.kind {
                         TypeDefKind::Option(ty) => {
                             out.push_str("Optional");
@@ -521,11 +863,17 @@ pub trait RustGenerator {
             } else if !info.has_handle {
                 self.push_str("#[derive(Clone)]\n");
             }
+            self.print_ord_derives(iface, id);
+            self.print_additional_derives();
+            let zeroize = self.print_zeroize_derives(iface, id, lt);
             self.push_str(&format!("pub struct {}", name));
             self.print_generics(&info, lt, true);
             self.push_str(" {\n");
             for field in record.fields.iter() {
                 self.rustdoc(&field.docs);
+                if zeroize && !zeroizes_member(iface, id, &field.name, &field.docs, &field.ty) {
+                    self.push_str("#[zeroize(skip)]\n");
+                }
                 self.push_str("pub ");
                 self.push_str(&to_rust_ident(&field.name));
                 self.push_str(": ");
@@ -545,18 +893,75 @@ pub trait RustGenerator {
             );
             self.push_str(&format!("f.debug_struct(\"{}\")", name));
             for field in record.fields.iter() {
-                self.push_str(&format!(
-                    ".field(\"{}\", &self.{})",
-                    field.name,
-                    to_rust_ident(&field.name)
-                ));
+                if is_sensitive(&field.docs) {
+                    self.push_str(&format!(".field(\"{}\", &{REDACTED})", field.name));
+                } else {
+                    self.push_str(&format!(
+                        ".field(\"{}\", &self.{})",
+                        field.name,
+                        to_rust_ident(&field.name)
+                    ));
+                }
             }
             self.push_str(".finish()");
             self.push_str("}\n");
             self.push_str("}\n");
+
+            if self.ct_eq() && is_sensitive_type(iface, id) {
+                self.print_ct_eq(iface, id, record, &name, lt);
+            }
+
+            let heap = self.lowered_sizes().and_then(|sizes| {
+                let fields = record
+                    .fields
+                    .iter()
+                    .filter_map(|field| {
+                        let operand = format!("self.{}", to_rust_ident(&field.name));
+                        lowered_heap(iface, sizes, &field.ty, &operand)
+                    })
+                    .collect::<Vec<_>>();
+                (!fields.is_empty()).then(|| fields.join(" + "))
+            });
+            self.print_lowered_size(id, &name, lt, heap);
         }
     }
 
+    /// Implements `subtle::ConstantTimeEq` for the sensitive record `id` by
+    /// comparing every field in constant time.
+    fn print_ct_eq(
+        &mut self,
+        iface: &Interface,
+        id: TypeId,
+        record: &Record,
+        name: &str,
+        lt: Option<&'static str>,
+    ) {
+        let info = self.info(id);
+        self.push_str("impl");
+        self.print_generics(&info, lt, true);
+        self.push_str(" subtle::ConstantTimeEq for ");
+        self.push_str(name);
+        self.print_generics(&info, lt, false);
+        self.push_str(" {\n");
+        self.push_str("fn ct_eq(&self, other: &Self) -> subtle::Choice {\n");
+        self.push_str("subtle::Choice::from(1)");
+        for field in record.fields.iter() {
+            let (prefix, suffix) = ct_eq_operand(iface, &field.ty).unwrap_or_else(|| {
+                panic!(
+                    "field `{}` of `{}` can't be compared in constant time",
+                    field.name,
+                    iface.types[id].name.as_deref().unwrap_or("<anonymous>"),
+                )
+            });
+            let field = to_rust_ident(&field.name);
+            self.push_str(&format!(
+                " & subtle::ConstantTimeEq::ct_eq({prefix}self.{field}{suffix}, {prefix}other.{field}{suffix})"
+            ));
+        }
+        self.push_str("\n}\n");
+        self.push_str("}\n");
+    }
+
     fn print_typedef_tuple(&mut self, iface: &Interface, id: TypeId, tuple: &Tuple, docs: &Docs) {
         let info = self.info(id);
         for (name, mode) in self.modes_of(iface, id) {
@@ -582,15 +987,13 @@ pub trait RustGenerator {
     ) where
         Self: Sized,
     {
-        self.print_rust_enum(
-            iface,
-            id,
-            variant
-                .cases
-                .iter()
-                .map(|c| (c.name.to_camel_case(), &c.docs, &c.ty)),
-            docs,
-        );
+        let name = iface.types[id].name.as_deref().unwrap_or_default();
+        let cases = variant
+            .cases
+            .iter()
+            .map(|c| (self.case_ident(name, &c.name), &c.docs, &c.ty))
+            .collect::<Vec<_>>();
+        self.print_rust_enum(iface, id, cases, docs);
     }
 
     fn print_typedef_union(&mut self, iface: &Interface, id: TypeId, union: &Union, docs: &Docs)
@@ -626,6 +1029,9 @@ pub trait RustGenerator {
             } else if !info.has_handle {
                 self.push_str("#[derive(Clone)]\n");
             }
+            self.print_ord_derives(iface, id);
+            self.print_additional_derives();
+            let zeroize = self.print_zeroize_derives(iface, id, lt);
             self.push_str(&format!("pub enum {name}"));
             self.print_generics(&info, lt, true);
             self.push_str("{\n");
@@ -634,6 +1040,9 @@ pub trait RustGenerator {
                 self.push_str(&case_name);
                 if *payload != Type::Unit {
                     self.push_str("(");
+                    if zeroize && !zeroizes_member(iface, id, &case_name, docs, payload) {
+                        self.push_str("#[zeroize(skip)] ");
+                    }
                     self.print_ty(iface, payload, mode);
                     self.push_str(")")
                 }
@@ -641,15 +1050,34 @@ pub trait RustGenerator {
             }
             self.push_str("}\n");
 
-            self.print_rust_enum_debug(
-                id,
-                mode,
-                &name,
-                cases
+            self.print_rust_enum_debug(id, mode, &name, cases.clone());
+
+            let heap = self.lowered_sizes().and_then(|sizes| {
+                let cases = cases
                     .clone()
                     .into_iter()
-                    .map(|(name, _docs, ty)| (name, ty)),
-            );
+                    .map(|(case_name, _, payload)| {
+                        let heap = lowered_heap(iface, sizes, payload, "e");
+                        (case_name, payload, heap)
+                    })
+                    .collect::<Vec<_>>();
+                if cases.iter().all(|(_, _, heap)| heap.is_none()) {
+                    return None;
+                }
+                let mut heap = String::from("match self {\n");
+                for (case_name, payload, case_heap) in cases {
+                    let pattern = match (payload, &case_heap) {
+                        (Type::Unit, _) => "",
+                        (_, Some(_)) => "(e)",
+                        (_, None) => "(_)",
+                    };
+                    let case_heap = case_heap.unwrap_or_else(|| "0".to_string());
+                    heap.push_str(&format!("Self::{case_name}{pattern} => {case_heap},\n"));
+                }
+                heap.push('}');
+                Some(heap)
+            });
+            self.print_lowered_size(id, &name, lt, heap);
         }
     }
 
@@ -658,7 +1086,7 @@ pub trait RustGenerator {
         id: TypeId,
         mode: TypeMode,
         name: &str,
-        cases: impl IntoIterator<Item = (String, &'a Type)>,
+        cases: impl IntoIterator<Item = (String, &'a Docs, &'a Type)>,
     ) where
         Self: Sized,
     {
@@ -672,17 +1100,22 @@ pub trait RustGenerator {
         self.push_str(" {\n");
         self.push_str("fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {\n");
         self.push_str("match self {\n");
-        for (case_name, payload) in cases {
+        for (case_name, docs, payload) in cases {
+            let sensitive = is_sensitive(docs);
             self.push_str(name);
             self.push_str("::");
             self.push_str(&case_name);
             if *payload != Type::Unit {
-                self.push_str("(e)");
+                self.push_str(if sensitive { "(_)" } else { "(e)" });
             }
             self.push_str(" => {\n");
             self.push_str(&format!("f.debug_tuple(\"{}::{}\")", name, case_name));
             if *payload != Type::Unit {
-                self.push_str(".field(e)");
+                if sensitive {
+                    self.push_str(&format!(".field(&{REDACTED})"));
+                } else {
+                    self.push_str(".field(e)");
+                }
             }
             self.push_str(".finish()\n");
             self.push_str("}\n");
@@ -735,15 +1168,20 @@ pub trait RustGenerator {
         // TODO: should this perhaps be an attribute in the wit file?
         let is_error = name.contains("errno");
 
-        let name = name.to_camel_case();
+        let wit_name = name;
+        let name = self.type_ident(name);
         self.rustdoc(docs);
         self.push_str("#[repr(");
         self.int_repr(enum_.tag());
         self.push_str(")]\n#[derive(Clone, Copy, PartialEq, Eq)]\n");
-        self.push_str(&format!("pub enum {} {{\n", name.to_camel_case()));
+        if self.derive_ord() {
+            self.push_str("#[derive(PartialOrd, Ord)]\n");
+        }
+        self.print_additional_derives();
+        self.push_str(&format!("pub enum {} {{\n", name));
         for case in enum_.cases.iter() {
             self.rustdoc(&case.docs);
-            self.push_str(&case.name.to_camel_case());
+            self.push_str(&self.case_ident(wit_name, &case.name));
             self.push_str(",\n");
         }
         self.push_str("}\n");
@@ -760,7 +1198,7 @@ pub trait RustGenerator {
             for case in enum_.cases.iter() {
                 self.push_str(&name);
                 self.push_str("::");
-                self.push_str(&case.name.to_camel_case());
+                self.push_str(&self.case_ident(wit_name, &case.name));
                 self.push_str(" => \"");
                 self.push_str(case.name.as_str());
                 self.push_str("\",\n");
@@ -773,7 +1211,7 @@ pub trait RustGenerator {
             for case in enum_.cases.iter() {
                 self.push_str(&name);
                 self.push_str("::");
-                self.push_str(&case.name.to_camel_case());
+                self.push_str(&self.case_ident(wit_name, &case.name));
                 self.push_str(" => \"");
                 if let Some(contents) = &case.docs.contents {
                     self.push_str(contents.trim());
@@ -808,21 +1246,22 @@ pub trait RustGenerator {
             self.push_str("write!(f, \"{} (error {})\", self.name(), *self as i32)");
             self.push_str("}\n");
             self.push_str("}\n");
-            self.push_str("\n");
-            self.push_str("impl std::error::Error for ");
-            self.push_str(&name);
-            self.push_str("{}\n");
+            if !self.no_std() {
+                self.push_str("\n");
+                self.push_str("impl std::error::Error for ");
+                self.push_str(&name);
+                self.push_str("{}\n");
+            }
         } else {
-            self.print_rust_enum_debug(
-                id,
-                TypeMode::Owned,
-                &name,
-                enum_
-                    .cases
-                    .iter()
-                    .map(|c| (c.name.to_camel_case(), &Type::Unit)),
-            )
+            let cases = enum_
+                .cases
+                .iter()
+                .map(|c| (self.case_ident(wit_name, &c.name), &c.docs, &Type::Unit))
+                .collect::<Vec<_>>();
+            self.print_rust_enum_debug(id, TypeMode::Owned, &name, cases)
         }
+
+        self.print_lowered_size(id, &name, None, None);
     }
 
     fn print_typedef_alias(&mut self, iface: &Interface, id: TypeId, ty: &Type, docs: &Docs) {
@@ -853,8 +1292,9 @@ pub trait RustGenerator {
 
     fn param_name(&self, iface: &Interface, ty: TypeId) -> String {
         let info = self.info(ty);
-        let name = iface.types[ty].name.as_ref().unwrap().to_camel_case();
-        if self.uses_two_names(&info) {
+        let wit_name = iface.types[ty].name.as_ref().unwrap();
+        let name = self.type_ident(wit_name);
+        if self.uses_two_names(&info) && self.mapped(wit_name).is_none() {
             format!("{}Param", name)
         } else {
             name
@@ -863,8 +1303,9 @@ pub trait RustGenerator {
 
     fn result_name(&self, iface: &Interface, ty: TypeId) -> String {
         let info = self.info(ty);
-        let name = iface.types[ty].name.as_ref().unwrap().to_camel_case();
-        if self.uses_two_names(&info) {
+        let wit_name = iface.types[ty].name.as_ref().unwrap();
+        let name = self.type_ident(wit_name);
+        if self.uses_two_names(&info) && self.mapped(wit_name).is_none() {
             format!("{}Result", name)
         } else {
             name
@@ -875,6 +1316,7 @@ pub trait RustGenerator {
         info.owns_data()
             && info.param
             && info.result
+            && self.borrows_named_type(info)
             && match self.default_param_mode() {
                 TypeMode::AllBorrowed(_) | TypeMode::LeafBorrowed(_) => true,
                 TypeMode::HandlesBorrowed(_) => info.has_handle,
@@ -893,6 +1335,146 @@ pub trait RustGenerator {
             _ => None,
         }
     }
+
+    /// Generates `Encode` and `Decode` implementations, from the `codec`
+    /// runtime module, for every named type needed to encode `tys`.
+    ///
+    /// Record fields named in `varint` are encoded with `Varint` instead of
+    /// their fixed-width encoding.
+    fn print_codec_impls(
+        &mut self,
+        iface: &Interface,
+        tys: &[Type],
+        codec: &str,
+        varint: &[String],
+    ) {
+        let varint = varint_fields(iface, varint);
+        let mut visited = HashSet::new();
+        let mut named = Vec::new();
+        for ty in tys {
+            collect_encoded_types(iface, ty, &mut visited, &mut named);
+        }
+
+        for id in named {
+            let ty = &iface.types[id];
+            let wit_name = ty.name.as_ref().unwrap();
+            // Mapped types are implemented alongside their definition.
+            if self.mapped(wit_name).is_some() {
+                continue;
+            }
+            let name = match &ty.kind {
+                TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => self.type_ident(wit_name),
+                _ => self.result_name(iface, id),
+            };
+
+            let mut encode = String::new();
+            let mut decode = String::new();
+            match &ty.kind {
+                TypeDefKind::Record(r) => {
+                    if r.fields.is_empty() {
+                        encode.push_str("let _ = buf;\n");
+                        decode.push_str("let _ = input;\n");
+                    }
+                    decode.push_str(&format!("Ok({name} {{\n"));
+                    for (i, field) in r.fields.iter().enumerate() {
+                        let field = to_rust_ident(&field.name);
+                        if varint.contains(&(id, i)) {
+                            encode.push_str(&format!(
+                                "{codec}::Varint::encode_varint_to(&self.{field}, buf);\n"
+                            ));
+                            decode.push_str(&format!(
+                                "{field}: {codec}::Varint::decode_varint_from(input)?,\n"
+                            ));
+                            continue;
+                        }
+                        encode.push_str(&format!(
+                            "{codec}::Encode::encode_to(&self.{field}, buf);\n"
+                        ));
+                        decode
+                            .push_str(&format!("{field}: {codec}::Decode::decode_from(input)?,\n"));
+                    }
+                    decode.push_str("})\n");
+                }
+                TypeDefKind::Variant(v) => {
+                    let cases = v
+                        .cases
+                        .iter()
+                        .map(|c| (self.case_ident(wit_name, &c.name), &c.ty))
+                        .collect::<Vec<_>>();
+                    print_codec_cases(
+                        codec,
+                        &name,
+                        wit_name,
+                        v.tag(),
+                        &cases,
+                        &mut encode,
+                        &mut decode,
+                    );
+                }
+                TypeDefKind::Union(u) => {
+                    let cases = self
+                        .union_case_names(iface, u)
+                        .into_iter()
+                        .zip(u.cases.iter().map(|c| &c.ty))
+                        .collect::<Vec<_>>();
+                    print_codec_cases(
+                        codec,
+                        &name,
+                        wit_name,
+                        u.tag(),
+                        &cases,
+                        &mut encode,
+                        &mut decode,
+                    );
+                }
+                TypeDefKind::Enum(e) => {
+                    let cases = e
+                        .cases
+                        .iter()
+                        .map(|c| (self.case_ident(wit_name, &c.name), &Type::Unit))
+                        .collect::<Vec<_>>();
+                    print_codec_cases(
+                        codec,
+                        &name,
+                        wit_name,
+                        e.tag(),
+                        &cases,
+                        &mut encode,
+                        &mut decode,
+                    );
+                }
+                TypeDefKind::Flags(_) => {
+                    encode.push_str(&format!("{codec}::Encode::encode_to(&self.bits(), buf);\n"));
+                    decode.push_str(&format!(
+                        "let bits = {codec}::Decode::decode_from(input)?;\n\
+                         Self::from_bits(bits).ok_or({codec}::DecodeError::InvalidFlags(\"{wit_name}\"))\n"
+                    ));
+                }
+                _ => unreachable!(),
+            }
+
+            let vec = if self.no_std() {
+                "alloc::vec::Vec"
+            } else {
+                "Vec"
+            };
+            self.push_str(&format!(
+                "
+                    impl {codec}::Encode for {name} {{
+                        fn encode_to(&self, buf: &mut {vec}<u8>) {{
+                            {encode}
+                        }}
+                    }}
+
+                    impl {codec}::Decode for {name} {{
+                        fn decode_from(input: &mut &[u8]) -> Result<Self, {codec}::DecodeError> {{
+                            {decode}
+                        }}
+                    }}
+                ",
+            ));
+        }
+    }
 }
 
 #[derive(Default)]
@@ -1021,6 +1603,874 @@ pub trait RustFunctionGenerator {
     }
 }
 
+/// Collects the named types which need codec implementations generated in
+/// order to encode `ty`, in the order they are first reached.
+fn collect_encoded_types(
+    iface: &Interface,
+    ty: &Type,
+    visited: &mut HashSet<TypeId>,
+    named: &mut Vec<TypeId>,
+) {
+    let id = match ty {
+        Type::Id(id) => *id,
+        Type::Handle(_) => panic!("resources cannot be encoded into bytes"),
+        _ => return,
+    };
+    if !visited.insert(id) {
+        return;
+    }
+    let ty = &iface.types[id];
+    match &ty.kind {
+        TypeDefKind::Record(r) => {
+            for field in r.fields.iter() {
+                collect_encoded_types(iface, &field.ty, visited, named);
+            }
+        }
+        TypeDefKind::Variant(v) => {
+            for case in v.cases.iter() {
+                collect_encoded_types(iface, &case.ty, visited, named);
+            }
+        }
+        TypeDefKind::Union(u) => {
+            for case in u.cases.iter() {
+                collect_encoded_types(iface, &case.ty, visited, named);
+            }
+        }
+        TypeDefKind::Tuple(t) => {
+            for ty in t.types.iter() {
+                collect_encoded_types(iface, ty, visited, named);
+            }
+        }
+        TypeDefKind::Type(t) | TypeDefKind::List(t) | TypeDefKind::Option(t) => {
+            collect_encoded_types(iface, t, visited, named)
+        }
+        TypeDefKind::Result(r) => {
+            collect_encoded_types(iface, &r.ok, visited, named);
+            collect_encoded_types(iface, &r.err, visited, named);
+        }
+        TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => {}
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => {
+            panic!("futures and streams cannot be encoded into bytes")
+        }
+    }
+    // Everything else is a type alias of something the runtime already knows
+    // how to encode.
+    if ty.name.is_some() {
+        match &ty.kind {
+            TypeDefKind::Record(_)
+            | TypeDefKind::Variant(_)
+            | TypeDefKind::Union(_)
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Enum(_) => named.push(id),
+            _ => {}
+        }
+    }
+}
+
+fn print_codec_cases(
+    codec: &str,
+    name: &str,
+    wit_name: &str,
+    tag: Int,
+    cases: &[(String, &Type)],
+    encode: &mut String,
+    decode: &mut String,
+) {
+    let tag = int_repr(tag);
+    encode.push_str("match self {\n");
+    decode.push_str(&format!(
+        "Ok(match <{tag} as {codec}::Decode>::decode_from(input)? {{\n"
+    ));
+    for (i, (case_name, payload)) in cases.iter().enumerate() {
+        if **payload == Type::Unit {
+            encode.push_str(&format!("{name}::{case_name} => {{\n"));
+            decode.push_str(&format!("{i} => {name}::{case_name},\n"));
+        } else {
+            encode.push_str(&format!("{name}::{case_name}(e) => {{\n"));
+            decode.push_str(&format!(
+                "{i} => {name}::{case_name}({codec}::Decode::decode_from(input)?),\n"
+            ));
+        }
+        encode.push_str(&format!(
+            "{codec}::Encode::encode_to(&({i} as {tag}), buf);\n"
+        ));
+        if **payload != Type::Unit {
+            encode.push_str(&format!("{codec}::Encode::encode_to(e, buf);\n"));
+        }
+        encode.push_str("}\n");
+    }
+    encode.push_str("}\n");
+    decode.push_str(&format!(
+        "tag => return Err({codec}::DecodeError::InvalidTag {{ ty: \"{wit_name}\", tag: tag.into() }}),\n"
+    ));
+    decode.push_str("})\n");
+}
+
+/// Looks up the functions named in `names` whose calls are queued into a
+/// batch, returning each along with its index in `iface.functions`.
+///
+/// Queued calls are flushed long after they are made, so this panics if any
+/// of them returns a value or takes a parameter rejected by
+/// [`encoded_param_by_ref`].
+pub fn batched_functions<'a>(iface: &'a Interface, names: &[String]) -> Vec<(u32, &'a Function)> {
+    names
+        .iter()
+        .map(|name| {
+            let (index, func) = iface
+                .functions
+                .iter()
+                .enumerate()
+                .find(|(_, f)| f.name == *name)
+                .unwrap_or_else(|| panic!("batched function `{}` does not exist", name));
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "batched function `{}` must not be a resource function",
+                    name
+                );
+            }
+            if func.result != Type::Unit {
+                panic!("batched function `{}` must not return a value", name);
+            }
+            for (param, ty) in func.params.iter() {
+                if encoded_param_by_ref(iface, ty).is_none() {
+                    panic!(
+                        "parameter `{}` of batched function `{}` must be a scalar, \
+                         string, enum, flags or byte list",
+                        param, name
+                    );
+                }
+            }
+            (index as u32, func)
+        })
+        .collect()
+}
+
+/// A step of the call-ordering protocol of an interface, declared by
+/// annotating a function with `@transition("from", "to")`: it may only be
+/// called in state `from`, after which the protocol is in state `to`.
+pub struct Transition<'a> {
+    pub func: &'a Function,
+    pub from: String,
+    pub to: String,
+}
+
+/// Returns the transitions of the call-ordering protocol of `iface`, in the
+/// order of their functions. The protocol starts in the state the first of
+/// them is called in.
+///
+/// This panics if an annotation doesn't name two states, or annotates a
+/// resource function.
+pub fn protocol_transitions(iface: &Interface) -> Vec<Transition<'_>> {
+    iface
+        .functions
+        .iter()
+        .filter_map(|func| {
+            let annotation = func.docs.annotation("transition")?;
+            let (from, to) = match annotation.args.as_slice() {
+                [from, to] => (from.clone(), to.clone()),
+                _ => panic!(
+                    "`@transition` on `{}` takes the states it goes from and to",
+                    func.name
+                ),
+            };
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "`@transition` on `{}` must not be on a resource function",
+                    func.name
+                );
+            }
+            Some(Transition { func, from, to })
+        })
+        .collect()
+}
+
+/// Looks up the functions named in `names` whose string parameters are
+/// interned, returning each along with the function wasm actually imports
+/// instead: `{name}-interned`, taking the id of each interned string as a
+/// `u32` in place of the string.
+///
+/// This panics if any of them is a resource function or takes no string.
+pub fn interned_functions<'a>(
+    iface: &'a Interface,
+    names: &[String],
+) -> Vec<(&'a Function, Function)> {
+    names
+        .iter()
+        .map(|name| {
+            let func = iface
+                .functions
+                .iter()
+                .find(|f| f.name == *name)
+                .unwrap_or_else(|| panic!("interned function `{}` does not exist", name));
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "interned function `{}` must not be a resource function",
+                    name
+                );
+            }
+            if !func.params.iter().any(|(_, ty)| *ty == Type::String) {
+                panic!("interned function `{}` must take a string", name);
+            }
+            let mut interned = func.clone();
+            interned.name = format!("{}-interned", func.name);
+            for (_, ty) in interned.params.iter_mut() {
+                if *ty == Type::String {
+                    *ty = Type::U32;
+                }
+            }
+            (func, interned)
+        })
+        .collect()
+}
+
+/// Returns the functions annotated `@splice`, each along with the function
+/// wasm can import in its place: `{name}-spliced`, which takes and returns
+/// every byte list as a `u32` token for data kept on the host.
+///
+/// A token returned by one spliced function can be passed to another, so the
+/// host hands data from one import to the next without copying it into and
+/// back out of guest memory.
+///
+/// This panics if any of them is a resource function or neither takes nor
+/// returns a byte list.
+pub fn spliced_functions(iface: &Interface) -> Vec<(&Function, Function)> {
+    iface
+        .functions
+        .iter()
+        .filter(|func| func.docs.annotation("splice").is_some())
+        .map(|func| {
+            let name = &func.name;
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "spliced function `{}` must not be a resource function",
+                    name
+                );
+            }
+            let is_bytes = |ty: &Type| is_byte_list(iface, ty);
+            if !is_bytes(&func.result) && !func.params.iter().any(|(_, ty)| is_bytes(ty)) {
+                panic!(
+                    "spliced function `{}` must take or return a `list<u8>`",
+                    name
+                );
+            }
+            let mut spliced = func.clone();
+            spliced.name = format!("{}-spliced", name);
+            spliced.docs = Docs {
+                contents: Some(format!(
+                    "Like `{}`, but passing byte lists as tokens for data kept on the host.",
+                    name.to_snake_case()
+                )),
+                annotations: Vec::new(),
+            };
+            for (_, ty) in spliced.params.iter_mut() {
+                if is_bytes(ty) {
+                    *ty = Type::U32;
+                }
+            }
+            if is_bytes(&spliced.result) {
+                spliced.result = Type::U32;
+            }
+            (func, spliced)
+        })
+        .collect()
+}
+
+/// Returns the functions annotated `@idempotent`, each along with the
+/// function wasm imports in its place: the same function taking the
+/// idempotency key of the call as a trailing `idempotency-key: u64`.
+///
+/// Guests draw a new key for each call and pass the same one again when they
+/// retry it, so that hosts can tell retries from new calls and perform each
+/// call only once.
+///
+/// This panics if any of them is a resource function, is also spliced, or
+/// already has an `idempotency-key` parameter.
+pub fn idempotent_functions(iface: &Interface) -> Vec<(&Function, Function)> {
+    iface
+        .functions
+        .iter()
+        .filter(|func| func.docs.annotation("idempotent").is_some())
+        .map(|func| {
+            let name = &func.name;
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "idempotent function `{}` must not be a resource function",
+                    name
+                );
+            }
+            if func.docs.annotation("splice").is_some() {
+                panic!("idempotent function `{}` can't also be spliced", name);
+            }
+            if func
+                .params
+                .iter()
+                .any(|(param, _)| param == "idempotency-key")
+            {
+                panic!(
+                    "idempotent function `{}` already has an `idempotency-key` parameter",
+                    name
+                );
+            }
+            let mut keyed = func.clone();
+            keyed
+                .params
+                .push(("idempotency-key".to_string(), Type::U64));
+            let note = "`idempotency_key` is the same for every retry of a call, \
+                        and differs between calls.";
+            keyed.docs.contents = Some(match &func.docs.contents {
+                Some(docs) => format!("{}\n\n{}", docs.trim(), note),
+                None => note.to_string(),
+            });
+            (func, keyed)
+        })
+        .collect()
+}
+
+/// Returns whether `ty` is a `list<u8>`, possibly behind aliases.
+pub fn is_byte_list(iface: &Interface, ty: &Type) -> bool {
+    list_element(iface, ty) == Some(Type::U8)
+}
+
+/// Looks up the functions named in `names` whose results are memoized,
+/// keyed by their encoded arguments.
+///
+/// This panics if any of them is a resource function, returns nothing, or
+/// takes a parameter rejected by [`encoded_param_by_ref`].
+pub fn memoized_functions<'a>(iface: &'a Interface, names: &[String]) -> Vec<&'a Function> {
+    names
+        .iter()
+        .map(|name| {
+            let func = iface
+                .functions
+                .iter()
+                .find(|f| f.name == *name)
+                .unwrap_or_else(|| panic!("memoized function `{}` does not exist", name));
+            if func.kind != FunctionKind::Freestanding {
+                panic!(
+                    "memoized function `{}` must not be a resource function",
+                    name
+                );
+            }
+            if func.result == Type::Unit {
+                panic!("memoized function `{}` must return a value", name);
+            }
+            for (param, ty) in func.params.iter() {
+                if encoded_param_by_ref(iface, ty).is_none() {
+                    panic!(
+                        "parameter `{}` of memoized function `{}` must be a scalar, \
+                         string, enum, flags or byte list",
+                        param, name
+                    );
+                }
+            }
+            func
+        })
+        .collect()
+}
+
+/// Returns the imports whose list results can be read in chunks, each along
+/// with its index in `iface.functions` and the type of the list's elements.
+///
+/// These are the freestanding functions returning a list of values which can
+/// be encoded into bytes, and whose parameters are all accepted by
+/// [`encoded_param_by_ref`], so the guest and host bindings agree on them
+/// without having to name them.
+pub fn chunked_functions(iface: &Interface) -> Vec<(u32, &Function, Type)> {
+    iface
+        .functions
+        .iter()
+        .enumerate()
+        .filter(|(_, func)| func.kind == FunctionKind::Freestanding)
+        .filter_map(|(index, func)| {
+            let element = list_element(iface, &func.result)?;
+            if element == Type::Unit || !encodable(iface, &element) {
+                return None;
+            }
+            if func
+                .params
+                .iter()
+                .any(|(_, ty)| encoded_param_by_ref(iface, ty).is_none())
+            {
+                return None;
+            }
+            Some((index as u32, func, element))
+        })
+        .collect()
+}
+
+/// Returns whether values of type `ty` can be encoded into bytes, which
+/// rules out handles, futures and streams.
+fn encodable(iface: &Interface, ty: &Type) -> bool {
+    let id = match ty {
+        Type::Id(id) => *id,
+        Type::Handle(_) => return false,
+        _ => return true,
+    };
+    match &iface.types[id].kind {
+        TypeDefKind::Record(r) => r.fields.iter().all(|f| encodable(iface, &f.ty)),
+        TypeDefKind::Variant(v) => v.cases.iter().all(|c| encodable(iface, &c.ty)),
+        TypeDefKind::Union(u) => u.cases.iter().all(|c| encodable(iface, &c.ty)),
+        TypeDefKind::Tuple(t) => t.types.iter().all(|t| encodable(iface, t)),
+        TypeDefKind::Type(t) | TypeDefKind::List(t) | TypeDefKind::Option(t) => encodable(iface, t),
+        TypeDefKind::Result(r) => encodable(iface, &r.ok) && encodable(iface, &r.err),
+        TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => true,
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => false,
+    }
+}
+
+/// Looks up the record fields named in `names`, each written as
+/// `record.field`, which are encoded as variable-length integers, returning
+/// each as its record's id and the field's index.
+///
+/// This panics if any of them doesn't exist or isn't an integer.
+pub fn varint_fields(iface: &Interface, names: &[String]) -> HashSet<(TypeId, usize)> {
+    names
+        .iter()
+        .map(|name| {
+            let (record, field) = name.split_once('.').unwrap_or_else(|| {
+                panic!("varint field `{}` must be written as `record.field`", name)
+            });
+            let id = *iface
+                .type_lookup
+                .get(record)
+                .unwrap_or_else(|| panic!("varint record `{}` does not exist", record));
+            let fields = match &iface.types[id].kind {
+                TypeDefKind::Record(r) => &r.fields,
+                _ => panic!("varint field `{}` must belong to a record", name),
+            };
+            let (index, field) = fields
+                .iter()
+                .enumerate()
+                .find(|(_, f)| f.name == field)
+                .unwrap_or_else(|| panic!("varint field `{}` does not exist", name));
+            let mut ty = &field.ty;
+            while let Type::Id(id) = ty {
+                match &iface.types[*id].kind {
+                    TypeDefKind::Type(t) => ty = t,
+                    _ => break,
+                }
+            }
+            match ty {
+                Type::U8
+                | Type::U16
+                | Type::U32
+                | Type::U64
+                | Type::S8
+                | Type::S16
+                | Type::S32
+                | Type::S64 => {}
+                _ => panic!("varint field `{}` must be an integer", name),
+            }
+            (id, index)
+        })
+        .collect()
+}
+
+/// What `Debug` prints in place of a value marked `@sensitive`.
+const REDACTED: &str = "format_args!(\"<redacted>\")";
+
+/// Returns whether `id` is the record named by the `error_context` option,
+/// after checking that it has the `message: string` and `context:
+/// list<string>` fields errors are chained with.
+pub fn is_error_context(iface: &Interface, id: TypeId, error_context: Option<&str>) -> bool {
+    let ty = &iface.types[id];
+    let name = match (&ty.name, error_context) {
+        (Some(name), Some(expected)) if name == expected => name,
+        _ => return false,
+    };
+    let record = match &ty.kind {
+        TypeDefKind::Record(record) => record,
+        _ => panic!("error context `{}` must be a record", name),
+    };
+    let field = |field: &str| record.fields.iter().find(|f| f.name == field);
+    let is_string_list = |ty: &Type| match ty {
+        Type::Id(id) => iface.types[*id].kind == TypeDefKind::List(Type::String),
+        _ => false,
+    };
+    match (field("message"), field("context")) {
+        (Some(message), Some(context))
+            if message.ty == Type::String && is_string_list(&context.ty) => {}
+        _ => panic!(
+            "error context `{}` must have `message: string` and `context: list<string>` fields",
+            name
+        ),
+    }
+    true
+}
+
+/// Returns the `ok` type of the `result` returned by `func` if its error is
+/// the error context record.
+pub fn error_context_ok(
+    iface: &Interface,
+    func: &Function,
+    error_context: Option<&str>,
+) -> Option<Type> {
+    let result = match &func.result {
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Result(r) => r,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match result.err {
+        Type::Id(err) if is_error_context(iface, err, error_context) => Some(result.ok),
+        _ => None,
+    }
+}
+
+/// Returns whether record `id` has the `error_location` field, which its
+/// constructors fill with the source location of their caller.
+pub fn has_error_location(iface: &Interface, id: TypeId, error_location: Option<&str>) -> bool {
+    let (record, expected) = match (&iface.types[id].kind, error_location) {
+        (TypeDefKind::Record(record), Some(expected)) => (record, expected),
+        _ => return false,
+    };
+    match record.fields.iter().find(|f| f.name == expected) {
+        Some(field) if field.ty == Type::String => true,
+        Some(_) => panic!("error location field `{}` must be a string", expected),
+        None => false,
+    }
+}
+
+/// Returns whether a field or case is annotated `@sensitive`, meaning its
+/// value must not end up in logs.
+pub fn is_sensitive(docs: &Docs) -> bool {
+    docs.annotation("sensitive").is_some()
+}
+
+/// Returns whether the named type `id` is a record, variant or union which
+/// is either marked `@sensitive` itself or has a member that is.
+fn is_sensitive_type(iface: &Interface, id: TypeId) -> bool {
+    let ty = &iface.types[id];
+    let member_sensitive = match &ty.kind {
+        TypeDefKind::Record(r) => r.fields.iter().any(|f| is_sensitive(&f.docs)),
+        TypeDefKind::Variant(v) => v.cases.iter().any(|c| is_sensitive(&c.docs)),
+        TypeDefKind::Union(u) => u.cases.iter().any(|c| is_sensitive(&c.docs)),
+        _ => return false,
+    };
+    member_sensitive || is_sensitive(&ty.docs)
+}
+
+/// Returns whether a field or case of the zeroized type `id` is zeroized
+/// too, which it is if either it or the whole type is marked `@sensitive`
+/// and its own type can be zeroized.
+fn zeroizes_member(iface: &Interface, id: TypeId, name: &str, docs: &Docs, ty: &Type) -> bool {
+    if !supports_zeroize(iface, ty) {
+        if is_sensitive(docs) {
+            panic!(
+                "`{}` in `{}` is marked `@sensitive` but its type can't be zeroized",
+                name,
+                iface.types[id].name.as_deref().unwrap_or("<anonymous>"),
+            );
+        }
+        return false;
+    }
+    is_sensitive(docs) || is_sensitive(&iface.types[id].docs)
+}
+
+/// Returns whether the Rust type generated for `ty` implements `Zeroize`,
+/// assuming it is printed without borrowing anything.
+fn supports_zeroize(iface: &Interface, ty: &Type) -> bool {
+    match ty {
+        Type::Bool
+        | Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::Float32
+        | Type::Float64
+        | Type::Char
+        | Type::String => true,
+        Type::Unit | Type::Handle(_) => false,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Union(_) => {
+                is_sensitive_type(iface, *id)
+            }
+            TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+                supports_zeroize(iface, ty)
+            }
+            _ => false,
+        },
+    }
+}
+
+/// Returns how to turn a field of type `ty` into something implementing
+/// `subtle::ConstantTimeEq`, as the text to put before and after it.
+fn ct_eq_operand(iface: &Interface, ty: &Type) -> Option<(&'static str, &'static str)> {
+    match ty {
+        Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64 => Some(("&", "")),
+        Type::Bool => Some(("&(", " as u8)")),
+        Type::Char => Some(("&(", " as u32)")),
+        Type::Float32 | Type::Float64 => Some(("&", ".to_bits()")),
+        Type::String => Some(("", ".as_bytes()")),
+        Type::Unit | Type::Handle(_) => None,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(ty) => ct_eq_operand(iface, ty),
+            TypeDefKind::Record(_) if is_sensitive_type(iface, *id) => Some(("&", "")),
+            // Slices compare their lengths in variable time, which only leaks
+            // the length.
+            TypeDefKind::List(element) => match ct_eq_operand(iface, element) {
+                Some(("&", "")) => Some(("&", "[..]")),
+                _ => None,
+            },
+            _ => None,
+        },
+    }
+}
+
+/// Returns whether values of `ty` are plain data, without any resources,
+/// futures or streams.
+pub fn is_plain(iface: &Interface, ty: &Type) -> bool {
+    let id = match ty {
+        Type::Id(id) => *id,
+        Type::Handle(_) => return false,
+        _ => return true,
+    };
+    match &iface.types[id].kind {
+        TypeDefKind::Record(r) => r.fields.iter().all(|f| is_plain(iface, &f.ty)),
+        TypeDefKind::Variant(v) => v.cases.iter().all(|c| is_plain(iface, &c.ty)),
+        TypeDefKind::Union(u) => u.cases.iter().all(|c| is_plain(iface, &c.ty)),
+        TypeDefKind::Tuple(t) => t.types.iter().all(|ty| is_plain(iface, ty)),
+        TypeDefKind::Type(t) | TypeDefKind::List(t) | TypeDefKind::Option(t) => is_plain(iface, t),
+        TypeDefKind::Result(r) => is_plain(iface, &r.ok) && is_plain(iface, &r.err),
+        TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => true,
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => false,
+    }
+}
+
+/// Returns whether the Rust type generated for `ty` can implement `Ord`,
+/// which rules out floats and handles anywhere inside it.
+pub fn supports_ord(iface: &Interface, ty: &Type) -> bool {
+    match ty {
+        Type::Float32 | Type::Float64 | Type::Handle(_) => false,
+        Type::Unit
+        | Type::Bool
+        | Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::Char
+        | Type::String => true,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Record(r) => r.fields.iter().all(|f| supports_ord(iface, &f.ty)),
+            TypeDefKind::Tuple(t) => t.types.iter().all(|ty| supports_ord(iface, ty)),
+            TypeDefKind::Variant(v) => v.cases.iter().all(|c| supports_ord(iface, &c.ty)),
+            TypeDefKind::Union(u) => u.cases.iter().all(|c| supports_ord(iface, &c.ty)),
+            TypeDefKind::Result(r) => supports_ord(iface, &r.ok) && supports_ord(iface, &r.err),
+            TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+                supports_ord(iface, ty)
+            }
+            TypeDefKind::Enum(_) | TypeDefKind::Flags(_) => true,
+            TypeDefKind::Future(_) | TypeDefKind::Stream(_) => false,
+        },
+    }
+}
+
+/// Returns the expression computing how many bytes the strings and lists
+/// owned by `operand`, a value of `ty`, take up in linear memory once
+/// lowered, or `None` if it owns none.
+///
+/// Named records, variants and unions get theirs from their `lowered_size`
+/// method.
+fn lowered_heap(iface: &Interface, sizes: &SizeAlign, ty: &Type, operand: &str) -> Option<String> {
+    let id = match ty {
+        Type::String => return Some(format!("{operand}.len()")),
+        Type::Id(id) => *id,
+        _ => return None,
+    };
+    match &iface.types[id].kind {
+        TypeDefKind::Type(ty) => lowered_heap(iface, sizes, ty, operand),
+        TypeDefKind::List(element) => {
+            let size = sizes.size(element);
+            Some(match lowered_heap(iface, sizes, element, "e") {
+                Some(heap) => format!(
+                    "({operand}.len() * {size} + {operand}.iter().map(|e| {heap}).sum::<usize>())"
+                ),
+                None => format!("{operand}.len() * {size}"),
+            })
+        }
+        TypeDefKind::Option(payload) => lowered_heap(iface, sizes, payload, "e")
+            .map(|heap| format!("{operand}.as_ref().map_or(0, |e| {heap})")),
+        TypeDefKind::Result(r) => {
+            let ok = lowered_heap(iface, sizes, &r.ok, "e");
+            let err = lowered_heap(iface, sizes, &r.err, "e");
+            if ok.is_none() && err.is_none() {
+                return None;
+            }
+            let arm = |heap: Option<String>| match heap {
+                Some(heap) => format!("(e) => {heap}"),
+                None => "(_) => 0".to_string(),
+            };
+            Some(format!(
+                "match &{operand} {{ Ok{}, Err{} }}",
+                arm(ok),
+                arm(err)
+            ))
+        }
+        TypeDefKind::Tuple(t) => {
+            let heaps = t
+                .types
+                .iter()
+                .enumerate()
+                .filter_map(|(i, ty)| lowered_heap(iface, sizes, ty, &format!("{operand}.{i}")))
+                .collect::<Vec<_>>();
+            (!heaps.is_empty()).then(|| format!("({})", heaps.join(" + ")))
+        }
+        TypeDefKind::Record(_) | TypeDefKind::Variant(_) | TypeDefKind::Union(_) => {
+            owns_heap(iface, ty).then(|| format!("({operand}.lowered_size() - {})", sizes.size(ty)))
+        }
+        TypeDefKind::Enum(_)
+        | TypeDefKind::Flags(_)
+        | TypeDefKind::Future(_)
+        | TypeDefKind::Stream(_) => None,
+    }
+}
+
+/// Returns whether values of `ty` own strings or lists.
+fn owns_heap(iface: &Interface, ty: &Type) -> bool {
+    match ty {
+        Type::String => true,
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::List(_) => true,
+            TypeDefKind::Record(r) => r.fields.iter().any(|f| owns_heap(iface, &f.ty)),
+            TypeDefKind::Tuple(t) => t.types.iter().any(|ty| owns_heap(iface, ty)),
+            TypeDefKind::Variant(v) => v.cases.iter().any(|c| owns_heap(iface, &c.ty)),
+            TypeDefKind::Union(u) => u.cases.iter().any(|c| owns_heap(iface, &c.ty)),
+            TypeDefKind::Result(r) => owns_heap(iface, &r.ok) || owns_heap(iface, &r.err),
+            TypeDefKind::Option(ty) | TypeDefKind::Type(ty) => owns_heap(iface, ty),
+            TypeDefKind::Enum(_)
+            | TypeDefKind::Flags(_)
+            | TypeDefKind::Future(_)
+            | TypeDefKind::Stream(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// Returns whether `a_ty` of interface `a` is declared exactly like `b_ty` of
+/// interface `b`, down to the names and docs of the named types within them.
+pub fn same_type(a: &Interface, a_ty: &Type, b: &Interface, b_ty: &Type) -> bool {
+    let (a_id, b_id) = match (a_ty, b_ty) {
+        (Type::Id(a_id), Type::Id(b_id)) => (*a_id, *b_id),
+        (Type::Handle(_), _) | (_, Type::Handle(_)) => return false,
+        _ => return a_ty == b_ty,
+    };
+    let (a_def, b_def) = (&a.types[a_id], &b.types[b_id]);
+    if a_def.name != b_def.name || a_def.docs != b_def.docs {
+        return false;
+    }
+    let same = |a_ty: &Type, b_ty: &Type| same_type(a, a_ty, b, b_ty);
+    match (&a_def.kind, &b_def.kind) {
+        (TypeDefKind::Record(x), TypeDefKind::Record(y)) => {
+            x.fields.len() == y.fields.len()
+                && zip(&x.fields, &y.fields)
+                    .all(|(f, g)| f.name == g.name && f.docs == g.docs && same(&f.ty, &g.ty))
+        }
+        (TypeDefKind::Tuple(x), TypeDefKind::Tuple(y)) => {
+            x.types.len() == y.types.len() && zip(&x.types, &y.types).all(|(x, y)| same(x, y))
+        }
+        (TypeDefKind::Variant(x), TypeDefKind::Variant(y)) => {
+            x.cases.len() == y.cases.len()
+                && zip(&x.cases, &y.cases)
+                    .all(|(c, d)| c.name == d.name && c.docs == d.docs && same(&c.ty, &d.ty))
+        }
+        (TypeDefKind::Union(x), TypeDefKind::Union(y)) => {
+            x.cases.len() == y.cases.len()
+                && zip(&x.cases, &y.cases).all(|(c, d)| c.docs == d.docs && same(&c.ty, &d.ty))
+        }
+        (TypeDefKind::Result(x), TypeDefKind::Result(y)) => {
+            same(&x.ok, &y.ok) && same(&x.err, &y.err)
+        }
+        (TypeDefKind::Option(x), TypeDefKind::Option(y))
+        | (TypeDefKind::List(x), TypeDefKind::List(y))
+        | (TypeDefKind::Type(x), TypeDefKind::Type(y)) => same(x, y),
+        (TypeDefKind::Enum(x), TypeDefKind::Enum(y)) => x == y,
+        (TypeDefKind::Flags(x), TypeDefKind::Flags(y)) => x == y,
+        _ => false,
+    }
+}
+
+/// Lists the named types which `ty` is made of, not looking past them.
+pub fn named_types_within(iface: &Interface, ty: &Type) -> Vec<TypeId> {
+    let def = match ty {
+        Type::Id(id) => &iface.types[*id],
+        _ => return Vec::new(),
+    };
+    let within = |tys: &mut dyn Iterator<Item = &Type>| -> Vec<TypeId> {
+        tys.flat_map(|ty| match ty {
+            Type::Id(id) if iface.types[*id].name.is_some() => vec![*id],
+            ty => named_types_within(iface, ty),
+        })
+        .collect()
+    };
+    match &def.kind {
+        TypeDefKind::Record(r) => within(&mut r.fields.iter().map(|f| &f.ty)),
+        TypeDefKind::Tuple(t) => within(&mut t.types.iter()),
+        TypeDefKind::Variant(v) => within(&mut v.cases.iter().map(|c| &c.ty)),
+        TypeDefKind::Union(u) => within(&mut u.cases.iter().map(|c| &c.ty)),
+        TypeDefKind::Result(r) => within(&mut [&r.ok, &r.err].into_iter()),
+        TypeDefKind::Option(ty) | TypeDefKind::List(ty) | TypeDefKind::Type(ty) => {
+            within(&mut std::iter::once(ty))
+        }
+        TypeDefKind::Enum(_) | TypeDefKind::Flags(_) => Vec::new(),
+        TypeDefKind::Future(_) | TypeDefKind::Stream(_) => Vec::new(),
+    }
+}
+
+fn list_element(iface: &Interface, ty: &Type) -> Option<Type> {
+    match ty {
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(t) => list_element(iface, t),
+            TypeDefKind::List(element) => Some(*element),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Returns whether a parameter of type `ty`, once encoded into bytes, is
+/// decoded into an owned value that is passed on by reference, or `None` if
+/// it can't be used as an encoded parameter at all.
+pub fn encoded_param_by_ref(iface: &Interface, ty: &Type) -> Option<bool> {
+    match ty {
+        Type::Bool
+        | Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::Float32
+        | Type::Float64
+        | Type::Char => Some(false),
+        Type::String => Some(true),
+        Type::Id(id) => match &iface.types[*id].kind {
+            TypeDefKind::Type(t) => encoded_param_by_ref(iface, t),
+            TypeDefKind::Enum(_) | TypeDefKind::Flags(_) => Some(false),
+            TypeDefKind::List(Type::U8 | Type::S8) => Some(true),
+            _ => None,
+        },
+        Type::Unit | Type::Handle(_) => None,
+    }
+}
+
 pub fn to_rust_ident(name: &str) -> String {
     match name {
         // Escape Rust keywords.
@@ -1097,7 +2547,9 @@ pub fn int_repr(repr: Int) -> &'static str {
     }
 }
 
-trait TypeInfoExt {
+pub trait TypeInfoExt {
+    /// Whether values of the type hold lists, strings or handles, which the
+    /// bindings may borrow rather than own.
     fn owns_data(&self) -> bool;
 }
 
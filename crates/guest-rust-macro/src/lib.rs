@@ -1,10 +1,20 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
 
+use heck::*;
 use proc_macro::TokenStream;
+use syn::ext::IdentExt;
 use syn::parse::{Error, Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::{token, Token};
-use wit_bindgen_core::{wit_parser::Interface, Direction, Files, Generator};
+use wit_bindgen_core::abi_lock::AbiLock;
+use wit_bindgen_core::lint::Thresholds;
+use wit_bindgen_core::lock::{InterfaceSource, Lockfile};
+use wit_bindgen_core::macro_options::Keys;
+use wit_bindgen_core::wit_parser::{abi, Interface, World};
+use wit_bindgen_core::{Direction, Files, Generator};
 
 #[proc_macro]
 pub fn import(input: TokenStream) -> TokenStream {
@@ -16,45 +26,676 @@ pub fn export(input: TokenStream) -> TokenStream {
     run(input, Direction::Export)
 }
 
+/// Generates the bindings of interfaces imported and exported together,
+/// sharing one set of options:
+///
+/// ```ignore
+/// wit_bindgen_guest_rust::generate!({
+///     import: ["wit/storage.wit"],
+///     export: ["wit/contract.wit"],
+///     derive_ord,
+/// });
+/// ```
+///
+/// `import` and `export` accept the same paths as the `paths` key of
+/// `import!` and `export!`, and any of their other options may follow.
+/// Instead, or as well, `world = "name"` binds the imports and exports of a
+/// world declared in the WIT package at `package`, which defaults to `wit`.
+///
+/// Both directions go through a single generator pass, in which named types
+/// that several interfaces declare identically are only defined by the first
+/// of them and re-exported by the others, so that values can be passed from
+/// an import to an export and back.
+#[proc_macro]
+pub fn generate(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as Generate);
+    expand(
+        input.opts,
+        &input.imports,
+        &input.exports,
+        &input.files,
+        input.module.as_ref(),
+    )
+}
+
 fn run(input: TokenStream, dir: Direction) -> TokenStream {
     let input = syn::parse_macro_input!(input as Opts);
-    let mut gen = input.opts.build();
-    let mut files = Files::default();
     let (imports, exports) = match dir {
         Direction::Import => (input.interfaces, vec![]),
         Direction::Export => (vec![], input.interfaces),
     };
-    gen.generate_all(&imports, &exports, &mut files);
+    let call_site = proc_macro2::Span::call_site();
+    if let Err(e) = check_export_macros(&input.opts, &exports, call_site) {
+        return e.to_compile_error().into();
+    }
+    expand(
+        input.opts,
+        &imports,
+        &exports,
+        &input.files,
+        input.module.as_ref(),
+    )
+}
+
+fn expand(
+    mut opts: wit_bindgen_gen_guest_rust::Opts,
+    imports: &[Interface],
+    exports: &[Interface],
+    read_files: &[String],
+    module: Option<&syn::Ident>,
+) -> TokenStream {
+    if module.is_some() {
+        // Interfaces must be reachable from outside the wrapping module.
+        opts.module_visibility = "pub".to_string();
+    }
+    let name = imports
+        .iter()
+        .map(|i| format!("import-{}", i.name))
+        .chain(exports.iter().map(|e| format!("export-{}", e.name)))
+        .collect::<Vec<_>>()
+        .join("+");
+    let mut gen = opts.build();
+    let mut files = Files::default();
+    gen.generate_all(imports, exports, &mut files);
     let (_, contents) = files.iter().next().unwrap();
     let mut contents = std::str::from_utf8(contents).unwrap().to_string();
 
-    // Include a dummy `include_str!` for any files we read so rustc knows that
-    // we depend on the contents of those files.
+    contents.push_str(&track_dependencies(read_files));
+
+    // The glob import keeps paths such as the default `super::{Interface}`
+    // export type pointing at the items of the invoking module.
+    if let Some(module) = module {
+        contents = format!(
+            "pub mod {} {{\n#[allow(unused_imports)]\nuse super::*;\n{}}}\n",
+            module, contents
+        );
+    }
+
+    // Stubs implement the exports in the invoking module, where the export
+    // types are expected by default.
+    for (name, stub) in files.iter() {
+        if name.starts_with("stubs/") {
+            contents.push_str(std::str::from_utf8(stub).unwrap());
+        }
+    }
+
+    dump_expansion(&name, &contents);
+    contents.parse().unwrap()
+}
+
+/// Prefixes the names of the environment variables interfaces were read
+/// from among the files they were read from.
+const ENV_DEPENDENCY: &str = "env:";
+
+/// Returns a dummy `include_str!` for each of the files read, or `env!` for
+/// each of the environment variables, so that rustc knows that the bindings
+/// depend on their contents.
+fn track_dependencies(files: &[String]) -> String {
     let cwd = std::env::var("CARGO_MANIFEST_DIR").unwrap();
-    for file in input.files.iter() {
-        contents.push_str(&format!(
-            "const _: &str = include_str!(r#\"{}\"#);\n",
-            Path::new(&cwd).join(file).display()
+    let mut src = String::new();
+    for file in files {
+        match file.strip_prefix(ENV_DEPENDENCY) {
+            Some(var) => src.push_str(&format!("const _: &str = env!({:?});\n", var)),
+            None => src.push_str(&format!(
+                "const _: &str = include_str!(r#\"{}\"#);\n",
+                Path::new(&cwd).join(file).display()
+            )),
+        }
+    }
+    src
+}
+
+/// Writes the `contents` an invocation expands to under the directory named
+/// by the `WIT_BINDGEN_DEBUG_DIR` environment variable, if set, so that they
+/// can be inspected and diffed between versions.
+///
+/// Files are written to `{dir}/{crate}/{name}.rs`, where `name` lists the
+/// interfaces bound. Invocations binding the same interfaces within a crate
+/// are numbered in the order they are expanded in, from `{name}-2.rs` on.
+fn dump_expansion(name: &str, contents: &str) {
+    static EXPANDED: Mutex<BTreeMap<String, usize>> = Mutex::new(BTreeMap::new());
+
+    let dir = match std::env::var_os("WIT_BINDGEN_DEBUG_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => return,
+    };
+    let count = {
+        let mut expanded = EXPANDED.lock().unwrap();
+        let count = expanded.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        *count
+    };
+    let file = match count {
+        1 => format!("{}.rs", name),
+        n => format!("{}-{}.rs", name, n),
+    };
+    let cwd = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let krate = std::env::var("CARGO_CRATE_NAME").unwrap_or_default();
+    let dir = Path::new(&cwd).join(dir).join(krate);
+    let path = dir.join(file);
+    if let Err(e) = std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, contents)) {
+        panic!("failed to write `{}`: {}", path.display(), e);
+    }
+}
+
+/// Exports an interface implemented by the annotated `impl` block, in the
+/// style of upstream `wit-bindgen`:
+///
+/// ```ignore
+/// #[wit_bindgen_guest_rust::guest(export = "contract.wit")]
+/// impl Contract for MyContract {
+///     fn execute(operation: Vec<u8>) -> Vec<u8> {
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// The bindings are generated next to the `impl` block, which names the
+/// trait of the interface without a module prefix. `export` accepts the same
+/// paths as the `paths` key of `export!`, and may be followed by any of its
+/// other options.
+///
+/// The path may also be given on its own, and the block be an inherent
+/// `impl`, which leaves room for helper methods besides the exports:
+///
+/// ```ignore
+/// #[wit_bindgen_guest_rust::guest("service.wit")]
+/// impl MyService {
+///     fn serve(request: Vec<u8>) -> Vec<u8> {
+///         // ...
+///     }
+/// }
+/// ```
+///
+/// Every function of the interface must then be implemented by an
+/// associated function of the same name and arity, which the trait of the
+/// interface is implemented with, so that mismatched types are reported
+/// against the signatures of the block.
+#[proc_macro_attribute]
+pub fn guest(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let attr = syn::parse_macro_input!(attr as GuestAttr);
+    let item = syn::parse_macro_input!(item as syn::ItemImpl);
+    match expand_guest(attr, item) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn expand_guest(attr: GuestAttr, mut item: syn::ItemImpl) -> Result<proc_macro2::TokenStream> {
+    let GuestAttr {
+        mut opts,
+        interface,
+        files,
+    } = attr;
+    let camel = quote::format_ident!("{}", interface.name.to_camel_case());
+    let snake = quote::format_ident!("{}", interface.name.to_snake_case());
+
+    // The generated module reaches the implementation through an alias, as
+    // the trait itself takes the interface's name.
+    let alias = quote::format_ident!("__{}Export", camel);
+    opts.export_type = Some(format!("super::{}", alias));
+
+    let delegate = match &mut item.trait_ {
+        Some((None, path, _)) => {
+            if path.is_ident(&camel) {
+                *path = syn::parse_quote!(#snake::#camel);
+            }
+            None
+        }
+        None => Some(delegate_exports(&opts, &interface, &item)?),
+        _ => {
+            return Err(Error::new_spanned(
+                &item.self_ty,
+                format!("expected `impl {} for ...`", camel),
+            ))
+        }
+    };
+
+    let name = format!("guest-{}", interface.name);
+    let mut gen = opts.build();
+    let mut generated = Files::default();
+    gen.generate_all(&[], &[interface], &mut generated);
+    let (_, contents) = generated.iter().next().unwrap();
+    let mut contents = std::str::from_utf8(contents).unwrap().to_string();
+    contents.push_str(&track_dependencies(&files));
+    dump_expansion(&name, &contents);
+    let bindings: proc_macro2::TokenStream = contents.parse().unwrap();
+
+    let self_ty = &item.self_ty;
+    Ok(quote::quote! {
+        #bindings
+
+        #[doc(hidden)]
+        #[allow(non_camel_case_types)]
+        type #alias = #self_ty;
+
+        #item
+
+        #delegate
+    })
+}
+
+/// Implements the trait of `interface` for the type of the inherent `impl`
+/// block `item`, forwarding each function to the associated function of the
+/// same name, after checking that the block has one for every function.
+fn delegate_exports(
+    opts: &wit_bindgen_gen_guest_rust::Opts,
+    interface: &Interface,
+    item: &syn::ItemImpl,
+) -> Result<proc_macro2::TokenStream> {
+    let camel = quote::format_ident!("{}", interface.name.to_camel_case());
+    let snake = quote::format_ident!("{}", interface.name.to_snake_case());
+    let self_ty = &item.self_ty;
+    if interface.resources.len() > 0 {
+        return Err(Error::new_spanned(
+            self_ty,
+            format!(
+                "interfaces with resources must be exported with `impl {} for ...`",
+                camel
+            ),
         ));
     }
 
-    contents.parse().unwrap()
+    let mut errors: Option<Error> = None;
+    let mut error = |e: Error| match &mut errors {
+        Some(errors) => errors.combine(e),
+        None => errors = Some(e),
+    };
+    let mut methods = Vec::new();
+    for func in interface.functions.iter() {
+        let name = opts.function_ident(&func.name);
+        let sig = item.items.iter().find_map(|item| match item {
+            syn::ImplItem::Method(method) if method.sig.ident == name => Some(&method.sig),
+            _ => None,
+        });
+        let sig = match sig {
+            Some(sig) => sig,
+            None => {
+                error(Error::new_spanned(
+                    self_ty,
+                    format!(
+                        "missing `fn {}` exporting `{}` of `{}`",
+                        name, func.name, interface.name
+                    ),
+                ));
+                continue;
+            }
+        };
+        if let Some(receiver) = sig.receiver() {
+            error(Error::new_spanned(
+                receiver,
+                format!("`{}` is exported as a function without `self`", name),
+            ));
+            continue;
+        }
+        if sig.inputs.len() != func.params.len() {
+            error(Error::new_spanned(
+                &sig.inputs,
+                format!(
+                    "`{}` takes {} parameters in `{}`, not {}",
+                    func.name,
+                    func.params.len(),
+                    interface.name,
+                    sig.inputs.len()
+                ),
+            ));
+            continue;
+        }
+
+        // Arguments are renamed, as the block may bind them with patterns.
+        let ident = &sig.ident;
+        let output = &sig.output;
+        let args = (0..sig.inputs.len())
+            .map(|i| quote::format_ident!("arg{}", i))
+            .collect::<Vec<_>>();
+        let tys = sig.inputs.iter().map(|input| match input {
+            syn::FnArg::Typed(pat) => &pat.ty,
+            syn::FnArg::Receiver(_) => unreachable!(),
+        });
+        methods.push(quote::quote! {
+            fn #ident(#(#args: #tys),*) #output {
+                <#self_ty>::#ident(#(#args),*)
+            }
+        });
+    }
+    if let Some(errors) = errors {
+        return Err(errors);
+    }
+
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote::quote! {
+        impl #impl_generics #snake::#camel for #self_ty #where_clause {
+            #(#methods)*
+        }
+    })
+}
+
+struct GuestAttr {
+    opts: wit_bindgen_gen_guest_rust::Opts,
+    interface: Interface,
+    files: Vec<String>,
+}
+
+impl Parse for GuestAttr {
+    fn parse(input: ParseStream<'_>) -> Result<GuestAttr> {
+        let call_site = proc_macro2::Span::call_site();
+        let mut opts = wit_bindgen_gen_guest_rust::Opts::default();
+        let mut interfaces = Vec::new();
+        let mut lints = Thresholds::default();
+        let mut module = None;
+        let mut files = Vec::new();
+        let mut overrides = Vec::new();
+        for arg in KEYS.parse_fields::<GuestArg>(input)? {
+            match arg {
+                GuestArg::Export(path) => {
+                    files.clear();
+                    overrides.clear();
+                    interfaces = load_interfaces([path], call_site, &mut files)?;
+                }
+                GuestArg::Config(field) => field.apply(
+                    &mut opts,
+                    &mut lints,
+                    &mut interfaces,
+                    &mut files,
+                    &mut module,
+                    &mut overrides,
+                ),
+            }
+        }
+        apply_overrides(&mut opts, overrides);
+        if module.is_some() {
+            return Err(Error::new(
+                call_site,
+                "`module` can't be used with `#[guest]`",
+            ));
+        }
+        if opts.export_macro.is_some() {
+            return Err(Error::new(
+                call_site,
+                "`export_macro` can't be used with `#[guest]`",
+            ));
+        }
+        if !opts.export_macros.is_empty() {
+            return Err(Error::new(
+                call_site,
+                "`export_macros` can't be used with `#[guest]`",
+            ));
+        }
+        if interfaces.len() != 1 {
+            return Err(Error::new(
+                call_site,
+                "must specify a single interface with `export = \"...\"`",
+            ));
+        }
+        opts.validate().map_err(|e| wit_error(call_site, e))?;
+        retain_features(&mut interfaces, &opts.features, call_site)?;
+        lints
+            .enforce(&interfaces)
+            .map_err(|e| Error::new(call_site, e))?;
+        Ok(GuestAttr {
+            opts,
+            interface: interfaces.remove(0),
+            files,
+        })
+    }
+}
+
+enum GuestArg {
+    Export(InterfacePath),
+    Config(ConfigField),
+}
+
+impl Parse for GuestArg {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if input.peek(kw::export) {
+            input.parse::<kw::export>()?;
+            input.parse::<token::Eq>()?;
+            Ok(GuestArg::Export(input.parse()?))
+        } else if input.peek(syn::LitStr) {
+            Ok(GuestArg::Export(input.parse()?))
+        } else {
+            Ok(GuestArg::Config(input.parse()?))
+        }
+    }
+}
+
+struct Generate {
+    opts: wit_bindgen_gen_guest_rust::Opts,
+    imports: Vec<Interface>,
+    exports: Vec<Interface>,
+    files: Vec<String>,
+    module: Option<syn::Ident>,
+}
+
+impl Parse for Generate {
+    fn parse(input: ParseStream<'_>) -> Result<Generate> {
+        let call_site = proc_macro2::Span::call_site();
+        let mut opts = wit_bindgen_gen_guest_rust::Opts::default();
+        let mut lints = Thresholds::default();
+        let mut interfaces = Vec::new();
+        let mut module = None;
+        let mut files = Vec::new();
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        let mut world = None;
+        let mut package = None;
+        let mut overrides = Vec::new();
+        let content;
+        syn::braced!(content in input);
+        for arg in KEYS.parse_fields::<GenerateArg>(&content)? {
+            let (paths, dst) = match arg {
+                GenerateArg::Import(paths) => (paths, &mut imports),
+                GenerateArg::Export(paths) => (paths, &mut exports),
+                GenerateArg::World(name) => {
+                    world = Some(name);
+                    continue;
+                }
+                GenerateArg::Package(path) => {
+                    package = Some(path);
+                    continue;
+                }
+                GenerateArg::Config(field) => {
+                    field.apply(
+                        &mut opts,
+                        &mut lints,
+                        &mut interfaces,
+                        &mut files,
+                        &mut module,
+                        &mut overrides,
+                    );
+                    continue;
+                }
+            };
+            dst.extend(load_interfaces(paths, call_site, &mut files)?);
+        }
+        apply_overrides(&mut opts, overrides);
+        match (world, package) {
+            (Some(name), package) => {
+                let (dir, span) = match &package {
+                    Some(package) => (interpolate(package)?, package.span()),
+                    None => ("wit".to_string(), name.span()),
+                };
+                let world = World::load(base_dir(span, &dir).join(dir), &name.value())
+                    .map_err(|e| wit_error(name.span(), e))?;
+                let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+                let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(span, e))?;
+                if let Some(abi_lock) = &abi_lock {
+                    for iface in world.imports.iter().chain(&world.exports) {
+                        abi_lock
+                            .verify(iface)
+                            .map_err(|e| Error::new(name.span(), e))?;
+                    }
+                }
+                imports.extend(world.imports);
+                exports.extend(world.exports);
+                files.extend(world.files.iter().map(|f| f.display().to_string()));
+            }
+            (None, Some(package)) => {
+                return Err(Error::new(
+                    package.span(),
+                    "`package` only names where to find the `world`",
+                ))
+            }
+            (None, None) => {}
+        }
+        if !interfaces.is_empty() {
+            return Err(Error::new(
+                call_site,
+                "`generate!` takes its interfaces from `import` and `export` keys",
+            ));
+        }
+        if imports.is_empty() && exports.is_empty() {
+            return Err(Error::new(
+                call_site,
+                "must specify `import`, `export` or `world` keys",
+            ));
+        }
+        if opts.prelude && opts.export_macro.is_some() {
+            return Err(Error::new(
+                call_site,
+                "`prelude` can't be used with `export_macro`",
+            ));
+        }
+
+        if let Some(iface) = imports
+            .iter()
+            .find(|i| exports.iter().any(|e| e.name == i.name))
+        {
+            return Err(Error::new(
+                call_site,
+                format!("interface `{}` is both imported and exported", iface.name),
+            ));
+        }
+        // Types declared alike on both sides are generated once.
+        opts.share_types = true;
+
+        opts.validate().map_err(|e| wit_error(call_site, e))?;
+        check_export_macros(&opts, &exports, call_site)?;
+        retain_features(&mut imports, &opts.features, call_site)?;
+        retain_features(&mut exports, &opts.features, call_site)?;
+        lints
+            .enforce(imports.iter().chain(&exports))
+            .map_err(|e| Error::new(call_site, e))?;
+        Ok(Generate {
+            opts,
+            imports,
+            exports,
+            files,
+            module,
+        })
+    }
+}
+
+enum GenerateArg {
+    Import(Punctuated<InterfacePath, Token![,]>),
+    Export(Punctuated<InterfacePath, Token![,]>),
+    World(syn::LitStr),
+    Package(syn::LitStr),
+    Config(ConfigField),
+}
+
+impl Parse for GenerateArg {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        let paths = |input: ParseStream<'_>| {
+            input.parse::<Token![:]>()?;
+            let paths;
+            syn::bracketed!(paths in input);
+            Punctuated::<InterfacePath, Token![,]>::parse_terminated(&paths)
+        };
+        if input.peek(kw::import) {
+            input.parse::<kw::import>()?;
+            Ok(GenerateArg::Import(paths(input)?))
+        } else if input.peek(kw::export) {
+            input.parse::<kw::export>()?;
+            Ok(GenerateArg::Export(paths(input)?))
+        } else if input.peek(kw::world) {
+            input.parse::<kw::world>()?;
+            input.parse::<token::Eq>()?;
+            Ok(GenerateArg::World(input.parse()?))
+        } else if input.peek(kw::package) {
+            input.parse::<kw::package>()?;
+            input.parse::<token::Eq>()?;
+            Ok(GenerateArg::Package(input.parse()?))
+        } else {
+            Ok(GenerateArg::Config(input.parse()?))
+        }
+    }
 }
 
 struct Opts {
     opts: wit_bindgen_gen_guest_rust::Opts,
     interfaces: Vec<Interface>,
     files: Vec<String>,
+    /// The module to wrap the bindings in, if any.
+    module: Option<syn::Ident>,
 }
 
 mod kw {
     syn::custom_keyword!(src);
+    syn::custom_keyword!(env_src);
     syn::custom_keyword!(paths);
+    syn::custom_keyword!(import);
+    syn::custom_keyword!(export);
+    syn::custom_keyword!(world);
+    syn::custom_keyword!(package);
+    syn::custom_keyword!(registry);
     syn::custom_keyword!(unchecked);
     syn::custom_keyword!(multi_module);
+    syn::custom_keyword!(module_names);
     syn::custom_keyword!(export_macro);
     syn::custom_keyword!(types_path);
     syn::custom_keyword!(reexported_crate_path);
+    syn::custom_keyword!(no_macro_export);
+    syn::custom_keyword!(export_macro_vis);
+    syn::custom_keyword!(export_macro_module);
+    syn::custom_keyword!(export_macros);
+    syn::custom_keyword!(message);
+    syn::custom_keyword!(query);
+    syn::custom_keyword!(query_response);
+    syn::custom_keyword!(batch);
+    syn::custom_keyword!(memoize);
+    syn::custom_keyword!(chunk_threshold);
+    syn::custom_keyword!(interned);
+    syn::custom_keyword!(varint);
+    syn::custom_keyword!(bulk);
+    syn::custom_keyword!(dispatch_table);
+    syn::custom_keyword!(profile);
+    syn::custom_keyword!(hot_calls);
+    syn::custom_keyword!(chunk_size);
+    syn::custom_keyword!(derive_ord);
+    syn::custom_keyword!(zeroize);
+    syn::custom_keyword!(ct_eq);
+    syn::custom_keyword!(proptest);
+    syn::custom_keyword!(call_depth_limit);
+    syn::custom_keyword!(max_flat_params);
+    syn::custom_keyword!(max_record_size);
+    syn::custom_keyword!(deny_lints);
+    syn::custom_keyword!(abi_version);
+    syn::custom_keyword!(module);
+    syn::custom_keyword!(prelude);
+    syn::custom_keyword!(prelude_exclude);
+    syn::custom_keyword!(no_alloc);
+    syn::custom_keyword!(no_std);
+    syn::custom_keyword!(const_fns);
+    syn::custom_keyword!(error_context);
+    syn::custom_keyword!(error_location);
+    syn::custom_keyword!(debug_frames);
+    syn::custom_keyword!(ensure);
+    syn::custom_keyword!(skip);
+    syn::custom_keyword!(rename);
+    syn::custom_keyword!(lowered_size);
+    syn::custom_keyword!(schema);
+    syn::custom_keyword!(with);
+    syn::custom_keyword!(additional_derives);
+    syn::custom_keyword!(max_list_len);
+    syn::custom_keyword!(stubs);
+    syn::custom_keyword!(check_alignment);
+    syn::custom_keyword!(native_fallback);
+    syn::custom_keyword!(features);
+    syn::custom_keyword!(intrinsics_path);
+    syn::custom_keyword!(ownership);
+    syn::custom_keyword!(path);
+    syn::custom_keyword!(recursive);
 }
 
 impl Parse for Opts {
@@ -62,31 +703,42 @@ impl Parse for Opts {
         let mut opts = wit_bindgen_gen_guest_rust::Opts::default();
         let call_site = proc_macro2::Span::call_site();
         let mut files = Vec::new();
-        let interfaces = if input.peek(token::Brace) {
+        let mut lints = Thresholds::default();
+        let mut module = None;
+        let mut interfaces = if input.peek(token::Brace) {
             let content;
             syn::braced!(content in input);
             let mut interfaces = Vec::new();
-            let fields = Punctuated::<ConfigField, Token![,]>::parse_terminated(&content)?;
-            for field in fields.into_pairs() {
-                match field.into_value() {
-                    ConfigField::Unchecked => opts.unchecked = true,
-                    ConfigField::MultiModule => opts.multi_module = true,
-                    ConfigField::ExportMacro(name) => opts.export_macro = Some(name),
-                    ConfigField::TypesPath(path) => opts.types_path = Some(path),
-                    ConfigField::ReexportedCratePath(path) => {
-                        opts.reexported_crate_path = Some(path)
-                    }
-                    ConfigField::Interfaces(v) => interfaces = v,
-                }
+            let mut overrides = Vec::new();
+            for field in KEYS.parse_fields::<ConfigField>(&content)? {
+                field.apply(
+                    &mut opts,
+                    &mut lints,
+                    &mut interfaces,
+                    &mut files,
+                    &mut module,
+                    &mut overrides,
+                );
             }
+            apply_overrides(&mut opts, overrides);
             if interfaces.is_empty() {
                 return Err(Error::new(
                     call_site,
-                    "must either specify `src` or `paths` keys",
+                    "must either specify `src`, `env_src` or `paths` keys",
                 ));
             }
+            let mut names = BTreeSet::new();
+            for iface in interfaces.iter() {
+                if !names.insert(&iface.name) {
+                    return Err(Error::new(
+                        call_site,
+                        format!("interface `{}` is given more than once", iface.name),
+                    ));
+                }
+            }
             interfaces
         } else {
+            let mut paths = Vec::new();
             while !input.is_empty() {
                 if input.peek(kw::export_macro) {
                     input.parse::<kw::export_macro>()?;
@@ -103,35 +755,523 @@ impl Parse for Opts {
                     input.parse::<token::Eq>()?;
                     let path = input.parse::<syn::LitStr>()?.value();
                     opts.reexported_crate_path = Some(path);
+                } else if input.peek(kw::no_macro_export) {
+                    input.parse::<kw::no_macro_export>()?;
+                    opts.no_macro_export = true;
+                } else if input.peek(kw::export_macro_vis) {
+                    input.parse::<kw::export_macro_vis>()?;
+                    input.parse::<token::Eq>()?;
+                    opts.export_macro_vis = Some(parse_vis(input)?);
+                } else if input.peek(kw::export_macro_module) {
+                    input.parse::<kw::export_macro_module>()?;
+                    input.parse::<token::Eq>()?;
+                    let module = input.parse::<syn::LitStr>()?.parse::<syn::Ident>()?;
+                    opts.export_macro_module = Some(module.to_string());
+                } else if input.peek(kw::message) {
+                    input.parse::<kw::message>()?;
+                    input.parse::<token::Eq>()?;
+                    let name = input.parse::<syn::LitStr>()?.value();
+                    opts.message = Some(name);
+                } else if input.peek(kw::query) {
+                    input.parse::<kw::query>()?;
+                    input.parse::<token::Eq>()?;
+                    let name = input.parse::<syn::LitStr>()?.value();
+                    opts.query = Some(name);
+                } else if input.peek(kw::query_response) {
+                    input.parse::<kw::query_response>()?;
+                    input.parse::<token::Eq>()?;
+                    let name = input.parse::<syn::LitStr>()?.value();
+                    opts.query_response = Some(name);
+                } else if input.peek(kw::module) {
+                    input.parse::<kw::module>()?;
+                    input.parse::<token::Eq>()?;
+                    module = Some(input.parse::<syn::LitStr>()?.parse()?);
+                } else if input.peek(kw::features) {
+                    input.parse::<kw::features>()?;
+                    input.parse::<token::Eq>()?;
+                    let names;
+                    syn::bracketed!(names in input);
+                    let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+                    opts.features = names.iter().map(|s| s.value()).collect();
                 } else {
-                    let s = input.parse::<syn::LitStr>()?;
-                    files.push(s.value());
+                    paths.push(InterfacePath::File(input.parse()?));
                 }
             }
-            let mut interfaces = Vec::new();
-            let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-            for path in files.iter() {
-                let path = manifest_dir.join(path);
-                let iface = Interface::parse_file(path).map_err(|e| Error::new(call_site, e))?;
-                interfaces.push(iface);
-            }
-            interfaces
+            load_interfaces(paths, call_site, &mut files)?
         };
+        opts.validate().map_err(|e| wit_error(call_site, e))?;
+        retain_features(&mut interfaces, &opts.features, call_site)?;
+        lints
+            .enforce(&interfaces)
+            .map_err(|e| Error::new(call_site, e))?;
         Ok(Opts {
             files,
             opts,
             interfaces,
+            module,
+        })
+    }
+}
+
+/// An entry of `paths`: either a file relative to the crate root, which may
+/// be a glob pattern such as `wit/**/*.wit` and refer to `${WORKSPACE_ROOT}`
+/// or `${ENV_VAR}`s, or `registry("namespace:name@version")`.
+///
+/// A directory stands for the `.wit` files in it, and with `recursive` for
+/// those in its subdirectories too, except for `deps` directories.
+enum InterfacePath {
+    File(syn::LitStr),
+    Recursive(syn::LitStr),
+    Registry(syn::LitStr),
+}
+
+impl Parse for InterfacePath {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if input.peek(kw::registry) {
+            input.parse::<kw::registry>()?;
+            let reference;
+            syn::parenthesized!(reference in input);
+            Ok(InterfacePath::Registry(reference.parse()?))
+        } else {
+            Ok(InterfacePath::File(input.parse()?))
+        }
+    }
+}
+
+/// An entry of `paths` along with the options overriding those of the macro
+/// for the interfaces it loads, written `{ path: "a.wit", unchecked }`.
+struct PathEntry {
+    path: InterfacePath,
+    overrides: Vec<ConfigField>,
+}
+
+impl Parse for PathEntry {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if !input.peek(token::Brace) {
+            return Ok(PathEntry {
+                path: input.parse()?,
+                overrides: Vec::new(),
+            });
+        }
+        let content;
+        syn::braced!(content in input);
+        content.parse::<kw::path>()?;
+        content.parse::<Token![:]>()?;
+        let mut path = content.parse()?;
+        let mut overrides = Vec::new();
+        while !content.is_empty() {
+            content.parse::<Token![,]>()?;
+            if content.is_empty() {
+                break;
+            }
+            let span = content.span();
+            if content.peek(kw::recursive) {
+                content.parse::<kw::recursive>()?;
+                path = match path {
+                    InterfacePath::File(s) => InterfacePath::Recursive(s),
+                    _ => return Err(Error::new(span, "`recursive` only applies to directories")),
+                };
+                continue;
+            }
+            let field = content.parse::<ConfigField>()?;
+            if !field.is_per_interface() {
+                return Err(Error::new(
+                    span,
+                    "this option applies to all the interfaces of the macro",
+                ));
+            }
+            overrides.push(field);
+        }
+        Ok(PathEntry { path, overrides })
+    }
+}
+
+/// Sets the options of each interface named in `overrides` to `opts` along
+/// with the fields overriding them.
+fn apply_overrides(opts: &mut wit_bindgen_gen_guest_rust::Opts, overrides: Overrides) {
+    for (names, fields) in overrides {
+        let mut overridden = opts.clone();
+        overridden.overrides.clear();
+        for field in fields {
+            field.apply(
+                &mut overridden,
+                &mut Thresholds::default(),
+                &mut Vec::new(),
+                &mut Vec::new(),
+                &mut None,
+                &mut Vec::new(),
+            );
+        }
+        for name in names {
+            opts.overrides.push((name, overridden.clone()));
+        }
+    }
+}
+
+/// Loads the interfaces at `paths`, checking them against the lockfile of
+/// the crate if there is one, and adds the files read to `files`.
+fn load_interfaces(
+    paths: impl IntoIterator<Item = InterfacePath>,
+    span: proc_macro2::Span,
+    files: &mut Vec<String>,
+) -> Result<Vec<Interface>> {
+    let mut interfaces = Vec::new();
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let lockfile = Lockfile::find(&manifest_dir).map_err(|e| Error::new(span, e))?;
+    let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(span, e))?;
+    let mut sources = Vec::new();
+    for path in paths {
+        let recursive = matches!(path, InterfacePath::Recursive(_));
+        match path {
+            InterfacePath::File(s) | InterfacePath::Recursive(s) => {
+                for path in expand_glob(&s, recursive)? {
+                    files.push(path.display().to_string());
+                    sources.push((s.span(), InterfaceSource::File(path)));
+                }
+            }
+            InterfacePath::Registry(s) => {
+                let reference = s.value().parse().map_err(|e| Error::new(s.span(), e))?;
+                sources.push((s.span(), InterfaceSource::Registry(reference)));
+            }
+        }
+    }
+    for (span, source) in sources {
+        let interface = match &source {
+            InterfaceSource::File(path) => parse_file(path, span)?,
+            InterfaceSource::Registry(_) => source
+                .load(lockfile.as_ref())
+                .map_err(|e| wit_error(span, e))?,
+        };
+        if let Some(lockfile) = &lockfile {
+            lockfile
+                .verify(&source, &interface)
+                .map_err(|e| Error::new(span, e))?;
+        }
+        if let Some(abi_lock) = &abi_lock {
+            abi_lock
+                .verify(&interface)
+                .map_err(|e| Error::new(span, e))?;
+        }
+        interfaces.push(interface);
+    }
+    Ok(interfaces)
+}
+
+/// Parses the interface at `path`, reusing the one parsed by an earlier
+/// invocation of the macros in this compiler process unless a file read to
+/// parse it changed since, as the crates of a workspace often bind the same
+/// interfaces.
+fn parse_file(path: &Path, span: proc_macro2::Span) -> Result<Interface> {
+    type Sources = Vec<(PathBuf, Option<SystemTime>)>;
+    static PARSED: Mutex<BTreeMap<PathBuf, (Interface, Sources)>> = Mutex::new(BTreeMap::new());
+
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    if let Some((interface, sources)) = PARSED.lock().unwrap().get(path) {
+        let unchanged =
+            |(path, at): &(PathBuf, Option<SystemTime>)| at.is_some() && modified(path) == *at;
+        if sources.iter().all(unchanged) {
+            return Ok(interface.clone());
+        }
+    }
+    let (interface, sources) =
+        Interface::parse_file_with_sources(path).map_err(|e| wit_error(span, e))?;
+    let sources = sources
+        .into_iter()
+        .map(|path| {
+            let at = modified(&path);
+            (path, at)
         })
+        .collect();
+    PARSED
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), (interface.clone(), sources));
+    Ok(interface)
+}
+
+/// Reports `e` at `span` along with its causes, so that an error loading
+/// WIT names the file and shows the offending line.
+fn wit_error(span: proc_macro2::Span, e: impl std::fmt::Display) -> Error {
+    Error::new(span, format!("{:#}", e))
+}
+
+/// Lists the files matching `pattern` relative to its [`base_dir`], in
+/// order, or the `.wit` files of the directory it names, or just the file it
+/// names if it isn't a pattern.
+fn expand_glob(pattern: &syn::LitStr, recursive: bool) -> Result<Vec<PathBuf>> {
+    let value = interpolate(pattern)?;
+    let path = base_dir(pattern.span(), &value).join(&value);
+    if path.is_dir() {
+        let mut files = Vec::new();
+        list_wit_files(&path, recursive, &mut files).map_err(|e| Error::new(pattern.span(), e))?;
+        if files.is_empty() {
+            return Err(Error::new(pattern.span(), "directory has no `.wit` files"));
+        }
+        files.sort();
+        return Ok(files);
+    }
+    if recursive {
+        return Err(Error::new(
+            pattern.span(),
+            "`recursive` only applies to directories",
+        ));
+    }
+    if !value.contains(['*', '?', '[']) {
+        return Ok(vec![path]);
+    }
+    let matches = glob::glob(&path.to_string_lossy())
+        .map_err(|e| Error::new(pattern.span(), e))?
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| Error::new(pattern.span(), e))?;
+    if matches.is_empty() {
+        return Err(Error::new(pattern.span(), "pattern matches no files"));
+    }
+    Ok(matches)
+}
+
+/// Returns the directory to resolve `path`, written at `span`, against: that
+/// of the source file it's written in if `path` exists there, as when a
+/// macro of another crate invokes this one with its own files, or else the
+/// root of the crate being compiled.
+///
+/// Absolute paths are left as they are by [`Path::join`] either way.
+fn base_dir(span: proc_macro2::Span, path: &str) -> PathBuf {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    // The compiler only knows the file of spans from local sources, relative
+    // to its working directory, which is also that of the macro.
+    let file = span.unwrap().local_file();
+    let dir = match file.as_deref().and_then(Path::parent) {
+        Some(dir) => std::env::current_dir().map_or(dir.to_path_buf(), |cwd| cwd.join(dir)),
+        None => return manifest_dir,
+    };
+    let candidate = dir.join(path);
+    let exists = if path.contains(['*', '?', '[']) {
+        glob::glob(&candidate.to_string_lossy()).is_ok_and(|mut m| m.next().is_some())
+    } else {
+        candidate.exists()
+    };
+    if exists {
+        dir
+    } else {
+        manifest_dir
+    }
+}
+
+/// Adds the `.wit` files in `dir` to `files`, along with those in its
+/// subdirectories if `recursive`. `deps` directories are skipped, as they
+/// hold the files `use`d by the others rather than interfaces to bind.
+fn list_wit_files(dir: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive && path.file_name() != Some("deps".as_ref()) {
+                list_wit_files(&path, recursive, files)?;
+            }
+        } else if path.extension() == Some("wit".as_ref()) {
+            files.push(path);
+        }
     }
+    Ok(())
 }
 
+/// Replaces the `${NAME}`s in `path` with the environment variables they
+/// name, except for `${WORKSPACE_ROOT}`, the root of the crate's workspace.
+fn interpolate(path: &syn::LitStr) -> Result<String> {
+    let value = path.value();
+    let mut interpolated = String::new();
+    let mut rest = value.as_str();
+    while let Some(start) = rest.find("${") {
+        interpolated.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| Error::new(path.span(), "unterminated `${` in path"))?;
+        let name = &rest[start + 2..start + end];
+        if name == "WORKSPACE_ROOT" {
+            interpolated.push_str(&workspace_root().display().to_string());
+        } else {
+            let var = std::env::var(name).map_err(|_| {
+                Error::new(
+                    path.span(),
+                    format!("environment variable `{}` is not set", name),
+                )
+            })?;
+            interpolated.push_str(&var);
+        }
+        rest = &rest[start + end + 1..];
+    }
+    interpolated.push_str(rest);
+    Ok(interpolated)
+}
+
+/// Returns the closest directory above the crate whose `Cargo.toml` declares
+/// a `[workspace]`, or the crate's own directory if there is none.
+fn workspace_root() -> PathBuf {
+    let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+    let is_workspace = |dir: &Path| {
+        std::fs::read_to_string(dir.join("Cargo.toml"))
+            .is_ok_and(|manifest| manifest.lines().any(|line| line.trim() == "[workspace]"))
+    };
+    let root = manifest_dir.ancestors().find(|dir| is_workspace(dir));
+    root.unwrap_or(&manifest_dir).to_path_buf()
+}
+
+/// The names of the interfaces loaded by each entry of `paths` with
+/// overrides, along with them.
+type Overrides = Vec<(Vec<String>, Vec<ConfigField>)>;
+
 enum ConfigField {
-    Interfaces(Vec<Interface>),
+    Interfaces(Vec<Interface>, Vec<String>, Overrides),
     Unchecked,
     MultiModule,
     ExportMacro(String),
     TypesPath(String),
     ReexportedCratePath(String),
+    NoMacroExport,
+    ExportMacroVis(String),
+    ExportMacroModule(String),
+    ExportMacros(Vec<(String, String)>),
+    Message(String),
+    Query(String),
+    QueryResponse(String),
+    Batch(Vec<String>),
+    Memoize(Vec<String>),
+    ChunkThreshold(usize),
+    Interned(Vec<String>),
+    Varint(Vec<String>),
+    Bulk,
+    DispatchTable,
+    Profile(PathBuf),
+    HotCalls(u64),
+    ChunkSize(usize),
+    DeriveOrd,
+    Zeroize,
+    CtEq,
+    Proptest,
+    CallDepthLimit(u32),
+    MaxFlatParams(usize),
+    MaxRecordSize(usize),
+    DenyLints,
+    AbiVersion(u32),
+    Module(syn::Ident),
+    ModuleNames(Vec<(String, String)>),
+    Prelude,
+    PreludeExclude(Vec<String>),
+    NoAlloc,
+    NoStd,
+    ConstFns(Vec<String>),
+    ErrorContext(String),
+    ErrorLocation(String),
+    DebugFrames,
+    Ensure(String),
+    Skip(Vec<String>),
+    Rename(Vec<(String, String)>),
+    LoweredSize,
+    Schema,
+    With(Vec<(String, String)>),
+    AdditionalDerives(Vec<String>),
+    MaxListLen(usize),
+    Stubs,
+    CheckAlignment,
+    NativeFallback,
+    Features(Vec<String>),
+    IntrinsicsPath(String),
+    Ownership(wit_bindgen_gen_guest_rust::Ownership),
+}
+
+impl ConfigField {
+    fn apply(
+        self,
+        opts: &mut wit_bindgen_gen_guest_rust::Opts,
+        lints: &mut Thresholds,
+        interfaces: &mut Vec<Interface>,
+        files: &mut Vec<String>,
+        module: &mut Option<syn::Ident>,
+        overrides: &mut Overrides,
+    ) {
+        match self {
+            ConfigField::Unchecked => opts.unchecked = true,
+            ConfigField::MultiModule => opts.multi_module = true,
+            ConfigField::ExportMacro(name) => opts.export_macro = Some(name),
+            ConfigField::TypesPath(path) => opts.types_path = Some(path),
+            ConfigField::ReexportedCratePath(path) => opts.reexported_crate_path = Some(path),
+            ConfigField::NoMacroExport => opts.no_macro_export = true,
+            ConfigField::ExportMacroVis(vis) => opts.export_macro_vis = Some(vis),
+            ConfigField::ExportMacroModule(name) => opts.export_macro_module = Some(name),
+            ConfigField::ExportMacros(names) => opts.export_macros = names,
+            ConfigField::Message(name) => opts.message = Some(name),
+            ConfigField::Query(name) => opts.query = Some(name),
+            ConfigField::QueryResponse(name) => opts.query_response = Some(name),
+            ConfigField::Batch(names) => opts.batch = names,
+            ConfigField::Memoize(names) => opts.memoize = names,
+            ConfigField::ChunkThreshold(size) => opts.chunk_threshold = Some(size),
+            ConfigField::Interned(names) => opts.interned = names,
+            ConfigField::Varint(names) => opts.varint = names,
+            ConfigField::Bulk => opts.bulk = true,
+            ConfigField::DispatchTable => opts.dispatch_table = true,
+            ConfigField::Profile(path) => opts.profile = Some(path),
+            ConfigField::HotCalls(calls) => opts.hot_calls = Some(calls),
+            ConfigField::ChunkSize(size) => opts.chunk_size = Some(size),
+            ConfigField::DeriveOrd => opts.derive_ord = true,
+            ConfigField::Zeroize => opts.zeroize = true,
+            ConfigField::CtEq => opts.ct_eq = true,
+            ConfigField::Proptest => opts.proptest = true,
+            ConfigField::CallDepthLimit(limit) => opts.call_depth_limit = Some(limit),
+            ConfigField::MaxFlatParams(max) => lints.max_flat_params = Some(max),
+            ConfigField::MaxRecordSize(max) => lints.max_record_size = Some(max),
+            ConfigField::DenyLints => lints.deny = true,
+            ConfigField::AbiVersion(version) => opts.abi_version = Some(version),
+            ConfigField::Interfaces(v, read, o) => {
+                interfaces.extend(v);
+                files.extend(read);
+                overrides.extend(o);
+            }
+            ConfigField::Module(name) => *module = Some(name),
+            ConfigField::ModuleNames(names) => opts.module_names = names,
+            ConfigField::Prelude => opts.prelude = true,
+            ConfigField::PreludeExclude(names) => opts.prelude_exclude = names,
+            ConfigField::NoAlloc => opts.no_alloc = true,
+            ConfigField::NoStd => opts.no_std = true,
+            ConfigField::ConstFns(names) => opts.const_fns = names,
+            ConfigField::ErrorContext(name) => opts.error_context = Some(name),
+            ConfigField::ErrorLocation(name) => opts.error_location = Some(name),
+            ConfigField::DebugFrames => opts.debug_frames = true,
+            ConfigField::Ensure(error) => opts.ensure = Some(error),
+            ConfigField::Skip(names) => opts.skip = names,
+            ConfigField::Rename(names) => opts.rename = names,
+            ConfigField::LoweredSize => opts.lowered_size = true,
+            ConfigField::Schema => opts.schema = true,
+            ConfigField::With(types) => opts.with = types,
+            ConfigField::AdditionalDerives(paths) => opts.additional_derives = paths,
+            ConfigField::MaxListLen(max) => opts.max_list_len = Some(max),
+            ConfigField::Stubs => opts.stubs = true,
+            ConfigField::CheckAlignment => opts.check_alignment = true,
+            ConfigField::NativeFallback => opts.native_fallback = true,
+            ConfigField::Features(names) => opts.features = names,
+            ConfigField::IntrinsicsPath(path) => opts.intrinsics_path = Some(path),
+            ConfigField::Ownership(ownership) => opts.ownership = Some(ownership),
+        }
+    }
+
+    /// Whether the field may be overridden for some interfaces only, as
+    /// opposed to naming them or applying to all the bindings at once.
+    fn is_per_interface(&self) -> bool {
+        !matches!(
+            self,
+            ConfigField::Interfaces(..)
+                | ConfigField::Module(_)
+                | ConfigField::ModuleNames(_)
+                | ConfigField::MaxFlatParams(_)
+                | ConfigField::MaxRecordSize(_)
+                | ConfigField::DenyLints
+                | ConfigField::Prelude
+                | ConfigField::PreludeExclude(_)
+                | ConfigField::NoStd
+                | ConfigField::Stubs
+                | ConfigField::Skip(_)
+                | ConfigField::Features(_)
+                | ConfigField::ExportMacros(_)
+        )
+    }
 }
 
 impl Parse for ConfigField {
@@ -145,24 +1285,49 @@ impl Parse for ConfigField {
             input.parse::<Token![:]>()?;
             let s = input.parse::<syn::LitStr>()?;
             let interface =
-                Interface::parse(&name.value(), &s.value()).map_err(|e| Error::new(s.span(), e))?;
-            Ok(ConfigField::Interfaces(vec![interface]))
+                Interface::parse(&name.value(), &s.value()).map_err(|e| wit_error(s.span(), e))?;
+            Ok(ConfigField::Interfaces(
+                vec![interface],
+                Vec::new(),
+                Vec::new(),
+            ))
+        } else if l.peek(kw::env_src) {
+            input.parse::<kw::env_src>()?;
+            input.parse::<token::Eq>()?;
+            let var = input.parse::<syn::LitStr>()?;
+            let src = std::env::var(var.value()).map_err(|_| {
+                Error::new(
+                    var.span(),
+                    format!("environment variable `{}` is not set", var.value()),
+                )
+            })?;
+            // Named after the variable, as interfaces read from files are
+            // after them.
+            let name = var.value().to_kebab_case();
+            let interface = Interface::parse(&name, &src).map_err(|e| wit_error(var.span(), e))?;
+            Ok(ConfigField::Interfaces(
+                vec![interface],
+                vec![format!("{}{}", ENV_DEPENDENCY, var.value())],
+                Vec::new(),
+            ))
         } else if l.peek(kw::paths) {
             input.parse::<kw::paths>()?;
             input.parse::<Token![:]>()?;
             let paths;
-            let bracket = syn::bracketed!(paths in input);
-            let paths = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&paths)?;
-            let values = paths.iter().map(|s| s.value()).collect::<Vec<_>>();
+            syn::bracketed!(paths in input);
+            let entries = Punctuated::<PathEntry, Token![,]>::parse_terminated(&paths)?;
+            let mut files = Vec::new();
             let mut interfaces = Vec::new();
-            let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-            for value in &values {
-                let value = manifest_dir.join(value);
-                let interface =
-                    Interface::parse_file(value).map_err(|e| Error::new(bracket.span, e))?;
-                interfaces.push(interface);
-            }
-            Ok(ConfigField::Interfaces(interfaces))
+            let mut overrides = Vec::new();
+            for entry in entries {
+                let loaded = load_interfaces([entry.path], input.span(), &mut files)?;
+                if !entry.overrides.is_empty() {
+                    let names = loaded.iter().map(|i| i.name.clone()).collect();
+                    overrides.push((names, entry.overrides));
+                }
+                interfaces.extend(loaded);
+            }
+            Ok(ConfigField::Interfaces(interfaces, files, overrides))
         } else if l.peek(kw::unchecked) {
             input.parse::<kw::unchecked>()?;
             Ok(ConfigField::Unchecked)
@@ -184,8 +1349,391 @@ impl Parse for ConfigField {
             input.parse::<token::Eq>()?;
             let path = input.parse::<syn::LitStr>()?.value();
             Ok(ConfigField::ReexportedCratePath(path))
+        } else if l.peek(kw::no_macro_export) {
+            input.parse::<kw::no_macro_export>()?;
+            Ok(ConfigField::NoMacroExport)
+        } else if l.peek(kw::export_macro_vis) {
+            input.parse::<kw::export_macro_vis>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::ExportMacroVis(parse_vis(input)?))
+        } else if l.peek(kw::export_macro_module) {
+            input.parse::<kw::export_macro_module>()?;
+            input.parse::<token::Eq>()?;
+            let module = input.parse::<syn::LitStr>()?.parse::<syn::Ident>()?;
+            Ok(ConfigField::ExportMacroModule(module.to_string()))
+        } else if l.peek(kw::export_macros) {
+            input.parse::<kw::export_macros>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::ExportMacros(parse_string_map(input)?))
+        } else if l.peek(kw::message) {
+            input.parse::<kw::message>()?;
+            input.parse::<token::Eq>()?;
+            let name = input.parse::<syn::LitStr>()?.value();
+            Ok(ConfigField::Message(name))
+        } else if l.peek(kw::query) {
+            input.parse::<kw::query>()?;
+            input.parse::<token::Eq>()?;
+            let name = input.parse::<syn::LitStr>()?.value();
+            Ok(ConfigField::Query(name))
+        } else if l.peek(kw::query_response) {
+            input.parse::<kw::query_response>()?;
+            input.parse::<token::Eq>()?;
+            let name = input.parse::<syn::LitStr>()?.value();
+            Ok(ConfigField::QueryResponse(name))
+        } else if l.peek(kw::batch) {
+            input.parse::<kw::batch>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Batch(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::memoize) {
+            input.parse::<kw::memoize>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Memoize(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::chunk_threshold) {
+            input.parse::<kw::chunk_threshold>()?;
+            input.parse::<token::Eq>()?;
+            let size = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::ChunkThreshold(size))
+        } else if l.peek(kw::interned) {
+            input.parse::<kw::interned>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Interned(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::varint) {
+            input.parse::<kw::varint>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Varint(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::bulk) {
+            input.parse::<kw::bulk>()?;
+            Ok(ConfigField::Bulk)
+        } else if l.peek(kw::dispatch_table) {
+            input.parse::<kw::dispatch_table>()?;
+            Ok(ConfigField::DispatchTable)
+        } else if l.peek(kw::profile) {
+            input.parse::<kw::profile>()?;
+            input.parse::<token::Eq>()?;
+            let s = input.parse::<syn::LitStr>()?;
+            let path = interpolate(&s)?;
+            Ok(ConfigField::Profile(base_dir(s.span(), &path).join(path)))
+        } else if l.peek(kw::hot_calls) {
+            input.parse::<kw::hot_calls>()?;
+            input.parse::<token::Eq>()?;
+            let calls = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::HotCalls(calls))
+        } else if l.peek(kw::chunk_size) {
+            input.parse::<kw::chunk_size>()?;
+            input.parse::<token::Eq>()?;
+            let size = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::ChunkSize(size))
+        } else if l.peek(kw::derive_ord) {
+            input.parse::<kw::derive_ord>()?;
+            Ok(ConfigField::DeriveOrd)
+        } else if l.peek(kw::zeroize) {
+            input.parse::<kw::zeroize>()?;
+            Ok(ConfigField::Zeroize)
+        } else if l.peek(kw::ct_eq) {
+            input.parse::<kw::ct_eq>()?;
+            Ok(ConfigField::CtEq)
+        } else if l.peek(kw::proptest) {
+            input.parse::<kw::proptest>()?;
+            Ok(ConfigField::Proptest)
+        } else if l.peek(kw::call_depth_limit) {
+            input.parse::<kw::call_depth_limit>()?;
+            input.parse::<token::Eq>()?;
+            let limit = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::CallDepthLimit(limit))
+        } else if l.peek(kw::max_flat_params) {
+            input.parse::<kw::max_flat_params>()?;
+            input.parse::<token::Eq>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxFlatParams(max))
+        } else if l.peek(kw::max_record_size) {
+            input.parse::<kw::max_record_size>()?;
+            input.parse::<token::Eq>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxRecordSize(max))
+        } else if l.peek(kw::deny_lints) {
+            input.parse::<kw::deny_lints>()?;
+            Ok(ConfigField::DenyLints)
+        } else if l.peek(kw::abi_version) {
+            input.parse::<kw::abi_version>()?;
+            input.parse::<token::Eq>()?;
+            let version = input.parse::<syn::LitInt>()?;
+            let value = version.base10_parse()?;
+            abi::check_abi_version(value).map_err(|e| Error::new(version.span(), e))?;
+            Ok(ConfigField::AbiVersion(value))
+        } else if l.peek(kw::module) {
+            input.parse::<kw::module>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::Module(input.parse::<syn::LitStr>()?.parse()?))
+        } else if l.peek(kw::module_names) {
+            input.parse::<kw::module_names>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::ModuleNames(parse_string_map(input)?))
+        } else if l.peek(kw::prelude) {
+            input.parse::<kw::prelude>()?;
+            Ok(ConfigField::Prelude)
+        } else if l.peek(kw::prelude_exclude) {
+            input.parse::<kw::prelude_exclude>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::PreludeExclude(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::no_alloc) {
+            input.parse::<kw::no_alloc>()?;
+            Ok(ConfigField::NoAlloc)
+        } else if l.peek(kw::no_std) {
+            input.parse::<kw::no_std>()?;
+            Ok(ConfigField::NoStd)
+        } else if l.peek(kw::const_fns) {
+            input.parse::<kw::const_fns>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::ConstFns(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::error_context) {
+            input.parse::<kw::error_context>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::ErrorContext(
+                input.parse::<syn::LitStr>()?.value(),
+            ))
+        } else if l.peek(kw::error_location) {
+            input.parse::<kw::error_location>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::ErrorLocation(
+                input.parse::<syn::LitStr>()?.value(),
+            ))
+        } else if l.peek(kw::debug_frames) {
+            input.parse::<kw::debug_frames>()?;
+            Ok(ConfigField::DebugFrames)
+        } else if l.peek(kw::ensure) {
+            input.parse::<kw::ensure>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::Ensure(input.parse::<syn::LitStr>()?.value()))
+        } else if l.peek(kw::skip) {
+            input.parse::<kw::skip>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Skip(names.iter().map(|s| s.value()).collect()))
+        } else if l.peek(kw::rename) {
+            input.parse::<kw::rename>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::Rename(parse_string_map(input)?))
+        } else if l.peek(kw::lowered_size) {
+            input.parse::<kw::lowered_size>()?;
+            Ok(ConfigField::LoweredSize)
+        } else if l.peek(kw::schema) {
+            input.parse::<kw::schema>()?;
+            Ok(ConfigField::Schema)
+        } else if l.peek(kw::with) {
+            input.parse::<kw::with>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::With(parse_string_map(input)?))
+        } else if l.peek(kw::additional_derives) {
+            input.parse::<kw::additional_derives>()?;
+            input.parse::<token::Eq>()?;
+            let paths;
+            syn::bracketed!(paths in input);
+            let paths = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&paths)?;
+            Ok(ConfigField::AdditionalDerives(
+                paths.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::max_list_len) {
+            input.parse::<kw::max_list_len>()?;
+            input.parse::<token::Eq>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxListLen(max))
+        } else if l.peek(kw::stubs) {
+            input.parse::<kw::stubs>()?;
+            Ok(ConfigField::Stubs)
+        } else if l.peek(kw::check_alignment) {
+            input.parse::<kw::check_alignment>()?;
+            Ok(ConfigField::CheckAlignment)
+        } else if l.peek(kw::native_fallback) {
+            input.parse::<kw::native_fallback>()?;
+            Ok(ConfigField::NativeFallback)
+        } else if l.peek(kw::features) {
+            input.parse::<kw::features>()?;
+            input.parse::<token::Eq>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Features(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::intrinsics_path) {
+            input.parse::<kw::intrinsics_path>()?;
+            input.parse::<token::Eq>()?;
+            Ok(ConfigField::IntrinsicsPath(
+                input.parse::<syn::LitStr>()?.value(),
+            ))
+        } else if l.peek(kw::ownership) {
+            input.parse::<kw::ownership>()?;
+            input.parse::<token::Eq>()?;
+            let ownership = input.parse::<syn::LitStr>()?;
+            ownership
+                .value()
+                .parse()
+                .map(ConfigField::Ownership)
+                .map_err(|e| Error::new(ownership.span(), e))
+        } else if input.peek(syn::Ident::peek_any) {
+            Err(KEYS.unknown(input))
         } else {
             Err(l.error())
         }
     }
 }
+
+/// The keys of the braced configuration, to suggest in place of unknown ones.
+const OPTIONS: &[&str] = &[
+    "src",
+    "env_src",
+    "paths",
+    "import",
+    "export",
+    "world",
+    "package",
+    "unchecked",
+    "multi_module",
+    "module_names",
+    "export_macro",
+    "types_path",
+    "reexported_crate_path",
+    "no_macro_export",
+    "export_macro_vis",
+    "export_macro_module",
+    "export_macros",
+    "message",
+    "query",
+    "query_response",
+    "batch",
+    "memoize",
+    "chunk_threshold",
+    "interned",
+    "varint",
+    "bulk",
+    "dispatch_table",
+    "profile",
+    "hot_calls",
+    "chunk_size",
+    "derive_ord",
+    "zeroize",
+    "ct_eq",
+    "proptest",
+    "call_depth_limit",
+    "max_flat_params",
+    "max_record_size",
+    "deny_lints",
+    "abi_version",
+    "module",
+    "prelude",
+    "prelude_exclude",
+    "no_alloc",
+    "no_std",
+    "const_fns",
+    "error_context",
+    "error_location",
+    "debug_frames",
+    "ensure",
+    "skip",
+    "rename",
+    "lowered_size",
+    "schema",
+    "with",
+    "additional_derives",
+    "max_list_len",
+    "stubs",
+    "check_alignment",
+    "native_fallback",
+    "features",
+    "intrinsics_path",
+    "ownership",
+];
+
+/// Omits the items of `interfaces` gated behind features not in `features`.
+fn retain_features(
+    interfaces: &mut [Interface],
+    features: &[String],
+    span: proc_macro2::Span,
+) -> Result<()> {
+    for iface in interfaces.iter_mut() {
+        iface
+            .retain_features(features)
+            .map_err(|e| Error::new(span, format!("{:#}", e)))?;
+    }
+    Ok(())
+}
+
+/// Checks that the `export_macros` of `opts` are keyed by the names of
+/// `exports`.
+fn check_export_macros(
+    opts: &wit_bindgen_gen_guest_rust::Opts,
+    exports: &[Interface],
+    span: proc_macro2::Span,
+) -> Result<()> {
+    for (name, _) in opts.export_macros.iter() {
+        if !exports.iter().any(|e| e.name == *name) {
+            return Err(Error::new(
+                span,
+                format!("`export_macros` names `{}`, which isn't exported", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a visibility written as a string, such as `"pub(crate)"`.
+fn parse_vis(input: ParseStream<'_>) -> Result<String> {
+    let s = input.parse::<syn::LitStr>()?;
+    s.parse::<syn::Visibility>()?;
+    Ok(s.value())
+}
+
+/// Keys which may be given several times, each adding to the others.
+const REPEATABLE: &[&str] = &["src", "env_src"];
+
+/// The keys of the braced configuration and how often each may be given.
+const KEYS: Keys = Keys {
+    known: OPTIONS,
+    repeatable: REPEATABLE,
+};
+
+/// Parses a map of string literals to string literals, written
+/// `{ "from": "to", ... }`.
+fn parse_string_map(input: ParseStream<'_>) -> Result<Vec<(String, String)>> {
+    let entries;
+    syn::braced!(entries in input);
+    let entries = Punctuated::<_, Token![,]>::parse_terminated_with(&entries, |input| {
+        let from = input.parse::<syn::LitStr>()?;
+        input.parse::<Token![:]>()?;
+        let to = input.parse::<syn::LitStr>()?;
+        Ok((from.value(), to.value()))
+    })?;
+    Ok(entries.into_iter().collect())
+}
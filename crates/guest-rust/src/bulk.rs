@@ -0,0 +1,179 @@
+//! Bulk data exchanged with the host through buffers it keeps.
+//!
+//! Bindings generated with `bulk` give each imported interface a
+//! `bulk_writer` and a `bulk_reader` function. A [`BulkWriter`] fills a new
+//! host buffer and hands back its handle, which is then passed to an import
+//! as a plain `u32`. A [`BulkReader`] takes such a handle returned by the host
+//! and copies the buffer out on demand, releasing it when dropped. Either way
+//! the data never goes through the lowering of function arguments.
+
+use std::io;
+
+/// The host functions managing the buffers of one interface.
+pub struct BulkImports {
+    create: unsafe extern "C" fn(len: i32) -> i32,
+    len: unsafe extern "C" fn(handle: i32) -> i32,
+    write: unsafe extern "C" fn(handle: i32, offset: i32, ptr: i32, len: i32),
+    read: unsafe extern "C" fn(handle: i32, offset: i32, ptr: i32, len: i32) -> i32,
+    release: unsafe extern "C" fn(handle: i32),
+}
+
+impl BulkImports {
+    /// Bundles the host functions managing buffers.
+    ///
+    /// # Safety
+    ///
+    /// Each function must be the host's `bulk_*` import of the same name.
+    pub const unsafe fn new(
+        create: unsafe extern "C" fn(len: i32) -> i32,
+        len: unsafe extern "C" fn(handle: i32) -> i32,
+        write: unsafe extern "C" fn(handle: i32, offset: i32, ptr: i32, len: i32),
+        read: unsafe extern "C" fn(handle: i32, offset: i32, ptr: i32, len: i32) -> i32,
+        release: unsafe extern "C" fn(handle: i32),
+    ) -> BulkImports {
+        BulkImports {
+            create,
+            len,
+            write,
+            read,
+            release,
+        }
+    }
+}
+
+/// Fills a new host buffer.
+///
+/// The buffer is released if the writer is dropped before
+/// [`BulkWriter::finish`] is called.
+pub struct BulkWriter {
+    imports: &'static BulkImports,
+    handle: Option<u32>,
+    offset: u32,
+}
+
+impl BulkWriter {
+    /// Creates an empty buffer on the host.
+    pub fn new(imports: &'static BulkImports) -> BulkWriter {
+        let handle = unsafe { (imports.create)(0) } as u32;
+        BulkWriter {
+            imports,
+            handle: Some(handle),
+            offset: 0,
+        }
+    }
+
+    /// Appends `bytes` to the buffer.
+    pub fn write(&mut self, bytes: &[u8]) {
+        let handle = self.handle.unwrap();
+        unsafe {
+            (self.imports.write)(
+                handle as i32,
+                self.offset as i32,
+                bytes.as_ptr() as i32,
+                bytes.len() as i32,
+            );
+        }
+        self.offset += bytes.len() as u32;
+    }
+
+    /// Returns how many bytes have been written so far.
+    pub fn len(&self) -> usize {
+        self.offset as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the handle of the filled buffer, to be passed to the host,
+    /// which then owns it.
+    pub fn finish(mut self) -> u32 {
+        self.handle.take().unwrap()
+    }
+}
+
+impl io::Write for BulkWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        BulkWriter::write(self, buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for BulkWriter {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle {
+            unsafe { (self.imports.release)(handle as i32) }
+        }
+    }
+}
+
+/// Reads a buffer the host returned, releasing it when dropped.
+pub struct BulkReader {
+    imports: &'static BulkImports,
+    handle: u32,
+    offset: u32,
+}
+
+impl BulkReader {
+    /// Takes ownership of the buffer behind `handle`.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must have been returned by the host for a buffer nothing else
+    /// reads or releases.
+    pub unsafe fn new(imports: &'static BulkImports, handle: u32) -> BulkReader {
+        BulkReader {
+            imports,
+            handle,
+            offset: 0,
+        }
+    }
+
+    /// Returns the length of the whole buffer.
+    pub fn len(&self) -> usize {
+        unsafe { (self.imports.len)(self.handle as i32) as u32 as usize }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the next bytes of the buffer into `buf`, returning how many
+    /// were copied, which is zero once the whole buffer has been read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let n = unsafe {
+            (self.imports.read)(
+                self.handle as i32,
+                self.offset as i32,
+                buf.as_mut_ptr() as i32,
+                buf.len() as i32,
+            )
+        } as u32;
+        self.offset += n;
+        n as usize
+    }
+
+    /// Copies the rest of the buffer out.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        let mut bytes = vec![0; self.len() - self.offset as usize];
+        let n = self.read(&mut bytes);
+        bytes.truncate(n);
+        bytes
+    }
+}
+
+impl io::Read for BulkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        Ok(BulkReader::read(self, buf))
+    }
+}
+
+impl Drop for BulkReader {
+    fn drop(&mut self) {
+        unsafe { (self.imports.release)(self.handle as i32) }
+    }
+}
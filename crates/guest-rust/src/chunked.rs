@@ -0,0 +1,163 @@
+//! Incremental reading of large lists returned by the host.
+//!
+//! Imports called through their `*_chunked` counterpart return small results
+//! along with the call, but leave larger ones on the host and hand back a
+//! continuation handle instead. A [`Chunked`] iterator then pulls the encoded
+//! elements across in chunks of a fixed size, decoding each element as soon
+//! as all of its bytes have arrived, so the guest never needs to hold more
+//! than one chunk plus one element at a time.
+
+use crate::codec::{Decode, DecodeError};
+use std::marker;
+
+/// Calls the import numbered `index` with its encoded arguments. If its encoded
+/// result fits into the `buf_len` bytes at `buf` it is copied there and `-1`
+/// minus its length is returned, otherwise the result is kept on the host and
+/// a continuation handle returned.
+pub type CallChunked =
+    unsafe extern "C" fn(index: i32, args: i32, args_len: i32, buf: i32, buf_len: i32) -> i32;
+
+/// Copies up to `len` bytes of the result behind `handle` to `ptr`, returning
+/// how many were copied. Zero means the result is exhausted and the handle has
+/// been released.
+pub type ReadChunk = unsafe extern "C" fn(handle: i32, ptr: i32, len: i32) -> i32;
+
+/// Releases the result behind `handle` before it has been read completely.
+pub type ReleaseChunks = unsafe extern "C" fn(handle: i32);
+
+/// An iterator over the elements of a list which is still held by the host.
+pub struct Chunked<T> {
+    handle: Option<i32>,
+    read: ReadChunk,
+    release: ReleaseChunks,
+    chunk_size: usize,
+    buf: Vec<u8>,
+    pos: usize,
+    _marker: marker::PhantomData<fn() -> T>,
+}
+
+impl<T: Decode> Chunked<T> {
+    /// Creates an iterator reading the list behind `handle` `chunk_size` bytes
+    /// at a time.
+    ///
+    /// # Safety
+    ///
+    /// `handle` must be a live continuation handle for a list of `T`, and
+    /// `read` and `release` must be the host functions which accept it.
+    pub unsafe fn new(
+        handle: i32,
+        read: ReadChunk,
+        release: ReleaseChunks,
+        chunk_size: usize,
+    ) -> Chunked<T> {
+        Chunked {
+            handle: Some(handle),
+            read,
+            release,
+            chunk_size: chunk_size.max(1),
+            buf: Vec::new(),
+            pos: 0,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Calls the import numbered `index` with the encoded `args` through
+    /// `call`, receiving a result of up to `threshold` bytes along with the
+    /// call and reading a larger one `chunk_size` bytes at a time.
+    ///
+    /// # Safety
+    ///
+    /// `index` must name an import returning a list of `T`, and `call`,
+    /// `read` and `release` must be the host functions for chunked calls.
+    pub unsafe fn call(
+        call: CallChunked,
+        index: u32,
+        args: &[u8],
+        read: ReadChunk,
+        release: ReleaseChunks,
+        threshold: usize,
+        chunk_size: usize,
+    ) -> Chunked<T> {
+        let mut buf = vec![0; threshold];
+        let ret = call(
+            index as i32,
+            args.as_ptr() as i32,
+            args.len() as i32,
+            buf.as_mut_ptr() as i32,
+            threshold as i32,
+        );
+        if ret >= 0 {
+            return Chunked::new(ret, read, release, chunk_size);
+        }
+        buf.truncate((-(ret + 1) as usize).min(threshold));
+        Chunked {
+            handle: None,
+            read,
+            release,
+            chunk_size: chunk_size.max(1),
+            buf,
+            pos: 0,
+            _marker: marker::PhantomData,
+        }
+    }
+
+    /// Reads the next chunk onto the end of the buffer, returning `false` if
+    /// the host had nothing left to send.
+    fn fill(&mut self) -> bool {
+        let handle = match self.handle {
+            Some(handle) => handle,
+            None => return false,
+        };
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        let start = self.buf.len();
+        self.buf.resize(start + self.chunk_size, 0);
+        let read = unsafe {
+            (self.read)(
+                handle,
+                self.buf[start..].as_mut_ptr() as i32,
+                self.chunk_size as i32,
+            )
+        };
+        let read = (read as usize).min(self.chunk_size);
+        self.buf.truncate(start + read);
+        if read == 0 {
+            self.handle = None;
+        }
+        read != 0
+    }
+}
+
+impl<T: Decode> Iterator for Chunked<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        loop {
+            let mut input = &self.buf[self.pos..];
+            if !input.is_empty() {
+                match T::decode_from(&mut input) {
+                    Ok(item) => {
+                        self.pos = self.buf.len() - input.len();
+                        return Some(item);
+                    }
+                    Err(DecodeError::UnexpectedEnd) => {}
+                    Err(e) => panic!("invalid element in chunked list: {}", e),
+                }
+            }
+            if !self.fill() {
+                if self.pos < self.buf.len() {
+                    panic!("chunked list ended in the middle of an element");
+                }
+                return None;
+            }
+        }
+    }
+}
+
+impl<T> Drop for Chunked<T> {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            unsafe { (self.release)(handle) }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Interning of strings passed to the host over and over.
+//!
+//! Imports configured for interning take each of their string parameters as
+//! a small id rather than a copy of the string. The first time a string is
+//! passed it is registered with the host, which keeps it for the lifetime of
+//! the instance, and an [`Interner`] remembers the id the host assigned so
+//! later calls with the same string only pass the id.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Registers the string at `ptr` with the host, returning its id.
+pub type RegisterString = unsafe extern "C" fn(ptr: i32, len: i32) -> i32;
+
+/// The ids of the strings registered with the host so far.
+pub struct Interner {
+    register: RegisterString,
+    ids: RefCell<HashMap<String, u32>>,
+}
+
+impl Interner {
+    /// Creates an interner registering new strings through `register`.
+    ///
+    /// # Safety
+    ///
+    /// `register` must be the host function which registers strings for the
+    /// imports the ids are passed to.
+    pub unsafe fn new(register: RegisterString) -> Interner {
+        Interner {
+            register,
+            ids: RefCell::default(),
+        }
+    }
+
+    /// Returns the id of `string`, registering it with the host first if
+    /// needed.
+    pub fn intern(&self, string: &str) -> u32 {
+        if let Some(id) = self.ids.borrow().get(string) {
+            return *id;
+        }
+        let id = unsafe { (self.register)(string.as_ptr() as i32, string.len() as i32) } as u32;
+        self.ids.borrow_mut().insert(string.to_string(), id);
+        id
+    }
+
+    /// Returns how many strings have been registered.
+    pub fn len(&self) -> usize {
+        self.ids.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
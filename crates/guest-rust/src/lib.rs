@@ -3,13 +3,74 @@ use std::marker;
 use std::mem;
 use std::ops::Deref;
 
+pub use wit_bindgen_codec as codec;
 #[cfg(feature = "macros")]
-pub use wit_bindgen_guest_rust_macro::{export, import};
+pub use wit_bindgen_guest_rust_macro::{export, generate, guest, import};
 
 // Re-export `bitflags` so that we can reference it from macros.
 #[doc(hidden)]
 pub use bitflags;
 
+/// Attribute forms of the macros whose names their function-like forms take.
+#[cfg(feature = "macros")]
+pub mod attr {
+    /// Exports the interface implemented by an `impl` block, as [`guest`]
+    /// does, since `export` itself names the function-like macro:
+    ///
+    /// ```ignore
+    /// #[wit_bindgen_guest_rust::attr::export("service.wit")]
+    /// impl MyService {
+    ///     fn serve(request: Vec<u8>) -> Vec<u8> {
+    ///         // ...
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// [`guest`]: crate::guest
+    pub use wit_bindgen_guest_rust_macro::guest as export;
+}
+
+pub mod bulk;
+pub mod chunked;
+pub mod intern;
+pub mod schema;
+
+/// Declares the intrinsics shared by the bindings of several invocations of
+/// the macros, which find them through their `intrinsics_path` option rather
+/// than each declaring their own:
+///
+/// ```ignore
+/// mod intrinsics {
+///     wit_bindgen_guest_rust::intrinsics!(ret_area_size = 256);
+/// }
+///
+/// wit_bindgen_guest_rust::import!({
+///     paths: ["wit/storage.wit"],
+///     intrinsics_path = "crate::intrinsics",
+/// });
+/// ```
+///
+/// These are the area results are returned through, which is 64 bytes
+/// unless `ret_area_size` says otherwise. Bindings needing a larger one fail
+/// to compile.
+#[macro_export]
+macro_rules! intrinsics {
+    () => {
+        $crate::intrinsics!(ret_area_size = 64);
+    };
+    (ret_area_size = $size:expr) => {
+        /// The size of [`RET_AREA`].
+        pub const RET_AREA_SIZE: usize = $size;
+
+        /// The area results of imports and exports are returned through,
+        /// aligned like the most aligned canonical ABI values.
+        #[repr(align(8))]
+        pub struct RetArea(pub [u8; RET_AREA_SIZE]);
+
+        pub static mut RET_AREA: RetArea = RetArea([0; RET_AREA_SIZE]);
+    };
+}
+
 /// A type for handles to resources that appear in exported functions.
 ///
 /// This type is used as `Handle<T>` for argument types and return values of
@@ -123,8 +184,11 @@ pub unsafe trait LocalHandle: HandleType {
 
 #[doc(hidden)]
 pub mod rt {
+    #[cfg(feature = "realloc")]
     use std::alloc::{self, Layout};
+    use std::sync::atomic::{AtomicU32, Ordering};
 
+    #[cfg(feature = "realloc")]
     #[no_mangle]
     unsafe extern "C" fn cabi_realloc(
         old_ptr: *mut u8,
@@ -149,6 +213,20 @@ pub mod rt {
         return ptr;
     }
 
+    /// The allocator under the name hosts built against version 1 of the ABI
+    /// call it by.
+    #[cfg(feature = "abi-v1")]
+    #[no_mangle]
+    pub unsafe extern "C" fn canonical_abi_realloc(
+        old_ptr: *mut u8,
+        old_len: usize,
+        align: usize,
+        new_len: usize,
+    ) -> *mut u8 {
+        cabi_realloc(old_ptr, old_len, align, new_len)
+    }
+
+    #[cfg(feature = "realloc")]
     #[no_mangle]
     pub unsafe extern "C" fn canonical_abi_free(ptr: *mut u8, len: usize, align: usize) {
         if len == 0 {
@@ -158,8 +236,168 @@ pub mod rt {
         alloc::dealloc(ptr, layout);
     }
 
+    static CALL_DEPTH: AtomicU32 = AtomicU32::new(0);
+
+    /// Counts a call into an exported function for as long as it runs, so
+    /// that exports reentered through host calls can't recurse deeper than a
+    /// fixed limit.
+    pub struct CallDepthGuard(());
+
+    impl CallDepthGuard {
+        /// Enters an export, aborting if `limit` calls are already running.
+        pub fn enter(limit: u32) -> CallDepthGuard {
+            match CallDepthGuard::try_enter(limit) {
+                Some(guard) => guard,
+                None => std::process::abort(),
+            }
+        }
+
+        /// Enters an export, or returns `None` if `limit` calls are already
+        /// running.
+        pub fn try_enter(limit: u32) -> Option<CallDepthGuard> {
+            let depth = CALL_DEPTH.fetch_add(1, Ordering::Relaxed);
+            if depth >= limit {
+                CALL_DEPTH.fetch_sub(1, Ordering::Relaxed);
+                return None;
+            }
+            Some(CallDepthGuard(()))
+        }
+
+        /// Returns the number of export calls currently running.
+        pub fn depth() -> u32 {
+            CALL_DEPTH.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Drop for CallDepthGuard {
+        fn drop(&mut self) {
+            CALL_DEPTH.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns whether `a` and `b` are equal, in constant contexts.
+    pub const fn str_eq(a: &str, b: &str) -> bool {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        if a.len() != b.len() {
+            return false;
+        }
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+
+    /// Panics on an invalid `kind` discriminant lifted from the other side.
+    ///
+    /// Generated shims call this rather than expanding `panic!` in place, so
+    /// that the formatting machinery stays out of them and they remain small
+    /// enough for optimizers such as `wasm-opt` to inline.
+    #[cold]
+    #[inline(never)]
+    pub fn invalid_discriminant(kind: &'static str) -> ! {
+        panic!("invalid {} discriminant", kind)
+    }
+
+    /// Panics on a list of `len` elements, or string of `len` bytes, lifted
+    /// from the other side when at most `max` are allowed.
+    #[cold]
+    #[inline(never)]
+    pub fn list_too_long(len: usize, max: usize) -> ! {
+        panic!("list of length {} is over the limit of {}", len, max)
+    }
+
+    /// Returns the layout of a list of `len` elements of `size` bytes
+    /// aligned to `align`, panicking if it overflows the address space.
+    #[inline]
+    pub fn list_layout(len: usize, size: usize, align: usize) -> Layout {
+        match len
+            .checked_mul(size)
+            .map(|bytes| Layout::from_size_align(bytes, align))
+        {
+            Some(Ok(layout)) => layout,
+            _ => list_overflow(),
+        }
+    }
+
+    /// Like [`list_layout`], for a list of `len` elements lifted from `base`,
+    /// additionally panicking if it runs past the end of the address space
+    /// rather than wrapping around.
+    #[inline]
+    pub fn lifted_list_layout(base: i32, len: i32, size: usize, align: usize) -> Layout {
+        let layout = list_layout(len as u32 as usize, size, align);
+        if (base as u32).checked_add(layout.size() as u32).is_none() {
+            list_overflow();
+        }
+        layout
+    }
+
+    /// Returns the address `offset` bytes past `base`, panicking if it runs
+    /// past the end of the address space rather than wrapping around.
+    #[inline]
+    pub fn address(base: i32, offset: i32) -> i32 {
+        match (base as u32).checked_add(offset as u32) {
+            Some(address) => address as i32,
+            None => address_overflow(),
+        }
+    }
+
+    /// Returns the address of the element at `index` of a list of elements
+    /// of `size` bytes starting at `base`, panicking if it runs past the end
+    /// of the address space.
+    #[inline]
+    pub fn element(base: i32, index: usize, size: usize) -> i32 {
+        match index
+            .checked_mul(size)
+            .and_then(|offset| u32::try_from(offset).ok())
+            .and_then(|offset| (base as u32).checked_add(offset))
+        {
+            Some(address) => address as i32,
+            None => list_overflow(),
+        }
+    }
+
+    /// Panics if `ptr`, received from the other side, isn't aligned to
+    /// `align`.
+    #[inline]
+    pub fn check_alignment(ptr: i32, align: usize) {
+        if !(ptr as usize).is_multiple_of(align) {
+            misaligned(ptr, align);
+        }
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn misaligned(ptr: i32, align: usize) -> ! {
+        panic!("pointer {:#x} is not aligned to {}", ptr, align)
+    }
+
+    /// Panics on a call to the import `func` of `iface` from a target other
+    /// than wasm32, where there is no host to call.
+    #[cold]
+    #[inline(never)]
+    pub fn native_import(iface: &str, func: &str) -> ! {
+        panic!("`{}.{}` can only be called on wasm32", iface, func)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn list_overflow() -> ! {
+        panic!("list overflows the address space")
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn address_overflow() -> ! {
+        panic!("address out of bounds")
+    }
+
     macro_rules! as_traits {
         ($(($trait_:ident $func:ident $ty:ident <=> $($tys:ident)*))*) => ($(
+            #[inline]
             pub fn $func<T: $trait_>(t: T) -> $ty {
                 t.$func()
             }
@@ -169,6 +407,7 @@ pub mod rt {
             }
 
             impl<'a, T: Copy + $trait_> $trait_ for &'a T {
+                #[inline]
                 fn $func(self) -> $ty{
                     (*self).$func()
                 }
@@ -0,0 +1,88 @@
+//! Descriptions of the interfaces a module binds, queryable at runtime.
+//!
+//! Bindings generated with the `schema` option describe each interface with
+//! a `SCHEMA` static in its module: the name and signature of each function,
+//! and the shape of each named type, without embedding the WIT text. Dispatch
+//! layers can then route calls by name, and applications describe
+//! themselves.
+
+/// The shape of a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Unit,
+    Bool,
+    U8,
+    U16,
+    U32,
+    U64,
+    S8,
+    S16,
+    S32,
+    S64,
+    Float32,
+    Float64,
+    Char,
+    String,
+    /// A handle to the resource of that name.
+    Handle(&'static str),
+    List(&'static Type),
+    Option(&'static Type),
+    Result(&'static Type, &'static Type),
+    Tuple(&'static [Type]),
+    /// The named type of that name, described in [`Interface::types`].
+    Named(&'static str),
+}
+
+/// A named type of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeDef {
+    pub name: &'static str,
+    pub kind: TypeKind,
+}
+
+/// The shape of a named type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeKind {
+    /// Another name for a type.
+    Alias(Type),
+    /// The fields of a record.
+    Record(&'static [(&'static str, Type)]),
+    /// The cases of a variant and their payloads.
+    Variant(&'static [(&'static str, Type)]),
+    /// The cases of a union.
+    Union(&'static [Type]),
+    /// The cases of an enum.
+    Enum(&'static [&'static str]),
+    /// The flags of a set of flags.
+    Flags(&'static [&'static str]),
+}
+
+/// A function of an interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Function {
+    pub name: &'static str,
+    pub params: &'static [(&'static str, Type)],
+    pub result: Type,
+}
+
+/// An interface, imported or exported by the module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interface {
+    pub name: &'static str,
+    /// Whether the module exports the interface rather than imports it.
+    pub exported: bool,
+    pub functions: &'static [Function],
+    pub types: &'static [TypeDef],
+}
+
+impl Interface {
+    /// Returns the function named `name`, as written in WIT.
+    pub fn function(&self, name: &str) -> Option<&'static Function> {
+        self.functions.iter().find(|f| f.name == name)
+    }
+
+    /// Returns the named type `name`, as written in WIT.
+    pub fn type_def(&self, name: &str) -> Option<&'static TypeDef> {
+        self.types.iter().find(|t| t.name == name)
+    }
+}
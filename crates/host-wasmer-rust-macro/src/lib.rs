@@ -1,10 +1,15 @@
 use std::path::{Path, PathBuf};
 
 use proc_macro::TokenStream;
+use syn::ext::IdentExt;
 use syn::parse::{Error, Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::{token, Token};
-use wit_bindgen_core::{wit_parser::Interface, Direction, Files, Generator};
+use wit_bindgen_core::abi_lock::AbiLock;
+use wit_bindgen_core::lock::{InterfaceSource, Lockfile};
+use wit_bindgen_core::macro_options::Keys;
+use wit_bindgen_core::wit_parser::{abi, Interface};
+use wit_bindgen_core::{Direction, Files, Generator};
 
 /// Generate code to support consuming the given interfaces, importaing them
 /// from wasm modules.
@@ -61,7 +66,10 @@ struct Opts {
 mod kw {
     syn::custom_keyword!(src);
     syn::custom_keyword!(paths);
+    syn::custom_keyword!(registry);
     syn::custom_keyword!(custom_error);
+    syn::custom_keyword!(derive_ord);
+    syn::custom_keyword!(abi_version);
 }
 
 impl Parse for Opts {
@@ -75,11 +83,12 @@ impl Parse for Opts {
             let content;
             syn::braced!(content in input);
             let mut interfaces = Vec::new();
-            let fields = Punctuated::<ConfigField, Token![,]>::parse_terminated(&content)?;
-            for field in fields.into_pairs() {
-                match field.into_value() {
+            for field in KEYS.parse_fields::<ConfigField>(&content)? {
+                match field {
                     ConfigField::Interfaces(v) => interfaces = v,
                     ConfigField::CustomError(v) => opts.custom_error = v,
+                    ConfigField::DeriveOrd(v) => opts.derive_ord = v,
+                    ConfigField::AbiVersion(v) => opts.abi_version = Some(v),
                 }
             }
             if interfaces.is_empty() {
@@ -96,9 +105,21 @@ impl Parse for Opts {
             }
             let mut interfaces = Vec::new();
             let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+            let lockfile = Lockfile::find(&manifest_dir).map_err(|e| Error::new(call_site, e))?;
+            let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(call_site, e))?;
             for path in files.iter() {
                 let path = manifest_dir.join(path);
-                let iface = Interface::parse_file(path).map_err(|e| Error::new(call_site, e))?;
+                let iface = Interface::parse_file(&path).map_err(|e| Error::new(call_site, e))?;
+                if let Some(lockfile) = &lockfile {
+                    lockfile
+                        .verify(&InterfaceSource::File(path), &iface)
+                        .map_err(|e| Error::new(call_site, e))?;
+                }
+                if let Some(abi_lock) = &abi_lock {
+                    abi_lock
+                        .verify(&iface)
+                        .map_err(|e| Error::new(call_site, e))?;
+                }
                 interfaces.push(iface);
             }
             interfaces
@@ -111,9 +132,31 @@ impl Parse for Opts {
     }
 }
 
+/// An entry of `paths`: either a file relative to the crate root or
+/// `registry("namespace:name@version")`.
+enum InterfacePath {
+    File(syn::LitStr),
+    Registry(syn::LitStr),
+}
+
+impl Parse for InterfacePath {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if input.peek(kw::registry) {
+            input.parse::<kw::registry>()?;
+            let reference;
+            syn::parenthesized!(reference in input);
+            Ok(InterfacePath::Registry(reference.parse()?))
+        } else {
+            Ok(InterfacePath::File(input.parse()?))
+        }
+    }
+}
+
 enum ConfigField {
     Interfaces(Vec<Interface>),
     CustomError(bool),
+    DeriveOrd(bool),
+    AbiVersion(u32),
 }
 
 impl Parse for ConfigField {
@@ -133,15 +176,37 @@ impl Parse for ConfigField {
             input.parse::<kw::paths>()?;
             input.parse::<Token![:]>()?;
             let paths;
-            let bracket = syn::bracketed!(paths in input);
-            let paths = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&paths)?;
-            let values = paths.iter().map(|s| s.value()).collect::<Vec<_>>();
+            syn::bracketed!(paths in input);
+            let paths = Punctuated::<InterfacePath, Token![,]>::parse_terminated(&paths)?;
             let mut interfaces = Vec::new();
             let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-            for value in &values {
-                let value = manifest_dir.join(value);
-                let interface =
-                    Interface::parse_file(value).map_err(|e| Error::new(bracket.span, e))?;
+            let lockfile =
+                Lockfile::find(&manifest_dir).map_err(|e| Error::new(input.span(), e))?;
+            let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(input.span(), e))?;
+            for path in paths {
+                let (span, source) = match path {
+                    InterfacePath::File(s) => (
+                        s.span(),
+                        InterfaceSource::File(manifest_dir.join(s.value())),
+                    ),
+                    InterfacePath::Registry(s) => {
+                        let reference = s.value().parse().map_err(|e| Error::new(s.span(), e))?;
+                        (s.span(), InterfaceSource::Registry(reference))
+                    }
+                };
+                let interface = source
+                    .load(lockfile.as_ref())
+                    .map_err(|e| Error::new(span, e))?;
+                if let Some(lockfile) = &lockfile {
+                    lockfile
+                        .verify(&source, &interface)
+                        .map_err(|e| Error::new(span, e))?;
+                }
+                if let Some(abi_lock) = &abi_lock {
+                    abi_lock
+                        .verify(&interface)
+                        .map_err(|e| Error::new(span, e))?;
+                }
                 interfaces.push(interface);
             }
             Ok(ConfigField::Interfaces(interfaces))
@@ -151,8 +216,30 @@ impl Parse for ConfigField {
             Ok(ConfigField::CustomError(
                 input.parse::<syn::LitBool>()?.value,
             ))
+        } else if l.peek(kw::derive_ord) {
+            input.parse::<kw::derive_ord>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::DeriveOrd(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::abi_version) {
+            input.parse::<kw::abi_version>()?;
+            input.parse::<Token![:]>()?;
+            let version = input.parse::<syn::LitInt>()?;
+            let value = version.base10_parse()?;
+            abi::check_abi_version(value).map_err(|e| Error::new(version.span(), e))?;
+            Ok(ConfigField::AbiVersion(value))
+        } else if input.peek(syn::Ident::peek_any) {
+            Err(KEYS.unknown(input))
         } else {
             Err(l.error())
         }
     }
 }
+
+/// The keys of the braced configuration, to suggest in place of unknown ones.
+const OPTIONS: &[&str] = &["src", "paths", "custom_error", "derive_ord", "abi_version"];
+
+/// The keys of the braced configuration, none of which may be repeated.
+const KEYS: Keys = Keys {
+    known: OPTIONS,
+    repeatable: &[],
+};
@@ -1,10 +1,16 @@
 use std::path::{Path, PathBuf};
 
 use proc_macro::TokenStream;
+use syn::ext::IdentExt;
 use syn::parse::{Error, Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
 use syn::{token, Token};
-use wit_bindgen_core::{wit_parser::Interface, Direction, Files, Generator};
+use wit_bindgen_core::abi_lock::AbiLock;
+use wit_bindgen_core::lint::Thresholds;
+use wit_bindgen_core::lock::{InterfaceSource, Lockfile};
+use wit_bindgen_core::macro_options::Keys;
+use wit_bindgen_core::wit_parser::{abi, Interface};
+use wit_bindgen_core::{Direction, Files, Generator};
 
 /// Generate code to support consuming the given interfaces, importaing them
 /// from wasm modules.
@@ -61,7 +67,41 @@ struct Opts {
 mod kw {
     syn::custom_keyword!(src);
     syn::custom_keyword!(paths);
+    syn::custom_keyword!(registry);
     syn::custom_keyword!(custom_error);
+    syn::custom_keyword!(batch);
+    syn::custom_keyword!(chunked);
+    syn::custom_keyword!(interned);
+    syn::custom_keyword!(varint);
+    syn::custom_keyword!(bulk);
+    syn::custom_keyword!(dispatch_table);
+    syn::custom_keyword!(memory_budget);
+    syn::custom_keyword!(derive_ord);
+    syn::custom_keyword!(proptest);
+    syn::custom_keyword!(object_safe);
+    syn::custom_keyword!(thread_bounds);
+    syn::custom_keyword!(task_scope);
+    syn::custom_keyword!(component_compat);
+    syn::custom_keyword!(spawn_blocking);
+    syn::custom_keyword!(coalesce);
+    syn::custom_keyword!(call_depth);
+    syn::custom_keyword!(max_flat_params);
+    syn::custom_keyword!(max_record_size);
+    syn::custom_keyword!(deny_lints);
+    syn::custom_keyword!(abi_version);
+    syn::custom_keyword!(error_context);
+    syn::custom_keyword!(error_location);
+    syn::custom_keyword!(symbols);
+    syn::custom_keyword!(mutants);
+    syn::custom_keyword!(faults);
+    syn::custom_keyword!(quotas);
+    syn::custom_keyword!(lowered_size);
+    syn::custom_keyword!(call_log);
+    syn::custom_keyword!(max_list_len);
+    syn::custom_keyword!(lift_budget);
+    syn::custom_keyword!(cfg_features);
+    syn::custom_keyword!(doc_examples);
+    syn::custom_keyword!(protocol);
 }
 
 impl Parse for Opts {
@@ -69,17 +109,51 @@ impl Parse for Opts {
         let call_site = proc_macro2::Span::call_site();
         let mut opts = wit_bindgen_gen_host_wasmtime_rust::Opts::default();
         let mut files = Vec::new();
+        let mut lints = Thresholds::default();
         opts.tracing = cfg!(feature = "tracing");
+        opts.object_safe = cfg!(feature = "object-safe");
 
         let interfaces = if input.peek(token::Brace) {
             let content;
             syn::braced!(content in input);
             let mut interfaces = Vec::new();
-            let fields = Punctuated::<ConfigField, Token![,]>::parse_terminated(&content)?;
-            for field in fields.into_pairs() {
-                match field.into_value() {
+            for field in KEYS.parse_fields::<ConfigField>(&content)? {
+                match field {
                     ConfigField::Interfaces(v) => interfaces = v,
                     ConfigField::CustomError(v) => opts.custom_error = v,
+                    ConfigField::Batch(v) => opts.batch = v,
+                    ConfigField::Chunked(v) => opts.chunked = v,
+                    ConfigField::Interned(v) => opts.interned = v,
+                    ConfigField::Varint(v) => opts.varint = v,
+                    ConfigField::Bulk(v) => opts.bulk = v,
+                    ConfigField::DispatchTable(v) => opts.dispatch_table = v,
+                    ConfigField::MemoryBudget(v) => opts.memory_budget = v,
+                    ConfigField::DeriveOrd(v) => opts.derive_ord = v,
+                    ConfigField::Proptest(v) => opts.proptest = v,
+                    ConfigField::ObjectSafe(v) => opts.object_safe = v,
+                    ConfigField::ThreadBounds(v) => opts.thread_bounds = Some(v),
+                    ConfigField::TaskScope(v) => opts.task_scope = v,
+                    ConfigField::ComponentCompat(v) => opts.component_compat = v,
+                    ConfigField::SpawnBlocking(v) => opts.spawn_blocking = v,
+                    ConfigField::Coalesce(v) => opts.coalesce = v,
+                    ConfigField::CallDepth(v) => opts.call_depth = Some(v),
+                    ConfigField::MaxFlatParams(v) => lints.max_flat_params = Some(v),
+                    ConfigField::MaxRecordSize(v) => lints.max_record_size = Some(v),
+                    ConfigField::DenyLints(v) => lints.deny = v,
+                    ConfigField::AbiVersion(v) => opts.abi_version = Some(v),
+                    ConfigField::ErrorContext(v) => opts.error_context = Some(v),
+                    ConfigField::ErrorLocation(v) => opts.error_location = Some(v),
+                    ConfigField::Symbols(v) => opts.symbols = v,
+                    ConfigField::Mutants(v) => opts.mutants = v,
+                    ConfigField::Faults(v) => opts.faults = v,
+                    ConfigField::Quotas(v) => opts.quotas = v,
+                    ConfigField::LoweredSize(v) => opts.lowered_size = v,
+                    ConfigField::CallLog(v) => opts.call_log = v,
+                    ConfigField::MaxListLen(max) => opts.max_list_len = Some(max),
+                    ConfigField::LiftBudget(budgets) => opts.lift_budget = budgets,
+                    ConfigField::CfgFeatures(v) => opts.cfg_features = v,
+                    ConfigField::DocExamples(v) => opts.doc_examples = v,
+                    ConfigField::Protocol(v) => opts.protocol = v,
                 }
             }
             if interfaces.is_empty() {
@@ -96,13 +170,28 @@ impl Parse for Opts {
             }
             let mut interfaces = Vec::new();
             let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
+            let lockfile = Lockfile::find(&manifest_dir).map_err(|e| Error::new(call_site, e))?;
+            let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(call_site, e))?;
             for path in files.iter() {
                 let path = manifest_dir.join(path);
-                let iface = Interface::parse_file(path).map_err(|e| Error::new(call_site, e))?;
+                let iface = Interface::parse_file(&path).map_err(|e| wit_error(call_site, e))?;
+                if let Some(lockfile) = &lockfile {
+                    lockfile
+                        .verify(&InterfaceSource::File(path), &iface)
+                        .map_err(|e| Error::new(call_site, e))?;
+                }
+                if let Some(abi_lock) = &abi_lock {
+                    abi_lock
+                        .verify(&iface)
+                        .map_err(|e| Error::new(call_site, e))?;
+                }
                 interfaces.push(iface);
             }
             interfaces
         };
+        lints
+            .enforce(&interfaces)
+            .map_err(|e| Error::new(call_site, e))?;
         Ok(Opts {
             opts,
             interfaces,
@@ -111,9 +200,62 @@ impl Parse for Opts {
     }
 }
 
+/// An entry of `paths`: either a file relative to the crate root or
+/// `registry("namespace:name@version")`.
+enum InterfacePath {
+    File(syn::LitStr),
+    Registry(syn::LitStr),
+}
+
+impl Parse for InterfacePath {
+    fn parse(input: ParseStream<'_>) -> Result<Self> {
+        if input.peek(kw::registry) {
+            input.parse::<kw::registry>()?;
+            let reference;
+            syn::parenthesized!(reference in input);
+            Ok(InterfacePath::Registry(reference.parse()?))
+        } else {
+            Ok(InterfacePath::File(input.parse()?))
+        }
+    }
+}
+
 enum ConfigField {
     Interfaces(Vec<Interface>),
     CustomError(bool),
+    Batch(Vec<String>),
+    Chunked(bool),
+    Interned(Vec<String>),
+    Varint(Vec<String>),
+    Bulk(bool),
+    DispatchTable(bool),
+    MemoryBudget(bool),
+    DeriveOrd(bool),
+    Proptest(bool),
+    ObjectSafe(bool),
+    ThreadBounds(wit_bindgen_gen_host_wasmtime_rust::ThreadBounds),
+    TaskScope(bool),
+    ComponentCompat(bool),
+    SpawnBlocking(bool),
+    Coalesce(Vec<String>),
+    CallDepth(u32),
+    MaxFlatParams(usize),
+    MaxRecordSize(usize),
+    DenyLints(bool),
+    AbiVersion(u32),
+    ErrorContext(String),
+    ErrorLocation(String),
+    Symbols(bool),
+    Mutants(bool),
+    Faults(bool),
+    Quotas(bool),
+    LoweredSize(bool),
+    CallLog(bool),
+    MaxListLen(u32),
+    LiftBudget(Vec<(String, u64)>),
+    CfgFeatures(bool),
+    DocExamples(bool),
+    Protocol(bool),
 }
 
 impl Parse for ConfigField {
@@ -127,21 +269,43 @@ impl Parse for ConfigField {
             input.parse::<Token![:]>()?;
             let s = input.parse::<syn::LitStr>()?;
             let interface =
-                Interface::parse(&name.value(), &s.value()).map_err(|e| Error::new(s.span(), e))?;
+                Interface::parse(&name.value(), &s.value()).map_err(|e| wit_error(s.span(), e))?;
             Ok(ConfigField::Interfaces(vec![interface]))
         } else if l.peek(kw::paths) {
             input.parse::<kw::paths>()?;
             input.parse::<Token![:]>()?;
             let paths;
-            let bracket = syn::bracketed!(paths in input);
-            let paths = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&paths)?;
-            let values = paths.iter().map(|s| s.value()).collect::<Vec<_>>();
+            syn::bracketed!(paths in input);
+            let paths = Punctuated::<InterfacePath, Token![,]>::parse_terminated(&paths)?;
             let mut interfaces = Vec::new();
             let manifest_dir = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap());
-            for value in &values {
-                let value = manifest_dir.join(value);
-                let interface =
-                    Interface::parse_file(value).map_err(|e| Error::new(bracket.span, e))?;
+            let lockfile =
+                Lockfile::find(&manifest_dir).map_err(|e| Error::new(input.span(), e))?;
+            let abi_lock = AbiLock::find(&manifest_dir).map_err(|e| Error::new(input.span(), e))?;
+            for path in paths {
+                let (span, source) = match path {
+                    InterfacePath::File(s) => (
+                        s.span(),
+                        InterfaceSource::File(manifest_dir.join(s.value())),
+                    ),
+                    InterfacePath::Registry(s) => {
+                        let reference = s.value().parse().map_err(|e| Error::new(s.span(), e))?;
+                        (s.span(), InterfaceSource::Registry(reference))
+                    }
+                };
+                let interface = source
+                    .load(lockfile.as_ref())
+                    .map_err(|e| wit_error(span, e))?;
+                if let Some(lockfile) = &lockfile {
+                    lockfile
+                        .verify(&source, &interface)
+                        .map_err(|e| Error::new(span, e))?;
+                }
+                if let Some(abi_lock) = &abi_lock {
+                    abi_lock
+                        .verify(&interface)
+                        .map_err(|e| Error::new(span, e))?;
+                }
                 interfaces.push(interface);
             }
             Ok(ConfigField::Interfaces(interfaces))
@@ -151,8 +315,271 @@ impl Parse for ConfigField {
             Ok(ConfigField::CustomError(
                 input.parse::<syn::LitBool>()?.value,
             ))
+        } else if l.peek(kw::batch) {
+            input.parse::<kw::batch>()?;
+            input.parse::<Token![:]>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Batch(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::chunked) {
+            input.parse::<kw::chunked>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Chunked(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::interned) {
+            input.parse::<kw::interned>()?;
+            input.parse::<Token![:]>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Interned(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::varint) {
+            input.parse::<kw::varint>()?;
+            input.parse::<Token![:]>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Varint(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::bulk) {
+            input.parse::<kw::bulk>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Bulk(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::dispatch_table) {
+            input.parse::<kw::dispatch_table>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::DispatchTable(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::memory_budget) {
+            input.parse::<kw::memory_budget>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::MemoryBudget(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::derive_ord) {
+            input.parse::<kw::derive_ord>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::DeriveOrd(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::proptest) {
+            input.parse::<kw::proptest>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Proptest(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::object_safe) {
+            input.parse::<kw::object_safe>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::ObjectSafe(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::thread_bounds) {
+            input.parse::<kw::thread_bounds>()?;
+            input.parse::<Token![:]>()?;
+            let bounds = input.parse::<syn::LitStr>()?;
+            bounds
+                .value()
+                .parse()
+                .map(ConfigField::ThreadBounds)
+                .map_err(|e| Error::new(bounds.span(), e))
+        } else if l.peek(kw::task_scope) {
+            let span = input.parse::<kw::task_scope>()?.span;
+            input.parse::<Token![:]>()?;
+            let enabled = input.parse::<syn::LitBool>()?.value;
+            if enabled && !cfg!(feature = "async") {
+                return Err(Error::new(
+                    span,
+                    "`task_scope` requires the `async` feature of `wit-bindgen-host-wasmtime-rust`",
+                ));
+            }
+            Ok(ConfigField::TaskScope(enabled))
+        } else if l.peek(kw::component_compat) {
+            input.parse::<kw::component_compat>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::ComponentCompat(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::spawn_blocking) {
+            let span = input.parse::<kw::spawn_blocking>()?.span;
+            input.parse::<Token![:]>()?;
+            let enabled = input.parse::<syn::LitBool>()?.value;
+            if enabled && !cfg!(feature = "tokio") {
+                return Err(Error::new(
+                    span,
+                    "`spawn_blocking` requires the `tokio` feature of `wit-bindgen-host-wasmtime-rust`",
+                ));
+            }
+            Ok(ConfigField::SpawnBlocking(enabled))
+        } else if l.peek(kw::coalesce) {
+            input.parse::<kw::coalesce>()?;
+            input.parse::<Token![:]>()?;
+            let names;
+            syn::bracketed!(names in input);
+            let names = Punctuated::<syn::LitStr, Token![,]>::parse_terminated(&names)?;
+            Ok(ConfigField::Coalesce(
+                names.iter().map(|s| s.value()).collect(),
+            ))
+        } else if l.peek(kw::call_depth) {
+            input.parse::<kw::call_depth>()?;
+            input.parse::<Token![:]>()?;
+            let depth = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::CallDepth(depth))
+        } else if l.peek(kw::max_flat_params) {
+            input.parse::<kw::max_flat_params>()?;
+            input.parse::<Token![:]>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxFlatParams(max))
+        } else if l.peek(kw::max_record_size) {
+            input.parse::<kw::max_record_size>()?;
+            input.parse::<Token![:]>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxRecordSize(max))
+        } else if l.peek(kw::deny_lints) {
+            input.parse::<kw::deny_lints>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::DenyLints(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::abi_version) {
+            input.parse::<kw::abi_version>()?;
+            input.parse::<Token![:]>()?;
+            let version = input.parse::<syn::LitInt>()?;
+            let value = version.base10_parse()?;
+            abi::check_abi_version(value).map_err(|e| Error::new(version.span(), e))?;
+            Ok(ConfigField::AbiVersion(value))
+        } else if l.peek(kw::error_context) {
+            input.parse::<kw::error_context>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::ErrorContext(
+                input.parse::<syn::LitStr>()?.value(),
+            ))
+        } else if l.peek(kw::error_location) {
+            input.parse::<kw::error_location>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::ErrorLocation(
+                input.parse::<syn::LitStr>()?.value(),
+            ))
+        } else if l.peek(kw::symbols) {
+            input.parse::<kw::symbols>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Symbols(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::mutants) {
+            input.parse::<kw::mutants>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Mutants(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::faults) {
+            input.parse::<kw::faults>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Faults(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::quotas) {
+            input.parse::<kw::quotas>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Quotas(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::lowered_size) {
+            input.parse::<kw::lowered_size>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::LoweredSize(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::call_log) {
+            input.parse::<kw::call_log>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::CallLog(input.parse::<syn::LitBool>()?.value))
+        } else if l.peek(kw::max_list_len) {
+            input.parse::<kw::max_list_len>()?;
+            input.parse::<Token![:]>()?;
+            let max = input.parse::<syn::LitInt>()?.base10_parse()?;
+            Ok(ConfigField::MaxListLen(max))
+        } else if l.peek(kw::lift_budget) {
+            input.parse::<kw::lift_budget>()?;
+            input.parse::<Token![:]>()?;
+            let content;
+            syn::braced!(content in input);
+            let mut budgets = Vec::new();
+            while !content.is_empty() {
+                let name = content.parse::<syn::LitStr>()?.value();
+                content.parse::<Token![:]>()?;
+                let bytes = content.parse::<syn::LitInt>()?.base10_parse()?;
+                budgets.push((name, bytes));
+                if content.is_empty() {
+                    break;
+                }
+                content.parse::<Token![,]>()?;
+            }
+            Ok(ConfigField::LiftBudget(budgets))
+        } else if l.peek(kw::cfg_features) {
+            input.parse::<kw::cfg_features>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::CfgFeatures(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::doc_examples) {
+            input.parse::<kw::doc_examples>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::DocExamples(
+                input.parse::<syn::LitBool>()?.value,
+            ))
+        } else if l.peek(kw::protocol) {
+            input.parse::<kw::protocol>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Protocol(input.parse::<syn::LitBool>()?.value))
+        } else if input.peek(syn::Ident::peek_any) {
+            Err(KEYS.unknown(input))
         } else {
             Err(l.error())
         }
     }
 }
+
+/// The keys of the braced configuration, to suggest in place of unknown ones.
+const OPTIONS: &[&str] = &[
+    "src",
+    "paths",
+    "custom_error",
+    "batch",
+    "chunked",
+    "interned",
+    "varint",
+    "bulk",
+    "dispatch_table",
+    "memory_budget",
+    "derive_ord",
+    "proptest",
+    "object_safe",
+    "thread_bounds",
+    "task_scope",
+    "component_compat",
+    "spawn_blocking",
+    "coalesce",
+    "call_depth",
+    "max_flat_params",
+    "max_record_size",
+    "deny_lints",
+    "abi_version",
+    "error_context",
+    "error_location",
+    "symbols",
+    "mutants",
+    "faults",
+    "quotas",
+    "lowered_size",
+    "call_log",
+    "max_list_len",
+    "lift_budget",
+    "cfg_features",
+    "doc_examples",
+    "protocol",
+];
+
+/// The keys of the braced configuration, none of which may be repeated.
+const KEYS: Keys = Keys {
+    known: OPTIONS,
+    repeatable: &[],
+};
+
+/// Reports `e`, raised while loading WIT, at `span` along with its causes,
+/// so that a parse error names the WIT file and shows the offending line.
+fn wit_error(span: proc_macro2::Span, e: impl std::fmt::Display) -> Error {
+    Error::new(span, format!("{:#}", e))
+}
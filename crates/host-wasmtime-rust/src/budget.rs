@@ -0,0 +1,143 @@
+use thiserror::Error;
+
+/// Limits how much guest memory generated bindings may allocate while
+/// lowering lists and strings into wasm.
+///
+/// This is only consulted by bindings generated with the `memory_budget`
+/// option. Every allocation is reserved before it is made, so a budget can
+/// refuse it before the guest's memory grows.
+pub trait MemoryBudget {
+    /// Reserves `bytes` bytes of guest memory for a single lowered value.
+    fn reserve(&mut self, bytes: u64) -> Result<(), BudgetExceeded>;
+}
+
+/// The error a [`MemoryBudget`] returns when an allocation doesn't fit.
+///
+/// Generated bindings turn this into a trap which wraps it.
+#[derive(Debug, Error, PartialEq, Eq, Clone, Copy)]
+#[error("allocating {requested} bytes of guest memory exceeds the remaining budget of {remaining} bytes")]
+pub struct BudgetExceeded {
+    pub requested: u64,
+    pub remaining: u64,
+}
+
+/// A budget which allows a fixed number of bytes in total.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedBudget {
+    remaining: u64,
+}
+
+impl FixedBudget {
+    pub fn new(bytes: u64) -> FixedBudget {
+        FixedBudget { remaining: bytes }
+    }
+
+    /// Returns how many bytes can still be reserved.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+}
+
+impl MemoryBudget for FixedBudget {
+    fn reserve(&mut self, bytes: u64) -> Result<(), BudgetExceeded> {
+        match self.remaining.checked_sub(bytes) {
+            Some(remaining) => {
+                self.remaining = remaining;
+                Ok(())
+            }
+            None => Err(BudgetExceeded {
+                requested: bytes,
+                remaining: self.remaining,
+            }),
+        }
+    }
+}
+
+/// Limits how many bytes a single call lifts out of guest memory.
+///
+/// Bindings generated with the `lift_budget` option start a budget for every
+/// call to or from a budgeted interface, and charge it each list and string
+/// they lift, at its size in guest memory with each element counting at
+/// least one byte, before copying anything out.
+#[derive(Debug, Clone, Copy)]
+pub struct LiftBudget {
+    function: &'static str,
+    limit: u64,
+    used: u64,
+}
+
+impl LiftBudget {
+    /// Creates the budget of a call to `function`, named
+    /// `interface.function`.
+    pub fn new(function: &'static str, limit: u64) -> LiftBudget {
+        LiftBudget {
+            function,
+            limit,
+            used: 0,
+        }
+    }
+
+    /// Charges `len` lifted elements of `size` bytes each.
+    pub fn charge(&mut self, len: u64, size: u64) -> Result<(), LiftBudgetExceeded> {
+        self.used = self.used.saturating_add(len.saturating_mul(size.max(1)));
+        if self.used > self.limit {
+            return Err(LiftBudgetExceeded {
+                function: self.function.to_string(),
+                limit: self.limit,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns how many bytes have been charged so far.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+}
+
+/// The error a [`LiftBudget`] returns when the guest passes more than it
+/// allows.
+///
+/// Generated bindings turn this into a trap which wraps it.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("the guest passed more than {limit} bytes through `{function}`")]
+pub struct LiftBudgetExceeded {
+    pub function: String,
+    pub limit: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lift_budget() {
+        let mut budget = LiftBudget::new("kv.get", 10);
+        assert_eq!(budget.charge(2, 4), Ok(()));
+        // Empty elements still count.
+        assert_eq!(budget.charge(2, 0), Ok(()));
+        assert_eq!(budget.used(), 10);
+        assert_eq!(
+            budget.charge(1, 1),
+            Err(LiftBudgetExceeded {
+                function: "kv.get".to_string(),
+                limit: 10,
+            })
+        );
+    }
+
+    #[test]
+    fn fixed_budget() {
+        let mut budget = FixedBudget::new(10);
+        assert_eq!(budget.reserve(4), Ok(()));
+        assert_eq!(budget.reserve(6), Ok(()));
+        assert_eq!(
+            budget.reserve(1),
+            Err(BudgetExceeded {
+                requested: 1,
+                remaining: 0
+            })
+        );
+        assert_eq!(budget.remaining(), 0);
+    }
+}
@@ -0,0 +1,117 @@
+//! Buffers of bulk data exchanged with wasm by handle.
+//!
+//! Bindings generated with `bulk: true` give wasm imports to create, fill,
+//! read and release buffers kept on the host. Functions of the interface then
+//! take or return a `u32` handle to such a buffer instead of a list, so that
+//! large blobs never go through the lowering of function arguments and
+//! results. The host reaches the same buffers through [`BulkBuffers`].
+
+use crate::slab::Slab;
+use wasmtime::Trap;
+
+/// The buffers of an instance, indexed by the handles passed to wasm.
+#[derive(Default, Debug)]
+pub struct BulkBuffers {
+    buffers: Slab<Vec<u8>>,
+}
+
+impl BulkBuffers {
+    /// Adds a buffer holding `bytes`, returning the handle to give to wasm.
+    pub fn insert(&mut self, bytes: Vec<u8>) -> u32 {
+        self.buffers.insert(bytes)
+    }
+
+    /// Returns the contents of the buffer behind `handle`.
+    pub fn get(&self, handle: u32) -> Result<&[u8], Trap> {
+        self.buffers
+            .get(handle)
+            .map(|buffer| &buffer[..])
+            .ok_or_else(|| Trap::new("invalid bulk buffer handle"))
+    }
+
+    /// Returns the buffer behind `handle`, which may be resized.
+    pub fn get_mut(&mut self, handle: u32) -> Result<&mut Vec<u8>, Trap> {
+        self.buffers
+            .get_mut(handle)
+            .ok_or_else(|| Trap::new("invalid bulk buffer handle"))
+    }
+
+    /// Releases the buffer behind `handle`, returning its contents.
+    pub fn remove(&mut self, handle: u32) -> Result<Vec<u8>, Trap> {
+        self.buffers
+            .remove(handle)
+            .ok_or_else(|| Trap::new("invalid bulk buffer handle"))
+    }
+
+    /// Copies `len` bytes at `ptr` in `memory` into the buffer behind
+    /// `handle`, starting at `offset` and growing the buffer if needed.
+    pub fn write_from(
+        &mut self,
+        memory: &[u8],
+        handle: u32,
+        offset: u32,
+        ptr: u32,
+        len: u32,
+    ) -> Result<(), Trap> {
+        let src = guest_range(memory.len(), ptr, len)?;
+        let buffer = self.get_mut(handle)?;
+        let start = offset as usize;
+        let end = start + len as usize;
+        if buffer.len() < end {
+            buffer.resize(end, 0);
+        }
+        buffer[start..end].copy_from_slice(&memory[src]);
+        Ok(())
+    }
+
+    /// Copies up to `len` bytes of the buffer behind `handle`, starting at
+    /// `offset`, to `ptr` in `memory`, returning how many were copied.
+    pub fn read_into(
+        &self,
+        memory: &mut [u8],
+        handle: u32,
+        offset: u32,
+        ptr: u32,
+        len: u32,
+    ) -> Result<u32, Trap> {
+        let buffer = self.get(handle)?;
+        let start = (offset as usize).min(buffer.len());
+        let available = &buffer[start..];
+        let n = available.len().min(len as usize);
+        let dst = guest_range(memory.len(), ptr, n as u32)?;
+        memory[dst].copy_from_slice(&available[..n]);
+        Ok(n as u32)
+    }
+}
+
+fn guest_range(size: usize, ptr: u32, len: u32) -> Result<std::ops::Range<usize>, Trap> {
+    let start = ptr as usize;
+    let end = start
+        .checked_add(len as usize)
+        .filter(|end| *end <= size)
+        .ok_or_else(|| Trap::new("out of bounds bulk buffer access"))?;
+    Ok(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_between_memory_and_buffers() {
+        let mut buffers = BulkBuffers::default();
+        let handle = buffers.insert(Vec::new());
+
+        let mut memory = [1, 2, 3, 4, 0, 0, 0, 0];
+        buffers.write_from(&memory, handle, 2, 0, 4).unwrap();
+        assert_eq!(buffers.get(handle).unwrap(), [0, 0, 1, 2, 3, 4]);
+
+        assert_eq!(buffers.read_into(&mut memory, handle, 3, 4, 4).unwrap(), 3);
+        assert_eq!(memory, [1, 2, 3, 4, 2, 3, 4, 0]);
+
+        assert!(buffers.write_from(&memory, handle, 0, 6, 4).is_err());
+        assert!(buffers.read_into(&mut memory, handle, 0, 6, 4).is_err());
+        assert_eq!(buffers.remove(handle).unwrap().len(), 6);
+        assert!(buffers.get(handle).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! A record of the last calls wasm made to the host.
+//!
+//! Host bindings generated with the `call_log` option enter each host
+//! function call into the [`CallLog`] the host trait exposes, with a summary
+//! of its arguments, and its result once it returns. The log only keeps the
+//! last calls, so it can stay enabled in production, and after a trap shows
+//! what the instance did leading up to it: a call which never returned is
+//! the one which trapped.
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Write};
+use std::sync::Mutex;
+
+/// How a call ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The call has not returned, either because it is still running or
+    /// because it trapped.
+    Pending,
+    /// The call returned this summarized result.
+    Returned(String),
+}
+
+/// A call to the function named `interface.function`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crossing {
+    /// The number of calls logged before this one.
+    pub seq: u64,
+    pub function: &'static str,
+    /// The summarized arguments, separated by commas.
+    pub args: String,
+    pub outcome: Outcome,
+}
+
+impl fmt::Display for Crossing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{} {}({})", self.seq, self.function, self.args)?;
+        match &self.outcome {
+            Outcome::Pending => f.write_str(" did not return"),
+            Outcome::Returned(result) => write!(f, " -> {}", result),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    next: u64,
+    crossings: VecDeque<Crossing>,
+}
+
+/// Keeps the last `capacity` host function calls, summarizing each value to
+/// at most `summary_len` bytes of its `Debug` output.
+#[derive(Debug)]
+pub struct CallLog {
+    capacity: usize,
+    summary_len: usize,
+    state: Mutex<State>,
+}
+
+impl CallLog {
+    /// Creates a log keeping the last `capacity` calls.
+    pub fn new(capacity: usize) -> CallLog {
+        CallLog {
+            capacity,
+            summary_len: 64,
+            state: Mutex::new(State {
+                next: 0,
+                crossings: VecDeque::with_capacity(capacity),
+            }),
+        }
+    }
+
+    /// Cuts the summary of each argument and result to `len` bytes, 64 by
+    /// default.
+    pub fn summary_len(mut self, len: usize) -> CallLog {
+        self.summary_len = len;
+        self
+    }
+
+    /// Logs a call to `function` with `args`, dropping the oldest call if
+    /// the log is full, and returns its sequence number.
+    pub fn enter(&self, function: &'static str, args: &[&dyn Debug]) -> u64 {
+        let mut summary = String::new();
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                summary.push_str(", ");
+            }
+            summary.push_str(&self.summarize(*arg));
+        }
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next;
+        state.next += 1;
+        if self.capacity == 0 {
+            return seq;
+        }
+        if state.crossings.len() == self.capacity {
+            state.crossings.pop_front();
+        }
+        state.crossings.push_back(Crossing {
+            seq,
+            function,
+            args: summary,
+            outcome: Outcome::Pending,
+        });
+        seq
+    }
+
+    /// Logs that the call numbered `seq` returned `result`, if it is still
+    /// in the log.
+    pub fn exit(&self, seq: u64, result: &dyn Debug) {
+        let summary = self.summarize(result);
+        let mut state = self.state.lock().unwrap();
+        if let Some(crossing) = state.crossings.iter_mut().rev().find(|c| c.seq == seq) {
+            crossing.outcome = Outcome::Returned(summary);
+        }
+    }
+
+    /// Returns the calls in the log, oldest first.
+    pub fn crossings(&self) -> Vec<Crossing> {
+        self.state
+            .lock()
+            .unwrap()
+            .crossings
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Empties the log.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().crossings.clear();
+    }
+
+    fn summarize(&self, value: &dyn Debug) -> String {
+        let mut summary = String::new();
+        let _ = write!(summary, "{:?}", value);
+        if summary.len() > self.summary_len {
+            let mut len = self.summary_len;
+            while !summary.is_char_boundary(len) {
+                len -= 1;
+            }
+            summary.truncate(len);
+            summary.push('…');
+        }
+        summary
+    }
+}
+
+impl Default for CallLog {
+    /// Creates a log keeping the last 32 calls.
+    fn default() -> CallLog {
+        CallLog::new(32)
+    }
+}
+
+impl fmt::Display for CallLog {
+    /// Writes the calls in the log, oldest first, one per line.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for crossing in self.state.lock().unwrap().crossings.iter() {
+            writeln!(f, "{}", crossing)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_last_calls() {
+        let log = CallLog::new(2).summary_len(4);
+        let first = log.enter("x.f", &[&1u32]);
+        log.exit(first, &());
+        let second = log.enter("x.g", &[&"abcdef", &true]);
+        log.exit(second, &Some(3));
+        log.enter("x.h", &[]);
+        assert_eq!(
+            log.crossings(),
+            [
+                Crossing {
+                    seq: 1,
+                    function: "x.g",
+                    args: "\"abc…, true".to_string(),
+                    outcome: Outcome::Returned("Some…".to_string()),
+                },
+                Crossing {
+                    seq: 2,
+                    function: "x.h",
+                    args: String::new(),
+                    outcome: Outcome::Pending,
+                },
+            ]
+        );
+        assert_eq!(
+            log.to_string(),
+            "#1 x.g(\"abc…, true) -> Some…\n#2 x.h() did not return\n"
+        );
+    }
+}
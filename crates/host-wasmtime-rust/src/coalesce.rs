@@ -0,0 +1,280 @@
+//! Deduplication of identical in-flight requests made by async imports.
+//!
+//! When a wasm instance issues the same request several times concurrently,
+//! for example the same storage read from parallel futures, only the first
+//! one needs to reach the host. A [`Coalescer`] runs that first request and
+//! hands a clone of its result to every identical request which arrived while
+//! it was in flight. A [`CoalescePolicy`] decides which requests may share a
+//! result.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::mem;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// Decides which requests a [`Coalescer`] may merge.
+pub trait CoalescePolicy<K> {
+    /// Returns whether a request for `key` may share the result of an
+    /// identical request that is already in flight.
+    ///
+    /// Requests for which this returns `false` are always run on their own.
+    fn coalesce(&self, key: &K) -> bool;
+}
+
+/// A policy which merges every identical request.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct AlwaysCoalesce;
+
+impl<K> CoalescePolicy<K> for AlwaysCoalesce {
+    fn coalesce(&self, _key: &K) -> bool {
+        true
+    }
+}
+
+impl<K, F: Fn(&K) -> bool> CoalescePolicy<K> for F {
+    fn coalesce(&self, key: &K) -> bool {
+        self(key)
+    }
+}
+
+/// Merges concurrent requests which have the same key.
+pub struct Coalescer<K, V, P = AlwaysCoalesce> {
+    in_flight: Mutex<HashMap<K, Arc<Slot<V>>>>,
+    policy: P,
+}
+
+struct Slot<V> {
+    state: Mutex<State<V>>,
+}
+
+enum State<V> {
+    Pending(Vec<Waker>),
+    Done(V),
+    /// The request was cancelled before it completed, so every waiter has to
+    /// make its own.
+    Abandoned,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Coalescer<K, V> {
+    /// Creates a coalescer which merges every identical request.
+    pub fn new() -> Self {
+        Self::with_policy(AlwaysCoalesce)
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> Default for Coalescer<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash + Clone, V: Clone, P: CoalescePolicy<K>> Coalescer<K, V, P> {
+    /// Creates a coalescer which consults `policy` before merging requests.
+    pub fn with_policy(policy: P) -> Self {
+        Coalescer {
+            in_flight: Mutex::new(HashMap::new()),
+            policy,
+        }
+    }
+
+    /// Returns the number of distinct requests currently in flight.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.lock().unwrap().len()
+    }
+
+    /// Runs the request produced by `request` for `key`, unless an identical
+    /// request is already in flight in which case its result is awaited and
+    /// cloned instead.
+    ///
+    /// If the request being awaited is cancelled, `request` is run after all.
+    pub async fn run<F, Fut>(&self, key: K, request: F) -> V
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = V>,
+    {
+        if !self.policy.coalesce(&key) {
+            return request().await;
+        }
+
+        let existing = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(slot) => Some(slot.clone()),
+                None => {
+                    in_flight.insert(
+                        key.clone(),
+                        Arc::new(Slot {
+                            state: Mutex::new(State::Pending(Vec::new())),
+                        }),
+                    );
+                    None
+                }
+            }
+        };
+
+        match existing {
+            Some(slot) => match (Wait { slot }).await {
+                Some(value) => value,
+                None => request().await,
+            },
+            None => {
+                let mut leader = Leader {
+                    coalescer: self,
+                    key: Some(key),
+                };
+                let value = request().await;
+                leader.finish(State::Done(value.clone()));
+                value
+            }
+        }
+    }
+}
+
+/// Publishes the outcome of the request made on behalf of everyone waiting
+/// for `key`, marking it abandoned if it is dropped before completing.
+struct Leader<'a, K: Eq + Hash, V, P> {
+    coalescer: &'a Coalescer<K, V, P>,
+    key: Option<K>,
+}
+
+impl<K: Eq + Hash, V, P> Leader<'_, K, V, P> {
+    fn finish(&mut self, outcome: State<V>) {
+        let key = match self.key.take() {
+            Some(key) => key,
+            None => return,
+        };
+        let slot = self.coalescer.in_flight.lock().unwrap().remove(&key);
+        if let Some(slot) = slot {
+            let prev = mem::replace(&mut *slot.state.lock().unwrap(), outcome);
+            if let State::Pending(wakers) = prev {
+                for waker in wakers {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, P> Drop for Leader<'_, K, V, P> {
+    fn drop(&mut self) {
+        self.finish(State::Abandoned);
+    }
+}
+
+/// Waits for another request to publish its outcome, resolving to `None` if
+/// that request was abandoned.
+struct Wait<V> {
+    slot: Arc<Slot<V>>,
+}
+
+impl<V: Clone> Future for Wait<V> {
+    type Output = Option<V>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<V>> {
+        let mut state = self.slot.state.lock().unwrap();
+        match &mut *state {
+            State::Pending(wakers) => {
+                if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+            State::Done(value) => Poll::Ready(Some(value.clone())),
+            State::Abandoned => Poll::Ready(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::task::Wake;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// A future which stays pending until its gate is opened.
+    struct Gate(Rc<Cell<bool>>, u32);
+
+    impl Future for Gate {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<u32> {
+            if self.0.get() {
+                Poll::Ready(self.1)
+            } else {
+                Poll::Pending
+            }
+        }
+    }
+
+    fn poll<F: Future>(f: Pin<&mut F>) -> Poll<F::Output> {
+        let waker = Waker::from(Arc::new(Noop));
+        f.poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn merges_identical_requests() {
+        let coalescer = Coalescer::new();
+        let open = Rc::new(Cell::new(false));
+        let calls = Cell::new(0);
+        let request = |value| {
+            calls.set(calls.get() + 1);
+            Gate(open.clone(), value)
+        };
+
+        let mut first = Box::pin(coalescer.run("a", || request(1)));
+        let mut second = Box::pin(coalescer.run("a", || request(2)));
+        let mut other = Box::pin(coalescer.run("b", || request(3)));
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_pending());
+        assert!(poll(other.as_mut()).is_pending());
+        assert_eq!(coalescer.in_flight(), 2);
+
+        open.set(true);
+        assert_eq!(poll(first.as_mut()), Poll::Ready(1));
+        assert_eq!(poll(second.as_mut()), Poll::Ready(1));
+        assert_eq!(poll(other.as_mut()), Poll::Ready(3));
+        assert_eq!(calls.get(), 2);
+        assert_eq!(coalescer.in_flight(), 0);
+    }
+
+    #[test]
+    fn abandoned_requests_are_retried() {
+        let coalescer = Coalescer::new();
+        let open = Rc::new(Cell::new(false));
+
+        let mut first = Box::pin(coalescer.run(1, || Gate(open.clone(), 1)));
+        let mut second = Box::pin(coalescer.run(1, || Gate(open.clone(), 2)));
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_pending());
+        drop(first);
+
+        open.set(true);
+        assert_eq!(poll(second.as_mut()), Poll::Ready(2));
+    }
+
+    #[test]
+    fn policy_can_refuse() {
+        let coalescer = Coalescer::with_policy(|key: &u32| *key != 0);
+        let open = Rc::new(Cell::new(false));
+
+        let mut first = Box::pin(coalescer.run(0, || Gate(open.clone(), 1)));
+        let mut second = Box::pin(coalescer.run(0, || Gate(open.clone(), 2)));
+        assert!(poll(first.as_mut()).is_pending());
+        assert!(poll(second.as_mut()).is_pending());
+        assert_eq!(coalescer.in_flight(), 0);
+
+        open.set(true);
+        assert_eq!(poll(first.as_mut()), Poll::Ready(1));
+        assert_eq!(poll(second.as_mut()), Poll::Ready(2));
+    }
+}
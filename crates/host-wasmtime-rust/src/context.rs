@@ -0,0 +1,88 @@
+//! Errors chained across the boundary with wasm.
+//!
+//! Bindings generated with the `error_context` option pass errors as a
+//! record holding a message and the stack of contexts added to it while it
+//! propagated, innermost first. The functions here turn such records into
+//! chained [`anyhow::Error`]s and back, so that the context gathered on
+//! either side of the boundary survives the crossing.
+
+use anyhow::Error;
+use wasmtime::Trap;
+
+/// Rebuilds the error whose root cause is `message` and which was given
+/// each of `context` in turn.
+pub fn chain(message: String, context: Vec<String>) -> Error {
+    context
+        .into_iter()
+        .fold(Error::msg(message), |err, context| err.context(context))
+}
+
+/// Splits `err` into the message of its root cause and its contexts,
+/// innermost first, the inverse of [`chain`].
+pub fn unchain(err: &Error) -> (String, Vec<String>) {
+    let mut chain = err.chain().map(|e| e.to_string()).collect::<Vec<_>>();
+    let message = chain.pop().unwrap_or_default();
+    chain.reverse();
+    (message, chain)
+}
+
+/// Merges the outcome of calling the wasm export `export` into a single
+/// error: either the error it returned, or the trap it ended in along with
+/// the backtrace the engine recorded.
+pub fn flatten<T, E: Into<Error>>(
+    export: &str,
+    result: Result<Result<T, E>, Trap>,
+) -> anyhow::Result<T> {
+    match result {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(err)) => Err(err.into().context(format!("export `{}` failed", export))),
+        Err(trap) => Err(Error::new(trap).context(format!("export `{}` trapped", export))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_chains() {
+        let err = chain(
+            "account not found".to_string(),
+            vec![
+                "loading balance".to_string(),
+                "handling transfer".to_string(),
+            ],
+        );
+        assert_eq!(
+            format!("{:#}", err),
+            "handling transfer: loading balance: account not found"
+        );
+        assert_eq!(
+            unchain(&err),
+            (
+                "account not found".to_string(),
+                vec![
+                    "loading balance".to_string(),
+                    "handling transfer".to_string()
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn flattens_export_errors() {
+        let ok: Result<Result<u32, Error>, Trap> = Ok(Ok(1));
+        assert_eq!(flatten("f", ok).unwrap(), 1);
+
+        let failed: Result<Result<u32, Error>, Trap> = Ok(Err(Error::msg("empty")));
+        assert_eq!(
+            format!("{:#}", flatten("f", failed).unwrap_err()),
+            "export `f` failed: empty"
+        );
+
+        let trapped: Result<Result<u32, Error>, Trap> = Err(Trap::new("unreachable"));
+        let err = flatten("f", trapped).unwrap_err();
+        assert_eq!(err.to_string(), "export `f` trapped");
+        assert!(err.root_cause().is::<Trap>());
+    }
+}
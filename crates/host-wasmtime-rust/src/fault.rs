@@ -0,0 +1,165 @@
+//! Fault injection into the calls wasm makes to the host.
+//!
+//! Host bindings generated with the `faults` option consult the
+//! [`FaultInjector`] the host trait exposes around each host function call,
+//! so that applications can be tested against flaky hosts. Before a call it
+//! may stall it, or fail it with a trap instead; after the call it may
+//! truncate the lists and strings of its result, which stays valid for its
+//! type. Which faults happen is drawn from a seeded generator, so that a
+//! failing run can be reproduced.
+
+use crate::mutate::{Mutate, Mutation, Mutator, Rng};
+use std::sync::Mutex;
+use std::time::Duration;
+use wasmtime::Trap;
+
+/// What happened to a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// The call was delayed by this long.
+    Latency(Duration),
+    /// The call trapped without reaching the host.
+    Error,
+    /// Lists or strings in the result were truncated.
+    Truncation,
+}
+
+/// A fault injected into a call to the function named
+/// `interface.function`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub function: &'static str,
+    pub kind: FaultKind,
+}
+
+/// Injects faults into host function calls with the configured
+/// probabilities, none by default, and keeps a log of them.
+#[derive(Debug)]
+pub struct FaultInjector {
+    error_rate: f64,
+    latency: Option<(f64, Duration)>,
+    truncation_rate: f64,
+    rng: Mutex<Rng>,
+    log: Mutex<Vec<Fault>>,
+}
+
+impl FaultInjector {
+    /// Creates an injector drawing faults from a generator seeded with
+    /// `seed`.
+    pub fn new(seed: u64) -> FaultInjector {
+        FaultInjector {
+            error_rate: 0.0,
+            latency: None,
+            truncation_rate: 0.0,
+            rng: Mutex::new(Rng::new(seed)),
+            log: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Fails calls with the given probability, with a trap.
+    pub fn errors(mut self, probability: f64) -> FaultInjector {
+        self.error_rate = probability;
+        self
+    }
+
+    /// Delays calls by `delay` with the given probability.
+    ///
+    /// The delay blocks the thread running wasm, which hosts calling it
+    /// through a `spawn_blocking` wrapper see as latency of their `async`
+    /// calls.
+    pub fn latency(mut self, probability: f64, delay: Duration) -> FaultInjector {
+        self.latency = Some((probability, delay));
+        self
+    }
+
+    /// Truncates each list and string of results to half its length with
+    /// the given probability.
+    pub fn truncation(mut self, probability: f64) -> FaultInjector {
+        self.truncation_rate = probability;
+        self
+    }
+
+    /// Returns the faults injected so far, in order.
+    pub fn faults(&self) -> Vec<Fault> {
+        self.log.lock().unwrap().clone()
+    }
+
+    fn inject(&self, function: &'static str, kind: FaultKind) {
+        self.log.lock().unwrap().push(Fault { function, kind });
+    }
+
+    /// Called by generated code before `function` is called, possibly
+    /// delaying the call or failing it.
+    pub fn before_call(&self, function: &'static str) -> Result<(), Trap> {
+        let (delay, error) = {
+            let mut rng = self.rng.lock().unwrap();
+            let delay = match self.latency {
+                Some((probability, delay)) if rng.chance(probability) => Some(delay),
+                _ => None,
+            };
+            (delay, rng.chance(self.error_rate))
+        };
+        if let Some(delay) = delay {
+            self.inject(function, FaultKind::Latency(delay));
+            std::thread::sleep(delay);
+        }
+        if error {
+            self.inject(function, FaultKind::Error);
+            return Err(Trap::new(format!("injected fault in `{}`", function)));
+        }
+        Ok(())
+    }
+
+    /// Called by generated code with the result of `function`, possibly
+    /// truncating its lists and strings.
+    pub fn after_call<T: Mutate + ?Sized>(&self, function: &'static str, result: &mut T) {
+        if self.truncation_rate <= 0.0 {
+            return;
+        }
+        let seed = self.rng.lock().unwrap().next();
+        let mutator = Mutator::random(Mutation::TruncateList, self.truncation_rate, seed);
+        mutator.mutate_result(function, result);
+        if mutator.applied().is_some() {
+            self.inject(function, FaultKind::Truncation);
+        }
+    }
+}
+
+impl Default for FaultInjector {
+    fn default() -> FaultInjector {
+        FaultInjector::new(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn injects_configured_faults() {
+        let quiet = FaultInjector::default();
+        let mut result = vec![1u32, 2, 3, 4];
+        assert!(quiet.before_call("x.f").is_ok());
+        quiet.after_call("x.f", &mut result);
+        assert_eq!(result, [1, 2, 3, 4]);
+        assert!(quiet.faults().is_empty());
+
+        let flaky = FaultInjector::new(7).errors(1.0).truncation(1.0);
+        assert!(flaky.before_call("x.f").is_err());
+        flaky.after_call("x.f", &mut result);
+        assert_eq!(result, [1, 2]);
+        assert_eq!(
+            flaky.faults(),
+            [
+                Fault {
+                    function: "x.f",
+                    kind: FaultKind::Error,
+                },
+                Fault {
+                    function: "x.f",
+                    kind: FaultKind::Truncation,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,156 @@
+//! Deduplication of retried calls to idempotent imports.
+//!
+//! Functions annotated `@idempotent` are imported taking an idempotency key
+//! along with their arguments: guest bindings draw a new key for each call,
+//! and pass the same key again when the guest retries a call, for example
+//! after a timeout. Host traits get the key as a trailing `idempotency_key`
+//! parameter, and can keep their results in a [`Deduplicator`] so that a
+//! retry gets the result of the call it repeats instead of performing it
+//! again.
+//!
+//! Keys are only unique among the calls an instance makes to the imports of
+//! one interface, so each instance needs its own deduplicator per interface.
+
+use std::collections::{HashMap, VecDeque};
+
+/// The results of completed idempotent calls, by idempotency key.
+#[derive(Debug, Clone)]
+pub struct Deduplicator<T> {
+    results: HashMap<u64, T>,
+    /// Keys in the order their results were recorded, oldest first.
+    order: VecDeque<u64>,
+    capacity: Option<usize>,
+}
+
+impl<T> Default for Deduplicator<T> {
+    fn default() -> Self {
+        Deduplicator {
+            results: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: None,
+        }
+    }
+}
+
+impl<T> Deduplicator<T> {
+    /// Creates a deduplicator keeping every result until it's forgotten.
+    pub fn new() -> Self {
+        Deduplicator::default()
+    }
+
+    /// Creates a deduplicator keeping at most `capacity` results, forgetting
+    /// the oldest ones first, for guests which only retry recent calls.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Deduplicator {
+            capacity: Some(capacity),
+            ..Deduplicator::default()
+        }
+    }
+
+    /// Returns the result of the call made with `key`, if it completed and
+    /// wasn't forgotten since.
+    pub fn get(&self, key: u64) -> Option<&T> {
+        self.results.get(&key)
+    }
+
+    /// Records `result` as the result of the call made with `key`.
+    pub fn insert(&mut self, key: u64, result: T) {
+        if self.results.insert(key, result).is_none() {
+            self.order.push_back(key);
+        }
+        if let Some(capacity) = self.capacity {
+            while self.results.len() > capacity {
+                let oldest = self.order.pop_front().unwrap();
+                self.results.remove(&oldest);
+            }
+        }
+    }
+
+    /// Forgets the result of the call made with `key`, so that it's
+    /// performed again if retried, returning the result if there was one.
+    pub fn forget(&mut self, key: u64) -> Option<T> {
+        let result = self.results.remove(&key)?;
+        self.order.retain(|k| *k != key);
+        Some(result)
+    }
+
+    /// Forgets every result, for example when the instance is reset.
+    pub fn clear(&mut self) {
+        self.results.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.results.is_empty()
+    }
+}
+
+impl<T: Clone> Deduplicator<T> {
+    /// Performs the call made with `key` through `call`, unless it already
+    /// completed, returning its result either way.
+    pub fn call(&mut self, key: u64, call: impl FnOnce() -> T) -> T {
+        if let Some(result) = self.results.get(&key) {
+            return result.clone();
+        }
+        let result = call();
+        self.insert(key, result.clone());
+        result
+    }
+
+    /// Like [`Deduplicator::call`], but only recording successful results,
+    /// so that retries of a failed call perform it again.
+    pub fn try_call<E>(&mut self, key: u64, call: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+        if let Some(result) = self.results.get(&key) {
+            return Ok(result.clone());
+        }
+        let result = call()?;
+        self.insert(key, result.clone());
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn performs_each_call_once() {
+        let mut calls = 0;
+        let mut dedup = Deduplicator::new();
+        let mut transfer = |dedup: &mut Deduplicator<u32>, key| {
+            dedup.call(key, || {
+                calls += 1;
+                calls
+            })
+        };
+        assert_eq!(transfer(&mut dedup, 0), 1);
+        assert_eq!(transfer(&mut dedup, 0), 1);
+        assert_eq!(transfer(&mut dedup, 1), 2);
+        assert_eq!(dedup.forget(0), Some(1));
+        assert_eq!(transfer(&mut dedup, 0), 3);
+
+        let failed = dedup.try_call(2, || Err::<u32, _>("timeout"));
+        assert_eq!(failed, Err("timeout"));
+        assert_eq!(dedup.try_call(2, || Ok::<_, &str>(4)), Ok(4));
+        assert_eq!(dedup.try_call(2, || Ok::<_, &str>(5)), Ok(4));
+    }
+
+    #[test]
+    fn forgets_oldest_results_beyond_capacity() {
+        let mut dedup = Deduplicator::with_capacity(2);
+        dedup.insert(0, "a");
+        dedup.insert(1, "b");
+        dedup.insert(0, "c");
+        dedup.insert(2, "d");
+        assert_eq!(dedup.len(), 2);
+        assert_eq!(dedup.get(0), None);
+        assert_eq!(dedup.get(1), Some(&"b"));
+        assert_eq!(dedup.get(2), Some(&"d"));
+        dedup.clear();
+        assert!(dedup.is_empty());
+    }
+}
@@ -2,19 +2,38 @@ pub use wit_bindgen_host_wasmtime_rust_macro::{export, import};
 
 #[cfg(feature = "async")]
 pub use async_trait::async_trait;
+#[cfg(feature = "tokio-lib")]
+pub use tokio_lib as tokio;
 #[cfg(feature = "tracing-lib")]
 pub use tracing_lib as tracing;
+pub use wit_bindgen_codec as codec;
 #[doc(hidden)]
 pub use {anyhow, bitflags, wasmtime};
 
+mod budget;
+pub mod bulk;
+pub mod call_log;
+#[cfg(feature = "async")]
+pub mod coalesce;
+pub mod context;
 mod error;
+pub mod fault;
+pub mod idempotency;
 mod le;
+pub mod mutate;
+pub mod protocol;
+mod quota;
 mod region;
 mod slab;
+pub mod symbolicate;
 mod table;
+#[cfg(feature = "async")]
+pub mod tasks;
 
+pub use budget::{BudgetExceeded, FixedBudget, LiftBudget, LiftBudgetExceeded, MemoryBudget};
 pub use error::GuestError;
 pub use le::{Endian, Le};
+pub use quota::{Quota, QuotaExceeded, Quotas, Usage};
 pub use region::{AllBytesValid, BorrowChecker, Region};
 pub use table::*;
 
@@ -64,6 +83,17 @@ pub mod rt {
         }
     }
 
+    /// The wasm stack reserved per call and level of value nesting.
+    pub const STACK_PER_LEVEL: usize = 16 * 1024;
+
+    /// Returns the wasm stack needed for `call_depth` nested calls with
+    /// values nesting `nesting` levels deep, on top of the outermost call.
+    pub fn max_wasm_stack(call_depth: usize, nesting: usize) -> usize {
+        (call_depth + 1)
+            .saturating_mul(nesting + 1)
+            .saturating_mul(STACK_PER_LEVEL)
+    }
+
     pub fn char_from_i32(val: i32) -> Result<char, Trap> {
         core::char::from_u32(val as u32).ok_or_else(|| Trap::new("char value out of valid range"))
     }
@@ -125,6 +155,112 @@ pub mod rt {
         Trap::new(msg)
     }
 
+    /// Reserves `bytes` from `budget`, if there is one, before they are
+    /// allocated in guest memory.
+    pub fn reserve_memory<B: crate::MemoryBudget + ?Sized>(
+        budget: Option<&mut B>,
+        bytes: u64,
+    ) -> Result<(), Trap> {
+        match budget {
+            Some(budget) => budget
+                .reserve(bytes)
+                .map_err(|e| Trap::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>)),
+            None => Ok(()),
+        }
+    }
+
+    /// Charges a call to `function` to `quotas`.
+    pub fn charge_call(quotas: &mut crate::Quotas, function: &str) -> Result<(), Trap> {
+        quotas
+            .charge_call(function)
+            .map_err(|e| Trap::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>))
+    }
+
+    /// Takes the transition of `function` between states of `protocol`.
+    pub fn transition(
+        protocol: &mut crate::protocol::Protocol,
+        function: &'static str,
+        initial: &'static str,
+        from: &'static str,
+        to: &'static str,
+    ) -> Result<(), Trap> {
+        protocol
+            .transition(function, initial, from, to)
+            .map_err(|e| Trap::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>))
+    }
+
+    /// Charges `bytes` transferred by a call to `function` to `quotas`.
+    pub fn charge_bytes(
+        quotas: &mut crate::Quotas,
+        function: &str,
+        bytes: u64,
+    ) -> Result<(), Trap> {
+        quotas
+            .charge_bytes(function, bytes)
+            .map_err(|e| Trap::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>))
+    }
+
+    /// Traps on a list of `len` elements, or string of `len` bytes, lifted
+    /// from wasm when at most `max` are allowed.
+    pub fn check_len(len: i32, max: u32) -> Result<(), Trap> {
+        if len as u32 > max {
+            return Err(Trap::new(format!(
+                "list of length {} is over the limit of {}",
+                len as u32, max
+            )));
+        }
+        Ok(())
+    }
+
+    /// Charges `len` elements of `size` bytes, lifted by a call, to its
+    /// `budget`.
+    pub fn charge_lift(budget: &mut crate::LiftBudget, len: i32, size: u64) -> Result<(), Trap> {
+        budget
+            .charge(len as u32 as u64, size)
+            .map_err(|e| Trap::from(Box::new(e) as Box<dyn std::error::Error + Send + Sync>))
+    }
+
+    /// Returns the size in bytes of a list of `len` elements of `size` bytes,
+    /// trapping if it doesn't fit in the wasm address space.
+    pub fn list_size(len: usize, size: u32) -> Result<i32, Trap> {
+        u32::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_mul(size))
+            .map(|bytes| bytes as i32)
+            .ok_or_else(|| Trap::new("list too large to fit in wasm memory"))
+    }
+
+    /// Returns the size in bytes of a list of `len` elements of `size` bytes
+    /// lifted from `base`, trapping if it runs past the end of the wasm
+    /// address space rather than wrapping around.
+    pub fn list_bounds(base: i32, len: i32, size: u32) -> Result<i32, Trap> {
+        let bytes = list_size(len as u32 as usize, size)?;
+        (base as u32)
+            .checked_add(bytes as u32)
+            .ok_or_else(|| Trap::new("list out of bounds"))?;
+        Ok(bytes)
+    }
+
+    /// Returns the address `offset` bytes past `base`, trapping if it runs
+    /// past the end of the wasm address space rather than wrapping around.
+    pub fn address(base: i32, offset: u32) -> Result<i32, Trap> {
+        (base as u32)
+            .checked_add(offset)
+            .map(|address| address as i32)
+            .ok_or_else(|| Trap::new("address out of bounds"))
+    }
+
+    /// Returns the address of the element at `index` of a list of elements
+    /// of `size` bytes starting at `base`, trapping if it runs past the end
+    /// of the wasm address space.
+    pub fn element(base: i32, index: usize, size: u32) -> Result<i32, Trap> {
+        u32::try_from(index)
+            .ok()
+            .and_then(|index| index.checked_mul(size))
+            .ok_or_else(|| Trap::new("address out of bounds"))
+            .and_then(|offset| address(base, offset))
+    }
+
     pub fn copy_slice<T: Endian>(
         store: impl AsContextMut,
         memory: &Memory,
@@ -143,6 +279,11 @@ pub mod rt {
         Ok(Le::from_slice(slice).iter().map(|s| s.get()).collect())
     }
 
+    /// Copies the values of a slice borrowed from wasm memory.
+    pub fn copy_le<T: Endian>(slice: &[Le<T>]) -> Vec<T> {
+        slice.iter().map(|s| s.get()).collect()
+    }
+
     macro_rules! as_traits {
         ($(($name:ident $tr:ident $ty:ident ($($tys:ident)*)))*) => ($(
             pub fn $name<T: $tr>(t: T) -> $ty {
@@ -202,6 +343,99 @@ pub mod rt {
         }
     }
 
+    /// Encoded results of chunked calls which wasm hasn't finished reading.
+    #[derive(Default, Debug)]
+    pub struct ChunkedResults {
+        slab: Slab<ChunkedResult>,
+    }
+
+    #[derive(Debug)]
+    struct ChunkedResult {
+        bytes: Vec<u8>,
+        read: usize,
+    }
+
+    impl ChunkedResults {
+        pub fn insert(&mut self, bytes: Vec<u8>) -> u32 {
+            self.slab.insert(ChunkedResult { bytes, read: 0 })
+        }
+
+        /// Returns the next `len` bytes of the result behind `handle`, or
+        /// fewer if that's all that is left. Once nothing is left the result
+        /// is released and an empty chunk returned.
+        pub fn read(&mut self, handle: u32, len: usize) -> Result<Vec<u8>, Trap> {
+            let result = self
+                .slab
+                .get_mut(handle)
+                .ok_or_else(|| Trap::new("invalid handle for chunked result"))?;
+            let end = result.bytes.len().min(result.read.saturating_add(len));
+            let chunk = result.bytes[result.read..end].to_vec();
+            result.read = end;
+            if chunk.is_empty() {
+                self.slab.remove(handle);
+            }
+            Ok(chunk)
+        }
+
+        pub fn release(&mut self, handle: u32) -> Result<(), Trap> {
+            match self.slab.remove(handle) {
+                Some(_) => Ok(()),
+                None => Err(Trap::new("invalid handle for chunked result")),
+            }
+        }
+    }
+
+    /// Strings which wasm has registered to pass by id.
+    #[derive(Default, Debug)]
+    pub struct InternedStrings {
+        strings: Vec<std::sync::Arc<str>>,
+        ids: std::collections::HashMap<std::sync::Arc<str>, u32>,
+    }
+
+    impl InternedStrings {
+        /// Registers `string`, returning the id it was already registered
+        /// under if there is one.
+        pub fn insert(&mut self, string: String) -> Result<u32, Trap> {
+            if let Some(id) = self.ids.get(string.as_str()) {
+                return Ok(*id);
+            }
+            let id = u32::try_from(self.strings.len())
+                .map_err(|_| Trap::new("too many interned strings"))?;
+            let string = std::sync::Arc::<str>::from(string);
+            self.strings.push(string.clone());
+            self.ids.insert(string, id);
+            Ok(id)
+        }
+
+        pub fn get(&self, id: u32) -> Result<std::sync::Arc<str>, Trap> {
+            self.strings
+                .get(id as usize)
+                .cloned()
+                .ok_or_else(|| Trap::new("invalid interned string id"))
+        }
+    }
+
+    /// Byte lists returned by spliced functions, kept on the host until wasm
+    /// passes their token to another spliced function.
+    #[derive(Default, Debug)]
+    pub struct DataTokens {
+        data: Slab<Vec<u8>>,
+    }
+
+    impl DataTokens {
+        /// Keeps `data`, returning the token wasm refers to it by.
+        pub fn insert(&mut self, data: Vec<u8>) -> u32 {
+            self.data.insert(data)
+        }
+
+        /// Takes back the data behind `token`, which can't be used again.
+        pub fn take(&mut self, token: u32) -> Result<Vec<u8>, Trap> {
+            self.data
+                .remove(token)
+                .ok_or_else(|| Trap::new("invalid data token"))
+        }
+    }
+
     #[derive(Default, Debug)]
     pub struct ResourceSlab {
         slab: Slab<Resource>,
@@ -0,0 +1,325 @@
+//! Mutation testing of guests against misbehaving hosts.
+//!
+//! Host bindings generated with the `mutants` option pass the result of each
+//! host function through the [`Mutator`] the host trait exposes before
+//! returning it to wasm. Each value visited along the way offers sites at
+//! which it can be corrupted without leaving the bounds of its type: booleans
+//! and flags are flipped, lists and strings truncated, and variant tags
+//! swapped for another case carrying the same payload. A mutator corrupts at
+//! most one site, so [`run`] runs a test suite once per site and reports which
+//! of those mutants the suite noticed.
+
+use std::sync::{Arc, Mutex};
+
+/// How a value is corrupted at a site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mutation {
+    /// A boolean or flag is flipped.
+    FlipBool,
+    /// A list or string is cut to half its length.
+    TruncateList,
+    /// An option becomes `None`, or a variant another case with the same
+    /// payload.
+    PermuteTag,
+}
+
+/// A corrupted result: the site at which it was corrupted, the function
+/// which returned it, as `interface.function`, and how.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mutant {
+    pub site: usize,
+    pub function: &'static str,
+    pub mutation: Mutation,
+}
+
+#[derive(Default)]
+struct State {
+    target: Option<usize>,
+    /// The mutation applied to each site where it applies with some
+    /// probability, instead of targeting a single site.
+    random: Option<(Mutation, f64, Rng)>,
+    sites: usize,
+    function: &'static str,
+    applied: Option<Mutant>,
+}
+
+/// Counts the sites of the results host functions return and corrupts the
+/// one it targets, if any. Clones share their state, so that one can be kept
+/// by the host while another is inspected once the suite is done.
+#[derive(Clone, Default)]
+pub struct Mutator {
+    state: Arc<Mutex<State>>,
+}
+
+impl Mutator {
+    /// Creates a mutator which only counts sites, leaving results intact.
+    pub fn none() -> Mutator {
+        Mutator::default()
+    }
+
+    /// Creates a mutator corrupting the result at the `site`th site visited.
+    pub fn at(site: usize) -> Mutator {
+        let state = State {
+            target: Some(site),
+            ..State::default()
+        };
+        Mutator {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Creates a mutator applying `mutation` to each site where it applies
+    /// with the given `probability`, drawn from a generator seeded with
+    /// `seed`.
+    pub fn random(mutation: Mutation, probability: f64, seed: u64) -> Mutator {
+        let state = State {
+            random: Some((mutation, probability, Rng::new(seed))),
+            ..State::default()
+        };
+        Mutator {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Visits the sites of `result`, returned by `function`, corrupting it if
+    /// it contains the targeted site.
+    pub fn mutate_result<T: Mutate + ?Sized>(&self, function: &'static str, result: &mut T) {
+        self.state.lock().unwrap().function = function;
+        result.mutate(self);
+    }
+
+    /// Visits a site at which a value can be corrupted by `mutation`,
+    /// returning whether it should be.
+    pub fn site(&self, mutation: Mutation) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let site = state.sites;
+        state.sites += 1;
+        let hit = match &mut state.random {
+            Some((only, probability, rng)) => *only == mutation && rng.chance(*probability),
+            None => state.target == Some(site),
+        };
+        if !hit {
+            return false;
+        }
+        state.applied = Some(Mutant {
+            site,
+            function: state.function,
+            mutation,
+        });
+        true
+    }
+
+    /// Returns the number of sites visited so far.
+    pub fn sites(&self) -> usize {
+        self.state.lock().unwrap().sites
+    }
+
+    /// Returns the mutant created, if the targeted site has been reached, or
+    /// the last one for random mutators.
+    pub fn applied(&self) -> Option<Mutant> {
+        self.state.lock().unwrap().applied
+    }
+}
+
+/// Values which can be corrupted within the bounds of their type.
+pub trait Mutate {
+    /// Visits the sites of the value through `mutator`, corrupting it at the
+    /// one the mutator targets.
+    fn mutate(&mut self, mutator: &Mutator);
+}
+
+/// The mutants of a test suite, depending on whether the suite failed with
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub killed: Vec<Mutant>,
+    pub survived: Vec<Mutant>,
+}
+
+impl Report {
+    /// Returns the share of the mutants the suite killed, or 1 if there
+    /// were none.
+    pub fn score(&self) -> f64 {
+        let total = self.killed.len() + self.survived.len();
+        if total == 0 {
+            return 1.0;
+        }
+        self.killed.len() as f64 / total as f64
+    }
+}
+
+/// Runs `suite` with a mutator targeting each site in turn, until it no
+/// longer reaches the targeted site, and reports whether it passed with each
+/// mutant.
+///
+/// The suite must give the mutator to the host of every instance it creates,
+/// pass when results are intact, and call the host the same way every time it
+/// runs.
+pub fn run(mut suite: impl FnMut(&Mutator) -> bool) -> Report {
+    let mut report = Report::default();
+    for site in 0.. {
+        let mutator = Mutator::at(site);
+        let passed = suite(&mutator);
+        let mutant = match mutator.applied() {
+            Some(mutant) => mutant,
+            None => break,
+        };
+        if passed {
+            report.survived.push(mutant);
+        } else {
+            report.killed.push(mutant);
+        }
+    }
+    report
+}
+
+/// A xorshift generator, enough to pick faults reproducibly.
+#[derive(Debug, Clone)]
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        // Xorshift gets stuck at zero.
+        Rng(seed | 1)
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns true with the given probability.
+    pub(crate) fn chance(&mut self, probability: f64) -> bool {
+        let sample = (self.next() >> 11) as f64 / (1u64 << 53) as f64;
+        sample < probability
+    }
+}
+
+macro_rules! intact {
+    ($($t:ty)*) => ($(
+        impl Mutate for $t {
+            fn mutate(&mut self, _mutator: &Mutator) {}
+        }
+    )*)
+}
+
+intact! { () u8 u16 u32 u64 i8 i16 i32 i64 f32 f64 char }
+
+impl Mutate for bool {
+    fn mutate(&mut self, mutator: &Mutator) {
+        if mutator.site(Mutation::FlipBool) {
+            *self = !*self;
+        }
+    }
+}
+
+impl Mutate for String {
+    fn mutate(&mut self, mutator: &Mutator) {
+        if !self.is_empty() && mutator.site(Mutation::TruncateList) {
+            let mut len = self.len() / 2;
+            while !self.is_char_boundary(len) {
+                len -= 1;
+            }
+            self.truncate(len);
+        }
+    }
+}
+
+impl<T: Mutate> Mutate for Vec<T> {
+    fn mutate(&mut self, mutator: &Mutator) {
+        if !self.is_empty() && mutator.site(Mutation::TruncateList) {
+            self.truncate(self.len() / 2);
+            return;
+        }
+        for item in self.iter_mut() {
+            item.mutate(mutator);
+        }
+    }
+}
+
+impl<T: Mutate> Mutate for Option<T> {
+    fn mutate(&mut self, mutator: &Mutator) {
+        if self.is_some() && mutator.site(Mutation::PermuteTag) {
+            *self = None;
+        } else if let Some(value) = self {
+            value.mutate(mutator);
+        }
+    }
+}
+
+impl<T: Mutate, E: Mutate> Mutate for Result<T, E> {
+    fn mutate(&mut self, mutator: &Mutator) {
+        match self {
+            Ok(value) => value.mutate(mutator),
+            Err(err) => err.mutate(mutator),
+        }
+    }
+}
+
+macro_rules! tuples {
+    ($(($($t:ident)*))*) => ($(
+        #[allow(non_snake_case)]
+        impl<$($t: Mutate,)*> Mutate for ($($t,)*) {
+            fn mutate(&mut self, mutator: &Mutator) {
+                let ($($t,)*) = self;
+                $($t.mutate(mutator);)*
+            }
+        }
+    )*)
+}
+
+tuples! {
+    (A)
+    (A B)
+    (A B C)
+    (A B C D)
+    (A B C D E)
+    (A B C D E F)
+    (A B C D E F G)
+    (A B C D E F G H)
+    (A B C D E F G H I)
+    (A B C D E F G H I J)
+    (A B C D E F G H I J K)
+    (A B C D E F G H I J K L)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupts_each_site_once() {
+        let mut seen = Vec::new();
+        let report = run(|mutator| {
+            let mut result = (vec![true, false], Some("héllo".to_string()));
+            mutator.mutate_result("x.f", &mut result);
+            seen.push(result.clone());
+            // Only notices changes to the list.
+            result.0 == [true, false]
+        });
+        assert_eq!(
+            seen,
+            [
+                (vec![true], Some("héllo".to_string())),
+                (vec![false, false], Some("héllo".to_string())),
+                (vec![true, true], Some("héllo".to_string())),
+                (vec![true, false], None),
+                (vec![true, false], Some("hé".to_string())),
+                (vec![true, false], Some("héllo".to_string())),
+            ]
+        );
+        assert_eq!(report.killed.len(), 3);
+        assert_eq!(report.survived.len(), 2);
+        assert_eq!(
+            report.survived[0],
+            Mutant {
+                site: 3,
+                function: "x.f",
+                mutation: Mutation::PermuteTag,
+            }
+        );
+        assert_eq!(report.score(), 0.6);
+    }
+}
@@ -0,0 +1,104 @@
+//! Runtime checks of the order in which wasm calls host functions.
+//!
+//! Interfaces declare a call-ordering protocol by annotating functions with
+//! `@transition("from", "to")`: such a function may only be called while the
+//! protocol is in state `from`, and leaves it in state `to`. Guest bindings
+//! check the order at compile time, and host bindings generated with the
+//! `protocol` option check it again against the [`Protocol`] the host trait
+//! exposes, trapping on calls out of order.
+
+use thiserror::Error;
+
+/// The error returned when wasm calls a host function in a state of the
+/// protocol other than the one it goes from.
+///
+/// Generated bindings turn this into a trap which wraps it.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+#[error("`{function}` was called in state `{state}` rather than `{expected}`")]
+pub struct ProtocolViolation {
+    pub function: &'static str,
+    pub state: &'static str,
+    pub expected: &'static str,
+}
+
+/// The state of the call-ordering protocol of an instance.
+#[derive(Debug, Default, Clone)]
+pub struct Protocol {
+    /// The current state, or `None` before any transition.
+    state: Option<&'static str>,
+}
+
+impl Protocol {
+    pub fn new() -> Protocol {
+        Protocol::default()
+    }
+
+    /// Returns the current state, or `initial` if no transition was taken.
+    pub fn state(&self, initial: &'static str) -> &'static str {
+        self.state.unwrap_or(initial)
+    }
+
+    /// Takes the transition of `function` from state `from` to state `to`,
+    /// where the protocol starts in state `initial`.
+    pub fn transition(
+        &mut self,
+        function: &'static str,
+        initial: &'static str,
+        from: &'static str,
+        to: &'static str,
+    ) -> Result<(), ProtocolViolation> {
+        let state = self.state(initial);
+        if state != from {
+            return Err(ProtocolViolation {
+                function,
+                state,
+                expected: from,
+            });
+        }
+        self.state = Some(to);
+        Ok(())
+    }
+
+    /// Goes back to the initial state, for a new execution to start afresh.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_transitions() {
+        let mut protocol = Protocol::new();
+        assert_eq!(protocol.state("idle"), "idle");
+        assert_eq!(
+            protocol.transition("kv.write", "idle", "batch", "batch"),
+            Err(ProtocolViolation {
+                function: "kv.write",
+                state: "idle",
+                expected: "batch",
+            })
+        );
+        assert_eq!(
+            protocol.transition("kv.begin-batch", "idle", "idle", "batch"),
+            Ok(())
+        );
+        assert_eq!(
+            protocol.transition("kv.write", "idle", "batch", "batch"),
+            Ok(())
+        );
+        assert_eq!(
+            protocol.transition("kv.commit", "idle", "batch", "idle"),
+            Ok(())
+        );
+        assert_eq!(protocol.state("idle"), "idle");
+
+        protocol
+            .transition("kv.begin-batch", "idle", "idle", "batch")
+            .unwrap();
+        protocol.reset();
+        assert_eq!(protocol.state("idle"), "idle");
+    }
+}
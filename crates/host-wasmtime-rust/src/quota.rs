@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Limits on the calls wasm makes to a host function during an execution.
+///
+/// Bytes count the lists and strings passed to the function and returned by
+/// it, at their size in guest memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Quota {
+    pub calls: Option<u64>,
+    pub bytes: Option<u64>,
+}
+
+/// What a host function has used of its quota.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Usage {
+    pub calls: u64,
+    pub bytes: u64,
+}
+
+/// The error returned when a host function goes over its quota.
+///
+/// Generated bindings turn this into a trap which wraps it.
+#[derive(Debug, Error, PartialEq, Eq, Clone)]
+pub enum QuotaExceeded {
+    #[error("`{function}` was called more than {limit} times")]
+    Calls { function: String, limit: u64 },
+    #[error("`{function}` transferred more than {limit} bytes")]
+    Bytes { function: String, limit: u64 },
+}
+
+/// The quotas of host functions, named `interface.function`, and what they
+/// have used of them.
+///
+/// This is only consulted by bindings generated with the `quotas` option,
+/// which charge every call and every list or string it transfers before the
+/// host function sees them or wasm gets them back. Functions without a quota
+/// of their own fall back to the default one, which is unlimited unless set.
+#[derive(Debug, Default, Clone)]
+pub struct Quotas {
+    default: Quota,
+    limits: HashMap<String, Quota>,
+    usage: HashMap<String, Usage>,
+}
+
+impl Quotas {
+    pub fn new() -> Quotas {
+        Quotas::default()
+    }
+
+    /// Sets the quota of functions which have none of their own.
+    pub fn with_default(mut self, quota: Quota) -> Quotas {
+        self.default = quota;
+        self
+    }
+
+    /// Sets the quota of `function`, named `interface.function`.
+    pub fn with_limit(mut self, function: &str, quota: Quota) -> Quotas {
+        self.limits.insert(function.to_string(), quota);
+        self
+    }
+
+    /// Returns what `function` has used of its quota.
+    pub fn usage(&self, function: &str) -> Usage {
+        self.usage.get(function).copied().unwrap_or_default()
+    }
+
+    /// Forgets about past usage, for a new execution to start afresh.
+    pub fn reset(&mut self) {
+        self.usage.clear();
+    }
+
+    fn quota(&self, function: &str) -> Quota {
+        self.limits.get(function).copied().unwrap_or(self.default)
+    }
+
+    fn usage_mut(&mut self, function: &str) -> &mut Usage {
+        self.usage.entry(function.to_string()).or_default()
+    }
+
+    /// Charges a call to `function`.
+    pub fn charge_call(&mut self, function: &str) -> Result<(), QuotaExceeded> {
+        let quota = self.quota(function);
+        let usage = self.usage_mut(function);
+        usage.calls += 1;
+        match quota.calls {
+            Some(limit) if usage.calls > limit => Err(QuotaExceeded::Calls {
+                function: function.to_string(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Charges `bytes` transferred by a call to `function`.
+    pub fn charge_bytes(&mut self, function: &str, bytes: u64) -> Result<(), QuotaExceeded> {
+        let quota = self.quota(function);
+        let usage = self.usage_mut(function);
+        usage.bytes = usage.bytes.saturating_add(bytes);
+        match quota.bytes {
+            Some(limit) if usage.bytes > limit => Err(QuotaExceeded::Bytes {
+                function: function.to_string(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn charges_against_quotas() {
+        let mut quotas = Quotas::new()
+            .with_default(Quota {
+                calls: Some(1),
+                bytes: None,
+            })
+            .with_limit(
+                "kv.get",
+                Quota {
+                    calls: None,
+                    bytes: Some(8),
+                },
+            );
+        assert_eq!(quotas.charge_call("kv.set"), Ok(()));
+        assert_eq!(
+            quotas.charge_call("kv.set"),
+            Err(QuotaExceeded::Calls {
+                function: "kv.set".to_string(),
+                limit: 1,
+            })
+        );
+        for _ in 0..3 {
+            assert_eq!(quotas.charge_call("kv.get"), Ok(()));
+        }
+        assert_eq!(quotas.charge_bytes("kv.get", 8), Ok(()));
+        assert!(quotas.charge_bytes("kv.get", 1).is_err());
+        assert_eq!(quotas.usage("kv.get"), Usage { calls: 3, bytes: 9 });
+
+        quotas.reset();
+        assert_eq!(quotas.usage("kv.get"), Usage::default());
+        assert_eq!(quotas.charge_call("kv.set"), Ok(()));
+    }
+}
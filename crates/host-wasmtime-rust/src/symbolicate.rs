@@ -0,0 +1,112 @@
+//! Readable backtraces of traps going through generated bindings.
+//!
+//! In the backtrace of a trap, the code generated for a guest shows up as
+//! frames of the glue of each function: the wrapper through which the guest
+//! calls an import, or the shim through which an export is entered. Host
+//! bindings generated with the `symbols` option describe that glue in a
+//! `SYMBOLS` constant per interface, which [`symbolicate`] uses to rewrite
+//! those frames into descriptions meant for application developers.
+
+use wasmtime::Trap;
+
+/// Which side of the boundary implements the functions of an interface, as
+/// seen from the guest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Import,
+    Export,
+}
+
+/// The glue which guest bindings generate for the functions of an interface.
+#[derive(Debug, Clone, Copy)]
+pub struct Symbols {
+    pub interface: &'static str,
+    pub direction: Direction,
+    /// Each function by its name in the interface, along with the path of
+    /// its import wrapper or the name of its export shim.
+    pub functions: &'static [(&'static str, &'static str)],
+}
+
+impl Symbols {
+    /// Describes the frame of the function named `name` in the name section
+    /// of the guest, if it's part of the glue of this interface.
+    pub fn describe(&self, name: &str) -> Option<String> {
+        let name = demangle(name);
+        let (function, _) = self.functions.iter().find(|(_, symbol)| {
+            match self.direction {
+                // Import wrappers are reached through the module of the
+                // interface, in whichever module the bindings were put.
+                Direction::Import => name == *symbol || name.ends_with(&format!("::{}", symbol)),
+                Direction::Export => name.rsplit("::").next() == Some(*symbol),
+            }
+        })?;
+        Some(match self.direction {
+            Direction::Import => format!("calling import {}.{}", self.interface, function),
+            Direction::Export => format!("in export {}.{}", self.interface, function),
+        })
+    }
+}
+
+/// Describes each frame of the backtrace of `trap`, innermost first, with
+/// the frames of the glue of `interfaces` rewritten.
+pub fn symbolicate(interfaces: &[Symbols], trap: &Trap) -> Vec<String> {
+    let frames = trap.trace().unwrap_or(&[]);
+    frames
+        .iter()
+        .map(|frame| describe_frame(interfaces, frame.func_name(), frame.func_index()))
+        .collect()
+}
+
+/// Describes the frame of the function at `index` named `name`, if it has a
+/// name.
+pub fn describe_frame(interfaces: &[Symbols], name: Option<&str>, index: u32) -> String {
+    match name {
+        Some(name) => interfaces
+            .iter()
+            .find_map(|symbols| symbols.describe(name))
+            .unwrap_or_else(|| demangle(name)),
+        None => format!("<wasm function {}>", index),
+    }
+}
+
+/// Demangles a Rust symbol, without its hash.
+fn demangle(name: &str) -> String {
+    format!("{:#}", rustc_demangle::demangle(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KV: Symbols = Symbols {
+        interface: "kv",
+        direction: Direction::Import,
+        functions: &[("get", "kv::get"), ("store::open", "kv::Store::open")],
+    };
+
+    const CONTRACT: Symbols = Symbols {
+        interface: "contract",
+        direction: Direction::Export,
+        functions: &[("execute", "__wit_bindgen_contract_execute")],
+    };
+
+    #[test]
+    fn describes_glue_frames() {
+        let interfaces = [KV, CONTRACT];
+        let describe = |name| describe_frame(&interfaces, Some(name), 0);
+        assert_eq!(
+            describe("_ZN5guest2kv3get17h0123456789abcdefE"),
+            "calling import kv.get"
+        );
+        assert_eq!(
+            describe("guest::kv::Store::open"),
+            "calling import kv.store::open"
+        );
+        assert_eq!(
+            describe("guest::contract::__wit_bindgen_contract_execute"),
+            "in export contract.execute"
+        );
+        assert_eq!(describe("guest::kv::get_all"), "guest::kv::get_all");
+        assert_eq!(describe_frame(&interfaces, None, 7), "<wasm function 7>");
+    }
+}
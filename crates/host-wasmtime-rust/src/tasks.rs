@@ -0,0 +1,257 @@
+//! Background tasks scoped to the lifetime of a wasm instance.
+//!
+//! Host functions called by an instance sometimes start work which outlives
+//! the call, such as prefetching or flushing a cache. Spawning that work
+//! directly onto the executor leaks it once the instance is gone. A
+//! [`TaskScope`] is owned by the host state of one instance and hands every
+//! task it spawns to the executor wrapped in a future which resolves as soon
+//! as the scope is dropped, dropping the task with it.
+//!
+//! Bindings generated with the `task_scope` option require each host trait
+//! implementation to expose its scope, and provide a `spawn` method on top of
+//! it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// A future handed to an executor by a [`TaskScope`].
+pub type Task = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Runs tasks on an executor, for example with `tokio::spawn`.
+pub trait Spawn: Send + Sync {
+    /// Starts running `task` in the background.
+    fn spawn(&self, task: Task);
+}
+
+impl<F: Fn(Task) + Send + Sync> Spawn for F {
+    fn spawn(&self, task: Task) {
+        self(task)
+    }
+}
+
+/// Owns the background tasks of one instance, cancelling them all when
+/// dropped.
+pub struct TaskScope {
+    spawner: Spawner,
+}
+
+/// A handle to spawn tasks into a [`TaskScope`], which can be cloned into the
+/// tasks themselves.
+#[derive(Clone)]
+pub struct Spawner {
+    executor: Arc<dyn Spawn>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    cancelled: bool,
+    next_id: u64,
+    /// The running tasks, along with the waker to cancel them with once they
+    /// have been polled.
+    tasks: HashMap<u64, Option<Waker>>,
+}
+
+impl TaskScope {
+    /// Creates a scope which runs its tasks on `executor`.
+    pub fn new(executor: impl Spawn + 'static) -> TaskScope {
+        TaskScope {
+            spawner: Spawner {
+                executor: Arc::new(executor),
+                state: Arc::default(),
+            },
+        }
+    }
+
+    /// Returns a handle to spawn tasks into this scope.
+    pub fn spawner(&self) -> Spawner {
+        self.spawner.clone()
+    }
+
+    /// Spawns `task` into this scope.
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) {
+        self.spawner.spawn(task);
+    }
+
+    /// Returns the number of tasks which have been spawned and haven't
+    /// finished yet.
+    pub fn running(&self) -> usize {
+        self.spawner.state.lock().unwrap().tasks.len()
+    }
+}
+
+impl Drop for TaskScope {
+    fn drop(&mut self) {
+        let wakers = {
+            let mut state = self.spawner.state.lock().unwrap();
+            state.cancelled = true;
+            std::mem::take(&mut state.tasks)
+        };
+        for waker in wakers.into_values().flatten() {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for TaskScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskScope")
+            .field("running", &self.running())
+            .finish()
+    }
+}
+
+impl Spawner {
+    /// Spawns `task` into the scope, returning `false` without running it if
+    /// the scope has already been dropped.
+    pub fn spawn(&self, task: impl Future<Output = ()> + Send + 'static) -> bool {
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            if state.cancelled {
+                return false;
+            }
+            let id = state.next_id;
+            state.next_id += 1;
+            state.tasks.insert(id, None);
+            id
+        };
+        self.executor.spawn(Box::pin(Scoped {
+            task: Box::pin(task),
+            id,
+            state: self.state.clone(),
+        }));
+        true
+    }
+
+    /// Returns whether the scope has been dropped.
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().cancelled
+    }
+}
+
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Spawner")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A task which finishes early once its scope is cancelled.
+struct Scoped {
+    task: Task,
+    id: u64,
+    state: Arc<Mutex<State>>,
+}
+
+impl Future for Scoped {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.cancelled {
+                return Poll::Ready(());
+            }
+            state.tasks.insert(self.id, Some(cx.waker().clone()));
+        }
+        self.task.as_mut().poll(cx)
+    }
+}
+
+impl Drop for Scoped {
+    fn drop(&mut self) {
+        self.state.lock().unwrap().tasks.remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// An executor which only queues tasks, to be polled by hand.
+    #[derive(Clone, Default)]
+    struct Queue(Arc<Mutex<Vec<Task>>>);
+
+    impl Queue {
+        /// Polls every queued task once, keeping the ones still pending.
+        fn run(&self) {
+            let waker = Waker::from(Arc::new(Noop));
+            let mut cx = Context::from_waker(&waker);
+            let tasks = std::mem::take(&mut *self.0.lock().unwrap());
+            for mut task in tasks {
+                if task.as_mut().poll(&mut cx).is_pending() {
+                    self.0.lock().unwrap().push(task);
+                }
+            }
+        }
+
+        fn len(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+    }
+
+    /// Sets its flag when dropped.
+    struct Dropped(Arc<Mutex<bool>>);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            *self.0.lock().unwrap() = true;
+        }
+    }
+
+    #[test]
+    fn tasks_finish_normally() {
+        let queue = Queue::default();
+        let executor = queue.clone();
+        let scope = TaskScope::new(move |task| executor.0.lock().unwrap().push(task));
+
+        let ran = Arc::new(Mutex::new(false));
+        let flag = ran.clone();
+        scope.spawn(async move { *flag.lock().unwrap() = true });
+        assert_eq!(scope.running(), 1);
+
+        queue.run();
+        assert!(*ran.lock().unwrap());
+        assert_eq!(scope.running(), 0);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn dropping_the_scope_cancels_tasks() {
+        let queue = Queue::default();
+        let executor = queue.clone();
+        let scope = TaskScope::new(move |task| executor.0.lock().unwrap().push(task));
+        let spawner = scope.spawner();
+
+        let dropped = Arc::new(Mutex::new(false));
+        let guard = Dropped(dropped.clone());
+        spawner.spawn(async move {
+            let _guard = guard;
+            std::future::pending::<()>().await
+        });
+        queue.run();
+        assert_eq!(queue.len(), 1);
+        assert!(!*dropped.lock().unwrap());
+
+        drop(scope);
+        assert!(spawner.is_cancelled());
+        queue.run();
+        assert_eq!(queue.len(), 0);
+        assert!(*dropped.lock().unwrap());
+
+        assert!(!spawner.spawn(async {}));
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -197,7 +197,9 @@ pub fn codegen_rust_wasm_export(input: TokenStream) -> TokenStream {
 #[proc_macro]
 #[cfg(feature = "host-wasmtime-rust")]
 pub fn codegen_wasmtime_export(input: TokenStream) -> TokenStream {
-    gen_rust(
+    use heck::*;
+
+    return gen_rust(
         input,
         Direction::Export,
         &[
@@ -216,8 +218,33 @@ pub fn codegen_wasmtime_export(input: TokenStream) -> TokenStream {
                 },
                 |_| quote::quote!(),
             ),
+            (
+                "export-object-safe",
+                || {
+                    let mut opts = wit_bindgen_gen_host_wasmtime_rust::Opts::default();
+                    opts.object_safe = true;
+                    opts.build()
+                },
+                assert_object_safe,
+            ),
         ],
-    )
+    );
+
+    // Naming `dyn Trait` only compiles if the generated trait is object safe.
+    fn assert_object_safe(iface: &wit_parser::Interface) -> proc_macro2::TokenStream {
+        if iface.functions.is_empty() {
+            return quote::quote!();
+        }
+        let snake = quote::format_ident!("{}", iface.name.to_snake_case());
+        let camel = quote::format_ident!("{}", iface.name.to_camel_case());
+        let handles = iface
+            .resources
+            .iter()
+            .map(|(_, r)| quote::format_ident!("{}", r.name.to_camel_case()));
+        quote::quote! {
+            const _: fn(&mut dyn #snake::#camel<#(#handles = ()),*>) = |_| {};
+        }
+    }
 }
 
 #[proc_macro]
@@ -0,0 +1,3 @@
+include!("../../../../tests/runtime/round_trips/wasm.rs");
+
+fn main() {}
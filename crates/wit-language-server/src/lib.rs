@@ -0,0 +1,321 @@
+//! A language server for WIT files, reporting the errors of the parser and
+//! offering go-to-definition, hover docs and renames of the types and other
+//! items of the files of a directory.
+
+use anyhow::{anyhow, bail, Result};
+use lsp_types::{
+    Diagnostic, DiagnosticSeverity, Hover, HoverContents, Location, MarkupContent, MarkupKind,
+    Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use wit_parser::{Interface, SourceError, Symbol, SymbolKind};
+
+/// The WIT files the server knows about: those open in the editor, whose
+/// contents may not be saved yet, and the others on disk.
+#[derive(Default)]
+pub struct Workspace {
+    open: HashMap<PathBuf, String>,
+}
+
+impl Workspace {
+    /// Starts tracking the contents of the document at `uri`, as edited.
+    pub fn open(&mut self, uri: &Url, text: String) {
+        if let Ok(path) = uri.to_file_path() {
+            self.open.insert(path, text);
+        }
+    }
+
+    /// Goes back to reading the document at `uri` from disk.
+    pub fn close(&mut self, uri: &Url) {
+        if let Ok(path) = uri.to_file_path() {
+            self.open.remove(&path);
+        }
+    }
+
+    /// Lists the documents open in the editor.
+    pub fn documents(&self) -> Vec<Url> {
+        let paths = self.open.keys();
+        paths.filter_map(|p| Url::from_file_path(p).ok()).collect()
+    }
+
+    /// Parses the document at `uri` along with the files it `use`s,
+    /// reporting the error of the parser, if any.
+    pub fn diagnostics(&self, uri: &Url) -> Vec<Diagnostic> {
+        let (path, text) = match self.document(uri) {
+            Some(document) => document,
+            None => return Vec::new(),
+        };
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let result = Interface::parse_with(&path, &text, |name| {
+            let file = self
+                .find_use(dir, name)
+                .ok_or_else(|| anyhow!("no `{}.wit` next to the file or in `deps`", name))?;
+            let contents = self
+                .read(&file)
+                .ok_or_else(|| anyhow!("failed to read {:?}", file))?;
+            Ok((file, contents))
+        });
+        let err = match result {
+            Ok(_) => return Vec::new(),
+            Err(err) => err,
+        };
+        // Errors in the files `use`d are reported at the start of this one.
+        let located = err.chain().find_map(|e| e.downcast_ref::<SourceError>());
+        let (range, message) = match located {
+            Some(e) if Path::new(&e.file) == path => {
+                let start = line_offset(&text, e.line - 1) + e.column - 1;
+                let end = token_end(&text, start);
+                (range(&text, start..end), e.message.clone())
+            }
+            _ => (Range::default(), format!("{:#}", err)),
+        };
+        vec![Diagnostic {
+            range,
+            severity: Some(DiagnosticSeverity::ERROR),
+            source: Some("wit".to_string()),
+            message,
+            ..Default::default()
+        }]
+    }
+
+    /// Returns where the item named at `position` is defined.
+    pub fn definition(&self, uri: &Url, position: Position) -> Option<Location> {
+        let (path, symbol) = self.definition_at(uri, position)?;
+        let text = self.read(&path)?;
+        Some(Location {
+            uri: Url::from_file_path(&path).ok()?,
+            range: range(&text, symbol.span),
+        })
+    }
+
+    /// Returns the line defining the item named at `position`, followed by
+    /// its documentation.
+    pub fn hover(&self, uri: &Url, position: Position) -> Option<Hover> {
+        let (path, symbol) = self.definition_at(uri, position)?;
+        let text = self.read(&path)?;
+        let line = text[..symbol.span.start].matches('\n').count();
+        let mut value = format!("```wit\n{}\n```", text.lines().nth(line)?.trim());
+        if let SymbolKind::Definition { docs: Some(docs) } = &symbol.kind {
+            value.push_str("\n\n");
+            value.push_str(docs.trim_end());
+        }
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        })
+    }
+
+    /// Renames the item named at `position` to `new_name`, where it is
+    /// defined and wherever the files of its directory, and of the `deps`
+    /// directory next to it, refer to it.
+    pub fn rename(
+        &self,
+        uri: &Url,
+        position: Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        wit_parser::validate_id(new_name)?;
+        let (def_path, def) = match self.definition_at(uri, position) {
+            Some(def) => def,
+            None => return Ok(None),
+        };
+        let mut changes = HashMap::new();
+        for file in self.files_near(&def_path) {
+            let text = match self.read(&file) {
+                Some(text) => text,
+                None => continue,
+            };
+            let symbols = match wit_parser::symbols(&text) {
+                Ok(symbols) => symbols,
+                Err(_) => continue,
+            };
+            let mut spans = BTreeSet::new();
+            if file == def_path {
+                let local = symbols.iter().filter(|s| s.name == def.name);
+                spans.extend(local.map(|s| (s.span.start, s.span.end)));
+            } else {
+                for symbol in symbols.iter() {
+                    let (from, original, original_span) = match &symbol.kind {
+                        SymbolKind::Use {
+                            from,
+                            original,
+                            original_span,
+                        } => (from, original, original_span),
+                        _ => continue,
+                    };
+                    let dir = file.parent().unwrap_or_else(|| Path::new("."));
+                    if *original != def.name || self.find_use(dir, from) != Some(def_path.clone()) {
+                        continue;
+                    }
+                    spans.insert((original_span.start, original_span.end));
+                    // Without `as`, the file refers to the item by its name.
+                    if symbol.span == *original_span {
+                        let references = symbols
+                            .iter()
+                            .filter(|s| s.name == symbol.name && s.kind == SymbolKind::Reference);
+                        spans.extend(references.map(|s| (s.span.start, s.span.end)));
+                    }
+                }
+            }
+            if spans.is_empty() {
+                continue;
+            }
+            let edits = spans
+                .into_iter()
+                .map(|(start, end)| TextEdit {
+                    range: range(&text, start..end),
+                    new_text: new_name.to_string(),
+                })
+                .collect();
+            let uri =
+                Url::from_file_path(&file).map_err(|()| anyhow!("invalid path {:?}", file))?;
+            changes.insert(uri, edits);
+        }
+        if changes.is_empty() {
+            bail!("`{}` isn't referred to anywhere", def.name);
+        }
+        Ok(Some(WorkspaceEdit {
+            changes: Some(changes),
+            ..Default::default()
+        }))
+    }
+
+    fn document(&self, uri: &Url) -> Option<(PathBuf, String)> {
+        let path = uri.to_file_path().ok()?;
+        let text = self.read(&path)?;
+        Some((path, text))
+    }
+
+    fn read(&self, path: &Path) -> Option<String> {
+        match self.open.get(path) {
+            Some(text) => Some(text.clone()),
+            None => std::fs::read_to_string(path).ok(),
+        }
+    }
+
+    /// Finds the file `use`d as `name` from the files of `dir`, like the
+    /// parser does: next to them, or else in `deps`.
+    fn find_use(&self, dir: &Path, name: &str) -> Option<PathBuf> {
+        let file = format!("{}.wit", name);
+        let candidates = [dir.join(&file), dir.join("deps").join(&file)];
+        candidates
+            .into_iter()
+            .find(|path| self.open.contains_key(path) || path.is_file())
+    }
+
+    /// Lists the files which may refer to items of the file at `path`: the
+    /// `.wit` files of its package and of the package's `deps` directory.
+    fn files_near(&self, path: &Path) -> BTreeSet<PathBuf> {
+        let mut dir = path.parent().unwrap_or_else(|| Path::new("."));
+        if dir.file_name() == Some("deps".as_ref()) {
+            dir = dir.parent().unwrap_or(dir);
+        }
+        let dirs = [dir.to_path_buf(), dir.join("deps")];
+        let mut files = BTreeSet::new();
+        for dir in dirs.iter() {
+            let entries = std::fs::read_dir(dir).into_iter().flatten().flatten();
+            files.extend(entries.map(|e| e.path()).filter(|p| is_wit(p)));
+            let open = self.open.keys().filter(|p| p.parent() == Some(dir));
+            files.extend(open.filter(|p| is_wit(p)).cloned());
+        }
+        files
+    }
+
+    /// Returns the file defining the item named at `position` of the
+    /// document at `uri`, along with the symbol defining it there.
+    fn definition_at(&self, uri: &Url, position: Position) -> Option<(PathBuf, Symbol)> {
+        let (path, text) = self.document(uri)?;
+        let symbols = wit_parser::symbols(&text).ok()?;
+        let offset = offset(&text, position);
+        let symbol = symbols
+            .iter()
+            .find(|s| s.span.start <= offset && offset <= s.span.end)?;
+        self.resolve(path, &symbols, symbol, 0)
+    }
+
+    /// Follows `symbol`, of the file at `path`, to its definition, through
+    /// the files it is `use`d from.
+    fn resolve(
+        &self,
+        path: PathBuf,
+        symbols: &[Symbol],
+        symbol: &Symbol,
+        depth: usize,
+    ) -> Option<(PathBuf, Symbol)> {
+        // `use`s can't be cyclic, but the files may be in the midst of edits.
+        if depth > 16 {
+            return None;
+        }
+        match &symbol.kind {
+            SymbolKind::Definition { .. } => Some((path, symbol.clone())),
+            SymbolKind::Reference => {
+                let def = symbols
+                    .iter()
+                    .find(|s| s.name == symbol.name && s.kind != SymbolKind::Reference)?;
+                self.resolve(path, symbols, def, depth + 1)
+            }
+            SymbolKind::Use { from, original, .. } => {
+                let file = self.find_use(path.parent()?, from)?;
+                let text = self.read(&file)?;
+                let symbols = wit_parser::symbols(&text).ok()?;
+                let def = symbols
+                    .iter()
+                    .find(|s| s.name == *original && s.kind != SymbolKind::Reference)?;
+                self.resolve(file, &symbols, def, depth + 1)
+            }
+        }
+    }
+}
+
+fn is_wit(path: &Path) -> bool {
+    path.extension() == Some("wit".as_ref())
+}
+
+/// Returns the byte offset of the start of the 0-based line `line`.
+fn line_offset(text: &str, line: usize) -> usize {
+    let starts = std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1));
+    starts.take(line + 1).last().unwrap_or(0)
+}
+
+/// Returns the end of the identifier or other token starting at `start`.
+fn token_end(text: &str, start: usize) -> usize {
+    let rest = &text[start.min(text.len())..];
+    let is_id = |c: char| c.is_alphanumeric() || c == '-' || c == '_' || c == '%';
+    let len = match rest.chars().next() {
+        Some(c) if is_id(c) => rest.find(|c: char| !is_id(c)).unwrap_or(rest.len()),
+        Some(c) => c.len_utf8(),
+        None => 0,
+    };
+    start + len
+}
+
+/// Returns the byte offset of `position`, whose character counts UTF-16
+/// code units as in the protocol.
+pub fn offset(text: &str, position: Position) -> usize {
+    let start = line_offset(text, position.line as usize);
+    let line = text[start..].split('\n').next().unwrap_or("");
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    start + line.len()
+}
+
+/// Returns the position of the byte offset `offset`.
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count();
+    let column = &before[before.rfind('\n').map_or(0, |i| i + 1)..];
+    Position::new(line as u32, column.encode_utf16().count() as u32)
+}
+
+fn range(text: &str, span: std::ops::Range<usize>) -> Range {
+    Range::new(position(text, span.start), position(text, span.end))
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{GotoDefinition, HoverRequest, Rename, Request as _};
+use lsp_types::{
+    GotoDefinitionResponse, HoverProviderCapability, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind,
+};
+use wit_language_server::Workspace;
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        rename_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut workspace = Workspace::default();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+                let response = handle_request(&workspace, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                handle_notification(&mut workspace, notification)?;
+                // A change to a file may fix or break the files `use`ing it.
+                for uri in workspace.documents() {
+                    let params = PublishDiagnosticsParams {
+                        diagnostics: workspace.diagnostics(&uri),
+                        uri,
+                        version: None,
+                    };
+                    let notification = Notification::new(PublishDiagnostics::METHOD.into(), params);
+                    connection
+                        .sender
+                        .send(Message::Notification(notification))?;
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+    io_threads.join()?;
+    Ok(())
+}
+
+fn handle_request(workspace: &Workspace, request: Request) -> Response {
+    let id = request.id.clone();
+    let result = match request.method.as_str() {
+        GotoDefinition::METHOD => params::<GotoDefinition>(request).and_then(|params| {
+            let position = params.text_document_position_params;
+            let location = workspace.definition(&position.text_document.uri, position.position);
+            Ok(serde_json::to_value(
+                location.map(GotoDefinitionResponse::Scalar),
+            )?)
+        }),
+        HoverRequest::METHOD => params::<HoverRequest>(request).and_then(|params| {
+            let position = params.text_document_position_params;
+            let hover = workspace.hover(&position.text_document.uri, position.position);
+            Ok(serde_json::to_value(hover)?)
+        }),
+        Rename::METHOD => params::<Rename>(request).and_then(|params| {
+            let position = params.text_document_position;
+            let edit = workspace.rename(
+                &position.text_document.uri,
+                position.position,
+                &params.new_name,
+            )?;
+            Ok(serde_json::to_value(edit)?)
+        }),
+        method => {
+            let message = format!("unsupported request `{}`", method);
+            return Response::new_err(id, ErrorCode::MethodNotFound as i32, message);
+        }
+    };
+    match result {
+        Ok(value) => Response::new_ok(id, value),
+        Err(e) => Response::new_err(id, ErrorCode::RequestFailed as i32, format!("{:#}", e)),
+    }
+}
+
+fn handle_notification(workspace: &mut Workspace, notification: Notification) -> Result<()> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params = notification_params::<DidOpenTextDocument>(notification)?;
+            workspace.open(&params.text_document.uri, params.text_document.text);
+        }
+        DidChangeTextDocument::METHOD => {
+            let params = notification_params::<DidChangeTextDocument>(notification)?;
+            // The server only asks for whole documents.
+            if let Some(change) = params.content_changes.into_iter().last() {
+                workspace.open(&params.text_document.uri, change.text);
+            }
+        }
+        DidCloseTextDocument::METHOD => {
+            let params = notification_params::<DidCloseTextDocument>(notification)?;
+            workspace.close(&params.text_document.uri);
+        }
+        // Saved documents are read from disk by the others already.
+        _ => {}
+    }
+    Ok(())
+}
+
+fn params<R: lsp_types::request::Request>(request: Request) -> Result<R::Params> {
+    Ok(serde_json::from_value(request.params)?)
+}
+
+fn notification_params<N: lsp_types::notification::Notification>(
+    notification: Notification,
+) -> Result<N::Params> {
+    Ok(serde_json::from_value(notification.params)?)
+}
@@ -0,0 +1,106 @@
+use lsp_types::{HoverContents, Position, Url};
+use std::path::PathBuf;
+use wit_language_server::Workspace;
+
+const CURRENCY: &str = "\
+/// An amount of money, in cents.
+type amount = u64
+";
+
+const PAYMENTS: &str = "\
+use { amount } from currency
+
+record payment { to: string, value: amount }
+";
+
+/// Writes the files of a package, with `currency.wit` as a dependency.
+fn package(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("wit-language-server-{}", name));
+    drop(std::fs::remove_dir_all(&dir));
+    std::fs::create_dir_all(dir.join("deps")).unwrap();
+    std::fs::write(dir.join("deps/currency.wit"), CURRENCY).unwrap();
+    std::fs::write(dir.join("payments.wit"), PAYMENTS).unwrap();
+    dir
+}
+
+fn uri(path: PathBuf) -> Url {
+    Url::from_file_path(path).unwrap()
+}
+
+#[test]
+fn reports_errors_in_open_documents() {
+    let dir = package("errors");
+    let payments = uri(dir.join("payments.wit"));
+    let mut workspace = Workspace::default();
+    assert_eq!(workspace.diagnostics(&payments), []);
+
+    workspace.open(
+        &payments,
+        PAYMENTS.replace("value: amount", "value: amount2"),
+    );
+    let diagnostics = workspace.diagnostics(&payments);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "no type named `amount2`");
+    assert_eq!(diagnostics[0].range.start, Position::new(2, 36));
+    assert_eq!(diagnostics[0].range.end, Position::new(2, 43));
+
+    workspace.close(&payments);
+    assert_eq!(workspace.diagnostics(&payments), []);
+}
+
+#[test]
+fn goes_to_definitions_through_uses() {
+    let dir = package("definition");
+    let payments = uri(dir.join("payments.wit"));
+    let workspace = Workspace::default();
+
+    let location = workspace
+        .definition(&payments, Position::new(2, 38))
+        .unwrap();
+    assert_eq!(location.uri, uri(dir.join("deps/currency.wit")));
+    assert_eq!(location.range.start, Position::new(1, 5));
+    assert_eq!(location.range.end, Position::new(1, 11));
+
+    let hover = workspace.hover(&payments, Position::new(2, 38)).unwrap();
+    let value = match hover.contents {
+        HoverContents::Markup(markup) => markup.value,
+        contents => panic!("unexpected hover {:?}", contents),
+    };
+    assert_eq!(
+        value,
+        "```wit\ntype amount = u64\n```\n\nAn amount of money, in cents."
+    );
+
+    assert!(workspace
+        .definition(&payments, Position::new(2, 22))
+        .is_none());
+}
+
+#[test]
+fn renames_across_files() {
+    let dir = package("rename");
+    let payments = uri(dir.join("payments.wit"));
+    let currency = uri(dir.join("deps/currency.wit"));
+    let workspace = Workspace::default();
+
+    let edit = workspace
+        .rename(&payments, Position::new(2, 38), "cents")
+        .unwrap()
+        .unwrap();
+    let changes = edit.changes.unwrap();
+    assert_eq!(changes.len(), 2);
+    let starts = |uri: &Url| {
+        let edits = changes[uri].iter();
+        edits.map(|e| e.range.start).collect::<Vec<_>>()
+    };
+    assert_eq!(starts(&currency), [Position::new(1, 5)]);
+    assert_eq!(
+        starts(&payments),
+        [Position::new(0, 6), Position::new(2, 36)]
+    );
+    assert!(changes[&payments].iter().all(|e| e.new_text == "cents"));
+
+    assert!(workspace
+        .rename(&payments, Position::new(2, 38), "not an id")
+        .is_err());
+}
@@ -4,6 +4,36 @@ use crate::{
     TypeDefKind, TypeId, Union, Variant,
 };
 
+/// The version of the ABI emitted by default.
+///
+/// Generators can be pinned to an older version with [`Bindgen::abi_version`]
+/// to keep interoperating with modules and hosts built against it:
+///
+/// * Version 1 is the ABI in which guests export their allocator as
+///   `canonical_abi_realloc`.
+/// * Version 2 renames the allocator to `cabi_realloc`, matching the
+///   component model.
+pub const ABI_VERSION: u32 = 2;
+
+/// Returns the name of the allocator guests export in ABI version `version`.
+pub fn realloc_name(version: u32) -> &'static str {
+    match version {
+        1 => "canonical_abi_realloc",
+        _ => "cabi_realloc",
+    }
+}
+
+/// Checks that `version` is a version of the ABI which can be emitted.
+pub fn check_abi_version(version: u32) -> Result<(), String> {
+    if version == 0 || version > ABI_VERSION {
+        return Err(format!(
+            "unknown ABI version {}, expected 1 through {}",
+            version, ABI_VERSION
+        ));
+    }
+    Ok(())
+}
+
 /// A raw WebAssembly signature with params and results.
 #[derive(Clone, Debug, Hash, Eq, PartialEq, PartialOrd, Ord)]
 pub struct WasmSignature {
@@ -800,9 +830,32 @@ pub trait Bindgen {
     /// "canonical" form for lists. This dictates whether the `ListCanonLower`
     /// and `ListCanonLift` instructions are used or not.
     fn is_list_canonical(&self, iface: &Interface, element: &Type) -> bool;
+
+    /// Returns the version of the ABI to lower and lift with.
+    fn abi_version(&self) -> u32 {
+        ABI_VERSION
+    }
 }
 
 impl Interface {
+    /// Returns the number of core wasm values the parameters of `func`
+    /// flatten to, before falling back to passing them through memory.
+    pub fn flat_param_count(&self, variant: AbiVariant, func: &Function) -> usize {
+        let mut params = Vec::new();
+        for (_, param) in func.params.iter() {
+            self.push_wasm(variant, param, &mut params);
+        }
+        params.len()
+    }
+
+    /// Returns the number of core wasm values the result of `func` flattens
+    /// to, before falling back to returning it through memory.
+    pub fn flat_result_count(&self, variant: AbiVariant, func: &Function) -> usize {
+        let mut results = Vec::new();
+        self.push_wasm(variant, &func.result, &mut results);
+        results.len()
+    }
+
     /// Get the WebAssembly type signature for this interface function
     ///
     /// The first entry returned is the list of parameters and the second entry
@@ -1048,7 +1101,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
                         // malloc needs to be called.
                         AbiVariant::GuestExport => {
                             self.emit(&Instruction::Malloc {
-                                realloc: "cabi_realloc",
+                                realloc: realloc_name(self.bindgen.abi_version()),
                                 size,
                                 align,
                             });
@@ -1471,7 +1524,7 @@ impl<'a, B: Bindgen> Generator<'a, B> {
         // ownership in all other cases.
         match (self.variant, self.lift_lower) {
             (AbiVariant::GuestImport, LiftLower::LowerArgsLiftResults) => None,
-            _ => Some("cabi_realloc"),
+            _ => Some(realloc_name(self.bindgen.abi_version())),
         }
     }
 
@@ -7,6 +7,8 @@ use std::fmt;
 
 mod lex;
 mod resolve;
+pub mod symbols;
+pub mod tokens;
 
 pub use lex::validate_id;
 
@@ -19,6 +21,7 @@ pub enum Item<'a> {
     Resource(Resource<'a>),
     TypeDef(TypeDef<'a>),
     Value(Value<'a>),
+    Event(Event<'a>),
     Interface(Interface<'a>),
 }
 
@@ -65,6 +68,13 @@ pub struct Resource<'a> {
 #[derive(Default)]
 struct Docs<'a> {
     docs: Vec<Cow<'a, str>>,
+    annotations: Vec<Annotation<'a>>,
+}
+
+/// An `@name` or `@name(arg, ...)` annotation written among an item's docs.
+struct Annotation<'a> {
+    name: Id<'a>,
+    args: Vec<Cow<'a, str>>,
 }
 
 pub struct TypeDef<'a> {
@@ -169,6 +179,12 @@ struct UnionCase<'a> {
     ty: Type<'a>,
 }
 
+pub struct Event<'a> {
+    docs: Docs<'a>,
+    name: Id<'a>,
+    ty: Type<'a>,
+}
+
 enum ValueKind<'a> {
     Function {
         params: Vec<(Id<'a>, Type<'a>)>,
@@ -206,6 +222,98 @@ impl<'a> Ast<'a> {
     }
 }
 
+/// A `world` declaration, listing the interfaces of its package which it
+/// imports and exports.
+pub struct World<'a> {
+    pub name: Id<'a>,
+    pub items: Vec<WorldItem<'a>>,
+}
+
+pub struct WorldItem<'a> {
+    pub export: bool,
+    /// The name the interface is imported or exported under.
+    pub name: Id<'a>,
+    /// The name of the interface's file in the package.
+    pub interface: Id<'a>,
+}
+
+impl<'a> World<'a> {
+    /// Parses the worlds declared in `input`, of which there are none if it
+    /// holds an interface instead.
+    pub fn parse_all(input: &'a str) -> Result<Vec<World<'a>>> {
+        let mut lexer = Tokenizer::new(input)?;
+        let mut worlds = Vec::new();
+        // `world` isn't a keyword of interfaces, which may use it as a name.
+        match lexer.clone().next()? {
+            Some((span, Token::Id)) if lexer.get_span(span) == "world" => {}
+            _ => return Ok(worlds),
+        }
+        while let Some(found) = lexer.next()? {
+            expect_keyword(&lexer, Some(found), "world", "`world`")?;
+            let name = parse_id(&mut lexer)?;
+            lexer.expect(Token::LeftBrace)?;
+            let mut items = Vec::new();
+            while !lexer.eat(Token::RightBrace)? {
+                let found = lexer.next()?;
+                let export = match found {
+                    Some((span, Token::Id)) if lexer.get_span(span) == "export" => true,
+                    _ => {
+                        expect_keyword(&lexer, found, "import", "`import` or `export`")?;
+                        false
+                    }
+                };
+                let name = parse_id(&mut lexer)?;
+                let interface = if lexer.eat(Token::Colon)? {
+                    parse_id(&mut lexer)?
+                } else {
+                    Id {
+                        name: name.name.clone(),
+                        span: name.span,
+                    }
+                };
+                if !lexer.eat(Token::Semicolon)? {
+                    lexer.eat(Token::Comma)?;
+                }
+                items.push(WorldItem {
+                    export,
+                    name,
+                    interface,
+                });
+            }
+            worlds.push(World { name, items });
+        }
+        Ok(worlds)
+    }
+}
+
+/// Checks that `found` is the contextual keyword `keyword`.
+fn expect_keyword(
+    tokens: &Tokenizer<'_>,
+    found: Option<(Span, Token)>,
+    keyword: &str,
+    expected: &'static str,
+) -> Result<()> {
+    match found {
+        Some((span, Token::Id)) if tokens.get_span(span) == keyword => Ok(()),
+        other => Err(err_expected(tokens, expected, other).into()),
+    }
+}
+
+/// Whether `tokens` start an event: `event` is only a keyword when followed
+/// by the event's name, so it remains usable as the name of anything else.
+fn starts_event(tokens: &Tokenizer<'_>) -> Result<bool> {
+    let mut tokens = tokens.clone();
+    match tokens.next()? {
+        Some((span, Token::Id)) if tokens.get_span(span) == "event" => {}
+        _ => return Ok(false),
+    }
+    let name = tokens.next()?;
+    Ok(matches!(
+        name,
+        Some((_, Token::Id)) | Some((_, Token::ExplicitId))
+    ))
+}
+
 impl<'a> Item<'a> {
     fn parse(tokens: &mut Tokenizer<'a>, docs: Docs<'a>) -> Result<Item<'a>> {
         match tokens.clone().next()? {
@@ -221,7 +329,11 @@ impl<'a> Item<'a> {
             Some((_span, Token::Resource)) => Resource::parse(tokens, docs).map(Item::Resource),
             Some((_span, Token::Interface)) => Interface::parse(tokens, docs).map(Item::Interface),
             Some((_span, Token::Id)) | Some((_span, Token::ExplicitId)) => {
-                Value::parse(tokens, docs).map(Item::Value)
+                if starts_event(tokens)? {
+                    Event::parse(tokens, docs).map(Item::Event)
+                } else {
+                    Value::parse(tokens, docs).map(Item::Value)
+                }
             }
             other => Err(err_expected(tokens, "`type`, `resource`, or `func`", other).into()),
         }
@@ -436,6 +548,17 @@ impl<'a> Value<'a> {
     }
 }
 
+impl<'a> Event<'a> {
+    fn parse(tokens: &mut Tokenizer<'a>, docs: Docs<'a>) -> Result<Self> {
+        let found = tokens.next()?;
+        expect_keyword(tokens, found, "event", "`event`")?;
+        let name = parse_id(tokens)?;
+        tokens.expect(Token::Colon)?;
+        let ty = Type::parse(tokens)?;
+        Ok(Event { docs, name, ty })
+    }
+}
+
 fn parse_id<'a>(tokens: &mut Tokenizer<'a>) -> Result<Id<'a>> {
     match tokens.next()? {
         Some((span, Token::Id)) => Ok(Id {
@@ -457,6 +580,7 @@ fn parse_docs<'a>(tokens: &mut Tokenizer<'a>) -> Result<Docs<'a>> {
         match token {
             Token::Whitespace => {}
             Token::Comment => docs.docs.push(tokens.get_span(span).into()),
+            Token::At => docs.annotations.push(Annotation::parse(&mut clone)?),
             _ => break,
         };
         *tokens = clone.clone();
@@ -464,6 +588,27 @@ fn parse_docs<'a>(tokens: &mut Tokenizer<'a>) -> Result<Docs<'a>> {
     Ok(docs)
 }
 
+impl<'a> Annotation<'a> {
+    fn parse(tokens: &mut Tokenizer<'a>) -> Result<Self> {
+        let name = parse_id(tokens)?;
+        let args = match tokens.clone().next()? {
+            Some((_span, Token::LeftParen)) => parse_list(
+                tokens,
+                Token::LeftParen,
+                Token::RightParen,
+                |_docs, tokens| match tokens.next()? {
+                    Some((span, Token::Str)) => Ok(tokens.parse_str(span).into()),
+                    Some((span, Token::Id)) => Ok(tokens.parse_id(span)?.into()),
+                    Some((span, Token::ExplicitId)) => Ok(tokens.parse_explicit_id(span)?.into()),
+                    other => Err(err_expected(tokens, "an identifier or string", other).into()),
+                },
+            )?,
+            _ => Vec::new(),
+        };
+        Ok(Annotation { name, args })
+    }
+}
+
 impl<'a> Type<'a> {
     fn parse(tokens: &mut Tokenizer<'a>) -> Result<Self> {
         match tokens.next()? {
@@ -644,48 +789,92 @@ pub fn rewrite_error(err: &mut anyhow::Error, file: &str, contents: &str) {
         Some(err) => err,
         None => return lex::rewrite_error(err, file, contents),
     };
-    let msg = highlight_err(
+    let source = highlight_err(
         parse.span.start as usize,
         Some(parse.span.end as usize),
         file,
         contents,
         &parse.msg,
     );
-    *err = anyhow::anyhow!("{}", msg);
+    *err = anyhow::Error::new(source);
 }
 
-fn highlight_err(
-    start: usize,
-    end: Option<usize>,
-    file: &str,
-    input: &str,
-    err: impl fmt::Display,
-) -> String {
-    let (line, col) = linecol_in(start, input);
-    let snippet = input.lines().nth(line).unwrap_or("");
-    let mut msg = format!(
-        "\
+/// Creates an error with `msg` at `span` of `contents`, read from `file`.
+pub fn error_at(span: Span, file: &str, contents: &str, msg: impl fmt::Display) -> anyhow::Error {
+    anyhow::Error::new(highlight_err(
+        span.start as usize,
+        Some(span.end as usize),
+        file,
+        contents,
+        msg,
+    ))
+}
+
+/// An error at a location in a WIT file.
+///
+/// Displays as the message followed by the offending line, with the
+/// location underlined.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError {
+    /// The file, as named to the parser.
+    pub file: String,
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    /// The column of the error, in bytes, starting at 1.
+    pub column: usize,
+    /// The offending line.
+    pub snippet: String,
+    pub message: String,
+    /// The number of characters after the first which the error spans.
+    extra: usize,
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "\
 {err}
      --> {file}:{line}:{col}
       |
  {line:4} | {snippet}
       | {marker:>0$}",
-        col + 1,
-        file = file,
-        line = line + 1,
-        col = col + 1,
-        err = err,
-        snippet = snippet,
-        marker = "^",
-    );
-    if let Some(end) = end {
-        if let Some(s) = input.get(start..end) {
-            for _ in s.chars().skip(1) {
-                msg.push('-');
-            }
+            self.column,
+            file = self.file,
+            line = self.line,
+            col = self.column,
+            err = self.message,
+            snippet = self.snippet,
+            marker = "^",
+        )?;
+        for _ in 0..self.extra {
+            f.write_str("-")?;
         }
+        Ok(())
     }
-    return msg;
+}
+
+impl std::error::Error for SourceError {}
+
+fn highlight_err(
+    start: usize,
+    end: Option<usize>,
+    file: &str,
+    input: &str,
+    err: impl fmt::Display,
+) -> SourceError {
+    let (line, col) = linecol_in(start, input);
+    let extra = end
+        .and_then(|end| input.get(start..end))
+        .map_or(0, |s| s.chars().skip(1).count());
+    return SourceError {
+        file: file.to_string(),
+        line: line + 1,
+        column: col + 1,
+        snippet: input.lines().nth(line).unwrap_or("").to_string(),
+        message: err.to_string(),
+        extra,
+    };
 
     fn linecol_in(pos: usize, text: &str) -> (usize, usize) {
         let mut cur = 0;
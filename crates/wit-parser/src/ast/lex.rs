@@ -45,6 +45,7 @@ pub enum Token {
     GreaterThan,
     RArrow,
     Star,
+    At,
 
     Use,
     Type,
@@ -85,6 +86,7 @@ pub enum Token {
 
     Id,
     ExplicitId,
+    Str,
 }
 
 #[derive(Eq, PartialEq, Debug)]
@@ -144,6 +146,28 @@ impl<'a> Tokenizer<'a> {
         Ok(id_part.to_owned())
     }
 
+    /// Returns the contents of the string literal at `span` with its quotes
+    /// removed and escapes resolved.
+    pub fn parse_str(&self, span: Span) -> String {
+        let token = self.get_span(span);
+        let mut ret = String::new();
+        let mut chars = token[1..token.len() - 1].chars();
+        while let Some(ch) = chars.next() {
+            if ch != '\\' {
+                ret.push(ch);
+                continue;
+            }
+            // The lexer has already validated every escape.
+            match chars.next() {
+                Some('n') => ret.push('\n'),
+                Some('t') => ret.push('\t'),
+                Some(ch) => ret.push(ch),
+                None => unreachable!(),
+            }
+        }
+        ret
+    }
+
     pub fn next(&mut self) -> Result<Option<(Span, Token)>, Error> {
         loop {
             match self.next_raw()? {
@@ -203,6 +227,20 @@ impl<'a> Tokenizer<'a> {
             '<' => LessThan,
             '>' => GreaterThan,
             '*' => Star,
+            '@' => At,
+            '"' => loop {
+                match self.chars.next() {
+                    Some((_, '"')) => break Str,
+                    Some((_, '\\')) => match self.chars.next() {
+                        Some((_, '"' | '\\' | 'n' | 't')) => {}
+                        Some((i, ch)) => return Err(Error::InvalidEscape(i, ch)),
+                        None => return Err(Error::UnterminatedString(start)),
+                    },
+                    Some((_, '\n')) => return Err(Error::NewlineInString(start)),
+                    Some(_) => {}
+                    None => return Err(Error::UnterminatedString(start)),
+                }
+            },
             '-' => {
                 if self.eatc('>') {
                     RArrow
@@ -533,6 +571,8 @@ impl Token {
             ExplicitId => "an '%' identifier",
             RArrow => "`->`",
             Star => "`*`",
+            At => "`@`",
+            Str => "a string literal",
             As => "keyword `as`",
             From_ => "keyword `from`",
             Static => "keyword `static`",
@@ -582,8 +622,8 @@ pub fn rewrite_error(err: &mut anyhow::Error, file: &str, contents: &str) {
         | Error::IdPartEmpty(at)
         | Error::InvalidEscape(at, _) => *at,
     };
-    let msg = super::highlight_err(pos, None, file, contents, lex);
-    *err = anyhow::anyhow!("{}", msg);
+    let source = super::highlight_err(pos, None, file, contents, lex);
+    *err = anyhow::Error::new(source);
 }
 
 #[test]
@@ -692,6 +732,22 @@ fn test_tokenizer() {
             Token::RightParen
         ]
     );
+    assert_eq!(
+        collect("@a(\"b\", c)").unwrap(),
+        vec![
+            Token::At,
+            Token::Id,
+            Token::LeftParen,
+            Token::Str,
+            Token::Comma,
+            Token::Id,
+            Token::RightParen
+        ]
+    );
+    assert_eq!(collect(r#""a\"\\b""#).unwrap(), vec![Token::Str]);
+    assert!(collect("\"a").is_err(), "unterminated string");
+    assert!(collect("\"a\nb\"").is_err(), "newline in string");
+    assert!(collect(r#""\q""#).is_err(), "invalid escape");
 
     assert!(collect("\u{149}").is_err(), "strongly discouraged");
     assert!(collect("\u{673}").is_err(), "strongly discouraged");
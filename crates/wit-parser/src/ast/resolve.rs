@@ -1,4 +1,4 @@
-use super::{Error, Item, Span, Value, ValueKind};
+use super::{Error, Event, Item, Span, Value, ValueKind};
 use crate::*;
 use anyhow::Result;
 use std::collections::{HashMap, HashSet};
@@ -15,6 +15,7 @@ pub struct Resolver {
     anon_types: HashMap<Key, TypeId>,
     functions: Vec<Function>,
     globals: Vec<Global>,
+    events: Vec<crate::Event>,
 }
 
 #[derive(PartialEq, Eq, Hash)]
@@ -63,6 +64,7 @@ impl Resolver {
         for field in fields {
             match field {
                 Item::Value(v) => self.resolve_value(v)?,
+                Item::Event(e) => self.resolve_event(e)?,
                 Item::Resource(r) => self.resolve_resource(r)?,
                 Item::TypeDef(t) => {
                     self.validate_type_not_recursive(
@@ -87,6 +89,7 @@ impl Resolver {
             interfaces: Default::default(),
             functions: mem::take(&mut self.functions),
             globals: mem::take(&mut self.globals),
+            events: mem::take(&mut self.events),
         })
     }
 
@@ -273,6 +276,7 @@ impl Resolver {
 
     fn register_names(&mut self, fields: &[Item<'_>]) -> Result<()> {
         let mut values = HashSet::new();
+        let mut events = HashSet::new();
         for field in fields {
             match field {
                 Item::Resource(r) => {
@@ -316,6 +320,15 @@ impl Resolver {
                         .into());
                     }
                 }
+                Item::Event(e) => {
+                    if !events.insert(&e.name.name) {
+                        return Err(Error {
+                            span: e.name.span,
+                            msg: format!("event {:?} defined twice", e.name.name),
+                        }
+                        .into());
+                    }
+                }
                 Item::Use(_) => {}
 
                 Item::Interface(_) => unimplemented!(),
@@ -551,7 +564,7 @@ impl Resolver {
         Type::Id(*id)
     }
 
-    fn docs(&mut self, doc: &super::Docs<'_>) -> Docs {
+    pub(super) fn docs(&mut self, doc: &super::Docs<'_>) -> Docs {
         let mut docs = None;
         for doc in doc.docs.iter() {
             // Comments which are not doc-comments are silently ignored
@@ -573,7 +586,18 @@ impl Resolver {
                 }
             }
         }
-        Docs { contents: docs }
+        let annotations = doc
+            .annotations
+            .iter()
+            .map(|a| Annotation {
+                name: a.name.name.to_string(),
+                args: a.args.iter().map(|a| a.to_string()).collect(),
+            })
+            .collect();
+        Docs {
+            contents: docs,
+            annotations,
+        }
     }
 
     fn resolve_value(&mut self, value: &Value<'_>) -> Result<()> {
@@ -605,6 +629,17 @@ impl Resolver {
         Ok(())
     }
 
+    fn resolve_event(&mut self, event: &Event<'_>) -> Result<()> {
+        let docs = self.docs(&event.docs);
+        let payload = self.resolve_type(&event.ty)?;
+        self.events.push(crate::Event {
+            docs,
+            name: event.name.name.to_string(),
+            payload,
+        });
+        Ok(())
+    }
+
     fn resolve_resource(&mut self, resource: &super::Resource<'_>) -> Result<()> {
         let mut names = HashSet::new();
         let id = self.resource_lookup[&*resource.name.name];
@@ -0,0 +1,170 @@
+//! The names defined and referred to in a WIT file, for editors.
+
+use super::{Ast, Docs, Id, Item, Resource, Type, Value, ValueKind};
+use anyhow::Result;
+use std::ops::Range;
+
+/// A name in a WIT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    /// The name as it is used in the file.
+    pub name: String,
+    /// The bytes of the file the name is written at.
+    pub span: Range<usize>,
+    pub kind: SymbolKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SymbolKind {
+    /// The definition of a type, resource, function, event or interface.
+    Definition {
+        /// The documentation of the item, if it has any.
+        docs: Option<String>,
+    },
+    /// A type or resource referred to by another item.
+    Reference,
+    /// A type `use`d from the file `from`, where it is called `original`,
+    /// and which this file calls by the name of the symbol.
+    Use {
+        from: String,
+        original: String,
+        /// Where `original` is written, the same as the symbol's span
+        /// unless the type is renamed with `as`.
+        original_span: Range<usize>,
+    },
+}
+
+/// Lists the names defined and referred to in the WIT file `contents`, in
+/// the order they appear in.
+pub fn symbols(contents: &str) -> Result<Vec<Symbol>> {
+    let ast = Ast::parse(contents)?;
+    let mut symbols = Vec::new();
+    items(&mut symbols, &ast.items);
+    symbols.sort_by_key(|s| s.span.start);
+    Ok(symbols)
+}
+
+fn items(symbols: &mut Vec<Symbol>, items: &[Item<'_>]) {
+    for item in items {
+        match item {
+            Item::Use(u) => {
+                for name in u.names.iter().flatten() {
+                    let local = name.as_.as_ref().unwrap_or(&name.name);
+                    let kind = SymbolKind::Use {
+                        from: u.from[0].name.to_string(),
+                        original: name.name.name.to_string(),
+                        original_span: span(&name.name),
+                    };
+                    symbols.push(symbol(local, kind));
+                }
+            }
+            Item::Resource(r) => resource(symbols, r),
+            Item::TypeDef(t) => {
+                symbols.push(definition(&t.name, &t.docs));
+                ty(symbols, &t.ty);
+            }
+            Item::Value(v) => value(symbols, v),
+            Item::Event(e) => {
+                symbols.push(definition(&e.name, &e.docs));
+                ty(symbols, &e.ty);
+            }
+            Item::Interface(i) => {
+                symbols.push(definition(&i.name, &i.docs));
+                self::items(symbols, &i.items);
+            }
+        }
+    }
+}
+
+fn resource(symbols: &mut Vec<Symbol>, r: &Resource<'_>) {
+    symbols.push(definition(&r.name, &r.docs));
+    if let Some(supertype) = &r.supertype {
+        symbols.push(symbol(supertype, SymbolKind::Reference));
+    }
+    for (_, v) in r.values.iter() {
+        value(symbols, v);
+    }
+}
+
+fn value(symbols: &mut Vec<Symbol>, v: &Value<'_>) {
+    symbols.push(definition(&v.name, &v.docs));
+    match &v.kind {
+        ValueKind::Function { params, result } => {
+            for (_, param) in params.iter() {
+                ty(symbols, param);
+            }
+            ty(symbols, result);
+        }
+        ValueKind::Global(t) => ty(symbols, t),
+    }
+}
+
+fn ty(symbols: &mut Vec<Symbol>, t: &Type<'_>) {
+    match t {
+        Type::Name(id) | Type::Handle(id) => symbols.push(symbol(id, SymbolKind::Reference)),
+        Type::List(t) | Type::Option(t) | Type::Future(t) => ty(symbols, t),
+        Type::Record(r) => {
+            for field in r.fields.iter() {
+                ty(symbols, &field.ty);
+            }
+        }
+        Type::Variant(v) => {
+            for case in v.cases.iter() {
+                if let Some(t) = &case.ty {
+                    ty(symbols, t);
+                }
+            }
+        }
+        Type::Union(u) => {
+            for case in u.cases.iter() {
+                ty(symbols, &case.ty);
+            }
+        }
+        Type::Tuple(types) => {
+            for t in types.iter() {
+                ty(symbols, t);
+            }
+        }
+        Type::Result(r) => {
+            ty(symbols, &r.ok);
+            ty(symbols, &r.err);
+        }
+        Type::Stream(s) => {
+            ty(symbols, &s.element);
+            ty(symbols, &s.end);
+        }
+        Type::Unit
+        | Type::Bool
+        | Type::U8
+        | Type::U16
+        | Type::U32
+        | Type::U64
+        | Type::S8
+        | Type::S16
+        | Type::S32
+        | Type::S64
+        | Type::Float32
+        | Type::Float64
+        | Type::Char
+        | Type::String
+        | Type::Flags(_)
+        | Type::Enum(_) => {}
+    }
+}
+
+fn definition(id: &Id<'_>, docs: &Docs<'_>) -> Symbol {
+    let docs = super::resolve::Resolver::default().docs(docs).contents;
+    symbol(id, SymbolKind::Definition { docs })
+}
+
+fn symbol(id: &Id<'_>, kind: SymbolKind) -> Symbol {
+    Symbol {
+        name: id.name.to_string(),
+        span: span(id),
+        kind,
+    }
+}
+
+fn span(id: &Id<'_>) -> Range<usize> {
+    id.span.start as usize..id.span.end as usize
+}
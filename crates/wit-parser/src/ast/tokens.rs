@@ -0,0 +1,87 @@
+//! The tokens of a WIT file, whitespace and comments included, so that
+//! editors and formatters work off the same lexer as the parser.
+
+use super::lex::Tokenizer;
+use anyhow::Result;
+use std::ops::Range;
+
+pub use super::lex::Token as TokenKind;
+
+/// A token of a WIT file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// The bytes of the file the token is written at.
+    pub span: Range<usize>,
+}
+
+/// How editors highlight a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Highlight {
+    Whitespace,
+    Comment,
+    /// A `///` or `/**` comment, documenting the item after it.
+    DocComment,
+    Keyword,
+    /// A keyword naming a builtin type, such as `u32` or `list`.
+    BuiltinType,
+    Identifier,
+    String,
+    Punctuation,
+}
+
+impl Token {
+    /// Returns how to highlight the token, which is written in `contents`.
+    pub fn highlight(&self, contents: &str) -> Highlight {
+        use TokenKind::*;
+        match self.kind {
+            Whitespace => Highlight::Whitespace,
+            Comment => {
+                let text = &contents[self.span.clone()];
+                if text.starts_with("///") || text.starts_with("/**") {
+                    Highlight::DocComment
+                } else {
+                    Highlight::Comment
+                }
+            }
+            Equals | Comma | Colon | Semicolon | LeftParen | RightParen | LeftBrace
+            | RightBrace | LessThan | GreaterThan | RArrow | Star | At => Highlight::Punctuation,
+            U8 | U16 | U32 | U64 | S8 | S16 | S32 | S64 | Float32 | Float64 | Char | Bool
+            | String_ | Option_ | Result_ | Future | Stream | List | Tuple | Unit => {
+                Highlight::BuiltinType
+            }
+            Use | Type | Resource | Func | Handle | Record | Flags | Variant | Enum | Union
+            | Underscore | As | From_ | Static | Interface | Implements => Highlight::Keyword,
+            Id | ExplicitId => Highlight::Identifier,
+            Str => Highlight::String,
+        }
+    }
+}
+
+impl TokenKind {
+    /// Whether the token is whitespace or a comment, which the parser skips.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, TokenKind::Whitespace | TokenKind::Comment)
+    }
+}
+
+/// Splits the WIT file `contents` into tokens, whose spans cover all of it
+/// in order, such that joining them gives back `contents`.
+pub fn tokens(contents: &str) -> Result<Vec<Token>> {
+    let mut tokenizer = Tokenizer::new(contents)?;
+    let mut tokens = Vec::new();
+    // The tokenizer skips a byte order mark, which is whitespace here.
+    if contents.starts_with('\u{feff}') {
+        tokens.push(Token {
+            kind: TokenKind::Whitespace,
+            span: 0..'\u{feff}'.len_utf8(),
+        });
+    }
+    while let Some((span, kind)) = tokenizer.next_raw()? {
+        tokens.push(Token {
+            kind,
+            span: span.start as usize..span.end as usize,
+        });
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,335 @@
+//! A canonical JSON rendering of interfaces.
+//!
+//! Two interfaces render to the same JSON exactly when they describe the same
+//! types, resources, functions, globals and events, regardless of comments,
+//! doc comments, formatting or the name of the file they were parsed from.
+//! Annotations are kept since they change the generated bindings. The output
+//! has no insignificant whitespace so that it can be hashed directly.
+
+use crate::*;
+
+impl Interface {
+    /// Renders this interface as canonical JSON.
+    pub fn canonical_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"types\":[");
+        for (i, (_, ty)) in self.types.iter().enumerate() {
+            comma(&mut out, i);
+            out.push('{');
+            if let Some(name) = &ty.name {
+                key(&mut out, "name");
+                string(&mut out, name);
+                out.push(',');
+            }
+            if let Some(module) = &ty.foreign_module {
+                key(&mut out, "foreign-module");
+                string(&mut out, module);
+                out.push(',');
+            }
+            annotations(&mut out, &ty.docs);
+            self.kind(&mut out, &ty.kind);
+            out.push('}');
+        }
+
+        out.push_str("],\"resources\":[");
+        for (i, (_, resource)) in self.resources.iter().enumerate() {
+            comma(&mut out, i);
+            out.push('{');
+            annotations(&mut out, &resource.docs);
+            if let Some(supertype) = &resource.supertype {
+                key(&mut out, "supertype");
+                string(&mut out, supertype);
+                out.push(',');
+            }
+            if let Some(module) = &resource.foreign_module {
+                key(&mut out, "foreign-module");
+                string(&mut out, module);
+                out.push(',');
+            }
+            key(&mut out, "name");
+            string(&mut out, &resource.name);
+            out.push('}');
+        }
+
+        out.push_str("],\"functions\":[");
+        for (i, func) in self.functions.iter().enumerate() {
+            comma(&mut out, i);
+            out.push('{');
+            annotations(&mut out, &func.docs);
+            match &func.kind {
+                FunctionKind::Freestanding => {}
+                FunctionKind::Static { resource, .. } => {
+                    key(&mut out, "static");
+                    string(&mut out, &self.resources[*resource].name);
+                    out.push(',');
+                }
+                FunctionKind::Method { resource, .. } => {
+                    key(&mut out, "method");
+                    string(&mut out, &self.resources[*resource].name);
+                    out.push(',');
+                }
+            }
+            key(&mut out, "name");
+            string(&mut out, &func.name);
+            out.push_str(",\"params\":[");
+            for (i, (name, ty)) in func.params.iter().enumerate() {
+                comma(&mut out, i);
+                out.push('[');
+                string(&mut out, name);
+                out.push(',');
+                self.ty(&mut out, ty);
+                out.push(']');
+            }
+            out.push_str("],\"result\":");
+            self.ty(&mut out, &func.result);
+            out.push('}');
+        }
+
+        out.push_str("],\"globals\":[");
+        for (i, global) in self.globals.iter().enumerate() {
+            comma(&mut out, i);
+            out.push('{');
+            annotations(&mut out, &global.docs);
+            key(&mut out, "name");
+            string(&mut out, &global.name);
+            out.push_str(",\"type\":");
+            self.ty(&mut out, &global.ty);
+            out.push('}');
+        }
+
+        out.push_str("],\"events\":[");
+        for (i, event) in self.events.iter().enumerate() {
+            comma(&mut out, i);
+            out.push('{');
+            annotations(&mut out, &event.docs);
+            key(&mut out, "name");
+            string(&mut out, &event.name);
+            out.push_str(",\"payload\":");
+            self.ty(&mut out, &event.payload);
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    fn kind(&self, out: &mut String, kind: &TypeDefKind) {
+        match kind {
+            TypeDefKind::Record(r) => {
+                out.push_str("\"record\":[");
+                for (i, field) in r.fields.iter().enumerate() {
+                    comma(out, i);
+                    self.member(out, &field.docs, Some(&field.name), &field.ty);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Flags(f) => {
+                out.push_str("\"flags\":[");
+                for (i, flag) in f.flags.iter().enumerate() {
+                    comma(out, i);
+                    self.member(out, &flag.docs, Some(&flag.name), &Type::Unit);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Tuple(t) => {
+                out.push_str("\"tuple\":[");
+                for (i, ty) in t.types.iter().enumerate() {
+                    comma(out, i);
+                    self.ty(out, ty);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Variant(v) => {
+                out.push_str("\"variant\":[");
+                for (i, case) in v.cases.iter().enumerate() {
+                    comma(out, i);
+                    self.member(out, &case.docs, Some(&case.name), &case.ty);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Enum(e) => {
+                out.push_str("\"enum\":[");
+                for (i, case) in e.cases.iter().enumerate() {
+                    comma(out, i);
+                    self.member(out, &case.docs, Some(&case.name), &Type::Unit);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Union(u) => {
+                out.push_str("\"union\":[");
+                for (i, case) in u.cases.iter().enumerate() {
+                    comma(out, i);
+                    self.member(out, &case.docs, None, &case.ty);
+                }
+                out.push(']');
+            }
+            TypeDefKind::Option(ty) => {
+                out.push_str("\"option\":");
+                self.ty(out, ty);
+            }
+            TypeDefKind::Result(r) => {
+                out.push_str("\"result\":{\"ok\":");
+                self.ty(out, &r.ok);
+                out.push_str(",\"err\":");
+                self.ty(out, &r.err);
+                out.push('}');
+            }
+            TypeDefKind::List(ty) => {
+                out.push_str("\"list\":");
+                self.ty(out, ty);
+            }
+            TypeDefKind::Future(ty) => {
+                out.push_str("\"future\":");
+                self.ty(out, ty);
+            }
+            TypeDefKind::Stream(s) => {
+                out.push_str("\"stream\":{\"element\":");
+                self.ty(out, &s.element);
+                out.push_str(",\"end\":");
+                self.ty(out, &s.end);
+                out.push('}');
+            }
+            TypeDefKind::Type(ty) => {
+                out.push_str("\"type\":");
+                self.ty(out, ty);
+            }
+        }
+    }
+
+    /// Renders a field, flag or case, leaving out the type if it is `unit`.
+    fn member(&self, out: &mut String, docs: &Docs, name: Option<&str>, ty: &Type) {
+        out.push('{');
+        annotations(out, docs);
+        if let Some(name) = name {
+            key(out, "name");
+            string(out, name);
+            if *ty != Type::Unit {
+                out.push(',');
+            }
+        }
+        if *ty != Type::Unit || name.is_none() {
+            key(out, "type");
+            self.ty(out, ty);
+        }
+        out.push('}');
+    }
+
+    /// Renders a reference to a type, naming other type definitions by their
+    /// index in `types`.
+    fn ty(&self, out: &mut String, ty: &Type) {
+        match ty {
+            Type::Unit => string(out, "unit"),
+            Type::Bool => string(out, "bool"),
+            Type::U8 => string(out, "u8"),
+            Type::U16 => string(out, "u16"),
+            Type::U32 => string(out, "u32"),
+            Type::U64 => string(out, "u64"),
+            Type::S8 => string(out, "s8"),
+            Type::S16 => string(out, "s16"),
+            Type::S32 => string(out, "s32"),
+            Type::S64 => string(out, "s64"),
+            Type::Float32 => string(out, "float32"),
+            Type::Float64 => string(out, "float64"),
+            Type::Char => string(out, "char"),
+            Type::String => string(out, "string"),
+            Type::Handle(resource) => {
+                out.push_str("{\"handle\":");
+                string(out, &self.resources[*resource].name);
+                out.push('}');
+            }
+            Type::Id(id) => out.push_str(&id.index().to_string()),
+        }
+    }
+}
+
+fn comma(out: &mut String, i: usize) {
+    if i > 0 {
+        out.push(',');
+    }
+}
+
+fn key(out: &mut String, key: &str) {
+    string(out, key);
+    out.push(':');
+}
+
+/// Renders the annotations in `docs` followed by a comma, if there are any.
+fn annotations(out: &mut String, docs: &Docs) {
+    if docs.annotations.is_empty() {
+        return;
+    }
+    out.push_str("\"annotations\":[");
+    for (i, annotation) in docs.annotations.iter().enumerate() {
+        comma(out, i);
+        out.push('[');
+        string(out, &annotation.name);
+        for arg in annotation.args.iter() {
+            out.push(',');
+            string(out, arg);
+        }
+        out.push(']');
+    }
+    out.push_str("],");
+}
+
+fn string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_docs_and_formatting() {
+        let a = Interface::parse(
+            "a",
+            "
+                /// A point.
+                record point { x: u32, y: u32 }
+                // Moves a point.
+                move: func(p: point, by: option<s32>) -> point
+            ",
+        )
+        .unwrap();
+        let b = Interface::parse(
+            "b",
+            "record point {
+                x: u32,
+                /// The vertical position.
+                y: u32,
+            }
+            move: func(p: point, by: option<s32>) -> point",
+        )
+        .unwrap();
+        assert_eq!(a.canonical_json(), b.canonical_json());
+        assert_eq!(
+            a.canonical_json(),
+            "{\"types\":[{\"name\":\"point\",\"record\":[{\"name\":\"x\",\"type\":\"u32\"},\
+             {\"name\":\"y\",\"type\":\"u32\"}]},{\"option\":\"s32\"}],\"resources\":[],\
+             \"functions\":[{\"name\":\"move\",\"params\":[[\"p\",0],[\"by\",1]],\"result\":0}],\
+             \"globals\":[],\"events\":[]}"
+        );
+    }
+
+    #[test]
+    fn keeps_annotations() {
+        let a = Interface::parse("a", "f: func()").unwrap();
+        let b = Interface::parse("a", "@idempotent f: func()").unwrap();
+        assert_ne!(a.canonical_json(), b.canonical_json());
+        assert!(b
+            .canonical_json()
+            .contains("{\"annotations\":[[\"idempotent\"]],\"name\":\"f\""));
+    }
+}
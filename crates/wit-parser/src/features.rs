@@ -0,0 +1,132 @@
+//! Items gated behind features with `@feature("name")` annotations, which
+//! only some builds generate bindings for.
+
+use crate::{Docs, Interface, Type, TypeDefKind, TypeId};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+impl Interface {
+    /// Removes the functions, globals, events and types annotated with
+    /// `@feature("name")` unless every feature they name is in `enabled`.
+    ///
+    /// Types can't be removed from the arena, so those left out lose their
+    /// names instead, which generators skip the definitions of. It is an
+    /// error for the items kept to refer to them.
+    pub fn retain_features(&mut self, enabled: &[String]) -> Result<()> {
+        let mut removed = HashMap::new();
+        for (id, ty) in self.types.iter() {
+            if let (Some(name), Some(feature)) = (&ty.name, missing_feature(&ty.docs, enabled)?) {
+                removed.insert(id, (name.clone(), feature));
+            }
+        }
+        let mut functions = Vec::new();
+        for func in std::mem::take(&mut self.functions) {
+            if missing_feature(&func.docs, enabled)?.is_none() {
+                functions.push(func);
+            }
+        }
+        self.functions = functions;
+        let mut globals = Vec::new();
+        for global in std::mem::take(&mut self.globals) {
+            if missing_feature(&global.docs, enabled)?.is_none() {
+                globals.push(global);
+            }
+        }
+        self.globals = globals;
+        let mut events = Vec::new();
+        for event in std::mem::take(&mut self.events) {
+            if missing_feature(&event.docs, enabled)?.is_none() {
+                events.push(event);
+            }
+        }
+        self.events = events;
+
+        let mut uses = Vec::new();
+        for (id, ty) in self.types.iter() {
+            if let (Some(name), false) = (&ty.name, removed.contains_key(&id)) {
+                uses.extend(kind_types(&ty.kind).into_iter().map(|t| (name, t)));
+            }
+        }
+        for func in self.functions.iter() {
+            let types = func.params.iter().map(|(_, t)| t).chain([&func.result]);
+            uses.extend(types.map(|t| (&func.name, t)));
+        }
+        uses.extend(self.globals.iter().map(|g| (&g.name, &g.ty)));
+        uses.extend(self.events.iter().map(|e| (&e.name, &e.payload)));
+        for (user, ty) in uses {
+            if let Some(id) = self.removed_type(ty, &removed) {
+                let (name, feature) = &removed[&id];
+                bail!("`{user}` refers to `{name}`, which requires feature `{feature}`");
+            }
+        }
+
+        for (id, (name, _)) in removed {
+            self.types[id].name = None;
+            self.type_lookup.remove(&name);
+        }
+        for (_, iface) in self.interfaces.iter_mut() {
+            iface.retain_features(enabled)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the removed type `ty` refers to, looking through anonymous
+    /// types, if any.
+    fn removed_type<T>(&self, ty: &Type, removed: &HashMap<TypeId, T>) -> Option<TypeId> {
+        let id = match ty {
+            Type::Id(id) => *id,
+            _ => return None,
+        };
+        if removed.contains_key(&id) {
+            return Some(id);
+        }
+        // Named types are checked on their own.
+        if self.types[id].name.is_some() {
+            return None;
+        }
+        kind_types(&self.types[id].kind)
+            .into_iter()
+            .find_map(|t| self.removed_type(t, removed))
+    }
+}
+
+impl Docs {
+    /// Returns the features named by the `@feature("name")` annotations of
+    /// the item documented, all of which it requires.
+    pub fn features(&self) -> impl Iterator<Item = &str> {
+        self.annotations
+            .iter()
+            .filter(|a| a.name == "feature")
+            .flat_map(|a| a.args.iter().map(|f| f.as_str()))
+    }
+}
+
+/// Returns a feature the item documented by `docs` requires which isn't in
+/// `enabled`, if any.
+fn missing_feature(docs: &Docs, enabled: &[String]) -> Result<Option<String>> {
+    for annotation in docs.annotations.iter().filter(|a| a.name == "feature") {
+        if annotation.args.is_empty() {
+            bail!("`@feature` requires the names of features");
+        }
+        if let Some(feature) = annotation.args.iter().find(|f| !enabled.contains(f)) {
+            return Ok(Some(feature.clone()));
+        }
+    }
+    Ok(None)
+}
+
+fn kind_types(kind: &TypeDefKind) -> Vec<&Type> {
+    match kind {
+        TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => Vec::new(),
+        TypeDefKind::Type(t)
+        | TypeDefKind::List(t)
+        | TypeDefKind::Option(t)
+        | TypeDefKind::Future(t) => vec![t],
+        TypeDefKind::Record(r) => r.fields.iter().map(|f| &f.ty).collect(),
+        TypeDefKind::Tuple(t) => t.types.iter().collect(),
+        TypeDefKind::Variant(v) => v.cases.iter().map(|c| &c.ty).collect(),
+        TypeDefKind::Union(u) => u.cases.iter().map(|c| &c.ty).collect(),
+        TypeDefKind::Result(r) => vec![&r.ok, &r.err],
+        TypeDefKind::Stream(s) => vec![&s.element, &s.end],
+    }
+}
@@ -1,15 +1,22 @@
 use anyhow::{anyhow, bail, Context, Result};
 use id_arena::{Arena, Id};
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+use pulldown_cmark::{CodeBlockKind, CowStr, Event as MdEvent, Options, Parser, Tag};
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
 pub mod abi;
 mod ast;
+mod canonical;
+mod features;
 pub mod mangle;
 mod sizealign;
+mod world;
+pub use ast::symbols::{symbols, Symbol, SymbolKind};
+pub use ast::tokens::{tokens, Highlight, Token, TokenKind};
+pub use ast::SourceError;
 pub use sizealign::*;
+pub use world::World;
 
 /// Checks if the given string is a legal identifier in wit.
 pub fn validate_id(s: &str) -> Result<()> {
@@ -36,6 +43,7 @@ pub struct Interface {
     pub interface_lookup: HashMap<String, InterfaceId>,
     pub functions: Vec<Function>,
     pub globals: Vec<Global>,
+    pub events: Vec<Event>,
 }
 
 pub type TypeId = Id<TypeDef>;
@@ -233,6 +241,25 @@ pub struct Stream {
 #[derive(Clone, Default, Debug, PartialEq)]
 pub struct Docs {
     pub contents: Option<String>,
+    pub annotations: Vec<Annotation>,
+}
+
+impl Docs {
+    /// Returns the annotation called `name`, if the item has one.
+    pub fn annotation(&self, name: &str) -> Option<&Annotation> {
+        self.annotations.iter().find(|a| a.name == name)
+    }
+}
+
+/// An annotation such as `@paginated` or `@feature("x")` written before an
+/// item, alongside its doc comments.
+///
+/// Annotations carry no meaning for the parser itself; they are hints for
+/// code generators.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Annotation {
+    pub name: String,
+    pub args: Vec<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -252,6 +279,14 @@ pub struct Global {
     pub ty: Type,
 }
 
+/// An event which can be emitted by a module, carrying a single payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub docs: Docs,
+    pub name: String,
+    pub payload: Type,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function {
     pub docs: Docs,
@@ -285,10 +320,10 @@ fn unwrap_md(contents: &str) -> String {
     Parser::new_ext(contents, Options::empty())
         .into_offset_iter()
         .for_each(|(event, range)| match (event, range) {
-            (Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::Borrowed("wit")))), _) => {
+            (MdEvent::Start(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::Borrowed("wit")))), _) => {
                 in_wit_code_block = true;
             }
-            (Event::Text(text), range) if in_wit_code_block => {
+            (MdEvent::Text(text), range) if in_wit_code_block => {
                 // Ensure that offsets are correct by inserting newlines to
                 // cover the Markdown content outside of wit code blocks.
                 for _ in contents[last_pos..range.start].lines() {
@@ -297,7 +332,7 @@ fn unwrap_md(contents: &str) -> String {
                 wit.push_str(&text);
                 last_pos = range.end;
             }
-            (Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::Borrowed("wit")))), _) => {
+            (MdEvent::End(Tag::CodeBlock(CodeBlockKind::Fenced(CowStr::Borrowed("wit")))), _) => {
                 in_wit_code_block = false;
             }
             _ => {}
@@ -313,11 +348,23 @@ impl Interface {
     }
 
     pub fn parse_file(path: impl AsRef<Path>) -> Result<Interface> {
+        Ok(Interface::parse_file_with_sources(path)?.0)
+    }
+
+    /// Like [`Interface::parse_file`], also returning the paths of the files
+    /// read: the one at `path`, then those it `use`s.
+    pub fn parse_file_with_sources(path: impl AsRef<Path>) -> Result<(Interface, Vec<PathBuf>)> {
         let path = path.as_ref();
         let parent = path.parent().unwrap();
         let contents = std::fs::read_to_string(&path)
             .with_context(|| format!("failed to read: {}", path.display()))?;
-        Interface::parse_with(path, &contents, |path| load_fs(parent, path))
+        let mut sources = vec![path.to_path_buf()];
+        let iface = Interface::parse_with(path, &contents, |name| {
+            let (path, contents) = load_fs(parent, name)?;
+            sources.push(path.clone());
+            Ok((path, contents))
+        })?;
+        Ok((iface, sources))
     }
 
     pub fn parse_with(
@@ -382,9 +429,11 @@ impl Interface {
             if map.contains_key(&*u.from[0].name) {
                 continue;
             }
-            let (filename, contents) = load(&u.from[0].name)
-                // TODO: insert context here about `u.name.span` and `filename`
-                ?;
+            let (filename, contents) = load(&u.from[0].name).map_err(|e| {
+                let file = filename.display().to_string();
+                let msg = format!("failed to load `{}`: {:#}", u.from[0].name, e);
+                ast::error_at(u.from[0].span, &file, contents, msg)
+            })?;
             let instance = Interface::_parse_with(&filename, &contents, load, visiting, map)?;
             map.insert(u.from[0].name.to_string(), instance);
         }
@@ -496,6 +545,56 @@ impl Interface {
         }
     }
 
+    /// Returns how deeply values of type `ty` nest, counting one level for
+    /// each aggregate or list a value is wrapped in.
+    pub fn nesting_depth(&self, ty: &Type) -> usize {
+        let id = match ty {
+            Type::Id(id) => *id,
+            _ => return 0,
+        };
+        let max = |tys: &mut dyn Iterator<Item = &Type>| {
+            1 + tys.map(|t| self.nesting_depth(t)).max().unwrap_or(0)
+        };
+        match &self.types[id].kind {
+            TypeDefKind::Type(t) => self.nesting_depth(t),
+            TypeDefKind::Flags(_) | TypeDefKind::Enum(_) => 1,
+            TypeDefKind::Record(r) => max(&mut r.fields.iter().map(|f| &f.ty)),
+            TypeDefKind::Tuple(t) => max(&mut t.types.iter()),
+            TypeDefKind::Variant(v) => max(&mut v.cases.iter().map(|c| &c.ty)),
+            TypeDefKind::Union(u) => max(&mut u.cases.iter().map(|c| &c.ty)),
+            TypeDefKind::Result(r) => max(&mut [&r.ok, &r.err].into_iter()),
+            TypeDefKind::Stream(s) => max(&mut [&s.element, &s.end].into_iter()),
+            TypeDefKind::Option(t) | TypeDefKind::List(t) | TypeDefKind::Future(t) => {
+                1 + self.nesting_depth(t)
+            }
+        }
+    }
+
+    /// Returns the deepest [`Interface::nesting_depth`] of the parameters and
+    /// results of this interface's functions.
+    pub fn max_nesting_depth(&self) -> usize {
+        self.functions
+            .iter()
+            .flat_map(|f| f.params.iter().map(|(_, t)| t).chain([&f.result]))
+            .map(|t| self.nesting_depth(t))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the topic that identifies `event` once it has been emitted.
+    ///
+    /// The topic is a 64-bit FNV-1a hash of the event's mangled signature,
+    /// so it changes whenever the event's name or payload shape changes but
+    /// not when payload types are merely renamed.
+    pub fn event_topic(&self, event: &Event) -> u64 {
+        let mut hash = 0xcbf29ce484222325u64;
+        for byte in self.mangle_event(event).bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
     pub fn get_variant(&self, ty: &Type) -> Option<&Variant> {
         if let Type::Id(id) = ty {
             match &self.types[*id].kind {
@@ -508,7 +607,24 @@ impl Interface {
     }
 }
 
+/// Loads the interface `name` used from a file in `root`, looking for it next
+/// to the file and then in `root/deps`.
 fn load_fs(root: &Path, name: &str) -> Result<(PathBuf, String)> {
+    match load_file(root, name) {
+        Err(err) if is_not_found(&err) => match load_file(&root.join("deps"), name) {
+            Err(deps_err) if is_not_found(&deps_err) => Err(err),
+            result => result,
+        },
+        result => result,
+    }
+}
+
+fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<std::io::Error>()
+        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound)
+}
+
+fn load_file(root: &Path, name: &str) -> Result<(PathBuf, String)> {
     let wit = root.join(name).with_extension("wit");
 
     // Attempt to read a ".wit" file.
@@ -4,7 +4,7 @@
 //! [here]: https://github.com/WebAssembly/component-model/blob/main/design/mvp/CanonicalABI.md#canonical-module-type
 
 use crate::{
-    Case, EnumCase, Field, Flag, Function, Interface, ResourceId, Stream, Type, TypeDefKind,
+    Case, EnumCase, Event, Field, Flag, Function, Interface, ResourceId, Stream, Type, TypeDefKind,
     UnionCase,
 };
 
@@ -23,6 +23,14 @@ impl Interface {
         self.mangle_funcname_with_name(&format!("cabi_start{{cabi={}}}", CABI_VERSION), func)
     }
 
+    pub fn mangle_event(&self, event: &Event) -> String {
+        format!(
+            "{}: event {}",
+            event.name,
+            self.mangle_valtype(event.payload)
+        )
+    }
+
     fn mangle_funcname_with_name(&self, name: &str, func: &Function) -> String {
         format!(
             "{}: func{} -> {}",
@@ -506,4 +514,15 @@ mod tests {
             "stream<s8, u8>"
         );
     }
+
+    #[test]
+    fn test_event() {
+        let iface = Interface::default();
+        let event = Event {
+            docs: Docs::default(),
+            name: "ping".to_owned(),
+            payload: Type::U32,
+        };
+        assert_eq!(iface.mangle_event(&event), "ping: event u32");
+    }
 }
@@ -0,0 +1,99 @@
+//! Worlds: the interfaces a component imports and exports.
+//!
+//! A package is a directory of interface files, each defining the interface
+//! named after it, along with files declaring worlds:
+//!
+//! ```wit
+//! world contract {
+//!     import storage
+//!     import log: logging
+//!     export contract
+//! }
+//! ```
+//!
+//! A world imports and exports interfaces of its package by their file name,
+//! under that name or the one given before a colon. As in the component
+//! model, a world's imports are implemented by its host and its exports by
+//! the component itself.
+
+use crate::{ast, Interface};
+use anyhow::{bail, Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub struct World {
+    pub name: String,
+    pub imports: Vec<Interface>,
+    pub exports: Vec<Interface>,
+    /// The files the world and its interfaces were read from.
+    pub files: Vec<PathBuf>,
+}
+
+impl World {
+    /// Loads the world `name` of the package at `dir`, along with the
+    /// interfaces it imports and exports.
+    pub fn load(dir: impl AsRef<Path>, name: &str) -> Result<World> {
+        let dir = dir.as_ref();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("failed to read: {}", dir.display()))?;
+        let mut declared = None;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("wit") {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read: {}", path.display()))?;
+            let worlds = match ast::World::parse_all(&contents) {
+                Ok(worlds) => worlds,
+                Err(mut e) => {
+                    ast::rewrite_error(&mut e, &path.display().to_string(), &contents);
+                    return Err(e);
+                }
+            };
+            for world in worlds.iter().filter(|w| w.name.name == name) {
+                if declared.is_some() {
+                    bail!("world `{}` is declared twice in {}", name, dir.display());
+                }
+                let items = world
+                    .items
+                    .iter()
+                    .map(|item| {
+                        let name = item.name.name.to_string();
+                        (item.export, name, item.interface.name.to_string())
+                    })
+                    .collect::<Vec<_>>();
+                declared = Some((path.clone(), items));
+            }
+        }
+        let (path, items) = match declared {
+            Some(declared) => declared,
+            None => bail!("no world `{}` in {}", name, dir.display()),
+        };
+
+        let mut world = World {
+            name: name.to_string(),
+            imports: Vec::new(),
+            exports: Vec::new(),
+            files: vec![path],
+        };
+        for (export, item, interface) in items {
+            let path = dir.join(format!("{}.wit", interface));
+            let mut iface = Interface::parse_file(&path).with_context(|| {
+                format!(
+                    "failed to load interface `{}` of world `{}`",
+                    interface, name
+                )
+            })?;
+            iface.name = item;
+            world.files.push(path);
+            if export {
+                world.exports.push(iface);
+            } else {
+                world.imports.push(iface);
+            }
+        }
+        Ok(world)
+    }
+}
@@ -176,6 +176,8 @@ fn to_json(i: &Interface) -> String {
         functions: Vec<Function>,
         #[serde(skip_serializing_if = "Vec::is_empty")]
         globals: Vec<Global>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        events: Vec<Event>,
     }
 
     #[derive(Serialize)]
@@ -220,6 +222,8 @@ fn to_json(i: &Interface) -> String {
         name: String,
         params: Vec<String>,
         result: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        annotations: Vec<String>,
     }
 
     #[derive(Serialize)]
@@ -228,6 +232,13 @@ fn to_json(i: &Interface) -> String {
         ty: String,
     }
 
+    #[derive(Serialize)]
+    struct Event {
+        name: String,
+        payload: String,
+        topic: u64,
+    }
+
     let resources = i
         .resources
         .iter()
@@ -255,6 +266,12 @@ fn to_json(i: &Interface) -> String {
             name: f.name.clone(),
             params: f.params.iter().map(|(_, ty)| translate_type(ty)).collect(),
             result: translate_type(&f.result),
+            annotations: f
+                .docs
+                .annotations
+                .iter()
+                .map(|a| format!("{}({})", a.name, a.args.join(", ")))
+                .collect(),
         })
         .collect::<Vec<_>>();
     let globals = i
@@ -265,12 +282,22 @@ fn to_json(i: &Interface) -> String {
             ty: translate_type(&g.ty),
         })
         .collect::<Vec<_>>();
+    let events = i
+        .events
+        .iter()
+        .map(|e| Event {
+            name: e.name.clone(),
+            payload: translate_type(&e.payload),
+            topic: i.event_topic(e),
+        })
+        .collect::<Vec<_>>();
 
     let iface = Interface {
         resources,
         types,
         functions,
         globals,
+        events,
     };
     return serde_json::to_string_pretty(&iface).unwrap();
 
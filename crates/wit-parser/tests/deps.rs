@@ -0,0 +1,27 @@
+use wit_parser::Interface;
+
+#[test]
+fn resolves_uses_from_deps() {
+    let iface = Interface::parse_file("tests/deps/ledger.wit").unwrap();
+    let amount = &iface.types[iface.type_lookup["amount"]];
+    assert_eq!(amount.foreign_module.as_deref(), Some("currency"));
+}
+
+#[test]
+fn lists_the_files_read() {
+    let (_, sources) = Interface::parse_file_with_sources("tests/deps/ledger.wit").unwrap();
+    let sources = sources
+        .iter()
+        .map(|p| p.to_str().unwrap().replace('\\', "/"))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        ["tests/deps/ledger.wit", "tests/deps/deps/currency.wit"]
+    );
+}
+
+#[test]
+fn reports_missing_uses() {
+    let err = Interface::parse("ledger", "use { amount } from missing").unwrap_err();
+    assert!(err.to_string().contains("missing"));
+}
@@ -0,0 +1,22 @@
+use wit_parser::{Interface, SourceError};
+
+#[test]
+fn locates_parse_errors() {
+    let err = Interface::parse(
+        "kv",
+        "get: func(key: string) -> u32\nset: func(key: strin)\n",
+    )
+    .unwrap_err();
+    let source = err.downcast_ref::<SourceError>().unwrap();
+    assert_eq!(source.file, "kv");
+    assert_eq!((source.line, source.column), (2, 16));
+    assert_eq!(source.snippet, "set: func(key: strin)");
+    assert!(err.to_string().contains("--> kv:2:16"));
+}
+
+#[test]
+fn locates_lexer_errors() {
+    let err = Interface::parse("kv", "get: func() -> u32\n\"unterminated\n").unwrap_err();
+    let source = err.downcast_ref::<SourceError>().unwrap();
+    assert_eq!(source.line, 2);
+}
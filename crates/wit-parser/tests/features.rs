@@ -0,0 +1,60 @@
+use wit_parser::Interface;
+
+const SRC: &str = r#"
+@feature("refunds")
+record refund { amount: u64 }
+
+pay: func(amount: u64)
+
+@feature("refunds")
+refund: func(r: refund)
+
+@feature("refunds", "partial")
+partial-refund: func(r: option<refund>)
+"#;
+
+fn names(iface: &Interface) -> Vec<&str> {
+    iface.functions.iter().map(|f| f.name.as_str()).collect()
+}
+
+#[test]
+fn omits_items_of_disabled_features() {
+    let mut iface = Interface::parse("payments", SRC).unwrap();
+    iface.retain_features(&[]).unwrap();
+    assert_eq!(names(&iface), ["pay"]);
+    assert!(!iface.type_lookup.contains_key("refund"));
+    assert!(iface.types.iter().all(|(_, t)| t.name.is_none()));
+}
+
+#[test]
+fn keeps_items_of_enabled_features() {
+    let mut iface = Interface::parse("payments", SRC).unwrap();
+    iface.retain_features(&["refunds".to_string()]).unwrap();
+    assert_eq!(names(&iface), ["pay", "refund"]);
+    assert!(iface.type_lookup.contains_key("refund"));
+
+    let mut iface = Interface::parse("payments", SRC).unwrap();
+    let all = ["refunds".to_string(), "partial".to_string()];
+    iface.retain_features(&all).unwrap();
+    assert_eq!(names(&iface), ["pay", "refund", "partial-refund"]);
+}
+
+#[test]
+fn lists_the_features_of_items() {
+    let iface = Interface::parse("payments", SRC).unwrap();
+    let features = |i: usize| iface.functions[i].docs.features().collect::<Vec<_>>();
+    assert!(features(0).is_empty());
+    assert_eq!(features(1), ["refunds"]);
+    assert_eq!(features(2), ["refunds", "partial"]);
+}
+
+#[test]
+fn rejects_references_to_omitted_types() {
+    let src = format!("{SRC}\ncancel: func() -> list<refund>");
+    let mut iface = Interface::parse("payments", &src).unwrap();
+    let err = iface.retain_features(&[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`cancel` refers to `refund`, which requires feature `refunds`"
+    );
+}
@@ -0,0 +1,42 @@
+use wit_parser::{symbols, Symbol, SymbolKind};
+
+#[test]
+fn lists_definitions_references_and_uses() {
+    let src = "\
+use { amount as money } from currency
+
+/// A payment.
+record payment { to: string, value: money }
+
+pay: func(p: payment) -> result<unit, list<payment>>
+";
+    let symbols = symbols(src).unwrap();
+    let at = |s: &Symbol| &src[s.span.clone()];
+    let names = symbols.iter().map(at).collect::<Vec<_>>();
+    assert_eq!(
+        names,
+        ["money", "payment", "money", "pay", "payment", "payment"]
+    );
+
+    assert_eq!(
+        symbols[0].kind,
+        SymbolKind::Use {
+            from: "currency".to_string(),
+            original: "amount".to_string(),
+            original_span: 6..12,
+        }
+    );
+    assert_eq!(
+        symbols[1].kind,
+        SymbolKind::Definition {
+            docs: Some("A payment.\n".to_string())
+        }
+    );
+    assert_eq!(symbols[2].kind, SymbolKind::Reference);
+    assert_eq!(symbols[3].kind, SymbolKind::Definition { docs: None });
+}
+
+#[test]
+fn fails_on_syntax_errors() {
+    assert!(symbols("record {").is_err());
+}
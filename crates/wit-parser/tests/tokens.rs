@@ -0,0 +1,68 @@
+use wit_parser::{tokens, Highlight, TokenKind};
+
+#[test]
+fn covers_the_whole_file() {
+    for entry in std::fs::read_dir("tests/ui").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().and_then(|s| s.to_str()) != Some("wit") {
+            continue;
+        }
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let tokens = tokens(&contents).unwrap();
+        let joined = tokens
+            .iter()
+            .map(|t| &contents[t.span.clone()])
+            .collect::<String>();
+        assert_eq!(joined, contents, "tokens of {:?}", path);
+    }
+}
+
+#[test]
+fn keeps_trivia() {
+    let src = "\u{feff}// a comment\r\n/// Docs.\nf: func(x: u32) -> list<%type>\n";
+    let tokens = tokens(src).unwrap();
+    let kinds = tokens.iter().map(|t| t.kind).collect::<Vec<_>>();
+    assert_eq!(
+        &kinds[..6],
+        [
+            TokenKind::Whitespace,
+            TokenKind::Comment,
+            TokenKind::Comment,
+            TokenKind::Id,
+            TokenKind::Colon,
+            TokenKind::Whitespace,
+        ]
+    );
+    assert!(kinds[0].is_trivia() && !kinds[3].is_trivia());
+
+    let highlights = tokens
+        .iter()
+        .map(|t| t.highlight(src))
+        .filter(|h| *h != Highlight::Whitespace)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        highlights,
+        [
+            Highlight::Comment,
+            Highlight::DocComment,
+            Highlight::Identifier,
+            Highlight::Punctuation,
+            Highlight::Keyword,
+            Highlight::Punctuation,
+            Highlight::Identifier,
+            Highlight::Punctuation,
+            Highlight::BuiltinType,
+            Highlight::Punctuation,
+            Highlight::Punctuation,
+            Highlight::BuiltinType,
+            Highlight::Punctuation,
+            Highlight::Identifier,
+            Highlight::Punctuation,
+        ]
+    );
+}
+
+#[test]
+fn fails_on_invalid_tokens() {
+    assert!(tokens("f: func() -> \"unterminated").is_err());
+}
@@ -0,0 +1,23 @@
+use wit_parser::World;
+
+#[test]
+fn loads_worlds() {
+    let world = World::load("tests/worlds", "proxy").unwrap();
+    let names = |ifaces: &[wit_parser::Interface]| {
+        ifaces.iter().map(|i| i.name.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(names(&world.imports), ["upstream"]);
+    assert_eq!(names(&world.exports), ["http"]);
+    assert_eq!(world.imports[0].functions[0].name, "serve");
+    assert_eq!(world.files.len(), 3);
+
+    let world = World::load("tests/worlds", "logger").unwrap();
+    assert_eq!(names(&world.imports), ["log"]);
+    assert_eq!(names(&world.exports), ["http"]);
+}
+
+#[test]
+fn reports_missing_worlds() {
+    let err = World::load("tests/worlds", "missing").unwrap_err();
+    assert!(err.to_string().starts_with("no world `missing`"));
+}
@@ -1,7 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use std::path::PathBuf;
 use structopt::StructOpt;
-use wit_bindgen_core::{wit_parser, Files, Generator};
+use wit_bindgen_core::abi_lock::{AbiLock, ABI_LOCKFILE};
+use wit_bindgen_core::from_json;
+use wit_bindgen_core::lint::Thresholds;
+use wit_bindgen_core::lock::{InterfaceSource, Lockfile, LOCKFILE};
+use wit_bindgen_core::options::{self, Options};
+use wit_bindgen_core::registry::{Registry, RegistryRef};
+use wit_bindgen_core::size_report::SizeReport;
+use wit_bindgen_core::stats::Stats;
+use wit_bindgen_core::{wit_parser, workspace, Files, Generator};
 use wit_parser::Interface;
 
 #[derive(Debug, StructOpt)]
@@ -24,6 +32,489 @@ enum Category {
         #[structopt(flatten)]
         common: Common,
     },
+    /// Publishes interfaces to, or fetches them from, a schema registry.
+    Registry(RegistryCommand),
+    /// Records the hashes of interfaces in the workspace's `wit.lock`.
+    ///
+    /// Bindings generated from an interface are only allowed to use it while
+    /// it matches its recorded hash.
+    Lock {
+        /// `*.wit` files to record.
+        files: Vec<PathBuf>,
+        /// Registry references to record, e.g. `linera:contract@2`. These are
+        /// fetched from the registry named by `WIT_BINDGEN_REGISTRY`, trusting
+        /// the hash they were published with.
+        #[structopt(long = "registry-ref")]
+        references: Vec<RegistryRef>,
+        /// Only check that every recorded interface still matches its hash.
+        #[structopt(long)]
+        check: bool,
+        /// The lockfile to update. Defaults to the closest `wit.lock` in the
+        /// current directory or its ancestors, or a new one in the current
+        /// directory.
+        #[structopt(long = "lockfile")]
+        lockfile: Option<PathBuf>,
+    },
+    /// Records the ABI of interfaces in the workspace's `wit-abi.lock`.
+    ///
+    /// Bindings are only generated for an interface while the signatures and
+    /// type layouts it lowers to match its recorded snapshot.
+    AbiLock {
+        /// `*.wit` files to record.
+        files: Vec<PathBuf>,
+        /// Only check that the ABI of each file still matches its snapshot.
+        #[structopt(long)]
+        check: bool,
+        /// The lockfile to update. Defaults to the closest `wit-abi.lock` in
+        /// the current directory or its ancestors, or a new one in the
+        /// current directory.
+        #[structopt(long = "lockfile")]
+        lockfile: Option<PathBuf>,
+    },
+    /// Checks that the crates of a workspace bind their interfaces
+    /// consistently.
+    ///
+    /// This reports interface files whose copies have diverged and
+    /// interfaces bound by the same macro with different options.
+    CheckWorkspace {
+        /// The workspace root. Defaults to the current directory.
+        dir: Option<PathBuf>,
+    },
+    /// Reports how much of a compiled guest's code the bindings of each
+    /// interface function take.
+    ///
+    /// Code is attributed through the names generated bindings give it, so
+    /// the module needs its `name` section and its exports.
+    SizeReport {
+        /// The compiled guest module.
+        wasm: PathBuf,
+        /// The `*.wit` interfaces the guest imports or exports. Can be
+        /// specified multiple times.
+        #[structopt(long = "interface", short)]
+        interfaces: Vec<PathBuf>,
+    },
+    /// Summarizes interfaces: their functions, how deeply their types nest,
+    /// their widest flattened signatures and the size of the code each
+    /// generator emits for them.
+    ///
+    /// Code sizes are those of bindings importing and exporting every
+    /// interface, with the default options of each generator.
+    Stats {
+        /// `*.wit` files to summarize.
+        files: Vec<PathBuf>,
+        /// Count the items annotated with `@feature("name")` for this
+        /// feature, which are otherwise omitted. Can be specified multiple
+        /// times.
+        #[structopt(long = "feature")]
+        features: Vec<String>,
+    },
+    /// Prints the options of a generator as JSON: their names, types, docs
+    /// and defaults.
+    ///
+    /// Generators are named like their subcommands, such as `guest-rust`
+    /// or `host-wasmtime-rust`.
+    Options {
+        /// The generator to describe.
+        generator: String,
+    },
+    /// Writes canonical test vectors for the types of interfaces.
+    ///
+    /// Each vector pairs a value literal with the bytes the canonical ABI
+    /// stores it as, so that other implementations can check their
+    /// marshaling against ours.
+    TestVectors {
+        /// `*.wit` files to write vectors for.
+        files: Vec<PathBuf>,
+        /// Where to write the vectors, in one directory per interface.
+        /// Defaults to the current directory.
+        #[structopt(long = "out-dir")]
+        out_dir: Option<PathBuf>,
+    },
+    /// Drafts WIT types from sample JSON documents.
+    ///
+    /// Objects become records, `null`s options and values of mixed kinds
+    /// variants. The draft is printed, to be reviewed before use.
+    FromJson {
+        /// The name of the type of the samples.
+        #[structopt(long, default_value = "sample")]
+        name: String,
+        /// JSON files holding one sample each.
+        samples: Vec<PathBuf>,
+        /// Ask which type to use where the samples leave a choice, such as
+        /// the width of integers, rather than taking the inferred one.
+        #[structopt(long, short)]
+        interactive: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum RegistryCommand {
+    /// Publishes a `*.wit` interface under `namespace:name@version`.
+    Publish {
+        /// The interface to publish.
+        wit: PathBuf,
+        /// The reference to publish it as, e.g. `linera:contract@2`.
+        reference: RegistryRef,
+        #[structopt(flatten)]
+        registry: RegistryRoot,
+    },
+    /// Fetches an interface and checks it against its pinned hash.
+    Fetch {
+        /// The reference to fetch, e.g. `linera:contract@2`.
+        reference: RegistryRef,
+        /// The hash the interface must have. Defaults to the one recorded in
+        /// the closest `wit.lock`.
+        #[structopt(long = "hash")]
+        hash: Option<String>,
+        /// Where to write the interface's WIT source.
+        #[structopt(long = "out")]
+        out: Option<PathBuf>,
+        #[structopt(flatten)]
+        registry: RegistryRoot,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+struct RegistryRoot {
+    /// The directory or `http(s)://` URL of the registry. Defaults to the
+    /// value of `WIT_BINDGEN_REGISTRY`.
+    #[structopt(long = "registry")]
+    registry: Option<String>,
+}
+
+impl RegistryRoot {
+    fn open(self) -> Result<Registry> {
+        match self.registry {
+            Some(root) => Ok(Registry::new(root)),
+            None => Registry::from_env(),
+        }
+    }
+}
+
+impl RegistryCommand {
+    fn run(self) -> Result<()> {
+        match self {
+            RegistryCommand::Publish {
+                wit,
+                reference,
+                registry,
+            } => {
+                let source = std::fs::read_to_string(&wit)
+                    .with_context(|| format!("failed to read {:?}", wit))?;
+                let hash = registry.open()?.publish(&reference, &source)?;
+                println!("Published {} ({})", reference, hash);
+            }
+            RegistryCommand::Fetch {
+                reference,
+                hash,
+                out,
+                registry,
+            } => {
+                let hash = match hash {
+                    Some(hash) => hash,
+                    None => Lockfile::find(&std::env::current_dir()?)?
+                        .and_then(|lockfile| lockfile.pinned(&reference).map(String::from))
+                        .with_context(|| {
+                            format!(
+                                "`{}` isn't pinned in a lockfile; pass the expected `--hash`",
+                                reference
+                            )
+                        })?,
+                };
+                let (iface, source) = registry.open()?.fetch_with_source(&reference, &hash)?;
+                println!(
+                    "Fetched {} ({})",
+                    reference,
+                    wit_bindgen_core::registry::interface_hash(&iface)
+                );
+                if let Some(out) = out {
+                    std::fs::write(&out, source)
+                        .with_context(|| format!("failed to write {:?}", out))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn lock(
+    files: Vec<PathBuf>,
+    references: Vec<RegistryRef>,
+    check: bool,
+    lockfile: Option<PathBuf>,
+) -> Result<()> {
+    let mut lockfile = match lockfile {
+        Some(path) if path.exists() => Lockfile::load(path)?,
+        Some(path) => Lockfile::new(path)?,
+        None => match Lockfile::find(&std::env::current_dir()?)? {
+            Some(lockfile) => lockfile,
+            None => Lockfile::new(LOCKFILE)?,
+        },
+    };
+
+    if check {
+        let mut failed = false;
+        for source in lockfile.sources()? {
+            let loaded = source.load(Some(&lockfile));
+            if let Err(e) = loaded.and_then(|i| lockfile.verify(&source, &i)) {
+                eprintln!("error: {:?}", e);
+                failed = true;
+            }
+        }
+        if failed {
+            bail!("{:?} is out of date", lockfile.path());
+        }
+        return Ok(());
+    }
+
+    let sources = files
+        .into_iter()
+        .map(InterfaceSource::File)
+        .chain(references.into_iter().map(InterfaceSource::Registry));
+    for source in sources {
+        let iface = match &source {
+            InterfaceSource::File(_) => source.load(None)?,
+            InterfaceSource::Registry(reference) => {
+                let registry = Registry::from_env()?;
+                registry.fetch(reference, &registry.published_hash(reference)?)?
+            }
+        };
+        lockfile.insert(&source, &iface)?;
+    }
+    lockfile.save()?;
+    println!("Updated {:?}", lockfile.path());
+    Ok(())
+}
+
+fn abi_lock(files: Vec<PathBuf>, check: bool, lockfile: Option<PathBuf>) -> Result<()> {
+    let mut lockfile = match lockfile {
+        Some(path) if path.exists() => AbiLock::load(path)?,
+        Some(path) => AbiLock::new(path),
+        None => match AbiLock::find(&std::env::current_dir()?)? {
+            Some(lockfile) => lockfile,
+            None => AbiLock::new(ABI_LOCKFILE),
+        },
+    };
+
+    let interfaces = files
+        .iter()
+        .map(Interface::parse_file)
+        .collect::<Result<Vec<_>>>()?;
+    if check {
+        let mut failed = false;
+        for iface in interfaces.iter() {
+            if let Err(e) = lockfile.verify(iface) {
+                eprintln!("error: {:?}", e);
+                failed = true;
+            }
+        }
+        if failed {
+            bail!("{:?} is out of date", lockfile.path());
+        }
+        return Ok(());
+    }
+
+    for iface in interfaces.iter() {
+        lockfile.insert(iface);
+    }
+    lockfile.save()?;
+    println!("Updated {:?}", lockfile.path());
+    Ok(())
+}
+
+fn size_report(wasm: PathBuf, interfaces: Vec<PathBuf>) -> Result<()> {
+    let bytes = std::fs::read(&wasm).with_context(|| format!("failed to read {:?}", wasm))?;
+    let interfaces = interfaces
+        .iter()
+        .map(Interface::parse_file)
+        .collect::<Result<Vec<_>>>()?;
+    let report = SizeReport::analyze(&bytes, &interfaces)
+        .with_context(|| format!("failed to analyze {:?}", wasm))?;
+    println!("{}", report);
+    Ok(())
+}
+
+fn stats(files: Vec<PathBuf>, features: Vec<String>) -> Result<()> {
+    let mut interfaces = Vec::new();
+    for file in files.iter() {
+        let mut iface = Interface::parse_file(file)?;
+        iface
+            .retain_features(&features)
+            .with_context(|| format!("failed to select the features of {:?}", file))?;
+        interfaces.push(iface);
+    }
+    let mut stats = Stats::collect(&interfaces);
+    let generators: [(&str, fn() -> Box<dyn Generator>); 7] = [
+        ("guest-rust", || {
+            Box::new(wit_bindgen_gen_guest_rust::Opts::default().build())
+        }),
+        ("guest-c", || {
+            Box::new(wit_bindgen_gen_guest_c::Opts::default().build())
+        }),
+        ("host-wasmtime-rust", || {
+            Box::new(wit_bindgen_gen_host_wasmtime_rust::Opts::default().build())
+        }),
+        ("host-wasmtime-py", || {
+            Box::new(wit_bindgen_gen_host_wasmtime_py::Opts::default().build())
+        }),
+        ("host-js", || {
+            Box::new(wit_bindgen_gen_host_js::Opts::default().build())
+        }),
+        ("host-wasmer-rust", || {
+            Box::new(wit_bindgen_gen_host_wasmer_rust::Opts::default().build())
+        }),
+        ("host-wasmer-py", || {
+            Box::new(wit_bindgen_gen_host_wasmer_py::Opts::default().build())
+        }),
+    ];
+    for (name, generator) in generators {
+        // Importing and exporting the same interface in one set of bindings
+        // would define it twice, so each direction is generated on its own.
+        let mut files = Files::default();
+        generator().generate_all(&interfaces, &[], &mut files);
+        generator().generate_all(&[], &interfaces, &mut files);
+        stats.measure(name, &files);
+    }
+    print!("{}", stats);
+    Ok(())
+}
+
+fn test_vectors(files: Vec<PathBuf>, out_dir: Option<PathBuf>) -> Result<()> {
+    let out_dir = out_dir.unwrap_or_default();
+    for file in files.iter() {
+        let iface = Interface::parse_file(file)?;
+        let dir = out_dir.join(&iface.name);
+        let count = wit_bindgen_core::vectors::write_corpus(&iface, &dir)?;
+        println!("Wrote {} vectors to {:?}", count, dir);
+    }
+    Ok(())
+}
+
+fn from_json(name: &str, samples: Vec<PathBuf>, interactive: bool) -> Result<()> {
+    let samples = samples
+        .iter()
+        .map(|path| {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read {:?}", path))?;
+            serde_json::from_str(&contents).with_context(|| format!("invalid JSON in {:?}", path))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let src = if interactive {
+        from_json::infer(name, &samples, &mut Prompt)?
+    } else {
+        from_json::infer(name, &samples, &mut from_json::Defaults)?
+    };
+    print!("{}", src);
+    Ok(())
+}
+
+/// Asks the choices of [`from_json::infer`] on the terminal.
+struct Prompt;
+
+impl from_json::Refine for Prompt {
+    fn choose(&mut self, question: &str, choices: &[String]) -> usize {
+        eprintln!("{}", question);
+        for (i, choice) in choices.iter().enumerate() {
+            eprintln!("  {}) {}", i + 1, choice);
+        }
+        loop {
+            eprint!("[1]: ");
+            let mut line = String::new();
+            if std::io::stdin().read_line(&mut line).unwrap_or(0) == 0 {
+                return 0;
+            }
+            match line.trim() {
+                "" => return 0,
+                answer => match answer.parse::<usize>() {
+                    Ok(n) if (1..=choices.len()).contains(&n) => return n - 1,
+                    _ => eprintln!("answer with a number from 1 to {}", choices.len()),
+                },
+            }
+        }
+    }
+}
+
+fn scaffold(
+    dir: PathBuf,
+    imports: Vec<PathBuf>,
+    exports: Vec<PathBuf>,
+    opts: wit_bindgen_gen_guest_rust::scaffold::ScaffoldOpts,
+) -> Result<()> {
+    if dir.exists() {
+        bail!("{:?} already exists", dir);
+    }
+    let read = |paths: &[PathBuf]| {
+        paths
+            .iter()
+            .map(|path| {
+                let file_name = path
+                    .file_name()
+                    .and_then(|s| s.to_str())
+                    .with_context(|| format!("invalid file name {:?}", path))?
+                    .to_string();
+                let source = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {:?}", path))?;
+                Ok((file_name, source))
+            })
+            .collect::<Result<Vec<_>>>()
+    };
+    let (imports, exports) = (read(&imports)?, read(&exports)?);
+    let wits = |files: &[(String, String)]| {
+        files
+            .iter()
+            .map(
+                |(file_name, source)| wit_bindgen_gen_guest_rust::scaffold::Wit {
+                    file_name,
+                    source,
+                },
+            )
+            .collect::<Vec<_>>()
+    };
+    let files =
+        wit_bindgen_gen_guest_rust::scaffold::generate(&opts, &wits(&imports), &wits(&exports))?;
+    for (name, contents) in files.iter() {
+        let dst = dir.join(name);
+        if let Some(parent) = dst.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create {:?}", parent))?;
+        }
+        std::fs::write(&dst, contents).with_context(|| format!("failed to write {:?}", dst))?;
+    }
+    println!("Created {:?}", dir);
+    Ok(())
+}
+
+fn print_options(generator: &str) -> Result<()> {
+    let schema = match generator {
+        "guest-rust" => wit_bindgen_gen_guest_rust::Opts::schema(),
+        "guest-c" => wit_bindgen_gen_guest_c::Opts::schema(),
+        "host-wasmtime-rust" => wit_bindgen_gen_host_wasmtime_rust::Opts::schema(),
+        "host-wasmtime-py" => wit_bindgen_gen_host_wasmtime_py::Opts::schema(),
+        "host-js" => wit_bindgen_gen_host_js::Opts::schema(),
+        "host-wasmer-rust" => wit_bindgen_gen_host_wasmer_rust::Opts::schema(),
+        "host-wasmer-py" => wit_bindgen_gen_host_wasmer_py::Opts::schema(),
+        "markdown" => wit_bindgen_gen_markdown::Opts::schema(),
+        _ => bail!("unknown generator `{}`", generator),
+    };
+    println!("{}", options::to_json(&schema));
+    Ok(())
+}
+
+fn check_workspace(dir: Option<PathBuf>) -> Result<()> {
+    let dir = match dir {
+        Some(dir) => dir,
+        None => std::env::current_dir()?,
+    };
+    let bindings = workspace::scan(&dir)?;
+    let issues = workspace::check(&bindings);
+    for issue in issues.iter() {
+        eprintln!("error: {}", issue);
+    }
+    if !issues.is_empty() {
+        bail!("found {} inconsistencies", issues.len());
+    }
+    println!("Checked {} bindings", bindings.len());
+    Ok(())
 }
 
 #[derive(Debug, StructOpt)]
@@ -74,6 +565,37 @@ enum GuestGenerator {
         #[structopt(flatten)]
         common: Common,
     },
+    /// Generates a Rust module adapting between the exports of two versions
+    /// of an interface.
+    RustShim {
+        /// The old version of the interface.
+        #[structopt(long)]
+        old: PathBuf,
+        /// The new version of the interface.
+        #[structopt(long)]
+        new: PathBuf,
+        #[structopt(flatten)]
+        opts: wit_bindgen_gen_guest_rust::shim::ShimOpts,
+        /// Where to write the module. Defaults to standard output.
+        #[structopt(long = "out")]
+        out: Option<PathBuf>,
+    },
+    /// Creates an example Rust guest crate binding interfaces, with stub
+    /// implementations of its exports to fill in.
+    RustScaffold {
+        /// The directory to create the crate in.
+        dir: PathBuf,
+        /// A `*.wit` interface for the crate to import. Can be specified
+        /// multiple times.
+        #[structopt(long = "import", short)]
+        imports: Vec<PathBuf>,
+        /// A `*.wit` interface for the crate to export. Can be specified
+        /// multiple times.
+        #[structopt(long = "export", short)]
+        exports: Vec<PathBuf>,
+        #[structopt(flatten)]
+        opts: wit_bindgen_gen_guest_rust::scaffold::ScaffoldOpts,
+    },
     /// Generates bindings for C/CPP guest modules.
     C {
         #[structopt(flatten)]
@@ -98,6 +620,25 @@ struct Common {
     /// specified multiple times.
     #[structopt(long = "export", short)]
     exports: Vec<PathBuf>,
+
+    /// Warn about functions whose parameters flatten to more than this many
+    /// core wasm values.
+    #[structopt(long = "max-flat-params")]
+    max_flat_params: Option<usize>,
+
+    /// Warn about records taking more than this many bytes in linear memory.
+    #[structopt(long = "max-record-size")]
+    max_record_size: Option<usize>,
+
+    /// Fail instead of warning when `--max-flat-params` or
+    /// `--max-record-size` is exceeded.
+    #[structopt(long = "deny-lints")]
+    deny_lints: bool,
+
+    /// Generate the items annotated with `@feature("name")` for this
+    /// feature, which are otherwise omitted. Can be specified multiple times.
+    #[structopt(long = "feature")]
+    features: Vec<String>,
 }
 
 fn main() -> Result<()> {
@@ -112,7 +653,54 @@ fn main() -> Result<()> {
         }
         Category::Host(HostGenerator::Js { opts, common }) => (Box::new(opts.build()), common),
         Category::Guest(GuestGenerator::C { opts, common }) => (Box::new(opts.build()), common),
+        Category::Guest(GuestGenerator::RustShim {
+            old,
+            new,
+            opts,
+            out,
+        }) => {
+            let old = Interface::parse_file(old)?;
+            let new = Interface::parse_file(new)?;
+            let shim = wit_bindgen_gen_guest_rust::shim::generate(&old, &new, &opts)?;
+            return match out {
+                Some(out) => {
+                    std::fs::write(&out, shim).with_context(|| format!("failed to write {:?}", out))
+                }
+                None => {
+                    print!("{}", shim);
+                    Ok(())
+                }
+            };
+        }
+        Category::Guest(GuestGenerator::RustScaffold {
+            dir,
+            imports,
+            exports,
+            opts,
+        }) => return scaffold(dir, imports, exports, opts),
         Category::Markdown { opts, common } => (Box::new(opts.build()), common),
+        Category::Registry(command) => return command.run(),
+        Category::Lock {
+            files,
+            references,
+            check,
+            lockfile,
+        } => return lock(files, references, check, lockfile),
+        Category::AbiLock {
+            files,
+            check,
+            lockfile,
+        } => return abi_lock(files, check, lockfile),
+        Category::CheckWorkspace { dir } => return check_workspace(dir),
+        Category::SizeReport { wasm, interfaces } => return size_report(wasm, interfaces),
+        Category::Stats { files, features } => return stats(files, features),
+        Category::Options { generator } => return print_options(&generator),
+        Category::TestVectors { files, out_dir } => return test_vectors(files, out_dir),
+        Category::FromJson {
+            name,
+            samples,
+            interactive,
+        } => return from_json(&name, samples, interactive),
         Category::Host(HostGenerator::WasmerRust { opts, common }) => {
             (Box::new(opts.build()), common)
         }
@@ -121,17 +709,39 @@ fn main() -> Result<()> {
         }
     };
 
+    let lockfile = Lockfile::find(&std::env::current_dir()?)?;
+    let abi_lock = AbiLock::find(&std::env::current_dir()?)?;
+    let load = |wit: &PathBuf| -> Result<Interface> {
+        let mut iface = Interface::parse_file(wit)?;
+        if let Some(lockfile) = &lockfile {
+            lockfile.verify(&InterfaceSource::File(wit.clone()), &iface)?;
+        }
+        if let Some(abi_lock) = &abi_lock {
+            abi_lock.verify(&iface)?;
+        }
+        iface
+            .retain_features(&common.features)
+            .with_context(|| format!("failed to select the features of {:?}", wit))?;
+        Ok(iface)
+    };
     let imports = common
         .imports
         .iter()
-        .map(|wit| Interface::parse_file(wit))
+        .map(&load)
         .collect::<Result<Vec<_>>>()?;
     let exports = common
         .exports
         .iter()
-        .map(|wit| Interface::parse_file(wit))
+        .map(&load)
         .collect::<Result<Vec<_>>>()?;
 
+    let thresholds = Thresholds {
+        max_flat_params: common.max_flat_params,
+        max_record_size: common.max_record_size,
+        deny: common.deny_lints,
+    };
+    thresholds.enforce(imports.iter().chain(exports.iter()))?;
+
     let mut files = Files::default();
     generator.generate_all(&imports, &exports, &mut files);
 
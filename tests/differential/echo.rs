@@ -0,0 +1,75 @@
+//! The guest of the round-trips of `crates/gen-host-wasmtime-rust/tests/differential.rs`,
+//! built once with the guest bindings of this fork and once with those of
+//! upstream wit-bindgen.
+
+wit_bindgen_guest_rust::export!("echo.wit");
+
+use echo::*;
+
+struct Echo;
+
+impl echo::Echo for Echo {
+    fn echo_u8(x: u8) -> u8 {
+        x
+    }
+
+    fn echo_s64(x: i64) -> i64 {
+        x
+    }
+
+    fn echo_float32(x: f32) -> f32 {
+        x
+    }
+
+    fn echo_float64(x: f64) -> f64 {
+        x
+    }
+
+    fn echo_char(x: char) -> char {
+        x
+    }
+
+    fn echo_string(x: String) -> String {
+        x
+    }
+
+    fn echo_bytes(x: Vec<u8>) -> Vec<u8> {
+        x
+    }
+
+    fn echo_strings(x: Vec<String>) -> Vec<String> {
+        x
+    }
+
+    fn echo_point(x: Point) -> Point {
+        x
+    }
+
+    fn echo_permissions(x: Permissions) -> Permissions {
+        x
+    }
+
+    fn echo_level(x: Level) -> Level {
+        x
+    }
+
+    fn echo_shapes(x: Vec<Shape>) -> Vec<Shape> {
+        x
+    }
+
+    fn echo_number(x: Number) -> Number {
+        x
+    }
+
+    fn echo_option(x: Option<String>) -> Option<String> {
+        x
+    }
+
+    fn echo_result(x: Result<u32, String>) -> Result<u32, String> {
+        x
+    }
+
+    fn echo_tuple(x: (u8, String, f64)) -> (u8, String, f64) {
+        x
+    }
+}
@@ -0,0 +1,22 @@
+use anyhow::Result;
+
+wit_bindgen_host_wasmtime_rust::import!({
+    paths: ["../../tests/runtime/round_trips/exports.wit"],
+    proptest: true,
+});
+
+fn run(wasm: &str) -> Result<()> {
+    use exports::*;
+
+    let (exports, mut store) = crate::instantiate(
+        wasm,
+        |_: &mut wasmtime::Linker<crate::Context<(), ExportsData>>| Ok(()),
+        |store, module, linker| Exports::instantiate(store, module, linker, |cx| &mut cx.exports),
+    )?;
+
+    assert_eq!(
+        exports.apply(&mut store, Vec::new(), vec![Id::U64(1)])?,
+        Ok(vec![Id::U64(1)])
+    );
+    exports.check_round_trips(&mut store)
+}
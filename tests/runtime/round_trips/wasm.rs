@@ -0,0 +1,17 @@
+wit_bindgen_guest_rust::export!({
+    paths: ["../../tests/runtime/round_trips/exports.wit"],
+    proptest,
+});
+
+use exports::*;
+
+struct Exports;
+
+impl exports::Exports for Exports {
+    fn apply(changes: Vec<Change>, ids: Vec<Id>) -> Result<Vec<Id>, String> {
+        match changes.is_empty() {
+            true => Ok(ids),
+            false => Err("read-only".to_string()),
+        }
+    }
+}